@@ -0,0 +1,13 @@
+#![no_main]
+
+use cnx::parse::color;
+use cnx::text::Color;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|s: &str| {
+    let parsed = color::hex_color(s);
+    assert_eq!(parsed.is_ok(), s.parse::<Color>().is_ok());
+    if parsed.is_ok() {
+        assert!(s.len() == 6 || s.len() == 8);
+    }
+});
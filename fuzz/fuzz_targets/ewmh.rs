@@ -0,0 +1,18 @@
+#![no_main]
+
+use cnx::parse::ewmh;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|input: (&[u8], u32, Vec<u32>)| {
+    let (bytes, number, icons) = input;
+
+    ewmh::join_strings(bytes);
+    ewmh::wm_class(bytes);
+    let names = ewmh::desktop_names(ewmh::utf8_strings(bytes), number);
+    assert_eq!(names.len(), (number as usize).min(ewmh::MAX_DESKTOPS));
+
+    if let Some((width, height, pixels)) = ewmh::pick_icon(&icons, number) {
+        assert!(!pixels.is_empty());
+        assert_eq!(pixels.len(), width as usize * height as usize);
+    }
+});
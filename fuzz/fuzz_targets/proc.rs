@@ -0,0 +1,13 @@
+#![no_main]
+
+use cnx::parse::proc;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|input: (&str, &str)| {
+    let (contents, interface) = input;
+
+    if let Some(quality) = proc::link_quality(contents, interface) {
+        assert!((0.0..=100.0).contains(&quality));
+    }
+    proc::pid(contents);
+});
@@ -0,0 +1,13 @@
+#![no_main]
+
+use cnx::parse::sysfs;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|contents: &str| {
+    if let Ok(temp) = sysfs::millidegrees(contents) {
+        assert!(temp.is_finite());
+    }
+    sysfs::temp_input(contents);
+    sysfs::cpu_number(contents);
+    let _ = sysfs::kilohertz(contents);
+});
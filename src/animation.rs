@@ -0,0 +1,80 @@
+//! Simple time-based animations.
+//!
+//! Animations are driven by their own fine-grained [`Timer`], as the timer
+//! shared by polling widgets only ticks every 100ms, which is far too coarse
+//! to animate smoothly.
+//!
+//! Animations are disabled entirely in low-power mode.
+//!
+//! [`Timer`]: https://docs.rs/tokio-timer/0.1/tokio_timer/struct.Timer.html
+
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use futures::{stream, Future, Stream};
+use tokio_timer::Timer;
+
+use crate::error::Error;
+use crate::timer::PowerState;
+
+/// The interval between frames of an animation, i.e. 60fps.
+const FRAME_INTERVAL: Duration = Duration::from_millis(16);
+
+pub(crate) fn frame_timer() -> Timer {
+    tokio_timer::wheel()
+        .tick_duration(Duration::from_millis(4))
+        .num_slots(1024)
+        .build()
+}
+
+/// Eases the linear progress `t` (0.0 to 1.0) so that animations start and
+/// finish gently.
+fn ease_in_out(t: f64) -> f64 {
+    t * t * (3.0 - 2.0 * t)
+}
+
+#[derive(Clone)]
+pub(crate) struct Animation {
+    timer: Timer,
+    power: Rc<PowerState>,
+    duration: Duration,
+}
+
+impl Animation {
+    pub fn new(timer: Timer, power: Rc<PowerState>, duration: Duration) -> Animation {
+        Animation {
+            timer,
+            power,
+            duration,
+        }
+    }
+
+    /// Returns a stream of the eased progress of the animation, which yields
+    /// once per frame and finishes after yielding `1.0`. In low-power mode,
+    /// only the final frame is yielded.
+    pub fn frames(&self) -> Box<dyn Stream<Item = f64, Error = Error>> {
+        if self.power.is_low_power() {
+            return Box::new(stream::once(Ok(1.0)));
+        }
+
+        let timer = self.timer.clone();
+        let duration = self.duration;
+        let start = Instant::now();
+        let stream = stream::unfold(false, move |finished| {
+            if finished {
+                return None;
+            }
+            let sleep = timer.sleep(FRAME_INTERVAL).map_err(Error::from);
+            Some(sleep.map(move |()| {
+                let elapsed = start.elapsed();
+                if elapsed >= duration {
+                    (1.0, true)
+                } else {
+                    let t = elapsed.as_secs_f64() / duration.as_secs_f64();
+                    (ease_in_out(t), false)
+                }
+            }))
+        });
+        Box::new(stream)
+    }
+}
@@ -0,0 +1,204 @@
+//! The desktop's appearance settings, e.g. whether it prefers a dark color
+//! scheme.
+//!
+//! These are read from the [XDG desktop portal]'s `Settings` interface, which
+//! desktops (and tools like `darkman`) use to tell applications when to switch
+//! between light and dark themes. Cnx uses a single `busctl monitor` to notice
+//! the setting changing, which it starts when the color scheme is first
+//! [`watch()`]ed, and sends each change to every stream watching it.
+//!
+//! The bars themselves can switch palettes with the color scheme, see
+//! [`Cnx::set_dark_palette()`].
+//!
+//! [XDG desktop portal]: https://flatpak.github.io/xdg-desktop-portal/
+//! [`watch()`]: struct.Appearance.html#method.watch
+//! [`Cnx::set_dark_palette()`]: ../struct.Cnx.html#method.set_dark_palette
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use futures::unsync::mpsc::{self, UnboundedSender};
+use futures::{stream, Future, Stream};
+use log::{error, warn};
+use tokio_core::reactor::Handle;
+
+use crate::busctl::{self, busctl, Bus};
+use crate::error::{format_err, Error, ResultExt};
+use crate::json::{self, Value};
+use crate::Result;
+
+const MATCH_RULE: &str = "type='signal',interface='org.freedesktop.portal.Settings',\
+                          member='SettingChanged',arg0='org.freedesktop.appearance',\
+                          arg1='color-scheme'";
+
+/// The color scheme which the desktop prefers applications to use.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ColorScheme {
+    /// The desktop doesn't mind, which usually means light.
+    NoPreference,
+    /// The desktop prefers a dark color scheme.
+    Dark,
+    /// The desktop prefers a light color scheme.
+    Light,
+}
+
+impl ColorScheme {
+    /// Whether this is the `Dark` color scheme.
+    pub fn is_dark(self) -> bool {
+        self == ColorScheme::Dark
+    }
+}
+
+/// The stream of color schemes returned by [`Appearance::watch()`].
+///
+/// [`Appearance::watch()`]: struct.Appearance.html#method.watch
+pub type ColorSchemes = Box<dyn Stream<Item = ColorScheme, Error = Error>>;
+
+/// Finds the number in the (possibly nested) variant returned by the portal,
+/// which `busctl` shows as objects with a `data` member.
+fn variant_number(value: &Value) -> Option<f64> {
+    match *value {
+        Value::Number(n) => Some(n),
+        Value::Object(_) => variant_number(value.get("data")?),
+        Value::Array(_) => variant_number(value.at(0)?),
+        _ => None,
+    }
+}
+
+/// Parses the output of the portal's `Read` method for `color-scheme`.
+fn parse_color_scheme(output: &str) -> Result<ColorScheme> {
+    let value = json::parse(output)?;
+    match variant_number(&value).map(|n| n as u32) {
+        Some(1) => Ok(ColorScheme::Dark),
+        Some(2) => Ok(ColorScheme::Light),
+        Some(_) => Ok(ColorScheme::NoPreference),
+        None => Err(format_err!("Unexpected format of color-scheme setting")),
+    }
+}
+
+/// Reads the desktop's current color scheme from the portal.
+fn read_color_scheme() -> Result<ColorScheme> {
+    let output = busctl(
+        Bus::User,
+        &[
+            "call",
+            "org.freedesktop.portal.Desktop",
+            "/org/freedesktop/portal/desktop",
+            "org.freedesktop.portal.Settings",
+            "Read",
+            "ss",
+            "org.freedesktop.appearance",
+            "color-scheme",
+        ],
+    )?;
+    parse_color_scheme(&output).context("Failed to read the desktop's color scheme")
+}
+
+struct Inner {
+    handle: Handle,
+    watching: bool,
+    subscribers: Vec<UnboundedSender<ColorScheme>>,
+}
+
+/// A handle to the desktop's appearance settings, returned by
+/// [`Cnx::appearance()`].
+///
+/// [`Cnx::appearance()`]: ../struct.Cnx.html#method.appearance
+#[derive(Clone)]
+pub struct Appearance {
+    inner: Rc<RefCell<Inner>>,
+}
+
+impl Appearance {
+    pub(crate) fn new(handle: Handle) -> Appearance {
+        Appearance {
+            inner: Rc::new(RefCell::new(Inner {
+                handle,
+                watching: false,
+                subscribers: Vec::new(),
+            })),
+        }
+    }
+
+    /// Returns the desktop's current color scheme.
+    pub fn color_scheme(&self) -> Result<ColorScheme> {
+        read_color_scheme()
+    }
+
+    /// Returns a stream which yields the desktop's current color scheme, and
+    /// then the new color scheme each time it changes.
+    ///
+    /// If the color scheme can't be read (e.g. because no portal is running),
+    /// the stream starts with `NoPreference`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # extern crate futures;
+    /// # use futures::Stream;
+    /// # use cnx::{Cnx, Position};
+    /// # fn run() -> ::cnx::Result<()> {
+    /// let cnx = Cnx::new(Position::Top)?;
+    /// let schemes = cnx.appearance().watch()?.map(|scheme| scheme.is_dark());
+    /// # Ok(())
+    /// # }
+    /// # fn main() { run().unwrap(); }
+    /// ```
+    pub fn watch(&self) -> Result<ColorSchemes> {
+        if !self.inner.borrow().watching {
+            self.start()?;
+        }
+
+        let current = read_color_scheme().unwrap_or_else(|e| {
+            warn!("{}", e);
+            ColorScheme::NoPreference
+        });
+        let (sender, receiver) = mpsc::unbounded();
+        self.inner.borrow_mut().subscribers.push(sender);
+        Ok(Box::new(stream::once(Ok(current)).chain(receiver.map_err(
+            |()| format_err!("Color scheme subscription closed"),
+        ))))
+    }
+
+    fn start(&self) -> Result<()> {
+        let mut inner = self.inner.borrow_mut();
+        let changes = busctl::monitor(&inner.handle, Bus::User, MATCH_RULE)?;
+        // Rather than parsing the new value from the signal, read it again,
+        // in the same way as the initial value.
+        let shared = self.inner.clone();
+        let fut = changes
+            .for_each(move |_| {
+                match read_color_scheme() {
+                    // Drop any subscribers whose streams have gone away.
+                    Ok(scheme) => shared
+                        .borrow_mut()
+                        .subscribers
+                        .retain(|subscriber| subscriber.unbounded_send(scheme).is_ok()),
+                    Err(e) => warn!("{}", e),
+                }
+                Ok(())
+            })
+            .map_err(|e| error!("Error watching the desktop's color scheme: {}", e));
+        inner.handle.spawn(fut);
+        inner.watching = true;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_color_scheme, ColorScheme};
+
+    #[test]
+    fn works() {
+        let read = r#"{"type":"v","data":[{"type":"v","data":{"type":"u","data":1}}]}"#;
+        assert_eq!(parse_color_scheme(read).unwrap(), ColorScheme::Dark);
+        let read_one = r#"{"type":"v","data":[{"type":"u","data":2}]}"#;
+        assert_eq!(parse_color_scheme(read_one).unwrap(), ColorScheme::Light);
+        assert_eq!(
+            parse_color_scheme(r#"{"type":"v","data":[{"type":"u","data":0}]}"#).unwrap(),
+            ColorScheme::NoPreference
+        );
+        assert!(parse_color_scheme(r#"{"type":"v","data":[]}"#).is_err());
+    }
+}
@@ -1,28 +1,45 @@
+use std::cell::{Cell, RefCell};
+use std::cmp::{self, Reverse};
 use std::f64;
 use std::io;
 use std::mem;
 use std::os::unix::io::RawFd;
 use std::rc::Rc;
+use std::time::Duration;
 
 use cairo::XCBSurface;
-use failure::{format_err, Error, ResultExt};
-use futures::{future, Async, Future, Poll, Stream};
+use futures::unsync::mpsc;
+use futures::{future, stream, Async, Future, Poll, Stream};
 use log::*;
 use mio::event::Evented;
 use mio::unix::EventedFd;
 use mio::{PollOpt, Ready, Token};
 use tokio_core::reactor::{Handle, PollEvented};
+use tokio_timer::Sleep;
 use xcb_util::ewmh;
 
-use crate::text::{ComputedText, Text};
-use crate::widgets::{Widget, WidgetList};
+use crate::appearance::{ColorScheme, ColorSchemes};
+use crate::cache::TextCache;
+use crate::control::{Command, Commands};
+use crate::dependency::Delayed;
+use crate::error::{format_err, Error, ResultExt};
+use crate::keyboard::{self, Hotkey, Keymap};
+use crate::text::{
+    Attributes, Backdrop, BarOrientation, Color, ComputedText, Font, Padding, Palette,
+    SectionStyle, Text,
+};
+use crate::timer::Timer;
+use crate::widgets::{
+    Area, Click, ClickHandler, LayoutHandler, Placement, Refresh, RestartPolicy, Widget,
+    WidgetFactory, WidgetList, WidgetOptions, WidgetStream,
+};
 use crate::Result;
 
-fn get_root_visual_type(conn: &xcb::Connection, screen: &xcb::Screen<'_>) -> xcb::Visualtype {
+pub(crate) fn get_visual_type(conn: &xcb::Connection, visual_id: xcb::Visualid) -> xcb::Visualtype {
     for root in conn.get_setup().roots() {
         for allowed_depth in root.allowed_depths() {
             for visual in allowed_depth.visuals() {
-                if visual.visual_id() == screen.root_visual() {
+                if visual.visual_id() == visual_id {
                     return visual;
                 }
             }
@@ -31,10 +48,37 @@ fn get_root_visual_type(conn: &xcb::Connection, screen: &xcb::Screen<'_>) -> xcb
     panic!("No visual type found");
 }
 
-/// Creates a `cairo::Surface` for the XCB window with the given `id`.
-fn cairo_surface_for_xcb_window(
+/// Returns a 32-bit visual with an alpha channel, which lets the bar be
+/// translucent, if the screen has one and a compositing manager is running
+/// to draw it.
+fn get_argb_visual_type(
     conn: &xcb::Connection,
     screen: &xcb::Screen<'_>,
+    screen_idx: usize,
+) -> Option<xcb::Visualtype> {
+    let selection = format!("_NET_WM_CM_S{}", screen_idx);
+    let atom = xcb::intern_atom(conn, false, &selection)
+        .get_reply()
+        .ok()?
+        .atom();
+    let owner = xcb::get_selection_owner(conn, atom)
+        .get_reply()
+        .ok()?
+        .owner();
+    if owner == xcb::NONE {
+        return None;
+    }
+    screen
+        .allowed_depths()
+        .filter(|depth| depth.depth() == 32)
+        .flat_map(|depth| depth.visuals())
+        .find(|visual| visual.class() == xcb::VISUAL_CLASS_TRUE_COLOR as u8)
+}
+
+/// Creates a `cairo::Surface` for the XCB window with the given `id`.
+pub(crate) fn cairo_surface_for_xcb_window(
+    conn: &xcb::Connection,
+    mut visual: xcb::Visualtype,
     id: u32,
     width: i32,
     height: i32,
@@ -44,8 +88,7 @@ fn cairo_surface_for_xcb_window(
     };
     let visual = unsafe {
         cairo::XCBVisualType::from_raw_none(
-            &mut get_root_visual_type(conn, screen).base as *mut xcb::ffi::xcb_visualtype_t
-                as *mut cairo_sys::xcb_visualtype_t,
+            &mut visual.base as *mut xcb::ffi::xcb_visualtype_t as *mut cairo_sys::xcb_visualtype_t,
         )
     };
     let drawable = cairo::XCBDrawable(id);
@@ -71,6 +114,293 @@ pub enum Position {
     Top,
     /// Position the Cnx bar at the bottom of the screen.
     Bottom,
+    /// Position the Cnx bar at the left edge of the screen.
+    ///
+    /// Vertical bars are laid out like horizontal bars turned on their side:
+    /// the [`Region`]s run from top (`Left`) to bottom (`Right`), and the
+    /// left and right padding of each section (see
+    /// [`Cnx::set_section_style()`]) is left at its ends. Each
+    /// text is upright, and as wide as it needs to be, unless they are
+    /// turned to run along the bar with [`Cnx::set_rotated_text()`].
+    ///
+    /// [`Region`]: enum.Region.html
+    /// [`Cnx::set_section_style()`]: struct.Cnx.html#method.set_section_style
+    /// [`Cnx::set_rotated_text()`]: struct.Cnx.html#method.set_rotated_text
+    Left,
+    /// Position the Cnx bar at the right edge of the screen. See
+    /// [`Left`](#variant.Left) for how vertical bars are laid out.
+    Right,
+}
+
+impl Position {
+    fn is_vertical(&self) -> bool {
+        matches!(*self, Position::Left | Position::Right)
+    }
+}
+
+/// An enum specifying which part of the bar a widget is shown in.
+///
+/// Passed to [`Cnx::add_widget_to()`]. Each region is laid out separately:
+/// the left and right regions are pushed against the edges of the bar and the
+/// center region is centered on it.
+///
+/// [`Cnx::add_widget_to()`]: struct.Cnx.html#method.add_widget_to
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Region {
+    /// The left of the bar. Widgets added with [`Cnx::add_widget()`] are
+    /// shown here.
+    ///
+    /// [`Cnx::add_widget()`]: struct.Cnx.html#method.add_widget
+    #[default]
+    Left = 0,
+    /// The middle of the bar.
+    Center = 1,
+    /// The right of the bar.
+    Right = 2,
+}
+
+/// An enum specifying an end of the bar.
+///
+/// Passed to [`Cnx::reserve_space()`], to leave space for another program at
+/// that end of the bar.
+///
+/// [`Cnx::reserve_space()`]: struct.Cnx.html#method.reserve_space
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Side {
+    /// The left end of the bar, or the top of a vertical bar.
+    Left,
+    /// The right end of the bar, or the bottom of a vertical bar.
+    Right,
+}
+
+/// How each bar looks and is laid out, as configured on the `Cnx`.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct BarOptions {
+    // The style of each `Region`'s section, indexed by the region.
+    pub section_styles: [Option<SectionStyle>; 3],
+    pub background: Color,
+    // Space left at one end of the bar for another program.
+    pub reserved: Option<(Side, u16)>,
+    // The height of the bar, rather than the height of its tallest text.
+    pub height: Option<u16>,
+    // The space left between the bar and the edges of its monitor.
+    pub margins: Padding,
+    // The space left between neighbouring widgets in a region.
+    pub spacing: f64,
+    // The X screen to show the bars on, rather than the default screen.
+    pub screen: Option<usize>,
+    // The palette to use instead of the background and section style while
+    // the desktop prefers a dark color scheme.
+    pub dark_palette: Option<Palette>,
+    // How much bigger everything is drawn in large mode, rather than
+    // `LARGE_MODE_SCALE`.
+    pub large_mode_scale: Option<f64>,
+}
+
+/// An enum specifying which monitors Cnx shows a bar on.
+///
+/// Passed to [`Cnx::set_monitors()`]. Monitors are discovered using RandR
+/// when Cnx starts running.
+///
+/// [`Cnx::set_monitors()`]: struct.Cnx.html#method.set_monitors
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum Monitors {
+    /// Show a single bar, spanning the whole X screen. This is the default.
+    #[default]
+    Screen,
+    /// Show a single bar on the primary monitor.
+    Primary,
+    /// Show a bar on every connected monitor.
+    All,
+    /// Show a single bar on the monitor connected to the named RandR output,
+    /// e.g. `HDMI-1`. (Run `xrandr` to discover the names of your outputs).
+    Named(String),
+}
+
+/// The area of the X screen covered by a monitor.
+#[derive(Clone, Debug, PartialEq)]
+struct Monitor {
+    // The name of the RandR output, or None if this is the whole X screen.
+    name: Option<String>,
+    x: i16,
+    y: i16,
+    width: u16,
+    height: u16,
+}
+
+impl Monitor {
+    /// Leaves `width` pixels at the given `side` of the monitor uncovered, or
+    /// at its top (`Side::Left`) or bottom if the bar is `vertical`.
+    fn reserve(&mut self, side: Side, width: u16, vertical: bool) {
+        let (start, length) = if vertical {
+            (&mut self.y, &mut self.height)
+        } else {
+            (&mut self.x, &mut self.width)
+        };
+        // Leave at least a pixel, as X doesn't allow empty windows.
+        let width = width.min(length.saturating_sub(1));
+        *length -= width;
+        if side == Side::Left {
+            *start = start.saturating_add(width as i16);
+        }
+    }
+}
+
+/// Finds the connected monitors which should each have a bar, as specified
+/// by `monitors`, on the given X `screen` (or the default screen).
+fn find_monitors(monitors: &Monitors, screen: Option<usize>) -> Result<Vec<Monitor>> {
+    let (conn, screen_idx) =
+        xcb::Connection::connect(None).context("Failed to connect to X server")?;
+    let screen = conn
+        .get_setup()
+        .roots()
+        .nth(screen.unwrap_or(screen_idx as usize))
+        .ok_or_else(|| Error::X("Invalid screen".to_owned()))?;
+    let whole_screen = Monitor {
+        name: None,
+        x: 0,
+        y: 0,
+        width: screen.width_in_pixels(),
+        height: screen.height_in_pixels(),
+    };
+    if *monitors == Monitors::Screen {
+        return Ok(vec![whole_screen]);
+    }
+
+    let resources = xcb::randr::get_screen_resources_current(&conn, screen.root())
+        .get_reply()
+        .context("Failed to get RandR screen resources")?;
+    let timestamp = resources.config_timestamp();
+    let primary = xcb::randr::get_output_primary(&conn, screen.root())
+        .get_reply()
+        .map(|reply| reply.output())
+        .unwrap_or(0);
+
+    let mut found = Vec::new();
+    for &output in resources.outputs() {
+        let info = xcb::randr::get_output_info(&conn, output, timestamp)
+            .get_reply()
+            .context("Failed to get RandR output info")?;
+        // Disconnected or disabled outputs have no CRTC.
+        if info.connection() != xcb::randr::CONNECTION_CONNECTED as u8 || info.crtc() == 0 {
+            continue;
+        }
+        let name = String::from_utf8_lossy(info.name()).into_owned();
+        let wanted = match *monitors {
+            Monitors::Screen | Monitors::All => true,
+            Monitors::Primary => output == primary,
+            Monitors::Named(ref wanted) => *wanted == name,
+        };
+        if !wanted {
+            continue;
+        }
+
+        let crtc = xcb::randr::get_crtc_info(&conn, info.crtc(), timestamp)
+            .get_reply()
+            .context("Failed to get RandR CRTC info")?;
+        found.push(Monitor {
+            name: Some(name),
+            x: crtc.x(),
+            y: crtc.y(),
+            width: crtc.width(),
+            height: crtc.height(),
+        });
+    }
+
+    match *monitors {
+        Monitors::Named(ref name) if found.is_empty() => Err(Error::X(format!(
+            "No connected monitor for output: {}",
+            name
+        ))),
+        _ if found.is_empty() => {
+            // No RandR outputs (or no primary output) - fall back to using the
+            // whole screen, like we would have done if RandR wasn't involved.
+            warn!("No connected RandR monitors found, using the whole screen");
+            Ok(vec![whole_screen])
+        }
+        _ => Ok(found),
+    }
+}
+
+/// Returns the position and size of the window of a bar at `position` on the
+/// `monitor`, which is `thickness` pixels tall (or wide, if it is vertical),
+/// and is kept `margins` away from the edge of the monitor.
+fn window_geometry(
+    position: &Position,
+    monitor: &Monitor,
+    thickness: u16,
+    margins: &Padding,
+) -> (i16, i16, u16, u16) {
+    let near = |start: i16, margin: f64| (i32::from(start) + margin as i32) as i16;
+    let far = |start: i16, length: u16, margin: f64| {
+        (i32::from(start) + i32::from(length) - i32::from(thickness) - margin as i32) as i16
+    };
+    match *position {
+        Position::Top => (
+            monitor.x,
+            near(monitor.y, margins.top),
+            monitor.width,
+            thickness,
+        ),
+        Position::Bottom => (
+            monitor.x,
+            far(monitor.y, monitor.height, margins.bottom),
+            monitor.width,
+            thickness,
+        ),
+        Position::Left => (
+            near(monitor.x, margins.left),
+            monitor.y,
+            thickness,
+            monitor.height,
+        ),
+        Position::Right => (
+            far(monitor.x, monitor.width, margins.right),
+            monitor.y,
+            thickness,
+            monitor.height,
+        ),
+    }
+}
+
+/// The thickness of the line under the widget highlighted while navigating
+/// with the keyboard.
+const FOCUS_THICKNESS: f64 = 2.0;
+
+/// How much bigger large mode draws the bars' fonts, padding and height,
+/// unless `Cnx::set_large_mode_scale()` is used.
+const LARGE_MODE_SCALE: f64 = 1.5;
+
+/// How a bar hides itself while the pointer is elsewhere. See
+/// `Cnx::set_auto_hide()`.
+struct AutoHide {
+    timeout: Duration,
+    // An invisible window along the bar's edge of its monitor, mapped while
+    // the bar is hidden, which shows the bar when the pointer reaches it.
+    trigger: xcb::Window,
+    // Whether the bar is withdrawn because it hid itself, rather than by a
+    // `Control`.
+    hidden: bool,
+    // How many times the wait before hiding has been started or cancelled,
+    // so that only the most recent wait hides the bar.
+    waits: usize,
+}
+
+/// The state of one of the bar's widgets.
+struct Slot {
+    options: WidgetOptions,
+    texts: Vec<ComputedText>,
+    // The texts as the widget gave them, before they were scaled for large
+    // mode.
+    sources: Vec<Text>,
+    // The least width the widget takes up, from its options.
+    min_width: f64,
+    // Whether the widget was hidden to make room for higher priority widgets.
+    hidden: bool,
+    // Whether the widget was restricted to a different monitor's bar.
+    excluded: bool,
+    // Whether the widget was hidden by a `Control`.
+    concealed: bool,
 }
 
 pub struct Bar {
@@ -78,17 +408,78 @@ pub struct Bar {
     window_id: u32,
     screen_idx: usize,
     surface: cairo::Surface,
-    width: u16,
+    monitor: Monitor,
+    // The bar's thickness: the height of its window, or its width if the bar
+    // is vertical.
     height: u16,
     position: Position,
-    contents: Vec<Vec<ComputedText>>,
+    // Whether a vertical bar's texts are turned to run along it.
+    rotate_text: bool,
+    options: BarOptions,
+    // The colors the bar is currently drawn with, from its options.
+    palette: Palette,
+    // The desktop's color scheme, which chose the palette.
+    color_scheme: ColorScheme,
+    // How much bigger everything is drawn: more than 1.0 in large mode.
+    scale: f64,
+    // Whether the window has an alpha channel, so that the background can be
+    // translucent.
+    argb: bool,
+    contents: Vec<Slot>,
+    // Whether a fullscreen window is covering the bar's monitor.
+    covered: bool,
+    // Whether the bar has been hidden using a `Control`.
+    withdrawn: bool,
+    // Whether this is a scratchpad bar, which is normally withdrawn and
+    // shows only the scratchpad widgets.
+    scratchpad: bool,
+    // Whether the bar needs redrawing once it is no longer hidden.
+    stale: bool,
+    // Whether clicks pass through the bar to the windows underneath.
+    click_through: bool,
+    auto_hide: Option<AutoHide>,
+    // The widget highlighted while navigating with the keyboard.
+    focused: Option<usize>,
 }
 
 impl Bar {
-    pub fn new(position: Position) -> Result<Bar> {
+    /// Creates the bars for each of the `monitors`.
+    pub fn for_monitors(
+        position: &Position,
+        monitors: &Monitors,
+        options: &BarOptions,
+    ) -> Result<Vec<Bar>> {
+        find_monitors(monitors, options.screen)?
+            .into_iter()
+            .map(|mut monitor| {
+                // The bar (and the space it reserves) just covers less of its
+                // monitor, leaving the rest for the other program, or as a
+                // margin. The margins at the ends of a vertical bar are its
+                // top and bottom ones.
+                let vertical = position.is_vertical();
+                if let Some((side, width)) = options.reserved {
+                    monitor.reserve(side, width, vertical);
+                }
+                let margins = &options.margins;
+                let (start, end) = if vertical {
+                    (margins.top, margins.bottom)
+                } else {
+                    (margins.left, margins.right)
+                };
+                monitor.reserve(Side::Left, start as u16, vertical);
+                monitor.reserve(Side::Right, end as u16, vertical);
+                let mut bar = Bar::new(position.clone(), monitor, options.screen)?;
+                bar.options = options.clone();
+                bar.set_color_scheme(ColorScheme::NoPreference);
+                Ok(bar)
+            })
+            .collect()
+    }
+
+    fn new(position: Position, monitor: Monitor, screen: Option<usize>) -> Result<Bar> {
         let (conn, screen_idx) =
             xcb::Connection::connect(None).context("Failed to connect to X server")?;
-        let screen_idx = screen_idx as usize;
+        let screen_idx = screen.unwrap_or(screen_idx as usize);
         let id = conn.generate_id();
 
         // We don't actually care about how tall our initial window is - we'll resize
@@ -96,61 +487,105 @@ impl Bar {
         // to be bigger than 0px, or either Xcb/Cairo (or maybe QTile?) gets upset.
         let height = 1;
 
-        let (width, surface) = {
+        let (x, y, width, window_height) =
+            window_geometry(&position, &monitor, height, &Padding::default());
+        let (surface, argb) = {
             let screen = conn
                 .get_setup()
                 .roots()
                 .nth(screen_idx)
-                .ok_or_else(|| format_err!("Invalid screen"))?;
+                .ok_or_else(|| Error::X("Invalid screen".to_owned()))?;
+
+            // A window with a different visual to its parent needs its own
+            // colormap, and a border pixel, or X refuses to create it.
+            let argb_visual = get_argb_visual_type(&conn, &screen, screen_idx);
+            let (depth, visual, colormap, back_pixel) = match argb_visual {
+                Some(visual) => {
+                    let colormap = conn.generate_id();
+                    xcb::create_colormap(
+                        &conn,
+                        xcb::COLORMAP_ALLOC_NONE as u8,
+                        colormap,
+                        screen.root(),
+                        visual.visual_id(),
+                    );
+                    (32, visual, colormap, 0)
+                }
+                None => (
+                    xcb::COPY_FROM_PARENT as u8,
+                    get_visual_type(&conn, screen.root_visual()),
+                    xcb::COPY_FROM_PARENT,
+                    screen.black_pixel(),
+                ),
+            };
             let values = [
-                (xcb::CW_BACK_PIXEL, screen.black_pixel()),
-                (xcb::CW_EVENT_MASK, xcb::EVENT_MASK_EXPOSURE),
+                (xcb::CW_BACK_PIXEL, back_pixel),
+                (xcb::CW_BORDER_PIXEL, 0),
+                (
+                    xcb::CW_EVENT_MASK,
+                    xcb::EVENT_MASK_EXPOSURE | xcb::EVENT_MASK_BUTTON_PRESS,
+                ),
+                (xcb::CW_COLORMAP, colormap),
             ];
 
-            let width = screen.width_in_pixels();
-
             xcb::create_window(
                 &conn,
-                xcb::COPY_FROM_PARENT as u8,
+                depth,
                 id,
                 screen.root(),
-                0,
-                0,
+                x,
+                y,
                 width,
-                height,
+                window_height,
                 0,
                 xcb::WINDOW_CLASS_INPUT_OUTPUT as u16,
-                screen.root_visual(),
+                visual.visual_id(),
                 &values,
             );
 
             let surface = cairo_surface_for_xcb_window(
                 &conn,
-                &screen,
+                visual,
                 id,
                 i32::from(width),
-                i32::from(height),
+                i32::from(window_height),
             );
-
-            (width, surface)
+            (surface, argb_visual.is_some())
         };
 
         let ewmh_conn = ewmh::Connection::connect(conn)
             .map_err(|(e, _)| e)
             .context("Failed to wrap xcb::Connection in ewmh::Connection")?;
 
-        #[allow(clippy::blacklisted_name)]
+        #[allow(clippy::disallowed_names)]
         let bar = Bar {
             conn: Rc::new(ewmh_conn),
             window_id: id,
             screen_idx,
             surface,
-            width,
+            monitor,
             height,
             position,
+            rotate_text: false,
+            options: BarOptions::default(),
+            palette: Palette {
+                background: Color::default(),
+                section_styles: Default::default(),
+            },
+            color_scheme: ColorScheme::NoPreference,
+            scale: 1.0,
+            argb,
             contents: Vec::new(),
+            covered: false,
+            withdrawn: false,
+            scratchpad: false,
+            stale: false,
+            click_through: false,
+            auto_hide: None,
+            focused: None,
         };
         bar.set_ewmh_properties();
+        bar.watch_active_window()?;
         // XXX We can't map the window until we've updated the window size, or nothing
         // gets rendered. I can't tell if this is something we're doing, something Cairo
         // is doing or something QTile is doing. This'll do for now and we'll see what
@@ -160,8 +595,219 @@ impl Bar {
         Ok(bar)
     }
 
-    fn flush(&self) {
-        self.conn.flush();
+    /// Switches the bar to the palette for the desktop's color `scheme`,
+    /// without redrawing it.
+    fn set_color_scheme(&mut self, scheme: ColorScheme) {
+        let mut palette = match self.options.dark_palette {
+            Some(ref dark) if scheme.is_dark() => dark.clone(),
+            _ => Palette {
+                background: self.options.background.clone(),
+                section_styles: self.options.section_styles.clone(),
+            },
+        };
+        if !self.argb {
+            // Without an alpha channel, drawing a translucent background
+            // would only darken it.
+            palette.background = palette.background.with_alpha(1.0);
+        }
+        for style in palette.section_styles.iter_mut().flatten() {
+            style.padding = style.padding.scaled(self.scale);
+        }
+        self.palette = palette;
+        self.color_scheme = scheme;
+    }
+
+    /// Switches to `options` which have been reloaded, without redrawing.
+    /// Only the bar's colors and the spacing between its widgets can change
+    /// while it is running, as the rest would need a new window.
+    fn reload_options(&mut self, options: &BarOptions) {
+        self.options.background = options.background.clone();
+        self.options.section_styles = options.section_styles.clone();
+        self.options.dark_palette = options.dark_palette.clone();
+        self.options.spacing = options.spacing;
+        self.options.large_mode_scale = options.large_mode_scale;
+        if self.is_large() {
+            // The widgets are reinstalled, so their texts will be scaled by
+            // the new factor as they arrive.
+            self.scale = options.large_mode_scale.unwrap_or(LARGE_MODE_SCALE);
+        }
+        self.set_color_scheme(self.color_scheme);
+    }
+
+    /// Switches large mode on or off, which draws the texts' fonts and
+    /// padding, the sections' padding and the bar's height bigger, without
+    /// redrawing.
+    fn set_large_mode(&mut self, large: bool) -> Result<()> {
+        self.scale = if large {
+            self.options.large_mode_scale.unwrap_or(LARGE_MODE_SCALE)
+        } else {
+            1.0
+        };
+        self.set_color_scheme(self.color_scheme);
+        let (surface, scale, orientation) = (&self.surface, self.scale, self.orientation());
+        for slot in &mut self.contents {
+            let mut texts = slot
+                .sources
+                .iter()
+                .map(|text| scale_text(text.clone(), scale).compute(surface, orientation))
+                .collect::<Result<Vec<_>>>()?;
+            widen_to(&mut texts, slot.min_width * scale);
+            slot.texts = texts;
+        }
+        Ok(())
+    }
+
+    fn is_large(&self) -> bool {
+        self.scale != 1.0
+    }
+
+    /// Turns the bar into a scratchpad bar, which is withdrawn until it is
+    /// shown with `Control::show_scratchpad()`. Scratchpads cover the main bar
+    /// while shown, rather than reserving space of their own.
+    pub fn make_scratchpad(&mut self) {
+        self.scratchpad = true;
+        self.withdrawn = true;
+        self.set_ewmh_properties();
+        self.flush();
+    }
+
+    /// Makes the bar ignore the pointer, by giving it an empty input shape,
+    /// so that clicks go to whatever is underneath it.
+    pub fn make_click_through(&mut self) {
+        self.click_through = true;
+        let cookie = xcb::shape::rectangles(
+            &self.conn,
+            xcb::shape::SO_SET as u8,
+            xcb::shape::SK_INPUT as u8,
+            xcb::CLIP_ORDERING_UNSORTED as u8,
+            self.window_id,
+            0,
+            0,
+            &[],
+        );
+        if let Err(e) = cookie.request_check() {
+            warn!(
+                "Failed to make the bar click-through (is the X Shape extension missing?): {}",
+                Error::from(e)
+            );
+        }
+        self.flush();
+    }
+
+    /// Turns the texts of a vertical bar to run along it, reading from top to
+    /// bottom, rather than having them upright.
+    pub fn rotate_text(&mut self) {
+        self.rotate_text = true;
+    }
+
+    /// Returns how the bar's texts are drawn on its window.
+    fn orientation(&self) -> BarOrientation {
+        if !self.position.is_vertical() {
+            BarOrientation::Horizontal
+        } else if self.rotate_text {
+            BarOrientation::Rotated(f64::from(self.height))
+        } else {
+            BarOrientation::Vertical
+        }
+    }
+
+    /// Returns the length of the bar, from one end to the other.
+    fn length(&self) -> u16 {
+        if self.position.is_vertical() {
+            self.monitor.height
+        } else {
+            self.monitor.width
+        }
+    }
+
+    /// Makes the bar hide itself once the pointer has been elsewhere for
+    /// `timeout`, and show itself again when the pointer reaches its edge of
+    /// the monitor.
+    pub fn make_auto_hide(&mut self, timeout: Duration) -> Result<()> {
+        let root = self.screen()?.root();
+        // The trigger is a strip one pixel thick, at the edge of the monitor.
+        let (x, y, width, height) =
+            window_geometry(&self.position, &self.monitor, 1, &Padding::default());
+        // The trigger is override-redirect, so that the WM leaves it alone.
+        let trigger = self.conn.generate_id();
+        let values = [
+            (xcb::CW_OVERRIDE_REDIRECT, 1),
+            (xcb::CW_EVENT_MASK, xcb::EVENT_MASK_ENTER_WINDOW),
+        ];
+        xcb::create_window(
+            &self.conn,
+            0,
+            trigger,
+            root,
+            x,
+            y,
+            width,
+            height,
+            0,
+            xcb::WINDOW_CLASS_INPUT_ONLY as u16,
+            xcb::COPY_FROM_PARENT,
+            &values,
+        );
+
+        // Watch the pointer entering and leaving the bar too.
+        let values = [(
+            xcb::CW_EVENT_MASK,
+            xcb::EVENT_MASK_EXPOSURE
+                | xcb::EVENT_MASK_BUTTON_PRESS
+                | xcb::EVENT_MASK_ENTER_WINDOW
+                | xcb::EVENT_MASK_LEAVE_WINDOW,
+        )];
+        xcb::change_window_attributes(&self.conn, self.window_id, &values);
+        self.auto_hide = Some(AutoHide {
+            timeout,
+            trigger,
+            hidden: false,
+            waits: 0,
+        });
+        self.flush();
+        Ok(())
+    }
+
+    /// Starts the wait before the bar hides itself, returning how long to
+    /// wait for and the number of the wait, or None if the bar doesn't hide
+    /// itself.
+    fn start_auto_hide(&mut self) -> Option<(Duration, usize)> {
+        let auto_hide = self.auto_hide.as_mut()?;
+        auto_hide.waits += 1;
+        Some((auto_hide.timeout, auto_hide.waits))
+    }
+
+    /// Cancels any wait before the bar hides itself, as the pointer is over
+    /// it.
+    fn cancel_auto_hide(&mut self) {
+        if let Some(ref mut auto_hide) = self.auto_hide {
+            auto_hide.waits += 1;
+        }
+    }
+
+    /// Hides the bar, if the given wait before hiding it is the most recent,
+    /// until the pointer reaches its edge of the monitor.
+    fn auto_hide(&mut self, wait: usize) -> Result<()> {
+        let trigger = match self.auto_hide {
+            Some(ref auto_hide) if auto_hide.waits == wait && !self.withdrawn => auto_hide.trigger,
+            _ => return Ok(()),
+        };
+        self.set_withdrawn(true)?;
+        xcb::map_window(&self.conn, trigger);
+        let values = [(xcb::CONFIG_WINDOW_STACK_MODE as u16, xcb::STACK_MODE_ABOVE)];
+        xcb::configure_window(&self.conn, trigger, &values);
+        if let Some(ref mut auto_hide) = self.auto_hide {
+            auto_hide.hidden = true;
+        }
+        Ok(())
+    }
+
+    /// Whether `window` is the trigger which shows the bar once it has hidden
+    /// itself.
+    fn is_auto_hide_trigger(&self, window: xcb::Window) -> bool {
+        self.auto_hide
+            .as_ref()
+            .is_some_and(|auto_hide| auto_hide.trigger == window)
     }
 
     fn map_window(&self) {
@@ -190,20 +836,214 @@ impl Bar {
             bottom_start_x: 0,
             bottom_end_x: 0,
         };
-        match self.position {
-            Position::Top => strut_partial.top = u32::from(self.height),
-            Position::Bottom => strut_partial.bottom = u32::from(self.height),
+        // Struts are relative to the edges of the X screen, rather than to the
+        // edges of our monitor.
+        let (screen_width, screen_height) = self
+            .screen()
+            .map(|screen| {
+                (
+                    i32::from(screen.width_in_pixels()),
+                    i32::from(screen.height_in_pixels()),
+                )
+            })
+            .unwrap_or((0, 0));
+        let monitor = &self.monitor;
+        let start_x = monitor.x.max(0) as u32;
+        let end_x = start_x + u32::from(monitor.width).saturating_sub(1);
+        let start_y = monitor.y.max(0) as u32;
+        let end_y = start_y + u32::from(monitor.height).saturating_sub(1);
+        // A withdrawn bar doesn't need any space reserving, and a scratchpad
+        // covers the main bar's space.
+        let position = if self.withdrawn || self.scratchpad {
+            None
+        } else {
+            Some(&self.position)
+        };
+        // Space is reserved for the bar's margins too, so that windows don't
+        // cover them.
+        let margins = &self.options.margins;
+        let height = i32::from(self.height)
+            + if self.position.is_vertical() {
+                margins.left as i32 + margins.right as i32
+            } else {
+                margins.top as i32 + margins.bottom as i32
+            };
+        match position {
+            Some(Position::Top) => {
+                strut_partial.top = (i32::from(monitor.y) + height).max(0) as u32;
+                strut_partial.top_start_x = start_x;
+                strut_partial.top_end_x = end_x;
+            }
+            Some(Position::Bottom) => {
+                let monitor_bottom = i32::from(monitor.y) + i32::from(monitor.height);
+                strut_partial.bottom = (screen_height - monitor_bottom + height).max(0) as u32;
+                strut_partial.bottom_start_x = start_x;
+                strut_partial.bottom_end_x = end_x;
+            }
+            Some(Position::Left) => {
+                strut_partial.left = (i32::from(monitor.x) + height).max(0) as u32;
+                strut_partial.left_start_y = start_y;
+                strut_partial.left_end_y = end_y;
+            }
+            Some(Position::Right) => {
+                let monitor_right = i32::from(monitor.x) + i32::from(monitor.width);
+                strut_partial.right = (screen_width - monitor_right + height).max(0) as u32;
+                strut_partial.right_start_y = start_y;
+                strut_partial.right_end_y = end_y;
+            }
+            None => {}
         }
         ewmh::set_wm_strut_partial(&self.conn, self.window_id, strut_partial);
     }
 
+    /// Registers for changes to the root window's properties, so that we
+    /// notice when the active window changes.
+    fn watch_active_window(&self) -> Result<()> {
+        let root = self.screen()?.root();
+        let attributes = [(xcb::CW_EVENT_MASK, xcb::EVENT_MASK_PROPERTY_CHANGE)];
+        xcb::change_window_attributes(&self.conn, root, &attributes);
+        Ok(())
+    }
+
+    /// Whether the bar is hidden, and so shouldn't be drawn.
+    fn is_hidden(&self) -> bool {
+        self.covered || self.withdrawn
+    }
+
+    /// Hides or shows the bar, unmapping its window and releasing the space
+    /// reserved for it while hidden.
+    fn set_withdrawn(&mut self, withdrawn: bool) -> Result<()> {
+        if self.withdrawn == withdrawn {
+            return Ok(());
+        }
+        let was_hidden = self.is_hidden();
+        self.withdrawn = withdrawn;
+        if withdrawn {
+            xcb::unmap_window(&self.conn, self.window_id);
+            if self.scratchpad && !self.click_through {
+                xcb::ungrab_pointer(&self.conn, xcb::CURRENT_TIME);
+            }
+        } else if self.height > 1 {
+            // The window isn't mapped until it has been sized (see
+            // `update_bar_height()`).
+            self.map_window();
+            if self.scratchpad {
+                let values = [(xcb::CONFIG_WINDOW_STACK_MODE as u16, xcb::STACK_MODE_ABOVE)];
+                xcb::configure_window(&self.conn, self.window_id, &values);
+                // A click-through scratchpad can't see clicks on itself, so
+                // it mustn't take them from everything else either.
+                if !self.click_through {
+                    self.grab_pointer();
+                }
+            }
+        }
+        if let Some(ref mut auto_hide) = self.auto_hide {
+            if !withdrawn && auto_hide.hidden {
+                auto_hide.hidden = false;
+                xcb::unmap_window(&self.conn, auto_hide.trigger);
+            }
+        }
+        self.set_ewmh_properties();
+        self.redraw_if_revealed(was_hidden)
+    }
+
+    /// Grabs the pointer while a scratchpad is shown, so that we see clicks
+    /// elsewhere on the screen and can hide the scratchpad.
+    fn grab_pointer(&self) {
+        let cookie = xcb::grab_pointer(
+            &self.conn,
+            true,
+            self.window_id,
+            xcb::EVENT_MASK_BUTTON_PRESS as u16,
+            xcb::GRAB_MODE_ASYNC as u8,
+            xcb::GRAB_MODE_ASYNC as u8,
+            xcb::NONE,
+            xcb::NONE,
+            xcb::CURRENT_TIME,
+        );
+        match cookie.get_reply() {
+            Ok(ref reply) if reply.status() == xcb::GRAB_STATUS_SUCCESS as u8 => {}
+            _ => warn!("Failed to grab pointer, so clicks elsewhere won't hide the scratchpad"),
+        }
+    }
+
+    /// Whether a click at (`x`, `y`) relative to the bar's window was outside
+    /// of it.
+    fn is_outside(&self, x: f64, y: f64) -> bool {
+        let (_, _, width, height) = window_geometry(
+            &self.position,
+            &self.monitor,
+            self.height,
+            &self.options.margins,
+        );
+        x < 0.0 || y < 0.0 || x >= f64::from(width) || y >= f64::from(height)
+    }
+
+    /// Redraws the bar if it was hidden, is no longer, and missed a redraw.
+    fn redraw_if_revealed(&mut self, was_hidden: bool) -> Result<()> {
+        if was_hidden && !self.is_hidden() && self.stale {
+            self.redraw_entire_bar()?;
+        }
+        Ok(())
+    }
+
+    /// Checks whether the active window is fullscreen on our monitor, in which
+    /// case it covers the bar.
+    fn update_covered(&mut self) -> Result<()> {
+        let window = ewmh::get_active_window(&self.conn, self.screen_idx as i32)
+            .get_reply()
+            .ok()
+            .filter(|&window| window != xcb::WINDOW_NONE && window != self.window_id);
+        let covered = match window {
+            Some(window) => {
+                // Watch the active window too, so that we notice when it
+                // enters or leaves fullscreen.
+                let attributes = [(xcb::CW_EVENT_MASK, xcb::EVENT_MASK_PROPERTY_CHANGE)];
+                xcb::change_window_attributes(&self.conn, window, &attributes);
+                self.is_fullscreen_on_monitor(window)?
+            }
+            None => false,
+        };
+
+        let was_hidden = self.is_hidden();
+        self.covered = covered;
+        self.redraw_if_revealed(was_hidden)
+    }
+
+    fn is_fullscreen_on_monitor(&self, window: xcb::Window) -> Result<bool> {
+        let fullscreen = ewmh::get_wm_state(&self.conn, window)
+            .get_reply()
+            .map(|reply| reply.atoms().contains(&self.conn.WM_STATE_FULLSCREEN()))
+            .unwrap_or(false);
+        if !fullscreen {
+            return Ok(false);
+        }
+
+        // Fullscreen windows only cover the monitor containing their centre.
+        let root = self.screen()?.root();
+        let geometry = xcb::get_geometry(&self.conn, window)
+            .get_reply()
+            .context("Failed to get geometry of active window")?;
+        let position = xcb::translate_coordinates(&self.conn, window, root, 0, 0)
+            .get_reply()
+            .context("Failed to get position of active window")?;
+        let centre_x = i32::from(position.dst_x()) + i32::from(geometry.width()) / 2;
+        let centre_y = i32::from(position.dst_y()) + i32::from(geometry.height()) / 2;
+        let monitor = &self.monitor;
+        let within_x = centre_x >= i32::from(monitor.x)
+            && centre_x < i32::from(monitor.x) + i32::from(monitor.width);
+        let within_y = centre_y >= i32::from(monitor.y)
+            && centre_y < i32::from(monitor.y) + i32::from(monitor.height);
+        Ok(within_x && within_y)
+    }
+
     fn screen(&self) -> Result<xcb::Screen<'_>> {
         let screen = self
             .conn
             .get_setup()
             .roots()
             .nth(self.screen_idx)
-            .ok_or_else(|| format_err!("Invalid screen"))?;
+            .ok_or_else(|| Error::X("Invalid screen".to_owned()))?;
         Ok(screen)
     }
 
@@ -211,23 +1051,24 @@ impl Bar {
         if self.height != height {
             self.height = height;
 
-            // If we're at the bottom of the screen, we'll need to update the
-            // position of the window.
-            let y = match self.position {
-                Position::Top => 0,
-                Position::Bottom => self.screen()?.height_in_pixels() - self.height,
-            };
+            // If we're at the bottom or right of the monitor, we'll need to
+            // update the position of the window.
+            let (x, y, width, height) =
+                window_geometry(&self.position, &self.monitor, height, &self.options.margins);
 
-            // Update the height/position of the XCB window and the height of the Cairo surface.
+            // Update the size/position of the XCB window and the size of the Cairo surface.
             let values = [
-                (xcb::CONFIG_WINDOW_Y as u16, u32::from(y)),
-                (xcb::CONFIG_WINDOW_HEIGHT as u16, u32::from(self.height)),
+                (xcb::CONFIG_WINDOW_X as u16, x as u32),
+                (xcb::CONFIG_WINDOW_Y as u16, y as u32),
+                (xcb::CONFIG_WINDOW_WIDTH as u16, u32::from(width)),
+                (xcb::CONFIG_WINDOW_HEIGHT as u16, u32::from(height)),
                 (xcb::CONFIG_WINDOW_STACK_MODE as u16, xcb::STACK_MODE_ABOVE),
             ];
             xcb::configure_window(&self.conn, self.window_id, &values);
-            self.map_window();
-            self.surface
-                .set_size(i32::from(self.width), i32::from(self.height));
+            if !self.withdrawn {
+                self.map_window();
+            }
+            self.surface.set_size(i32::from(width), i32::from(height));
 
             // Update EWMH properties - we might need to reserve more or less space.
             self.set_ewmh_properties();
@@ -243,23 +1084,29 @@ impl Bar {
         //    the entire bar.
         //  - Otherwise, draw the texts that have changed since last time.
         let mut redraw_entire_bar = false;
+        let hidden = self.is_hidden();
+        let orientation = self.orientation();
 
         // Borrow these here, as otherwise our closures will try to borrow
         // self as both immutable/mutable.
         let surface = &self.surface;
+        let background = &self.palette.background;
         let contents = &mut self.contents;
+        let scale = self.scale;
 
         let it = new_contents
             .into_iter()
             .zip(contents.iter_mut())
             // We get a stream of updates from each widget, but not every
             // widget will have given us an update. Filter out those which are
-            // None (no update).
-            .filter_map(|(opt, old)| opt.map(|new| (new, old)))
+            // None (no update). Ignore widgets which aren't on this bar too.
+            .filter(|(_, slot)| !slot.excluded)
+            .filter_map(|(opt, slot)| opt.map(|new| (new, slot)))
             // Even if we have actually received an update, it may be identical
             // to the text it gave previously. (If that's the case, we can
             // avoid even calling .compute()).
-            .filter(|&(ref new, ref old)| {
+            .filter(|(new, slot)| {
+                let old = &slot.sources;
                 let length_different = new.len() != old.len();
                 let all_same = !length_different && new.iter().zip(old.iter()).all(|(n, o)| n == o);
                 !all_same
@@ -267,15 +1114,20 @@ impl Bar {
             // We finally have a list of the texts which have actually changed.
             // Call .compute() on each of the new texts so that we can get
             // layout information.
-            .map(|(new, old)| {
+            .map(|(new, slot)| {
+                slot.sources = new.clone();
                 new.into_iter()
-                    .map(|text| text.compute(surface))
+                    .map(|text| scale_text(text, scale).compute(surface, orientation))
                     .collect::<Result<Vec<ComputedText>>>()
-                    .map(|computeds| (computeds, old))
+                    .map(|mut computeds| {
+                        widen_to(&mut computeds, slot.min_width * scale);
+                        (computeds, slot)
+                    })
             })
             .collect::<Result<Vec<_>>>()?;
 
-        for (mut new_texts, old_texts) in it {
+        for (mut new_texts, slot) in it {
+            let old_texts = &mut slot.texts;
             // Redraw the entire bar if any of widget's non-stretch texts
             // have changed size, or if the number of texts for this widget
             // has changed. (Both of these would affect the size of other
@@ -287,20 +1139,24 @@ impl Bar {
                 || length_different
                 || new_texts.iter().zip(old_texts.iter()).any(|(new, old)| {
                     let not_stretch = !new.stretch && !old.stretch;
-                    let diff_width = (new.width - old.width).abs().round() >= 1.0;
+                    let diff_width = (new.natural_width - old.natural_width).abs().round() >= 1.0;
                     let diff_height = (new.height - old.height).abs().round() >= 1.0;
                     (not_stretch && diff_width) || diff_height
                 });
 
             // Where possible, re-use the position of the widget's previous
             // texts. (If we re-draw the entire bar, it'll get updated anyway).
-            // For stretch widgets, use the old width/height as well.
+            // Use the old width as well, as it may have been stretched or
+            // truncated, and for stretch widgets, the old height.
             for (new, old) in new_texts.iter_mut().zip(old_texts.iter()) {
                 new.x = old.x;
                 new.y = old.y;
-                if !redraw_entire_bar && new.stretch {
+                new.backdrop = old.backdrop.clone();
+                if !redraw_entire_bar {
                     new.width = old.width;
-                    new.height = old.height;
+                    if new.stretch {
+                        new.height = old.height;
+                    }
                 }
             }
 
@@ -308,8 +1164,9 @@ impl Bar {
             // (It would actually be better if we could delay this render until
             // after we've processes all the other widgets, in case any of them
             // need to redraw the entire bar. However, that's more effort than
-            // it's worth!)
-            if !redraw_entire_bar {
+            // it's worth!) Hidden widgets aren't rendered at all, and nothing
+            // is rendered while the bar itself is hidden.
+            if !redraw_entire_bar && !slot.hidden && !hidden {
                 let changed = new_texts
                     .iter()
                     .zip(old_texts.iter())
@@ -317,115 +1174,1172 @@ impl Bar {
                     .map(|(n, _)| n);
                 for text in changed {
                     trace!("Redrawing one");
-                    text.render(surface)?;
+                    text.render(surface, background, orientation)?;
                 }
             }
 
             mem::swap(&mut new_texts, old_texts);
         }
 
+        if hidden {
+            self.stale = true;
+        }
         Ok(redraw_entire_bar)
     }
 
     fn redraw_entire_bar(&mut self) -> Result<()> {
+        if self.is_hidden() {
+            // We'll redraw once we're visible again.
+            self.stale = true;
+            return Ok(());
+        }
         trace!("Redraw entire bar");
+        self.stale = false;
 
-        // Calculate how much free space we have after laying out all the
-        // non-stretch blocks. Split the remaining space (if any) between the
-        // stretch blocks. If there isn't enough space for the non-stretch blocks
-        // do nothing and allow it to overflow.
-        // While we're at it, we also calculate how
-        let screen_width = f64::from(
-            self.screen()
-                .context("Could not get screen width")?
-                .width_in_pixels(),
-        );
-        let width_per_stretched =
-            {
-                let texts = self.contents.iter().flatten();
-                let (stretched, non_stretched): (Vec<_>, Vec<_>) =
-                    texts.partition(|text| text.stretch);
-                let width = non_stretched.iter().fold(0.0, |acc, text| {
-                    if text.stretch {
-                        0.0
-                    } else {
-                        acc + text.width
-                    }
-                });
-                let remaining_width = (screen_width - width).max(0.0);
-                remaining_width / (stretched.len() as f64)
-            };
+        let screen_width = f64::from(self.length());
+        self.shrink_to_fit(screen_width);
+
+        // Each region with visible texts is its own section, whose padding
+        // is left around the outside of the region's texts.
+        let sections = self.sections();
+        let padding = sections
+            .clone()
+            .map(|style| style.map(|style| style.padding).unwrap_or_default());
+
+        // Work out where each region starts, how wide it is and how wide its
+        // stretch texts are.
+        let layout = self.layout_regions(screen_width);
 
         // Get the height of the biggest Text and set the bar to be that big.
         // TODO: Update all the Layouts so they all render that big too?
-        let height = self
-            .contents
-            .iter()
-            .flatten()
+        let text_height = self
+            .visible_texts()
             .fold(f64::NEG_INFINITY, |acc, text| text.height.max(acc));
+        let padding_y = [0, 1, 2].map(|region| padding[region].top + padding[region].bottom);
+        let natural_height = text_height + padding_y.iter().fold(0.0, |acc, &y| y.max(acc));
+        let height = self
+            .options
+            .height
+            .map_or(natural_height, |height| f64::from(height) * self.scale);
+        // Each region's texts are centered in a bar which is taller than they
+        // need.
+        let offset_y = padding_y.map(|y| ((height - text_height - y) / 2.0).max(0.0));
         if let Err(e) = self.update_bar_height(height as u16) {
             // Log and continue - the bar is hopefully still useful.
             error!("Failed to update bar height to {}: {}", height, e);
         }
+        let orientation = self.orientation();
+
+        // Clear the bar to its background, which may be translucent, before
+        // drawing anything over it.
+        let context = cairo::Context::new(&self.surface);
+        context.set_operator(cairo::Operator::Source);
+        self.palette.background.apply_to_context(&context);
+        context.paint();
+
+        let mut backdrops: [Option<Backdrop>; 3] = Default::default();
+        for (region, style) in sections.iter().enumerate() {
+            if let Some(style) = style {
+                let (start, width, _) = layout[region];
+                let backdrop = Backdrop {
+                    color: style.bg_color.clone(),
+                    corner_radius: style.corner_radius,
+                    x: start,
+                    y: 0.0,
+                    width,
+                    height,
+                };
+                backdrop.render(&self.surface, orientation);
+                backdrops[region] = Some(backdrop);
+            }
+        }
 
         // Render each Text in turn. If it's a stretch block, override its width
         // with the width we've just computed. Regardless of whether it's a stretch
         // block, override its height - everything should be as big as the biggest item.
-        let texts = self.contents.iter_mut().flatten();
-        let mut x = 0.0;
-        for text in texts {
-            if text.stretch {
-                text.width = width_per_stretched;
+        let mut x = [0, 1, 2].map(|region| layout[region].0 + padding[region].left);
+        let mut started = [false; 3];
+        for (i, slot) in self
+            .contents
+            .iter_mut()
+            .enumerate()
+            .filter(|(_, slot)| !slot.hidden)
+        {
+            let region = slot.options.region as usize;
+            if slot.texts.is_empty() {
+                continue;
+            }
+            if started[region] {
+                x[region] += self.options.spacing;
+            }
+            started[region] = true;
+            let start = x[region];
+            for text in &mut slot.texts {
+                if text.stretch {
+                    text.width = layout[region].2;
+                }
+                text.x = x[region];
+                text.y = padding[region].top + offset_y[region];
+                text.backdrop = backdrops[region].clone();
+                text.render(&self.surface, &self.palette.background, orientation)?;
+                x[region] += text.width;
+            }
+            if self.focused == Some(i) {
+                // Underline the widget in the color of its text.
+                let context = cairo::Context::new(&self.surface);
+                slot.texts[0].attr.fg_color.apply_to_context(&context);
+                let (x, y, width, height) = orientation.bar_to_window(
+                    start,
+                    height - FOCUS_THICKNESS,
+                    x[region] - start,
+                    FOCUS_THICKNESS,
+                );
+                context.rectangle(x, y, width, height);
+                context.fill();
             }
-            text.x = x;
-            text.y = 0.0;
-            text.render(&self.surface)?;
-            x += text.width;
         }
 
         Ok(())
     }
 
-    pub fn run_event_loop(
-        mut self,
-        handle: &Handle,
-        widgets: Vec<Box<dyn Widget>>,
-    ) -> Result<Box<dyn Future<Item = (), Error = Error>>> {
-        self.contents = vec![Vec::new(); widgets.len()];
+    /// Lays out each `Region` within `available_width`, returning the offset
+    /// at which each region starts, its width (including the padding of its
+    /// section) and the width of each of its stretch texts.
+    ///
+    /// The left and right regions are pushed against the edges of the bar,
+    /// and the center region is centered on it (as far as it can be, without
+    /// overlapping the others). Stretch texts in the left or right region
+    /// share the gap between their region and the center region. If the
+    /// center region has stretch texts (or is empty), every stretch text
+    /// shares all of the free space instead, so the regions fill the bar.
+    fn layout_regions(&self, available_width: f64) -> [(f64, f64, f64); 3] {
+        // The width of the non-stretch texts and the number of stretch texts
+        // in each region.
+        let mut widths = self.spacing();
+        for (width, padding) in widths.iter_mut().zip(&self.section_padding()) {
+            *width += padding;
+        }
+        let mut stretched = [0usize; 3];
+        for slot in self.contents.iter().filter(|slot| !slot.hidden) {
+            let region = slot.options.region as usize;
+            for text in &slot.texts {
+                if text.stretch {
+                    stretched[region] += 1;
+                } else {
+                    widths[region] += text.width;
+                }
+            }
+        }
+        let (left, center, right) = (
+            Region::Left as usize,
+            Region::Center as usize,
+            Region::Right as usize,
+        );
+        let share = |gap: f64, count: usize| if count > 0 { gap / count as f64 } else { 0.0 };
+
+        let center_is_empty = widths[center] == 0.0 && stretched[center] == 0;
+        if stretched[center] > 0 || center_is_empty {
+            let free = (available_width - widths.iter().sum::<f64>()).max(0.0);
+            let per_stretched = share(free, stretched.iter().sum());
+            let width = |region: usize| widths[region] + per_stretched * stretched[region] as f64;
+            return [
+                (0.0, width(left), per_stretched),
+                (width(left), width(center), per_stretched),
+                (available_width - width(right), width(right), per_stretched),
+            ];
+        }
 
-        enum Event {
-            Xcb(<XcbEventStream as Stream>::Item),
-            Widget(<WidgetList as Stream>::Item),
+        let center_start = ((available_width - widths[center]) / 2.0)
+            .min(available_width - widths[right] - widths[center])
+            .max(widths[left]);
+        let center_end = center_start + widths[center];
+        let left_gap = (center_start - widths[left]).max(0.0);
+        let right_gap = (available_width - widths[right] - center_end).max(0.0);
+        let right_start = if stretched[right] > 0 {
+            center_end
+        } else {
+            available_width - widths[right]
         };
+        let (left_stretch, right_stretch) = (
+            share(left_gap, stretched[left]),
+            share(right_gap, stretched[right]),
+        );
+        [
+            (
+                0.0,
+                widths[left] + left_stretch * stretched[left] as f64,
+                left_stretch,
+            ),
+            (center_start, widths[center], 0.0),
+            (
+                right_start,
+                widths[right] + right_stretch * stretched[right] as f64,
+                right_stretch,
+            ),
+        ]
+    }
 
-        let events_stream = XcbEventStream::new(self.conn.clone(), handle)?.map(Event::Xcb);
-        let widget_updates_stream = WidgetList::new(widgets)?.map(Event::Widget);
-        let event_loop = events_stream.select(widget_updates_stream);
-
-        let fut = event_loop.for_each(move |event| {
-            let redraw_entire_bar = match event {
-                Event::Widget(update) => match self.update_widget_contents(update) {
-                    Ok(b) => b,
-                    Err(e) => return future::err(e),
-                },
-                Event::Xcb(event) => event.response_type() & !0x80 == xcb::EXPOSE,
-            };
+    /// Finds the text under the point (`x`, `y`) on the bar's window,
+    /// returning the index of its widget and the `Click` on it.
+    fn click_at(&self, button: u8, x: f64, y: f64) -> Option<(usize, Click)> {
+        let orientation = self.orientation();
+        let (x, y) = orientation.window_to_bar(x, y);
+        self.contents
+            .iter()
+            .enumerate()
+            .filter(|(_, slot)| !slot.hidden && !slot.excluded)
+            .find_map(|(i, slot)| {
+                slot.texts
+                    .iter()
+                    .enumerate()
+                    .find(|(_, text)| x >= text.x && x < text.x + text.width)
+                    .map(|(t, text)| {
+                        // Upright texts' axes are the window's, not the bar's.
+                        let (x, y) = match orientation {
+                            BarOrientation::Vertical => (y - text.y, x - text.x),
+                            _ => (x - text.x, y - text.y),
+                        };
+                        let click = Click {
+                            button,
+                            x,
+                            y,
+                            text: t,
+                        };
+                        (i, click)
+                    })
+            })
+    }
 
-            if redraw_entire_bar {
-                if let Err(e) = self.redraw_entire_bar() {
-                    error!("Error redrawing bar: {}", e);
-                    return future::err(e);
+    /// Returns where each widget's texts are on this bar, or `None` for the
+    /// widgets which aren't shown on it.
+    fn placements(&self) -> Vec<Option<Placement>> {
+        let orientation = self.orientation();
+        self.contents
+            .iter()
+            .map(|slot| {
+                if slot.excluded {
+                    return None;
                 }
+                let texts = if slot.hidden {
+                    Vec::new()
+                } else {
+                    slot.texts
+                        .iter()
+                        .map(|text| {
+                            let (x, y, width, height) =
+                                orientation.bar_to_window(text.x, text.y, text.width, text.height);
+                            Area {
+                                x,
+                                y,
+                                width,
+                                height,
+                            }
+                        })
+                        .collect()
+                };
+                Some(Placement {
+                    window: self.window_id,
+                    texts,
+                })
+            })
+            .collect()
+    }
+
+    fn visible_texts(&self) -> impl Iterator<Item = &ComputedText> {
+        self.contents
+            .iter()
+            .filter(|slot| !slot.hidden)
+            .flat_map(|slot| slot.texts.iter())
+    }
+
+    /// Returns the width of the spacing between the visible widgets in each
+    /// `Region`.
+    fn spacing(&self) -> [f64; 3] {
+        let mut widgets = [0usize; 3];
+        for slot in &self.contents {
+            if !slot.hidden && !slot.texts.is_empty() {
+                widgets[slot.options.region as usize] += 1;
             }
-            self.conn.flush();
+        }
+        widgets.map(|count| count.saturating_sub(1) as f64 * self.options.spacing)
+    }
 
-            future::ok(())
-        });
+    /// Returns the style of each `Region`'s section, or `None` for the
+    /// regions without a style or without any visible texts.
+    fn sections(&self) -> [Option<SectionStyle>; 3] {
+        let mut sections: [Option<SectionStyle>; 3] = Default::default();
+        for slot in &self.contents {
+            let region = slot.options.region as usize;
+            if !slot.hidden && !slot.texts.is_empty() {
+                sections[region] = self.palette.section_styles[region].clone();
+            }
+        }
+        sections
+    }
+
+    /// Returns the width of the padding at the ends of each `Region`'s
+    /// section.
+    fn section_padding(&self) -> [f64; 3] {
+        self.sections()
+            .map(|style| style.map_or(0.0, |style| style.padding.left + style.padding.right))
+    }
+
+    /// Hides and truncates widgets until their non-stretch texts fit into
+    /// `available_width`.
+    ///
+    /// Widgets are hidden entirely, lowest priority first, until either
+    /// everything fits or only widgets of the highest priority remain. If
+    /// that isn't enough, the remaining widgets' texts are truncated, again
+    /// lowest priority first.
+    fn shrink_to_fit(&mut self, available_width: f64) {
+        let natural_width = |slot: &Slot| {
+            slot.texts
+                .iter()
+                .filter(|text| !text.stretch)
+                .map(|text| text.natural_width)
+                .sum::<f64>()
+        };
+
+        for slot in &mut self.contents {
+            slot.hidden = slot.concealed;
+            for text in &mut slot.texts {
+                text.width = text.natural_width;
+            }
+        }
+
+        // Widgets with the lowest priority come first. Of those with equal
+        // priority, those furthest to the right come first.
+        let mut order = (0..self.contents.len()).collect::<Vec<_>>();
+        order.sort_by_key(|&i| (self.contents[i].options.priority, Reverse(i)));
+        let max_priority = order
+            .last()
+            .map_or(0, |&i| self.contents[i].options.priority);
+
+        let mut overflow = self
+            .contents
+            .iter()
+            .filter(|slot| !slot.hidden)
+            .map(natural_width)
+            .sum::<f64>()
+            + self.spacing().iter().sum::<f64>()
+            + self.section_padding().iter().sum::<f64>()
+            - available_width;
+        for &i in &order {
+            let slot = &mut self.contents[i];
+            if slot.hidden {
+                continue;
+            }
+            if overflow <= 0.0 || slot.options.priority == max_priority {
+                break;
+            }
+            debug!("Hiding widget {} to make room for others", i);
+            slot.hidden = true;
+            overflow -= natural_width(slot);
+            if !slot.texts.is_empty() && overflow > 0.0 {
+                // There is one less space between widgets too. (Unless it
+                // was the only widget in its region, but close enough).
+                overflow -= self.options.spacing;
+            }
+        }
+
+        for &i in &order {
+            let slot = &mut self.contents[i];
+            if slot.hidden {
+                continue;
+            }
+            for text in slot.texts.iter_mut().rev().filter(|text| !text.stretch) {
+                if overflow <= 0.0 {
+                    return;
+                }
+                let shrink_by = overflow.min(text.width);
+                text.width -= shrink_by;
+                overflow -= shrink_by;
+            }
+        }
+    }
+
+    /// Moves the keyboard highlight `step` widgets along, wrapping around,
+    /// or onto the first widget if none is highlighted. Only the widgets with
+    /// `click_handlers` can be highlighted.
+    fn move_focus(&mut self, step: isize, click_handlers: &[Option<ClickHandler>]) -> Result<()> {
+        let focusable = self
+            .contents
+            .iter()
+            .enumerate()
+            .filter(|(i, slot)| {
+                !slot.hidden
+                    && !slot.excluded
+                    && !slot.texts.is_empty()
+                    && click_handlers.get(*i).is_some_and(Option::is_some)
+            })
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+        let position = self
+            .focused
+            .and_then(|focused| focusable.iter().position(|&i| i == focused));
+        let focused = match position {
+            Some(position) => {
+                let len = focusable.len() as isize;
+                Some(focusable[(position as isize + step).rem_euclid(len) as usize])
+            }
+            None => focusable.first().cloned(),
+        };
+        self.set_focus(focused)
+    }
+
+    /// Highlights the given widget for keyboard navigation, or none.
+    fn set_focus(&mut self, focused: Option<usize>) -> Result<()> {
+        if self.focused == focused {
+            return Ok(());
+        }
+        self.focused = focused;
+        self.redraw_entire_bar()
+    }
+
+    /// Grabs the keyboard, so that every key press comes to the bar,
+    /// returning whether it could be grabbed.
+    fn grab_keyboard(&self) -> bool {
+        let cookie = xcb::grab_keyboard(
+            &self.conn,
+            false,
+            self.window_id,
+            xcb::CURRENT_TIME,
+            xcb::GRAB_MODE_ASYNC as u8,
+            xcb::GRAB_MODE_ASYNC as u8,
+        );
+        matches!(
+            cookie.get_reply(),
+            Ok(ref reply) if reply.status() == xcb::GRAB_STATUS_SUCCESS as u8
+        )
+    }
+
+    /// Hides or shows the widgets with the given `name`, returning whether
+    /// there were any.
+    fn conceal(&mut self, name: &str, concealed: bool) -> Result<bool> {
+        let mut found = false;
+        for slot in &mut self.contents {
+            if slot.options.name.as_deref() == Some(name) {
+                slot.concealed = concealed;
+                found = true;
+            }
+        }
+        if found {
+            self.redraw_entire_bar()?;
+        }
+        Ok(found)
+    }
+
+    fn flush(&self) {
+        self.conn.flush();
+    }
+}
+
+/// Tells the widgets with `layout_handlers` where their texts are on `bar`,
+/// if they have moved since it was `placed`.
+fn notify_layout(
+    bar: &Bar,
+    placed: &mut Vec<Option<Placement>>,
+    layout_handlers: &mut [Option<LayoutHandler>],
+) {
+    let placements = bar.placements();
+    for (i, handler) in layout_handlers.iter_mut().enumerate() {
+        if let (Some(handler), Some(Some(placement))) = (handler, placements.get(i)) {
+            if placed.get(i) != Some(&Some(placement.clone())) {
+                if let Err(e) = handler(placement.clone()) {
+                    error!("Error handling layout: {}", e);
+                }
+            }
+        }
+    }
+    *placed = placements;
+}
+
+/// Multiplies the size of `text`'s font and its padding by `scale`.
+fn scale_text(mut text: Text, scale: f64) -> Text {
+    if scale != 1.0 {
+        text.attr = text.attr.scaled(scale);
+    }
+    text
+}
+
+/// Widens the last non-stretch text of a widget's `texts`, if need be, so
+/// that they take up at least `min_width` between them.
+fn widen_to(texts: &mut [ComputedText], min_width: f64) {
+    let width = texts.iter().map(|text| text.natural_width).sum::<f64>();
+    if width >= min_width {
+        return;
+    }
+    if let Some(text) = texts.iter_mut().rev().find(|text| !text.stretch) {
+        text.natural_width += min_width - width;
+        text.width = text.natural_width;
+    }
+}
+
+/// A new set of widgets, and the bars' options, to replace the current ones
+/// while the event loop is running.
+pub(crate) struct Reload {
+    pub widgets: Vec<(Box<dyn Widget>, WidgetOptions)>,
+    pub options: BarOptions,
+}
+
+pub(crate) type Reloads = Box<dyn Stream<Item = Reload, Error = Error>>;
+
+/// The widgets shown on the bars, which are replaced when they're reloaded.
+pub(crate) struct Installed {
+    pub options: Vec<WidgetOptions>,
+    pub click_handlers: Vec<Option<ClickHandler>>,
+    pub layout_handlers: Vec<Option<LayoutHandler>>,
+    // Where each bar's widgets were last told their texts are.
+    pub placed: Vec<Vec<Option<Placement>>>,
+    pub refreshers: Vec<mpsc::UnboundedSender<()>>,
+}
+
+impl Installed {
+    /// Asks the widgets with the given `name` to update immediately.
+    pub fn refresh(&self, name: &str) {
+        let indices = self
+            .options
+            .iter()
+            .enumerate()
+            .filter(|(_, options)| options.name.as_deref() == Some(name))
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+        if indices.is_empty() {
+            warn!("Can't refresh unknown widget: {}", name);
+        }
+        for i in indices {
+            // The widget's stream may have finished, in which case there's
+            // nothing to refresh.
+            let _ = self.refreshers[i].unbounded_send(());
+        }
+    }
+}
+
+/// The stream of every widget's updates, which can be swapped for the new
+/// widgets' stream while it is being polled by the event loop.
+struct SharedWidgetList(Rc<RefCell<WidgetList>>);
+
+impl Stream for SharedWidgetList {
+    type Item = <WidgetList as Stream>::Item;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        self.0.borrow_mut().poll()
+    }
+}
+
+/// Gives each of the `bars` a slot for each of the `widgets`, returning the
+/// widgets' stream of updates along with how to refresh and click them.
+pub(crate) fn install_widgets(
+    bars: &mut [Bar],
+    timer: &Timer,
+    mut widgets: Vec<(Box<dyn Widget>, WidgetOptions)>,
+    error_flash: Option<(Attributes, Duration)>,
+) -> Result<(WidgetList, Installed)> {
+    let errors = error_flash.map(|(attr, duration)| {
+        let (sender, errors) = mpsc::unbounded();
+        let flash = ErrorFlash {
+            errors,
+            attr,
+            duration,
+        };
+        let options = WidgetOptions {
+            region: Region::Right,
+            priority: u8::MAX,
+            ..WidgetOptions::default()
+        };
+        widgets.push((Box::new(flash), options));
+        sender
+    });
+    let (widgets, options): (Vec<_>, Vec<_>) = widgets.into_iter().unzip();
+    let placed = vec![Vec::new(); bars.len()];
+    // Whether each widget is still showing its texts from before a reload.
+    let mut carried = vec![false; options.len()];
+    // The bars aren't changed until every slot has been made, in case one
+    // can't be.
+    let contents = bars
+        .iter()
+        .map(|bar| {
+            let orientation = bar.orientation();
+            options
+                .iter()
+                .enumerate()
+                .map(|(i, options)| {
+                    let widest = match options.widest {
+                        Some(ref text) => {
+                            text.clone()
+                                .compute(&bar.surface, orientation)?
+                                .natural_width
+                        }
+                        None => 0.0,
+                    };
+                    let min_width = options.min_width.max(widest);
+                    let excluded = options.scratchpad != bar.scratchpad
+                        || match (&options.monitor, &bar.monitor.name) {
+                            (Some(wanted), Some(name)) => wanted != name,
+                            (Some(_), None) => true,
+                            (None, _) => false,
+                        };
+                    // When the widgets are reloaded, keep showing what the
+                    // widget in the same place showed until the new one has
+                    // something to show.
+                    let sources = match bar.contents.get(i) {
+                        Some(old)
+                            if !excluded
+                                && old.options.name == options.name
+                                && old.options.region == options.region =>
+                        {
+                            old.sources.clone()
+                        }
+                        _ => Vec::new(),
+                    };
+                    let mut texts = sources
+                        .iter()
+                        .map(|text| {
+                            scale_text(text.clone(), bar.scale).compute(&bar.surface, orientation)
+                        })
+                        .collect::<Result<Vec<_>>>()?;
+                    if !texts.is_empty() {
+                        widen_to(&mut texts, min_width * bar.scale);
+                        carried[i] = true;
+                    }
+                    Ok(Slot {
+                        options: options.clone(),
+                        texts,
+                        sources,
+                        min_width,
+                        hidden: false,
+                        concealed: false,
+                        excluded,
+                    })
+                })
+                .collect::<Result<Vec<_>>>()
+        })
+        .collect::<Result<Vec<_>>>()?;
+    for (bar, contents) in bars.iter_mut().zip(contents) {
+        // The widgets may not be where they were.
+        bar.focused = None;
+        bar.contents = contents;
+    }
+
+    let mut click_handlers = Vec::new();
+    let mut layout_handlers = Vec::new();
+    // For widgets which wait for an X selection to be owned.
+    let conn = bars.first().map(|bar| bar.conn.clone());
+    let (widget_list, refreshers) = WidgetList::new(widgets, |i, widget, refresh| {
+        let options = &options[i];
+        let (stream, restart) = match options.factory {
+            Some(ref factory) => {
+                let mut restart = Restart {
+                    factory: factory.clone(),
+                    options: options.clone(),
+                    timer: timer.clone(),
+                    conn: conn.clone(),
+                    refresh: Rc::new(RefCell::new(refresh)),
+                    click_handler: Rc::new(RefCell::new(None)),
+                    layout_handler: Rc::new(RefCell::new(None)),
+                };
+                let stream = restart.start(widget);
+                click_handlers.push(forward(&restart.click_handler));
+                layout_handlers.push(forward(&restart.layout_handler));
+                (stream, Some(restart))
+            }
+            None => {
+                let mut widget = Delayed::wrap(widget, options, timer, conn.as_ref());
+                click_handlers.push(widget.click_handler());
+                layout_handlers.push(widget.layout_handler());
+                (widget.refreshable_stream(refresh), None)
+            }
+        };
+        let supervised = Supervised {
+            stream: Some(stream.unwrap_or_else(|e| Box::new(stream::once(Err(e))))),
+            restart,
+            timer: timer.clone(),
+            policy: options.restart_policy,
+            name: options.name.clone().unwrap_or_else(|| "widget".to_owned()),
+            attr: options.placeholder.as_ref().map(|text| text.attr.clone()),
+            // The widget's old texts are better than a placeholder.
+            placeholder: options.placeholder.clone().filter(|_| !carried[i]),
+            failures: 0,
+            retry: None,
+            errors: errors.clone(),
+        };
+        // Clear any texts which only last a while, whichever widget they
+        // come from.
+        Box::new(timer.expiring(supervised))
+    });
+    let installed = Installed {
+        options,
+        click_handlers,
+        layout_handlers,
+        placed,
+        refreshers,
+    };
+    Ok((widget_list, installed))
+}
+
+/// Navigating the widgets of one of the bars with the keyboard, once a
+/// hotkey is pressed. See `Cnx::set_keyboard_hotkey()`.
+pub(crate) struct KeyboardNav {
+    hotkey: Hotkey,
+    keymap: Keymap,
+    // The bar which is navigated, and is sent the hotkey.
+    bar: usize,
+    // Whether the keyboard is grabbed, to navigate the bar.
+    active: bool,
+}
+
+impl KeyboardNav {
+    /// Grabs the `hotkey` for navigating the first of the `bars` which isn't a
+    /// scratchpad bar.
+    pub fn new(bars: &[Bar], hotkey: Hotkey) -> Result<KeyboardNav> {
+        let (i, bar) = bars
+            .iter()
+            .enumerate()
+            .find(|(_, bar)| !bar.scratchpad)
+            .ok_or_else(|| format_err!("There are no bars to navigate"))?;
+        let keymap = Keymap::new(&bar.conn)?;
+        hotkey.grab(&bar.conn, &keymap, bar.screen()?.root())?;
+        bar.flush();
+        Ok(KeyboardNav {
+            hotkey,
+            keymap,
+            bar: i,
+            active: false,
+        })
+    }
+
+    /// Handles a key press on the navigated `bar`, starting or stopping
+    /// navigation, moving the highlight or clicking the highlighted widget.
+    fn key_press(
+        &mut self,
+        bar: &mut Bar,
+        click_handlers: &mut [Option<ClickHandler>],
+        event: &xcb::KeyPressEvent,
+    ) -> Result<()> {
+        let keysym = self.keymap.keysym(event.detail());
+        let hotkey = self.hotkey.matches(keysym, event.state());
+        if !self.active {
+            if !hotkey {
+                return Ok(());
+            }
+            if !bar.grab_keyboard() {
+                warn!("Failed to grab the keyboard, so the bar can't be navigated");
+                return Ok(());
+            }
+            debug!("Navigating the bar with the keyboard");
+            self.active = true;
+            return bar.move_focus(0, click_handlers);
+        }
+
+        let shift = event.state() & xcb::MOD_MASK_SHIFT as u16 != 0;
+        match keysym {
+            _ if hotkey => self.stop(bar),
+            keyboard::KEY_ESCAPE => self.stop(bar),
+            keyboard::KEY_TAB if shift => bar.move_focus(-1, click_handlers),
+            keyboard::KEY_LEFT | keyboard::KEY_UP => bar.move_focus(-1, click_handlers),
+            keyboard::KEY_RIGHT | keyboard::KEY_DOWN | keyboard::KEY_TAB => {
+                bar.move_focus(1, click_handlers)
+            }
+            keyboard::KEY_RETURN | keyboard::KEY_KP_ENTER | keyboard::KEY_SPACE => {
+                let focused = bar.focused;
+                self.stop(bar)?;
+                if let Some(Some(ref mut handler)) = focused.map(|i| &mut click_handlers[i]) {
+                    let click = Click {
+                        button: 1,
+                        x: 0.0,
+                        y: 0.0,
+                        text: 0,
+                    };
+                    debug!("Widget {:?} activated with the keyboard", focused);
+                    if let Err(e) = handler(click) {
+                        error!("Error handling click: {}", e);
+                    }
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
 
-        Ok(Box::new(fut))
+    /// Stops navigating the bar, giving the keyboard back.
+    fn stop(&mut self, bar: &mut Bar) -> Result<()> {
+        self.active = false;
+        xcb::ungrab_keyboard(&bar.conn, xcb::CURRENT_TIME);
+        bar.set_focus(None)
     }
 }
 
+/// Runs the event loop which redraws each of the `bars` whenever their X
+/// windows are exposed, or whenever the `widgets` update. Clicks on the bars
+/// are passed to the widget which was clicked.
+///
+/// Every bar shows the same widgets, except for those which have been
+/// restricted to a different monitor.
+///
+/// While every bar is hidden, polling widgets are paused and nothing is
+/// drawn.
+///
+/// Widgets are refreshed, hidden and shown, and the bars hidden and shown,
+/// by the `commands` sent by a `Control`. Scratchpad bars are hidden again
+/// after `scratchpad_timeout`, or when there is a click anywhere else.
+///
+/// If there's an `error_flash`, widgets' errors are shown at the right of
+/// the bars for a while.
+///
+/// Each of the `reloads` replaces every widget, without recreating the bars.
+/// The bars keep showing the old widgets until the new ones have updated.
+///
+/// If there's a `keyboard`, its hotkey starts navigating its bar's widgets
+/// with the keyboard.
+#[allow(clippy::too_many_arguments)]
+pub fn run_event_loop(
+    handle: &Handle,
+    timer: Timer,
+    mut bars: Vec<Bar>,
+    widgets: Vec<(Box<dyn Widget>, WidgetOptions)>,
+    commands: Commands,
+    scratchpad_timeout: Option<Duration>,
+    error_flash: Option<(Attributes, Duration)>,
+    color_schemes: Option<ColorSchemes>,
+    reloads: Option<Reloads>,
+    mut keyboard: Option<KeyboardNav>,
+) -> Result<Box<dyn Future<Item = (), Error = Error>>> {
+    let (widget_list, mut installed) =
+        install_widgets(&mut bars, &timer, widgets, error_flash.clone())?;
+    let widget_list = Rc::new(RefCell::new(widget_list));
+
+    enum Event {
+        Xcb(usize, <XcbEventStream as Stream>::Item),
+        Widget(<WidgetList as Stream>::Item),
+        Command(Command),
+        // The scratchpad timed out, having been shown for the nth time.
+        ScratchpadExpired(usize),
+        // A bar's nth wait before hiding itself finished.
+        AutoHide(usize, usize),
+        ColorScheme(ColorScheme),
+        Reload(Box<Reload>),
+    }
+
+    let commands = commands
+        .map(Event::Command)
+        .map_err(|()| format_err!("Control channel closed"));
+    let (expiry_sender, expiries) = mpsc::unbounded();
+    let expiries = expiries
+        .map(Event::ScratchpadExpired)
+        .map_err(|()| format_err!("Scratchpad expiry channel closed"));
+    let (auto_hide_sender, auto_hides) = mpsc::unbounded();
+    let auto_hides = auto_hides
+        .map(|(i, wait)| Event::AutoHide(i, wait))
+        .map_err(|()| format_err!("Auto-hide channel closed"));
+    let mut event_loop: Box<dyn Stream<Item = Event, Error = Error>> = Box::new(
+        SharedWidgetList(widget_list.clone())
+            .map(Event::Widget)
+            .select(commands)
+            .select(expiries)
+            .select(auto_hides),
+    );
+    if let Some(color_schemes) = color_schemes {
+        event_loop = Box::new(event_loop.select(color_schemes.map(Event::ColorScheme)));
+    }
+    if let Some(reloads) = reloads {
+        event_loop =
+            Box::new(event_loop.select(reloads.map(|reload| Event::Reload(Box::new(reload)))));
+    }
+    for (i, bar) in bars.iter().enumerate() {
+        let events_stream =
+            XcbEventStream::new(bar.conn.clone(), handle)?.map(move |event| Event::Xcb(i, event));
+        event_loop = Box::new(event_loop.select(events_stream));
+    }
+
+    // How many times the scratchpad has been shown, so that only the most
+    // recent showing's timeout hides it.
+    let scratchpad_shown = Rc::new(Cell::new(0));
+    let show_scratchpad = {
+        let scratchpad_shown = scratchpad_shown.clone();
+        let handle = handle.clone();
+        let timer = timer.clone();
+        move |bars: &mut [Bar], shown: bool| -> Result<()> {
+            withdraw(bars, true, !shown)?;
+            if !shown {
+                return Ok(());
+            }
+            scratchpad_shown.set(scratchpad_shown.get() + 1);
+            if let Some(timeout) = scratchpad_timeout {
+                let shown = scratchpad_shown.get();
+                let sender = expiry_sender.clone();
+                handle.spawn(timer.sleep(timeout).then(move |_| {
+                    let _ = sender.unbounded_send(shown);
+                    Ok(())
+                }));
+            }
+            Ok(())
+        }
+    };
+
+    // Starts the wait before the shown bars which hide themselves do so, or
+    // before just the given bar does.
+    let start_auto_hide = {
+        let handle = handle.clone();
+        let timer = timer.clone();
+        move |bars: &mut [Bar], only: Option<usize>| {
+            for (i, bar) in bars.iter_mut().enumerate() {
+                if bar.withdrawn || only.is_some_and(|only| only != i) {
+                    continue;
+                }
+                if let Some((timeout, wait)) = bar.start_auto_hide() {
+                    let sender = auto_hide_sender.clone();
+                    handle.spawn(timer.sleep(timeout).then(move |_| {
+                        let _ = sender.unbounded_send((i, wait));
+                        Ok(())
+                    }));
+                }
+            }
+        }
+    };
+    start_auto_hide(&mut bars, None);
+
+    // Whether the widgets have been reloaded since the bars were last drawn,
+    // in which case they need redrawing entirely.
+    let mut reloaded = false;
+    let mut cache = TextCache::default();
+
+    let fut = event_loop.for_each(move |event| {
+        let result = match event {
+            Event::Widget(update) => {
+                let update = match cache.filter(update) {
+                    Some(update) => update,
+                    // Nothing changed, so there's nothing to lay out.
+                    None => return future::ok(()),
+                };
+                let result = bars.iter_mut().try_for_each(|bar| {
+                    if bar.update_widget_contents(update.clone())? || reloaded {
+                        bar.redraw_entire_bar()?;
+                    }
+                    bar.flush();
+                    Ok(())
+                });
+                reloaded = false;
+                result
+            }
+            Event::Xcb(i, event) => {
+                let mut dismiss_scratchpad = false;
+                let mut start_wait = false;
+                let bar = &mut bars[i];
+                let result = match event.response_type() & !0x80 {
+                    xcb::EXPOSE => bar.redraw_entire_bar(),
+                    xcb::PROPERTY_NOTIFY => {
+                        let event: &xcb::PropertyNotifyEvent = unsafe { xcb::cast_event(&event) };
+                        let atom = event.atom();
+                        if atom == bar.conn.ACTIVE_WINDOW() || atom == bar.conn.WM_STATE() {
+                            bar.update_covered()
+                        } else {
+                            Ok(())
+                        }
+                    }
+                    xcb::BUTTON_PRESS => {
+                        let event: &xcb::ButtonPressEvent = unsafe { xcb::cast_event(&event) };
+                        let x = f64::from(event.event_x());
+                        let y = f64::from(event.event_y());
+                        if bar.scratchpad && bar.is_outside(x, y) {
+                            // A click elsewhere, seen because of our grab.
+                            dismiss_scratchpad = true;
+                        } else if let Some((widget, click)) = bar.click_at(event.detail(), x, y) {
+                            if let Some(ref mut handler) = installed.click_handlers[widget] {
+                                debug!("Widget {} clicked: {:?}", widget, click);
+                                if let Err(e) = handler(click) {
+                                    error!("Error handling click: {}", e);
+                                }
+                            }
+                        }
+                        Ok(())
+                    }
+                    xcb::KEY_PRESS => {
+                        let event: &xcb::KeyPressEvent = unsafe { xcb::cast_event(&event) };
+                        match keyboard {
+                            Some(ref mut keyboard) if keyboard.bar == i => {
+                                keyboard.key_press(bar, &mut installed.click_handlers, event)
+                            }
+                            _ => Ok(()),
+                        }
+                    }
+                    xcb::ENTER_NOTIFY => {
+                        let event: &xcb::EnterNotifyEvent = unsafe { xcb::cast_event(&event) };
+                        if bar.is_auto_hide_trigger(event.event()) {
+                            // The pointer reached the edge of the monitor,
+                            // and will hide the bar again unless it moves
+                            // onto it.
+                            start_wait = true;
+                            bar.set_withdrawn(false)
+                        } else {
+                            bar.cancel_auto_hide();
+                            Ok(())
+                        }
+                    }
+                    xcb::LEAVE_NOTIFY => {
+                        let event: &xcb::LeaveNotifyEvent = unsafe { xcb::cast_event(&event) };
+                        // Ignore the pointer being grabbed for a scratchpad.
+                        start_wait = event.mode() == xcb::NOTIFY_MODE_NORMAL as u8;
+                        Ok(())
+                    }
+                    _ => Ok(()),
+                };
+                bar.flush();
+                if start_wait {
+                    start_auto_hide(&mut bars, Some(i));
+                }
+                if dismiss_scratchpad {
+                    result.and_then(|()| show_scratchpad(&mut bars, false))
+                } else {
+                    result
+                }
+            }
+            Event::Command(Command::Refresh(name)) => {
+                installed.refresh(&name);
+                Ok(())
+            }
+            Event::Command(Command::Hide(name)) => conceal(&mut bars, &name, true),
+            Event::Command(Command::Show(name)) => conceal(&mut bars, &name, false),
+            Event::Command(Command::HideBar) => withdraw(&mut bars, false, true),
+            Event::Command(Command::ShowBar) => {
+                let result = withdraw(&mut bars, false, false);
+                start_auto_hide(&mut bars, None);
+                result
+            }
+            Event::Command(Command::ToggleBar) => {
+                let withdrawn = bars.iter().any(|bar| !bar.scratchpad && bar.withdrawn);
+                let result = withdraw(&mut bars, false, !withdrawn);
+                start_auto_hide(&mut bars, None);
+                result
+            }
+            Event::Command(Command::HideScratchpad) => show_scratchpad(&mut bars, false),
+            Event::Command(Command::ShowScratchpad) => show_scratchpad(&mut bars, true),
+            Event::Command(Command::ToggleScratchpad) => {
+                let shown = bars.iter().any(|bar| bar.scratchpad && !bar.withdrawn);
+                show_scratchpad(&mut bars, !shown)
+            }
+            Event::ScratchpadExpired(shown) if shown == scratchpad_shown.get() => {
+                show_scratchpad(&mut bars, false)
+            }
+            Event::ScratchpadExpired(_) => Ok(()),
+            Event::AutoHide(i, wait) => {
+                let bar = &mut bars[i];
+                let result = bar.auto_hide(wait);
+                bar.flush();
+                result
+            }
+            Event::Command(Command::SetColorScheme(scheme)) => {
+                debug!("Switching to the {:?} color scheme", scheme);
+                switch_color_scheme(&mut bars, scheme)
+            }
+            Event::Command(Command::SetLargeMode(large)) => {
+                debug!("Switching large mode {}", if large { "on" } else { "off" });
+                switch_large_mode(&mut bars, large)
+            }
+            Event::Command(Command::ToggleLargeMode) => {
+                let large = !bars.iter().any(Bar::is_large);
+                switch_large_mode(&mut bars, large)
+            }
+            // Sent straight to the widget by the `Control`.
+            Event::Command(Command::SetText(..)) | Event::Command(Command::Stopwatch(..)) => Ok(()),
+            Event::ColorScheme(scheme) => {
+                debug!("Desktop color scheme is now {:?}", scheme);
+                switch_color_scheme(&mut bars, scheme)
+            }
+            Event::Reload(reload) => {
+                debug!("Reloading {} widgets", reload.widgets.len());
+                let previous = bars
+                    .iter()
+                    .map(|bar| (bar.options.clone(), bar.scale))
+                    .collect::<Vec<_>>();
+                for bar in &mut bars {
+                    bar.reload_options(&reload.options);
+                }
+                match install_widgets(&mut bars, &timer, reload.widgets, error_flash.clone()) {
+                    Ok((new_widget_list, new_installed)) => {
+                        *widget_list.borrow_mut() = new_widget_list;
+                        installed = new_installed;
+                        cache.clear();
+                        reloaded = true;
+                    }
+                    Err(e) => {
+                        // Carry on with the widgets that are installed, and
+                        // the options they were laid out with.
+                        error!("Failed to reload widgets: {}", e);
+                        for (bar, (options, scale)) in bars.iter_mut().zip(previous) {
+                            bar.options = options;
+                            bar.scale = scale;
+                            bar.set_color_scheme(bar.color_scheme);
+                        }
+                    }
+                }
+                Ok(())
+            }
+        };
+        timer.power().set_paused(bars.iter().all(Bar::is_hidden));
+
+        if installed.layout_handlers.iter().any(Option::is_some) {
+            let Installed {
+                ref mut layout_handlers,
+                ref mut placed,
+                ..
+            } = installed;
+            for (bar, placed) in bars.iter().zip(placed.iter_mut()) {
+                notify_layout(bar, placed, layout_handlers);
+            }
+        }
+
+        if let Err(ref e) = result {
+            error!("Error redrawing bar: {}", e);
+        }
+        future::result(result)
+    });
+
+    Ok(Box::new(fut))
+}
+
+/// Redraws each of the `bars` with the palette for the color `scheme`.
+fn switch_color_scheme(bars: &mut [Bar], scheme: ColorScheme) -> Result<()> {
+    bars.iter_mut().try_for_each(|bar| {
+        bar.set_color_scheme(scheme);
+        bar.redraw_entire_bar()?;
+        bar.flush();
+        Ok(())
+    })
+}
+
+/// Redraws each of the `bars` with large mode switched on or off.
+fn switch_large_mode(bars: &mut [Bar], large: bool) -> Result<()> {
+    bars.iter_mut().try_for_each(|bar| {
+        bar.set_large_mode(large)?;
+        bar.redraw_entire_bar()?;
+        bar.flush();
+        Ok(())
+    })
+}
+
+/// Hides or shows the widgets with the given `name` on each of the `bars`.
+fn conceal(bars: &mut [Bar], name: &str, concealed: bool) -> Result<()> {
+    let mut found = false;
+    for bar in bars {
+        found |= bar.conceal(name, concealed)?;
+        bar.flush();
+    }
+    if !found {
+        warn!("Can't hide or show unknown widget: {}", name);
+    }
+    Ok(())
+}
+
+/// Hides or shows each of the `bars` which are (or aren't) scratchpads.
+fn withdraw(bars: &mut [Bar], scratchpad: bool, withdrawn: bool) -> Result<()> {
+    for bar in bars.iter_mut().filter(|bar| bar.scratchpad == scratchpad) {
+        bar.set_withdrawn(withdrawn)?;
+        bar.flush();
+    }
+    Ok(())
+}
+
 struct XcbEvented(Rc<ewmh::Connection>);
 
 impl XcbEvented {
@@ -500,3 +2414,269 @@ impl Stream for XcbEventStream {
         }
     }
 }
+
+/// A `ClickHandler` or `LayoutHandler`.
+type Handler<T> = Box<dyn FnMut(T) -> Result<()>>;
+
+/// Returns a handler which calls whichever handler is in `handler`, i.e. that
+/// of a restartable widget's current instance, if the first instance had one.
+fn forward<T: 'static>(handler: &Rc<RefCell<Option<Handler<T>>>>) -> Option<Handler<T>> {
+    handler.borrow().as_ref()?;
+    let handler = handler.clone();
+    Some(Box::new(move |arg| match *handler.borrow_mut() {
+        Some(ref mut handler) => handler(arg),
+        None => Ok(()),
+    }))
+}
+
+/// The refreshes of a restartable widget, which are passed on to each of its
+/// instances in turn.
+struct SharedRefresh(Rc<RefCell<Refresh>>);
+
+impl Stream for SharedRefresh {
+    type Item = ();
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        self.0.borrow_mut().poll()
+    }
+}
+
+/// Starts instances of a widget added with `Cnx::add_restartable_widget()`,
+/// so that it can be created again once it has failed.
+struct Restart {
+    factory: WidgetFactory,
+    options: WidgetOptions,
+    timer: Timer,
+    conn: Option<Rc<ewmh::Connection>>,
+    refresh: Rc<RefCell<Refresh>>,
+    // The handlers of the current instance, which the bar calls through
+    // `forward()`.
+    click_handler: Rc<RefCell<Option<ClickHandler>>>,
+    layout_handler: Rc<RefCell<Option<LayoutHandler>>>,
+}
+
+impl Restart {
+    /// Returns the stream of `widget`, which replaces any earlier instance.
+    fn start(&mut self, widget: Box<dyn Widget>) -> Result<WidgetStream> {
+        let mut widget = Delayed::wrap(widget, &self.options, &self.timer, self.conn.as_ref());
+        *self.click_handler.borrow_mut() = widget.click_handler();
+        *self.layout_handler.borrow_mut() = widget.layout_handler();
+        widget.refreshable_stream(Box::new(SharedRefresh(self.refresh.clone())))
+    }
+
+    /// Returns the stream of a new instance of the widget.
+    fn restart(&mut self) -> Result<WidgetStream> {
+        let widget = self.factory.create()?;
+        self.start(widget)
+    }
+}
+
+/// A widget's stream, which shows a placeholder instead of stopping the bar
+/// when it fails, according to its `RestartPolicy`.
+struct Supervised {
+    // None once the widget has failed for good.
+    stream: Option<WidgetStream>,
+    // How to create the widget again once it has failed, if it can be.
+    restart: Option<Restart>,
+    timer: Timer,
+    policy: RestartPolicy,
+    name: String,
+    // The attributes of the widget's last text, for the placeholder.
+    attr: Option<Attributes>,
+    // The text shown until the widget first yields, if it isn't ready to.
+    placeholder: Option<Text>,
+    // The number of errors since the widget last yielded.
+    failures: u32,
+    retry: Option<Sleep>,
+    // Where to send a summary of each error, to be flashed on the bar.
+    errors: Option<mpsc::UnboundedSender<String>>,
+}
+
+impl Supervised {
+    /// Returns the text shown in place of the failed widget.
+    fn failed(&self) -> Vec<Text> {
+        let mut attr = self.attr.clone().unwrap_or_else(|| Attributes {
+            font: Font::new("Sans"),
+            fg_color: Color::default(),
+            bg_color: None,
+            padding: Padding::new(8.0, 8.0, 0.0, 0.0),
+            border: None,
+        });
+        attr.fg_color = Color::red();
+        vec![Text::new(attr, format!("⚠ {}", self.name))]
+    }
+}
+
+impl Stream for Supervised {
+    type Item = Vec<Text>;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        if let Some(ref mut retry) = self.retry {
+            if retry.poll()?.is_not_ready() {
+                return Ok(Async::NotReady);
+            }
+        }
+        if self.retry.take().is_some() {
+            if let Some(ref mut restart) = self.restart {
+                let stream = restart
+                    .restart()
+                    .unwrap_or_else(|e| Box::new(stream::once(Err(e))));
+                self.stream = Some(stream);
+            }
+        }
+
+        let result = match self.stream {
+            Some(ref mut stream) => stream.poll(),
+            None => return Ok(Async::NotReady),
+        };
+        let e = match result {
+            Ok(Async::Ready(Some(texts))) => {
+                self.placeholder = None;
+                self.failures = 0;
+                if let Some(text) = texts.first() {
+                    self.attr = Some(text.attr.clone());
+                }
+                return Ok(Async::Ready(Some(texts)));
+            }
+            Ok(Async::NotReady) => match self.placeholder.take() {
+                Some(placeholder) => return Ok(Async::Ready(Some(vec![placeholder]))),
+                None => return Ok(Async::NotReady),
+            },
+            Ok(other) => return Ok(other),
+            Err(e) => e,
+        };
+
+        if let Some(ref errors) = self.errors {
+            if self.policy != RestartPolicy::Fail {
+                let _ = errors.unbounded_send(format!("{}: {}", self.name, e));
+            }
+        }
+        match self.policy {
+            RestartPolicy::Fail => return Err(Error::Widget(Box::new(e))),
+            RestartPolicy::Never => {
+                error!("The {} failed, and won't be restarted: {}", self.name, e);
+                self.stream = None;
+            }
+            RestartPolicy::Backoff { initial, max } => {
+                self.failures += 1;
+                let wait = initial
+                    .checked_mul(1 << cmp::min(self.failures - 1, 16))
+                    .map_or(max, |wait| cmp::min(wait, max));
+                error!(
+                    "The {} failed, restarting it in {}s: {}",
+                    self.name,
+                    wait.as_secs(),
+                    e
+                );
+                self.retry = Some(self.timer.sleep(wait));
+            }
+        }
+        Ok(Async::Ready(Some(self.failed())))
+    }
+}
+
+/// Shows a summary of each widget's errors for `duration`, e.g.
+/// `⚠ clock: Failed to read file`.
+struct ErrorFlash {
+    errors: mpsc::UnboundedReceiver<String>,
+    attr: Attributes,
+    duration: Duration,
+}
+
+impl Widget for ErrorFlash {
+    fn stream(self: Box<Self>) -> Result<WidgetStream> {
+        let attr = self.attr;
+        let duration = self.duration;
+        let texts = self
+            .errors
+            .map(move |error| {
+                vec![Text::new(attr.clone(), format!("⚠ {}", error)).with_timeout(duration)]
+            })
+            .map_err(|()| format_err!("Error channel closed"));
+        Ok(Box::new(texts))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::Cell;
+    use std::rc::Rc;
+    use std::time::Duration;
+
+    use futures::{stream, Stream};
+
+    use super::install_widgets;
+    use crate::error::format_err;
+    use crate::text::{Attributes, Color, Font, Padding, Text};
+    use crate::timer::{PowerState, Timer};
+    use crate::widgets::{RestartPolicy, Widget, WidgetFactory, WidgetOptions, WidgetStream};
+    use crate::Result;
+
+    /// A widget whose first instance fails, and whose later ones work.
+    struct Flaky {
+        instance: usize,
+        attr: Attributes,
+    }
+
+    impl Widget for Flaky {
+        fn stream(self: Box<Self>) -> Result<WidgetStream> {
+            if self.instance == 0 {
+                return Ok(Box::new(stream::once(Err(format_err!("Failed")))));
+            }
+            let texts = vec![Text::new(self.attr, "Recovered".to_owned())];
+            Ok(Box::new(stream::once(Ok(texts))))
+        }
+    }
+
+    #[test]
+    fn restarts_failed_widgets() {
+        let timer = Timer::new(tokio_timer::Timer::default(), Rc::new(PowerState::new()));
+        let attr = Attributes {
+            font: Font::new("Sans"),
+            fg_color: Color::white(),
+            bg_color: None,
+            padding: Padding::new(0.0, 0.0, 0.0, 0.0),
+            border: None,
+        };
+        let instances = Rc::new(Cell::new(0));
+        let factory = {
+            let instances = instances.clone();
+            WidgetFactory::new(move || {
+                let instance = instances.get();
+                instances.set(instance + 1);
+                Ok(Box::new(Flaky {
+                    instance,
+                    attr: attr.clone(),
+                }))
+            })
+        };
+        let options = WidgetOptions {
+            name: Some("flaky".to_owned()),
+            restart_policy: RestartPolicy::Backoff {
+                initial: Duration::from_millis(1),
+                max: Duration::from_millis(1),
+            },
+            factory: Some(factory.clone()),
+            ..WidgetOptions::default()
+        };
+        let widgets = vec![(factory.create().unwrap(), options)];
+        let (widget_list, _) = install_widgets(&mut [], &timer, widgets, None).unwrap();
+
+        let texts = widget_list
+            .take(2)
+            .map(|all| {
+                all.into_iter()
+                    .flatten()
+                    .flatten()
+                    .map(|text| text.text)
+                    .collect::<Vec<_>>()
+            })
+            .wait()
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(texts, vec![vec!["⚠ flaky"], vec!["Recovered"]]);
+        assert_eq!(instances.get(), 2);
+    }
+}
@@ -21,14 +21,37 @@ fn init_log() -> Result<()> {
     Ok(())
 }
 
+/// Finds the config file to load: the path given as the first argument, or
+/// `$XDG_CONFIG_HOME/cnx/config.toml`, if it exists.
+#[cfg(feature = "config")]
+fn config_path() -> Option<std::path::PathBuf> {
+    use std::path::PathBuf;
+
+    if let Some(path) = env::args_os().nth(1) {
+        return Some(PathBuf::from(path));
+    }
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+    Some(config_home.join("cnx").join("config.toml")).filter(|path| path.exists())
+}
+
 fn main() -> Result<()> {
     init_log()?;
 
+    #[cfg(feature = "config")]
+    {
+        if let Some(path) = config_path() {
+            return Cnx::from_config(path)?.run();
+        }
+    }
+
     let attr = Attributes {
         font: Font::new("Noto Sans Mono"),
         fg_color: "3c3836".parse().unwrap(),
         bg_color: Some("fbf1c7".parse().unwrap()),
         padding: Padding::new(5.0, 5.0, 0.0, 0.0),
+        border: None,
     };
     let mut active_attr = attr.clone();
     active_attr.bg_color = Some("d65d0e".parse().unwrap());
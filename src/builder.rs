@@ -0,0 +1,355 @@
+//! Configuring a `Cnx` instance in one expression.
+
+use std::time::Duration;
+
+use crate::bar::BarOptions;
+use crate::fifo;
+use crate::text::{Color, Padding, Palette, SectionStyle};
+#[cfg(feature = "config")]
+use crate::widgets::WidgetFactory;
+use crate::{Cnx, Monitors, Position, Region, Result, Widget, WidgetOptions};
+
+// Adds a widget to the `Cnx` once it has been created, as widgets need the
+// `Cnx` in order to be created themselves.
+type AddWidget = Box<dyn FnOnce(&mut Cnx)>;
+
+/// Configures and creates a [`Cnx`] instance, returned by [`Cnx::builder()`].
+///
+/// Each option defaults to the same as for [`Cnx::new()`], and the bars are
+/// positioned at the top of the screen unless [`position()`] says otherwise.
+/// Widgets are added as closures which create them, since widgets borrow the
+/// [`Cnx`] instance when they are created. [`build()`] creates the instance
+/// and all of its widgets, in the order they were added.
+///
+/// New options are added as new methods, so configuring Cnx this way won't
+/// break as Cnx grows.
+///
+/// [`Cnx`]: struct.Cnx.html
+/// [`Cnx::builder()`]: struct.Cnx.html#method.builder
+/// [`Cnx::new()`]: struct.Cnx.html#method.new
+/// [`position()`]: #method.position
+/// [`build()`]: #method.build
+///
+/// # Examples
+///
+/// ```no_run
+/// # use cnx::*;
+/// # use cnx::text::*;
+/// # use cnx::widgets::*;
+/// #
+/// # fn run() -> ::cnx::Result<()> {
+/// let attr = Attributes {
+///     font: Font::new("SourceCodePro 21"),
+///     fg_color: Color::white(),
+///     bg_color: None,
+///     padding: Padding::new(8.0, 8.0, 0.0, 0.0),
+///     border: None,
+/// };
+///
+/// let title_attr = attr.clone();
+/// let clock_attr = attr.clone();
+/// Cnx::builder()
+///     .position(Position::Bottom)
+///     .height(32)
+///     .background(Color::blue())
+///     .add_widget(move |cnx| ActiveWindowTitle::new(cnx, title_attr))
+///     .add_widget_to(Region::Right, move |cnx| {
+///         Clock::new(cnx, "%H:%M".to_owned(), clock_attr)
+///     })
+///     .build()?
+///     .run()?;
+/// # Ok(())
+/// # }
+/// # fn main() { run().unwrap(); }
+/// ```
+pub struct CnxBuilder {
+    position: Position,
+    monitors: Monitors,
+    bar_options: BarOptions,
+    auto_hide: Option<Duration>,
+    root_name_separator: Option<String>,
+    fifo_output: bool,
+    keyboard_hotkey: Option<String>,
+    widgets: Vec<AddWidget>,
+}
+
+impl CnxBuilder {
+    pub(crate) fn new() -> CnxBuilder {
+        CnxBuilder {
+            position: Position::Top,
+            monitors: Monitors::default(),
+            bar_options: BarOptions::default(),
+            auto_hide: None,
+            root_name_separator: None,
+            fifo_output: false,
+            keyboard_hotkey: None,
+            widgets: Vec::new(),
+        }
+    }
+
+    /// Positions the bars at the top or bottom of the screen, depending on the
+    /// value of the [`Position`] enum.
+    ///
+    /// [`Position`]: enum.Position.html
+    pub fn position(mut self, position: Position) -> CnxBuilder {
+        self.position = position;
+        self
+    }
+
+    /// Shows the bars on the `screen`th X screen. See
+    /// [`Cnx::set_screen()`].
+    ///
+    /// [`Cnx::set_screen()`]: struct.Cnx.html#method.set_screen
+    pub fn screen(mut self, screen: usize) -> CnxBuilder {
+        self.bar_options.screen = Some(screen);
+        self
+    }
+
+    /// Sets which monitors get a bar. See [`Cnx::set_monitors()`].
+    ///
+    /// [`Cnx::set_monitors()`]: struct.Cnx.html#method.set_monitors
+    pub fn monitors(mut self, monitors: Monitors) -> CnxBuilder {
+        self.monitors = monitors;
+        self
+    }
+
+    /// Sets the height of the bars, in pixels. See [`Cnx::set_height()`].
+    ///
+    /// [`Cnx::set_height()`]: struct.Cnx.html#method.set_height
+    pub fn height(mut self, height: u16) -> CnxBuilder {
+        self.bar_options.height = Some(height);
+        self
+    }
+
+    /// Sets the color drawn behind everything on the bars. See
+    /// [`Cnx::set_background()`].
+    ///
+    /// [`Cnx::set_background()`]: struct.Cnx.html#method.set_background
+    pub fn background(mut self, color: Color) -> CnxBuilder {
+        self.bar_options.background = color;
+        self
+    }
+
+    /// Leaves `margins` around each bar. See [`Cnx::set_margins()`].
+    ///
+    /// [`Cnx::set_margins()`]: struct.Cnx.html#method.set_margins
+    pub fn margins(mut self, margins: Padding) -> CnxBuilder {
+        self.bar_options.margins = margins;
+        self
+    }
+
+    /// Leaves `spacing` pixels between neighbouring widgets. See
+    /// [`Cnx::set_spacing()`].
+    ///
+    /// [`Cnx::set_spacing()`]: struct.Cnx.html#method.set_spacing
+    pub fn spacing(mut self, spacing: f64) -> CnxBuilder {
+        self.bar_options.spacing = spacing;
+        self
+    }
+
+    /// Sets the appearance of the section of widgets in `region` of the
+    /// bars. See [`Cnx::set_section_style()`].
+    ///
+    /// [`Cnx::set_section_style()`]: struct.Cnx.html#method.set_section_style
+    pub fn section_style(mut self, region: Region, style: SectionStyle) -> CnxBuilder {
+        self.bar_options.section_styles[region as usize] = Some(style);
+        self
+    }
+
+    /// Draws the bars with `palette` while the desktop prefers a dark color
+    /// scheme. See [`Cnx::set_dark_palette()`].
+    ///
+    /// [`Cnx::set_dark_palette()`]: struct.Cnx.html#method.set_dark_palette
+    pub fn dark_palette(mut self, palette: Palette) -> CnxBuilder {
+        self.bar_options.dark_palette = Some(palette);
+        self
+    }
+
+    /// Sets how much bigger large mode draws the bars. See
+    /// [`Cnx::set_large_mode_scale()`].
+    ///
+    /// [`Cnx::set_large_mode_scale()`]: struct.Cnx.html#method.set_large_mode_scale
+    pub fn large_mode_scale(mut self, factor: f64) -> CnxBuilder {
+        self.bar_options.large_mode_scale = Some(factor);
+        self
+    }
+
+    /// Hides the bars once the pointer has been elsewhere for `timeout`. See
+    /// [`Cnx::set_auto_hide()`].
+    ///
+    /// [`Cnx::set_auto_hide()`]: struct.Cnx.html#method.set_auto_hide
+    pub fn auto_hide(mut self, timeout: Duration) -> CnxBuilder {
+        self.auto_hide = Some(timeout);
+        self
+    }
+
+    /// Navigates the bar's widgets with the keyboard after pressing `hotkey`,
+    /// e.g. `Mod4+b`. See [`Cnx::set_keyboard_hotkey()`].
+    ///
+    /// [`Cnx::set_keyboard_hotkey()`]: struct.Cnx.html#method.set_keyboard_hotkey
+    pub fn keyboard_hotkey<S: Into<String>>(mut self, hotkey: S) -> CnxBuilder {
+        self.keyboard_hotkey = Some(hotkey.into());
+        self
+    }
+
+    /// Shows the widgets in the root window's `WM_NAME`, with `separator`
+    /// between them, rather than drawing any bars. See
+    /// [`Cnx::set_root_name_output()`].
+    ///
+    /// [`Cnx::set_root_name_output()`]: struct.Cnx.html#method.set_root_name_output
+    pub fn root_name_output<S: Into<String>>(mut self, separator: S) -> CnxBuilder {
+        self.root_name_separator = Some(separator.into());
+        self
+    }
+
+    /// Writes the text of each named widget to its own FIFO. See
+    /// [`Cnx::enable_fifo_output()`].
+    ///
+    /// [`Cnx::enable_fifo_output()`]: struct.Cnx.html#method.enable_fifo_output
+    pub fn fifo_output(mut self) -> CnxBuilder {
+        self.fifo_output = true;
+        self
+    }
+
+    /// Adds the widget created by `widget` to the left region of the bar, to
+    /// the right of any existing widgets there. See [`Cnx::add_widget()`].
+    ///
+    /// [`Cnx::add_widget()`]: struct.Cnx.html#method.add_widget
+    pub fn add_widget<W, F>(self, widget: F) -> CnxBuilder
+    where
+        W: Widget + 'static,
+        F: FnOnce(&Cnx) -> W + 'static,
+    {
+        self.add_widget_with(widget, |_| ())
+    }
+
+    /// Adds the widget created by `widget` to the given [`Region`] of the bar.
+    /// See [`Cnx::add_widget_to()`].
+    ///
+    /// [`Region`]: enum.Region.html
+    /// [`Cnx::add_widget_to()`]: struct.Cnx.html#method.add_widget_to
+    pub fn add_widget_to<W, F>(self, region: Region, widget: F) -> CnxBuilder
+    where
+        W: Widget + 'static,
+        F: FnOnce(&Cnx) -> W + 'static,
+    {
+        self.add_widget_with(widget, move |options| {
+            options.region = region;
+        })
+    }
+
+    /// Adds the widget created by `widget`, calling `configure` with its
+    /// [`WidgetOptions`], e.g. to set its priority or name.
+    ///
+    /// [`WidgetOptions`]: widgets/struct.WidgetOptions.html
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use cnx::*;
+    /// # use cnx::text::*;
+    /// # use cnx::widgets::*;
+    /// #
+    /// # fn run() -> ::cnx::Result<()> {
+    /// # let attr = Attributes {
+    /// #     font: Font::new("SourceCodePro 21"),
+    /// #     fg_color: Color::white(),
+    /// #     bg_color: None,
+    /// #     padding: Padding::new(8.0, 8.0, 0.0, 0.0),
+    /// #     border: None,
+    /// # };
+    /// let cnx = Cnx::builder()
+    ///     .add_widget_with(
+    ///         move |cnx| Clock::new(cnx, "%H:%M".to_owned(), attr),
+    ///         |options| {
+    ///             options.priority(255).name("clock");
+    ///         },
+    ///     )
+    ///     .build()?;
+    /// # Ok(())
+    /// # }
+    /// # fn main() { run().unwrap(); }
+    /// ```
+    pub fn add_widget_with<W, F, C>(mut self, widget: F, configure: C) -> CnxBuilder
+    where
+        W: Widget + 'static,
+        F: FnOnce(&Cnx) -> W + 'static,
+        C: FnOnce(&mut WidgetOptions) + 'static,
+    {
+        self.widgets.push(Box::new(move |cnx: &mut Cnx| {
+            let widget = widget(cnx);
+            configure(cnx.add_widget(widget));
+        }));
+        self
+    }
+
+    /// Adds the widget created by `widget`, calling it again whenever the
+    /// widget is restarted after failing, and calling `configure` with its
+    /// [`WidgetOptions`]. See [`Cnx::add_restartable_widget()`].
+    ///
+    /// [`WidgetOptions`]: widgets/struct.WidgetOptions.html
+    /// [`Cnx::add_restartable_widget()`]: struct.Cnx.html#method.add_restartable_widget
+    pub fn add_restartable_widget_with<W, F, C>(mut self, widget: F, configure: C) -> CnxBuilder
+    where
+        W: Widget + 'static,
+        F: FnMut(&Cnx) -> W + 'static,
+        C: FnOnce(&mut WidgetOptions) + 'static,
+    {
+        self.widgets.push(Box::new(move |cnx: &mut Cnx| {
+            configure(cnx.add_restartable_widget(widget));
+        }));
+        self
+    }
+
+    /// Calls `widget` to create a new instance of the widget added last, each
+    /// time it's restarted after failing, e.g. from its configuration.
+    #[cfg(feature = "config")]
+    pub(crate) fn restart_last_with<F>(mut self, mut widget: F) -> CnxBuilder
+    where
+        F: FnMut(&Cnx) -> Result<Box<dyn Widget>> + 'static,
+    {
+        self.widgets.push(Box::new(move |cnx: &mut Cnx| {
+            let copy = cnx.without_widgets();
+            if let Some((_, options)) = cnx.widgets.last_mut() {
+                options.factory = Some(WidgetFactory::new(move || widget(&copy)));
+            }
+        }));
+        self
+    }
+
+    /// Creates the [`Cnx`] instance, and each of its widgets.
+    ///
+    /// [`Cnx`]: struct.Cnx.html
+    pub fn build(self) -> Result<Cnx> {
+        let mut cnx = Cnx::new(self.position.clone())?;
+        self.apply(&mut cnx);
+        Ok(cnx)
+    }
+
+    /// Configures the existing `cnx` instance, except for its position, and
+    /// adds each of the widgets to it.
+    pub(crate) fn apply(self, cnx: &mut Cnx) {
+        cnx.monitors = self.monitors;
+        cnx.bar_options = self.bar_options;
+        cnx.auto_hide = self.auto_hide;
+        cnx.root_name_separator = self.root_name_separator;
+        cnx.keyboard_hotkey = self.keyboard_hotkey;
+        cnx.fifo_dir = if self.fifo_output {
+            Some(fifo::default_dir())
+        } else {
+            None
+        };
+        for add_widget in self.widgets {
+            add_widget(cnx);
+        }
+    }
+
+    /// Adds each of the widgets to the existing `cnx` instance, without
+    /// configuring it.
+    #[cfg(feature = "config")]
+    pub(crate) fn apply_widgets(self, cnx: &mut Cnx) {
+        for add_widget in self.widgets {
+            add_widget(cnx);
+        }
+    }
+}
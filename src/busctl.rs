@@ -0,0 +1,127 @@
+//! Talking to D-Bus services with `busctl` (part of `systemd`), for the
+//! widgets which use D-Bus APIs. Its JSON output can be read with the `json`
+//! module.
+
+use std::process::Command;
+use std::time::Duration;
+
+use futures::Future;
+use log::warn;
+use tokio_core::reactor::Handle;
+
+use crate::error::{format_err, Error, ResultExt};
+use crate::process::{self, OutputLines};
+use crate::timer::Timer;
+use crate::{Cnx, Result};
+
+// How long to wait for a D-Bus service to reply, rather than `busctl`'s
+// default of 25s, so that one which hangs doesn't hold up its widget for long.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Which bus to talk to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Bus {
+    System,
+    /// The session bus of the user running Cnx.
+    User,
+}
+
+impl Bus {
+    fn flag(self) -> &'static str {
+        match self {
+            Bus::System => "--system",
+            Bus::User => "--user",
+        }
+    }
+}
+
+fn command(bus: Bus, args: &[&str]) -> Command {
+    let mut command = Command::new("busctl");
+    command
+        .args([bus.flag(), "--json=short"])
+        .arg(format!("--timeout={}", TIMEOUT.as_secs()))
+        .args(args);
+    command
+}
+
+/// Runs `busctl` on `bus` with `args`, returning its JSON output.
+///
+/// This blocks until `busctl` exits, so widgets should use a [`Busctl`]
+/// instead, which doesn't block the event loop.
+pub(crate) fn busctl(bus: Bus, args: &[&str]) -> Result<String> {
+    let output = command(bus, args)
+        .output()
+        .context("Failed to run `busctl`")?;
+    if !output.status.success() {
+        return Err(format_err!(
+            "`busctl {}` failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    String::from_utf8(output.stdout).context("Invalid UTF-8 in busctl output")
+}
+
+/// Runs `busctl` on a bus from the event loop, for widgets which use D-Bus
+/// APIs.
+#[derive(Clone)]
+pub(crate) struct Busctl {
+    handle: Handle,
+    timer: Timer,
+    bus: Bus,
+}
+
+impl Busctl {
+    pub fn new(cnx: &Cnx, bus: Bus) -> Busctl {
+        Busctl {
+            handle: cnx.handle(),
+            timer: cnx.timer(),
+            bus,
+        }
+    }
+
+    /// Returns a copy which talks to `bus` instead.
+    pub fn on(self, bus: Bus) -> Busctl {
+        Busctl { bus, ..self }
+    }
+
+    /// Runs `busctl` with `args`, returning its JSON output, without blocking
+    /// the event loop. It is killed if it is still running after the
+    /// timeout, e.g. because the service hasn't replied.
+    pub fn call(&self, args: &[&str]) -> Box<dyn Future<Item = String, Error = Error>> {
+        let description = format!("`busctl {}`", args.join(" "));
+        // Give busctl a moment to report the service's timeout itself.
+        let timeout = TIMEOUT + Duration::from_secs(1);
+        let output = process::output(&self.handle, &self.timer, command(self.bus, args), timeout);
+        Box::new(output.and_then(move |(status, stdout)| {
+            if !status.success() {
+                return Err(format_err!("{} failed: {}", description, status));
+            }
+            String::from_utf8(stdout).context("Invalid UTF-8 in busctl output")
+        }))
+    }
+
+    /// Runs `busctl` with `args` like [`call()`], logging rather than
+    /// returning any error, e.g. for click handlers.
+    ///
+    /// [`call()`]: #method.call
+    pub fn spawn(&self, args: &[&str]) {
+        let call = self.call(args).map(|_| ());
+        self.handle.spawn(call.map_err(|e| warn!("{}", e)));
+    }
+}
+
+/// Runs `busctl monitor` on `bus`, returning a line of JSON for each message
+/// which matches `match_rule`.
+pub(crate) fn monitor(handle: &Handle, bus: Bus, match_rule: &str) -> Result<OutputLines> {
+    let mut monitor = Command::new("busctl");
+    monitor.args([
+        bus.flag(),
+        // One line per message, so that we only update once per message.
+        "--json=short",
+        "monitor",
+        "--match",
+        match_rule,
+    ]);
+    process::output_lines(handle, monitor)
+}
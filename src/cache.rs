@@ -0,0 +1,118 @@
+//! Remembering the texts each widget last showed, so that updates which
+//! don't change anything are dropped before the bars do any work for them.
+
+use log::{debug, trace};
+
+use crate::text::Text;
+
+/// How many updates are counted between each log of the hit rate.
+const REPORT_EVERY: u64 = 1000;
+
+/// The last texts of each of the installed widgets.
+///
+/// Many widgets poll, and most often find that nothing has changed, so they
+/// show the same texts again. The hit rate (the proportion of updates which
+/// didn't change anything) is logged every so often, at the debug level.
+#[derive(Default)]
+pub(crate) struct TextCache {
+    last: Vec<Option<Vec<Text>>>,
+    hits: u64,
+    misses: u64,
+}
+
+impl TextCache {
+    /// Forgets every widget's texts, as the widgets have been reinstalled.
+    pub fn clear(&mut self) {
+        self.last.clear();
+    }
+
+    /// Drops each widget's update from `updates` if it is the same as the
+    /// texts the widget last showed. Returns None if nothing has changed.
+    pub fn filter(&mut self, updates: Vec<Option<Vec<Text>>>) -> Option<Vec<Option<Vec<Text>>>> {
+        if self.last.len() < updates.len() {
+            self.last.resize(updates.len(), None);
+        }
+        let mut changed = false;
+        let mut filtered = Vec::with_capacity(updates.len());
+        for (i, (update, last)) in updates.into_iter().zip(&mut self.last).enumerate() {
+            filtered.push(match update {
+                Some(texts) if last.as_ref() == Some(&texts) => {
+                    trace!("Widget {} showed the same texts again", i);
+                    self.hits += 1;
+                    None
+                }
+                Some(texts) => {
+                    self.misses += 1;
+                    changed = true;
+                    *last = Some(texts.clone());
+                    Some(texts)
+                }
+                None => None,
+            });
+        }
+
+        let total = self.hits + self.misses;
+        if total >= REPORT_EVERY {
+            debug!(
+                "{:.0}% of the last {} widget updates changed nothing",
+                self.hit_rate() * 100.0,
+                total
+            );
+            self.hits = 0;
+            self.misses = 0;
+        }
+        if changed {
+            Some(filtered)
+        } else {
+            None
+        }
+    }
+
+    /// The proportion of updates since the last report which didn't change
+    /// anything.
+    fn hit_rate(&self) -> f64 {
+        match self.hits + self.misses {
+            0 => 0.0,
+            total => self.hits as f64 / total as f64,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::TextCache;
+    use crate::text::{Attributes, Color, Font, Padding, Text};
+
+    fn text(text: &str) -> Text {
+        Text::new(
+            Attributes {
+                font: Font::new("Sans"),
+                fg_color: Color::white(),
+                bg_color: None,
+                padding: Padding::new(0.0, 0.0, 0.0, 0.0),
+                border: None,
+            },
+            text.to_owned(),
+        )
+    }
+
+    #[test]
+    fn works() {
+        let mut cache = TextCache::default();
+        let first = vec![Some(vec![text("12:00")]), Some(vec![text("50%")])];
+        assert_eq!(cache.filter(first.clone()), Some(first));
+        // Only the clock changed.
+        assert_eq!(
+            cache.filter(vec![Some(vec![text("12:01")]), Some(vec![text("50%")])]),
+            Some(vec![Some(vec![text("12:01")]), None])
+        );
+        assert_eq!(cache.filter(vec![None, Some(vec![text("50%")])]), None);
+        assert_eq!(cache.hit_rate(), 0.5);
+
+        cache.clear();
+        assert_eq!(
+            cache.filter(vec![None, Some(vec![text("50%")])]),
+            Some(vec![None, Some(vec![text("50%")])])
+        );
+    }
+}
@@ -0,0 +1,159 @@
+//! Owning the X `CLIPBOARD` selection, so that text can be copied from the
+//! bar.
+//!
+//! X doesn't store copied text itself. Instead, the program which copied it
+//! owns the selection, and sends the text to each program which asks for it
+//! (e.g. when the user pastes), until another program takes ownership.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use futures::Stream;
+use tokio_core::reactor::Handle;
+use xcb_util::ewmh;
+
+use crate::bar::XcbEventStream;
+use crate::error::{Error, ResultExt};
+use crate::{xcb_ext, Result};
+
+fn intern_atom(conn: &xcb::Connection, name: &str) -> Result<xcb::Atom> {
+    Ok(xcb::intern_atom(conn, false, name)
+        .get_reply()
+        .with_context(|_| format!("Failed to intern {}", name))?
+        .atom())
+}
+
+/// Copies text to the `CLIPBOARD` selection, using a window of its own.
+pub(crate) struct Clipboard {
+    conn: Rc<ewmh::Connection>,
+    window: xcb::Window,
+    clipboard: xcb::Atom,
+    targets: xcb::Atom,
+    utf8_string: xcb::Atom,
+    // The text we were last asked to copy, while we own the selection.
+    text: RefCell<Option<String>>,
+}
+
+impl Clipboard {
+    pub fn new() -> Result<Rc<Clipboard>> {
+        let (conn, screen_idx) = xcb_ext::connect()?;
+        let root = xcb_ext::root_window(&conn, screen_idx)?;
+
+        // Selections must be owned by a window, but it's never shown.
+        let window = conn.generate_id();
+        xcb::create_window_checked(
+            &conn,
+            xcb::COPY_FROM_PARENT as u8,
+            window,
+            root,
+            0,
+            0,
+            1,
+            1,
+            0,
+            xcb::WINDOW_CLASS_INPUT_ONLY as u16,
+            xcb::COPY_FROM_PARENT,
+            &[],
+        )
+        .request_check()
+        .context("Failed to create clipboard window")?;
+
+        Ok(Rc::new(Clipboard {
+            clipboard: intern_atom(&conn, "CLIPBOARD")?,
+            targets: intern_atom(&conn, "TARGETS")?,
+            utf8_string: intern_atom(&conn, "UTF8_STRING")?,
+            conn,
+            window,
+            text: RefCell::new(None),
+        }))
+    }
+
+    /// Copies `text`, taking ownership of the selection.
+    pub fn copy(&self, text: String) -> Result<()> {
+        *self.text.borrow_mut() = Some(text);
+        xcb::set_selection_owner(&self.conn, self.window, self.clipboard, xcb::CURRENT_TIME);
+        let owner = xcb::get_selection_owner(&self.conn, self.clipboard)
+            .get_reply()
+            .context("Failed to get clipboard owner")?
+            .owner();
+        if owner != self.window {
+            *self.text.borrow_mut() = None;
+            return Err(Error::X("Failed to take ownership of clipboard".to_owned()));
+        }
+        Ok(())
+    }
+
+    /// Returns a stream which sends the copied text to the programs which ask
+    /// for it. It never yields anything, but must be polled for as long as
+    /// the text should be available.
+    pub fn serve(self: Rc<Self>, handle: &Handle) -> Result<impl Stream<Item = (), Error = Error>> {
+        let events = XcbEventStream::new(self.conn.clone(), handle)?;
+        Ok(events.filter_map(move |event| {
+            match event.response_type() & !0x80 {
+                xcb::SELECTION_REQUEST => {
+                    let request: &xcb::SelectionRequestEvent = unsafe { xcb::cast_event(&event) };
+                    self.respond(request);
+                }
+                xcb::SELECTION_CLEAR => {
+                    // Another program has copied something.
+                    *self.text.borrow_mut() = None;
+                }
+                _ => {}
+            }
+            None
+        }))
+    }
+
+    /// Sends the text to the program which asked for it, in the format it
+    /// asked for (if we can).
+    fn respond(&self, request: &xcb::SelectionRequestEvent) {
+        // Obsolete clients don't say which property to store the text in, and
+        // expect the target to be used.
+        let property = match request.property() {
+            xcb::NONE => request.target(),
+            property => property,
+        };
+        let target = request.target();
+
+        let text = self.text.borrow();
+        let stored = match *text {
+            Some(_) if target == self.targets => {
+                let targets = [self.targets, self.utf8_string, xcb::ATOM_STRING];
+                xcb::change_property(
+                    &self.conn,
+                    xcb::PROP_MODE_REPLACE as u8,
+                    request.requestor(),
+                    property,
+                    xcb::ATOM_ATOM,
+                    32,
+                    &targets,
+                );
+                true
+            }
+            Some(ref text) if target == self.utf8_string || target == xcb::ATOM_STRING => {
+                xcb::change_property(
+                    &self.conn,
+                    xcb::PROP_MODE_REPLACE as u8,
+                    request.requestor(),
+                    property,
+                    target,
+                    8,
+                    text.as_bytes(),
+                );
+                true
+            }
+            _ => false,
+        };
+
+        // Tell the requestor that the text is there, or that we refused.
+        let notify = xcb::SelectionNotifyEvent::new(
+            request.time(),
+            request.requestor(),
+            request.selection(),
+            target,
+            if stored { property } else { xcb::NONE },
+        );
+        xcb::send_event(&self.conn, false, request.requestor(), 0, &notify);
+        self.conn.flush();
+    }
+}
@@ -0,0 +1,823 @@
+//! Configuring Cnx from a TOML file, so that the stock binary can be used
+//! without writing any Rust. See [`Cnx::from_config()`].
+//!
+//! [`Cnx::from_config()`]: ../struct.Cnx.html#method.from_config
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::error::{format_err, ResultExt};
+use crate::template::Template;
+use crate::text::{Attributes, Color, DotShape, Ellipsis, Font, Padding, SectionStyle, Threshold};
+use crate::toml::{self, Value};
+use crate::widgets::*;
+use crate::zoneinfo::Zone;
+use crate::{Cnx, CnxBuilder, Monitors, Position, Region, Result, Widget, WidgetOptions};
+
+/// A table of the configuration, along with a description of where it is,
+/// e.g. `widget 2`, for errors.
+struct Section<'a> {
+    value: &'a Value,
+    name: String,
+}
+
+impl<'a> Section<'a> {
+    fn get<T, F>(&self, key: &str, kind: &str, f: F) -> Result<Option<T>>
+    where
+        F: FnOnce(&'a Value) -> Option<T>,
+    {
+        match self.value.get(key) {
+            Some(value) => f(value)
+                .map(Some)
+                .ok_or_else(|| format_err!("`{}` of {} must be {}", key, self.name, kind)),
+            None => Ok(None),
+        }
+    }
+
+    fn string(&self, key: &str) -> Result<Option<String>> {
+        self.get(key, "a string", |value| {
+            value.as_str().map(ToOwned::to_owned)
+        })
+    }
+
+    fn required_string(&self, key: &str) -> Result<String> {
+        self.string(key)?
+            .ok_or_else(|| format_err!("{} is missing `{}`", self.name, key))
+    }
+
+    fn strings(&self, key: &str) -> Result<Option<Vec<String>>> {
+        self.get(key, "an array of strings", |value| {
+            value
+                .as_array()?
+                .iter()
+                .map(|s| s.as_str().map(ToOwned::to_owned))
+                .collect()
+        })
+    }
+
+    fn boolean(&self, key: &str) -> Result<Option<bool>> {
+        self.get(key, "true or false", Value::as_bool)
+    }
+
+    fn number(&self, key: &str) -> Result<Option<f64>> {
+        self.get(key, "a number", Value::as_f64)
+    }
+
+    fn integer<T: std::convert::TryFrom<i64>>(&self, key: &str) -> Result<Option<T>> {
+        self.get(key, "a (small enough) integer", |value| {
+            T::try_from(value.as_integer()?).ok()
+        })
+    }
+
+    fn color(&self, key: &str) -> Result<Option<Color>> {
+        self.string(key)?.map(|s| parse_color(&s)).transpose()
+    }
+
+    /// Reads a widget's format, e.g. `{percent}%`.
+    fn template(&self, key: &str) -> Result<Option<Template>> {
+        self.string(key)?
+            .map(|s| {
+                s.parse()
+                    .with_context(|_| format!("Invalid `{}` of {}", key, self.name))
+            })
+            .transpose()
+    }
+
+    /// Reads a padding, as an array of `[left, right, top, bottom]`.
+    fn padding(&self, key: &str) -> Result<Option<Padding>> {
+        self.get(key, "an array of 4 numbers", |value| {
+            let numbers = value
+                .as_array()?
+                .iter()
+                .map(Value::as_f64)
+                .collect::<Option<Vec<_>>>()?;
+            match numbers[..] {
+                [left, right, top, bottom] => Some(Padding::new(left, right, top, bottom)),
+                _ => None,
+            }
+        })
+    }
+
+    /// Looks up the named attributes, defaulting to `default`.
+    fn attributes(
+        &self,
+        key: &str,
+        attributes: &HashMap<String, Attributes>,
+    ) -> Result<Attributes> {
+        let name = self.string(key)?.unwrap_or_else(|| "default".to_owned());
+        attributes
+            .get(&name)
+            .cloned()
+            .ok_or_else(|| format_err!("{} uses unknown attributes {:?}", self.name, name))
+    }
+
+    /// Reads a `Threshold` from the `warn` and `crit` levels, shown with the
+    /// named `warn_attributes` and `crit_attributes`, if either level is set.
+    fn threshold(
+        &self,
+        attributes: &HashMap<String, Attributes>,
+    ) -> Result<Option<Threshold<f64>>> {
+        let (warn, crit) = (self.number("warn")?, self.number("crit")?);
+        if warn.is_none() && crit.is_none() {
+            return Ok(None);
+        }
+        let mut threshold = Threshold::new();
+        if let Some(warn) = warn {
+            threshold = threshold.warn(warn, self.attributes("warn_attributes", attributes)?);
+        }
+        if let Some(crit) = crit {
+            threshold = threshold.crit(crit, self.attributes("crit_attributes", attributes)?);
+        }
+        Ok(Some(threshold))
+    }
+}
+
+/// Parses a color like `Color`'s `FromStr`, e.g. `3c3836` or `3c383680`,
+/// without panicking if it is too short.
+fn parse_color(s: &str) -> Result<Color> {
+    s.parse().map_err(|_| format_err!("Invalid color {:?}", s))
+}
+
+fn parse_attributes(section: &Section) -> Result<Attributes> {
+    let font = section
+        .string("font")?
+        .unwrap_or_else(|| "Sans 12".to_owned());
+    Ok(Attributes {
+        font: Font::new(&font),
+        fg_color: section.color("fg_color")?.unwrap_or_else(Color::white),
+        bg_color: section.color("bg_color")?,
+        padding: section.padding("padding")?.unwrap_or_default(),
+        border: None,
+    })
+}
+
+fn parse_section_style(section: &Section) -> Result<SectionStyle> {
+    Ok(SectionStyle {
+        bg_color: section.color("bg_color")?,
+        padding: section.padding("padding")?.unwrap_or_default(),
+        corner_radius: section.number("corner_radius")?.unwrap_or(0.0),
+    })
+}
+
+fn parse_options(section: &Section) -> Result<WidgetOptions> {
+    let region = match section.string("region")?.as_deref() {
+        None | Some("left") => Region::Left,
+        Some("center") => Region::Center,
+        Some("right") => Region::Right,
+        Some(region) => return Err(format_err!("Unknown region {:?}", region)),
+    };
+    let mut options = WidgetOptions {
+        region,
+        ..WidgetOptions::default()
+    };
+    if let Some(priority) = section.integer("priority")? {
+        options.priority(priority);
+    }
+    if let Some(name) = section.string("name")? {
+        options.name(name);
+    }
+    if let Some(monitor) = section.string("monitor")? {
+        options.monitor(monitor);
+    }
+    if let Some(width) = section.number("min_width")? {
+        options.min_width(width);
+    }
+    for dependency in section.strings("depends_on")?.unwrap_or_default() {
+        options.depends_on(parse_dependency(&dependency)?);
+    }
+    Ok(options)
+}
+
+/// Parses a dependency like `session_bus` or `interface:wlan0`.
+fn parse_dependency(s: &str) -> Result<Dependency> {
+    match s.split_once(':') {
+        None if s == "session_bus" => Ok(Dependency::SessionBus),
+        None if s == "system_bus" => Ok(Dependency::SystemBus),
+        Some(("interface", name)) => Ok(Dependency::Interface(name.to_owned())),
+        Some(("selection", name)) => Ok(Dependency::Selection(name.to_owned())),
+        _ => Err(format_err!("Unknown dependency {:?}", s)),
+    }
+}
+
+fn add<W, F>(builder: CnxBuilder, options: WidgetOptions, widget: F) -> CnxBuilder
+where
+    W: Widget + 'static,
+    F: FnOnce(&Cnx) -> W + 'static,
+{
+    builder.add_widget_with(widget, move |widget_options| *widget_options = options)
+}
+
+fn add_widget(
+    builder: CnxBuilder,
+    section: &Section,
+    attributes: &HashMap<String, Attributes>,
+) -> Result<CnxBuilder> {
+    let attr = section.attributes("attributes", attributes)?;
+    let options = parse_options(section)?;
+    let warning_color = section.color("warning_color")?.unwrap_or_else(Color::red);
+
+    let builder = match section.required_string("type")?.as_str() {
+        "active_window_title" => {
+            let ellipsis = match section.string("ellipsis")?.as_deref() {
+                Some("start") => Ellipsis::Start,
+                Some("middle") => Ellipsis::Middle,
+                None | Some("end") => Ellipsis::End,
+                Some(ellipsis) => return Err(format_err!("Unknown ellipsis {:?}", ellipsis)),
+            };
+            let max_chars = section.integer("max_chars")?;
+            let max_width = section.number("max_width")?;
+            let icon = section.boolean("icon")?.unwrap_or(false);
+            let class_attributes = section
+                .get("class_attributes", "a table", Value::as_table)?
+                .unwrap_or_default()
+                .iter()
+                .map(|(class, name)| {
+                    let name = name.as_str().ok_or_else(|| {
+                        format_err!("`class_attributes` of {} must be strings", section.name)
+                    })?;
+                    let attr = attributes.get(name).cloned().ok_or_else(|| {
+                        format_err!("{} uses unknown attributes {:?}", section.name, name)
+                    })?;
+                    Ok((class.clone(), attr))
+                })
+                .collect::<Result<HashMap<_, _>>>()?;
+            add(builder, options, move |cnx| {
+                let mut title = ActiveWindowTitle::new(cnx, attr);
+                if let Some(max_chars) = max_chars {
+                    title = title.with_max_chars(max_chars, ellipsis);
+                }
+                if let Some(max_width) = max_width {
+                    title = title.with_max_width(max_width, ellipsis);
+                }
+                if icon {
+                    title = title.with_icon();
+                }
+                if !class_attributes.is_empty() {
+                    title = title
+                        .with_class_attributes(move |class| class_attributes.get(class).cloned());
+                }
+                title
+            })
+        }
+        "battery" => {
+            let separate = section.boolean("separate")?.unwrap_or(false);
+            let format = section.template("format")?;
+            add(builder, options, move |cnx| {
+                let mut battery = Battery::new(cnx, attr, warning_color);
+                if separate {
+                    battery = battery.with_separate_batteries();
+                }
+                if let Some(format) = format {
+                    battery = battery.with_format(format);
+                }
+                battery
+            })
+        }
+        "clock" => {
+            let format = section
+                .string("format")?
+                .unwrap_or_else(|| "%H:%M".to_owned());
+            let alternate_format = section.string("alternate_format")?;
+            let calendar_popup = section.boolean("calendar_popup")?.unwrap_or(false);
+            let calendar = section.string("holiday_calendar")?;
+            let holiday_attr = section.attributes("holiday_attributes", attributes)?;
+            let zones = section
+                .get(
+                    "zones",
+                    "an array of [label, zone] or [label, zone, format] arrays",
+                    |value| {
+                        value
+                            .as_array()?
+                            .iter()
+                            .map(|zone| match zone.as_array()?[..] {
+                                [ref label, ref zone] => {
+                                    Some((label.as_str()?, zone.as_str()?, None))
+                                }
+                                [ref label, ref zone, ref format] => {
+                                    Some((label.as_str()?, zone.as_str()?, Some(format.as_str()?)))
+                                }
+                                _ => None,
+                            })
+                            .collect::<Option<Vec<_>>>()
+                    },
+                )?
+                .unwrap_or_default()
+                .into_iter()
+                .map(|(label, zone, zone_format)| {
+                    let zone = Zone::load(zone)
+                        .with_context(|_| format!("Invalid `zones` of {}", section.name))?;
+                    let zone_format = zone_format.unwrap_or(&format).to_owned();
+                    Ok((label.to_owned(), zone, zone_format))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            add(builder, options, move |cnx| {
+                let mut clock = Clock::new(cnx, format, attr);
+                if let Some(path) = calendar {
+                    clock = clock.with_holiday_calendar(path, holiday_attr);
+                }
+                if !zones.is_empty() {
+                    clock = clock.with_zones(zones);
+                }
+                if let Some(format) = alternate_format {
+                    clock = clock.with_alternate_format(format);
+                }
+                if calendar_popup {
+                    clock = clock.with_calendar_popup();
+                }
+                clock
+            })
+        }
+        "connectivity" => {
+            let labels = match (
+                section.string("online")?,
+                section.string("portal")?,
+                section.string("offline")?,
+            ) {
+                (None, None, None) => None,
+                (online, portal, offline) => Some((
+                    online.unwrap_or_else(|| "online".to_owned()),
+                    portal.unwrap_or_else(|| "portal".to_owned()),
+                    offline.unwrap_or_else(|| "offline".to_owned()),
+                )),
+            };
+            add(builder, options, move |cnx| {
+                let connectivity = Connectivity::new(cnx, attr);
+                match labels {
+                    Some((online, portal, offline)) => {
+                        connectivity.with_labels(online, portal, offline)
+                    }
+                    None => connectivity,
+                }
+            })
+        }
+        "cpu_freq" => {
+            let format = match section.template("format")? {
+                Some(format) => format,
+                None => "{avg}GHz {governor}".parse()?,
+            };
+            add(builder, options, move |cnx| CpuFreq::new(cnx, attr, format))
+        }
+        "custom" => {
+            let name = section.required_string("name")?;
+            add(builder, options, move |cnx| Custom::new(cnx, attr, name))
+        }
+        "device_batteries" => add(builder, options, move |cnx| {
+            DeviceBatteries::new(cnx, attr, warning_color)
+        }),
+        "disk_temperature" => {
+            let threshold = section.threshold(attributes)?;
+            add(builder, options, move |cnx| {
+                let disk_temperature = DiskTemperature::new(cnx, attr);
+                match threshold {
+                    Some(threshold) => disk_temperature.with_threshold(threshold),
+                    None => disk_temperature,
+                }
+            })
+        }
+        "dunst" => add(builder, options, move |cnx| Dunst::new(cnx, attr)),
+        "exec" => {
+            let command = section.required_string("command")?;
+            let interval = Duration::from_secs_f64(section.number("interval")?.unwrap_or(60.0));
+            add(builder, options, move |cnx| {
+                Exec::new(cnx, attr, command, interval)
+            })
+        }
+        "inhibitors" => add(builder, options, move |cnx| Inhibitors::new(cnx, attr)),
+        "mpd" => {
+            let format = section
+                .string("format")?
+                .unwrap_or_else(|| "{artist} - {title}".to_owned());
+            let address = section.string("address")?;
+            add(builder, options, move |cnx| {
+                let mpd = Mpd::new(cnx, attr, format);
+                match address {
+                    Some(address) => mpd.with_address(address),
+                    None => mpd,
+                }
+            })
+        }
+        "mpris" => add(builder, options, move |cnx| Mpris::new(cnx, attr)),
+        "status_notifier" => {
+            let attention_attr = section.attributes("attention_attributes", attributes)?;
+            add(builder, options, move |cnx| {
+                StatusNotifier::new(cnx, attr, attention_attr)
+            })
+        }
+        "session_lock" => {
+            let unlocked = section
+                .string("unlocked")?
+                .unwrap_or_else(|| "🔓".to_owned());
+            let locked = section.string("locked")?.unwrap_or_else(|| "🔒".to_owned());
+            add(builder, options, move |cnx| {
+                SessionLock::new(cnx, attr).with_labels(unlocked, locked)
+            })
+        }
+        "pager" => {
+            let active_attr = section.attributes("active_attributes", attributes)?;
+            let shape = match section.string("dots")?.as_deref() {
+                Some("circle") => Some(DotShape::Circle),
+                Some("square") => Some(DotShape::Square),
+                None => None,
+                Some(dots) => return Err(format_err!("Unknown dots {:?}", dots)),
+            };
+            let dot_size = section.number("dot_size")?.unwrap_or(8.0);
+            add(builder, options, move |cnx| {
+                let pager = Pager::new(cnx, active_attr, attr);
+                match shape {
+                    Some(shape) => pager.with_dots(shape, dot_size),
+                    None => pager,
+                }
+            })
+        }
+        #[cfg(feature = "pulse-widget")]
+        "mic_level" => {
+            let source = section.string("source")?;
+            let width = section.number("width")?;
+            let history = section.integer("history")?;
+            add(builder, options, move |cnx| {
+                let mut mic_level = MicLevel::new(cnx, attr);
+                if let Some(source) = source {
+                    mic_level = mic_level.with_source(source);
+                }
+                if let Some(width) = width {
+                    mic_level = mic_level.with_width(width);
+                }
+                if let Some(history) = history {
+                    mic_level = mic_level.with_history(history);
+                }
+                mic_level
+            })
+        }
+        #[cfg(feature = "pulse-widget")]
+        "pulse_volume" => {
+            let sinks = section.get("sinks", "an array of [name, short name] pairs", |value| {
+                value
+                    .as_array()?
+                    .iter()
+                    .map(|pair| match pair.as_array()?[..] {
+                        [ref name, ref short_name] => {
+                            Some((name.as_str()?.to_owned(), short_name.as_str()?.to_owned()))
+                        }
+                        _ => None,
+                    })
+                    .collect::<Option<Vec<_>>>()
+            })?;
+            let streams = section.boolean("streams")?.unwrap_or(false);
+            add(builder, options, move |cnx| {
+                let pulse = sinks
+                    .unwrap_or_default()
+                    .into_iter()
+                    .fold(PulseVolume::new(cnx, attr), |pulse, (name, short_name)| {
+                        pulse.with_sink(name, short_name)
+                    });
+                if streams {
+                    pulse.with_streams()
+                } else {
+                    pulse
+                }
+            })
+        }
+        "root_property" => {
+            let property = section.required_string("property")?;
+            add(builder, options, move |cnx| {
+                RootProperty::new(cnx, attr, property)
+            })
+        }
+        "screen_capture" => add(builder, options, move |cnx| ScreenCapture::new(cnx, attr)),
+        "sensors" => {
+            let sensors = section
+                .strings("sensors")?
+                .ok_or_else(|| format_err!("{} is missing `sensors`", section.name))?;
+            let threshold = section.threshold(attributes)?;
+            add(builder, options, move |cnx| {
+                let sensors = Sensors::new(cnx, attr, sensors);
+                match threshold {
+                    Some(threshold) => sensors.with_threshold(threshold),
+                    None => sensors,
+                }
+            })
+        }
+        "stopwatch" => {
+            let name = section.required_string("name")?;
+            add(builder, options, move |cnx| Stopwatch::new(cnx, attr, name))
+        }
+        "systemd_timers" => add(builder, options, move |cnx| {
+            SystemdTimers::new(cnx, attr, warning_color)
+        }),
+        "taskbar" => {
+            let active_attr = section.attributes("active_attributes", attributes)?;
+            add(builder, options, move |cnx| {
+                Taskbar::new(cnx, active_attr, attr)
+            })
+        }
+        "time_tracker" => {
+            let display = match section.string("display")?.as_deref() {
+                None | Some("top_application") => TimeTrackerDisplay::TopApplication,
+                Some("total") => TimeTrackerDisplay::Total,
+                Some(display) => return Err(format_err!("Unknown display {:?}", display)),
+            };
+            add(builder, options, move |cnx| {
+                TimeTracker::new(cnx, display, attr)
+            })
+        }
+        "update_check" => {
+            let git_tags = section.string("git_tags")?;
+            add(builder, options, move |cnx| {
+                let update_check = UpdateCheck::new(cnx, attr);
+                match git_tags {
+                    Some(url) => update_check.with_git_tags(url),
+                    None => update_check,
+                }
+            })
+        }
+        "upower_battery" => add(builder, options, move |cnx| {
+            UPowerBattery::new(cnx, attr, warning_color)
+        }),
+        "usb_toast" => add(builder, options, move |cnx| UsbToast::new(cnx, attr)),
+        #[cfg(feature = "volume-widget")]
+        "volume" => add(builder, options, move |cnx| Volume::new(cnx, attr)),
+        "wireless" => {
+            let interface = section.required_string("interface")?;
+            let format = section.template("format")?;
+            add(builder, options, move |cnx| {
+                let wireless = Wireless::new(cnx, attr, interface);
+                match format {
+                    Some(format) => wireless.with_format(format),
+                    None => wireless,
+                }
+            })
+        }
+        other => return Err(format_err!("Unknown type of {}: {:?}", section.name, other)),
+    };
+    Ok(builder)
+}
+
+/// Parses a configuration file into a `CnxBuilder`, with its widgets.
+fn parse_config(config: &str) -> Result<CnxBuilder> {
+    let root = toml::parse(config)?;
+    let root = Section {
+        value: &root,
+        name: "the config".to_owned(),
+    };
+
+    let mut builder = Cnx::builder();
+    builder = builder.position(match root.string("position")?.as_deref() {
+        None | Some("top") => Position::Top,
+        Some("bottom") => Position::Bottom,
+        Some("left") => Position::Left,
+        Some("right") => Position::Right,
+        Some(position) => return Err(format_err!("Unknown position {:?}", position)),
+    });
+    if let Some(monitors) = root.string("monitors")? {
+        builder = builder.monitors(match monitors.as_str() {
+            "screen" => Monitors::Screen,
+            "primary" => Monitors::Primary,
+            "all" => Monitors::All,
+            _ => Monitors::Named(monitors),
+        });
+    }
+    if let Some(screen) = root.integer("screen")? {
+        builder = builder.screen(screen);
+    }
+    if let Some(height) = root.integer("height")? {
+        builder = builder.height(height);
+    }
+    if let Some(background) = root.color("background")? {
+        builder = builder.background(background);
+    }
+    if let Some(margins) = root.padding("margins")? {
+        builder = builder.margins(margins);
+    }
+    if let Some(spacing) = root.number("spacing")? {
+        builder = builder.spacing(spacing);
+    }
+    if let Some(factor) = root.number("large_mode_scale")? {
+        if factor <= 0.0 {
+            return Err(format_err!("large_mode_scale must be positive"));
+        }
+        builder = builder.large_mode_scale(factor);
+    }
+    if let Some(timeout) = root.number("auto_hide")? {
+        builder = builder.auto_hide(Duration::from_secs_f64(timeout));
+    }
+    if let Some(separator) = root.string("root_name_output")? {
+        builder = builder.root_name_output(separator);
+    }
+    if let Some(hotkey) = root.string("keyboard_hotkey")? {
+        builder = builder.keyboard_hotkey(hotkey);
+    }
+    if root.boolean("fifo_output")? == Some(true) {
+        builder = builder.fifo_output();
+    }
+    if let Some(value) = root.value.get("section") {
+        let section = Section {
+            value,
+            name: "[section]".to_owned(),
+        };
+        let style = parse_section_style(&section)?;
+        for &(name, region) in &[
+            ("left", Region::Left),
+            ("center", Region::Center),
+            ("right", Region::Right),
+        ] {
+            let style = match value.get(name) {
+                Some(value) => parse_section_style(&Section {
+                    value,
+                    name: format!("[section.{}]", name),
+                })?,
+                None => style.clone(),
+            };
+            builder = builder.section_style(region, style);
+        }
+    }
+
+    let mut attributes = HashMap::new();
+    attributes.insert("default".to_owned(), parse_attributes(&root)?);
+    if let Some(tables) = root.value.get("attributes") {
+        let tables = tables
+            .as_table()
+            .ok_or_else(|| format_err!("`attributes` must be a table of attributes"))?;
+        for (name, value) in tables {
+            let section = Section {
+                value,
+                name: format!("[attributes.{}]", name),
+            };
+            attributes.insert(name.clone(), parse_attributes(&section)?);
+        }
+    }
+
+    let widgets = match root.value.get("widget") {
+        Some(widgets) => widgets
+            .as_array()
+            .ok_or_else(|| format_err!("Widgets must be given as [[widget]] tables"))?,
+        None => &[],
+    };
+    for (i, value) in widgets.iter().enumerate() {
+        let section = Section {
+            value,
+            name: format!("widget {}", i + 1),
+        };
+        builder = add_widget(builder, &section, &attributes)?;
+        // Restarted widgets are created again from their section.
+        let (value, name, attributes) = (value.clone(), section.name, attributes.clone());
+        builder = builder.restart_last_with(move |cnx| {
+            let section = Section {
+                value: &value,
+                name: name.clone(),
+            };
+            let mut copy = cnx.without_widgets();
+            add_widget(CnxBuilder::new(), &section, &attributes)?.apply_widgets(&mut copy);
+            copy.widgets
+                .pop()
+                .map(|(widget, _)| widget)
+                .ok_or_else(|| format_err!("{} created no widget", name))
+        });
+    }
+    Ok(builder)
+}
+
+/// Reads and parses the configuration file at `path`.
+fn read_config(path: &Path) -> Result<CnxBuilder> {
+    let config = fs::read_to_string(path)
+        .with_context(|_| format!("Failed to read config file {}", path.display()))?;
+    parse_config(&config).with_context(|_| format!("Invalid config file {}", path.display()))
+}
+
+impl Cnx {
+    /// Creates a new `Cnx` instance, and its widgets, as described by the
+    /// TOML file at `path`. (Requires the `config` feature).
+    ///
+    /// This allows the stock `cnx` binary to be configured without writing any
+    /// Rust, although not every option (or widget) is available. The widgets
+    /// are reloaded whenever the file changes, see [`reload_on_change()`].
+    /// For example:
+    ///
+    /// ```toml
+    /// position = "bottom"   # Or "top", the default, "left" or "right".
+    /// monitors = "all"      # Or "screen", "primary" or an output, e.g. "HDMI-1".
+    /// height = 24
+    /// background = "282828" # Colors are hex, with an optional alpha byte.
+    /// spacing = 4
+    /// margins = [0, 0, 0, 0] # [left, right, top, bottom]
+    /// auto_hide = 2          # Seconds before hiding, while the pointer is elsewhere.
+    /// large_mode_scale = 2   # How much bigger large mode (see Control) draws the bar.
+    /// keyboard_hotkey = "Mod4+b" # Navigate the widgets with the arrow keys and Enter.
+    /// # root_name_output = " | " # Set the root window's name instead, for dwm.
+    /// # fifo_output = true       # Write named widgets to $XDG_RUNTIME_DIR/cnx/.
+    ///
+    /// # The default attributes of every widget.
+    /// font = "Noto Sans Mono 12"
+    /// fg_color = "ebdbb2"
+    /// padding = [5, 5, 0, 0]
+    ///
+    /// # More attributes, which widgets can use by name.
+    /// [attributes.active]
+    /// font = "Noto Sans Mono 12"
+    /// fg_color = "282828"
+    /// bg_color = "d65d0e"
+    /// padding = [5, 5, 0, 0]
+    ///
+    /// # Widgets are shown in the order they are given.
+    /// [[widget]]
+    /// type = "pager"
+    /// active_attributes = "active"
+    ///
+    /// [[widget]]
+    /// type = "active_window_title"
+    /// region = "center"     # Or "left", the default, or "right".
+    ///
+    /// [[widget]]
+    /// type = "clock"
+    /// format = "%Y-%m-%d %a %H:%M"
+    /// region = "right"
+    /// priority = 255
+    /// name = "clock"
+    /// ```
+    ///
+    /// An optional `[section]` table sets the [`SectionStyle`] of each
+    /// region's section, with `bg_color`, `padding` and `corner_radius` keys.
+    /// Its `[section.left]`, `[section.center]` and `[section.right]` tables
+    /// style a region's section differently.
+    ///
+    /// Each widget's `type` is the name of its module in [`widgets`], e.g.
+    /// `disk_temperature`. Widgets use the `default` [`Attributes`] unless
+    /// they name others with `attributes`. The `region`, `priority`, `name`,
+    /// `monitor` and `min_width` keys set their [`WidgetOptions`], and
+    /// `depends_on` is an array of what they need before they start:
+    /// `session_bus`, `system_bus`, `interface:<name>` or
+    /// `selection:<name>`. Widgets are otherwise configured with these keys:
+    ///
+    ///  - `active_window_title`: `max_chars` or `max_width` (in pixels), to
+    ///    shorten long titles, cutting off the `ellipsis` part: `start`,
+    ///    `middle` or `end` (the default). `class_attributes` is a table of
+    ///    window classes to the names of the attributes to show them with,
+    ///    e.g. `{ Alacritty = "terminal" }`. `icon = true` shows the
+    ///    window's icon before its title.
+    ///  - `battery`: `separate = true`, to show each battery on its own
+    ///    rather than adding them up, and `format`, which defaults to
+    ///    `({percent}% - {time_left})`.
+    ///  - `clock`: `format`, which defaults to `%H:%M`, and optionally
+    ///    `holiday_calendar`, the path of an `.ics` file of holidays to show
+    ///    with `holiday_attributes`, `zones`, other time zones to show,
+    ///    e.g. `[["UTC", "UTC"], ["SF", "America/Los_Angeles", "%H:%M %Z"]]`,
+    ///    `alternate_format`, shown instead once the clock is clicked, and
+    ///    `calendar_popup`, whether clicking it shows a month calendar.
+    ///  - `connectivity`: `online`, `portal` and `offline`, the labels for
+    ///    each state, which can be empty to show nothing.
+    ///  - `cpu_freq`: `format`, which defaults to `{avg}GHz {governor}`.
+    ///  - `custom`: `name`, which scripts send its text to.
+    ///  - `disk_temperature` and `sensors`: `warn` and `crit`, temperatures
+    ///    from which to use the named `warn_attributes` and `crit_attributes`.
+    ///  - `exec`: `command` and `interval`, in seconds, which defaults to 60.
+    ///  - `mic_level`: `source`, which defaults to the default source,
+    ///    `width`, of the meter in pixels, and `history`, a number of seconds
+    ///    of levels to graph instead.
+    ///  - `mpd`: `format` and `address`.
+    ///  - `pager` and `taskbar`: `active_attributes`.
+    ///  - `pager`: `dots`, either `circle` or `square`, to draw each
+    ///    workspace as a dot `dot_size` pixels across (8 by default).
+    ///  - `pulse_volume`: `sinks`, an array of `[name, short name]` pairs of
+    ///    the sinks to switch between, and `streams = true` to show each
+    ///    application's volume too.
+    ///  - `root_property`: `property`, the name of the property to show.
+    ///  - `sensors`: `sensors`, an array of the sensors to show.
+    ///  - `session_lock`: `unlocked` and `locked`, the labels for each state.
+    ///  - `status_notifier`: `attention_attributes`, for items which need
+    ///    attention.
+    ///  - `stopwatch`: `name`, which IPC commands control it by.
+    ///  - `time_tracker`: `display`, either `top_application` (the default)
+    ///    or `total`.
+    ///  - `update_check`: `git_tags`, the URL of a repository to check.
+    ///  - `wireless`: `interface`, and optionally `format`, e.g.
+    ///    `{ssid} {quality}%`.
+    ///  - `battery`, `device_batteries`, `systemd_timers` and
+    ///    `upower_battery`: `warning_color`, which defaults to red.
+    ///
+    /// The `format` of the `battery`, `cpu_freq` and `wireless` widgets is a
+    /// [`Template`], whose placeholders can give a precision, e.g.
+    /// `{percent:.1}`.
+    ///
+    /// Each widget is created again from its table whenever it's restarted
+    /// after failing, as if it were added with
+    /// [`add_restartable_widget()`].
+    ///
+    /// [`add_restartable_widget()`]: #method.add_restartable_widget
+    /// [`reload_on_change()`]: #method.reload_on_change
+    /// [`SectionStyle`]: text/struct.SectionStyle.html
+    /// [`Template`]: template/struct.Template.html
+    /// [`widgets`]: widgets/index.html
+    /// [`Attributes`]: text/struct.Attributes.html
+    /// [`WidgetOptions`]: widgets/struct.WidgetOptions.html
+    pub fn from_config<P: AsRef<Path>>(path: P) -> Result<Cnx> {
+        let path = path.as_ref().to_owned();
+        let mut cnx = read_config(&path)?.build()?;
+        let reload_path = path.clone();
+        cnx.reload_on_change(path, move |cnx| {
+            read_config(&reload_path)?.apply(cnx);
+            Ok(())
+        });
+        Ok(cnx)
+    }
+}
@@ -0,0 +1,419 @@
+//! Controlling a running Cnx instance.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use futures::unsync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+use crate::appearance::ColorScheme;
+use crate::error::format_err;
+use crate::json::{self, Value};
+use crate::widgets::StopwatchAction;
+use crate::Result;
+
+/// A command sent to the running bar by a [`Control`].
+///
+/// [`Control`]: struct.Control.html
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Command {
+    Refresh(String),
+    Hide(String),
+    Show(String),
+    HideBar,
+    ShowBar,
+    ToggleBar,
+    HideScratchpad,
+    ShowScratchpad,
+    ToggleScratchpad,
+    // Sent straight to the custom widget, rather than to the bar.
+    SetText(String, String),
+    // Also sent straight to the stopwatch widget.
+    Stopwatch(String, StopwatchAction),
+    SetColorScheme(ColorScheme),
+    SetLargeMode(bool),
+    ToggleLargeMode,
+}
+
+impl Command {
+    /// Parses a command in the form used over IPC, e.g. `refresh updates`,
+    /// `bar toggle` or `stopwatch start tea`.
+    pub fn parse(line: &str) -> Result<Command> {
+        let line = line.trim();
+        let mut parts = line.splitn(2, char::is_whitespace);
+        let verb = parts.next().unwrap_or("");
+        let name = parts.next().map(str::trim).unwrap_or("");
+        if verb == "bar" {
+            return match name {
+                "hide" => Ok(Command::HideBar),
+                "show" => Ok(Command::ShowBar),
+                "toggle" => Ok(Command::ToggleBar),
+                _ => Err(format_err!("Unknown bar command: {:?}", line)),
+            };
+        }
+        if verb == "scratchpad" {
+            return match name {
+                "hide" => Ok(Command::HideScratchpad),
+                "show" => Ok(Command::ShowScratchpad),
+                "toggle" => Ok(Command::ToggleScratchpad),
+                _ => Err(format_err!("Unknown scratchpad command: {:?}", line)),
+            };
+        }
+        if verb == "theme" {
+            return match name {
+                "dark" => Ok(Command::SetColorScheme(ColorScheme::Dark)),
+                "light" => Ok(Command::SetColorScheme(ColorScheme::Light)),
+                _ => Err(format_err!("Unknown theme command: {:?}", line)),
+            };
+        }
+        if verb == "large" {
+            return match name {
+                "on" => Ok(Command::SetLargeMode(true)),
+                "off" => Ok(Command::SetLargeMode(false)),
+                "toggle" => Ok(Command::ToggleLargeMode),
+                _ => Err(format_err!("Unknown large mode command: {:?}", line)),
+            };
+        }
+        if name.is_empty() {
+            return Err(format_err!("Missing widget name in command: {:?}", line));
+        }
+        if verb == "stopwatch" {
+            let mut parts = name.splitn(2, char::is_whitespace);
+            let action = StopwatchAction::parse(parts.next().unwrap_or(""))?;
+            return match parts.next().map(str::trim) {
+                Some(name) if !name.is_empty() => Ok(Command::Stopwatch(name.to_owned(), action)),
+                _ => Err(format_err!("Missing widget name in command: {:?}", line)),
+            };
+        }
+        let name = name.to_owned();
+        match verb {
+            "refresh" => Ok(Command::Refresh(name)),
+            "hide" => Ok(Command::Hide(name)),
+            "show" => Ok(Command::Show(name)),
+            _ => Err(format_err!("Unknown command: {:?}", line)),
+        }
+    }
+
+    /// Parses a command in the JSON form used over IPC, e.g.
+    /// `{"command": "refresh", "widget": "updates"}`.
+    pub fn from_json(line: &str) -> Result<Command> {
+        let value = json::parse(line)?;
+        let field = |key| {
+            value
+                .get(key)
+                .and_then(Value::as_str)
+                .map(ToOwned::to_owned)
+                .ok_or_else(|| format_err!("Missing string {:?} in command: {}", key, line.trim()))
+        };
+        match field("command")?.as_str() {
+            "refresh" => Ok(Command::Refresh(field("widget")?)),
+            "hide" => Ok(Command::Hide(field("widget")?)),
+            "show" => Ok(Command::Show(field("widget")?)),
+            "hide_bar" => Ok(Command::HideBar),
+            "show_bar" => Ok(Command::ShowBar),
+            "toggle_bar" => Ok(Command::ToggleBar),
+            "hide_scratchpad" => Ok(Command::HideScratchpad),
+            "show_scratchpad" => Ok(Command::ShowScratchpad),
+            "toggle_scratchpad" => Ok(Command::ToggleScratchpad),
+            "set_text" => Ok(Command::SetText(field("widget")?, field("text")?)),
+            "stopwatch" => Ok(Command::Stopwatch(
+                field("widget")?,
+                StopwatchAction::parse(&field("action")?)?,
+            )),
+            "set_theme" => match field("theme")?.as_str() {
+                "dark" => Ok(Command::SetColorScheme(ColorScheme::Dark)),
+                "light" => Ok(Command::SetColorScheme(ColorScheme::Light)),
+                theme => Err(format_err!("Unknown theme: {:?}", theme)),
+            },
+            "large_mode_on" => Ok(Command::SetLargeMode(true)),
+            "large_mode_off" => Ok(Command::SetLargeMode(false)),
+            "toggle_large_mode" => Ok(Command::ToggleLargeMode),
+            command => Err(format_err!("Unknown command: {:?}", command)),
+        }
+    }
+}
+
+pub(crate) type Commands = UnboundedReceiver<Command>;
+
+/// A handle for controlling a running Cnx instance.
+///
+/// This is returned by [`Cnx::control()`]. It can be kept (e.g. by a widget's
+/// [`ClickHandler`]) and used once Cnx is running to act on widgets, which are
+/// identified by the name given to [`WidgetOptions::name()`]. Commands for
+/// widgets which don't exist are logged and ignored.
+///
+/// The same commands can be sent by other processes over IPC. See
+/// [`Cnx::enable_ipc()`].
+///
+/// [`Cnx::control()`]: struct.Cnx.html#method.control
+/// [`Cnx::enable_ipc()`]: struct.Cnx.html#method.enable_ipc
+/// [`ClickHandler`]: widgets/type.ClickHandler.html
+/// [`WidgetOptions::name()`]: widgets/struct.WidgetOptions.html#method.name
+#[derive(Clone)]
+pub struct Control {
+    sender: UnboundedSender<Command>,
+    // Where to send the text of each `Custom` widget, by name.
+    texts: Senders<String>,
+    // Where to send the actions for each `Stopwatch` widget, by name.
+    stopwatches: Senders<StopwatchAction>,
+}
+
+type Senders<T> = Rc<RefCell<HashMap<String, UnboundedSender<T>>>>;
+
+/// Sends `value` straight to the widget called `name`, which is a `kind`
+/// widget.
+fn send_to<T>(senders: &Senders<T>, kind: &str, name: String, value: T) -> Result<()> {
+    let mut senders = senders.borrow_mut();
+    let sent = senders
+        .get(&name)
+        .map(|sender| sender.unbounded_send(value).is_ok());
+    match sent {
+        Some(true) => Ok(()),
+        Some(false) => {
+            senders.remove(&name);
+            Err(format_err!(
+                "{} widget {:?} is no longer running",
+                kind,
+                name
+            ))
+        }
+        None => Err(format_err!(
+            "No {} widget named {:?}",
+            kind.to_lowercase(),
+            name
+        )),
+    }
+}
+
+impl Control {
+    pub(crate) fn new(sender: UnboundedSender<Command>) -> Control {
+        Control {
+            sender,
+            texts: Rc::new(RefCell::new(HashMap::new())),
+            stopwatches: Rc::new(RefCell::new(HashMap::new())),
+        }
+    }
+
+    pub(crate) fn send(&self, command: Command) -> Result<()> {
+        match command {
+            Command::SetText(name, text) => return send_to(&self.texts, "Custom", name, text),
+            Command::Stopwatch(name, action) => {
+                return send_to(&self.stopwatches, "Stopwatch", name, action)
+            }
+            _ => {}
+        }
+        self.sender
+            .unbounded_send(command)
+            .map_err(|_| format_err!("Cnx is no longer running"))
+    }
+
+    /// Returns the text sent to the custom widget called `name`, replacing
+    /// any other custom widget of the same name.
+    pub(crate) fn texts(&self, name: String) -> UnboundedReceiver<String> {
+        let (sender, receiver) = mpsc::unbounded();
+        self.texts.borrow_mut().insert(name, sender);
+        receiver
+    }
+
+    /// Sends the actions for the stopwatch widget called `name` to `sender`,
+    /// replacing any other stopwatch widget of the same name.
+    pub(crate) fn add_stopwatch(&self, name: String, sender: UnboundedSender<StopwatchAction>) {
+        self.stopwatches.borrow_mut().insert(name, sender);
+    }
+
+    /// Starts, stops or resets the [`Stopwatch`] widget called `name`.
+    ///
+    /// [`Stopwatch`]: widgets/struct.Stopwatch.html
+    pub fn stopwatch<S: Into<String>>(&self, name: S, action: StopwatchAction) -> Result<()> {
+        self.send(Command::Stopwatch(name.into(), action))
+    }
+
+    /// Shows `text` on the [`Custom`] widget called `name`.
+    ///
+    /// [`Custom`]: widgets/struct.Custom.html
+    pub fn set_text<N: Into<String>, T: Into<String>>(&self, name: N, text: T) -> Result<()> {
+        self.send(Command::SetText(name.into(), text.into()))
+    }
+
+    /// Draws the bars with the palette for `scheme`, as if the desktop's color
+    /// scheme had changed. See [`Cnx::set_dark_palette()`].
+    ///
+    /// [`Cnx::set_dark_palette()`]: struct.Cnx.html#method.set_dark_palette
+    pub fn set_color_scheme(&self, scheme: ColorScheme) -> Result<()> {
+        self.send(Command::SetColorScheme(scheme))
+    }
+
+    /// Switches large mode on or off. In large mode, the bars' fonts, padding
+    /// and height are multiplied by the factor set with
+    /// [`Cnx::set_large_mode_scale()`], e.g. to make the bars readable during
+    /// a presentation.
+    ///
+    /// [`Cnx::set_large_mode_scale()`]: struct.Cnx.html#method.set_large_mode_scale
+    pub fn set_large_mode(&self, large: bool) -> Result<()> {
+        self.send(Command::SetLargeMode(large))
+    }
+
+    /// Switches large mode on if it is off, or off if it is on. See
+    /// [`set_large_mode()`].
+    ///
+    /// [`set_large_mode()`]: #method.set_large_mode
+    pub fn toggle_large_mode(&self) -> Result<()> {
+        self.send(Command::ToggleLargeMode)
+    }
+
+    /// Makes the named widget update immediately, rather than waiting for its
+    /// next update.
+    ///
+    /// This only affects widgets which periodically poll for changes. Widgets
+    /// which are notified of changes are always up-to-date.
+    pub fn refresh<S: Into<String>>(&self, name: S) -> Result<()> {
+        self.send(Command::Refresh(name.into()))
+    }
+
+    /// Hides the named widget, until it is shown again with [`show()`].
+    ///
+    /// [`show()`]: #method.show
+    pub fn hide<S: Into<String>>(&self, name: S) -> Result<()> {
+        self.send(Command::Hide(name.into()))
+    }
+
+    /// Shows the named widget, if it was hidden with [`hide()`].
+    ///
+    /// [`hide()`]: #method.hide
+    pub fn show<S: Into<String>>(&self, name: S) -> Result<()> {
+        self.send(Command::Show(name.into()))
+    }
+
+    /// Hides every bar, until they are shown again with [`show_bar()`].
+    ///
+    /// The bars' windows are unmapped and the space reserved for them is
+    /// released, so that other windows can use it. Polling widgets are paused
+    /// while the bars are hidden.
+    ///
+    /// [`show_bar()`]: #method.show_bar
+    pub fn hide_bar(&self) -> Result<()> {
+        self.send(Command::HideBar)
+    }
+
+    /// Shows the bars again, if they were hidden with [`hide_bar()`].
+    ///
+    /// [`hide_bar()`]: #method.hide_bar
+    pub fn show_bar(&self) -> Result<()> {
+        self.send(Command::ShowBar)
+    }
+
+    /// Hides the bars if they are shown, or shows them if they were hidden.
+    pub fn toggle_bar(&self) -> Result<()> {
+        self.send(Command::ToggleBar)
+    }
+
+    /// Shows the scratchpad bars, which show the widgets added with
+    /// [`Cnx::add_scratchpad_widget()`].
+    ///
+    /// The scratchpads are hidden again by [`hide_scratchpad()`], by a click
+    /// anywhere outside of them, or once the timeout set with
+    /// [`Cnx::set_scratchpad_timeout()`] has passed.
+    ///
+    /// [`Cnx::add_scratchpad_widget()`]: struct.Cnx.html#method.add_scratchpad_widget
+    /// [`Cnx::set_scratchpad_timeout()`]: struct.Cnx.html#method.set_scratchpad_timeout
+    /// [`hide_scratchpad()`]: #method.hide_scratchpad
+    pub fn show_scratchpad(&self) -> Result<()> {
+        self.send(Command::ShowScratchpad)
+    }
+
+    /// Hides the scratchpad bars, if they were shown with
+    /// [`show_scratchpad()`].
+    ///
+    /// [`show_scratchpad()`]: #method.show_scratchpad
+    pub fn hide_scratchpad(&self) -> Result<()> {
+        self.send(Command::HideScratchpad)
+    }
+
+    /// Shows the scratchpad bars if they are hidden, or hides them if they
+    /// are shown.
+    pub fn toggle_scratchpad(&self) -> Result<()> {
+        self.send(Command::ToggleScratchpad)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Command;
+    use crate::appearance::ColorScheme;
+    use crate::widgets::StopwatchAction;
+
+    #[test]
+    fn parses_commands() {
+        assert_eq!(
+            Command::parse("refresh updates\n").unwrap(),
+            Command::Refresh("updates".to_owned())
+        );
+        assert_eq!(
+            Command::parse("hide  battery ").unwrap(),
+            Command::Hide("battery".to_owned())
+        );
+        assert_eq!(
+            Command::parse("show my clock").unwrap(),
+            Command::Show("my clock".to_owned())
+        );
+        assert_eq!(Command::parse("bar toggle").unwrap(), Command::ToggleBar);
+        assert_eq!(
+            Command::parse("scratchpad show").unwrap(),
+            Command::ShowScratchpad
+        );
+        assert_eq!(
+            Command::parse("theme dark").unwrap(),
+            Command::SetColorScheme(ColorScheme::Dark)
+        );
+        assert_eq!(
+            Command::parse("large on").unwrap(),
+            Command::SetLargeMode(true)
+        );
+        assert_eq!(
+            Command::parse("large toggle").unwrap(),
+            Command::ToggleLargeMode
+        );
+        assert_eq!(
+            Command::parse("stopwatch toggle tea").unwrap(),
+            Command::Stopwatch("tea".to_owned(), StopwatchAction::Toggle)
+        );
+        assert!(Command::parse("stopwatch start").is_err());
+        assert!(Command::parse("refresh").is_err());
+        assert!(Command::parse("bar explode").is_err());
+        assert!(Command::parse("large").is_err());
+        assert!(Command::parse("explode updates").is_err());
+    }
+
+    #[test]
+    fn parses_json_commands() {
+        assert_eq!(
+            Command::from_json(r#"{"command": "refresh", "widget": "updates"}"#).unwrap(),
+            Command::Refresh("updates".to_owned())
+        );
+        assert_eq!(
+            Command::from_json(r#"{"command": "toggle_bar"}"#).unwrap(),
+            Command::ToggleBar
+        );
+        assert_eq!(
+            Command::from_json(r#"{"command": "set_text", "widget": "vpn", "text": "🔒 on"}"#)
+                .unwrap(),
+            Command::SetText("vpn".to_owned(), "🔒 on".to_owned())
+        );
+        assert_eq!(
+            Command::from_json(r#"{"command": "set_theme", "theme": "light"}"#).unwrap(),
+            Command::SetColorScheme(ColorScheme::Light)
+        );
+        assert_eq!(
+            Command::from_json(r#"{"command": "large_mode_off"}"#).unwrap(),
+            Command::SetLargeMode(false)
+        );
+        assert_eq!(
+            Command::from_json(r#"{"command": "stopwatch", "widget": "tea", "action": "reset"}"#)
+                .unwrap(),
+            Command::Stopwatch("tea".to_owned(), StopwatchAction::Reset)
+        );
+        assert!(Command::from_json(r#"{"command": "hide"}"#).is_err());
+        assert!(Command::from_json(r#"{"command": "explode"}"#).is_err());
+        assert!(Command::from_json("refresh updates").is_err());
+    }
+}
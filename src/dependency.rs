@@ -0,0 +1,195 @@
+//! Delaying widgets until the shared subsystems they rely on are ready, see
+//! `WidgetOptions::depends_on()`.
+//!
+//! At login, Cnx often starts alongside the things its widgets talk to (the
+//! D-Bus session bus, a system tray, the network interfaces of slow drivers),
+//! so these may not be there yet. Rather than failing and being restarted
+//! with backoff, a widget with dependencies isn't started until they are all
+//! ready, showing its placeholder (if any) meanwhile.
+
+use std::env;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::Duration;
+
+use futures::{stream, Future, Stream};
+use log::{debug, info};
+use xcb_util::ewmh;
+
+use crate::timer::Timer;
+use crate::widgets::{
+    ClickHandler, Dependency, LayoutHandler, Refresh, Widget, WidgetOptions, WidgetStream,
+};
+use crate::{xcb_ext, Result};
+
+/// How often the dependencies are checked while they aren't ready.
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Returns the path of the socket in a D-Bus address like
+/// `unix:path=/run/user/1000/bus,guid=…`, if it has one.
+fn socket_path(address: &str) -> Option<PathBuf> {
+    address
+        .split(';')
+        .filter_map(|address| address.strip_prefix("unix:"))
+        .flat_map(|options| options.split(','))
+        .find_map(|option| option.strip_prefix("path="))
+        .map(PathBuf::from)
+}
+
+/// Whether the bus whose address is in the environment variable `var` (or
+/// `default`, if it isn't set) accepts connections.
+fn bus_ready(var: &str, default: Option<PathBuf>) -> bool {
+    let path = match env::var(var) {
+        Ok(address) => socket_path(&address),
+        Err(_) => default,
+    };
+    match path {
+        Some(path) => UnixStream::connect(path).is_ok(),
+        // Abstract sockets and other transports can't be checked this way,
+        // so leave it to the widget.
+        None => true,
+    }
+}
+
+/// Checks whether dependencies are ready, using the bar's X connection to
+/// check selections, or otherwise keeping its own between checks.
+struct Checker {
+    conn: Option<Rc<ewmh::Connection>>,
+}
+
+impl Checker {
+    fn is_ready(&mut self, dependency: &Dependency) -> bool {
+        match *dependency {
+            Dependency::SessionBus => bus_ready(
+                "DBUS_SESSION_BUS_ADDRESS",
+                env::var_os("XDG_RUNTIME_DIR").map(|dir| Path::new(&dir).join("bus")),
+            ),
+            Dependency::SystemBus => bus_ready(
+                "DBUS_SYSTEM_BUS_ADDRESS",
+                Some(PathBuf::from("/run/dbus/system_bus_socket")),
+            ),
+            Dependency::Interface(ref name) => Path::new("/sys/class/net").join(name).exists(),
+            Dependency::Selection(ref name) => match self.selection_owned(name) {
+                Ok(owned) => owned,
+                Err(e) => {
+                    debug!("Failed to check the owner of {}: {}", name, e);
+                    false
+                }
+            },
+        }
+    }
+
+    fn selection_owned(&mut self, name: &str) -> Result<bool> {
+        if self.conn.is_none() {
+            self.conn = Some(xcb_ext::connect()?.0);
+        }
+        let conn = match self.conn {
+            Some(ref conn) => conn,
+            None => return Ok(false),
+        };
+        let atom = xcb_ext::intern_atom(conn, name)?;
+        let owner = xcb::get_selection_owner(conn, atom).get_reply();
+        Ok(owner.is_ok_and(|reply| reply.owner() != xcb::NONE))
+    }
+}
+
+/// A widget which isn't started until its dependencies are ready.
+pub(crate) struct Delayed {
+    inner: Box<dyn Widget>,
+    timer: Timer,
+    conn: Option<Rc<ewmh::Connection>>,
+    name: String,
+    dependencies: Vec<Dependency>,
+}
+
+impl Delayed {
+    /// Wraps `widget`, unless its `options` give it no dependencies. `conn`
+    /// is the bars' X connection, if there are any bars.
+    pub fn wrap(
+        widget: Box<dyn Widget>,
+        options: &WidgetOptions,
+        timer: &Timer,
+        conn: Option<&Rc<ewmh::Connection>>,
+    ) -> Box<dyn Widget> {
+        if options.dependencies.is_empty() {
+            return widget;
+        }
+        Box::new(Delayed {
+            inner: widget,
+            timer: timer.clone(),
+            conn: conn.cloned(),
+            name: options.name.clone().unwrap_or_else(|| "widget".to_owned()),
+            dependencies: options.dependencies.clone(),
+        })
+    }
+}
+
+impl Widget for Delayed {
+    fn stream(self: Box<Self>) -> Result<WidgetStream> {
+        self.refreshable_stream(Box::new(stream::empty()))
+    }
+
+    fn refreshable_stream(self: Box<Self>, refresh: Refresh) -> Result<WidgetStream> {
+        let Delayed {
+            inner,
+            timer,
+            conn,
+            name,
+            dependencies,
+        } = *self;
+        let mut checker = Checker { conn };
+        let mut waiting = false;
+        let ready = stream::once(Ok(()))
+            .chain(timer.interval(CHECK_INTERVAL))
+            .filter(move |()| {
+                let unready = dependencies
+                    .iter()
+                    .find(|dependency| !checker.is_ready(dependency));
+                match unready {
+                    Some(dependency) if !waiting => {
+                        info!("The {} is waiting for {}", name, dependency);
+                        waiting = true;
+                    }
+                    None if waiting => info!("Starting the {}", name),
+                    _ => {}
+                }
+                unready.is_none()
+            })
+            .into_future()
+            .map_err(|(e, _)| e);
+        Ok(Box::new(
+            ready
+                .and_then(move |_| inner.refreshable_stream(refresh))
+                .flatten_stream(),
+        ))
+    }
+
+    fn click_handler(&mut self) -> Option<ClickHandler> {
+        self.inner.click_handler()
+    }
+
+    fn layout_handler(&mut self) -> Option<LayoutHandler> {
+        self.inner.layout_handler()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use super::socket_path;
+
+    #[test]
+    fn works() {
+        assert_eq!(
+            socket_path("unix:path=/run/user/1000/bus,guid=0123abcd"),
+            Some(PathBuf::from("/run/user/1000/bus"))
+        );
+        assert_eq!(
+            socket_path("tcp:host=localhost;unix:path=/tmp/bus"),
+            Some(PathBuf::from("/tmp/bus"))
+        );
+        assert_eq!(socket_path("unix:abstract=/tmp/dbus-XYZ"), None);
+    }
+}
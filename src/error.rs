@@ -0,0 +1,167 @@
+//! The error type used throughout Cnx and its widgets.
+
+use std::error;
+use std::fmt;
+use std::io;
+use std::result;
+
+/// An error from Cnx, or from one of its widgets.
+///
+/// Errors which happened while doing something that Cnx can describe (e.g.
+/// reading a particular file) are wrapped in [`Context`], so use
+/// [`root_cause()`] to match on what actually went wrong.
+///
+/// [`Context`]: #variant.Context
+/// [`root_cause()`]: #method.root_cause
+#[derive(Debug)]
+pub enum Error {
+    /// Talking to the X server failed, e.g. connecting or a request failing.
+    X(String),
+    /// Cairo failed to draw the bar.
+    Cairo(String),
+    /// Pango failed to lay out text.
+    Pango(String),
+    /// A widget's stream failed, stopping the bar because of the widget's
+    /// [`RestartPolicy`].
+    ///
+    /// [`RestartPolicy`]: ../widgets/enum.RestartPolicy.html
+    Widget(Box<Error>),
+    /// An I/O operation failed, e.g. reading a file or running a command.
+    Io(io::Error),
+    /// Anything else, described by a message.
+    Message(String),
+    /// An error from another crate, e.g. failing to parse a number.
+    Other(Box<dyn error::Error + Send + Sync>),
+    /// An error, along with what was being done when it happened.
+    Context { context: String, source: Box<Error> },
+}
+
+impl Error {
+    /// Returns the error underneath any [`Context`] or [`Widget`] wrapping
+    /// it.
+    ///
+    /// [`Context`]: #variant.Context
+    /// [`Widget`]: #variant.Widget
+    pub fn root_cause(&self) -> &Error {
+        match *self {
+            Error::Context { ref source, .. } | Error::Widget(ref source) => source.root_cause(),
+            ref e => e,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::X(ref message)
+            | Error::Cairo(ref message)
+            | Error::Pango(ref message)
+            | Error::Message(ref message) => f.write_str(message),
+            Error::Widget(ref e) => write!(f, "Widget failed: {}", e),
+            Error::Io(ref e) => e.fmt(f),
+            Error::Other(ref e) => e.fmt(f),
+            Error::Context { ref context, .. } => f.write_str(context),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::Widget(ref e) | Error::Context { source: ref e, .. } => Some(&**e),
+            Error::Io(ref e) => Some(e),
+            Error::Other(ref e) => Some(&**e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
+impl From<xcb::ConnError> for Error {
+    fn from(e: xcb::ConnError) -> Error {
+        Error::X(e.to_string())
+    }
+}
+
+impl From<xcb::GenericError> for Error {
+    fn from(e: xcb::GenericError) -> Error {
+        Error::X(format!(
+            "X request failed with error code {}",
+            e.error_code()
+        ))
+    }
+}
+
+macro_rules! other_errors {
+    ($($error:ty),*) => {
+        $(
+            impl From<$error> for Error {
+                fn from(e: $error) -> Error {
+                    Error::Other(Box::new(e))
+                }
+            }
+        )*
+    };
+}
+
+other_errors!(
+    std::env::VarError,
+    std::net::AddrParseError,
+    std::num::ParseFloatError,
+    std::num::ParseIntError,
+    std::str::Utf8Error,
+    std::string::FromUtf8Error,
+    std::time::SystemTimeError,
+    chrono::ParseError,
+    log::SetLoggerError,
+    tokio_timer::TimerError
+);
+
+#[cfg(feature = "volume-widget")]
+other_errors!(alsa::Error);
+
+/// Creates an [`Error::Message`] from a format string, like `format!()`.
+macro_rules! format_err {
+    ($($arg:tt)*) => {
+        crate::error::Error::Message(format!($($arg)*))
+    };
+}
+pub(crate) use format_err;
+
+/// Adds context to the errors of `Result`s.
+pub(crate) trait ResultExt<T, E> {
+    /// Wraps the error, if any, in an [`Error::Context`] describing what was
+    /// being done.
+    fn context<D: fmt::Display>(self, context: D) -> result::Result<T, Error>;
+
+    /// Like `context()`, but only creates the context if there is an error.
+    fn with_context<D, F>(self, f: F) -> result::Result<T, Error>
+    where
+        D: fmt::Display,
+        F: FnOnce(&E) -> D;
+}
+
+impl<T, E: Into<Error>> ResultExt<T, E> for result::Result<T, E> {
+    fn context<D: fmt::Display>(self, context: D) -> result::Result<T, Error> {
+        self.with_context(|_| context)
+    }
+
+    fn with_context<D, F>(self, f: F) -> result::Result<T, Error>
+    where
+        D: fmt::Display,
+        F: FnOnce(&E) -> D,
+    {
+        self.map_err(|e| {
+            let context = f(&e).to_string();
+            Error::Context {
+                context,
+                source: Box::new(e.into()),
+            }
+        })
+    }
+}
@@ -0,0 +1,137 @@
+//! Writing the text of named widgets to FIFOs, so that other bars (e.g.
+//! xmobar's `PipeReader` or polybar's `tail` scripts) can show them. See
+//! [`Cnx::enable_fifo_output()`].
+//!
+//! [`Cnx::enable_fifo_output()`]: ../struct.Cnx.html#method.enable_fifo_output
+
+use std::env;
+use std::ffi::CString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, ErrorKind, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{FileTypeExt, MetadataExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+
+use futures::Stream;
+use log::{debug, warn};
+
+use crate::error::{format_err, ResultExt};
+use crate::ipc;
+use crate::text::{self, Text};
+use crate::widgets::{ClickHandler, LayoutHandler, Refresh, Widget, WidgetStream};
+use crate::Result;
+
+/// The default directory of the FIFOs: `$XDG_RUNTIME_DIR/cnx/`, or
+/// `/tmp/cnx-$UID/` (alongside the IPC socket) if `$XDG_RUNTIME_DIR` isn't
+/// set.
+pub(crate) fn default_dir() -> PathBuf {
+    match env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) => PathBuf::from(dir).join("cnx"),
+        None => {
+            let uid = unsafe { libc::getuid() };
+            PathBuf::from(format!("/tmp/cnx-{}", uid))
+        }
+    }
+}
+
+/// Creates the FIFO at `path`, unless there already is one. One which
+/// already exists must belong to us, and mustn't be readable by others.
+fn make_fifo(path: &Path) -> Result<()> {
+    match fs::symlink_metadata(path) {
+        Ok(ref metadata) if !metadata.file_type().is_fifo() => {
+            return Err(format_err!("{} isn't a FIFO", path.display()))
+        }
+        Ok(ref metadata) if metadata.uid() != unsafe { libc::getuid() } => {
+            return Err(format_err!("{} belongs to another user", path.display()))
+        }
+        Ok(ref metadata) if metadata.mode() & 0o066 != 0 => {
+            return Err(format_err!(
+                "{} is readable or writable by other users",
+                path.display()
+            ))
+        }
+        Ok(_) => return Ok(()),
+        Err(_) => {}
+    }
+    let c_path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|_| format_err!("Invalid path {}", path.display()))?;
+    if unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) } == -1 {
+        return Err(io::Error::last_os_error())
+            .with_context(|_| format!("Failed to create FIFO {}", path.display()));
+    }
+    Ok(())
+}
+
+/// Opens the FIFO called `name` in `dir`, creating it if need be.
+///
+/// It is opened for reading as well as writing, so that opening it doesn't
+/// wait for a reader, and readers don't see the end of the file when we
+/// haven't written for a while.
+fn open(dir: &Path, name: &str) -> Result<File> {
+    if name.is_empty() || name.contains('/') {
+        return Err(format_err!("Widget name {:?} can't name a FIFO", name));
+    }
+    ipc::private_dir(dir)?;
+    let path = dir.join(name);
+    make_fifo(&path)?;
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .custom_flags(libc::O_NONBLOCK)
+        .open(&path)
+        .with_context(|_| format!("Failed to open FIFO {}", path.display()))
+}
+
+/// Writes a widget's `texts` to its FIFO, as a line of plain text.
+fn write(fifo: &mut File, name: &str, texts: &[Text]) {
+    let line = format!("{}\n", text::join_plain_text(texts));
+    match fifo.write_all(line.as_bytes()) {
+        Ok(()) => {}
+        // Nobody is reading, and the pipe is full of older lines.
+        Err(ref e) if e.kind() == ErrorKind::WouldBlock => {
+            debug!("Dropped update to the FIFO of {}", name)
+        }
+        Err(e) => warn!("Failed to write to the FIFO of {}: {}", name, e),
+    }
+}
+
+/// A widget, which also writes its text to the FIFO of its `name`.
+pub(crate) struct FifoOutput {
+    pub inner: Box<dyn Widget>,
+    pub dir: PathBuf,
+    pub name: String,
+}
+
+impl Widget for FifoOutput {
+    fn stream(self: Box<Self>) -> Result<WidgetStream> {
+        self.refreshable_stream(Box::new(futures::stream::empty()))
+    }
+
+    fn refreshable_stream(self: Box<Self>, refresh: Refresh) -> Result<WidgetStream> {
+        let this = *self;
+        let texts = this.inner.refreshable_stream(refresh)?;
+        match open(&this.dir, &this.name) {
+            Ok(mut fifo) => {
+                let name = this.name;
+                Ok(Box::new(
+                    texts.inspect(move |texts| write(&mut fifo, &name, texts)),
+                ))
+            }
+            Err(e) => {
+                warn!(
+                    "The text of {} won't be written to a FIFO: {}",
+                    this.name, e
+                );
+                Ok(texts)
+            }
+        }
+    }
+
+    fn click_handler(&mut self) -> Option<ClickHandler> {
+        self.inner.click_handler()
+    }
+
+    fn layout_handler(&mut self) -> Option<LayoutHandler> {
+        self.inner.layout_handler()
+    }
+}
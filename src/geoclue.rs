@@ -0,0 +1,156 @@
+//! Finding where the computer is with [GeoClue], for the widgets which can
+//! follow the location rather than using fixed coordinates.
+//!
+//! GeoClue only keeps a client for as long as the D-Bus connection which
+//! created it, so it can't be driven with one-off `busctl` calls. Instead,
+//! this runs GeoClue's `where-am-i` demo (which most distributions ship, and
+//! which GeoClue allows by default) and reads the locations it prints.
+//!
+//! [GeoClue]: https://gitlab.freedesktop.org/geoclue/geoclue/-/wikis/home
+
+use std::path::Path;
+use std::process::Command;
+
+use futures::{stream, Stream};
+use log::warn;
+use tokio_core::reactor::Handle;
+
+use crate::error::{format_err, Error};
+use crate::process;
+use crate::Result;
+
+/// Where distributions install `where-am-i`.
+const WHERE_AM_I: &[&str] = &[
+    "/usr/libexec/geoclue-2.0/demos/where-am-i",
+    "/usr/lib/geoclue-2.0/demos/where-am-i",
+    "/usr/lib/geoclue/demos/where-am-i",
+];
+
+// City-level accuracy is plenty for the weather, and doesn't need GPS.
+const ACCURACY_CITY: &str = "4";
+
+/// How far (in metres) the location must move before widgets are told.
+const THRESHOLD: f64 = 5_000.0;
+
+/// A position on Earth, in degrees.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Location {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+impl Location {
+    pub fn new(latitude: f64, longitude: f64) -> Location {
+        Location {
+            latitude,
+            longitude,
+        }
+    }
+
+    /// The great-circle distance to `other`, in metres.
+    fn distance(self, other: Location) -> f64 {
+        const EARTH_RADIUS: f64 = 6_371_000.0;
+        let (lat1, lat2) = (self.latitude.to_radians(), other.latitude.to_radians());
+        let dlat = lat2 - lat1;
+        let dlon = (other.longitude - self.longitude).to_radians();
+        let a = (dlat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (dlon / 2.0).sin().powi(2);
+        2.0 * EARTH_RADIUS * a.sqrt().asin()
+    }
+}
+
+/// Picks the locations out of `where-am-i`'s output, which describes each
+/// new location over several lines, e.g. `Latitude:    51.507400°`.
+#[derive(Default)]
+struct Parser {
+    latitude: Option<f64>,
+}
+
+impl Parser {
+    fn line(&mut self, line: &str) -> Option<Location> {
+        let (key, value) = line.split_once(':')?;
+        let value = value.trim().trim_end_matches('°').parse().ok();
+        match key.trim() {
+            "Latitude" => self.latitude = value,
+            "Longitude" => return Some(Location::new(self.latitude.take()?, value?)),
+            _ => {}
+        }
+        None
+    }
+}
+
+fn where_am_i(handle: &Handle) -> Result<impl Stream<Item = Location, Error = Error>> {
+    let path = WHERE_AM_I
+        .iter()
+        .find(|path| Path::new(path).exists())
+        .ok_or_else(|| format_err!("GeoClue's `where-am-i` wasn't found"))?;
+    let mut command = Command::new(path);
+    // Keep running (rather than exiting after 30 seconds), printing each new
+    // location.
+    command.args(["--timeout", "0", "--accuracy", ACCURACY_CITY]);
+
+    let mut parser = Parser::default();
+    Ok(process::output_lines(handle, command)?.filter_map(move |line| parser.line(&line)))
+}
+
+/// Returns a stream of the computer's location each time it moves
+/// significantly, starting from `from`.
+///
+/// This never fails: if the location can't be found, a warning is logged and
+/// the stream ends, so that widgets keep using the location they have.
+pub(crate) fn locations(
+    handle: &Handle,
+    from: Location,
+) -> Box<dyn Stream<Item = Location, Error = Error>> {
+    let locations = match where_am_i(handle) {
+        Ok(locations) => locations,
+        Err(e) => {
+            warn!("Failed to follow the location: {}", e);
+            return Box::new(stream::empty());
+        }
+    };
+
+    let mut last = from;
+    let moves = locations
+        .filter(move |&location| {
+            let moved = last.distance(location) >= THRESHOLD;
+            if moved {
+                last = location;
+            }
+            moved
+        })
+        .then(|result| {
+            if let Err(ref e) = result {
+                warn!("Failed to follow the location: {}", e);
+            }
+            Ok::<_, Error>(result.ok())
+        })
+        // Finish at the first error.
+        .take_while(|location| Ok(location.is_some()))
+        .filter_map(|location| location);
+    Box::new(moves)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Location, Parser};
+
+    #[test]
+    fn works() {
+        let output = "Client object: /org/freedesktop/GeoClue2/Client/1\n\
+                      \n\
+                      New location:\n\
+                      Latitude:    51.507400°\n\
+                      Longitude:   -0.127800°\n\
+                      Accuracy:    25000.000000 meters\n\
+                      Description: GeoIP\n";
+        let mut parser = Parser::default();
+        let locations: Vec<_> = output.lines().filter_map(|l| parser.line(l)).collect();
+        assert_eq!(locations, vec![Location::new(51.5074, -0.1278)]);
+
+        let london = Location::new(51.5074, -0.1278);
+        let paris = Location::new(48.8566, 2.3522);
+        let distance = london.distance(paris);
+        assert!(distance > 340_000.0 && distance < 345_000.0, "{}", distance);
+        assert_eq!(london.distance(london), 0.0);
+    }
+}
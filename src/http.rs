@@ -0,0 +1,199 @@
+//! A minimal HTTP client, for the widgets which fetch data from web APIs, or
+//! probe whether we're online.
+//!
+//! Only plain-text `GET` requests are supported, using HTTP/1.0 so that the
+//! server closes the connection (rather than using chunked encoding) when it
+//! has sent its response. As there is no TLS, requests and responses can be
+//! read (and changed) by anything on the network between us and the server.
+
+use std::io::Read;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::thread;
+use std::time::Duration;
+
+use futures::sync::oneshot;
+use futures::{future, Future};
+use log::debug;
+use tokio_core::net::TcpStream;
+use tokio_core::reactor::Handle;
+
+use crate::error::{format_err, Error, ResultExt};
+use crate::timer::Timer;
+use crate::Result;
+
+// How long a request can take, from looking up the host to reading the end
+// of the response, before it fails.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+// The longest response we read, so that a broken server can't make us fill
+// memory. Every API we use answers with a few kilobytes.
+const MAX_RESPONSE_LEN: u64 = 1024 * 1024;
+
+/// Parses the status code of an HTTP response, e.g. `204`.
+fn parse_status(response: &[u8]) -> Result<u16> {
+    let response = String::from_utf8_lossy(response);
+    let status = response.lines().next().unwrap_or("");
+    status
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse().ok())
+        .ok_or_else(|| format_err!("Invalid HTTP status: {}", status))
+}
+
+/// Splits an HTTP response into its status line and body, returning the body
+/// if the status was `200 OK`.
+fn parse_response(response: &[u8]) -> Result<String> {
+    let response = String::from_utf8_lossy(response);
+    let (head, body) = match response.find("\r\n\r\n") {
+        Some(i) => (&response[..i], &response[i + 4..]),
+        None => return Err(format_err!("Truncated HTTP response")),
+    };
+    let status = head.lines().next().unwrap_or("");
+    match status.split_whitespace().nth(1) {
+        Some("200") => Ok(body.to_owned()),
+        _ => Err(format_err!("Unexpected HTTP status: {}", status)),
+    }
+}
+
+/// Fetches `http://<host><path>`, returning the body of the response.
+pub(crate) fn get(
+    handle: &Handle,
+    timer: &Timer,
+    host: &str,
+    path: &str,
+) -> Box<dyn Future<Item = String, Error = Error>> {
+    let url = format!("http://{}{}", host, path);
+    Box::new(
+        request(handle, timer, host, path)
+            .and_then(|response| parse_response(&response))
+            .then(move |result| result.with_context(|_| format!("Failed to GET {}", url))),
+    )
+}
+
+/// Fetches `http://<host><path>`, returning only the status code of the
+/// response, whatever it is.
+pub(crate) fn status(
+    handle: &Handle,
+    timer: &Timer,
+    host: &str,
+    path: &str,
+) -> Box<dyn Future<Item = u16, Error = Error>> {
+    let url = format!("http://{}{}", host, path);
+    Box::new(
+        request(handle, timer, host, path)
+            .and_then(|response| parse_status(&response))
+            .then(move |result| result.with_context(|_| format!("Failed to GET {}", url))),
+    )
+}
+
+/// Looks up the addresses of `address`, a `host:port`, on a new thread as the
+/// lookup blocks. Fails if the host has no addresses.
+pub(crate) fn resolve(address: &str) -> Box<dyn Future<Item = Vec<SocketAddr>, Error = Error>> {
+    let address = address.to_owned();
+    let (tx, rx) = oneshot::channel();
+    thread::spawn(move || {
+        let addresses = address
+            .to_socket_addrs()
+            .map(Iterator::collect::<Vec<_>>)
+            .with_context(|_| format!("Failed to resolve {}", address))
+            .and_then(|addresses| {
+                if addresses.is_empty() {
+                    return Err(format_err!("{} has no addresses", address));
+                }
+                Ok(addresses)
+            });
+        // If the request has been dropped, nobody is interested.
+        let _ = tx.send(addresses);
+    });
+    Box::new(
+        rx.map_err(|_| format_err!("The resolver thread stopped unexpectedly"))
+            .and_then(|result| result),
+    )
+}
+
+/// Connects to the first of `addresses` which accepts the connection, e.g.
+/// falling back to a host's IPv4 address when its IPv6 one is unreachable.
+pub(crate) fn connect(
+    handle: &Handle,
+    mut addresses: Vec<SocketAddr>,
+) -> Box<dyn Future<Item = TcpStream, Error = Error>> {
+    if addresses.is_empty() {
+        return Box::new(future::err(format_err!("No addresses to connect to")));
+    }
+    let address = addresses.remove(0);
+    let handle = handle.clone();
+    Box::new(TcpStream::connect(&address, &handle).or_else(move |e| {
+        if addresses.is_empty() {
+            return Box::new(future::err(Error::from(e))) as Box<dyn Future<Item = _, Error = _>>;
+        }
+        debug!(
+            "Failed to connect to {}, trying the next address: {}",
+            address, e
+        );
+        connect(&handle, addresses)
+    }))
+}
+
+/// Sends a `GET` request for `path` to `host`, returning the whole response,
+/// or failing if it takes longer than `TIMEOUT`.
+fn request(
+    handle: &Handle,
+    timer: &Timer,
+    host: &str,
+    path: &str,
+) -> Box<dyn Future<Item = Vec<u8>, Error = Error>> {
+    debug!("GET http://{}{}", host, path);
+    let request = format!(
+        "GET {} HTTP/1.0\r\nHost: {}\r\nUser-Agent: cnx\r\nConnection: close\r\n\r\n",
+        path, host
+    );
+    let handle = handle.clone();
+    let response = resolve(&format!("{}:80", host))
+        .and_then(move |addresses| connect(&handle, addresses))
+        .and_then(move |socket| {
+            tokio_io::io::write_all(socket, request)
+                // Read one byte too many, to tell whether there was more.
+                .and_then(|(socket, _)| {
+                    tokio_io::io::read_to_end(socket.take(MAX_RESPONSE_LEN + 1), Vec::new())
+                })
+                .map_err(Error::from)
+        })
+        .and_then(|(_, response)| {
+            if response.len() as u64 > MAX_RESPONSE_LEN {
+                return Err(format_err!(
+                    "Response is longer than {} bytes",
+                    MAX_RESPONSE_LEN
+                ));
+            }
+            Ok(response)
+        });
+    let timeout = timer
+        .sleep(TIMEOUT)
+        .map_err(Error::from)
+        .and_then(|()| Err(format_err!("Timed out after {:?}", TIMEOUT)));
+    Box::new(
+        response
+            .select(timeout)
+            .map(|(response, _)| response)
+            .map_err(|(e, _)| e),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_response, parse_status};
+
+    #[test]
+    fn works() {
+        let ok = b"HTTP/1.0 200 OK\r\nContent-Type: application/json\r\n\r\n{\"a\": 1}";
+        assert_eq!(parse_response(ok).unwrap(), "{\"a\": 1}");
+        assert!(parse_response(b"HTTP/1.1 404 Not Found\r\n\r\n").is_err());
+        assert!(parse_response(b"HTTP/1.1 200 OK\r\n").is_err());
+
+        assert_eq!(
+            parse_status(b"HTTP/1.1 204 No Content\r\n\r\n").unwrap(),
+            204
+        );
+        assert!(parse_status(b"<html>").is_err());
+    }
+}
@@ -0,0 +1,113 @@
+//! Icon glyphs for widgets' texts, e.g. a battery which empties as it
+//! discharges.
+//!
+//! These are the [Material Design Icons] at the codepoints used by
+//! [Nerd Fonts] (which also include Font Awesome), so a Nerd Font needs to be
+//! installed for them to show. Pango falls back to another font for glyphs
+//! the widget's font lacks, so the icons can be mixed with text in any font:
+//!
+//! ```
+//! use cnx::icons;
+//!
+//! let text = format!("{} 42%", icons::battery_icon(42.0, false));
+//! assert_eq!(text, "\u{f007d} 42%");
+//! ```
+//!
+//! [Material Design Icons]: https://pictogrammers.com/library/mdi/
+//! [Nerd Fonts]: https://www.nerdfonts.com/
+
+/// A battery which is all but empty.
+pub const BATTERY_ALERT: &str = "\u{f0083}";
+/// A battery with 10%, 20%, … 90% left.
+pub const BATTERY_LEVELS: [&str; 9] = [
+    "\u{f007a}",
+    "\u{f007b}",
+    "\u{f007c}",
+    "\u{f007d}",
+    "\u{f007e}",
+    "\u{f007f}",
+    "\u{f0080}",
+    "\u{f0081}",
+    "\u{f0082}",
+];
+/// A full battery.
+pub const BATTERY_FULL: &str = "\u{f0079}";
+/// A battery which is charging.
+pub const BATTERY_CHARGING: &str = "\u{f0084}";
+
+/// Wi-Fi with no signal, e.g. while disconnected.
+pub const WIFI_OFF: &str = "\u{f092d}";
+/// Wi-Fi with a signal too weak to show any bars.
+pub const WIFI_NONE: &str = "\u{f092f}";
+/// Wi-Fi with one, two, three and four bars of signal.
+pub const WIFI_LEVELS: [&str; 4] = ["\u{f091f}", "\u{f0922}", "\u{f0925}", "\u{f0928}"];
+
+/// A muted speaker.
+pub const VOLUME_MUTED: &str = "\u{f0581}";
+/// A speaker at a low, medium and high volume.
+pub const VOLUME_LEVELS: [&str; 3] = ["\u{f057f}", "\u{f0580}", "\u{f057e}"];
+
+/// Picks one of `count` levels for `percent`, each covering an equal share of
+/// 0–100%.
+fn level(percent: f64, count: usize) -> usize {
+    // NaN is cast to the first level.
+    ((percent.clamp(0.0, 100.0) / 100.0 * count as f64) as usize).min(count - 1)
+}
+
+/// Returns the glyph of a battery with `percent` left (to the nearest 10%),
+/// or charging.
+pub fn battery_icon(percent: f64, charging: bool) -> &'static str {
+    if charging {
+        return BATTERY_CHARGING;
+    }
+    match (percent / 10.0).round() as i64 {
+        n if n <= 0 => BATTERY_ALERT,
+        n if n >= 10 => BATTERY_FULL,
+        n => BATTERY_LEVELS[n as usize - 1],
+    }
+}
+
+/// Returns the glyph of Wi-Fi with a signal of `strength`, as a percentage
+/// (e.g. the `{quality}` of the [`Wireless`] widget).
+///
+/// [`Wireless`]: ../widgets/struct.Wireless.html
+pub fn wifi_icon(strength: f64) -> &'static str {
+    if strength <= 0.0 {
+        return WIFI_NONE;
+    }
+    WIFI_LEVELS[level(strength, WIFI_LEVELS.len())]
+}
+
+/// Returns the glyph of a speaker at volume `level`, as a percentage, or
+/// muted. A volume of 0% shows as muted too.
+pub fn volume_icon(level: f64, muted: bool) -> &'static str {
+    if muted || level <= 0.0 {
+        return VOLUME_MUTED;
+    }
+    VOLUME_LEVELS[self::level(level, VOLUME_LEVELS.len())]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn works() {
+        assert_eq!(battery_icon(3.0, false), BATTERY_ALERT);
+        assert_eq!(battery_icon(42.0, false), BATTERY_LEVELS[3]);
+        assert_eq!(battery_icon(97.0, false), BATTERY_FULL);
+        assert_eq!(battery_icon(3.0, true), BATTERY_CHARGING);
+
+        assert_eq!(wifi_icon(0.0), WIFI_NONE);
+        assert_eq!(wifi_icon(10.0), WIFI_LEVELS[0]);
+        assert_eq!(wifi_icon(60.0), WIFI_LEVELS[2]);
+        assert_eq!(wifi_icon(100.0), WIFI_LEVELS[3]);
+
+        assert_eq!(volume_icon(0.0, false), VOLUME_MUTED);
+        assert_eq!(volume_icon(80.0, true), VOLUME_MUTED);
+        assert_eq!(volume_icon(20.0, false), VOLUME_LEVELS[0]);
+        assert_eq!(volume_icon(50.0, false), VOLUME_LEVELS[1]);
+        // Some mixers go past 100%.
+        assert_eq!(volume_icon(150.0, false), VOLUME_LEVELS[2]);
+    }
+}
@@ -0,0 +1,414 @@
+//! A shared inotify watcher, for widgets which want to know when files
+//! change.
+//!
+//! Rather than each widget polling its files (or opening its own inotify
+//! instance), widgets [`watch()`] the paths they are interested in. Cnx uses
+//! a single inotify file descriptor for every watch, which it opens when the
+//! first path is watched, and dispatches each change to the streams watching
+//! the changed path.
+//!
+//! Note that most files in `/sys` and `/proc` don't support inotify, so still
+//! need to be polled.
+//!
+//! [`watch()`]: struct.Inotify.html#method.watch
+
+use std::cell::RefCell;
+use std::ffi::{CString, OsStr};
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::RawFd;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use futures::unsync::mpsc::{self, UnboundedSender};
+use futures::{Async, Future, Poll, Stream};
+use log::{debug, error};
+use mio::event::Evented;
+use mio::unix::EventedFd;
+use mio::{PollOpt, Ready, Token};
+use tokio_core::reactor::{Handle, PollEvented};
+
+use crate::error::{format_err, Error, ResultExt};
+use crate::Result;
+
+const WATCH_MASK: u32 = libc::IN_MODIFY
+    | libc::IN_CLOSE_WRITE
+    | libc::IN_ATTRIB
+    | libc::IN_CREATE
+    | libc::IN_MOVED_TO
+    | libc::IN_DELETE
+    | libc::IN_MOVED_FROM
+    | libc::IN_DELETE_SELF
+    | libc::IN_MOVE_SELF;
+
+// The size of `struct inotify_event`, before its name.
+const EVENT_HEADER_LEN: usize = 16;
+
+/// What happened to a watched file.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FileEventKind {
+    /// The file's contents or attributes changed.
+    Modified,
+    /// The file was created, or moved into the watched directory.
+    Created,
+    /// The file was removed, or moved away.
+    Removed,
+    /// Too many changes happened at once, and some were lost. The watcher
+    /// should re-read everything it is interested in.
+    Overflowed,
+}
+
+/// A change to a watched file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FileEvent {
+    /// The path which changed. If a directory is being watched, this is the
+    /// path of the file within it.
+    pub path: PathBuf,
+    /// What happened to the file.
+    pub kind: FileEventKind,
+}
+
+/// The stream of events returned by [`Inotify::watch()`].
+///
+/// [`Inotify::watch()`]: struct.Inotify.html#method.watch
+pub type FileEvents = Box<dyn Stream<Item = FileEvent, Error = Error>>;
+
+/// An event read from the inotify fd, before it has been matched up with its
+/// watch.
+#[derive(Debug, PartialEq)]
+struct RawEvent {
+    wd: i32,
+    mask: u32,
+    name: Option<PathBuf>,
+}
+
+impl RawEvent {
+    fn kind(&self) -> Option<FileEventKind> {
+        let mask = self.mask;
+        if mask & libc::IN_Q_OVERFLOW != 0 {
+            Some(FileEventKind::Overflowed)
+        } else if mask & (libc::IN_CREATE | libc::IN_MOVED_TO) != 0 {
+            Some(FileEventKind::Created)
+        } else if mask
+            & (libc::IN_DELETE | libc::IN_MOVED_FROM | libc::IN_DELETE_SELF | libc::IN_MOVE_SELF)
+            != 0
+        {
+            Some(FileEventKind::Removed)
+        } else if mask & (libc::IN_MODIFY | libc::IN_CLOSE_WRITE | libc::IN_ATTRIB) != 0 {
+            Some(FileEventKind::Modified)
+        } else {
+            None
+        }
+    }
+}
+
+/// Parses a buffer of `struct inotify_event`s.
+fn parse_events(mut buf: &[u8]) -> Vec<RawEvent> {
+    let ne_u32 = |bytes: &[u8], offset: usize| {
+        let mut n = [0; 4];
+        n.copy_from_slice(&bytes[offset..offset + 4]);
+        u32::from_ne_bytes(n)
+    };
+
+    let mut events = Vec::new();
+    while buf.len() >= EVENT_HEADER_LEN {
+        let name_len = ne_u32(buf, 12) as usize;
+        if buf.len() < EVENT_HEADER_LEN + name_len {
+            break;
+        }
+        // The name is nul-padded, and is empty for events on the watched
+        // path itself.
+        let name = &buf[EVENT_HEADER_LEN..EVENT_HEADER_LEN + name_len];
+        let name = name.split(|&b| b == 0).next().unwrap_or(name);
+        events.push(RawEvent {
+            wd: ne_u32(buf, 0) as i32,
+            mask: ne_u32(buf, 4),
+            name: if name.is_empty() {
+                None
+            } else {
+                Some(PathBuf::from(OsStr::from_bytes(name)))
+            },
+        });
+        buf = &buf[EVENT_HEADER_LEN + name_len..];
+    }
+    events
+}
+
+/// A non-blocking inotify file descriptor.
+struct Fd(RawFd);
+
+impl Fd {
+    fn open() -> io::Result<Fd> {
+        let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+        if fd < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(Fd(fd))
+        }
+    }
+
+    fn add_watch(&self, path: &Path) -> io::Result<i32> {
+        let path = CString::new(path.as_os_str().as_bytes())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let wd = unsafe { libc::inotify_add_watch(self.0, path.as_ptr(), WATCH_MASK) };
+        if wd < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(wd)
+        }
+    }
+
+    fn remove_watch(&self, wd: i32) {
+        unsafe {
+            libc::inotify_rm_watch(self.0, wd);
+        }
+    }
+
+    fn read(&self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = unsafe { libc::read(self.0, buf.as_mut_ptr() as *mut libc::c_void, buf.len()) };
+        if len < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(len as usize)
+        }
+    }
+}
+
+impl Drop for Fd {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.0);
+        }
+    }
+}
+
+impl Evented for Fd {
+    fn register(
+        &self,
+        poll: &mio::Poll,
+        token: Token,
+        interest: Ready,
+        opts: PollOpt,
+    ) -> io::Result<()> {
+        EventedFd(&self.0).register(poll, token, interest, opts)
+    }
+
+    fn reregister(
+        &self,
+        poll: &mio::Poll,
+        token: Token,
+        interest: Ready,
+        opts: PollOpt,
+    ) -> io::Result<()> {
+        EventedFd(&self.0).reregister(poll, token, interest, opts)
+    }
+
+    fn deregister(&self, poll: &mio::Poll) -> io::Result<()> {
+        EventedFd(&self.0).deregister(poll)
+    }
+}
+
+/// A stream of the events read from the inotify fd.
+struct FdStream {
+    poll: Rc<PollEvented<Fd>>,
+    buf: Vec<u8>,
+}
+
+impl Stream for FdStream {
+    type Item = Vec<RawEvent>;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        if let Async::NotReady = self.poll.poll_read() {
+            return Ok(Async::NotReady);
+        }
+
+        match self.poll.get_ref().read(&mut self.buf) {
+            Ok(len) => Ok(Async::Ready(Some(parse_events(&self.buf[..len])))),
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                self.poll.need_read();
+                Ok(Async::NotReady)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+struct Watcher {
+    wd: i32,
+    path: PathBuf,
+    sender: UnboundedSender<FileEvent>,
+}
+
+struct Inner {
+    handle: Handle,
+    fd: Option<Rc<PollEvented<Fd>>>,
+    watchers: Vec<Watcher>,
+}
+
+impl Inner {
+    fn dispatch(&mut self, event: RawEvent) {
+        let kind = match event.kind() {
+            Some(kind) => kind,
+            None => return,
+        };
+        // Drop any watchers whose streams have gone away, or whose watch has
+        // been removed by the kernel (e.g. because the file was deleted).
+        let ignored = event.mask & libc::IN_IGNORED != 0;
+        self.watchers.retain(|watcher| {
+            if kind != FileEventKind::Overflowed && watcher.wd != event.wd {
+                return true;
+            }
+            let path = match event.name {
+                Some(ref name) => watcher.path.join(name),
+                None => watcher.path.clone(),
+            };
+            let sent = watcher
+                .sender
+                .unbounded_send(FileEvent { path, kind })
+                .is_ok();
+            sent && !(ignored && watcher.wd == event.wd)
+        });
+
+        // Stop watching paths which nobody is interested in any more.
+        if let Some(ref fd) = self.fd {
+            let watchers = &self.watchers;
+            if !ignored && !watchers.iter().any(|watcher| watcher.wd == event.wd) {
+                fd.get_ref().remove_watch(event.wd);
+            }
+        }
+    }
+}
+
+/// A handle to Cnx's shared inotify watcher.
+///
+/// Returned by [`Cnx::inotify()`]. See the [module documentation] for more
+/// details.
+///
+/// [`Cnx::inotify()`]: ../struct.Cnx.html#method.inotify
+/// [module documentation]: index.html
+#[derive(Clone)]
+pub struct Inotify {
+    inner: Rc<RefCell<Inner>>,
+}
+
+impl Inotify {
+    pub(crate) fn new(handle: Handle) -> Inotify {
+        Inotify {
+            inner: Rc::new(RefCell::new(Inner {
+                handle,
+                fd: None,
+                watchers: Vec::new(),
+            })),
+        }
+    }
+
+    /// Watches `path` for changes. If `path` is a directory, changes to the
+    /// files within it are reported too.
+    ///
+    /// The path must already exist. If it is removed, a
+    /// [`FileEventKind::Removed`] event is yielded and the stream finishes.
+    ///
+    /// [`FileEventKind::Removed`]: enum.FileEventKind.html#variant.Removed
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use cnx::*;
+    /// # fn run() -> ::cnx::Result<()> {
+    /// let cnx = Cnx::new(Position::Top)?;
+    /// let changes = cnx.inotify().watch("/home/user/.todo")?;
+    /// # Ok(())
+    /// # }
+    /// # fn main() { run().unwrap(); }
+    /// ```
+    pub fn watch<P: AsRef<Path>>(&self, path: P) -> Result<FileEvents> {
+        let path = path.as_ref();
+        let fd = self.fd()?;
+        let wd = fd
+            .get_ref()
+            .add_watch(path)
+            .with_context(|_| format!("Failed to watch {}", path.display()))?;
+        debug!("Watching {} (wd {})", path.display(), wd);
+
+        let (sender, receiver) = mpsc::unbounded();
+        self.inner.borrow_mut().watchers.push(Watcher {
+            wd,
+            path: path.to_owned(),
+            sender,
+        });
+        Ok(Box::new(
+            receiver.map_err(|()| format_err!("Inotify watch closed")),
+        ))
+    }
+
+    /// Gets the inotify fd, opening it if this is the first watch.
+    fn fd(&self) -> Result<Rc<PollEvented<Fd>>> {
+        let mut inner = self.inner.borrow_mut();
+        if let Some(ref fd) = inner.fd {
+            return Ok(fd.clone());
+        }
+
+        let fd = Fd::open().context("Failed to initialise inotify")?;
+        let fd = Rc::new(PollEvented::new(fd, &inner.handle)?);
+        let stream = FdStream {
+            poll: fd.clone(),
+            buf: vec![0; 16 * 1024],
+        };
+        let shared = self.inner.clone();
+        let fut = stream
+            .for_each(move |events| {
+                let mut inner = shared.borrow_mut();
+                for event in events {
+                    inner.dispatch(event);
+                }
+                Ok(())
+            })
+            .map_err(|e| error!("Error reading inotify events: {}", e));
+        inner.handle.spawn(fut);
+        inner.fd = Some(fd.clone());
+        Ok(fd)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use super::{parse_events, FileEventKind, RawEvent};
+
+    fn push_event(buf: &mut Vec<u8>, wd: i32, mask: u32, name: &[u8]) {
+        buf.extend_from_slice(&wd.to_ne_bytes());
+        buf.extend_from_slice(&mask.to_ne_bytes());
+        buf.extend_from_slice(&0u32.to_ne_bytes());
+        buf.extend_from_slice(&(name.len() as u32).to_ne_bytes());
+        buf.extend_from_slice(name);
+    }
+
+    #[test]
+    fn parses_events() {
+        let mut buf = Vec::new();
+        push_event(&mut buf, 1, libc::IN_CLOSE_WRITE, b"");
+        push_event(&mut buf, 2, libc::IN_CREATE, b"new.txt\0\0\0\0\0\0\0\0\0");
+        let events = parse_events(&buf);
+        assert_eq!(
+            events,
+            vec![
+                RawEvent {
+                    wd: 1,
+                    mask: libc::IN_CLOSE_WRITE,
+                    name: None,
+                },
+                RawEvent {
+                    wd: 2,
+                    mask: libc::IN_CREATE,
+                    name: Some(PathBuf::from("new.txt")),
+                },
+            ]
+        );
+        assert_eq!(events[0].kind(), Some(FileEventKind::Modified));
+        assert_eq!(events[1].kind(), Some(FileEventKind::Created));
+        // Truncated events are ignored.
+        assert_eq!(parse_events(&buf[..20]).len(), 1);
+    }
+}
@@ -0,0 +1,156 @@
+//! Receiving commands from other processes, over a Unix socket.
+//!
+//! Each line written to the socket is parsed as a command, e.g. `refresh
+//! updates`, and sent to the bar using a [`Control`]. Lines which are JSON
+//! objects, e.g. `{"command": "refresh", "widget": "updates"}`, are answered
+//! with a line of JSON saying whether the command worked, for scripts which
+//! want to know: `{"ok": true}` or `{"ok": false, "error": "..."}`.
+//!
+//! [`Control`]: ../control/struct.Control.html
+
+use std::env;
+use std::fs::{self, DirBuilder, Permissions};
+use std::io::{self, BufReader};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+
+use futures::{future, Async, Future, Poll, Stream};
+use log::{debug, error, warn};
+use tokio_core::reactor::{Handle, PollEvented};
+use tokio_io::AsyncRead;
+
+use crate::control::{Command, Control};
+use crate::error::{format_err, Error, ResultExt};
+use crate::json;
+use crate::Result;
+
+/// The default path of the IPC socket: `$XDG_RUNTIME_DIR/cnx.sock`, or
+/// `/tmp/cnx-$UID/cnx.sock` if `$XDG_RUNTIME_DIR` isn't set.
+pub(crate) fn default_socket_path() -> PathBuf {
+    match env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) => PathBuf::from(dir).join("cnx.sock"),
+        None => {
+            let uid = unsafe { libc::getuid() };
+            PathBuf::from(format!("/tmp/cnx-{}/cnx.sock", uid))
+        }
+    }
+}
+
+/// Creates `dir` for our socket (or FIFOs), readable only by us, unless it
+/// already exists. Anybody can create directories in `/tmp`, so one which
+/// already exists must belong to us, and mustn't let others in.
+pub(crate) fn private_dir(dir: &Path) -> Result<()> {
+    if !dir.exists() {
+        DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(dir)
+            .with_context(|_| format!("Failed to create {}", dir.display()))?;
+    }
+    let metadata =
+        fs::metadata(dir).with_context(|_| format!("Failed to read {}", dir.display()))?;
+    if metadata.uid() != unsafe { libc::getuid() } {
+        return Err(format_err!("{} belongs to another user", dir.display()));
+    }
+    if metadata.mode() & 0o022 != 0 {
+        return Err(format_err!("{} is writable by other users", dir.display()));
+    }
+    Ok(())
+}
+
+/// Runs the command on a `line` read from the socket, returning the reply to
+/// write back, if any.
+fn run_command(control: &Control, line: &str) -> Option<String> {
+    debug!("Received IPC command: {}", line);
+    if line.trim_start().starts_with('{') {
+        let reply = match Command::from_json(line).and_then(|command| control.send(command)) {
+            Ok(()) => r#"{"ok": true}"#.to_owned(),
+            Err(e) => format!(
+                r#"{{"ok": false, "error": {}}}"#,
+                json::quote(&e.to_string())
+            ),
+        };
+        return Some(reply + "\n");
+    }
+    if let Err(e) = Command::parse(line).and_then(|command| control.send(command)) {
+        warn!("Ignoring invalid IPC command: {}", e);
+    }
+    None
+}
+
+/// Listens on the socket at `path`, sending each command received to
+/// `control`.
+pub(crate) fn listen(handle: &Handle, path: &Path, control: Control) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        private_dir(dir)?;
+    }
+    // A socket left behind by a previous instance would stop us binding.
+    if path.exists() {
+        fs::remove_file(path)
+            .with_context(|_| format!("Failed to remove old socket: {}", path.display()))?;
+    }
+    let listener = mio_uds::UnixListener::bind(path)
+        .with_context(|_| format!("Failed to listen on socket: {}", path.display()))?;
+    // Only we may send commands, whatever the umask.
+    fs::set_permissions(path, Permissions::from_mode(0o600))
+        .with_context(|_| format!("Failed to restrict socket: {}", path.display()))?;
+    let incoming = Incoming {
+        poll: PollEvented::new(listener, handle)?,
+    };
+
+    let spawn_handle = handle.clone();
+    let fut = incoming.for_each(move |stream| {
+        let handle = &spawn_handle;
+        let control = control.clone();
+        let (reader, writer) = PollEvented::new(stream, handle)?.split();
+        let lines = tokio_io::io::lines(BufReader::new(reader))
+            .map_err(Error::from)
+            .fold(writer, move |writer, line| {
+                let reply: Box<dyn Future<Item = _, Error = Error>> =
+                    match run_command(&control, &line) {
+                        Some(reply) => Box::new(
+                            tokio_io::io::write_all(writer, reply)
+                                .map(|(writer, _)| writer)
+                                .map_err(Error::from),
+                        ),
+                        None => Box::new(future::ok(writer)),
+                    };
+                reply
+            })
+            .map(|_| ())
+            .map_err(|e| error!("Error reading IPC command: {}", e));
+        handle.spawn(lines);
+        Ok(())
+    });
+    handle.spawn(fut.map_err(|e| error!("Error accepting IPC connection: {}", e)));
+    Ok(())
+}
+
+/// A stream of the connections accepted by a listening socket.
+struct Incoming {
+    poll: PollEvented<mio_uds::UnixListener>,
+}
+
+impl Stream for Incoming {
+    type Item = mio_uds::UnixStream;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        if let Async::NotReady = self.poll.poll_read() {
+            return Ok(Async::NotReady);
+        }
+
+        match self.poll.get_ref().accept() {
+            Ok(Some((stream, _))) => Ok(Async::Ready(Some(stream))),
+            Ok(None) => {
+                self.poll.need_read();
+                Ok(Async::NotReady)
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                self.poll.need_read();
+                Ok(Async::NotReady)
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
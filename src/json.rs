@@ -0,0 +1,338 @@
+//! A minimal JSON parser, for the handful of widgets which talk to tools that
+//! only speak JSON.
+
+use std::char;
+use std::iter::Peekable;
+use std::str::Chars;
+
+use crate::error::format_err;
+use crate::Result;
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    // Objects are kept in document order. They're small enough that a linear
+    // search in get() is fine.
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// Gets the value of `key`, if this is an object which contains it.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match *self {
+            Value::Object(ref members) => members.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    /// Gets the `index`th element, if this is an array which is long enough.
+    pub fn at(&self, index: usize) -> Option<&Value> {
+        match *self {
+            Value::Array(ref elements) => elements.get(index),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match *self {
+            Value::String(ref s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            Value::Bool(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            Value::Number(n) => Some(n),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match *self {
+            Value::Array(ref elements) => Some(elements),
+            _ => None,
+        }
+    }
+}
+
+/// Quotes `s` as a JSON string, for building the few messages which widgets
+/// send.
+pub(crate) fn quote(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+// How deeply arrays and objects can be nested, so that untrusted documents
+// (e.g. from the network) can't overflow the stack.
+const MAX_DEPTH: usize = 128;
+
+pub(crate) fn parse(s: &str) -> Result<Value> {
+    let mut parser = Parser {
+        chars: s.chars().peekable(),
+        depth: 0,
+    };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    if let Some(c) = parser.chars.next() {
+        return Err(format_err!(
+            "Unexpected trailing character in JSON: {:?}",
+            c
+        ));
+    }
+    Ok(value)
+}
+
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn skip_whitespace(&mut self) {
+        while let Some(&c) = self.chars.peek() {
+            if !c.is_whitespace() {
+                break;
+            }
+            self.chars.next();
+        }
+    }
+
+    fn next(&mut self) -> Result<char> {
+        self.chars
+            .next()
+            .ok_or_else(|| format_err!("Unexpected end of JSON"))
+    }
+
+    fn expect(&mut self, expected: char) -> Result<()> {
+        match self.next()? {
+            c if c == expected => Ok(()),
+            c => Err(format_err!("Expected {:?} in JSON, got {:?}", expected, c)),
+        }
+    }
+
+    fn keyword(&mut self, keyword: &str, value: Value) -> Result<Value> {
+        for expected in keyword.chars() {
+            self.expect(expected)?;
+        }
+        Ok(value)
+    }
+
+    fn value(&mut self) -> Result<Value> {
+        self.skip_whitespace();
+        match self.chars.peek().cloned() {
+            Some('{') => self.nested(Parser::object),
+            Some('[') => self.nested(Parser::array),
+            Some('"') => Ok(Value::String(self.string()?)),
+            Some('t') => self.keyword("true", Value::Bool(true)),
+            Some('f') => self.keyword("false", Value::Bool(false)),
+            Some('n') => self.keyword("null", Value::Null),
+            Some(c) if c == '-' || c.is_ascii_digit() => self.number(),
+            Some(c) => Err(format_err!("Unexpected character in JSON: {:?}", c)),
+            None => Err(format_err!("Unexpected end of JSON")),
+        }
+    }
+
+    fn nested<F>(&mut self, parse: F) -> Result<Value>
+    where
+        F: FnOnce(&mut Self) -> Result<Value>,
+    {
+        if self.depth == MAX_DEPTH {
+            return Err(format_err!(
+                "JSON is nested more than {} levels deep",
+                MAX_DEPTH
+            ));
+        }
+        self.depth += 1;
+        let value = parse(self);
+        self.depth -= 1;
+        value
+    }
+
+    fn object(&mut self) -> Result<Value> {
+        self.expect('{')?;
+        let mut members = Vec::new();
+        self.skip_whitespace();
+        if self.chars.peek() == Some(&'}') {
+            self.chars.next();
+            return Ok(Value::Object(members));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.string()?;
+            self.skip_whitespace();
+            self.expect(':')?;
+            members.push((key, self.value()?));
+            self.skip_whitespace();
+            match self.next()? {
+                ',' => continue,
+                '}' => return Ok(Value::Object(members)),
+                c => return Err(format_err!("Expected ',' or '}}' in JSON, got {:?}", c)),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Value> {
+        self.expect('[')?;
+        let mut elements = Vec::new();
+        self.skip_whitespace();
+        if self.chars.peek() == Some(&']') {
+            self.chars.next();
+            return Ok(Value::Array(elements));
+        }
+        loop {
+            elements.push(self.value()?);
+            self.skip_whitespace();
+            match self.next()? {
+                ',' => continue,
+                ']' => return Ok(Value::Array(elements)),
+                c => return Err(format_err!("Expected ',' or ']' in JSON, got {:?}", c)),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String> {
+        self.expect('"')?;
+        let mut s = String::new();
+        loop {
+            match self.next()? {
+                '"' => return Ok(s),
+                '\\' => match self.next()? {
+                    '"' => s.push('"'),
+                    '\\' => s.push('\\'),
+                    '/' => s.push('/'),
+                    'b' => s.push('\u{8}'),
+                    'f' => s.push('\u{c}'),
+                    'n' => s.push('\n'),
+                    'r' => s.push('\r'),
+                    't' => s.push('\t'),
+                    'u' => s.push(self.unicode_escape()?),
+                    c => return Err(format_err!("Invalid escape in JSON string: {:?}", c)),
+                },
+                c => s.push(c),
+            }
+        }
+    }
+
+    fn hex4(&mut self) -> Result<u32> {
+        let mut n = 0;
+        for _ in 0..4 {
+            let c = self.next()?;
+            let digit = c
+                .to_digit(16)
+                .ok_or_else(|| format_err!("Invalid hex digit in JSON string: {:?}", c))?;
+            n = n * 16 + digit;
+        }
+        Ok(n)
+    }
+
+    fn unicode_escape(&mut self) -> Result<char> {
+        let high = self.hex4()?;
+        let code = if (0xD800..0xDC00).contains(&high) {
+            // A UTF-16 surrogate pair, which must be followed by its low half.
+            self.expect('\\')?;
+            self.expect('u')?;
+            let low = self.hex4()?;
+            if !(0xDC00..0xE000).contains(&low) {
+                return Err(format_err!("Invalid surrogate pair in JSON string"));
+            }
+            0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)
+        } else {
+            high
+        };
+        // Lone surrogates aren't valid chars. Be forgiving and replace them.
+        Ok(char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER))
+    }
+
+    fn number(&mut self) -> Result<Value> {
+        let mut s = String::new();
+        while let Some(&c) = self.chars.peek() {
+            if !(c.is_ascii_digit() || "+-.eE".contains(c)) {
+                break;
+            }
+            s.push(c);
+            self.chars.next();
+        }
+        let n = s
+            .parse()
+            .map_err(|_| format_err!("Invalid number in JSON: {:?}", s))?;
+        Ok(Value::Number(n))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse, quote, Value};
+
+    #[test]
+    fn parses_values() {
+        let value = parse(
+            r#" {"a": [1, -2.5e1, true, false, null], "b": {"c": "d\"\u00e9\ud83d\ude00"}, "e": []} "#,
+        )
+        .unwrap();
+        assert_eq!(
+            value.get("a"),
+            Some(&Value::Array(vec![
+                Value::Number(1.0),
+                Value::Number(-25.0),
+                Value::Bool(true),
+                Value::Bool(false),
+                Value::Null,
+            ]))
+        );
+        assert_eq!(
+            value
+                .get("b")
+                .and_then(|b| b.get("c"))
+                .and_then(Value::as_str),
+            Some("d\"é😀")
+        );
+        assert_eq!(value.get("e").and_then(Value::as_array), Some(&[][..]));
+        assert_eq!(value.get("f"), None);
+
+        let s = "a \"quoted\"\\ string\n\u{1}";
+        assert_eq!(parse(&quote(s)).unwrap(), Value::String(s.to_owned()));
+    }
+
+    #[test]
+    fn rejects_invalid() {
+        assert!(parse("").is_err());
+        assert!(parse("{").is_err());
+        assert!(parse("[1,]").is_err());
+        assert!(parse(r#"{"a" 1}"#).is_err());
+        assert!(parse(r#""\x""#).is_err());
+        assert!(parse("1 2").is_err());
+        assert!(parse("nul").is_err());
+    }
+
+    #[test]
+    fn limits_nesting() {
+        let nested = |depth| format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+        assert!(parse(&nested(128)).is_ok());
+        assert!(parse(&nested(129)).is_err());
+        assert!(parse(&"[{\"a\":".repeat(100_000)).is_err());
+    }
+}
@@ -0,0 +1,193 @@
+//! Hotkeys and the keyboard's mapping, for navigating the bar without a
+//! pointer. See [`Cnx::set_keyboard_hotkey()`].
+//!
+//! [`Cnx::set_keyboard_hotkey()`]: ../struct.Cnx.html#method.set_keyboard_hotkey
+
+use std::collections::HashMap;
+
+use xcb_util::ewmh;
+
+use crate::error::format_err;
+use crate::Result;
+
+pub(crate) const KEY_LEFT: xcb::Keysym = 0xff51;
+pub(crate) const KEY_UP: xcb::Keysym = 0xff52;
+pub(crate) const KEY_RIGHT: xcb::Keysym = 0xff53;
+pub(crate) const KEY_DOWN: xcb::Keysym = 0xff54;
+pub(crate) const KEY_TAB: xcb::Keysym = 0xff09;
+pub(crate) const KEY_RETURN: xcb::Keysym = 0xff0d;
+pub(crate) const KEY_KP_ENTER: xcb::Keysym = 0xff8d;
+pub(crate) const KEY_SPACE: xcb::Keysym = 0x20;
+pub(crate) const KEY_ESCAPE: xcb::Keysym = 0xff1b;
+
+/// The modifiers which shouldn't stop a hotkey from working: Caps Lock and
+/// (usually) Num Lock.
+const IGNORED_MODIFIERS: u16 = (xcb::MOD_MASK_LOCK | xcb::MOD_MASK_2) as u16;
+
+/// Looks up the keysym of a key's name, e.g. `b`, `F12` or `space`.
+fn keysym(name: &str) -> Option<xcb::Keysym> {
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        if c.is_ascii_alphanumeric() || c.is_ascii_punctuation() {
+            return Some(c.to_ascii_lowercase() as xcb::Keysym);
+        }
+    }
+    if let Some(n) = name.strip_prefix('F').and_then(|n| n.parse::<u32>().ok()) {
+        if (1..=24).contains(&n) {
+            return Some(0xffbe + n - 1);
+        }
+    }
+    Some(match name {
+        "space" => KEY_SPACE,
+        "Return" => KEY_RETURN,
+        "Escape" => KEY_ESCAPE,
+        "Tab" => KEY_TAB,
+        "Left" => KEY_LEFT,
+        "Up" => KEY_UP,
+        "Right" => KEY_RIGHT,
+        "Down" => KEY_DOWN,
+        "Home" => 0xff50,
+        "End" => 0xff57,
+        "Print" => 0xff61,
+        "Menu" => 0xff67,
+        _ => return None,
+    })
+}
+
+/// Looks up the mask of a modifier's name, e.g. `Mod4` or `Shift`.
+fn modifier(name: &str) -> Option<u16> {
+    let mask = match name {
+        "Shift" => xcb::MOD_MASK_SHIFT,
+        "Control" | "Ctrl" => xcb::MOD_MASK_CONTROL,
+        "Mod1" | "Alt" => xcb::MOD_MASK_1,
+        "Mod2" => xcb::MOD_MASK_2,
+        "Mod3" => xcb::MOD_MASK_3,
+        "Mod4" | "Super" => xcb::MOD_MASK_4,
+        "Mod5" => xcb::MOD_MASK_5,
+        _ => return None,
+    };
+    Some(mask as u16)
+}
+
+/// A key, along with the modifiers held down with it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Hotkey {
+    pub modifiers: u16,
+    pub keysym: xcb::Keysym,
+}
+
+impl Hotkey {
+    /// Parses a hotkey written like `Mod4+b` or `Control+Alt+F12`.
+    pub fn parse(s: &str) -> Result<Hotkey> {
+        let mut names = s.split('+').map(str::trim).collect::<Vec<_>>();
+        let key = names.pop().unwrap_or_default();
+        let keysym = keysym(key).ok_or_else(|| format_err!("Unknown key {:?} in {:?}", key, s))?;
+        let modifiers = names.iter().try_fold(0, |modifiers, name| {
+            modifier(name)
+                .map(|mask| modifiers | mask)
+                .ok_or_else(|| format_err!("Unknown modifier {:?} in {:?}", name, s))
+        })?;
+        Ok(Hotkey { modifiers, keysym })
+    }
+
+    /// Whether a key press with the given modifier `state` is of this hotkey.
+    pub fn matches(&self, keysym: xcb::Keysym, state: u16) -> bool {
+        keysym == self.keysym && state & !IGNORED_MODIFIERS == self.modifiers
+    }
+
+    /// Grabs the hotkey on the `root` window, so that it is sent to us
+    /// whichever window is focused.
+    pub fn grab(&self, conn: &ewmh::Connection, keymap: &Keymap, root: xcb::Window) -> Result<()> {
+        let keycode = keymap
+            .keycode(self.keysym)
+            .ok_or_else(|| format_err!("No key on the keyboard types the hotkey"))?;
+        // The hotkey should work regardless of Caps Lock and Num Lock.
+        for ignored in &[
+            0,
+            xcb::MOD_MASK_LOCK,
+            xcb::MOD_MASK_2,
+            IGNORED_MODIFIERS as u32,
+        ] {
+            xcb::grab_key(
+                conn,
+                false,
+                root,
+                self.modifiers | *ignored as u16,
+                keycode,
+                xcb::GRAB_MODE_ASYNC as u8,
+                xcb::GRAB_MODE_ASYNC as u8,
+            )
+            .request_check()
+            .map_err(|_| format_err!("The hotkey is already grabbed by another program"))?;
+        }
+        Ok(())
+    }
+}
+
+/// The keysym typed by each key on the keyboard, without any modifiers.
+pub(crate) struct Keymap {
+    keysyms: HashMap<xcb::Keycode, xcb::Keysym>,
+}
+
+impl Keymap {
+    pub fn new(conn: &ewmh::Connection) -> Result<Keymap> {
+        let setup = conn.get_setup();
+        let (min, max) = (setup.min_keycode(), setup.max_keycode());
+        let reply = xcb::get_keyboard_mapping(conn, min, max - min + 1).get_reply()?;
+        let per_keycode = usize::from(reply.keysyms_per_keycode()).max(1);
+        let keysyms = reply
+            .keysyms()
+            .chunks(per_keycode)
+            .zip(min..=max)
+            .filter_map(|(keysyms, keycode)| Some((keycode, *keysyms.first()?)))
+            .collect();
+        Ok(Keymap { keysyms })
+    }
+
+    /// The keysym typed by the key with `keycode`, or 0 if it types nothing.
+    pub fn keysym(&self, keycode: xcb::Keycode) -> xcb::Keysym {
+        self.keysyms.get(&keycode).cloned().unwrap_or(0)
+    }
+
+    /// The keycode of a key which types `keysym`.
+    pub fn keycode(&self, keysym: xcb::Keysym) -> Option<xcb::Keycode> {
+        // Keysyms are lowercase without Shift, which matters for letters.
+        self.keysyms
+            .iter()
+            .filter(|&(_, &typed)| typed == keysym)
+            .map(|(&keycode, _)| keycode)
+            .min()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Hotkey, KEY_ESCAPE};
+
+    #[test]
+    fn works() {
+        let hotkey = Hotkey::parse("Mod4+b").unwrap();
+        assert_eq!(
+            hotkey,
+            Hotkey {
+                modifiers: xcb::MOD_MASK_4 as u16,
+                keysym: 'b' as u32,
+            }
+        );
+        // Caps Lock doesn't matter, but Shift does.
+        let lock = xcb::MOD_MASK_LOCK as u16;
+        assert!(hotkey.matches('b' as u32, hotkey.modifiers | lock));
+        assert!(!hotkey.matches('b' as u32, hotkey.modifiers | xcb::MOD_MASK_SHIFT as u16));
+
+        assert_eq!(
+            Hotkey::parse("Control+Alt+F12").unwrap(),
+            Hotkey {
+                modifiers: (xcb::MOD_MASK_CONTROL | xcb::MOD_MASK_1) as u16,
+                keysym: 0xffc9,
+            }
+        );
+        assert_eq!(Hotkey::parse("Escape").unwrap().keysym, KEY_ESCAPE);
+        assert!(Hotkey::parse("Hyper+b").is_err());
+        assert!(Hotkey::parse("Mod4+banana").is_err());
+    }
+}
@@ -13,16 +13,14 @@
 //!
 //! In normal usage, you will create a new binary project that relies on the
 //! `cnx` crate, and customize it through options passed to the main [`Cnx`]
-//! object and its widgets. (It's inspired by [`QTile`] and [`dwm`], in that the
-//! configuration is done entirely in code, allowing greater extensibility
-//! without needing complex configuration handling).
+//! object (usually using a [`CnxBuilder`]) and its widgets. (It's inspired by
+//! [`QTile`] and [`dwm`], in that the configuration is done entirely in code,
+//! allowing greater extensibility without needing complex configuration
+//! handling).
 //!
 //! An simple example of a binary using Cnx is:
 //!
 //! ```no_run
-//! #[macro_use]
-//! extern crate cnx;
-//!
 //! use cnx::*;
 //! use cnx::text::*;
 //! use cnx::widgets::*;
@@ -33,12 +31,16 @@
 //!         fg_color: Color::white(),
 //!         bg_color: None,
 //!         padding: Padding::new(8.0, 8.0, 0.0, 0.0),
+//!         border: None,
 //!     };
 //!
-//!     let mut cnx = Cnx::new(Position::Top)?;
-//!     cnx_add_widget!(cnx, ActiveWindowTitle::new(&cnx, attr.clone()));
-//!     cnx_add_widget!(cnx, Clock::new(&cnx, attr.clone()));
-//!     Ok(cnx.run()?)
+//!     let title_attr = attr.clone();
+//!     Cnx::builder()
+//!         .position(Position::Top)
+//!         .add_widget(move |cnx| ActiveWindowTitle::new(cnx, title_attr))
+//!         .add_widget(move |cnx| Clock::new(cnx, "%H:%M".to_owned(), attr))
+//!         .build()?
+//!         .run()
 //! }
 //! ```
 //!
@@ -48,6 +50,10 @@
 //! install cnx; cnx`. However, neither of these are recommended as options for
 //! customizing Cnx are then limited).
 //!
+//! Alternatively, the `config` feature allows the stock binary to be
+//! configured with a TOML file, without writing any Rust. See
+//! [`Cnx::from_config()`].
+//!
 //! Before running Cnx, you'll need to make sure your system has the required
 //! dependencies, which are described in the [`README`][readme-deps].
 //!
@@ -60,14 +66,90 @@
 //! - [`Pager`] — Shows the WM's workspaces/groups, highlighting whichever is
 //!   currently active. (Uses [`EWMH`]'s `_NET_DESKTOP_NAMES`,
 //!   `_NET_NUMBER_OF_DESKTOPS` and `_NET_CURRENT_DESKTOP`).
-//! - [`Sensors`] — Periodically parses and displays the output of the
-//!   [`lm_sensors`] utility, allowing CPU temperature to be displayed.
+//! - [`Taskbar`] — Shows the windows on the current workspace, highlighting
+//!   the focused one. (Uses [`EWMH`]'s `_NET_CLIENT_LIST`). Click on a window
+//!   to activate it.
+//! - [`Sensors`] — Periodically reads the kernel's hwmon sensors (as shown
+//!   by [`lm_sensors`]), allowing CPU temperature to be displayed, changing
+//!   color once it gets high or critical.
+//! - [`CPU Frequency`] — Shows the lowest, average and highest frequencies of
+//!   the CPUs, and their governor. Click to switch governors.
 //! - [`Volume`] — Uses `alsa-lib` to show the current volume/mute status of the
 //!   default output device. (Disable by removing default feature
-//!   `volume-control`).
+//!   `volume-widget`).
+//! - [`Pulse Volume`] — Shows the volume of PulseAudio's default sink and its
+//!   name, using `pactl subscribe` to notice changes. (Enable with feature
+//!   `pulse-widget`).
+//! - [`Mic Level`] — Shows a live level meter of PulseAudio's default source,
+//!   to check the right microphone is active. (Enable with feature
+//!   `pulse-widget`).
+//! - [`Weather`] — Shows the temperature and conditions from
+//!   [OpenWeatherMap], cached so that they survive restarts and going
+//!   offline. (Enable with feature `weather-widget`).
 //! - [`Battery`] — Uses `/sys/class/power_supply/` to show details on the
 //!   remaining battery and charge status.
-//! - [`Clock`] — Shows the time.
+//! - [`Backlight`] — Uses `/sys/class/backlight/` to show the screen's
+//!   brightness, and raises or lowers it when scrolled over.
+//! - [`UPower Battery`] — Shows the charge of each battery from [UPower],
+//!   updating as soon as it changes.
+//! - [`Device Batteries`] — Shows the battery of each wireless device, e.g.
+//!   mice, keyboards and gamepads, from [UPower].
+//! - [`Disk Temperature`] — Uses the kernel's `drivetemp` hwmon driver to show
+//!   the temperature of each hard disk.
+//! - [`Indoor Outdoor`] — Shows the temperature from a local hwmon sensor
+//!   alongside the weather outdoors, from [Open-Meteo].
+//! - [`Clock`] — Shows the time, optionally highlighting holidays, and the
+//!   time in other time zones. Clicking it can switch its format or pop up a
+//!   month calendar.
+//! - [`Copy On Click`] — Wraps another widget, copying its text to the
+//!   clipboard when it is middle-clicked.
+//! - [`Threshold`] — Wraps another widget, showing it with warning or critical
+//!   attributes once a number it shows reaches them.
+//! - [`Custom`] — Shows whatever text it is sent, e.g. by scripts over IPC.
+//! - [`Backup Status`] — Shows how long ago the last backup ran, according to
+//!   a stamp file written by the backup script.
+//! - [`Alarm`] — Shows the next of a set of daily alarms, turning urgent (and
+//!   optionally sending a notification or running a command) when it is due.
+//! - [`Exec`] — Shows the output of a shell command, run either every
+//!   interval or once, showing each line as it is printed.
+//! - [`Dunst`] — Shows the number of notifications in [`dunst`]'s history and
+//!   the summary of the most recent one, which is shown again when clicked.
+//! - [`Home Assistant`] — Shows the states of [`Home Assistant`][hass]
+//!   entities, e.g. a thermostat or door sensor, as soon as they change.
+//! - [`Inhibitors`] — Shows what is preventing the system from sleeping or
+//!   idling, according to `logind`'s inhibitor locks.
+//! - [`Session Lock`] — Shows whether the session is locked, according to
+//!   `logind`, and locks it when clicked.
+//! - [`Mpd`] — Shows the song that [`MPD`] is playing, using its `idle`
+//!   command to notice changes.
+//! - [`Mqtt`] — Shows the latest messages published to [`MQTT`] topics, e.g.
+//!   by Home Assistant or ESPHome sensors.
+//! - [`Mpris`] — Shows the artist and title of the track being played by an
+//!   MPRIS media player, e.g. Spotify or mpv.
+//! - [`Root Property`] — Shows a property of the root window, which scripts
+//!   can set with `xprop`.
+//! - [`Screen Capture`] — Shows a recording dot while a screen recorder (e.g.
+//!   `ffmpeg` or `obs`) is running.
+//! - [`Stopwatch`] — Times something to the tenth of a second, started and
+//!   stopped by clicks or over IPC.
+//! - [`Systemd Timers`] — Shows when the next systemd timer (e.g. a backup)
+//!   is due, and any whose last run failed.
+//! - [`Time Tracker`] — Adds up how long each application has been focused
+//!   for today, showing the top application or the total.
+//! - [`Tray`] — A system tray, which embeds applications' icons (e.g.
+//!   `nm-applet`'s) in the bar using [`XEmbed`].
+//! - [`Status Notifier`] — Shows applications' StatusNotifierItems (also
+//!   known as AppIndicators), e.g. Telegram's or Discord's.
+//! - [`Update Check`] — Shows when a newer version of Cnx has been released,
+//!   checking once a day.
+//! - [`Usb Toast`] — Briefly shows USB devices being plugged in and
+//!   unplugged.
+//! - [`Wireless`] — Shows the SSID and signal quality of a wireless network
+//!   interface.
+//! - [`Connectivity`] — Shows whether we're online, behind a captive portal or
+//!   offline, checking whenever the network changes.
+//! - [`WM State`] — Shows state which the WM or scripts set as properties of
+//!   the root window, e.g. the current layout.
 //!
 //! # Dependencies
 //!
@@ -76,6 +158,8 @@
 //!
 //!  - `xcb-util`: `xcb-ewmh` / `xcb-icccm` / `xcb-keysyms`
 //!  - `x11-xcb`
+//!  - `xcb-randr`
+//!  - `xcb-shape`
 //!  - `pango`
 //!  - `cairo`
 //!  - `pangocairo`
@@ -83,7 +167,25 @@
 //! Some widgets have additional dependencies:
 //!
 //!  - [`Volume`] widget relies on `alsa-lib`
-//!  - [`Sensors`] widget relies on [`lm_sensors`] being installed.
+//!  - [`Pulse Volume`] widget relies on `pactl` being installed.
+//!  - [`Mic Level`] widget relies on `parec` being installed.
+//!  - [`Dunst`] widget relies on `dunstctl` being installed.
+//!  - [`Update Check`] widget relies on `curl` (or `git`, if checking a git
+//!    repository's tags) being installed.
+//!  - [`Alarm`] and [`Threshold`] widgets rely on `notify-send` being
+//!    installed, if they send notifications.
+//!  - [`Weather`] and [`Indoor Outdoor`] widgets rely on GeoClue's
+//!    `where-am-i` demo being installed, if they follow the location.
+//!  - [`Inhibitors`], [`Mpris`], [`Systemd Timers`], [`UPower Battery`] and
+//!    [`Device Batteries`] widgets rely on `busctl` (from `systemd`) being
+//!    installed. The last two also rely on `gdbus` (from GLib) to watch
+//!    UPower.
+//!  - [`Backlight`] widget relies on `busctl` to change the brightness, unless
+//!    Cnx can write to `/sys/class/backlight/` itself.
+//!  - [`Session Lock`] widget relies on `busctl` (from `systemd`) being
+//!    installed.
+//!  - [`Status Notifier`] widget relies on `busctl` being installed, and on
+//!    a `StatusNotifierWatcher` (e.g. KDE's) running.
 //!
 //! # Creating new widgets
 //!
@@ -94,57 +196,167 @@
 //! The adventurous may choose to ignore this warning and look into the
 //! documentation of the [`Widget`] trait. The built-in [`widgets`] should give you
 //! some examples on which to base your work.
+//! Widgets which watch [`EWMH`] properties can use the helpers in the
+//! [`xcb_ext`] module, as the built-in ones do.
 //!
 //! [`mio`]: https://docs.rs/mio
 //! [`tokio`]: https://tokio.rs/
 //! [`Cnx`]: struct.Cnx.html
+//! [`CnxBuilder`]: struct.CnxBuilder.html
+//! [`Cnx::from_config()`]: struct.Cnx.html#method.from_config
+//! [`xcb_ext`]: xcb_ext/index.html
 //! [`dwm`]: http://dwm.suckless.org/
 //! [readme-deps]: https://github.com/mjkillough/cnx/blob/master/README.md#dependencies
 //! [`src/bin/cnx.rs`]: https://github.com/mjkillough/cnx/blob/master/src/bin/cnx.rs
 //! [`Active Window Title`]: widgets/struct.ActiveWindowTitle.html
 //! [`EWMH`]: https://specifications.freedesktop.org/wm-spec/wm-spec-latest.html
 //! [`Pager`]: widgets/struct.Pager.html
+//! [`Taskbar`]: widgets/struct.Taskbar.html
 //! [`Sensors`]: widgets/struct.Sensors.html
+//! [`CPU Frequency`]: widgets/struct.CpuFreq.html
+//! [`Pulse Volume`]: widgets/struct.PulseVolume.html
+//! [`Mic Level`]: widgets/struct.MicLevel.html
 //! [`lm_sensors`]: https://wiki.archlinux.org/index.php/lm_sensors
 //! [`Volume`]: widgets/struct.Volume.html
 //! [`Battery`]: widgets/struct.Battery.html
+//! [`Backlight`]: widgets/struct.Backlight.html
+//! [`UPower Battery`]: widgets/struct.UPowerBattery.html
+//! [UPower]: https://upower.freedesktop.org/
+//! [`Device Batteries`]: widgets/struct.DeviceBatteries.html
+//! [`Weather`]: widgets/struct.Weather.html
+//! [OpenWeatherMap]: https://openweathermap.org/
+//! [`Disk Temperature`]: widgets/struct.DiskTemperature.html
+//! [`Indoor Outdoor`]: widgets/struct.IndoorOutdoor.html
+//! [Open-Meteo]: https://open-meteo.com/
 //! [`Clock`]: widgets/struct.Clock.html
+//! [`Copy On Click`]: widgets/struct.CopyOnClick.html
+//! [`Threshold`]: widgets/struct.Threshold.html
+//! [`Custom`]: widgets/struct.Custom.html
+//! [`Backup Status`]: widgets/struct.BackupStatus.html
+//! [`Alarm`]: widgets/struct.Alarm.html
+//! [`Root Property`]: widgets/struct.RootProperty.html
+//! [`Screen Capture`]: widgets/struct.ScreenCapture.html
+//! [`Dunst`]: widgets/struct.Dunst.html
+//! [`Exec`]: widgets/struct.Exec.html
+//! [`Stopwatch`]: widgets/struct.Stopwatch.html
+//! [`Systemd Timers`]: widgets/struct.SystemdTimers.html
+//! [`Time Tracker`]: widgets/struct.TimeTracker.html
+//! [`Update Check`]: widgets/struct.UpdateCheck.html
+//! [`Usb Toast`]: widgets/struct.UsbToast.html
+//! [`Wireless`]: widgets/struct.Wireless.html
+//! [`Connectivity`]: widgets/struct.Connectivity.html
+//! [`WM State`]: widgets/struct.WmState.html
+//! [`Home Assistant`]: widgets/struct.HomeAssistant.html
+//! [hass]: https://www.home-assistant.io/
+//! [`Inhibitors`]: widgets/struct.Inhibitors.html
+//! [`Tray`]: widgets/struct.Tray.html
+//! [`Status Notifier`]: widgets/struct.StatusNotifier.html
+//! [`XEmbed`]: https://specifications.freedesktop.org/xembed-spec/xembed-spec-latest.html
+//! [`Session Lock`]: widgets/struct.SessionLock.html
+//! [`Mpd`]: widgets/struct.Mpd.html
+//! [`MPD`]: https://www.musicpd.org/
+//! [`Mpris`]: widgets/struct.Mpris.html
+//! [`Mqtt`]: widgets/struct.Mqtt.html
+//! [`MQTT`]: https://mqtt.org/
+//! [`dunst`]: https://dunst-project.org/
 //! [`Widget`]: widgets/trait.Widget.html
 //! [`widgets`]: widgets/index.html
 
 // new(...) -> Result<T> is used in a lot of places:
 #![allow(clippy::new_ret_no_self)]
 
+mod animation;
+pub mod appearance;
 mod bar;
+mod builder;
+mod busctl;
+mod cache;
+mod clipboard;
+#[cfg(feature = "config")]
+mod config;
+mod control;
+mod dependency;
+mod error;
+mod fifo;
+mod geoclue;
+mod http;
+pub mod icons;
+pub mod inotify;
+mod ipc;
+mod json;
+mod keyboard;
+mod mqtt;
+pub mod netlink;
+// Only public for the fuzz targets in `fuzz/`, which enable `fuzzing`.
+#[cfg(feature = "fuzzing")]
+#[doc(hidden)]
+pub mod parse;
+#[cfg(not(feature = "fuzzing"))]
+mod parse;
+mod popup;
+mod process;
+mod reload;
+mod root_name;
+pub mod state;
+pub mod template;
 pub mod text;
+mod timer;
+#[cfg(feature = "config")]
+mod toml;
+mod upower;
+pub mod weather;
+mod websocket;
 pub mod widgets;
+pub mod xcb_ext;
+pub mod zoneinfo;
+
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::Duration;
 
-use failure::ResultExt;
+use futures::unsync::mpsc;
+use log::warn;
 use tokio_core::reactor::{Core, Handle};
-use tokio_timer::Timer;
 
-use crate::bar::Bar;
+use crate::animation::Animation;
+use crate::appearance::Appearance;
+use crate::bar::{Bar, BarOptions, KeyboardNav};
+use crate::control::Commands;
+use crate::error::{format_err, ResultExt};
+use crate::inotify::Inotify;
+use crate::keyboard::Hotkey;
+use crate::netlink::Netlink;
+use crate::reload::Reloader;
+use crate::text::{Attributes, Color, Padding, Palette, SectionStyle, Text};
+use crate::timer::{PowerState, Timer};
+use crate::widgets::WidgetFactory;
 
-pub use crate::bar::Position;
-pub use crate::widgets::Widget;
+pub use crate::bar::{Monitors, Position, Region, Side};
+pub use crate::builder::CnxBuilder;
+pub use crate::control::Control;
+pub use crate::error::Error;
+pub use crate::timer::LowPower;
+pub use crate::widgets::{Widget, WidgetOptions};
 
-pub type Result<T> = std::result::Result<T, failure::Error>;
+pub type Result<T> = std::result::Result<T, Error>;
 
 /// The main object, used to instantiate an instance of Cnx.
 ///
-/// The [`cnx_add_widget!()`] macro can be used to add widgets to the Cnx
-/// instance. Once configured, the [`run()`] method will take ownership of the
-/// instance and run it until the process is killed or an error is returned.
+/// A `Cnx` instance is usually configured with a [`CnxBuilder`], returned by
+/// [`builder()`]. Alternatively, it can be created with [`new()`], and then
+/// configured using its methods, e.g. [`add_widget()`]. Once configured, the
+/// [`run()`] method will take ownership of the instance and run it until the
+/// process is killed or an error is returned.
 ///
-/// [`cnx_add_widget!()`]: macro.cnx_add_widget.html
+/// [`CnxBuilder`]: struct.CnxBuilder.html
+/// [`builder()`]: #method.builder
+/// [`new()`]: #method.new
+/// [`add_widget()`]: #method.add_widget
 /// [`run()`]: #method.run
 ///
 /// # Examples
 ///
 /// ```no_run
-/// # #[macro_use]
-/// # extern crate cnx;
-/// #
 /// # use cnx::*;
 /// # use cnx::text::*;
 /// # use cnx::widgets::*;
@@ -155,28 +367,52 @@ pub type Result<T> = std::result::Result<T, failure::Error>;
 ///     fg_color: Color::white(),
 ///     bg_color: None,
 ///     padding: Padding::new(8.0, 8.0, 0.0, 0.0),
+///     border: None,
 /// };
 ///
 /// let mut cnx = Cnx::new(Position::Top)?;
-/// cnx_add_widget!(cnx, ActiveWindowTitle::new(&cnx, attr.clone()));
-/// cnx_add_widget!(cnx, Clock::new(&cnx, attr.clone()));
+/// cnx.add_widget(ActiveWindowTitle::new(&cnx, attr.clone()));
+/// cnx.add_widget(Clock::new(&cnx, "%H:%M".to_owned(), attr.clone()));
 /// cnx.run()?;
 /// # Ok(())
 /// # }
 /// # fn main() { run().unwrap(); }
 /// ```
 pub struct Cnx {
-    core: Core,
+    // Taken by `run()`, so that the rest of the instance can be kept for
+    // reloading widgets into.
+    core: Option<Core>,
+    handle: Handle,
     timer: Timer,
-    bar: Bar,
-    widgets: Vec<Box<dyn Widget>>,
+    frame_timer: tokio_timer::Timer,
+    power: Rc<PowerState>,
+    position: Position,
+    monitors: Monitors,
+    bar_options: BarOptions,
+    widgets: Vec<(Box<dyn Widget>, WidgetOptions)>,
+    control: Control,
+    commands: Commands,
+    ipc_socket: Option<PathBuf>,
+    fifo_dir: Option<PathBuf>,
+    scratchpad_timeout: Option<Duration>,
+    error_flash: Option<(Attributes, Duration)>,
+    click_through: bool,
+    rotate_text: bool,
+    auto_hide: Option<Duration>,
+    root_name_separator: Option<String>,
+    keyboard_hotkey: Option<String>,
+    placeholder: Option<Text>,
+    netlink: Netlink,
+    inotify: Inotify,
+    appearance: Appearance,
+    reload: Option<(PathBuf, Reloader)>,
 }
 
 impl Cnx {
     /// Creates a new `Cnx` instance.
     ///
-    /// This creates a new `Cnx` instance at either the top or bottom of the
-    /// screen, depending on the value of the [`Position`] enum.
+    /// This creates a new `Cnx` instance at the top, bottom, left or right
+    /// edge of the screen, depending on the value of the [`Position`] enum.
     ///
     /// [`Position`]: enum.Position.html
     ///
@@ -191,47 +427,856 @@ impl Cnx {
     /// let mut cnx = Cnx::new(Position::Bottom);
     /// ```
     pub fn new(position: Position) -> Result<Cnx> {
+        let power = Rc::new(PowerState::new());
+        let (sender, commands) = mpsc::unbounded();
+        let core = Core::new().context("Could not create Tokio Core")?;
+        let netlink = Netlink::new(core.handle());
+        let inotify = Inotify::new(core.handle());
+        let appearance = Appearance::new(core.handle());
         Ok(Cnx {
-            core: Core::new().context("Could not create Tokio Core")?,
-            timer: Timer::default(),
-            bar: Bar::new(position)?,
+            handle: core.handle(),
+            core: Some(core),
+            timer: Timer::new(tokio_timer::Timer::default(), power.clone()),
+            frame_timer: animation::frame_timer(),
+            power,
+            position,
+            monitors: Monitors::default(),
+            bar_options: BarOptions::default(),
             widgets: Vec::new(),
+            control: Control::new(sender),
+            commands,
+            ipc_socket: None,
+            fifo_dir: None,
+            scratchpad_timeout: None,
+            error_flash: None,
+            click_through: false,
+            rotate_text: false,
+            auto_hide: None,
+            root_name_separator: None,
+            keyboard_hotkey: None,
+            placeholder: None,
+            netlink,
+            inotify,
+            appearance,
+            reload: None,
         })
     }
 
+    /// Returns a copy of this instance with no widgets or event loop, which
+    /// reloaded widgets are added to while it is running.
+    fn without_widgets(&self) -> Cnx {
+        // Commands are only received by the running instance.
+        let (_, commands) = mpsc::unbounded();
+        Cnx {
+            core: None,
+            handle: self.handle(),
+            timer: self.timer(),
+            frame_timer: self.frame_timer.clone(),
+            power: self.power.clone(),
+            position: self.position.clone(),
+            monitors: self.monitors.clone(),
+            bar_options: self.bar_options.clone(),
+            widgets: Vec::new(),
+            control: self.control(),
+            commands,
+            ipc_socket: None,
+            fifo_dir: self.fifo_dir.clone(),
+            scratchpad_timeout: self.scratchpad_timeout,
+            error_flash: self.error_flash.clone(),
+            click_through: self.click_through,
+            rotate_text: self.rotate_text,
+            auto_hide: self.auto_hide,
+            root_name_separator: self.root_name_separator.clone(),
+            keyboard_hotkey: self.keyboard_hotkey.clone(),
+            placeholder: self.placeholder.clone(),
+            netlink: self.netlink(),
+            inotify: self.inotify(),
+            appearance: self.appearance(),
+            reload: None,
+        }
+    }
+
+    /// Returns a [`CnxBuilder`], which configures a new `Cnx` instance and
+    /// the widgets on it in one expression.
+    ///
+    /// [`CnxBuilder`]: struct.CnxBuilder.html
+    pub fn builder() -> CnxBuilder {
+        CnxBuilder::new()
+    }
+
     fn handle(&self) -> Handle {
-        self.core.handle()
+        self.handle.clone()
     }
 
     fn timer(&self) -> Timer {
         self.timer.clone()
     }
 
+    fn animation(&self, duration: Duration) -> Animation {
+        Animation::new(self.frame_timer.clone(), self.power.clone(), duration)
+    }
+
+    /// Returns a handle to the shared netlink monitor, which widgets can use
+    /// to subscribe to network and device events.
+    ///
+    /// See the [`netlink`] module for more details.
+    ///
+    /// [`netlink`]: netlink/index.html
+    pub fn netlink(&self) -> Netlink {
+        self.netlink.clone()
+    }
+
+    /// Returns a handle to the shared inotify watcher, which widgets can use
+    /// to be notified when files change.
+    ///
+    /// See the [`inotify`] module for more details.
+    ///
+    /// [`inotify`]: inotify/index.html
+    pub fn inotify(&self) -> Inotify {
+        self.inotify.clone()
+    }
+
+    /// Returns a handle to the desktop's appearance settings, which widgets
+    /// can use to follow its color scheme (i.e. dark mode).
+    ///
+    /// See the [`appearance`] module for more details.
+    ///
+    /// [`appearance`]: appearance/index.html
+    pub fn appearance(&self) -> Appearance {
+        self.appearance.clone()
+    }
+
     /// Adds a widget to the Cnx instance.
     ///
     /// This method takes a [`Widget`] and adds it to the current Cnx instance,
     /// to the right of any existing widgets.
     ///
-    /// It is recommended that you instead use the [`cnx_add_widget!()`] macro,
-    /// as this will eventually grow to have a more flexible syntax for
-    /// configuring widget attributes.
+    /// Widgets can also be added using [`CnxBuilder::add_widget()`].
+    ///
+    /// The returned [`WidgetOptions`] can be used to control how the widget is
+    /// laid out, e.g. its priority when space on the bar runs out, or what
+    /// happens when it fails.
     ///
     /// [`Widget`]: widgets/trait.Widget.html
-    /// [`cnx_add_widget!()`]: macro.cnx_add_widget.html
-    pub fn add_widget<W>(&mut self, widget: W)
+    /// [`CnxBuilder::add_widget()`]: struct.CnxBuilder.html#method.add_widget
+    /// [`WidgetOptions`]: widgets/struct.WidgetOptions.html
+    pub fn add_widget<W>(&mut self, widget: W) -> &mut WidgetOptions
+    where
+        W: Widget + 'static,
+    {
+        self.widgets.push((
+            Box::new(widget) as Box<dyn Widget>,
+            WidgetOptions::default(),
+        ));
+        &mut self.widgets.last_mut().unwrap().1
+    }
+
+    /// Adds the widget created by `widget`, which is called again to create a
+    /// new instance of the widget each time it's restarted after failing.
+    ///
+    /// Widgets added with [`add_widget()`] can't be created again, so only
+    /// their stream is polled again when they're restarted. This suits
+    /// widgets whose streams end, or can't recover, after an error, e.g.
+    /// those which keep a connection open. See [`RestartPolicy`].
+    ///
+    /// [`add_widget()`]: #method.add_widget
+    /// [`RestartPolicy`]: widgets/enum.RestartPolicy.html
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use cnx::*;
+    /// # use cnx::text::*;
+    /// # use cnx::widgets::*;
+    /// #
+    /// # fn run() -> ::cnx::Result<()> {
+    /// # let attr = Attributes {
+    /// #     font: Font::new("SourceCodePro 21"),
+    /// #     fg_color: "ffffff".parse().unwrap(),
+    /// #     bg_color: None,
+    /// #     padding: Padding::new(8.0, 8.0, 0.0, 0.0),
+    /// #     border: None,
+    /// # };
+    /// let mut cnx = Cnx::new(Position::Top)?;
+    /// cnx.add_restartable_widget(move |cnx| {
+    ///     Mpd::new(cnx, attr.clone(), "{artist} - {title}".to_owned())
+    /// })
+    /// .name("mpd");
+    /// # Ok(())
+    /// # }
+    /// # fn main() { run().unwrap(); }
+    /// ```
+    pub fn add_restartable_widget<W, F>(&mut self, mut widget: F) -> &mut WidgetOptions
     where
         W: Widget + 'static,
+        F: FnMut(&Cnx) -> W + 'static,
+    {
+        let first = widget(self);
+        let cnx = self.without_widgets();
+        let factory = WidgetFactory::new(move || Ok(Box::new(widget(&cnx))));
+        let options = self.add_widget(first);
+        options.factory = Some(factory);
+        options
+    }
+
+    /// Adds a widget to the given [`Region`] of the bar.
+    ///
+    /// This is like [`add_widget()`], except that the widget is added to the
+    /// right of any existing widgets in `region`, rather than in the left
+    /// region.
+    ///
+    /// [`Region`]: enum.Region.html
+    /// [`add_widget()`]: #method.add_widget
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use cnx::*;
+    /// # use cnx::text::*;
+    /// # use cnx::widgets::*;
+    /// #
+    /// # fn run() -> ::cnx::Result<()> {
+    /// # let attr = Attributes {
+    /// #     font: Font::new("SourceCodePro 21"),
+    /// #     fg_color: "ffffff".parse().unwrap(),
+    /// #     bg_color: None,
+    /// #     padding: Padding::new(8.0, 8.0, 0.0, 0.0),
+    /// #     border: None,
+    /// # };
+    /// let mut cnx = Cnx::new(Position::Top)?;
+    /// cnx.add_widget_to(Region::Left, Pager::new(&cnx, attr.clone(), attr.clone()));
+    /// cnx.add_widget_to(Region::Center, ActiveWindowTitle::new(&cnx, attr.clone()));
+    /// cnx.add_widget_to(Region::Right, Clock::new(&cnx, "%H:%M".to_owned(), attr.clone()));
+    /// # Ok(())
+    /// # }
+    /// # fn main() { run().unwrap(); }
+    /// ```
+    pub fn add_widget_to<W>(&mut self, region: Region, widget: W) -> &mut WidgetOptions
+    where
+        W: Widget + 'static,
+    {
+        let options = self.add_widget(widget);
+        options.region = region;
+        options
+    }
+
+    /// Adds a widget to the scratchpad bars, rather than to the main bars.
+    ///
+    /// The scratchpad bars are normally hidden, and are shown on demand
+    /// (e.g. by a WM key binding which runs `echo scratchpad toggle | socat -
+    /// UNIX-CONNECT:$XDG_RUNTIME_DIR/cnx.sock`, see [`enable_ipc()`]), like a
+    /// drop-down dashboard. While shown, each scratchpad covers the main bar
+    /// on its monitor. Scratchpad widgets are otherwise laid out just like
+    /// those added with [`add_widget()`].
+    ///
+    /// No scratchpad bars are created unless a widget is added to them.
+    ///
+    /// [`enable_ipc()`]: #method.enable_ipc
+    /// [`add_widget()`]: #method.add_widget
+    pub fn add_scratchpad_widget<W>(&mut self, widget: W) -> &mut WidgetOptions
+    where
+        W: Widget + 'static,
+    {
+        let options = self.add_widget(widget);
+        options.scratchpad = true;
+        options
+    }
+
+    /// Hides the scratchpad bars automatically, once they have been shown for
+    /// `timeout`.
+    ///
+    /// By default, the scratchpads stay shown until they are hidden, or there
+    /// is a click outside of them.
+    pub fn set_scratchpad_timeout(&mut self, timeout: Duration) {
+        self.scratchpad_timeout = Some(timeout);
+    }
+
+    /// Makes the bars ignore the pointer, so that clicks pass through them to
+    /// whatever is underneath.
+    ///
+    /// This is for purely informational bars, e.g. a translucent overlay (see
+    /// [`set_background()`]). Widgets no longer respond to clicks, and
+    /// clicking elsewhere no longer hides the scratchpad bars.
+    ///
+    /// [`set_background()`]: #method.set_background
+    pub fn set_click_through(&mut self, click_through: bool) {
+        self.click_through = click_through;
+    }
+
+    /// Turns the texts on a vertical bar (see [`Position::Left`]) to run along
+    /// it, reading from top to bottom, rather than having each of them
+    /// upright.
+    ///
+    /// This keeps the bar slim when its texts are long. It has no effect on
+    /// horizontal bars.
+    ///
+    /// [`Position::Left`]: enum.Position.html#variant.Left
+    pub fn set_rotated_text(&mut self, rotated: bool) {
+        self.rotate_text = rotated;
+    }
+
+    /// Hides each bar once the pointer has been elsewhere for `timeout`, and
+    /// shows it again when the pointer reaches the bar's edge of its monitor.
+    ///
+    /// While hidden, a bar doesn't reserve any space, so maximized windows
+    /// grow to fill its monitor. It can also be shown with
+    /// [`Control::show_bar()`] or [`Control::toggle_bar()`] (e.g. over IPC,
+    /// from a key binding), after which it hides again once `timeout` has
+    /// passed without the pointer over it.
+    ///
+    /// [`Control::show_bar()`]: struct.Control.html#method.show_bar
+    /// [`Control::toggle_bar()`]: struct.Control.html#method.toggle_bar
+    pub fn set_auto_hide(&mut self, timeout: Duration) {
+        self.auto_hide = Some(timeout);
+    }
+
+    /// Lets the bar's widgets be used without a pointer, after pressing
+    /// `hotkey`, e.g. `Mod4+b` or `Control+Alt+F12`.
+    ///
+    /// Pressing the hotkey highlights the first widget which handles clicks,
+    /// on the first bar. The arrow keys (or Tab and Shift+Tab) then move the
+    /// highlight between widgets, and Enter (or Space) clicks the highlighted
+    /// widget with the left button. Escape, or the hotkey again, stops
+    /// without clicking anything. The keyboard is grabbed meanwhile, so keys
+    /// don't reach any other window.
+    ///
+    /// The hotkey is made of any of the modifiers `Shift`, `Control`, `Alt`
+    /// (or `Mod1`), `Super` (or `Mod4`) and `Mod2` to `Mod5`, followed by the
+    /// key: a letter, digit, punctuation, `F1` to `F24`, or one of `space`,
+    /// `Return`, `Escape`, `Tab`, `Left`, `Up`, `Right`, `Down`, `Home`, `End`,
+    /// `Print` or `Menu`. If it is invalid, [`run()`] fails, but if another
+    /// program has already grabbed it, Cnx runs without keyboard navigation.
+    ///
+    /// [`run()`]: #method.run
+    pub fn set_keyboard_hotkey<S: Into<String>>(&mut self, hotkey: S) {
+        self.keyboard_hotkey = Some(hotkey.into());
+    }
+
+    /// Shows the widgets in another bar by setting the root window's
+    /// `WM_NAME` to their texts, rather than drawing any bars.
+    ///
+    /// This is for WMs like dwm, which show the root window's name in their
+    /// own bar (as set by e.g. `xsetroot -name`), so that they can show Cnx's
+    /// widgets. The text of each widget is joined, with `separator` (e.g. `" |
+    /// "`) between the widgets. The regions are shown one after another, and
+    /// markup, graphics and attributes are dropped, as is anything only
+    /// affecting the bars. Clicks can't be passed to the widgets, and they
+    /// aren't reloaded (see [`reload_on_change()`]), but commands for them
+    /// (see [`control()`]) still work.
+    ///
+    /// [`control()`]: #method.control
+    /// [`reload_on_change()`]: #method.reload_on_change
+    pub fn set_root_name_output<S: Into<String>>(&mut self, separator: S) {
+        self.root_name_separator = Some(separator.into());
+    }
+
+    /// Leaves `width` pixels empty at the given [`Side`] of each bar, for
+    /// another program to use.
+    ///
+    /// This is for docks, such as system trays like `stalonetray` or
+    /// `trayer`, which can be placed beside Cnx. The bar's window doesn't
+    /// cover the space, and the space isn't reserved with the WM, so the dock
+    /// should reserve it itself.
+    ///
+    /// [`Side`]: enum.Side.html
+    pub fn reserve_space(&mut self, side: Side, width: u16) {
+        self.bar_options.reserved = Some((side, width));
+    }
+
+    /// Shows `text` in place of each widget until it first has something to
+    /// show, e.g. `…`, so that the bar appears straight away with a stable
+    /// layout, rather than widgets popping in as they start up.
+    ///
+    /// Widgets with their own placeholder (see
+    /// [`WidgetOptions::placeholder()`]) show that instead.
+    ///
+    /// [`WidgetOptions::placeholder()`]: widgets/struct.WidgetOptions.html#method.placeholder
+    pub fn set_placeholder(&mut self, text: Text) {
+        self.placeholder = Some(text);
+    }
+
+    /// Briefly shows each widget error at the right of the bar, with the given
+    /// [`Attributes`], e.g. `⚠ clock: Failed to read file`, so that problems
+    /// are noticed without reading the logs. Each error is shown for
+    /// `duration`.
+    ///
+    /// Errors are only shown for widgets which are restarted (or not)
+    /// according to their [`RestartPolicy`], rather than stopping Cnx.
+    ///
+    /// [`Attributes`]: text/struct.Attributes.html
+    /// [`RestartPolicy`]: widgets/enum.RestartPolicy.html
+    pub fn flash_errors(&mut self, attr: Attributes, duration: Duration) {
+        self.error_flash = Some((attr, duration));
+    }
+
+    /// Sets the appearance of the section of widgets in `region` of the bar.
+    ///
+    /// The widgets in each [`Region`] make up a section, which spans them.
+    /// The [`SectionStyle`] controls the background drawn behind the
+    /// section's widgets, the padding around them and the rounding of the
+    /// background's corners. Regions without a style, or without any widgets
+    /// to show, have no section drawn.
+    ///
+    /// [`Region`]: enum.Region.html
+    /// [`SectionStyle`]: text/struct.SectionStyle.html
+    pub fn set_section_style(&mut self, region: Region, style: SectionStyle) {
+        self.bar_options.section_styles[region as usize] = Some(style);
+    }
+
+    /// Sets the height of the bar, in pixels.
+    ///
+    /// By default, the bar is as tall as its tallest text (plus the biggest
+    /// padding of its [`SectionStyle`]s). In a taller bar, the texts are centered
+    /// vertically.
+    ///
+    /// [`SectionStyle`]: text/struct.SectionStyle.html
+    pub fn set_height(&mut self, height: u16) {
+        self.bar_options.height = Some(height);
+    }
+
+    /// Leaves `margins` between each bar and the edges of its monitor, e.g.
+    /// for a floating bar with gaps around it.
+    ///
+    /// The space reserved with the WM includes the margins above and below
+    /// the bar (or beside it, for a vertical bar), so windows don't cover
+    /// them.
+    pub fn set_margins(&mut self, margins: Padding) {
+        self.bar_options.margins = margins;
+    }
+
+    /// Leaves `spacing` pixels between neighbouring widgets in the same
+    /// [`Region`] of the bar, which defaults to none.
+    ///
+    /// [`Region`]: enum.Region.html
+    pub fn set_spacing(&mut self, spacing: f64) {
+        self.bar_options.spacing = spacing;
+    }
+
+    /// Sets the color drawn behind everything on the bar, which defaults to
+    /// black.
+    ///
+    /// While a compositing manager is running, the bar is created with an
+    /// alpha channel, so a translucent background (see
+    /// [`Color::with_alpha()`]) lets the desktop show through. Otherwise, the
+    /// background is opaque.
+    ///
+    /// [`Color::with_alpha()`]: text/struct.Color.html#method.with_alpha
+    pub fn set_background(&mut self, color: Color) {
+        self.bar_options.background = color;
+    }
+
+    /// Draws the bars with `palette` instead of their background and section
+    /// style (see [`set_background()`] and [`set_section_style()`]) while the
+    /// desktop prefers a dark color scheme, switching as soon as it changes.
+    ///
+    /// The color scheme is read from the XDG desktop portal, using `busctl`.
+    /// Widgets' own colors aren't changed, but widgets can follow the color
+    /// scheme using [`appearance()`].
+    ///
+    /// [`set_background()`]: #method.set_background
+    /// [`set_section_style()`]: #method.set_section_style
+    /// [`appearance()`]: #method.appearance
+    pub fn set_dark_palette(&mut self, palette: Palette) {
+        self.bar_options.dark_palette = Some(palette);
+    }
+
+    /// Sets how much bigger large mode draws the bars, which defaults to 1.5.
+    ///
+    /// Large mode is switched on and off while Cnx is running, by
+    /// [`Control::set_large_mode()`] or over IPC (see [`enable_ipc()`]), e.g.
+    /// to make the bars readable on a projector during a presentation. It
+    /// multiplies the size of the texts' fonts, their padding, the padding of
+    /// each [`SectionStyle`] and the height set by [`set_height()`] by
+    /// `factor`, regardless of the screen's DPI. Sizes set in a widget's
+    /// markup aren't changed.
+    ///
+    /// [`Control::set_large_mode()`]: struct.Control.html#method.set_large_mode
+    /// [`enable_ipc()`]: #method.enable_ipc
+    /// [`SectionStyle`]: text/struct.SectionStyle.html
+    /// [`set_height()`]: #method.set_height
+    pub fn set_large_mode_scale(&mut self, factor: f64) {
+        self.bar_options.large_mode_scale = Some(factor);
+    }
+
+    /// Sets when Cnx runs in low-power mode.
+    ///
+    /// By default ([`LowPower::Auto`]), low-power mode is used while a
+    /// [`Battery`] widget reports that the battery is discharging. In
+    /// low-power mode, the update interval of every polling widget is
+    /// lengthened by the [low-power factor] and animations are disabled.
+    ///
+    /// [`LowPower::Auto`]: enum.LowPower.html#variant.Auto
+    /// [`Battery`]: widgets/struct.Battery.html
+    /// [low-power factor]: #method.set_low_power_factor
+    pub fn set_low_power(&mut self, low_power: LowPower) {
+        self.power.set_mode(low_power);
+    }
+
+    /// Sets how many times longer polling widgets wait between updates in
+    /// low-power mode. Defaults to 3.
+    ///
+    /// Widgets which update at a particular time (such as the [`Clock`]) or
+    /// which wait for events rather than polling are unaffected.
+    ///
+    /// [`Clock`]: widgets/struct.Clock.html
+    pub fn set_low_power_factor(&mut self, factor: u32) {
+        self.power.set_factor(factor);
+    }
+
+    /// Lets the update interval of every polling widget adapt to how often
+    /// its values change, which is off by default.
+    ///
+    /// Each time a widget updates without anything having changed, its
+    /// interval grows by half, up to `max` times its usual interval. Each
+    /// time something has changed, its interval halves, down to `min` times
+    /// its usual interval. So widgets whose values rarely change (such as
+    /// [`DiskTemperature`] while the disk is idle) wake Cnx up less often,
+    /// while those whose values are changing quickly keep up with them. For
+    /// example, with a `min` of 0.5 and a `max` of 8, a widget which usually
+    /// updates every minute does so between every 30 seconds and every 8
+    /// minutes. `min` is at least 0.1, and `max` at most 1000.
+    ///
+    /// As with low-power mode (see [`set_low_power()`]), widgets which update
+    /// at a particular time or wait for events are unaffected.
+    ///
+    /// [`DiskTemperature`]: widgets/struct.DiskTemperature.html
+    /// [`set_low_power()`]: #method.set_low_power
+    pub fn set_adaptive_polling(&mut self, min: f64, max: f64) {
+        self.power.set_adaptive(min, max);
+    }
+
+    /// Sets which monitors Cnx shows a bar on.
+    ///
+    /// By default, a single bar spans the whole X screen. With multiple
+    /// monitors, [`Monitors::All`] shows a separate bar on each connected
+    /// monitor instead, each showing the same widgets. Individual widgets can
+    /// be restricted to a single monitor's bar using
+    /// [`WidgetOptions::monitor()`].
+    ///
+    /// Monitors are discovered once, when [`run()`] is called. Monitors which
+    /// are connected or rearranged later won't get a bar until Cnx restarts.
+    ///
+    /// [`Monitors::All`]: enum.Monitors.html#variant.All
+    /// [`WidgetOptions::monitor()`]: widgets/struct.WidgetOptions.html#method.monitor
+    /// [`run()`]: #method.run
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use cnx::{Cnx, Monitors, Position};
+    /// # fn run() -> ::cnx::Result<()> {
+    /// let mut cnx = Cnx::new(Position::Top)?;
+    /// cnx.set_monitors(Monitors::All);
+    /// # Ok(())
+    /// # }
+    /// # fn main() { run().unwrap(); }
+    /// ```
+    pub fn set_monitors(&mut self, monitors: Monitors) {
+        self.monitors = monitors;
+    }
+
+    /// Shows the bars on the `screen`th X screen, rather than the default
+    /// screen of `$DISPLAY`.
+    ///
+    /// This is only useful with a multi-screen (Zaphod) setup. Widgets which
+    /// watch the WM (e.g. the [`Pager`]) still watch the default screen.
+    ///
+    /// [`Pager`]: widgets/struct.Pager.html
+    pub fn set_screen(&mut self, screen: usize) {
+        self.bar_options.screen = Some(screen);
+    }
+
+    /// Returns a [`Control`], which can be used to refresh, hide or show named
+    /// widgets, or to hide and show the bars, once Cnx is running.
+    ///
+    /// [`Control`]: struct.Control.html
+    pub fn control(&self) -> Control {
+        self.control.clone()
+    }
+
+    /// Allows other processes to control Cnx, by writing commands to a Unix
+    /// socket.
+    ///
+    /// The socket is created at `$XDG_RUNTIME_DIR/cnx.sock` when Cnx starts
+    /// running. Each line written to it is a command, which acts on the named
+    /// widget (see [`WidgetOptions::name()`]) or on the bars:
+    ///
+    ///  - `refresh <name>` updates the widget immediately.
+    ///  - `hide <name>` hides the widget.
+    ///  - `show <name>` shows the widget again.
+    ///  - `bar hide`, `bar show` and `bar toggle` hide or show the bars.
+    ///  - `scratchpad hide`, `scratchpad show` and `scratchpad toggle` hide or
+    ///    show the scratchpad bars (see [`add_scratchpad_widget()`]).
+    ///  - `theme dark` and `theme light` switch the bars' palette, as if the
+    ///    desktop's color scheme had changed (see [`set_dark_palette()`]).
+    ///  - `large on`, `large off` and `large toggle` switch large mode (see
+    ///    [`set_large_mode_scale()`]).
+    ///  - `stopwatch <action> <name>` starts, stops, toggles or resets the
+    ///    [`Stopwatch`] widget, where the action is `start`, `stop`, `toggle`
+    ///    or `reset`.
+    ///
+    /// For example, after running `pacman`:
+    ///
+    /// ```sh
+    /// echo "refresh updates" | socat - UNIX-CONNECT:$XDG_RUNTIME_DIR/cnx.sock
+    /// ```
+    ///
+    /// Commands can also be written as JSON objects, one per line, which are
+    /// each answered with `{"ok": true}`, or `{"ok": false, "error": "..."}` if
+    /// the command failed. Their `command` is one of `refresh`, `hide` and
+    /// `show`, with the `widget`'s name; `hide_bar`, `show_bar`, `toggle_bar`,
+    /// `hide_scratchpad`, `show_scratchpad` and `toggle_scratchpad`; `set_theme`
+    /// with a `theme` of `dark` or `light`; `large_mode_on`, `large_mode_off`
+    /// and `toggle_large_mode`; or `set_text`, which shows the
+    /// `text` on the [`Custom`] widget of that `widget` name; or `stopwatch`,
+    /// with a `widget` and an `action`. For example:
+    ///
+    /// ```sh
+    /// echo '{"command": "set_text", "widget": "vpn", "text": "🔒 VPN"}' \
+    ///     | socat - UNIX-CONNECT:$XDG_RUNTIME_DIR/cnx.sock
+    /// ```
+    ///
+    /// [`WidgetOptions::name()`]: widgets/struct.WidgetOptions.html#method.name
+    /// [`add_scratchpad_widget()`]: #method.add_scratchpad_widget
+    /// [`set_dark_palette()`]: #method.set_dark_palette
+    /// [`set_large_mode_scale()`]: #method.set_large_mode_scale
+    /// [`Custom`]: widgets/struct.Custom.html
+    /// [`Stopwatch`]: widgets/struct.Stopwatch.html
+    pub fn enable_ipc(&mut self) {
+        self.ipc_socket = Some(ipc::default_socket_path());
+    }
+
+    /// Writes the text of each named widget (see [`WidgetOptions::name()`])
+    /// to its own FIFO, so that other bars and scripts can show it.
+    ///
+    /// The FIFOs are created in `$XDG_RUNTIME_DIR/cnx/`, named after the
+    /// widgets. Each time a widget updates, its text is written to its FIFO
+    /// as a line, without any markup. This works both with Cnx's own bars and
+    /// without them (see [`set_root_name_output()`]), e.g. to use Cnx's
+    /// widgets from xmobar's `PipeReader`:
+    ///
+    /// ```haskell
+    /// Run PipeReader "/run/user/1000/cnx/volume" "volume"
+    /// ```
+    ///
+    /// Updates are dropped while nobody reads a FIFO and it is full, so a
+    /// reader may see a few old lines before the latest.
+    ///
+    /// [`WidgetOptions::name()`]: widgets/struct.WidgetOptions.html#method.name
+    /// [`set_root_name_output()`]: #method.set_root_name_output
+    pub fn enable_fifo_output(&mut self) {
+        self.fifo_dir = Some(fifo::default_dir());
+    }
+
+    /// Reloads the widgets each time the file at `path` changes, replacing
+    /// them with those added by `reload`, without restarting the bars.
+    ///
+    /// `reload` is called with a `Cnx` instance which has the same options as
+    /// this one, but no widgets, and should add the widgets just as they were
+    /// added to begin with, e.g. by reading them from `path`. Changes it makes
+    /// to the bars' colors (e.g. with [`set_background()`]) and spacing are
+    /// shown too, but the bars' size, position and monitors only change when
+    /// Cnx is restarted. If `reload` fails, its error is logged and the
+    /// existing widgets and options are kept. Each reloaded widget keeps
+    /// showing the texts of the widget it replaces (if that had the same
+    /// name and region) until it has something to show.
+    ///
+    /// [`from_config()`] reloads its config file in this way.
+    ///
+    /// [`set_background()`]: #method.set_background
+    /// [`from_config()`]: #method.from_config
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use cnx::*;
+    /// # use cnx::text::*;
+    /// # use cnx::widgets::*;
+    /// #
+    /// // Shows the clock in whichever font is in `~/.config/cnx/font`.
+    /// fn add_widgets(cnx: &mut Cnx) -> Result<()> {
+    ///     let font = std::fs::read_to_string("/home/user/.config/cnx/font")?;
+    ///     let attr = Attributes {
+    ///         font: Font::new(font.trim()),
+    ///         fg_color: Color::white(),
+    ///         bg_color: None,
+    ///         padding: Padding::new(8.0, 8.0, 0.0, 0.0),
+    ///         border: None,
+    ///     };
+    ///     let clock = Clock::new(cnx, "%H:%M".to_owned(), attr);
+    ///     cnx.add_widget(clock);
+    ///     Ok(())
+    /// }
+    ///
+    /// # fn run() -> ::cnx::Result<()> {
+    /// let mut cnx = Cnx::new(Position::Top)?;
+    /// add_widgets(&mut cnx)?;
+    /// cnx.reload_on_change("/home/user/.config/cnx/font", add_widgets);
+    /// cnx.run()?;
+    /// # Ok(())
+    /// # }
+    /// # fn main() { run().unwrap(); }
+    /// ```
+    pub fn reload_on_change<P, F>(&mut self, path: P, reload: F)
+    where
+        P: AsRef<Path>,
+        F: FnMut(&mut Cnx) -> Result<()> + 'static,
     {
-        self.widgets.push(Box::new(widget) as Box<dyn Widget>);
+        self.reload = Some((path.as_ref().to_owned(), Box::new(reload)));
     }
 
     /// Runs the Cnx instance.
     ///
     /// This method takes ownership of the Cnx instance and runs it until either
     /// the process is terminated, or an internal error is returned.
+    ///
+    /// A widget which fails is shown as a placeholder and restarted, rather
+    /// than stopping Cnx, unless its [`RestartPolicy`] says otherwise.
+    ///
+    /// [`RestartPolicy`]: widgets/enum.RestartPolicy.html
     pub fn run(mut self) -> Result<()> {
         let handle = self.handle();
-        self.core
-            .run(self.bar.run_event_loop(&handle, self.widgets)?)
+        if let Some(separator) = self.root_name_separator.take() {
+            return self.run_root_name(separator);
+        }
+        let mut bars = Bar::for_monitors(&self.position, &self.monitors, &self.bar_options)?;
+        if let Some(timeout) = self.auto_hide {
+            for bar in &mut bars {
+                bar.make_auto_hide(timeout)?;
+            }
+        }
+        if self.widgets.iter().any(|(_, options)| options.scratchpad) {
+            let scratchpads = Bar::for_monitors(&self.position, &self.monitors, &self.bar_options)?;
+            for mut scratchpad in scratchpads {
+                scratchpad.make_scratchpad();
+                bars.push(scratchpad);
+            }
+        }
+        if self.click_through {
+            for bar in &mut bars {
+                bar.make_click_through();
+            }
+        }
+        if self.rotate_text {
+            for bar in &mut bars {
+                bar.rotate_text();
+            }
+        }
+        self.apply_placeholder();
+        self.apply_fifo_output();
+        if let Some(ref path) = self.ipc_socket {
+            ipc::listen(&handle, path, self.control.clone())?;
+        }
+        let color_schemes = match self.bar_options.dark_palette {
+            Some(_) => match self.appearance.watch() {
+                Ok(schemes) => Some(schemes),
+                Err(e) => {
+                    warn!("The bars won't follow the desktop's color scheme: {}", e);
+                    None
+                }
+            },
+            None => None,
+        };
+        let keyboard = match self.keyboard_hotkey {
+            Some(ref hotkey) => match KeyboardNav::new(&bars, Hotkey::parse(hotkey)?) {
+                Ok(keyboard) => Some(keyboard),
+                Err(e) => {
+                    warn!("The bar can't be navigated with the keyboard: {}", e);
+                    None
+                }
+            },
+            None => None,
+        };
+        let reloads = match self.reload.take() {
+            Some((path, reload)) => match reload::watch(&self, path, reload) {
+                Ok(reloads) => Some(reloads),
+                Err(e) => {
+                    warn!("The widgets won't be reloaded: {}", e);
+                    None
+                }
+            },
+            None => None,
+        };
+        let mut core = self
+            .core
+            .take()
+            .ok_or_else(|| format_err!("Cnx is already running"))?;
+        core.run(bar::run_event_loop(
+            &handle,
+            self.timer,
+            bars,
+            self.widgets,
+            self.commands,
+            self.scratchpad_timeout,
+            self.error_flash,
+            color_schemes,
+            reloads,
+            keyboard,
+        )?)
+    }
+
+    /// Runs the Cnx instance without any bars, setting the root window's name
+    /// instead. See `set_root_name_output()`.
+    fn run_root_name(mut self, separator: String) -> Result<()> {
+        self.apply_placeholder();
+        self.apply_fifo_output();
+        if let Some(ref path) = self.ipc_socket {
+            ipc::listen(&self.handle, path, self.control.clone())?;
+        }
+        let mut core = self
+            .core
+            .take()
+            .ok_or_else(|| format_err!("Cnx is already running"))?;
+        core.run(root_name::run_event_loop(
+            self.timer,
+            self.widgets,
+            self.commands,
+            self.error_flash,
+            separator,
+        )?)
+    }
+
+    /// Writes the text of each named widget to its FIFO, if
+    /// `enable_fifo_output()` was called.
+    fn apply_fifo_output(&mut self) {
+        let dir = match self.fifo_dir {
+            Some(ref dir) => dir,
+            None => return,
+        };
+        self.widgets = std::mem::take(&mut self.widgets)
+            .into_iter()
+            .map(|(widget, mut options)| {
+                let name = match options.name {
+                    Some(ref name) => name.clone(),
+                    None => return (widget, options),
+                };
+                let dir = dir.clone();
+                let wrap = move |inner| -> Box<dyn Widget> {
+                    Box::new(fifo::FifoOutput {
+                        inner,
+                        dir: dir.clone(),
+                        name: name.clone(),
+                    })
+                };
+                // Restarted instances are written to the FIFO too.
+                options.factory = options
+                    .factory
+                    .take()
+                    .map(|factory| factory.map(wrap.clone()));
+                (wrap(widget), options)
+            })
+            .collect();
+    }
+
+    /// Shows the placeholder set with `set_placeholder()` for each widget
+    /// without one of its own.
+    fn apply_placeholder(&mut self) {
+        if let Some(ref placeholder) = self.placeholder {
+            for (_, options) in &mut self.widgets {
+                options
+                    .placeholder
+                    .get_or_insert_with(|| placeholder.clone());
+            }
+        }
     }
 }
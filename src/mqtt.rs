@@ -0,0 +1,304 @@
+//! A minimal MQTT 3.1.1 client, which can only subscribe to topics.
+//!
+//! Messages are received at QoS 0 (at most once), which is all that a widget
+//! showing the latest value of a sensor needs.
+
+use std::io;
+use std::time::Duration;
+
+use futures::{stream, try_ready, Async, Future, Poll, Stream};
+use log::debug;
+use tokio_core::reactor::Handle;
+use tokio_io::AsyncRead;
+
+use crate::error::{format_err, Error};
+use crate::http;
+use crate::timer::Timer;
+use crate::Result;
+
+const DEFAULT_PORT: u16 = 1883;
+const KEEP_ALIVE: Duration = Duration::from_secs(60);
+const PINGREQ: &[u8] = &[0xc0, 0x00];
+
+/// A message published to a topic which we subscribed to.
+pub(crate) type Messages = Box<dyn Stream<Item = (String, Vec<u8>), Error = Error>>;
+
+#[derive(Debug, PartialEq)]
+enum Packet {
+    ConnAck { return_code: u8 },
+    Publish { topic: String, payload: Vec<u8> },
+    // Packets which we don't need to do anything with, e.g. SUBACK and
+    // PINGRESP.
+    Other(u8),
+}
+
+fn push_length(packet: &mut Vec<u8>, mut length: usize) {
+    loop {
+        let mut byte = (length % 128) as u8;
+        length /= 128;
+        if length > 0 {
+            byte |= 0x80;
+        }
+        packet.push(byte);
+        if length == 0 {
+            break;
+        }
+    }
+}
+
+fn push_string(packet: &mut Vec<u8>, s: &str) {
+    packet.extend_from_slice(&(s.len() as u16).to_be_bytes());
+    packet.extend_from_slice(s.as_bytes());
+}
+
+/// Prefixes `body` with the fixed header for a packet of type `header`.
+fn packet(header: u8, body: &[u8]) -> Vec<u8> {
+    let mut packet = vec![header];
+    push_length(&mut packet, body.len());
+    packet.extend_from_slice(body);
+    packet
+}
+
+fn encode_connect(client_id: &str, credentials: Option<&(String, String)>) -> Vec<u8> {
+    let mut body = Vec::new();
+    push_string(&mut body, "MQTT");
+    // Protocol level 4 is MQTT 3.1.1.
+    body.push(4);
+    // Always start a clean session, as we don't want messages from while we
+    // were disconnected.
+    let mut flags = 0x02;
+    if credentials.is_some() {
+        flags |= 0x80 | 0x40;
+    }
+    body.push(flags);
+    body.extend_from_slice(&(KEEP_ALIVE.as_secs() as u16).to_be_bytes());
+    push_string(&mut body, client_id);
+    if let Some((username, password)) = credentials {
+        push_string(&mut body, username);
+        push_string(&mut body, password);
+    }
+    packet(0x10, &body)
+}
+
+fn encode_subscribe(topics: &[String]) -> Vec<u8> {
+    // We only ever send one SUBSCRIBE, so the packet identifier doesn't
+    // matter.
+    let mut body = vec![0x00, 0x01];
+    for topic in topics {
+        push_string(&mut body, topic);
+        body.push(0);
+    }
+    packet(0x82, &body)
+}
+
+/// Decodes the packet at the start of `buf`, returning it and its length, or
+/// `None` if the whole packet hasn't been received yet.
+fn decode(buf: &[u8]) -> Result<Option<(Packet, usize)>> {
+    let mut length = 0;
+    let mut header_len = 1;
+    loop {
+        let byte = match buf.get(header_len) {
+            Some(&byte) => byte,
+            None => return Ok(None),
+        };
+        length |= usize::from(byte & 0x7f) << (7 * (header_len - 1));
+        header_len += 1;
+        if byte & 0x80 == 0 {
+            break;
+        }
+        if header_len > 4 {
+            return Err(format_err!("Invalid MQTT packet length"));
+        }
+    }
+    if buf.len() < header_len + length {
+        return Ok(None);
+    }
+
+    let body = &buf[header_len..header_len + length];
+    let packet = match buf[0] >> 4 {
+        2 if body.len() == 2 => Packet::ConnAck {
+            return_code: body[1],
+        },
+        3 => {
+            if body.len() < 2 {
+                return Err(format_err!("Truncated MQTT PUBLISH"));
+            }
+            let topic_len = usize::from(u16::from_be_bytes([body[0], body[1]]));
+            // QoS 1 and 2 messages have a packet identifier after the topic.
+            let qos = (buf[0] >> 1) & 0x03;
+            let payload_start = 2 + topic_len + if qos > 0 { 2 } else { 0 };
+            if body.len() < payload_start {
+                return Err(format_err!("Truncated MQTT PUBLISH"));
+            }
+            Packet::Publish {
+                topic: String::from_utf8_lossy(&body[2..2 + topic_len]).into_owned(),
+                payload: body[payload_start..].to_vec(),
+            }
+        }
+        kind => Packet::Other(kind),
+    };
+    Ok(Some((packet, header_len + length)))
+}
+
+/// Whether `topic` matches the subscription `filter`, which may contain `+`
+/// and `#` wildcards.
+pub(crate) fn topic_matches(filter: &str, topic: &str) -> bool {
+    let mut topic_levels = topic.split('/');
+    for level in filter.split('/') {
+        match (level, topic_levels.next()) {
+            ("#", _) => return true,
+            ("+", Some(_)) => {}
+            (level, Some(topic_level)) if level == topic_level => {}
+            _ => return false,
+        }
+    }
+    topic_levels.next().is_none()
+}
+
+/// The packets received from a broker.
+struct Packets<R> {
+    reader: R,
+    buf: Vec<u8>,
+}
+
+impl<R: AsyncRead> Stream for Packets<R> {
+    type Item = Packet;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Packet>, Error> {
+        loop {
+            if let Some((packet, len)) = decode(&self.buf)? {
+                self.buf.drain(..len);
+                return Ok(Async::Ready(Some(packet)));
+            }
+            let mut chunk = [0; 4096];
+            let n = try_ready!(self.reader.poll_read(&mut chunk));
+            if n == 0 {
+                if self.buf.is_empty() {
+                    return Ok(Async::Ready(None));
+                }
+                return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+            }
+            self.buf.extend_from_slice(&chunk[..n]);
+        }
+    }
+}
+
+/// Connects to the broker at `address` (`host` or `host:port`) and
+/// subscribes to `topics`, returning the messages published to them.
+///
+/// The stream ends (or fails) when the connection is lost. It doesn't
+/// reconnect, so that callers can decide how long to wait before trying
+/// again.
+pub(crate) fn subscribe(
+    handle: &Handle,
+    timer: &Timer,
+    address: &str,
+    credentials: Option<&(String, String)>,
+    topics: &[String],
+) -> Result<Messages> {
+    let with_port = if address.contains(':') {
+        address.to_owned()
+    } else {
+        format!("{}:{}", address, DEFAULT_PORT)
+    };
+    let client_id = format!("cnx-{}", std::process::id());
+    let connect = encode_connect(&client_id, credentials);
+    let subscribe = encode_subscribe(topics);
+    let timer = timer.clone();
+    debug!("Connecting to MQTT broker at {}", with_port);
+
+    let handle = handle.clone();
+    let messages = http::resolve(&with_port)
+        .and_then(move |addresses| http::connect(&handle, addresses))
+        .and_then(move |socket| tokio_io::io::write_all(socket, connect).from_err())
+        .and_then(|(socket, _)| {
+            let (reader, writer) = socket.split();
+            let packets = Packets {
+                reader,
+                buf: Vec::new(),
+            };
+            packets
+                .into_future()
+                .map_err(|(e, _)| e)
+                .and_then(|(packet, packets)| match packet {
+                    Some(Packet::ConnAck { return_code: 0 }) => Ok((packets, writer)),
+                    Some(Packet::ConnAck { return_code }) => Err(format_err!(
+                        "MQTT broker refused connection (return code {})",
+                        return_code
+                    )),
+                    packet => Err(format_err!("Expected MQTT CONNACK, got {:?}", packet)),
+                })
+        })
+        .and_then(move |(packets, writer)| {
+            tokio_io::io::write_all(writer, subscribe)
+                .map_err(Error::from)
+                .map(|(writer, _)| (packets, writer))
+        })
+        .map(move |(packets, writer)| {
+            // The broker disconnects us if we're quiet for longer than the
+            // keep alive, so ping it regularly.
+            let pings = stream::unfold(writer, move |writer| {
+                Some(
+                    timer
+                        .sleep(KEEP_ALIVE / 2)
+                        .map_err(Error::from)
+                        .and_then(|()| tokio_io::io::write_all(writer, PINGREQ).from_err())
+                        .map(|(writer, _)| (Some(None), writer)),
+                )
+            });
+            // The pings never end, so the end of the packets is marked with
+            // `None`, to stop pinging once the broker closes the connection.
+            let publishes = packets
+                .map(|packet| match packet {
+                    Packet::Publish { topic, payload } => Some(Some((topic, payload))),
+                    _ => Some(None),
+                })
+                .chain(stream::once(Ok(None)));
+            publishes
+                .select(pings)
+                .take_while(|message| Ok(message.is_some()))
+                .filter_map(Option::flatten)
+        })
+        .flatten_stream();
+
+    Ok(Box::new(messages))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{decode, encode_subscribe, topic_matches, Packet};
+
+    #[test]
+    fn works() {
+        assert_eq!(
+            encode_subscribe(&["a/b".to_owned()]),
+            vec![0x82, 8, 0, 1, 0, 3, b'a', b'/', b'b', 0]
+        );
+
+        let publish = [0x30, 7, 0, 3, b'a', b'/', b'b', b'4', b'2', 0xd0];
+        assert_eq!(
+            decode(&publish).unwrap(),
+            Some((
+                Packet::Publish {
+                    topic: "a/b".to_owned(),
+                    payload: b"42".to_vec(),
+                },
+                9
+            ))
+        );
+        assert_eq!(decode(&publish[..5]).unwrap(), None);
+        assert_eq!(
+            decode(&[0x20, 2, 0, 5]).unwrap(),
+            Some((Packet::ConnAck { return_code: 5 }, 4))
+        );
+
+        assert!(topic_matches(
+            "home/+/temperature",
+            "home/kitchen/temperature"
+        ));
+        assert!(topic_matches("home/#", "home/kitchen/temperature"));
+        assert!(!topic_matches("home/+", "home/kitchen/temperature"));
+        assert!(!topic_matches("home/kitchen", "home/hall"));
+    }
+}
@@ -0,0 +1,532 @@
+//! A shared netlink monitor, for widgets which want to know about network or
+//! device changes.
+//!
+//! Rather than each widget opening its own netlink socket (and parsing its
+//! messages), widgets [`subscribe()`] to the events they are interested in.
+//! Cnx opens at most one socket per [`Family`], when it is first subscribed
+//! to, and dispatches each event to every subscriber whose filter accepts it.
+//!
+//! [`subscribe()`]: struct.Netlink.html#method.subscribe
+//! [`Family`]: enum.Family.html
+
+use std::cell::RefCell;
+use std::io;
+use std::mem;
+use std::os::unix::io::RawFd;
+use std::rc::Rc;
+
+use futures::unsync::mpsc::{self, UnboundedSender};
+use futures::{Async, Future, Poll, Stream};
+use log::{debug, error};
+use mio::event::Evented;
+use mio::unix::EventedFd;
+use mio::{PollOpt, Ready, Token};
+use tokio_core::reactor::{Handle, PollEvented};
+
+use crate::error::{format_err, Error};
+use crate::Result;
+
+// Multicast groups of NETLINK_ROUTE, from <linux/rtnetlink.h>.
+const RTMGRP_LINK: u32 = 0x1;
+const RTMGRP_IPV4_IFADDR: u32 = 0x10;
+const RTMGRP_IPV4_ROUTE: u32 = 0x40;
+const RTMGRP_IPV6_IFADDR: u32 = 0x100;
+const RTMGRP_IPV6_ROUTE: u32 = 0x400;
+
+// The multicast group of NETLINK_KOBJECT_UEVENT used by the kernel (rather
+// than by udev).
+const UEVENT_KERNEL_GROUP: u32 = 0x1;
+
+const RTM_NEWLINK: u16 = 16;
+const RTM_DELLINK: u16 = 17;
+const RTM_NEWADDR: u16 = 20;
+const RTM_DELADDR: u16 = 21;
+const RTM_NEWROUTE: u16 = 24;
+const RTM_DELROUTE: u16 = 25;
+
+const NLMSG_HEADER_LEN: usize = 16;
+const IFINFOMSG_LEN: usize = 16;
+const IFADDRMSG_LEN: usize = 8;
+const IFLA_IFNAME: u16 = 3;
+const IFF_UP: u32 = 0x1;
+const IFF_RUNNING: u32 = 0x40;
+
+/// The netlink families which can be subscribed to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Family {
+    /// Changes to network interfaces, their addresses and the routing table
+    /// (`NETLINK_ROUTE`).
+    Route,
+    /// The kernel's device events (`NETLINK_KOBJECT_UEVENT`), e.g. a USB
+    /// device being plugged in or a charger being connected.
+    Uevent,
+}
+
+/// An event received from netlink.
+#[derive(Clone, Debug, PartialEq)]
+pub enum NetlinkEvent {
+    /// A network interface was added, changed or removed.
+    Link {
+        /// The index of the interface.
+        index: u32,
+        /// The name of the interface, e.g. `wlan0`, if the kernel sent it.
+        name: Option<String>,
+        /// Whether the interface is up and running.
+        up: bool,
+        /// Whether the interface was removed.
+        removed: bool,
+    },
+    /// An address was added to or removed from a network interface.
+    Address {
+        /// The index of the interface.
+        index: u32,
+        /// Whether the address was removed.
+        removed: bool,
+    },
+    /// A route was added or removed.
+    Route {
+        /// Whether the route was removed.
+        removed: bool,
+    },
+    /// A device event.
+    Uevent {
+        /// What happened to the device, e.g. `add`, `remove` or `change`.
+        action: String,
+        /// The path of the device, under `/sys`.
+        devpath: String,
+        /// The subsystem of the device, e.g. `usb` or `power_supply`.
+        subsystem: String,
+        /// All of the event's variables, including those above.
+        vars: Vec<(String, String)>,
+    },
+}
+
+impl NetlinkEvent {
+    /// Gets a variable of a `Uevent`, e.g. `PRODUCT`.
+    pub fn var(&self, key: &str) -> Option<&str> {
+        match *self {
+            NetlinkEvent::Uevent { ref vars, .. } => {
+                vars.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str())
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The stream of events returned by [`Netlink::subscribe()`].
+///
+/// [`Netlink::subscribe()`]: struct.Netlink.html#method.subscribe
+pub type NetlinkEvents = Box<dyn Stream<Item = NetlinkEvent, Error = Error>>;
+
+/// Reads a native-endian `u16` at `offset` in `bytes`.
+fn u16_at(bytes: &[u8], offset: usize) -> Option<u16> {
+    let bytes = bytes.get(offset..offset + 2)?;
+    Some(u16::from_ne_bytes([bytes[0], bytes[1]]))
+}
+
+/// Reads a native-endian `u32` at `offset` in `bytes`.
+fn u32_at(bytes: &[u8], offset: usize) -> Option<u32> {
+    let bytes = bytes.get(offset..offset + 4)?;
+    Some(u32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn align(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+/// Parses the `IFLA_IFNAME` attribute from a link message's attributes.
+fn link_name(mut attrs: &[u8]) -> Option<String> {
+    while let (Some(len), Some(kind)) = (u16_at(attrs, 0), u16_at(attrs, 2)) {
+        let len = len as usize;
+        if len < 4 || len > attrs.len() {
+            break;
+        }
+        if kind == IFLA_IFNAME {
+            let value = &attrs[4..len];
+            let value = value.split(|&b| b == 0).next().unwrap_or(value);
+            return Some(String::from_utf8_lossy(value).into_owned());
+        }
+        attrs = &attrs[align(len).min(attrs.len())..];
+    }
+    None
+}
+
+/// Parses a buffer of `NETLINK_ROUTE` messages, ignoring any which aren't
+/// about links, addresses or routes.
+fn parse_route_messages(mut buf: &[u8]) -> Vec<NetlinkEvent> {
+    let mut events = Vec::new();
+    while let (Some(len), Some(kind)) = (u32_at(buf, 0), u16_at(buf, 4)) {
+        let len = len as usize;
+        if len < NLMSG_HEADER_LEN || len > buf.len() {
+            break;
+        }
+        let payload = &buf[NLMSG_HEADER_LEN..len];
+        let event = match kind {
+            RTM_NEWLINK | RTM_DELLINK if payload.len() >= IFINFOMSG_LEN => {
+                let index = u32_at(payload, 4).unwrap_or(0);
+                let flags = u32_at(payload, 8).unwrap_or(0);
+                Some(NetlinkEvent::Link {
+                    index,
+                    name: link_name(&payload[IFINFOMSG_LEN..]),
+                    up: flags & IFF_UP != 0 && flags & IFF_RUNNING != 0,
+                    removed: kind == RTM_DELLINK,
+                })
+            }
+            RTM_NEWADDR | RTM_DELADDR if payload.len() >= IFADDRMSG_LEN => {
+                Some(NetlinkEvent::Address {
+                    index: u32_at(payload, 4).unwrap_or(0),
+                    removed: kind == RTM_DELADDR,
+                })
+            }
+            RTM_NEWROUTE | RTM_DELROUTE => Some(NetlinkEvent::Route {
+                removed: kind == RTM_DELROUTE,
+            }),
+            _ => None,
+        };
+        events.extend(event);
+        buf = &buf[align(len).min(buf.len())..];
+    }
+    events
+}
+
+/// Parses a kernel uevent, which is a header (`<action>@<devpath>`) followed
+/// by nul-separated `KEY=VALUE` variables.
+fn parse_uevent(buf: &[u8]) -> Option<NetlinkEvent> {
+    let mut fields = buf
+        .split(|&b| b == 0)
+        .filter(|field| !field.is_empty())
+        .map(String::from_utf8_lossy);
+    if !fields.next()?.contains('@') {
+        // Not a kernel uevent (e.g. a message from udev).
+        return None;
+    }
+    let vars = fields
+        .filter_map(|field| {
+            let mut parts = field.splitn(2, '=');
+            Some((parts.next()?.to_owned(), parts.next()?.to_owned()))
+        })
+        .collect::<Vec<_>>();
+    let var = |key| {
+        vars.iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.clone())
+            .unwrap_or_default()
+    };
+    Some(NetlinkEvent::Uevent {
+        action: var("ACTION"),
+        devpath: var("DEVPATH"),
+        subsystem: var("SUBSYSTEM"),
+        vars,
+    })
+}
+
+/// A non-blocking netlink socket.
+struct Socket(RawFd);
+
+impl Socket {
+    fn open(protocol: i32, groups: u32) -> io::Result<Socket> {
+        unsafe {
+            let fd = libc::socket(
+                libc::AF_NETLINK,
+                libc::SOCK_RAW | libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC,
+                protocol,
+            );
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let socket = Socket(fd);
+
+            let mut addr: libc::sockaddr_nl = mem::zeroed();
+            addr.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+            addr.nl_groups = groups;
+            let result = libc::bind(
+                fd,
+                &addr as *const libc::sockaddr_nl as *const libc::sockaddr,
+                mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+            );
+            if result < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(socket)
+        }
+    }
+
+    fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        let len =
+            unsafe { libc::recv(self.0, buf.as_mut_ptr() as *mut libc::c_void, buf.len(), 0) };
+        if len < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(len as usize)
+        }
+    }
+}
+
+impl Drop for Socket {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.0);
+        }
+    }
+}
+
+impl Evented for Socket {
+    fn register(
+        &self,
+        poll: &mio::Poll,
+        token: Token,
+        interest: Ready,
+        opts: PollOpt,
+    ) -> io::Result<()> {
+        EventedFd(&self.0).register(poll, token, interest, opts)
+    }
+
+    fn reregister(
+        &self,
+        poll: &mio::Poll,
+        token: Token,
+        interest: Ready,
+        opts: PollOpt,
+    ) -> io::Result<()> {
+        EventedFd(&self.0).reregister(poll, token, interest, opts)
+    }
+
+    fn deregister(&self, poll: &mio::Poll) -> io::Result<()> {
+        EventedFd(&self.0).deregister(poll)
+    }
+}
+
+/// A stream of the events received on a netlink socket.
+struct SocketStream {
+    poll: PollEvented<Socket>,
+    family: Family,
+    buf: Vec<u8>,
+}
+
+impl Stream for SocketStream {
+    type Item = Vec<NetlinkEvent>;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        if let Async::NotReady = self.poll.poll_read() {
+            return Ok(Async::NotReady);
+        }
+
+        match self.poll.get_ref().recv(&mut self.buf) {
+            Ok(len) => {
+                let buf = &self.buf[..len];
+                let events = match self.family {
+                    Family::Route => parse_route_messages(buf),
+                    Family::Uevent => parse_uevent(buf).into_iter().collect(),
+                };
+                Ok(Async::Ready(Some(events)))
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                self.poll.need_read();
+                Ok(Async::NotReady)
+            }
+            // The kernel drops messages if we fall behind. There's nothing we
+            // can do to recover them, so carry on.
+            Err(ref e) if e.raw_os_error() == Some(libc::ENOBUFS) => {
+                Ok(Async::Ready(Some(Vec::new())))
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+struct Subscriber {
+    family: Family,
+    filter: Box<dyn Fn(&NetlinkEvent) -> bool>,
+    sender: UnboundedSender<NetlinkEvent>,
+}
+
+struct Inner {
+    handle: Handle,
+    subscribers: Vec<Subscriber>,
+    open: Vec<Family>,
+}
+
+/// A handle to Cnx's shared netlink monitor.
+///
+/// Returned by [`Cnx::netlink()`]. See the [module documentation] for more
+/// details.
+///
+/// [`Cnx::netlink()`]: ../struct.Cnx.html#method.netlink
+/// [module documentation]: index.html
+#[derive(Clone)]
+pub struct Netlink {
+    inner: Rc<RefCell<Inner>>,
+}
+
+impl Netlink {
+    pub(crate) fn new(handle: Handle) -> Netlink {
+        Netlink {
+            inner: Rc::new(RefCell::new(Inner {
+                handle,
+                subscribers: Vec::new(),
+                open: Vec::new(),
+            })),
+        }
+    }
+
+    /// Subscribes to the events from `family` which are accepted by `filter`.
+    ///
+    /// The family's socket is opened if this is the first subscription to it.
+    /// The returned stream yields events only while Cnx is running.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use cnx::*;
+    /// # use cnx::netlink::*;
+    /// # fn run() -> ::cnx::Result<()> {
+    /// let cnx = Cnx::new(Position::Top)?;
+    /// let usb_events = cnx.netlink().subscribe(Family::Uevent, |event| match event {
+    ///     NetlinkEvent::Uevent { subsystem, .. } => subsystem == "usb",
+    ///     _ => false,
+    /// })?;
+    /// # Ok(())
+    /// # }
+    /// # fn main() { run().unwrap(); }
+    /// ```
+    pub fn subscribe<F>(&self, family: Family, filter: F) -> Result<NetlinkEvents>
+    where
+        F: Fn(&NetlinkEvent) -> bool + 'static,
+    {
+        let needs_opening = !self.inner.borrow().open.contains(&family);
+        if needs_opening {
+            self.open(family)?;
+        }
+
+        let (sender, receiver) = mpsc::unbounded();
+        self.inner.borrow_mut().subscribers.push(Subscriber {
+            family,
+            filter: Box::new(filter),
+            sender,
+        });
+        Ok(Box::new(
+            receiver.map_err(|()| format_err!("Netlink subscription closed")),
+        ))
+    }
+
+    fn open(&self, family: Family) -> Result<()> {
+        let socket = match family {
+            Family::Route => Socket::open(
+                libc::NETLINK_ROUTE,
+                RTMGRP_LINK
+                    | RTMGRP_IPV4_IFADDR
+                    | RTMGRP_IPV6_IFADDR
+                    | RTMGRP_IPV4_ROUTE
+                    | RTMGRP_IPV6_ROUTE,
+            ),
+            Family::Uevent => Socket::open(libc::NETLINK_KOBJECT_UEVENT, UEVENT_KERNEL_GROUP),
+        }
+        .map_err(|e| format_err!("Failed to open {:?} netlink socket: {}", family, e))?;
+        debug!("Opened {:?} netlink socket", family);
+
+        let mut inner = self.inner.borrow_mut();
+        let stream = SocketStream {
+            poll: PollEvented::new(socket, &inner.handle)?,
+            family,
+            buf: vec![0; 16 * 1024],
+        };
+        let shared = self.inner.clone();
+        let fut = stream
+            .for_each(move |events| {
+                let mut inner = shared.borrow_mut();
+                for event in events {
+                    // Drop any subscribers whose streams have gone away.
+                    inner.subscribers.retain(|subscriber| {
+                        if subscriber.family != family || !(subscriber.filter)(&event) {
+                            return true;
+                        }
+                        subscriber.sender.unbounded_send(event.clone()).is_ok()
+                    });
+                }
+                Ok(())
+            })
+            .map_err(move |e| error!("Error reading {:?} netlink socket: {}", family, e));
+        inner.handle.spawn(fut);
+        inner.open.push(family);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_route_messages, parse_uevent, NetlinkEvent};
+
+    fn push_u16(buf: &mut Vec<u8>, n: u16) {
+        buf.extend_from_slice(&n.to_ne_bytes());
+    }
+
+    fn push_u32(buf: &mut Vec<u8>, n: u32) {
+        buf.extend_from_slice(&n.to_ne_bytes());
+    }
+
+    #[test]
+    fn parses_route_messages() {
+        let mut buf = Vec::new();
+        // RTM_NEWLINK for wlan0 (index 3), which is up and running.
+        push_u32(&mut buf, 16 + 16 + 12);
+        push_u16(&mut buf, 16);
+        push_u16(&mut buf, 0);
+        push_u32(&mut buf, 0);
+        push_u32(&mut buf, 0);
+        buf.extend_from_slice(&[0, 0, 1, 0]);
+        push_u32(&mut buf, 3);
+        push_u32(&mut buf, 0x41);
+        push_u32(&mut buf, 0);
+        push_u16(&mut buf, 10);
+        push_u16(&mut buf, 3);
+        buf.extend_from_slice(b"wlan0\0\0\0");
+        // RTM_DELADDR for index 3.
+        push_u32(&mut buf, 16 + 8);
+        push_u16(&mut buf, 21);
+        push_u16(&mut buf, 0);
+        push_u32(&mut buf, 0);
+        push_u32(&mut buf, 0);
+        buf.extend_from_slice(&[2, 24, 0, 0]);
+        push_u32(&mut buf, 3);
+
+        assert_eq!(
+            parse_route_messages(&buf),
+            vec![
+                NetlinkEvent::Link {
+                    index: 3,
+                    name: Some("wlan0".to_owned()),
+                    up: true,
+                    removed: false,
+                },
+                NetlinkEvent::Address {
+                    index: 3,
+                    removed: true,
+                },
+            ]
+        );
+        // Truncated messages are ignored.
+        assert_eq!(parse_route_messages(&buf[..20]), vec![]);
+    }
+
+    #[test]
+    fn parses_uevents() {
+        let buf = b"change@/devices/LNXSYSTM:00/ACPI0003:00/power_supply/AC\0\
+                    ACTION=change\0DEVPATH=/devices/LNXSYSTM:00/ACPI0003:00/power_supply/AC\0\
+                    SUBSYSTEM=power_supply\0POWER_SUPPLY_ONLINE=1\0SEQNUM=4242\0";
+        let event = parse_uevent(buf).unwrap();
+        match event {
+            NetlinkEvent::Uevent {
+                ref action,
+                ref subsystem,
+                ..
+            } => {
+                assert_eq!(action, "change");
+                assert_eq!(subsystem, "power_supply");
+            }
+            _ => panic!("Expected a uevent: {:?}", event),
+        }
+        assert_eq!(event.var("POWER_SUPPLY_ONLINE"), Some("1"));
+        assert_eq!(parse_uevent(b"libudev\0ACTION=add\0"), None);
+    }
+}
@@ -0,0 +1,74 @@
+//! Colors, as written in configs and by widgets, e.g. `fabd2f`.
+
+use crate::text::ParseColorError;
+
+/// Parses the two hexadecimal digits of a color starting at `start`.
+fn byte(
+    s: &str,
+    start: usize,
+    error: fn(std::num::ParseIntError) -> ParseColorError,
+) -> Result<u8, ParseColorError> {
+    let digits = s.get(start..start + 2).ok_or(ParseColorError::Format)?;
+    // `from_str_radix()` would allow a sign.
+    if digits.starts_with('+') {
+        return Err(ParseColorError::Format);
+    }
+    u8::from_str_radix(digits, 16).map_err(error)
+}
+
+/// Parses a color written as `rrggbb` or `rrggbbaa` in hexadecimal, e.g.
+/// `fabd2f` or `00000080` (translucent black), returning its red, green and
+/// blue bytes, and its alpha byte if it has one.
+pub fn hex_color(s: &str) -> Result<(u8, u8, u8, Option<u8>), ParseColorError> {
+    if !s.is_ascii() || (s.len() != 6 && s.len() != 8) {
+        return Err(ParseColorError::Format);
+    }
+    let alpha = if s.len() == 8 {
+        Some(byte(s, 6, ParseColorError::Alpha)?)
+    } else {
+        None
+    };
+    Ok((
+        byte(s, 0, ParseColorError::Red)?,
+        byte(s, 2, ParseColorError::Green)?,
+        byte(s, 4, ParseColorError::Blue)?,
+        alpha,
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::hex_color;
+    use crate::text::ParseColorError;
+
+    #[test]
+    fn parses_colors() {
+        assert_eq!(hex_color("fabd2f").unwrap(), (0xfa, 0xbd, 0x2f, None));
+        assert_eq!(hex_color("FABD2F").unwrap(), (0xfa, 0xbd, 0x2f, None));
+        assert_eq!(hex_color("00000080").unwrap(), (0, 0, 0, Some(0x80)));
+    }
+
+    #[test]
+    fn rejects_malformed_colors() {
+        let format = |s| matches!(hex_color(s), Err(ParseColorError::Format));
+        for s in ["", "f", "fabd2", "fabd2f0", "fabd2f000", "#fabd2f"] {
+            assert!(format(s), "{:?}", s);
+        }
+        // Six bytes, but not six characters.
+        assert!(format("éabd2"));
+        assert!(format("fa€d"));
+        // Signs, which `u8::from_str_radix()` would allow.
+        assert!(format("+abd2f"));
+        assert!(format("fabd2f+0"));
+        assert!(matches!(hex_color("gabd2f"), Err(ParseColorError::Red(_))));
+        assert!(matches!(
+            hex_color("fab 2f"),
+            Err(ParseColorError::Green(_))
+        ));
+        assert!(matches!(hex_color("fabd-f"), Err(ParseColorError::Blue(_))));
+        assert!(matches!(
+            hex_color("fabd2fzz"),
+            Err(ParseColorError::Alpha(_))
+        ));
+    }
+}
@@ -0,0 +1,145 @@
+//! The values of X properties, as set by the WM and other programs, e.g.
+//! `_NET_DESKTOP_NAMES` or `_NET_WM_ICON`.
+//!
+//! Any program can set any property of any window to anything, so none of
+//! these assume that a property is as the [EWMH spec] says it should be.
+//!
+//! [EWMH spec]: https://specifications.freedesktop.org/wm-spec/wm-spec-latest.html
+
+/// The most desktops which are shown, however many the WM claims there are.
+pub const MAX_DESKTOPS: usize = 1024;
+
+/// Splits a list of nul-separated UTF-8 strings (e.g. `_NET_DESKTOP_NAMES`),
+/// which may or may not end with a nul. Invalid UTF-8 is replaced with `�`.
+pub fn utf8_strings(value: &[u8]) -> Vec<String> {
+    if value.is_empty() {
+        return Vec::new();
+    }
+    let value = value.strip_suffix(b"\0").unwrap_or(value);
+    value
+        .split(|&b| b == 0)
+        .map(|s| String::from_utf8_lossy(s).into_owned())
+        .collect()
+}
+
+/// Joins the non-empty strings in a property's `value` (e.g. `one\0two\0`)
+/// with spaces.
+pub fn join_strings(value: &[u8]) -> String {
+    String::from_utf8_lossy(value)
+        .split('\0')
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Returns the class (the second string) of a `WM_CLASS` value, which is the
+/// instance name and then the class, each nul-terminated.
+pub fn wm_class(value: &[u8]) -> Option<String> {
+    value
+        .split(|&b| b == 0)
+        .nth(1)
+        .filter(|class| !class.is_empty())
+        .map(|class| String::from_utf8_lossy(class).into_owned())
+}
+
+/// Returns one name for each of the `number` desktops, from the `names` in
+/// `_NET_DESKTOP_NAMES`.
+///
+/// EWMH allows there to be more or fewer names than there are desktops
+/// (`_NET_NUMBER_OF_DESKTOPS`), so the names are truncated, or padded with
+/// `"?"` for unnamed desktops. At most `MAX_DESKTOPS` are returned.
+pub fn desktop_names(mut names: Vec<String>, number: u32) -> Vec<String> {
+    let number = (number as usize).min(MAX_DESKTOPS);
+    names.resize(number, "?".to_owned());
+    names
+}
+
+/// Picks the icon out of `_NET_WM_ICON`'s `data` (the width and height of
+/// each of the window's sizes of icon, followed by its pixels) which is the
+/// smallest at least `size` pixels tall, or otherwise the tallest. Returns
+/// its width, height and pixels.
+///
+/// Icons are read until one is truncated, and empty icons are skipped.
+pub fn pick_icon(mut data: &[u32], size: u32) -> Option<(u32, u32, &[u32])> {
+    let mut best: Option<(u32, u32, &[u32])> = None;
+    while let [width, height, ref rest @ ..] = *data {
+        let len = match (width as usize).checked_mul(height as usize) {
+            Some(len) if len <= rest.len() => len,
+            _ => break,
+        };
+        let better = match best {
+            _ if len == 0 => false,
+            None => true,
+            Some((_, best_height, _)) if best_height < size => height > best_height,
+            Some((_, best_height, _)) => height >= size && height < best_height,
+        };
+        if better {
+            best = Some((width, height, &rest[..len]));
+        }
+        data = &rest[len..];
+    }
+    best
+}
+
+#[cfg(test)]
+mod test {
+    use super::{desktop_names, join_strings, pick_icon, utf8_strings, wm_class, MAX_DESKTOPS};
+
+    #[test]
+    fn parses_strings() {
+        assert_eq!(join_strings(b"Tall"), "Tall");
+        assert_eq!(join_strings(b"one\0two\0"), "one two");
+        assert_eq!(join_strings(b"\0\0one\0\0"), "one");
+        assert_eq!(join_strings(b""), "");
+        assert_eq!(join_strings(b"bad\xff"), "bad\u{fffd}");
+
+        assert_eq!(utf8_strings(b"one\0two\0"), ["one", "two"]);
+        assert_eq!(utf8_strings(b"one\0two"), ["one", "two"]);
+        assert_eq!(utf8_strings(b"one\0\0three"), ["one", "", "three"]);
+        assert_eq!(utf8_strings(b"\xc3\0\xe2\x82"), ["\u{fffd}", "\u{fffd}"]);
+        assert!(utf8_strings(b"").is_empty());
+        // Unnamed desktops.
+        assert_eq!(utf8_strings(b"\0"), [""]);
+        assert_eq!(utf8_strings(b"\0\0"), ["", ""]);
+
+        assert_eq!(wm_class(b"urxvt\0URxvt\0").as_deref(), Some("URxvt"));
+        assert_eq!(wm_class(b"urxvt\0URxvt").as_deref(), Some("URxvt"));
+        assert_eq!(wm_class(b"urxvt\0\xff\0").as_deref(), Some("\u{fffd}"));
+        for value in [&b""[..], b"urxvt", b"urxvt\0", b"urxvt\0\0"] {
+            assert_eq!(wm_class(value), None, "{:?}", value);
+        }
+    }
+
+    #[test]
+    fn pads_desktop_names() {
+        let names = || vec!["web".to_owned(), "code".to_owned()];
+        assert_eq!(desktop_names(names(), 1), ["web"]);
+        assert_eq!(desktop_names(names(), 3), ["web", "code", "?"]);
+        assert!(desktop_names(names(), 0).is_empty());
+        assert_eq!(desktop_names(Vec::new(), u32::MAX).len(), MAX_DESKTOPS);
+    }
+
+    #[test]
+    fn picks_icons() {
+        let icons = [&[2, 2][..], &[1; 4], &[1, 1, 2], &[4, 4], &[3; 16]].concat();
+        let height = |data: &[u32], size| pick_icon(data, size).map(|(_, height, _)| height);
+        assert_eq!(height(&icons, 2), Some(2));
+        assert_eq!(height(&icons, 3), Some(4));
+        assert_eq!(height(&icons, 16), Some(4));
+        assert_eq!(pick_icon(&icons, 1), Some((1, 1, &[2][..])));
+        // A truncated icon is ignored.
+        assert_eq!(height(&icons[..12], 16), Some(2));
+        assert_eq!(pick_icon(&[], 16), None);
+        assert_eq!(pick_icon(&[16], 16), None);
+        // Sizes which overflow, or claim more pixels than there are.
+        assert_eq!(pick_icon(&[u32::MAX, u32::MAX, 0], 16), None);
+        assert_eq!(
+            height(&[&[1, 1, 0][..], &[u32::MAX, 2, 0]].concat(), 16),
+            Some(1)
+        );
+        // Empty icons are skipped, rather than stopping the search.
+        assert_eq!(height(&[0, 0, 0, 5, 1, 1], 16), None);
+        assert_eq!(height(&[0, 0, 1, 1, 7], 16), Some(1));
+        assert_eq!(height(&[0, 9, 2, 1, 7, 7], 16), Some(1));
+    }
+}
@@ -0,0 +1,15 @@
+//! Parsers for what Cnx reads from outside itself: color strings, files in
+//! `/sys` and `/proc`, and the X properties which other programs set.
+//!
+//! Any of these can be malformed, e.g. a driver reporting nonsense, a
+//! program setting a window title which isn't UTF-8, or a typo in a config
+//! file. So that a bad value only affects the widget showing it, rather than
+//! panicking the whole bar, the parsing is kept apart from the I/O, as pure
+//! functions which return `None` or an error for anything they don't
+//! understand. Each has unit tests for malformed input, and the fuzz targets
+//! in `fuzz/` feed them arbitrary bytes (see `fuzz/README.md`).
+
+pub mod color;
+pub mod ewmh;
+pub mod proc;
+pub mod sysfs;
@@ -0,0 +1,80 @@
+//! Files in `/proc`, which the kernel fills in, e.g. `/proc/net/wireless`.
+
+/// The link quality which cfg80211 drivers report as 100%.
+const MAX_LINK_QUALITY: f64 = 70.0;
+
+/// Parses the link quality of `interface` from the contents of
+/// `/proc/net/wireless`, as a percentage.
+pub fn link_quality(contents: &str, interface: &str) -> Option<f64> {
+    contents
+        .lines()
+        // The first two lines are headers.
+        .skip(2)
+        .filter_map(|line| line.trim().split_once(':'))
+        .find(|&(name, _)| name == interface)
+        .and_then(|(_, fields)| {
+            // The status, and then the link quality (e.g. `54.`).
+            let quality = fields.split_whitespace().nth(1)?;
+            let quality: f64 = quality.trim_end_matches('.').parse().ok()?;
+            if !quality.is_finite() {
+                return None;
+            }
+            Some((quality / MAX_LINK_QUALITY * 100.0).clamp(0.0, 100.0))
+        })
+}
+
+/// Returns the process ID of the directory in `/proc` called `name`, or
+/// `None` if it isn't a process's directory (e.g. `self` or `net`).
+pub fn pid(name: &str) -> Option<u32> {
+    // `parse()` would allow a sign.
+    if !name.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    name.parse().ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{link_quality, pid};
+
+    const WIRELESS: &str = "\
+Inter-| sta-|   Quality        |   Discarded packets               | Missed | WE
+ face | tus | link level noise |  nwid  crypt   frag  retry   misc | beacon | 22
+ wlan0: 0000   54.  -56.  -256        0      0      0      0     34        0
+";
+
+    #[test]
+    fn parses_link_quality() {
+        let quality = link_quality(WIRELESS, "wlan0").unwrap();
+        assert_eq!(quality.round(), 77.0);
+        assert_eq!(link_quality(WIRELESS, "wlan1"), None);
+    }
+
+    #[test]
+    fn ignores_malformed_link_quality() {
+        let headers = WIRELESS.lines().take(2).collect::<Vec<_>>().join("\n");
+        let quality = |line: &str| link_quality(&format!("{}\n{}\n", headers, line), "wlan0");
+        assert_eq!(link_quality("", "wlan0"), None);
+        assert_eq!(link_quality(&headers, "wlan0"), None);
+        // Only the headers, even if they look like an interface.
+        assert_eq!(link_quality("wlan0: 0000 54.\n", "wlan0"), None);
+        assert_eq!(quality("wlan0"), None);
+        assert_eq!(quality("wlan0:"), None);
+        assert_eq!(quality("wlan0: 0000"), None);
+        assert_eq!(quality("wlan0: 0000 good."), None);
+        assert_eq!(quality("wlan0: 0000 NaN."), None);
+        assert_eq!(quality("wlan0: 0000 inf."), None);
+        assert_eq!(quality("wlan0: 0000 -5."), Some(0.0));
+        assert_eq!(quality("wlan0: 0000 255."), Some(100.0));
+        assert_eq!(quality("wlan0: 0000 35 ::"), Some(50.0));
+    }
+
+    #[test]
+    fn parses_pids() {
+        assert_eq!(pid("1"), Some(1));
+        assert_eq!(pid("4194304"), Some(4_194_304));
+        for name in ["", "self", "net", "+1", "-1", "1a", "99999999999"] {
+            assert_eq!(pid(name), None, "{:?}", name);
+        }
+    }
+}
@@ -0,0 +1,111 @@
+//! Files in `/sys`, which drivers fill in, e.g. hwmon's temperatures.
+
+use crate::error::{format_err, ResultExt};
+use crate::Result;
+
+/// Parses the contents of a hwmon `temp*_input` file (or `temp*_max`, etc.),
+/// which contains the temperature in millidegrees Celsius.
+pub fn millidegrees(contents: &str) -> Result<f64> {
+    let millidegrees = contents
+        .trim()
+        .parse::<i64>()
+        .with_context(|_| format!("Invalid hwmon temperature: {:?}", contents))?;
+    Ok(millidegrees as f64 / 1000.0)
+}
+
+/// Returns the number of the sensor whose temperature is in the hwmon file
+/// called `file_name`, e.g. `1` for `temp1_input`.
+pub fn temp_input(file_name: &str) -> Option<u32> {
+    file_name
+        .strip_prefix("temp")?
+        .strip_suffix("_input")?
+        .parse()
+        .ok()
+}
+
+/// Returns the number of the CPU whose directory in
+/// `/sys/devices/system/cpu` is called `name`, e.g. `3` for `cpu3`.
+pub fn cpu_number(name: &str) -> Option<u32> {
+    let number = name.strip_prefix("cpu")?;
+    // `parse()` would allow a sign, e.g. in `cpu+1`.
+    if !number.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    number.parse().ok()
+}
+
+/// Parses the contents of a cpufreq file like `scaling_cur_freq`, which
+/// contains a frequency in kHz.
+pub fn kilohertz(contents: &str) -> Result<u64> {
+    let contents = contents.trim();
+    if !contents.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(format_err!("Invalid CPU frequency: {:?}", contents));
+    }
+    contents
+        .parse()
+        .with_context(|_| format!("Invalid CPU frequency: {:?}", contents))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{cpu_number, kilohertz, millidegrees, temp_input};
+
+    #[test]
+    fn parses_temperatures() {
+        assert_eq!(millidegrees("38000\n").unwrap(), 38.0);
+        assert_eq!(millidegrees("-1500").unwrap(), -1.5);
+        for contents in [
+            "",
+            "\n",
+            "hot",
+            "38.5",
+            "38000 38000",
+            "99999999999999999999",
+        ] {
+            assert!(millidegrees(contents).is_err(), "{:?}", contents);
+        }
+    }
+
+    #[test]
+    fn parses_file_names() {
+        assert_eq!(temp_input("temp1_input"), Some(1));
+        assert_eq!(temp_input("temp12_input"), Some(12));
+        for name in [
+            "temp_input",
+            "temp1_max",
+            "tempX_input",
+            "fan1_input",
+            "temp",
+        ] {
+            assert_eq!(temp_input(name), None, "{:?}", name);
+        }
+
+        assert_eq!(cpu_number("cpu0"), Some(0));
+        assert_eq!(cpu_number("cpu15"), Some(15));
+        for name in [
+            "cpu",
+            "cpufreq",
+            "cpuidle",
+            "cpu+1",
+            "cpu-1",
+            "cpu99999999999",
+        ] {
+            assert_eq!(cpu_number(name), None, "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn parses_frequencies() {
+        assert_eq!(kilohertz("2400000\n").unwrap(), 2_400_000);
+        for contents in [
+            "",
+            "fast",
+            "-800000",
+            "+800000",
+            "2.4",
+            "99999999999999999999999",
+        ] {
+            assert!(kilohertz(contents).is_err(), "{:?}", contents);
+        }
+    }
+}
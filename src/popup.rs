@@ -0,0 +1,152 @@
+//! Small windows which widgets show next to the pointer, e.g. the
+//! [`Clock`]'s month calendar.
+//!
+//! A popup is drawn once, into a pixmap which is the window's background, so
+//! that the X server redraws it by itself when it is uncovered. Popups are
+//! override-redirect, so the WM leaves them where they are put, and close
+//! once they are dropped.
+//!
+//! [`Clock`]: ../widgets/struct.Clock.html
+
+use std::rc::Rc;
+
+use cairo::prelude::*;
+use xcb_util::ewmh;
+
+use crate::bar::{cairo_surface_for_xcb_window, get_visual_type};
+use crate::error::{format_err, ResultExt};
+use crate::text::{BarOrientation, Color, Text};
+use crate::{xcb_ext, Result};
+
+/// The gap between the pointer and a popup.
+const POINTER_GAP: i32 = 16;
+
+/// Returns where to put a popup of the given size, next to the pointer at
+/// `pointer` on a screen of the given size: below it in the top half of the
+/// screen and above it otherwise, moved to fit on the screen if it can.
+fn place(pointer: (i32, i32), size: (i32, i32), screen: (i32, i32)) -> (i32, i32) {
+    let (x, y) = pointer;
+    let (width, height) = size;
+    let (screen_width, screen_height) = screen;
+    let x = (x - width / 2).min(screen_width - width).max(0);
+    let y = if y < screen_height / 2 {
+        y + POINTER_GAP
+    } else {
+        y - POINTER_GAP - height
+    };
+    (x, y.min(screen_height - height).max(0))
+}
+
+/// A window showing a single text, next to where the pointer was when it
+/// was shown.
+pub(crate) struct Popup {
+    conn: Rc<ewmh::Connection>,
+    window: xcb::Window,
+    pixmap: xcb::Pixmap,
+}
+
+impl Popup {
+    /// Shows `text` (which may have several lines) with its attributes, on
+    /// a background of its `bg_color`, or black if it hasn't one.
+    pub fn show(text: Text) -> Result<Popup> {
+        let (conn, screen_idx) = xcb_ext::connect()?;
+        let setup = conn.get_setup();
+        let screen = setup
+            .roots()
+            .nth(screen_idx as usize)
+            .ok_or_else(|| format_err!("Invalid screen: {}", screen_idx))?;
+        let visual = get_visual_type(&conn, screen.root_visual());
+
+        // Measure the text before creating anything of that size.
+        let measure = cairo::ImageSurface::create(cairo::Format::ARgb32, 1, 1)
+            .map_err(|status| format_err!("Failed to create surface: {:?}", status))?;
+        let mut computed = text.compute(&measure, BarOrientation::Horizontal)?;
+        let (width, height) = (computed.width.ceil(), computed.height.ceil());
+        computed.width = width;
+        computed.height = height;
+
+        let pointer = xcb::query_pointer(&conn, screen.root())
+            .get_reply()
+            .context("Failed to find the pointer")?;
+        let (x, y) = place(
+            (i32::from(pointer.root_x()), i32::from(pointer.root_y())),
+            (width as i32, height as i32),
+            (
+                i32::from(screen.width_in_pixels()),
+                i32::from(screen.height_in_pixels()),
+            ),
+        );
+
+        let pixmap = conn.generate_id();
+        xcb::create_pixmap(
+            &conn,
+            screen.root_depth(),
+            pixmap,
+            screen.root(),
+            width as u16,
+            height as u16,
+        );
+        let surface =
+            cairo_surface_for_xcb_window(&conn, visual, pixmap, width as i32, height as i32);
+        // The text draws its own background over black, if it has one.
+        computed.render(&surface, &Color::default(), BarOrientation::Horizontal)?;
+        surface.flush();
+
+        let window = conn.generate_id();
+        let values = [
+            (xcb::CW_BACK_PIXMAP, pixmap),
+            (xcb::CW_OVERRIDE_REDIRECT, 1),
+        ];
+        xcb::create_window_checked(
+            &conn,
+            screen.root_depth(),
+            window,
+            screen.root(),
+            x as i16,
+            y as i16,
+            width as u16,
+            height as u16,
+            0,
+            xcb::WINDOW_CLASS_INPUT_OUTPUT as u16,
+            screen.root_visual(),
+            &values,
+        )
+        .request_check()
+        .context("Failed to create popup window")?;
+        ewmh::set_wm_window_type(&conn, window, &[conn.WM_WINDOW_TYPE_POPUP_MENU()]);
+        xcb::map_window(&conn, window);
+        conn.flush();
+
+        Ok(Popup {
+            conn,
+            window,
+            pixmap,
+        })
+    }
+}
+
+impl Drop for Popup {
+    fn drop(&mut self) {
+        xcb::destroy_window(&self.conn, self.window);
+        xcb::free_pixmap(&self.conn, self.pixmap);
+        self.conn.flush();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::place;
+
+    #[test]
+    fn works() {
+        // Below the pointer near the top of the screen, centred on it.
+        assert_eq!(place((500, 10), (200, 100), (1920, 1080)), (400, 26));
+        // Above it near the bottom.
+        assert_eq!(place((500, 1070), (200, 100), (1920, 1080)), (400, 954));
+        // Kept on the screen near its edges.
+        assert_eq!(place((1910, 10), (200, 100), (1920, 1080)), (1720, 26));
+        assert_eq!(place((5, 10), (200, 100), (1920, 1080)), (0, 26));
+        // Too big to fit, so at the top left.
+        assert_eq!(place((500, 10), (3000, 2000), (1920, 1080)), (0, 0));
+    }
+}
@@ -0,0 +1,254 @@
+//! Reading the output of child processes from the event loop, for widgets
+//! which use a command that reports changes (e.g. `pactl subscribe`), which
+//! streams data (e.g. `parec`), or whose output they show once it exits.
+
+use std::io::{self, BufReader, Read};
+use std::mem;
+use std::os::unix::io::AsRawFd;
+use std::process::{Child, ChildStdout, Command, ExitStatus, Stdio};
+use std::thread;
+use std::time::Duration;
+
+use futures::{future, try_ready, Async, Future, Poll, Stream};
+use log::{debug, error};
+use mio::event::Evented;
+use mio::unix::EventedFd;
+use mio::{PollOpt, Ready, Token};
+use tokio_core::reactor::{Handle, PollEvented};
+use tokio_io::io::Lines;
+use tokio_timer::Sleep;
+
+use crate::error::{format_err, Error, ResultExt};
+use crate::timer::Timer;
+use crate::Result;
+
+// How often to check whether a child which has closed its stdout has exited.
+const EXIT_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The stdout of a child process, made non-blocking so that it can be read
+/// from the event loop.
+struct Pipe(ChildStdout);
+
+impl Read for Pipe {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+impl Evented for Pipe {
+    fn register(
+        &self,
+        poll: &mio::Poll,
+        token: Token,
+        interest: Ready,
+        opts: PollOpt,
+    ) -> io::Result<()> {
+        EventedFd(&self.0.as_raw_fd()).register(poll, token, interest, opts)
+    }
+
+    fn reregister(
+        &self,
+        poll: &mio::Poll,
+        token: Token,
+        interest: Ready,
+        opts: PollOpt,
+    ) -> io::Result<()> {
+        EventedFd(&self.0.as_raw_fd()).reregister(poll, token, interest, opts)
+    }
+
+    fn deregister(&self, poll: &mio::Poll) -> io::Result<()> {
+        EventedFd(&self.0.as_raw_fd()).deregister(poll)
+    }
+}
+
+/// A stream of the lines written to stdout by a child process. The child is
+/// killed when the stream is dropped.
+pub(crate) struct OutputLines {
+    child: Child,
+    lines: Lines<BufReader<PollEvented<Pipe>>>,
+}
+
+impl Stream for OutputLines {
+    type Item = String;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        self.lines.poll().map_err(Error::from)
+    }
+}
+
+impl Drop for OutputLines {
+    fn drop(&mut self) {
+        // The child may well have already exited.
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// A stream of the chunks of bytes written to stdout by a child process, as
+/// they are read. The child is killed when the stream is dropped.
+pub(crate) struct OutputChunks {
+    child: Child,
+    pipe: PollEvented<Pipe>,
+}
+
+impl Stream for OutputChunks {
+    type Item = Vec<u8>;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        let mut buf = [0; 4096];
+        match self.pipe.read(&mut buf) {
+            Ok(0) => Ok(futures::Async::Ready(None)),
+            Ok(n) => Ok(futures::Async::Ready(Some(buf[..n].to_vec()))),
+            // The pipe has arranged for us to be polled again once there is
+            // more to read.
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => Ok(futures::Async::NotReady),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+impl Drop for OutputChunks {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Runs `command`, returning a stream of the lines it writes to stdout. The
+/// stream finishes when the command exits.
+pub(crate) fn output_lines(handle: &Handle, command: Command) -> Result<OutputLines> {
+    let (child, pipe) = spawn(handle, command)?;
+    Ok(OutputLines {
+        child,
+        lines: tokio_io::io::lines(BufReader::new(pipe)),
+    })
+}
+
+/// A future which reads everything a child process writes to stdout, and
+/// then waits for it to exit.
+struct Exited {
+    chunks: OutputChunks,
+    stdout: Vec<u8>,
+    timer: Timer,
+    wait: Option<Sleep>,
+}
+
+impl Future for Exited {
+    type Item = (ExitStatus, Vec<u8>);
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        while let Some(chunk) = try_ready!(self.chunks.poll()) {
+            self.stdout.extend(chunk);
+        }
+        // The child has closed its stdout, so is usually exiting, but it
+        // can't be waited for without blocking the event loop.
+        loop {
+            if let Some(ref mut wait) = self.wait {
+                try_ready!(wait.poll());
+            }
+            match self.chunks.child.try_wait()? {
+                Some(status) => return Ok(Async::Ready((status, mem::take(&mut self.stdout)))),
+                None => self.wait = Some(self.timer.sleep(EXIT_POLL_INTERVAL)),
+            }
+        }
+    }
+}
+
+/// Runs `command`, returning a stream of the bytes it writes to stdout. The
+/// stream finishes when the command exits.
+pub(crate) fn output_chunks(handle: &Handle, command: Command) -> Result<OutputChunks> {
+    let (child, pipe) = spawn(handle, command)?;
+    Ok(OutputChunks { child, pipe })
+}
+
+/// Runs `command` until it exits, returning its exit status and everything
+/// it wrote to stdout, like `Command::output()` but without blocking the
+/// event loop. The command is killed if it is still running after
+/// `timeout`, and the future fails.
+pub(crate) fn output(
+    handle: &Handle,
+    timer: &Timer,
+    command: Command,
+    timeout: Duration,
+) -> Box<dyn Future<Item = (ExitStatus, Vec<u8>), Error = Error>> {
+    let description = format!("{:?}", command);
+    let exited = match output_chunks(handle, command) {
+        Ok(chunks) => Exited {
+            chunks,
+            stdout: Vec::new(),
+            timer: timer.clone(),
+            wait: None,
+        },
+        Err(e) => return Box::new(future::err(e)),
+    };
+    let expired = timer
+        .sleep(timeout)
+        .map_err(Error::from)
+        .and_then(move |()| Err(format_err!("{} timed out after {:?}", description, timeout)));
+    // Dropping whichever loses kills the command if it is still running.
+    Box::new(
+        exited
+            .select(expired)
+            .map(|(output, _)| output)
+            .map_err(|(e, _)| e),
+    )
+}
+
+/// Runs `command` on a thread, without waiting for it to finish, for
+/// commands whose output isn't needed. Failures are only logged.
+pub(crate) fn run_detached(mut command: Command) {
+    debug!("Running {:?}", command);
+    thread::spawn(move || match command.status() {
+        Ok(status) if !status.success() => error!("{:?} failed: {}", command, status),
+        Ok(_) => {}
+        Err(e) => error!("Failed to run {:?}: {}", command, e),
+    });
+}
+
+/// Sends a desktop notification with `notify-send`, without waiting for it
+/// to be sent. `urgency` is one of `low`, `normal` or `critical`.
+pub(crate) fn notify(urgency: &str, summary: &str, body: &str) {
+    let mut command = Command::new("notify-send");
+    command
+        .arg(format!("--urgency={}", urgency))
+        .arg(summary)
+        .arg(body);
+    run_detached(command);
+}
+
+fn spawn(handle: &Handle, mut command: Command) -> Result<(Child, PollEvented<Pipe>)> {
+    debug!("Running {:?}", command);
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .spawn()
+        .with_context(|_| format!("Failed to run {:?}", command))?;
+    match pipe(handle, &mut child) {
+        Ok(pipe) => Ok((child, pipe)),
+        Err(e) => {
+            let _ = child.kill();
+            let _ = child.wait();
+            Err(Error::Context {
+                context: format!("Failed to read output of {:?}", command),
+                source: Box::new(e),
+            })
+        }
+    }
+}
+
+fn pipe(handle: &Handle, child: &mut Child) -> Result<PollEvented<Pipe>> {
+    let stdout = child
+        .stdout
+        .take()
+        .ok_or_else(|| format_err!("Child has no stdout"))?;
+
+    let fd = stdout.as_raw_fd();
+    let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+    if flags < 0 || unsafe { libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) } < 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    Ok(PollEvented::new(Pipe(stdout), handle)?)
+}
@@ -0,0 +1,100 @@
+//! Reloading the widgets while Cnx is running, whenever a file changes. See
+//! [`Cnx::reload_on_change()`].
+//!
+//! [`Cnx::reload_on_change()`]: ../struct.Cnx.html#method.reload_on_change
+
+use std::cell::Cell;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::Duration;
+
+use futures::unsync::mpsc;
+use futures::{Future, Stream};
+use log::{error, info, warn};
+
+use crate::bar::{BarOptions, Reload, Reloads};
+use crate::error::format_err;
+use crate::inotify::FileEventKind;
+use crate::{Cnx, Result};
+
+/// How long to wait for a file to stop changing before reloading it, as
+/// editors often write files in several steps.
+const SETTLE_DELAY: Duration = Duration::from_millis(200);
+
+/// Adds the widgets to a `Cnx` instance, given to
+/// [`Cnx::reload_on_change()`].
+///
+/// [`Cnx::reload_on_change()`]: ../struct.Cnx.html#method.reload_on_change
+pub(crate) type Reloader = Box<dyn FnMut(&mut Cnx) -> Result<()>>;
+
+/// Whether the bars would be the same size with either of the options.
+fn same_geometry(a: &BarOptions, b: &BarOptions) -> bool {
+    a.height == b.height
+        && a.margins == b.margins
+        && a.reserved == b.reserved
+        && a.screen == b.screen
+}
+
+/// Returns a stream of the widgets added by `reload` each time the file at
+/// `path` changes, for the running `cnx`.
+pub(crate) fn watch(cnx: &Cnx, path: PathBuf, mut reload: Reloader) -> Result<Reloads> {
+    // Editors often replace files rather than writing to them, which would
+    // end a watch on the file itself, so watch its directory instead.
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_owned(),
+        _ => Path::new(".").to_owned(),
+    };
+    let name = path.file_name().map(ToOwned::to_owned);
+    let changes = cnx.inotify().watch(&dir)?.filter(move |event| {
+        event.kind != FileEventKind::Removed && event.path.file_name() == name.as_deref()
+    });
+
+    // Reload once no more changes have been seen for a while: each change
+    // restarts the wait.
+    let (sender, settled) = mpsc::unbounded();
+    let changed = Rc::new(Cell::new(0));
+    let watch = {
+        let changed = changed.clone();
+        let handle = cnx.handle();
+        let timer = cnx.timer();
+        changes
+            .for_each(move |_| {
+                changed.set(changed.get() + 1);
+                let sender = sender.clone();
+                let change = changed.get();
+                handle.spawn(timer.sleep(SETTLE_DELAY).then(move |_| {
+                    let _ = sender.unbounded_send(change);
+                    Ok(())
+                }));
+                Ok(())
+            })
+            .map_err(|e| error!("Stopped watching for changes to reload: {}", e))
+    };
+    cnx.handle().spawn(watch);
+
+    let mut reloaded = cnx.without_widgets();
+    let reloads = settled
+        .filter(move |&change| change == changed.get())
+        .map_err(|()| format_err!("Reload channel closed"))
+        .filter_map(move |_| {
+            info!("Reloading widgets, as {} changed", path.display());
+            let (options, monitors) = (reloaded.bar_options.clone(), reloaded.monitors.clone());
+            if let Err(e) = reload(&mut reloaded) {
+                error!("Failed to reload widgets: {}", e);
+                reloaded.widgets.clear();
+                return None;
+            }
+
+            if !same_geometry(&reloaded.bar_options, &options) || reloaded.monitors != monitors {
+                warn!("The bars' size and monitors won't change until Cnx is restarted");
+            }
+            reloaded.apply_placeholder();
+            reloaded.apply_fifo_output();
+            Some(Reload {
+                widgets: mem::take(&mut reloaded.widgets),
+                options: reloaded.bar_options.clone(),
+            })
+        });
+    Ok(Box::new(reloads))
+}
@@ -0,0 +1,123 @@
+//! Showing the widgets in another bar, such as dwm's, by setting the root
+//! window's `WM_NAME` rather than drawing bars of our own. See
+//! [`Cnx::set_root_name_output()`].
+//!
+//! [`Cnx::set_root_name_output()`]: ../struct.Cnx.html#method.set_root_name_output
+
+use std::time::Duration;
+
+use futures::{Future, Stream};
+use log::{debug, warn};
+
+use crate::bar::install_widgets;
+use crate::control::{Command, Commands};
+use crate::error::{format_err, Error};
+use crate::text::{self, Attributes, Text};
+use crate::timer::Timer;
+use crate::widgets::{Widget, WidgetOptions};
+use crate::{xcb_ext, Result};
+
+/// The latest texts of one of the widgets.
+#[derive(Clone, Default)]
+struct Slot {
+    texts: Vec<Text>,
+    // Whether the widget was hidden by a `Control`.
+    concealed: bool,
+}
+
+/// Joins the text of each widget which has something to show, with
+/// `separator` between them. The regions are shown one after the other, as
+/// there's only one place to show them.
+fn root_name(options: &[WidgetOptions], slots: &[Slot], separator: &str) -> String {
+    let mut widgets = (0..slots.len())
+        .filter(|&i| !slots[i].concealed && !options[i].scratchpad)
+        .collect::<Vec<_>>();
+    widgets.sort_by_key(|&i| options[i].region as u8);
+    widgets
+        .into_iter()
+        .map(|i| text::join_plain_text(&slots[i].texts))
+        .filter(|text| !text.is_empty())
+        .collect::<Vec<_>>()
+        .join(separator)
+}
+
+/// Hides or shows the widgets with the given `name`.
+fn conceal(slots: &mut [Slot], options: &[WidgetOptions], name: &str, concealed: bool) {
+    let mut found = false;
+    for (slot, options) in slots.iter_mut().zip(options) {
+        if options.name.as_deref() == Some(name) {
+            slot.concealed = concealed;
+            found = true;
+        }
+    }
+    if !found {
+        warn!("Can't hide or show unknown widget: {}", name);
+    }
+}
+
+/// Runs the event loop which sets the root window's `WM_NAME` to the texts of
+/// the `widgets` whenever they update.
+///
+/// Commands which act on widgets work as they do for the bars, while those
+/// for the bars themselves are ignored.
+pub(crate) fn run_event_loop(
+    timer: Timer,
+    widgets: Vec<(Box<dyn Widget>, WidgetOptions)>,
+    commands: Commands,
+    error_flash: Option<(Attributes, Duration)>,
+    separator: String,
+) -> Result<Box<dyn Future<Item = (), Error = Error>>> {
+    let (conn, screen_idx) = xcb_ext::connect()?;
+    let root = xcb_ext::root_window(&conn, screen_idx)?;
+    let utf8_string = xcb_ext::intern_atom(&conn, "UTF8_STRING")?;
+    let (widget_list, installed) = install_widgets(&mut [], &timer, widgets, error_flash)?;
+
+    enum Event {
+        Widget(Vec<Option<Vec<Text>>>),
+        Command(Command),
+    }
+
+    let commands = commands
+        .map(Event::Command)
+        .map_err(|()| format_err!("Control channel closed"));
+    let events = widget_list.map(Event::Widget).select(commands);
+
+    let mut slots = vec![Slot::default(); installed.options.len()];
+    let mut shown = None;
+    let fut = events.for_each(move |event| {
+        match event {
+            Event::Widget(updates) => {
+                for (slot, update) in slots.iter_mut().zip(updates) {
+                    if let Some(texts) = update {
+                        slot.texts = texts;
+                    }
+                }
+            }
+            Event::Command(Command::Refresh(name)) => installed.refresh(&name),
+            Event::Command(Command::Hide(name)) => {
+                conceal(&mut slots, &installed.options, &name, true)
+            }
+            Event::Command(Command::Show(name)) => {
+                conceal(&mut slots, &installed.options, &name, false)
+            }
+            Event::Command(command) => debug!("Ignoring {:?} without any bars", command),
+        }
+
+        let name = root_name(&installed.options, &slots, &separator);
+        if shown.as_ref() != Some(&name) {
+            xcb::change_property(
+                &conn,
+                xcb::PROP_MODE_REPLACE as u8,
+                root,
+                xcb::ATOM_WM_NAME,
+                utf8_string,
+                8,
+                name.as_bytes(),
+            );
+            conn.flush();
+            shown = Some(name);
+        }
+        Ok(())
+    });
+    Ok(Box::new(fut))
+}
@@ -0,0 +1,287 @@
+//! A small key-value store, for widgets which keep some state between runs
+//! of Cnx, e.g. how long each application has been focused for today.
+//!
+//! Each widget's [`State`] is kept in its own file in `$XDG_STATE_HOME/cnx/`
+//! (usually `~/.local/state/cnx/`), named after the widget, with a `key=value`
+//! line for each of its keys. Widgets [`load()`] their state when they start,
+//! change it with [`set()`], and [`save()`] it every so often. Both loading
+//! and saving read or write the file on a separate thread, so that a slow
+//! disk never holds up the bar.
+//!
+//! ```no_run
+//! use cnx::state::State;
+//! # use futures::Future;
+//!
+//! # fn run() -> ::cnx::Result<()> {
+//! let mut state = State::load("pomodoro").wait()?;
+//! let count = state.parse::<u32>("count").unwrap_or(0);
+//! state.set("count", count + 1);
+//! state.save().wait()?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`State`]: struct.State.html
+//! [`load()`]: struct.State.html#method.load
+//! [`set()`]: struct.State.html#method.set
+//! [`save()`]: struct.State.html#method.save
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::thread;
+
+use futures::sync::oneshot;
+use futures::{future, Future};
+use log::warn;
+
+use crate::error::{format_err, Error, ResultExt};
+use crate::Result;
+
+/// The directory the state is kept in: `$XDG_STATE_HOME/cnx/`, or
+/// `~/.local/state/cnx/` if `$XDG_STATE_HOME` isn't set.
+fn default_dir() -> Option<PathBuf> {
+    env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/state")))
+        .map(|state| state.join("cnx"))
+}
+
+/// Escapes backslashes and line breaks, and (in keys) equals signs, so that
+/// each key and value fits on a single line.
+fn escape(s: &str, key: bool) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '=' if key => escaped.push_str("\\="),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Parses one line written by `serialize()`, returning its key and value.
+fn parse_line(line: &str) -> Option<(String, String)> {
+    let mut key = String::new();
+    let mut value = String::new();
+    let mut in_key = true;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        let unescaped = match c {
+            '=' if in_key => {
+                in_key = false;
+                continue;
+            }
+            '\\' => match chars.next()? {
+                'n' => '\n',
+                'r' => '\r',
+                c @ ('\\' | '=') => c,
+                _ => return None,
+            },
+            c => c,
+        };
+        if in_key {
+            key.push(unescaped);
+        } else {
+            value.push(unescaped);
+        }
+    }
+    if in_key {
+        None
+    } else {
+        Some((key, value))
+    }
+}
+
+/// Parses the contents of a state file, skipping (and warning about) any
+/// lines which can't be parsed.
+fn parse(s: &str) -> BTreeMap<String, String> {
+    s.lines()
+        .filter(|line| !line.is_empty())
+        .filter_map(|line| {
+            let parsed = parse_line(line);
+            if parsed.is_none() {
+                warn!("Ignoring invalid line of saved state: {:?}", line);
+            }
+            parsed
+        })
+        .collect()
+}
+
+fn serialize(values: &BTreeMap<String, String>) -> String {
+    let mut s = String::new();
+    for (key, value) in values {
+        s.push_str(&format!("{}={}\n", escape(key, true), escape(value, false)));
+    }
+    s
+}
+
+/// Runs `f` on a new thread, resolving to its result.
+fn spawn<T, F>(f: F) -> Box<dyn Future<Item = T, Error = Error>>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T> + Send + 'static,
+{
+    let (tx, rx) = oneshot::channel();
+    thread::spawn(move || {
+        // If the future has been dropped, nobody is interested in the result.
+        let _ = tx.send(f());
+    });
+    Box::new(
+        rx.map_err(|_| format_err!("The state's thread stopped unexpectedly"))
+            .and_then(|result| result),
+    )
+}
+
+/// The saved state of a widget, see the [module documentation].
+///
+/// [module documentation]: index.html
+#[derive(Clone, Debug, PartialEq)]
+pub struct State {
+    name: String,
+    path: Option<PathBuf>,
+    values: BTreeMap<String, String>,
+}
+
+impl State {
+    /// Loads the state called `name` (usually the name of the widget it
+    /// belongs to), which is empty if it has never been saved.
+    ///
+    /// Names may only contain ASCII letters and digits, `-`, `_` and `.`,
+    /// and can't start with `.`. If neither `$XDG_STATE_HOME` nor `$HOME` is
+    /// set, the state starts empty and is never saved.
+    pub fn load(name: &str) -> Box<dyn Future<Item = State, Error = Error>> {
+        let valid = name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c));
+        if name.is_empty() || name.starts_with('.') || !valid {
+            return Box::new(future::err(format_err!("Invalid state name: {:?}", name)));
+        }
+        let name = name.to_owned();
+        let path = default_dir().map(|dir| dir.join(&name));
+        spawn(move || {
+            let values = match path {
+                Some(ref path) => match fs::read_to_string(path) {
+                    Ok(s) => parse(&s),
+                    Err(ref e) if e.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+                    Err(e) => {
+                        return Err(Error::from(e))
+                            .with_context(|_| format!("Failed to read {}", path.display()))
+                    }
+                },
+                None => BTreeMap::new(),
+            };
+            Ok(State { name, path, values })
+        })
+    }
+
+    /// An empty state which is never saved.
+    #[cfg(test)]
+    pub(crate) fn empty(name: &str) -> State {
+        State {
+            name: name.to_owned(),
+            path: None,
+            values: BTreeMap::new(),
+        }
+    }
+
+    /// The name the state was loaded with.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the value of `key`, if it has one.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.values.get(key).map(String::as_str)
+    }
+
+    /// Returns the value of `key` parsed as a `T`, if it has one and it can
+    /// be parsed.
+    pub fn parse<T: FromStr>(&self, key: &str) -> Option<T> {
+        self.get(key)?.parse().ok()
+    }
+
+    /// Iterates over the keys starting with `prefix` and their values, in
+    /// order, with the prefix removed from the keys.
+    pub fn prefixed<'a>(&'a self, prefix: &'a str) -> impl Iterator<Item = (&'a str, &'a str)> {
+        self.values
+            .range(prefix.to_owned()..)
+            .map_while(move |(key, value)| Some((key.strip_prefix(prefix)?, value.as_str())))
+    }
+
+    /// Sets the value of `key`, which is only written to disk once the state
+    /// is saved.
+    pub fn set<V: ToString>(&mut self, key: &str, value: V) {
+        self.values.insert(key.to_owned(), value.to_string());
+    }
+
+    /// Removes `key` and its value.
+    pub fn remove(&mut self, key: &str) {
+        self.values.remove(key);
+    }
+
+    /// Removes every key.
+    pub fn clear(&mut self) {
+        self.values.clear();
+    }
+
+    /// Writes the state to its file, creating `$XDG_STATE_HOME/cnx/` if
+    /// needed.
+    ///
+    /// The state is written to a temporary file which then replaces the old
+    /// one, so that Cnx being killed part of the way through doesn't lose the
+    /// state.
+    pub fn save(&self) -> Box<dyn Future<Item = (), Error = Error>> {
+        let path = match self.path {
+            Some(ref path) => path.clone(),
+            None => return Box::new(future::ok(())),
+        };
+        let contents = serialize(&self.values);
+        spawn(move || {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)
+                    .with_context(|_| format!("Failed to create {}", dir.display()))?;
+            }
+            let mut temporary = path.clone().into_os_string();
+            temporary.push(".tmp");
+            fs::write(&temporary, contents)
+                .and_then(|()| fs::rename(&temporary, &path))
+                .with_context(|_| format!("Failed to write {}", path.display()))?;
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use super::{parse, parse_line, serialize};
+
+    #[test]
+    fn works() {
+        let mut values = BTreeMap::new();
+        values.insert("date".to_owned(), "2018-11-03".to_owned());
+        values.insert("app=Firefox\\".to_owned(), "3700".to_owned());
+        values.insert("title".to_owned(), "two\nlines = one".to_owned());
+        values.insert("empty".to_owned(), String::new());
+        let serialized = serialize(&values);
+        assert_eq!(serialized.lines().count(), 4);
+        assert_eq!(parse(&serialized), values);
+
+        assert_eq!(
+            parse_line("a=b=c"),
+            Some(("a".to_owned(), "b=c".to_owned()))
+        );
+        assert_eq!(parse_line("no value"), None);
+        assert_eq!(parse_line("bad=escape\\x"), None);
+        assert_eq!(parse_line("truncated=\\"), None);
+        assert_eq!(parse("\nkey=value\nnope\n").len(), 1);
+    }
+}
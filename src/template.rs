@@ -0,0 +1,189 @@
+//! Filling in the `{key}` placeholders of widgets' format strings.
+//!
+//! Built-in widgets which show numbers take their format as a [`Template`],
+//! e.g. `({percent}% - {time_left})` for the [`Battery`], so that what they
+//! show can be changed without forking them:
+//!
+//! ```
+//! use cnx::template::{Template, Value};
+//!
+//! let template: Template = "{used:.1}/{total:.1}GB ({percent}%)".parse().unwrap();
+//! let text = template.render(&[
+//!     ("used", Value::Number(3.14159, 2)),
+//!     ("total", Value::Number(16.0, 2)),
+//!     ("percent", Value::Number(19.6, 0)),
+//! ]);
+//! assert_eq!(text, "3.1/16.0GB (20%)");
+//! ```
+//!
+//! [`Template`]: struct.Template.html
+//! [`Battery`]: ../widgets/struct.Battery.html
+
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use crate::error::{format_err, Error};
+
+/// Replaces each `{key}` in `format` with the value of `key` in `values`, or
+/// with nothing if there isn't one. Keys are case-insensitive, so the keys of
+/// `values` should be lowercase.
+pub(crate) fn fill(format: &str, values: &HashMap<String, String>) -> String {
+    let mut formatted = String::new();
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '{' {
+            formatted.push(c);
+            continue;
+        }
+        let key = chars.by_ref().take_while(|&c| c != '}').collect::<String>();
+        if let Some(value) = values.get(&key.to_lowercase()) {
+            formatted.push_str(value);
+        }
+    }
+    formatted
+}
+
+/// The value of a placeholder in a [`Template`].
+///
+/// [`Template`]: struct.Template.html
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    /// Text, e.g. a Wi-Fi network's name.
+    Text(String),
+    /// A number, shown with the given number of decimal places unless the
+    /// placeholder gives its own.
+    Number(f64, usize),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Part {
+    Literal(String),
+    Placeholder {
+        key: String,
+        precision: Option<usize>,
+    },
+}
+
+/// A widget's format, in which `{key}` placeholders are replaced by the
+/// widget's values, e.g. `{percent}%`.
+///
+/// Like Rust's format strings, a placeholder can give a precision, as in
+/// `{percent:.1}`: numbers are shown with that many decimal places, and text
+/// is cut off after that many characters. `{{` and `}}` stand for literal
+/// braces. Keys are case-insensitive, and placeholders the widget has no
+/// value for are left out.
+///
+/// Templates are parsed from strings, which fails if a brace is unmatched or
+/// a placeholder can't be parsed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+impl Template {
+    /// Fills in the placeholders with the `values` of their keys, which
+    /// should be lowercase.
+    pub fn render(&self, values: &[(&str, Value)]) -> String {
+        let mut rendered = String::new();
+        for part in &self.parts {
+            let (key, precision) = match *part {
+                Part::Literal(ref literal) => {
+                    rendered.push_str(literal);
+                    continue;
+                }
+                Part::Placeholder { ref key, precision } => (key, precision),
+            };
+            match values.iter().find(|&&(name, _)| name == key) {
+                Some((_, Value::Number(number, places))) => {
+                    let places = precision.unwrap_or(*places);
+                    rendered.push_str(&format!("{:.*}", places, number));
+                }
+                Some((_, Value::Text(text))) => match precision {
+                    Some(chars) => rendered.extend(text.chars().take(chars)),
+                    None => rendered.push_str(text),
+                },
+                None => {}
+            }
+        }
+        rendered
+    }
+}
+
+impl FromStr for Template {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Template, Error> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = s.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '}' => return Err(format_err!("Unmatched `}}` in format {:?}", s)),
+                '{' => {
+                    let mut placeholder = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => placeholder.push(c),
+                            None => return Err(format_err!("Unmatched `{{` in format {:?}", s)),
+                        }
+                    }
+                    let (key, precision) = match placeholder.find(':') {
+                        Some(colon) => {
+                            let spec = &placeholder[colon + 1..];
+                            let precision = spec
+                                .strip_prefix('.')
+                                .and_then(|places| places.parse().ok())
+                                .ok_or_else(|| {
+                                    format_err!("Invalid precision {:?} in format {:?}", spec, s)
+                                })?;
+                            (&placeholder[..colon], Some(precision))
+                        }
+                        None => (&placeholder[..], None),
+                    };
+                    if !literal.is_empty() {
+                        parts.push(Part::Literal(std::mem::take(&mut literal)));
+                    }
+                    parts.push(Part::Placeholder {
+                        key: key.trim().to_lowercase(),
+                        precision,
+                    });
+                }
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+        Ok(Template { parts })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Template, Value};
+
+    #[test]
+    fn works() {
+        let values = [
+            ("ssid", Value::Text("HomeNetwork".to_owned())),
+            ("quality", Value::Number(72.6, 0)),
+        ];
+        let render = |format: &str| format.parse::<Template>().unwrap().render(&values);
+        assert_eq!(render("{ssid} ({quality}%)"), "HomeNetwork (73%)");
+        assert_eq!(render("{SSID:.4} {quality:.1}"), "Home 72.6");
+        assert_eq!(render("{{{quality}}} {unknown}!"), "{73} !");
+
+        assert!("{ssid".parse::<Template>().is_err());
+        assert!("ssid}".parse::<Template>().is_err());
+        assert!("{quality:x}".parse::<Template>().is_err());
+    }
+}
@@ -1,12 +1,18 @@
+use std::collections::VecDeque;
+use std::f64;
 use std::fmt;
 use std::num::ParseIntError;
+use std::rc::Rc;
 use std::str::FromStr;
+use std::time::Duration;
 
-use cairo::{Context, Surface};
-use failure::format_err;
-use pango::{Alignment, EllipsizeMode, FontDescription, LayoutExt};
+use cairo::{Context, Format, ImageSurface, Matrix, MatrixTrait, Operator, Surface};
+use log::warn;
+use pango::{Alignment, EllipsizeMode, FontDescription, FontMapExt, LayoutExt};
 use pangocairo;
 
+use crate::error::Error;
+use crate::parse::color;
 use crate::Result;
 
 #[derive(Clone, Debug, PartialEq)]
@@ -14,18 +20,90 @@ pub struct Color {
     red: f64,
     green: f64,
     blue: f64,
+    alpha: f64,
 }
 
 #[derive(Debug)]
 pub enum ParseColorError {
+    /// The color isn't six or eight hexadecimal digits.
+    Format,
     Red(ParseIntError),
     Green(ParseIntError),
     Blue(ParseIntError),
+    Alpha(ParseIntError),
 }
 
 impl Color {
+    pub fn white() -> Color {
+        Color {
+            red: 1.0,
+            green: 1.0,
+            blue: 1.0,
+            alpha: 1.0,
+        }
+    }
+
+    pub fn red() -> Color {
+        Color {
+            red: 1.0,
+            green: 0.0,
+            blue: 0.0,
+            alpha: 1.0,
+        }
+    }
+
+    pub fn yellow() -> Color {
+        Color {
+            red: 1.0,
+            green: 1.0,
+            blue: 0.0,
+            alpha: 1.0,
+        }
+    }
+
+    pub fn blue() -> Color {
+        Color {
+            red: 0.0,
+            green: 0.0,
+            blue: 1.0,
+            alpha: 1.0,
+        }
+    }
+
+    /// Returns this color with the given `alpha`, from 0.0 (transparent) to
+    /// 1.0 (opaque).
+    ///
+    /// Translucent colors are drawn over whatever is behind them on the bar.
+    /// The bar's own background (see [`Cnx::set_background()`]) only lets the
+    /// desktop show through while a compositing manager is running.
+    ///
+    /// [`Cnx::set_background()`]: ../struct.Cnx.html#method.set_background
+    pub fn with_alpha(mut self, alpha: f64) -> Color {
+        self.alpha = alpha.clamp(0.0, 1.0);
+        self
+    }
+
     pub fn apply_to_context(&self, cr: &Context) {
-        cr.set_source_rgb(self.red, self.green, self.blue);
+        cr.set_source_rgba(self.red, self.green, self.blue, self.alpha);
+    }
+
+    /// Returns the color as a `0xRRGGBB` pixel value, as used by 24-bit
+    /// TrueColor X visuals.
+    pub(crate) fn to_rgb24(&self) -> u32 {
+        let byte = |c: f64| (c * 256.0).clamp(0.0, 255.0) as u32;
+        byte(self.red) << 16 | byte(self.green) << 8 | byte(self.blue)
+    }
+
+    /// Linearly interpolates between `self` (when `t` is 0.0) and `other`
+    /// (when `t` is 1.0).
+    pub(crate) fn blend(&self, other: &Color, t: f64) -> Color {
+        let mix = |a: f64, b: f64| a + (b - a) * t;
+        Color {
+            red: mix(self.red, other.red),
+            green: mix(self.green, other.green),
+            blue: mix(self.blue, other.blue),
+            alpha: mix(self.alpha, other.alpha),
+        }
     }
 }
 
@@ -35,28 +113,34 @@ impl Default for Color {
             red: 0.0,
             green: 0.0,
             blue: 0.0,
+            alpha: 1.0,
         }
     }
 }
 
 impl FromStr for Color {
     type Err = ParseColorError;
+
+    /// Parses a color written as `rrggbb` in hexadecimal, or as `rrggbbaa`
+    /// with an alpha, e.g. `00000080` for translucent black, where `ff` is
+    /// opaque.
     fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (red, green, blue, alpha) = color::hex_color(s)?;
         Ok(Color {
-            red: (u8::from_str_radix(&s[0..2], 16).map_err(ParseColorError::Red)?) as f64 / 256.0,
-            green: (u8::from_str_radix(&s[2..4], 16).map_err(ParseColorError::Green)?) as f64
-                / 256.0,
-            blue: (u8::from_str_radix(&s[4..6], 16).map_err(ParseColorError::Blue)?) as f64 / 256.0,
+            red: f64::from(red) / 256.0,
+            green: f64::from(green) / 256.0,
+            blue: f64::from(blue) / 256.0,
+            alpha: alpha.map_or(1.0, |alpha| f64::from(alpha) / 255.0),
         })
     }
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, Default, PartialEq)]
 pub struct Padding {
-    left: f64,
-    right: f64,
-    top: f64,
-    bottom: f64,
+    pub(crate) left: f64,
+    pub(crate) right: f64,
+    pub(crate) top: f64,
+    pub(crate) bottom: f64,
 }
 
 impl Padding {
@@ -68,6 +152,16 @@ impl Padding {
             bottom,
         }
     }
+
+    /// The padding, multiplied by `factor`.
+    pub(crate) fn scaled(&self, factor: f64) -> Padding {
+        Padding::new(
+            self.left * factor,
+            self.right * factor,
+            self.top * factor,
+            self.bottom * factor,
+        )
+    }
 }
 
 #[derive(Clone, PartialEq)]
@@ -77,6 +171,18 @@ impl Font {
     pub fn new(name: &str) -> Font {
         Font(FontDescription::from_string(name))
     }
+
+    /// The font, with its size multiplied by `factor`.
+    pub(crate) fn scaled(&self, factor: f64) -> Font {
+        let mut description = self.0.clone();
+        let size = f64::from(description.get_size()) * factor;
+        if description.get_size_is_absolute() {
+            description.set_absolute_size(size);
+        } else {
+            description.set_size(size.round() as i32);
+        }
+        Font(description)
+    }
 }
 
 impl fmt::Debug for Font {
@@ -91,47 +197,981 @@ pub struct Attributes {
     pub fg_color: Color,
     pub bg_color: Option<Color>,
     pub padding: Padding,
+    pub border: Option<Border>,
+}
+
+/// Which edges of a text a [`Border`] is drawn along.
+///
+/// [`Border`]: struct.Border.html
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Edges {
+    pub top: bool,
+    pub bottom: bool,
+    pub left: bool,
+    pub right: bool,
+}
+
+impl Edges {
+    /// All four edges.
+    pub fn all() -> Edges {
+        Edges {
+            top: true,
+            bottom: true,
+            left: true,
+            right: true,
+        }
+    }
+
+    /// Only the bottom edge, for an underline.
+    pub fn bottom() -> Edges {
+        Edges {
+            top: false,
+            bottom: true,
+            left: false,
+            right: false,
+        }
+    }
+}
+
+/// A line drawn along some of the edges of a text, e.g. to underline the
+/// active workspace.
+///
+/// The border is drawn `width` pixels thick, just inside the text's edges,
+/// over its padding and background. Add padding to keep it clear of the text.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Border {
+    pub color: Color,
+    pub width: f64,
+    pub edges: Edges,
+}
+
+impl Border {
+    /// Creates a border along all four edges.
+    pub fn new(color: Color, width: f64) -> Border {
+        Border {
+            color,
+            width,
+            edges: Edges::all(),
+        }
+    }
+
+    /// Creates an underline: a border along only the bottom edge.
+    pub fn underline(color: Color, width: f64) -> Border {
+        Border {
+            color,
+            width,
+            edges: Edges::bottom(),
+        }
+    }
+
+    /// Draws the border around a `width` by `height` text at the origin.
+    fn render(&self, context: &Context, width: f64, height: f64) {
+        let thickness = self.width.min(width).min(height).max(0.0);
+        let edges = [
+            (self.edges.top, (0.0, 0.0, width, thickness)),
+            (
+                self.edges.bottom,
+                (0.0, height - thickness, width, thickness),
+            ),
+            (self.edges.left, (0.0, 0.0, thickness, height)),
+            (
+                self.edges.right,
+                (width - thickness, 0.0, thickness, height),
+            ),
+        ];
+        self.color.apply_to_context(context);
+        for &(_, (x, y, width, height)) in edges.iter().filter(|(shown, _)| *shown) {
+            context.rectangle(x, y, width, height);
+        }
+        context.fill();
+    }
+}
+
+impl Attributes {
+    /// The attributes, with the font's size, the padding and the width of
+    /// the border multiplied by `factor`.
+    pub(crate) fn scaled(&self, factor: f64) -> Attributes {
+        Attributes {
+            font: self.font.scaled(factor),
+            fg_color: self.fg_color.clone(),
+            bg_color: self.bg_color.clone(),
+            padding: self.padding.scaled(factor),
+            border: self.border.as_ref().map(|border| Border {
+                width: border.width * factor,
+                ..border.clone()
+            }),
+        }
+    }
+}
+
+/// How worrying a value is, according to a [`Threshold`].
+///
+/// [`Threshold`]: struct.Threshold.html
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum Severity {
+    /// The value hasn't reached either level.
+    Normal,
+    /// The value has reached the warning level, but not the critical level.
+    Warn,
+    /// The value has reached the critical level.
+    Crit,
+}
+
+/// Which [`Attributes`] a widget shows a value with, once it reaches a
+/// warning or critical level.
+///
+/// For example, for a temperature which is worrying from 70°C and critical
+/// from 85°C:
+///
+/// ```
+/// # use cnx::text::*;
+/// let attr = Attributes {
+///     font: Font::new("SourceCodePro 21"),
+///     fg_color: Color::white(),
+///     bg_color: None,
+///     padding: Padding::new(8.0, 8.0, 0.0, 0.0),
+///     border: None,
+/// };
+/// let mut warn_attr = attr.clone();
+/// warn_attr.fg_color = "fabd2f".parse().unwrap();
+/// let mut crit_attr = attr.clone();
+/// crit_attr.fg_color = Color::red();
+///
+/// let threshold = Threshold::new().warn(70.0, warn_attr).crit(85.0, crit_attr);
+/// assert_eq!(threshold.apply(&90.0, &attr).fg_color, Color::red());
+/// assert_eq!(threshold.apply(&50.0, &attr), attr);
+/// ```
+///
+/// Values which are worrying as they fall, like a battery's charge, use
+/// [`Threshold::falling()`] instead.
+///
+/// [`Attributes`]: struct.Attributes.html
+/// [`Threshold::falling()`]: #method.falling
+#[derive(Clone, Debug, PartialEq)]
+pub struct Threshold<T> {
+    warn: Option<(T, Attributes)>,
+    crit: Option<(T, Attributes)>,
+    // Whether lower values are worse, rather than higher values.
+    falling: bool,
+}
+
+impl<T: PartialOrd> Threshold<T> {
+    /// Creates a threshold for a value which gets worse as it rises, without
+    /// any levels yet.
+    pub fn new() -> Threshold<T> {
+        Threshold {
+            warn: None,
+            crit: None,
+            falling: false,
+        }
+    }
+
+    /// Creates a threshold for a value which gets worse as it falls, e.g. a
+    /// battery's charge, without any levels yet.
+    pub fn falling() -> Threshold<T> {
+        Threshold {
+            falling: true,
+            ..Threshold::new()
+        }
+    }
+
+    /// Shows the value with `attr` once it reaches `level`.
+    pub fn warn(mut self, level: T, attr: Attributes) -> Threshold<T> {
+        self.warn = Some((level, attr));
+        self
+    }
+
+    /// Shows the value with `attr` once it reaches `level`, even if it has
+    /// also reached the warning level.
+    pub fn crit(mut self, level: T, attr: Attributes) -> Threshold<T> {
+        self.crit = Some((level, attr));
+        self
+    }
+
+    /// The worst level which `value` has reached.
+    pub fn severity(&self, value: &T) -> Severity {
+        let reached = |level: &Option<(T, Attributes)>| match *level {
+            Some((ref level, _)) if self.falling => value <= level,
+            Some((ref level, _)) => value >= level,
+            None => false,
+        };
+        if reached(&self.crit) {
+            Severity::Crit
+        } else if reached(&self.warn) {
+            Severity::Warn
+        } else {
+            Severity::Normal
+        }
+    }
+
+    /// The attributes of the worst level which `value` has reached, if any.
+    pub fn attributes(&self, value: &T) -> Option<&Attributes> {
+        let level = match self.severity(value) {
+            Severity::Crit => &self.crit,
+            Severity::Warn => &self.warn,
+            Severity::Normal => &None,
+        };
+        level.as_ref().map(|(_, attr)| attr)
+    }
+
+    /// The attributes to show `value` with: those of the worst level it has
+    /// reached, or otherwise `attr`.
+    pub fn apply(&self, value: &T, attr: &Attributes) -> Attributes {
+        self.attributes(value).unwrap_or(attr).clone()
+    }
+}
+
+impl<T: PartialOrd> Default for Threshold<T> {
+    fn default() -> Threshold<T> {
+        Threshold::new()
+    }
+}
+
+/// The appearance of a section of the bar, i.e. a group of widgets.
+///
+/// Each section has a single background, which is drawn behind any of its
+/// widgets' texts that don't have their own `bg_color`. The `padding` is
+/// left between the edges of the section's background and its widgets, and
+/// the corners of the background are rounded with the given `corner_radius`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SectionStyle {
+    pub bg_color: Option<Color>,
+    pub padding: Padding,
+    pub corner_radius: f64,
+}
+
+/// How texts are drawn on the bar's window.
+///
+/// Texts are always laid out as if the bar were horizontal: their `x` and
+/// `width` are along the bar, and their `y` and `height` are across it. This
+/// maps them onto the window.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum BarOrientation {
+    /// The bar is horizontal.
+    Horizontal,
+    /// The bar is vertical, running downwards, with each text upright. Each
+    /// text's length along the bar is its height.
+    Vertical,
+    /// The bar is vertical, with everything turned clockwise to read from
+    /// top to bottom. Holds the bar's thickness.
+    Rotated(f64),
+}
+
+impl BarOrientation {
+    /// Makes `context` draw on the window as if the bar were horizontal.
+    fn apply(self, context: &Context) {
+        match self {
+            BarOrientation::Horizontal => {}
+            BarOrientation::Vertical => context.transform(transpose()),
+            BarOrientation::Rotated(thickness) => {
+                context.translate(thickness, 0.0);
+                context.rotate(f64::consts::FRAC_PI_2);
+            }
+        }
+    }
+
+    /// Converts a point on the window to one on the horizontal bar.
+    pub fn window_to_bar(self, x: f64, y: f64) -> (f64, f64) {
+        match self {
+            BarOrientation::Horizontal => (x, y),
+            BarOrientation::Vertical => (y, x),
+            BarOrientation::Rotated(thickness) => (y, thickness - x),
+        }
+    }
+
+    /// Converts an area of the horizontal bar (as its `x`, `y`, `width` and
+    /// `height`) to the area of the window it is drawn on.
+    pub fn bar_to_window(self, x: f64, y: f64, width: f64, height: f64) -> (f64, f64, f64, f64) {
+        match self {
+            BarOrientation::Horizontal => (x, y, width, height),
+            BarOrientation::Vertical => (y, x, height, width),
+            BarOrientation::Rotated(thickness) => (thickness - y - height, x, height, width),
+        }
+    }
+}
+
+/// Swaps the x and y axes.
+fn transpose() -> Matrix {
+    Matrix::new(0.0, 1.0, 1.0, 0.0, 0.0, 0.0)
+}
+
+/// The colors of the bar itself, rather than of its widgets' texts: the
+/// `background` drawn behind everything, and the `section_styles` of its
+/// sections, indexed by their [`Region`], e.g.
+/// `section_styles[Region::Right as usize]`.
+///
+/// Used to switch the bar's colors with the desktop's color scheme, see
+/// [`Cnx::set_dark_palette()`].
+///
+/// [`Region`]: ../enum.Region.html
+/// [`Cnx::set_dark_palette()`]: ../struct.Cnx.html#method.set_dark_palette
+#[derive(Clone, Debug, PartialEq)]
+pub struct Palette {
+    pub background: Color,
+    pub section_styles: [Option<SectionStyle>; 3],
+}
+
+/// The area behind a section's texts, which the texts are clipped to.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Backdrop {
+    pub color: Option<Color>,
+    pub corner_radius: f64,
+
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl Backdrop {
+    fn path(&self, context: &Context) {
+        let radius = self
+            .corner_radius
+            .min(self.width / 2.0)
+            .min(self.height / 2.0)
+            .max(0.0);
+        let (left, top) = (self.x, self.y);
+        let (right, bottom) = (self.x + self.width, self.y + self.height);
+        let degrees = f64::consts::PI / 180.0;
+
+        context.new_sub_path();
+        context.arc(right - radius, top + radius, radius, -90.0 * degrees, 0.0);
+        context.arc(right - radius, bottom - radius, radius, 0.0, 90.0 * degrees);
+        context.arc(
+            left + radius,
+            bottom - radius,
+            radius,
+            90.0 * degrees,
+            180.0 * degrees,
+        );
+        context.arc(
+            left + radius,
+            top + radius,
+            radius,
+            180.0 * degrees,
+            270.0 * degrees,
+        );
+        context.close_path();
+    }
+
+    pub fn render(&self, surface: &Surface, orientation: BarOrientation) {
+        if let Some(ref color) = self.color {
+            let context = Context::new(&surface);
+            orientation.apply(&context);
+            color.apply_to_context(&context);
+            self.path(&context);
+            context.fill();
+        }
+    }
 }
 
 fn create_pango_layout(cairo_context: &cairo::Context) -> Result<pango::Layout> {
     let layout = pangocairo::functions::create_layout(cairo_context)
-        .ok_or_else(|| format_err!("Failed to create Pango layout"))?;
+        .ok_or_else(|| Error::Pango("Failed to create Pango layout".to_owned()))?;
     Ok(layout)
 }
 
+/// Sets the `layout`'s text, parsing it as Pango markup if `use_markup` is
+/// set. Returns the error if the markup is invalid, in which case the text is
+/// shown as it is.
+fn set_layout_text(layout: &pango::Layout, text: &str, use_markup: bool) -> Result<()> {
+    if !use_markup {
+        layout.set_text(text);
+        return Ok(());
+    }
+    match pango::parse_markup(text, '\0') {
+        Ok(_) => {
+            layout.set_markup(text);
+            Ok(())
+        }
+        Err(e) => {
+            layout.set_text(text);
+            Err(Error::Pango(e.to_string()))
+        }
+    }
+}
+
 fn show_pango_layout(cairo_context: &cairo::Context, layout: &pango::Layout) {
     pangocairo::functions::show_layout(cairo_context, layout);
 }
 
+/// How a [`Text`] is wrapped onto more than one line, for taller bars.
+///
+/// The text is wrapped once it is wider than `width` (not counting its
+/// padding), onto at most `max_lines` lines, the last of which is ellipsized
+/// if the text still doesn't fit. Newlines in the text always start a new
+/// line.
+///
+/// [`Text`]: struct.Text.html
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Wrap {
+    pub mode: WrapMode,
+    pub width: f64,
+    pub max_lines: u16,
+}
+
+/// Where a [`Wrap`]ped text's lines may be broken.
+///
+/// [`Wrap`]: struct.Wrap.html
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WrapMode {
+    /// Between words.
+    Word,
+    /// Between any characters.
+    Char,
+    /// Between words, or between characters if a word doesn't fit on a line
+    /// by itself.
+    WordChar,
+}
+
+/// Which part of a text that is too long is cut off, and replaced with an
+/// ellipsis.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Ellipsis {
+    Start,
+    Middle,
+    End,
+}
+
+impl Ellipsis {
+    /// Shortens `text` to at most `max_chars` characters, counting the
+    /// ellipsis, if it is any longer.
+    pub(crate) fn shorten(self, text: &str, max_chars: usize) -> String {
+        let chars = text.chars().collect::<Vec<_>>();
+        if chars.len() <= max_chars {
+            return text.to_owned();
+        }
+        let keep = max_chars.saturating_sub(1);
+        // The number of characters kept from the start and from the end.
+        let (start, end) = match self {
+            Ellipsis::Start => (0, keep),
+            Ellipsis::Middle => (keep - keep / 2, keep / 2),
+            Ellipsis::End => (keep, 0),
+        };
+        let mut shortened = chars[..start].iter().collect::<String>();
+        shortened.push('…');
+        shortened.extend(&chars[chars.len() - end..]);
+        shortened
+    }
+
+    /// Shortens `text` until it is at most `max_width` pixels wide, when
+    /// shown in `font`.
+    pub(crate) fn shorten_to_width(
+        self,
+        text: &str,
+        font: &Font,
+        max_width: f64,
+    ) -> Result<String> {
+        let context = pangocairo::FontMap::get_default()
+            .and_then(|font_map| font_map.create_context())
+            .ok_or_else(|| Error::Pango("Failed to create Pango context".to_owned()))?;
+        let layout = pango::Layout::new(&context);
+        layout.set_font_description(Some(&font.0));
+        let fits = |text: &str| {
+            layout.set_text(text);
+            f64::from(layout.get_pixel_size().0) <= max_width
+        };
+        if fits(text) {
+            return Ok(text.to_owned());
+        }
+        // Find the most characters which fit, knowing that all of them don't.
+        let (mut most, mut too_many) = (0, text.chars().count());
+        while too_many - most > 1 {
+            let chars = (most + too_many) / 2;
+            if fits(&self.shorten(text, chars)) {
+                most = chars;
+            } else {
+                too_many = chars;
+            }
+        }
+        Ok(self.shorten(text, most))
+    }
+}
+
+impl Wrap {
+    fn apply_to_layout(&self, layout: &pango::Layout) {
+        layout.set_wrap(match self.mode {
+            WrapMode::Word => pango::WrapMode::Word,
+            WrapMode::Char => pango::WrapMode::Char,
+            WrapMode::WordChar => pango::WrapMode::WordChar,
+        });
+        layout.set_ellipsize(EllipsizeMode::End);
+        layout.set_width(self.width as i32 * pango::SCALE);
+        // A negative height is a number of lines.
+        layout.set_height(-i32::from(self.max_lines.max(1)));
+    }
+}
+
+/// Something drawn in place of a [`Text`]'s text, e.g. to show a value more
+/// compactly. See [`Text::graphic`].
+///
+/// [`Text`]: struct.Text.html
+/// [`Text::graphic`]: struct.Text.html#structfield.graphic
+#[derive(Clone, Debug, PartialEq)]
+pub enum Graphic {
+    Gauge(Gauge),
+    Graph(Graph),
+    Icon(Icon),
+    Dot(Dot),
+}
+
+/// Which way a [`Gauge`] fills up.
+///
+/// [`Gauge`]: struct.Gauge.html
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Orientation {
+    /// From left to right.
+    Horizontal,
+    /// From bottom to top.
+    Vertical,
+}
+
+/// A bar which is filled in proportion to a value, e.g. the volume or the
+/// charge of a battery.
+///
+/// The gauge is `width` pixels wide (plus the text's padding), and as tall as
+/// a line of text in the text's font. `value` is from 0.0 (empty) to 1.0
+/// (full). The filled part is drawn in `fill_color`, and the rest in
+/// `empty_color`, if any, over the text's background.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Gauge {
+    pub value: f64,
+    pub width: f64,
+    pub fill_color: Color,
+    pub empty_color: Option<Color>,
+    pub orientation: Orientation,
+}
+
+impl Gauge {
+    fn render(&self, context: &Context, width: f64, height: f64) {
+        if let Some(ref empty_color) = self.empty_color {
+            empty_color.apply_to_context(context);
+            context.rectangle(0.0, 0.0, width, height);
+            context.fill();
+        }
+
+        let value = self.value.clamp(0.0, 1.0);
+        self.fill_color.apply_to_context(context);
+        match self.orientation {
+            Orientation::Horizontal => context.rectangle(0.0, 0.0, width * value, height),
+            Orientation::Vertical => {
+                context.rectangle(0.0, height * (1.0 - value), width, height * value)
+            }
+        }
+        context.fill();
+    }
+}
+
+/// How a [`Graph`] draws its samples.
+///
+/// [`Graph`]: struct.Graph.html
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GraphStyle {
+    /// The area under the samples is filled in.
+    Filled,
+    /// A line joins the samples.
+    Line,
+}
+
+/// A sparkline of recent samples, e.g. CPU usage or network traffic over
+/// the last minute.
+///
+/// The graph keeps up to `capacity` samples, dropping the oldest as new ones
+/// are [`push()`]ed, and draws them from left (oldest) to right (newest)
+/// across `width` pixels (plus the text's padding), as tall as a line of text
+/// in the text's font. Samples are scaled so that `max`, if any, is at the
+/// top, or else so that the largest sample is. Samples are drawn in `color`,
+/// and the rest of the graph in `empty_color`, if any, over the text's
+/// background.
+///
+/// Widgets should keep their `Graph`, pushing each new sample onto it and
+/// showing a clone of it.
+///
+/// [`push()`]: #method.push
+#[derive(Clone, Debug, PartialEq)]
+pub struct Graph {
+    samples: VecDeque<f64>,
+    capacity: usize,
+    /// The value at the top of the graph, or `None` to scale the largest
+    /// sample to the top.
+    pub max: Option<f64>,
+    /// How wide the graph is, in pixels, not counting the text's padding.
+    pub width: f64,
+    /// The color the samples are drawn in.
+    pub color: Color,
+    /// The color of the rest of the graph, or `None` to leave the text's
+    /// background.
+    pub empty_color: Option<Color>,
+    /// Whether the area under the samples is filled, or a line is drawn.
+    pub style: GraphStyle,
+}
+
+impl Graph {
+    /// Creates an empty, filled `Graph` of up to `capacity` samples, with no
+    /// `max`.
+    pub fn new(capacity: usize, width: f64, color: Color) -> Graph {
+        Graph {
+            samples: VecDeque::with_capacity(capacity),
+            capacity,
+            max: None,
+            width,
+            color,
+            empty_color: None,
+            style: GraphStyle::Filled,
+        }
+    }
+
+    /// Adds `sample` as the newest sample, dropping the oldest one if the
+    /// graph is full. Negative samples are treated as 0.
+    pub fn push(&mut self, sample: f64) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.samples.len() == self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample.max(0.0));
+    }
+
+    /// Returns the samples, from oldest to newest.
+    pub fn samples(&self) -> impl Iterator<Item = f64> + '_ {
+        self.samples.iter().cloned()
+    }
+
+    /// Returns where each sample is drawn, as `(x, y)` from the top left of
+    /// a graph of `width` by `height`, or nothing if every sample is 0.
+    fn points(&self, width: f64, height: f64) -> Vec<(f64, f64)> {
+        let max = self
+            .max
+            .unwrap_or_else(|| self.samples().fold(0.0, f64::max));
+        if self.samples.is_empty() || max <= 0.0 {
+            return Vec::new();
+        }
+
+        // The newest sample is at the right edge, however many there are, so
+        // that a graph which is filling up scrolls in from the right.
+        let step = width / (self.capacity.max(2) - 1) as f64;
+        let start = width - step * (self.samples.len() - 1) as f64;
+        self.samples()
+            .enumerate()
+            .map(|(i, sample)| {
+                let y = height * (1.0 - (sample / max).min(1.0));
+                (start + step * i as f64, y)
+            })
+            .collect()
+    }
+
+    fn render(&self, context: &Context, width: f64, height: f64) {
+        if let Some(ref empty_color) = self.empty_color {
+            empty_color.apply_to_context(context);
+            context.rectangle(0.0, 0.0, width, height);
+            context.fill();
+        }
+
+        let points = self.points(width, height);
+        let start = match points.first() {
+            Some(&(start, _)) => start,
+            None => return,
+        };
+
+        self.color.apply_to_context(context);
+        match self.style {
+            GraphStyle::Filled => {
+                context.move_to(start, height);
+                for (x, y) in points {
+                    context.line_to(x, y);
+                }
+                context.line_to(width, height);
+                context.close_path();
+                context.fill();
+            }
+            GraphStyle::Line => {
+                for (i, (x, y)) in points.into_iter().enumerate() {
+                    if i == 0 {
+                        context.move_to(x, y);
+                    } else {
+                        context.line_to(x, y);
+                    }
+                }
+                context.set_line_width(1.0);
+                context.stroke();
+            }
+        }
+    }
+}
+
+/// The shape of a [`Dot`].
+///
+/// [`Dot`]: struct.Dot.html
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DotShape {
+    Circle,
+    Square,
+}
+
+/// A small shape, e.g. standing in for a workspace's name on a minimal bar.
+///
+/// The dot is `size` pixels across, centred on a line of text in the text's
+/// font, and drawn in the text's `fg_color`. It is either `filled` in, or
+/// just outlined.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Dot {
+    pub shape: DotShape,
+    pub size: f64,
+    pub filled: bool,
+}
+
+impl Dot {
+    fn render(&self, context: &Context, color: &Color, height: f64) {
+        // Outlines are drawn inside the dot's size.
+        let line_width = if self.filled { 0.0 } else { 1.5 };
+        let size = self.size - line_width;
+        let (x, y) = (self.size / 2.0, height / 2.0);
+        color.apply_to_context(context);
+        match self.shape {
+            DotShape::Circle => context.arc(x, y, size / 2.0, 0.0, 2.0 * f64::consts::PI),
+            DotShape::Square => {
+                context.rectangle(x - size / 2.0, y - size / 2.0, size, size);
+            }
+        }
+        if self.filled {
+            context.fill();
+        } else {
+            context.set_line_width(line_width);
+            context.stroke();
+        }
+    }
+}
+
+/// A bitmap, e.g. a window's icon, scaled to be as tall as a line of text in
+/// the text's font.
+///
+/// The pixels are ARGB, with 8 bits per channel, not premultiplied by the
+/// alpha, as in the `_NET_WM_ICON` [`EWMH`] property.
+///
+/// [`EWMH`]: https://specifications.freedesktop.org/wm-spec/wm-spec-latest.html
+#[derive(Clone, Debug, PartialEq)]
+pub struct Icon {
+    width: u32,
+    height: u32,
+    // Shared, as texts are cloned every time they are compared or shown.
+    pixels: Rc<[u32]>,
+}
+
+impl Icon {
+    /// Creates an `Icon` `width` by `height` pixels, from `pixels` row by row.
+    /// Returns None if there aren't `width * height` pixels, or none at all.
+    pub fn new(width: u32, height: u32, pixels: Vec<u32>) -> Option<Icon> {
+        let len = (width as usize).checked_mul(height as usize)?;
+        if len == 0 || pixels.len() != len {
+            return None;
+        }
+        Some(Icon {
+            width,
+            height,
+            pixels: pixels.into(),
+        })
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    fn width_at(&self, height: f64) -> f64 {
+        (height * f64::from(self.width) / f64::from(self.height)).round()
+    }
+
+    fn render(&self, context: &Context, height: f64) -> Result<()> {
+        // Cairo wants native-endian words of premultiplied alpha.
+        let data = self
+            .pixels
+            .iter()
+            .flat_map(|&pixel| {
+                let alpha = pixel >> 24;
+                let premultiply = |shift: u32| ((pixel >> shift & 0xff) * alpha / 255) << shift;
+                (alpha << 24 | premultiply(16) | premultiply(8) | premultiply(0)).to_ne_bytes()
+            })
+            .collect::<Vec<u8>>();
+        let (width, stride) = (self.width as i32, self.width as i32 * 4);
+        let surface =
+            ImageSurface::create_for_data(data, Format::ARgb32, width, self.height as i32, stride)
+                .map_err(|status| Error::Cairo(format!("Failed to create icon: {:?}", status)))?;
+        let scale = height / f64::from(self.height);
+        context.save();
+        context.scale(scale, scale);
+        context.set_source_surface(&surface, 0.0, 0.0);
+        context.paint();
+        context.restore();
+        Ok(())
+    }
+}
+
+/// One of the texts which a widget shows on the bar.
+///
+/// Texts are created with [`Text::new()`] and its `with_*()` methods, which
+/// keep widgets working as more fields are added.
+///
+/// [`Text::new()`]: #method.new
 #[derive(Clone, Debug, PartialEq)]
 pub struct Text {
     pub attr: Attributes,
     pub text: String,
     pub stretch: bool,
+    /// If set, the bar clears the text once this long has passed since the
+    /// widget yielded it, unless the widget has yielded new texts since.
+    pub timeout: Option<Duration>,
+    /// Whether `text` is [Pango markup], e.g. `<b>12</b>:34`, which lets one
+    /// text mix colors and weights. Invalid markup is logged, and shown as
+    /// plain text.
+    ///
+    /// [Pango markup]: https://developer.gnome.org/pango/stable/pango-Markup.html
+    pub use_markup: bool,
+    /// Whether the text may be wrapped onto more than one line. If not, it is
+    /// shown on one line, and ellipsized if there isn't room for all of it.
+    pub wrap: Option<Wrap>,
+    /// Something to draw instead of the text, which is then ignored. The
+    /// text's background and padding are used, but its `fg_color` is only
+    /// used by a [`Dot`].
+    ///
+    /// [`Dot`]: struct.Dot.html
+    pub graphic: Option<Graphic>,
+}
+
+/// Joins the plain text of each of a widget's `texts` with spaces, skipping
+/// any which are blank.
+pub(crate) fn join_plain_text(texts: &[Text]) -> String {
+    texts
+        .iter()
+        .map(Text::plain_text)
+        .filter(|text| !text.trim().is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
 }
 
 impl Text {
-    pub(crate) fn compute(self, surface: &Surface) -> Result<ComputedText> {
+    /// Creates a text showing `text` with the given [`Attributes`], on one
+    /// line, as plain text and as wide as it needs to be. The other fields
+    /// can be set with the `with_*()` methods, e.g.
+    /// `Text::new(attr, "12:34".to_owned()).with_stretch()`.
+    ///
+    /// [`Attributes`]: struct.Attributes.html
+    pub fn new(attr: Attributes, text: String) -> Text {
+        Text {
+            attr,
+            text,
+            stretch: false,
+            timeout: None,
+            use_markup: false,
+            wrap: None,
+            graphic: None,
+        }
+    }
+
+    /// Makes the text take up an equal share of any space left on the bar.
+    pub fn with_stretch(mut self) -> Text {
+        self.stretch = true;
+        self
+    }
+
+    /// Clears the text once `timeout` has passed. See [`timeout`].
+    ///
+    /// [`timeout`]: #structfield.timeout
+    pub fn with_timeout(mut self, timeout: Duration) -> Text {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Treats the text as Pango markup. See [`use_markup`].
+    ///
+    /// [`use_markup`]: #structfield.use_markup
+    pub fn with_markup(mut self) -> Text {
+        self.use_markup = true;
+        self
+    }
+
+    /// Lets the text wrap onto more than one line. See [`Wrap`].
+    ///
+    /// [`Wrap`]: struct.Wrap.html
+    pub fn with_wrap(mut self, wrap: Wrap) -> Text {
+        self.wrap = Some(wrap);
+        self
+    }
+
+    /// Draws `graphic` instead of the text. See [`graphic`].
+    ///
+    /// [`graphic`]: #structfield.graphic
+    pub fn with_graphic(mut self, graphic: Graphic) -> Text {
+        self.graphic = Some(graphic);
+        self
+    }
+
+    /// The text without any markup, or nothing if a graphic is drawn instead,
+    /// for showing somewhere other than on a bar.
+    pub(crate) fn plain_text(&self) -> String {
+        if self.graphic.is_some() {
+            return String::new();
+        }
+        match pango::parse_markup(&self.text, '\0') {
+            Ok((_, text, _)) if self.use_markup => text,
+            _ => self.text.clone(),
+        }
+    }
+
+    pub(crate) fn compute(
+        self,
+        surface: &Surface,
+        orientation: BarOrientation,
+    ) -> Result<ComputedText> {
         let (width, height) = {
             let context = Context::new(&surface);
             let layout = create_pango_layout(&context)?;
-            layout.set_text(&self.text);
+            if let Err(e) = set_layout_text(&layout, &self.text, self.use_markup) {
+                warn!("Invalid markup in {:?}: {}", self.text, e);
+            }
             layout.set_font_description(Some(&self.attr.font.0));
+            if let Some(ref wrap) = self.wrap {
+                wrap.apply_to_layout(&layout);
+            }
+
+            // Graphics are as tall as a line of text in the font.
+            if self.graphic.is_some() {
+                layout.set_text("");
+            }
 
             let padding = &self.attr.padding;
             let (text_width, text_height) = layout.get_pixel_size();
-            let width = f64::from(text_width) + padding.left + padding.right;
+            let text_width = match self.graphic {
+                Some(Graphic::Gauge(ref gauge)) => gauge.width,
+                Some(Graphic::Graph(ref graph)) => graph.width,
+                Some(Graphic::Icon(ref icon)) => icon.width_at(f64::from(text_height)),
+                Some(Graphic::Dot(ref dot)) => dot.size,
+                None => f64::from(text_width),
+            };
+            let width = text_width + padding.left + padding.right;
             let height = f64::from(text_height) + padding.top + padding.bottom;
-            (width, height)
+            if orientation == BarOrientation::Vertical {
+                (height, width)
+            } else {
+                (width, height)
+            }
         };
 
         Ok(ComputedText {
             attr: self.attr,
             text: self.text,
             stretch: self.stretch,
+            use_markup: self.use_markup,
+            wrap: self.wrap,
+            graphic: self.graphic,
+            backdrop: None,
             x: 0.0,
             y: 0.0,
             width,
+            natural_width: width,
             height,
         })
     }
@@ -141,7 +1181,12 @@ impl Text {
 // having to call the (relatively) expensive .compute().
 impl PartialEq<ComputedText> for Text {
     fn eq(&self, other: &ComputedText) -> bool {
-        self.attr == other.attr && self.text == other.text && self.stretch == other.stretch
+        self.attr == other.attr
+            && self.text == other.text
+            && self.stretch == other.stretch
+            && self.use_markup == other.use_markup
+            && self.wrap == other.wrap
+            && self.graphic == other.graphic
     }
 }
 
@@ -150,26 +1195,60 @@ pub(crate) struct ComputedText {
     pub attr: Attributes,
     pub text: String,
     pub stretch: bool,
+    pub use_markup: bool,
+    pub wrap: Option<Wrap>,
+    pub graphic: Option<Graphic>,
+    pub backdrop: Option<Backdrop>,
 
     pub x: f64,
     pub y: f64,
     pub width: f64,
+    // The width the text needs, before it is stretched or truncated.
+    pub natural_width: f64,
     pub height: f64,
 }
 
 impl ComputedText {
-    pub fn render(&self, surface: &Surface) -> Result<()> {
+    /// Draws the text over the bar's `background`.
+    pub fn render(
+        &self,
+        surface: &Surface,
+        background: &Color,
+        orientation: BarOrientation,
+    ) -> Result<()> {
         let context = Context::new(&surface);
+        orientation.apply(&context);
         let layout = create_pango_layout(&context)?;
-        layout.set_text(&self.text);
+        // Any error was logged by `Text::compute()`.
+        let _ = set_layout_text(&layout, &self.text, self.use_markup);
         layout.set_font_description(Some(&self.attr.font.0));
 
+        // Keep within the (possibly rounded) background of our section.
+        if let Some(ref backdrop) = self.backdrop {
+            backdrop.path(&context);
+            context.clip();
+        }
+
         context.translate(self.x, self.y);
+        context.rectangle(0.0, 0.0, self.width, self.height);
+        context.clip();
 
         // Set the width/height on the Pango layout so that it word-wraps/ellipises.
+        // Upright texts on a vertical bar are drawn across it. Wrapped texts
+        // keep their wrap mode, but may have been truncated to less than
+        // their wrap width.
+        let upright = orientation == BarOrientation::Vertical;
+        let (width, height) = if upright {
+            (self.height, self.width)
+        } else {
+            (self.width, self.height)
+        };
         let padding = &self.attr.padding;
-        let text_width = self.width - padding.left - padding.right;
-        let text_height = self.height - padding.top - padding.bottom;
+        let text_width = width - padding.left - padding.right;
+        let text_height = height - padding.top - padding.bottom;
+        if let Some(ref wrap) = self.wrap {
+            wrap.apply_to_layout(&layout);
+        }
         layout.set_ellipsize(EllipsizeMode::End);
         layout.set_width(text_width as i32 * pango::SCALE);
         layout.set_height(text_height as i32 * pango::SCALE);
@@ -177,19 +1256,97 @@ impl ComputedText {
             layout.set_alignment(Alignment::Center)
         }
 
-        let bg_color = &self.attr.bg_color.clone().unwrap_or_default();
-        bg_color.apply_to_context(&context);
+        // Replace whatever was drawn here before with the bar's background,
+        // then draw our section's background and our own over it, as any of
+        // them could be translucent.
         // FIXME: The use of `height` isnt' right here: we want to do the
         // full height of the bar, not the full height of the text. It
         // would be useful if we could do Surface.get_height(), but that
         // doesn't seem to be available in cairo-rs for some reason?
+        context.set_operator(Operator::Source);
+        background.apply_to_context(&context);
         context.rectangle(0.0, 0.0, self.width, self.height);
         context.fill();
+        context.set_operator(Operator::Over);
+        let bg_colors = self
+            .backdrop
+            .as_ref()
+            .and_then(|b| b.color.as_ref())
+            .into_iter()
+            .chain(self.attr.bg_color.as_ref());
+        for bg_color in bg_colors {
+            bg_color.apply_to_context(&context);
+            context.rectangle(0.0, 0.0, self.width, self.height);
+            context.fill();
+        }
+        if let Some(ref border) = self.attr.border {
+            context.save();
+            if upright {
+                context.transform(transpose());
+            }
+            border.render(&context, width, height);
+            context.restore();
+        }
 
-        self.attr.fg_color.apply_to_context(&context);
+        if text_width < 1.0 {
+            // Pango would take a negative width to mean "don't ellipsize". The
+            // text may have been truncated to nothing, so don't draw it at all.
+            return Ok(());
+        }
+
+        if upright {
+            // Turn the text back the right way, which swaps its axes again.
+            context.transform(transpose());
+            pangocairo::functions::update_layout(&context, &layout);
+        }
         context.translate(padding.left, padding.top);
-        show_pango_layout(&context, &layout);
+        match self.graphic {
+            Some(Graphic::Gauge(ref gauge)) => gauge.render(&context, text_width, text_height),
+            Some(Graphic::Graph(ref graph)) => graph.render(&context, text_width, text_height),
+            Some(Graphic::Icon(ref icon)) => icon.render(&context, text_height)?,
+            Some(Graphic::Dot(ref dot)) => dot.render(&context, &self.attr.fg_color, text_height),
+            None => {
+                self.attr.fg_color.apply_to_context(&context);
+                show_pango_layout(&context, &layout);
+            }
+        }
 
-        Ok(())
+        match context.status() {
+            cairo::Status::Success => Ok(()),
+            status => Err(Error::Cairo(format!("Failed to draw text: {:?}", status))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Color, Graph};
+
+    #[test]
+    fn graphs_recent_samples() {
+        let mut graph = Graph::new(3, 20.0, Color::white());
+        assert_eq!(graph.points(20.0, 10.0), Vec::new());
+
+        graph.push(1.0);
+        graph.push(-1.0);
+        graph.push(4.0);
+        graph.push(2.0);
+        // The oldest sample was dropped, and the negative one is 0.
+        assert_eq!(graph.samples().collect::<Vec<_>>(), vec![0.0, 4.0, 2.0]);
+        // With no max, the largest sample is at the top.
+        assert_eq!(
+            graph.points(20.0, 10.0),
+            vec![(0.0, 10.0), (10.0, 0.0), (20.0, 5.0)]
+        );
+        graph.max = Some(8.0);
+        assert_eq!(
+            graph.points(20.0, 10.0),
+            vec![(0.0, 10.0), (10.0, 5.0), (20.0, 7.5)]
+        );
+
+        let mut empty = Graph::new(0, 20.0, Color::white());
+        empty.push(1.0);
+        assert_eq!(empty.samples().count(), 0);
+        assert_eq!(empty.points(20.0, 10.0), Vec::new());
     }
 }
@@ -0,0 +1,510 @@
+//! The timer shared by widgets which poll, and the low-power mode which slows
+//! them down. Polling is paused entirely while no bar is visible, and can
+//! adapt to how often each widget's values change.
+
+use std::cell::{Cell, RefCell};
+use std::cmp;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use futures::task::{self, Task};
+use futures::{stream, Async, Future, Poll, Stream};
+use log::warn;
+use tokio_timer::Sleep;
+
+use crate::error::Error;
+use crate::text::Text;
+
+// The bounds on how many times their usual interval polling widgets may wait,
+// with adaptive polling.
+const MIN_ADAPTIVE: f64 = 0.1;
+const MAX_ADAPTIVE: f64 = 1000.0;
+
+/// An enum specifying when Cnx runs in low-power mode.
+///
+/// In low-power mode, widgets which poll for changes do so less often (see
+/// [`Cnx::set_low_power_factor()`]) and animations are disabled, so that Cnx
+/// wakes the CPU up less often.
+///
+/// Passed to [`Cnx::set_low_power()`].
+///
+/// [`Cnx::set_low_power()`]: struct.Cnx.html#method.set_low_power
+/// [`Cnx::set_low_power_factor()`]: struct.Cnx.html#method.set_low_power_factor
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum LowPower {
+    /// Use low-power mode while a [`Battery`] widget reports that the battery
+    /// is discharging. This is the default. Without a `Battery` widget, Cnx
+    /// never uses low-power mode.
+    ///
+    /// [`Battery`]: widgets/struct.Battery.html
+    #[default]
+    Auto,
+    /// Always use low-power mode.
+    Always,
+    /// Never use low-power mode.
+    Never,
+}
+
+/// The power-saving state shared by every `Timer` and `Animation`.
+#[derive(Debug)]
+pub(crate) struct PowerState {
+    mode: Cell<LowPower>,
+    factor: Cell<u32>,
+    discharging: Cell<bool>,
+    paused: Cell<bool>,
+    // The least and most times the usual interval that polling widgets wait,
+    // if their intervals adapt to how often their values change.
+    adaptive: Cell<Option<(f64, f64)>>,
+    // The tasks waiting for polling to be resumed.
+    waiting: RefCell<Vec<Task>>,
+}
+
+impl PowerState {
+    pub fn new() -> PowerState {
+        PowerState {
+            mode: Cell::new(LowPower::default()),
+            factor: Cell::new(3),
+            discharging: Cell::new(false),
+            paused: Cell::new(false),
+            adaptive: Cell::new(None),
+            waiting: RefCell::new(Vec::new()),
+        }
+    }
+
+    pub fn set_mode(&self, mode: LowPower) {
+        self.mode.set(mode);
+    }
+
+    pub fn set_factor(&self, factor: u32) {
+        self.factor.set(factor.max(1));
+    }
+
+    /// Makes polling widgets wait between `min` and `max` times their usual
+    /// interval, depending on how often their values change.
+    pub fn set_adaptive(&self, min: f64, max: f64) {
+        if min.is_nan() || max.is_nan() {
+            warn!("Adaptive polling needs numbers, not {} and {}", min, max);
+            return;
+        }
+        // Polling without waiting at all would never let anything else run,
+        // and intervals too long for a `Duration` would panic.
+        let min = min.clamp(MIN_ADAPTIVE, MAX_ADAPTIVE);
+        self.adaptive.set(Some((min, max.clamp(min, MAX_ADAPTIVE))));
+    }
+
+    /// Records whether the battery is discharging, for `LowPower::Auto`.
+    pub fn set_discharging(&self, discharging: bool) {
+        self.discharging.set(discharging);
+    }
+
+    pub fn is_low_power(&self) -> bool {
+        match self.mode.get() {
+            LowPower::Auto => self.discharging.get(),
+            LowPower::Always => true,
+            LowPower::Never => false,
+        }
+    }
+
+    /// Pauses or resumes polling. When resumed, every widget which missed a
+    /// tick while paused ticks immediately.
+    pub fn set_paused(&self, paused: bool) {
+        if self.paused.replace(paused) && !paused {
+            for task in self.waiting.borrow_mut().drain(..) {
+                task.notify();
+            }
+        }
+    }
+
+    /// Lengthens a polling `interval`, if we're in low-power mode.
+    fn scale(&self, interval: Duration) -> Duration {
+        if self.is_low_power() {
+            interval * self.factor.get()
+        } else {
+            interval
+        }
+    }
+}
+
+/// A [`tokio_timer::Timer`] which knows about low-power mode.
+///
+/// [`tokio_timer::Timer`]: https://docs.rs/tokio-timer/0.1/tokio_timer/struct.Timer.html
+#[derive(Clone)]
+pub(crate) struct Timer {
+    inner: tokio_timer::Timer,
+    power: Rc<PowerState>,
+}
+
+impl Timer {
+    pub fn new(inner: tokio_timer::Timer, power: Rc<PowerState>) -> Timer {
+        Timer { inner, power }
+    }
+
+    pub fn power(&self) -> &PowerState {
+        &self.power
+    }
+
+    /// Sleeps for exactly `duration`, regardless of low-power mode.
+    ///
+    /// This is for widgets which need to wake up at a particular time, like
+    /// the `Clock`, rather than those which poll.
+    pub fn sleep(&self, duration: Duration) -> Sleep {
+        self.inner.sleep(duration)
+    }
+
+    /// Returns a stream which yields every `interval`, or less often while in
+    /// low-power mode.
+    ///
+    /// The interval is re-scaled after every tick, so that entering or leaving
+    /// low-power mode takes effect from the next tick. While polling is
+    /// paused, ticks are held back until it is resumed.
+    pub fn interval(&self, interval: Duration) -> Box<dyn Stream<Item = (), Error = Error>> {
+        self.ticks(move || interval)
+    }
+
+    /// Returns a `Pace` for a widget which polls every `interval`.
+    pub fn pace(&self, interval: Duration) -> Pace {
+        Pace {
+            power: self.power.clone(),
+            base: interval,
+            current: Rc::new(Cell::new(interval)),
+        }
+    }
+
+    /// Returns a stream which yields at the `pace`'s current interval, which
+    /// is otherwise like `interval()`.
+    pub fn paced(&self, pace: &Pace) -> Box<dyn Stream<Item = (), Error = Error>> {
+        let current = pace.current.clone();
+        self.ticks(move || current.get())
+    }
+
+    /// Returns a stream which yields after each `next()` interval, scaled for
+    /// low-power mode.
+    fn ticks<F>(&self, next: F) -> Box<dyn Stream<Item = (), Error = Error>>
+    where
+        F: Fn() -> Duration + 'static,
+    {
+        let inner = self.inner.clone();
+        let power = self.power.clone();
+        Box::new(stream::unfold((), move |()| {
+            let resumed = Resumed {
+                power: power.clone(),
+            };
+            let sleep = inner
+                .sleep(power.scale(next()))
+                .map_err(Error::from)
+                .and_then(|()| resumed);
+            Some(sleep.map(|()| ((), ())))
+        }))
+    }
+
+    /// Returns the items of the streams returned by `connect`, calling it
+    /// again to reconnect whenever a stream ends or fails.
+    ///
+    /// Failures are logged as warnings, naming the `service`. While
+    /// connections fail without yielding anything, the wait before each
+    /// attempt doubles, up to five minutes.
+    pub fn reconnecting<T, F>(
+        &self,
+        service: String,
+        connect: F,
+    ) -> Box<dyn Stream<Item = T, Error = Error>>
+    where
+        T: 'static,
+        F: FnMut() -> Result<Box<dyn Stream<Item = T, Error = Error>>, Error> + 'static,
+    {
+        // The number of connection attempts since we last received anything.
+        let failures = Rc::new(Cell::new(0));
+        let timer = self.clone();
+        let connect = Rc::new(RefCell::new(connect));
+        let connections = stream::unfold((), move |()| {
+            let attempt = failures.get();
+            failures.set(attempt + 1);
+            let failures = failures.clone();
+            let service = service.clone();
+            let connect = connect.clone();
+            let connection = timer.sleep(backoff(attempt)).map(move |()| {
+                let connected = (*connect.borrow_mut())();
+                let items = connected
+                    .unwrap_or_else(|e| Box::new(stream::once(Err(e))))
+                    .then(move |result| match result {
+                        Ok(item) => {
+                            failures.set(1);
+                            Ok(Some(item))
+                        }
+                        Err(e) => {
+                            warn!("Lost connection to {}: {}", service, e);
+                            Ok(None)
+                        }
+                    })
+                    .take_while(|item| Ok(item.is_some()))
+                    .map(Option::unwrap);
+                (items, ())
+            });
+            Some(connection.map_err(Error::from))
+        });
+        Box::new(connections.flatten())
+    }
+
+    /// Returns the texts yielded by `stream`, yielding them again without
+    /// each text whose [`timeout`] has passed.
+    ///
+    /// The bar wraps every widget's stream with this, so that widgets which
+    /// only show something briefly, like a notification, don't have to keep
+    /// track of time themselves.
+    ///
+    /// [`timeout`]: ../text/struct.Text.html#structfield.timeout
+    pub fn expiring<S>(&self, stream: S) -> Expiring<S>
+    where
+        S: Stream<Item = Vec<Text>, Error = Error>,
+    {
+        Expiring {
+            stream,
+            timer: self.inner.clone(),
+            texts: Vec::new(),
+            expiry: None,
+            done: false,
+        }
+    }
+
+    /// Returns a stream which yields once `quiet` has passed without `stream`
+    /// yielding, so that a burst of items is only acted on once.
+    ///
+    /// This is for widgets which are told about changes in bursts, like a
+    /// network interface connecting, and only need to know once it's done.
+    pub fn debounced<S>(&self, stream: S, quiet: Duration) -> Debounced<S>
+    where
+        S: Stream<Error = Error>,
+    {
+        Debounced {
+            stream: stream.fuse(),
+            timer: self.inner.clone(),
+            quiet,
+            wait: None,
+        }
+    }
+}
+
+/// The interval of one polling widget, which adapts to how often its values
+/// change, if adaptive polling is on (see `Cnx::set_adaptive_polling()`).
+#[derive(Clone)]
+pub(crate) struct Pace {
+    power: Rc<PowerState>,
+    // The widget's usual interval.
+    base: Duration,
+    current: Rc<Cell<Duration>>,
+}
+
+impl Pace {
+    /// Whether the interval adapts, so that `record()` needs to be told what
+    /// each tick found.
+    pub fn is_adaptive(&self) -> bool {
+        self.power.adaptive.get().is_some()
+    }
+
+    /// Records whether the widget's latest tick `changed` its value,
+    /// lengthening or shortening the interval before the next tick.
+    pub fn record(&self, changed: bool) {
+        let next = match self.power.adaptive.get() {
+            Some(bounds) => adapt(self.current.get(), self.base, changed, bounds),
+            None => self.base,
+        };
+        self.current.set(next);
+    }
+}
+
+/// The interval after `current`, which halves when a widget's value has
+/// `changed`, and otherwise grows by half, staying between `min` and `max`
+/// times the `base` interval.
+fn adapt(current: Duration, base: Duration, changed: bool, (min, max): (f64, f64)) -> Duration {
+    let next = if changed {
+        current / 2
+    } else {
+        current.mul_f64(1.5)
+    };
+    next.max(base.mul_f64(min)).min(base.mul_f64(max))
+}
+
+/// How long to wait before reconnecting, after `failures` attempts in a row
+/// which didn't receive anything.
+fn backoff(failures: u32) -> Duration {
+    match failures {
+        0 => Duration::from_secs(0),
+        n => cmp::min(
+            Duration::from_secs(1 << cmp::min(n - 1, 16)),
+            Duration::from_secs(300),
+        ),
+    }
+}
+
+/// The stream returned by [`Timer::expiring()`].
+///
+/// [`Timer::expiring()`]: struct.Timer.html#method.expiring
+pub(crate) struct Expiring<S> {
+    stream: S,
+    timer: tokio_timer::Timer,
+    // The stream's last texts, along with when each of them expires.
+    texts: Vec<(Text, Option<Instant>)>,
+    // Wakes us when the first of the texts expires.
+    expiry: Option<Sleep>,
+    // Whether `stream` has ended. We still clear its last texts.
+    done: bool,
+}
+
+impl<S> Expiring<S> {
+    /// Waits for the next of the texts to expire, if any will.
+    fn schedule(&mut self, now: Instant) {
+        self.expiry = self
+            .texts
+            .iter()
+            .filter_map(|&(_, expires)| expires)
+            .min()
+            .map(|expires| self.timer.sleep(expires.saturating_duration_since(now)));
+    }
+}
+
+impl<S> Stream for Expiring<S>
+where
+    S: Stream<Item = Vec<Text>, Error = Error>,
+{
+    type Item = Vec<Text>;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        if !self.done {
+            match self.stream.poll()? {
+                Async::Ready(Some(texts)) => {
+                    let now = Instant::now();
+                    self.texts = texts
+                        .iter()
+                        .map(|text| (text.clone(), text.timeout.map(|timeout| now + timeout)))
+                        .collect();
+                    self.schedule(now);
+                    return Ok(Async::Ready(Some(texts)));
+                }
+                Async::Ready(None) => self.done = true,
+                Async::NotReady => {}
+            }
+        }
+
+        let expired = match self.expiry {
+            Some(ref mut expiry) => expiry.poll()?.is_ready(),
+            None if self.done => return Ok(Async::Ready(None)),
+            None => false,
+        };
+        if !expired {
+            return Ok(Async::NotReady);
+        }
+        let now = Instant::now();
+        let len = self.texts.len();
+        self.texts
+            .retain(|&(_, expires)| expires.is_none_or(|expires| expires > now));
+        self.schedule(now);
+        if self.texts.len() == len {
+            // The timer woke us a little early.
+            return self.poll();
+        }
+        let texts = self.texts.iter().map(|(text, _)| text.clone()).collect();
+        Ok(Async::Ready(Some(texts)))
+    }
+}
+
+/// The stream returned by [`Timer::debounced()`].
+///
+/// [`Timer::debounced()`]: struct.Timer.html#method.debounced
+pub(crate) struct Debounced<S> {
+    stream: stream::Fuse<S>,
+    timer: tokio_timer::Timer,
+    quiet: Duration,
+    // The wait after the latest item, if there's been one since we last
+    // yielded.
+    wait: Option<Sleep>,
+}
+
+impl<S> Stream for Debounced<S>
+where
+    S: Stream<Error = Error>,
+{
+    type Item = ();
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        loop {
+            match self.stream.poll()? {
+                Async::Ready(Some(_)) => self.wait = Some(self.timer.sleep(self.quiet)),
+                // Act on the last burst before ending.
+                Async::Ready(None) if self.wait.is_none() => return Ok(Async::Ready(None)),
+                Async::Ready(None) | Async::NotReady => break,
+            }
+        }
+        let waited = match self.wait {
+            Some(ref mut wait) => wait.poll()?.is_ready(),
+            None => false,
+        };
+        if waited {
+            self.wait = None;
+            Ok(Async::Ready(Some(())))
+        } else {
+            Ok(Async::NotReady)
+        }
+    }
+}
+
+/// A future which resolves once polling is no longer paused.
+struct Resumed {
+    power: Rc<PowerState>,
+}
+
+impl Future for Resumed {
+    type Item = ();
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        if self.power.paused.get() {
+            let mut waiting = self.power.waiting.borrow_mut();
+            if !waiting.iter().any(Task::will_notify_current) {
+                waiting.push(task::current());
+            }
+            Ok(Async::NotReady)
+        } else {
+            Ok(Async::Ready(()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{adapt, PowerState};
+    use std::time::Duration;
+
+    #[test]
+    fn works() {
+        let base = Duration::from_secs(60);
+        let bounds = (0.5, 4.0);
+        assert_eq!(adapt(base, base, false, bounds), Duration::from_secs(90));
+        assert_eq!(
+            adapt(Duration::from_secs(200), base, false, bounds),
+            Duration::from_secs(240)
+        );
+        assert_eq!(adapt(base, base, true, bounds), Duration::from_secs(30));
+        assert_eq!(
+            adapt(Duration::from_secs(30), base, true, bounds),
+            Duration::from_secs(30)
+        );
+    }
+
+    #[test]
+    fn bounds_adaptive_polling() {
+        let power = PowerState::new();
+        power.set_adaptive(0.5, f64::INFINITY);
+        assert_eq!(power.adaptive.get(), Some((0.5, 1000.0)));
+        power.set_adaptive(0.0, f64::NAN);
+        assert_eq!(power.adaptive.get(), Some((0.5, 1000.0)));
+        power.set_adaptive(0.0, 2.0);
+        assert_eq!(power.adaptive.get(), Some((0.1, 2.0)));
+
+        power.set_adaptive(1.0, f64::INFINITY);
+        let day = Duration::from_secs(24 * 60 * 60);
+        let bounds = power.adaptive.get().unwrap();
+        assert_eq!(adapt(day * 1000, day, false, bounds), day * 1000);
+    }
+}
@@ -0,0 +1,489 @@
+//! A minimal TOML parser, for reading configuration files.
+//!
+//! Only the parts of TOML which a configuration file needs are supported:
+//! tables, arrays of tables, dotted keys, basic and literal strings, integers,
+//! floats, booleans, arrays and inline tables. Multi-line strings and dates
+//! aren't.
+
+use std::iter::Peekable;
+use std::str::Chars;
+
+use crate::error::format_err;
+use crate::Result;
+
+type Table = Vec<(String, Value)>;
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Value {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    Array(Vec<Value>),
+    // Tables are kept in document order, like JSON objects.
+    Table(Table),
+}
+
+impl Value {
+    /// Gets the value of `key`, if this is a table which contains it.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match *self {
+            Value::Table(ref members) => members.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match *self {
+            Value::String(ref s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_integer(&self) -> Option<i64> {
+        match *self {
+            Value::Integer(n) => Some(n),
+            _ => None,
+        }
+    }
+
+    /// Gets the number, if this is a float or an integer.
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            Value::Float(n) => Some(n),
+            Value::Integer(n) => Some(n as f64),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            Value::Boolean(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match *self {
+            Value::Array(ref elements) => Some(elements),
+            _ => None,
+        }
+    }
+
+    pub fn as_table(&self) -> Option<&[(String, Value)]> {
+        match *self {
+            Value::Table(ref members) => Some(members),
+            _ => None,
+        }
+    }
+}
+
+/// Parses a TOML document, returning its root table.
+pub(crate) fn parse(s: &str) -> Result<Value> {
+    let mut parser = Parser {
+        chars: s.chars().peekable(),
+        line: 1,
+    };
+    parser
+        .document()
+        .map_err(|e| format_err!("{} on line {} of TOML", e, parser.line))
+}
+
+/// Returns the table at `path` within `table`, creating any tables which
+/// don't exist yet. Arrays of tables lead to their last table.
+fn table_at<'a>(table: &'a mut Table, path: &[String]) -> Result<&'a mut Table> {
+    let (first, rest) = match path.split_first() {
+        Some(split) => split,
+        None => return Ok(table),
+    };
+    let i = match table.iter().position(|(k, _)| k == first) {
+        Some(i) => i,
+        None => {
+            table.push((first.clone(), Value::Table(Vec::new())));
+            table.len() - 1
+        }
+    };
+    let next = match table[i].1 {
+        Value::Table(ref mut next) => next,
+        Value::Array(ref mut elements) => match elements.last_mut() {
+            Some(Value::Table(ref mut next)) => next,
+            _ => return Err(format_err!("{:?} is not an array of tables", first)),
+        },
+        _ => return Err(format_err!("{:?} is not a table", first)),
+    };
+    table_at(next, rest)
+}
+
+fn insert(table: &mut Table, key: String, value: Value) -> Result<()> {
+    if table.iter().any(|(k, _)| *k == key) {
+        return Err(format_err!("Duplicate key {:?}", key));
+    }
+    table.push((key, value));
+    Ok(())
+}
+
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+    // The line being parsed, for errors.
+    line: usize,
+}
+
+impl<'a> Parser<'a> {
+    /// Skips spaces and tabs, but not newlines.
+    fn skip_whitespace(&mut self) {
+        while let Some(&c) = self.chars.peek() {
+            if c != ' ' && c != '\t' {
+                break;
+            }
+            self.chars.next();
+        }
+    }
+
+    /// Skips whitespace, newlines and comments.
+    fn skip_blank(&mut self) {
+        loop {
+            self.skip_whitespace();
+            match self.chars.peek() {
+                Some('#') => {
+                    while self.chars.peek().is_some_and(|&c| c != '\n') {
+                        self.chars.next();
+                    }
+                }
+                Some('\n') | Some('\r') => {
+                    self.next_char();
+                }
+                _ => return,
+            }
+        }
+    }
+
+    fn next_char(&mut self) -> Option<char> {
+        let c = self.chars.next();
+        if c == Some('\n') {
+            self.line += 1;
+        }
+        c
+    }
+
+    fn next(&mut self) -> Result<char> {
+        self.next_char()
+            .ok_or_else(|| format_err!("Unexpected end"))
+    }
+
+    fn expect(&mut self, expected: char) -> Result<()> {
+        match self.next()? {
+            c if c == expected => Ok(()),
+            c => Err(format_err!("Expected {:?}, got {:?}", expected, c)),
+        }
+    }
+
+    /// Expects the end of the line, after an optional comment.
+    fn end_of_line(&mut self) -> Result<()> {
+        self.skip_whitespace();
+        match self.chars.peek() {
+            None | Some('#') | Some('\n') | Some('\r') => Ok(()),
+            Some(&c) => Err(format_err!("Expected the end of the line, got {:?}", c)),
+        }
+    }
+
+    fn document(&mut self) -> Result<Value> {
+        let mut root = Vec::new();
+        let mut current = Vec::new();
+        loop {
+            self.skip_blank();
+            match self.chars.peek() {
+                None => return Ok(Value::Table(root)),
+                Some('[') => {
+                    self.chars.next();
+                    if self.chars.peek() == Some(&'[') {
+                        self.chars.next();
+                        current = self.key()?;
+                        self.expect(']')?;
+                        self.expect(']')?;
+                        let (last, parent) = current.split_last().unwrap();
+                        let parent = table_at(&mut root, parent)?;
+                        match parent.iter_mut().find(|(k, _)| k == last) {
+                            Some((_, Value::Array(ref mut elements))) => {
+                                elements.push(Value::Table(Vec::new()))
+                            }
+                            Some(_) => return Err(format_err!("{:?} is not an array", last)),
+                            None => parent
+                                .push((last.clone(), Value::Array(vec![Value::Table(Vec::new())]))),
+                        }
+                    } else {
+                        current = self.key()?;
+                        self.expect(']')?;
+                        table_at(&mut root, &current)?;
+                    }
+                }
+                Some(_) => {
+                    let mut key = self.key()?;
+                    self.expect('=')?;
+                    let value = self.value()?;
+                    let last = key.pop().unwrap();
+                    let path = current.iter().cloned().chain(key).collect::<Vec<_>>();
+                    insert(table_at(&mut root, &path)?, last, value)?;
+                }
+            }
+            self.end_of_line()?;
+        }
+    }
+
+    /// Parses a (possibly dotted) key, e.g. `attributes."my theme".font`,
+    /// along with any whitespace around it.
+    fn key(&mut self) -> Result<Vec<String>> {
+        let mut key = Vec::new();
+        loop {
+            self.skip_whitespace();
+            let part = match self.chars.peek() {
+                Some('"') => self.string()?,
+                Some('\'') => self.literal_string()?,
+                _ => {
+                    let mut part = String::new();
+                    while let Some(&c) = self.chars.peek() {
+                        if !(c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+                            break;
+                        }
+                        part.push(c);
+                        self.chars.next();
+                    }
+                    if part.is_empty() {
+                        return Err(format_err!("Expected a key"));
+                    }
+                    part
+                }
+            };
+            key.push(part);
+            self.skip_whitespace();
+            if self.chars.peek() != Some(&'.') {
+                return Ok(key);
+            }
+            self.chars.next();
+        }
+    }
+
+    fn value(&mut self) -> Result<Value> {
+        self.skip_whitespace();
+        match self.chars.peek().cloned() {
+            Some('[') => self.array(),
+            Some('{') => self.inline_table(),
+            Some('"') => Ok(Value::String(self.string()?)),
+            Some('\'') => Ok(Value::String(self.literal_string()?)),
+            Some(c) if c.is_ascii_alphanumeric() || "+-.".contains(c) => self.bare_value(),
+            Some(c) => Err(format_err!("Unexpected character {:?}", c)),
+            None => Err(format_err!("Unexpected end")),
+        }
+    }
+
+    fn array(&mut self) -> Result<Value> {
+        self.expect('[')?;
+        let mut elements = Vec::new();
+        loop {
+            // Arrays may span lines, with comments between the elements, and
+            // end with a trailing comma.
+            self.skip_blank();
+            if self.chars.peek() == Some(&']') {
+                self.chars.next();
+                return Ok(Value::Array(elements));
+            }
+            elements.push(self.value()?);
+            self.skip_blank();
+            match self.next()? {
+                ',' => continue,
+                ']' => return Ok(Value::Array(elements)),
+                c => return Err(format_err!("Expected ',' or ']', got {:?}", c)),
+            }
+        }
+    }
+
+    fn inline_table(&mut self) -> Result<Value> {
+        self.expect('{')?;
+        let mut table = Vec::new();
+        self.skip_whitespace();
+        if self.chars.peek() == Some(&'}') {
+            self.chars.next();
+            return Ok(Value::Table(table));
+        }
+        loop {
+            let mut key = self.key()?;
+            self.expect('=')?;
+            let value = self.value()?;
+            let last = key.pop().unwrap();
+            insert(table_at(&mut table, &key)?, last, value)?;
+            self.skip_whitespace();
+            match self.next()? {
+                ',' => continue,
+                '}' => return Ok(Value::Table(table)),
+                c => return Err(format_err!("Expected ',' or '}}', got {:?}", c)),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String> {
+        self.expect('"')?;
+        let mut s = String::new();
+        loop {
+            match self.next()? {
+                '"' => return Ok(s),
+                '\n' => return Err(format_err!("Unterminated string")),
+                '\\' => match self.next()? {
+                    '"' => s.push('"'),
+                    '\\' => s.push('\\'),
+                    'b' => s.push('\u{8}'),
+                    'f' => s.push('\u{c}'),
+                    'n' => s.push('\n'),
+                    'r' => s.push('\r'),
+                    't' => s.push('\t'),
+                    'u' => s.push(self.unicode_escape(4)?),
+                    'U' => s.push(self.unicode_escape(8)?),
+                    c => return Err(format_err!("Invalid escape in string: {:?}", c)),
+                },
+                c => s.push(c),
+            }
+        }
+    }
+
+    fn unicode_escape(&mut self, digits: usize) -> Result<char> {
+        let mut n = 0;
+        for _ in 0..digits {
+            let c = self.next()?;
+            let digit = c
+                .to_digit(16)
+                .ok_or_else(|| format_err!("Invalid hex digit in string: {:?}", c))?;
+            n = n * 16 + digit;
+        }
+        std::char::from_u32(n).ok_or_else(|| format_err!("Invalid unicode escape in string"))
+    }
+
+    fn literal_string(&mut self) -> Result<String> {
+        self.expect('\'')?;
+        let mut s = String::new();
+        loop {
+            match self.next()? {
+                '\'' => return Ok(s),
+                '\n' => return Err(format_err!("Unterminated string")),
+                c => s.push(c),
+            }
+        }
+    }
+
+    /// Parses a boolean or a number.
+    fn bare_value(&mut self) -> Result<Value> {
+        let mut s = String::new();
+        while let Some(&c) = self.chars.peek() {
+            if !(c.is_ascii_alphanumeric() || "+-._".contains(c)) {
+                break;
+            }
+            s.push(c);
+            self.chars.next();
+        }
+        match s.as_str() {
+            "true" => return Ok(Value::Boolean(true)),
+            "false" => return Ok(Value::Boolean(false)),
+            _ => {}
+        }
+
+        let number = s.replace('_', "");
+        if let Ok(n) = number.parse() {
+            return Ok(Value::Integer(n));
+        }
+        match number.as_str() {
+            "inf" | "+inf" => Ok(Value::Float(f64::INFINITY)),
+            "-inf" => Ok(Value::Float(f64::NEG_INFINITY)),
+            // Rust would accept e.g. `infinity` or `NaN`, which TOML doesn't.
+            _ if number
+                .chars()
+                .any(|c| c.is_ascii_alphabetic() && c != 'e' && c != 'E') =>
+            {
+                Err(format_err!("Invalid value: {:?}", s))
+            }
+            _ => number
+                .parse()
+                .map(Value::Float)
+                .map_err(|_| format_err!("Invalid value: {:?}", s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse, Value};
+
+    #[test]
+    fn parses_values() {
+        let value = parse(
+            r#"
+# A comment
+position = "top" # Another comment
+height = 24
+spacing = 1_0.5
+padding = [
+    8, 8.0,  # Trailing commas are allowed
+    -1e1,
+]
+
+[attributes.default]
+font = 'Noto Sans Mono 12'
+fg_color = "\"3c3836\"é"
+
+[[widget]]
+type = "pager"
+options = { priority = 10, name = "pager" }
+
+[[widget]]
+type = "clock"
+enabled = false
+"#,
+        )
+        .unwrap();
+        assert_eq!(value.get("position").and_then(Value::as_str), Some("top"));
+        assert_eq!(value.get("height").and_then(Value::as_integer), Some(24));
+        assert_eq!(value.get("spacing").and_then(Value::as_f64), Some(10.5));
+        assert_eq!(
+            value.get("padding"),
+            Some(&Value::Array(vec![
+                Value::Integer(8),
+                Value::Float(8.0),
+                Value::Float(-10.0),
+            ]))
+        );
+
+        let attributes = value.get("attributes").and_then(|a| a.get("default"));
+        assert_eq!(
+            attributes
+                .and_then(|a| a.get("font"))
+                .and_then(Value::as_str),
+            Some("Noto Sans Mono 12")
+        );
+        assert_eq!(
+            attributes
+                .and_then(|a| a.get("fg_color"))
+                .and_then(Value::as_str),
+            Some("\"3c3836\"é")
+        );
+
+        let widgets = value.get("widget").and_then(Value::as_array).unwrap();
+        assert_eq!(widgets.len(), 2);
+        assert_eq!(
+            widgets[0]
+                .get("options")
+                .and_then(|o| o.get("priority"))
+                .and_then(Value::as_integer),
+            Some(10)
+        );
+        assert_eq!(widgets[1].get("enabled"), Some(&Value::Boolean(false)));
+    }
+
+    #[test]
+    fn rejects_invalid() {
+        assert!(parse("a = ").is_err());
+        assert!(parse("a = 1\na = 2").is_err());
+        assert!(parse("a = 1 b = 2").is_err());
+        assert!(parse("a = \"unterminated").is_err());
+        assert!(parse("a = [1, 2").is_err());
+        assert!(parse("a = nan").is_err());
+        assert!(parse("[a\nb = 1").is_err());
+        assert!(parse("a = 1\n[a]").is_err());
+        assert!(parse("a = 1\n[[a]]").is_err());
+    }
+}
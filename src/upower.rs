@@ -0,0 +1,266 @@
+//! Reading the devices which UPower knows about, for the widgets which show
+//! batteries. UPower is queried with `busctl`, and watched with `gdbus`.
+
+use std::process::Command;
+use std::time::Duration;
+
+use futures::{future, Future, Stream};
+use tokio_core::reactor::Handle;
+
+use crate::busctl::Busctl;
+use crate::error::{format_err, Error};
+use crate::json::{self, Value};
+use crate::process;
+use crate::Result;
+
+const UPOWER: &str = "org.freedesktop.UPower";
+const DEVICE_INTERFACE: &str = "org.freedesktop.UPower.Device";
+
+/// What a device is, from its `Type` property.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Kind {
+    LinePower,
+    Battery,
+    Mouse,
+    Keyboard,
+    Phone,
+    Tablet,
+    GamingInput,
+    Pen,
+    Touchpad,
+    Headset,
+    Speakers,
+    Headphones,
+    Other,
+}
+
+impl Kind {
+    fn from_u32(kind: u32) -> Kind {
+        match kind {
+            1 => Kind::LinePower,
+            2 => Kind::Battery,
+            5 => Kind::Mouse,
+            6 => Kind::Keyboard,
+            8 => Kind::Phone,
+            10 => Kind::Tablet,
+            12 => Kind::GamingInput,
+            13 => Kind::Pen,
+            14 => Kind::Touchpad,
+            17 => Kind::Headset,
+            18 => Kind::Speakers,
+            19 => Kind::Headphones,
+            _ => Kind::Other,
+        }
+    }
+}
+
+/// Whether a device is charging, from its `State` property.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum State {
+    Charging,
+    Discharging,
+    Empty,
+    FullyCharged,
+    PendingCharge,
+    PendingDischarge,
+    Unknown,
+}
+
+impl State {
+    fn from_u32(state: u32) -> State {
+        match state {
+            1 => State::Charging,
+            2 => State::Discharging,
+            3 => State::Empty,
+            4 => State::FullyCharged,
+            5 => State::PendingCharge,
+            6 => State::PendingDischarge,
+            _ => State::Unknown,
+        }
+    }
+}
+
+/// A device's battery, and what it belongs to.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Device {
+    pub kind: Kind,
+    pub model: String,
+    /// Whether the device powers the system, rather than e.g. being a mouse.
+    pub power_supply: bool,
+    pub present: bool,
+    pub percentage: f64,
+    pub state: State,
+    pub time_to_empty: Option<Duration>,
+    pub time_to_full: Option<Duration>,
+}
+
+// The properties read by parse_device(), in the order that `busctl` prints
+// them.
+const DEVICE_PROPERTIES: &[&str] = &[
+    "Type",
+    "Model",
+    "PowerSupply",
+    "IsPresent",
+    "Percentage",
+    "State",
+    "TimeToEmpty",
+    "TimeToFull",
+];
+
+/// Parses the reply to `EnumerateDevices`, returning the devices' paths.
+fn parse_device_paths(output: &str) -> Result<Vec<String>> {
+    let reply = json::parse(output)?;
+    let paths = reply
+        .get("data")
+        .and_then(|data| data.at(0))
+        .and_then(Value::as_array)
+        .ok_or_else(|| format_err!("Unexpected format of EnumerateDevices reply"))?;
+    Ok(paths
+        .iter()
+        .filter_map(Value::as_str)
+        .map(ToOwned::to_owned)
+        .collect())
+}
+
+/// Parses the output of getting `DEVICE_PROPERTIES`, which `busctl` prints
+/// one per line.
+fn parse_device(path: &str, output: &str) -> Result<Device> {
+    let properties = output
+        .lines()
+        .map(|line| Ok(json::parse(line)?.get("data").cloned()))
+        .collect::<Result<Vec<_>>>()?;
+    if properties.len() != DEVICE_PROPERTIES.len() {
+        return Err(format_err!(
+            "Unexpected properties of UPower device {}",
+            path
+        ));
+    }
+    let number = |i: usize| {
+        properties[i]
+            .as_ref()
+            .and_then(Value::as_f64)
+            .ok_or_else(|| {
+                format_err!("Invalid {} of UPower device {}", DEVICE_PROPERTIES[i], path)
+            })
+    };
+    let boolean = |i: usize| properties[i] == Some(Value::Bool(true));
+    // Times are in seconds, or 0 if unknown.
+    let time = |i: usize| -> Result<Option<Duration>> {
+        let secs = number(i)?;
+        Ok(Some(Duration::from_secs(secs as u64)).filter(|_| secs > 0.0))
+    };
+
+    let model = properties[1]
+        .as_ref()
+        .and_then(Value::as_str)
+        .unwrap_or("")
+        .to_owned();
+
+    Ok(Device {
+        kind: Kind::from_u32(number(0)? as u32),
+        model,
+        power_supply: boolean(2),
+        present: boolean(3),
+        percentage: number(4)?,
+        state: State::from_u32(number(5)? as u32),
+        time_to_empty: time(6)?,
+        time_to_full: time(7)?,
+    })
+}
+
+/// Returns every device that UPower knows about, asking UPower with
+/// `busctl`, which must be on the system bus.
+pub(crate) fn devices(busctl: &Busctl) -> Box<dyn Future<Item = Vec<Device>, Error = Error>> {
+    let output = busctl.call(&[
+        "call",
+        UPOWER,
+        "/org/freedesktop/UPower",
+        UPOWER,
+        "EnumerateDevices",
+    ]);
+    let busctl = busctl.clone();
+    Box::new(
+        output
+            .and_then(|output| parse_device_paths(&output))
+            .and_then(move |paths| {
+                future::join_all(paths.into_iter().map(move |path| {
+                    let mut args = vec!["get-property", UPOWER, &path, DEVICE_INTERFACE];
+                    args.extend_from_slice(DEVICE_PROPERTIES);
+                    let properties = busctl.call(&args);
+                    properties.and_then(move |output| parse_device(&path, &output))
+                }))
+            }),
+    )
+}
+
+/// Returns a line for each signal that UPower sends, i.e. whenever a device is
+/// added or removed, or one of their properties changes, and whenever UPower
+/// starts or stops.
+///
+/// `busctl monitor` can't be used, as it becomes a bus monitor, which only
+/// root may do on the system bus. `gdbus monitor` subscribes to UPower's
+/// signals like any other client.
+pub(crate) fn monitor(handle: &Handle) -> Result<Box<dyn Stream<Item = String, Error = Error>>> {
+    let mut monitor = Command::new("gdbus");
+    monitor.args(["monitor", "--system", "--dest", UPOWER]);
+    let lines = process::output_lines(handle, monitor)?;
+    // Each signal is a line like `/org/freedesktop/UPower/devices/battery_BAT0:
+    // org.freedesktop.DBus.Properties.PropertiesChanged (...)`, and changes
+    // of UPower's owner are lines like `The name org.freedesktop.UPower is
+    // owned by :1.5`, after a banner.
+    Ok(Box::new(
+        lines.filter(|line| !line.starts_with("Monitoring signals")),
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::{parse_device, parse_device_paths, Device, Kind, State};
+
+    #[test]
+    fn works() {
+        let paths = r#"{"type":"ao","data":[["/org/freedesktop/UPower/devices/line_power_AC","/org/freedesktop/UPower/devices/battery_BAT0"]]}"#;
+        assert_eq!(
+            parse_device_paths(paths).unwrap(),
+            vec![
+                "/org/freedesktop/UPower/devices/line_power_AC".to_owned(),
+                "/org/freedesktop/UPower/devices/battery_BAT0".to_owned(),
+            ]
+        );
+
+        let properties = concat!(
+            r#"{"type":"u","data":2}"#,
+            "\n",
+            r#"{"type":"s","data":"5B10W13930"}"#,
+            "\n",
+            r#"{"type":"b","data":true}"#,
+            "\n",
+            r#"{"type":"b","data":true}"#,
+            "\n",
+            r#"{"type":"d","data":87.5}"#,
+            "\n",
+            r#"{"type":"u","data":2}"#,
+            "\n",
+            r#"{"type":"x","data":9000}"#,
+            "\n",
+            r#"{"type":"x","data":0}"#,
+            "\n"
+        );
+        assert_eq!(
+            parse_device("/org/freedesktop/UPower/devices/battery_BAT0", properties).unwrap(),
+            Device {
+                kind: Kind::Battery,
+                model: "5B10W13930".to_owned(),
+                power_supply: true,
+                present: true,
+                percentage: 87.5,
+                state: State::Discharging,
+                time_to_empty: Some(Duration::from_secs(9000)),
+                time_to_full: None,
+            }
+        );
+        assert!(parse_device("/", r#"{"type":"u","data":2}"#).is_err());
+    }
+}
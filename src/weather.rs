@@ -0,0 +1,297 @@
+//! Weather conditions, and the icons used to show them.
+//!
+//! Weather data sources each describe the weather with their own codes.
+//! These are translated into a [`Condition`], which an [`IconSet`] can then
+//! turn into an icon, so that every weather widget can show the same icons
+//! regardless of where its data comes from.
+//!
+//! ```
+//! use cnx::weather::{Condition, IconSet};
+//!
+//! // OpenWeatherMap's "light rain".
+//! let condition = Condition::from_owm_code(500);
+//! assert_eq!(condition, Condition::Rain);
+//! assert_eq!(IconSet::Text.icon(condition, true), "rain");
+//! ```
+//!
+//! [`Condition`]: enum.Condition.html
+//! [`IconSet`]: enum.IconSet.html
+
+use futures::Future;
+use tokio_core::reactor::Handle;
+
+use crate::error::{format_err, Error, ResultExt};
+use crate::http;
+use crate::json::{self, Value};
+use crate::timer::Timer;
+use crate::Result;
+
+/// A weather condition, as shown by an icon.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Condition {
+    /// A clear sky.
+    Clear,
+    /// Some clouds, but with the sun (or moon) showing.
+    PartlyCloudy,
+    /// An overcast sky.
+    Cloudy,
+    /// Fog, mist or haze.
+    Fog,
+    /// Drizzle or light showers.
+    Drizzle,
+    /// Rain.
+    Rain,
+    /// Sleet, or freezing rain.
+    Sleet,
+    /// Snow.
+    Snow,
+    /// A thunderstorm.
+    Thunderstorm,
+    /// Strong wind, squalls or a tornado.
+    Wind,
+    /// A condition which the data source didn't describe, or which we don't
+    /// recognise.
+    Unknown,
+}
+
+impl Condition {
+    /// Translates an [OpenWeatherMap condition code], e.g. `500` for "light
+    /// rain".
+    ///
+    /// [OpenWeatherMap condition code]: https://openweathermap.org/weather-conditions
+    pub fn from_owm_code(code: u32) -> Condition {
+        match code {
+            200..=299 => Condition::Thunderstorm,
+            300..=399 => Condition::Drizzle,
+            511 => Condition::Sleet,
+            500..=599 => Condition::Rain,
+            611..=616 => Condition::Sleet,
+            600..=699 => Condition::Snow,
+            771 | 781 => Condition::Wind,
+            700..=799 => Condition::Fog,
+            800 => Condition::Clear,
+            801 | 802 => Condition::PartlyCloudy,
+            803 | 804 => Condition::Cloudy,
+            _ => Condition::Unknown,
+        }
+    }
+
+    /// Translates a [WMO weather interpretation code], as used by e.g.
+    /// Open-Meteo, such as `61` for "slight rain".
+    ///
+    /// [WMO weather interpretation code]: https://open-meteo.com/en/docs#weathervariables
+    pub fn from_wmo_code(code: u32) -> Condition {
+        match code {
+            0 => Condition::Clear,
+            1 | 2 => Condition::PartlyCloudy,
+            3 => Condition::Cloudy,
+            45 | 48 => Condition::Fog,
+            51..=55 => Condition::Drizzle,
+            56 | 57 | 66 | 67 => Condition::Sleet,
+            61..=65 | 80..=82 => Condition::Rain,
+            71..=77 | 85 | 86 => Condition::Snow,
+            95..=99 => Condition::Thunderstorm,
+            _ => Condition::Unknown,
+        }
+    }
+}
+
+/// A set of icons for weather [`Condition`]s.
+///
+/// Icons which need a particular font (e.g. [`IconSet::WeatherIcons`]) are
+/// only shown properly if the widget's [`Font`] includes them.
+///
+/// [`Condition`]: enum.Condition.html
+/// [`IconSet::WeatherIcons`]: #variant.WeatherIcons
+/// [`Font`]: ../text/struct.Font.html
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum IconSet {
+    /// Unicode emoji, e.g. ☀ and 🌧, which most emoji fonts provide.
+    #[default]
+    Emoji,
+    /// The glyphs of Erik Flowers' [Weather Icons] font.
+    ///
+    /// [Weather Icons]: https://erikflowers.github.io/weather-icons/
+    WeatherIcons,
+    /// A short description, e.g. `rain`, for when no icon font is available.
+    Text,
+}
+
+impl IconSet {
+    /// Returns the icon for `condition`. Some icon sets have separate icons
+    /// for the night, which are used when `is_day` is false.
+    pub fn icon(self, condition: Condition, is_day: bool) -> &'static str {
+        match self {
+            IconSet::Emoji => match condition {
+                Condition::Clear if is_day => "\u{2600}",
+                Condition::Clear => "\u{1f319}",
+                Condition::PartlyCloudy if is_day => "\u{26c5}",
+                Condition::PartlyCloudy => "\u{2601}",
+                Condition::Cloudy => "\u{2601}",
+                Condition::Fog => "\u{1f32b}",
+                Condition::Drizzle => "\u{1f326}",
+                Condition::Rain => "\u{1f327}",
+                Condition::Sleet | Condition::Snow => "\u{1f328}",
+                Condition::Thunderstorm => "\u{26c8}",
+                Condition::Wind => "\u{1f4a8}",
+                Condition::Unknown => "?",
+            },
+            IconSet::WeatherIcons => match condition {
+                Condition::Clear if is_day => "\u{f00d}",
+                Condition::Clear => "\u{f02e}",
+                Condition::PartlyCloudy if is_day => "\u{f002}",
+                Condition::PartlyCloudy => "\u{f086}",
+                Condition::Cloudy => "\u{f013}",
+                Condition::Fog => "\u{f014}",
+                Condition::Drizzle => "\u{f01c}",
+                Condition::Rain => "\u{f019}",
+                Condition::Sleet => "\u{f0b5}",
+                Condition::Snow => "\u{f01b}",
+                Condition::Thunderstorm => "\u{f01e}",
+                Condition::Wind => "\u{f050}",
+                Condition::Unknown => "\u{f07b}",
+            },
+            IconSet::Text => match condition {
+                Condition::Clear => "clear",
+                Condition::PartlyCloudy => "partly cloudy",
+                Condition::Cloudy => "cloudy",
+                Condition::Fog => "fog",
+                Condition::Drizzle => "drizzle",
+                Condition::Rain => "rain",
+                Condition::Sleet => "sleet",
+                Condition::Snow => "snow",
+                Condition::Thunderstorm => "thunderstorm",
+                Condition::Wind => "wind",
+                Condition::Unknown => "?",
+            },
+        }
+    }
+}
+
+/// The current weather at a location.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Observation {
+    /// The temperature, in degrees Celsius.
+    pub temperature: f64,
+    pub condition: Condition,
+    /// Whether the sun is up.
+    pub is_day: bool,
+}
+
+/// Parses the `current_weather` of an [Open-Meteo] forecast.
+///
+/// [Open-Meteo]: https://open-meteo.com/en/docs
+fn parse_open_meteo(forecast: &str) -> Result<Observation> {
+    let forecast = json::parse(forecast)?;
+    let current = forecast
+        .get("current_weather")
+        .ok_or_else(|| format_err!("No current_weather in Open-Meteo forecast"))?;
+    let number = |key| {
+        current
+            .get(key)
+            .and_then(Value::as_f64)
+            .ok_or_else(|| format_err!("No {} in Open-Meteo forecast", key))
+    };
+    Ok(Observation {
+        temperature: number("temperature")?,
+        condition: Condition::from_wmo_code(number("weathercode")? as u32),
+        is_day: number("is_day")? != 0.0,
+    })
+}
+
+/// Parses the [current weather] from OpenWeatherMap, in metric units.
+///
+/// [current weather]: https://openweathermap.org/current
+#[cfg(feature = "weather-widget")]
+pub(crate) fn parse_owm(weather: &str) -> Result<Observation> {
+    let weather = json::parse(weather)?;
+    let temperature = weather
+        .get("main")
+        .and_then(|main| main.get("temp"))
+        .and_then(Value::as_f64)
+        .ok_or_else(|| format_err!("No temperature in OpenWeatherMap response"))?;
+    let conditions = weather.get("weather").and_then(|w| w.at(0));
+    let code = conditions
+        .and_then(|c| c.get("id"))
+        .and_then(Value::as_f64)
+        .ok_or_else(|| format_err!("No condition in OpenWeatherMap response"))?;
+    // Icons are e.g. `10d` by day and `10n` by night.
+    let icon = conditions
+        .and_then(|c| c.get("icon"))
+        .and_then(Value::as_str);
+    Ok(Observation {
+        temperature,
+        condition: Condition::from_owm_code(code as u32),
+        is_day: !matches!(icon, Some(icon) if icon.ends_with('n')),
+    })
+}
+
+/// Fetches the current weather at `latitude` and `longitude` from
+/// [Open-Meteo], which doesn't need an API key.
+///
+/// [Open-Meteo]: https://open-meteo.com/
+pub(crate) fn open_meteo(
+    handle: &Handle,
+    timer: &Timer,
+    latitude: f64,
+    longitude: f64,
+) -> Box<dyn Future<Item = Observation, Error = Error>> {
+    let path = format!(
+        "/v1/forecast?latitude={}&longitude={}&current_weather=true",
+        latitude, longitude
+    );
+    Box::new(
+        http::get(handle, timer, "api.open-meteo.com", &path).and_then(|forecast| {
+            parse_open_meteo(&forecast).context("Invalid Open-Meteo forecast")
+        }),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_open_meteo, Condition, Observation};
+
+    #[test]
+    fn translates_codes() {
+        assert_eq!(Condition::from_owm_code(211), Condition::Thunderstorm);
+        assert_eq!(Condition::from_owm_code(511), Condition::Sleet);
+        assert_eq!(Condition::from_owm_code(741), Condition::Fog);
+        assert_eq!(Condition::from_owm_code(781), Condition::Wind);
+        assert_eq!(Condition::from_owm_code(803), Condition::Cloudy);
+        assert_eq!(Condition::from_owm_code(42), Condition::Unknown);
+
+        assert_eq!(Condition::from_wmo_code(2), Condition::PartlyCloudy);
+        assert_eq!(Condition::from_wmo_code(66), Condition::Sleet);
+        assert_eq!(Condition::from_wmo_code(81), Condition::Rain);
+        assert_eq!(Condition::from_wmo_code(96), Condition::Thunderstorm);
+    }
+
+    #[test]
+    fn parses_open_meteo() {
+        let forecast = r#"{"latitude":51.5,"longitude":-0.12,"current_weather":{"temperature":13.2,"windspeed":10.3,"winddirection":250.0,"weathercode":61,"is_day":0,"time":"2023-03-01T20:00"}}"#;
+        assert_eq!(
+            parse_open_meteo(forecast).unwrap(),
+            Observation {
+                temperature: 13.2,
+                condition: Condition::Rain,
+                is_day: false,
+            }
+        );
+        assert!(parse_open_meteo(r#"{"error":true}"#).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "weather-widget")]
+    fn parses_owm() {
+        let weather = r#"{"coord":{"lon":-0.13,"lat":51.51},"weather":[{"id":803,"main":"Clouds","description":"broken clouds","icon":"04n"}],"main":{"temp":7.4,"feels_like":5.1,"humidity":81},"name":"London","cod":200}"#;
+        assert_eq!(
+            super::parse_owm(weather).unwrap(),
+            Observation {
+                temperature: 7.4,
+                condition: Condition::Cloudy,
+                is_day: false,
+            }
+        );
+        assert!(super::parse_owm(r#"{"cod":401,"message":"Invalid API key"}"#).is_err());
+    }
+}
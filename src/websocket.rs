@@ -0,0 +1,356 @@
+//! A minimal WebSocket client, for the widgets which talk to WebSocket APIs.
+//!
+//! Only unencrypted (`ws://`) connections and text messages are supported.
+//! Pings from the server are answered automatically.
+
+use std::convert::TryFrom;
+use std::io;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use futures::unsync::mpsc::{self, UnboundedSender};
+use futures::{try_ready, Async, Future, Poll, Stream};
+use log::debug;
+use tokio_core::reactor::Handle;
+use tokio_io::AsyncRead;
+
+use crate::error::{format_err, Error, ResultExt};
+use crate::http;
+use crate::Result;
+
+const TEXT: u8 = 0x1;
+const CLOSE: u8 = 0x8;
+const PING: u8 = 0x9;
+const PONG: u8 = 0xa;
+
+// The longest message we accept, so that a broken or hostile server can't
+// make us fill memory. Home Assistant's list of every entity's state is the
+// biggest message anything needs.
+const MAX_MESSAGE_LEN: usize = 16 * 1024 * 1024;
+
+// The longest response to the opening handshake we accept, for the same
+// reason. Its headers are usually a few hundred bytes.
+const MAX_HANDSHAKE_LEN: usize = 8 * 1024;
+
+/// The text messages received from the server.
+pub(crate) type Messages = Box<dyn Stream<Item = String, Error = Error>>;
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::new();
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &b)| n | u32::from(b) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// Some bytes which are unpredictable enough for the handshake's key and
+/// frames' masks, which only exist to confuse misbehaving proxies.
+fn nonce() -> [u8; 16] {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or(0);
+    let x = nanos ^ (u128::from(std::process::id()) << 64);
+    x.wrapping_mul(0x2545_f491_4f6c_dd1d).to_le_bytes()
+}
+
+/// Encodes a frame with `opcode` and `payload`. Clients must mask every
+/// frame that they send.
+fn encode_frame(opcode: u8, payload: &[u8]) -> Vec<u8> {
+    let mut frame = vec![0x80 | opcode];
+    match payload.len() {
+        len if len < 126 => frame.push(0x80 | len as u8),
+        len if len <= 0xffff => {
+            frame.push(0x80 | 126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(0x80 | 127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    let mask = nonce();
+    frame.extend_from_slice(&mask[..4]);
+    frame.extend(payload.iter().zip(mask.iter().cycle()).map(|(b, m)| b ^ m));
+    frame
+}
+
+/// A frame received from the server.
+#[derive(Debug, PartialEq)]
+struct Frame {
+    fin: bool,
+    opcode: u8,
+    payload: Vec<u8>,
+}
+
+/// Decodes the frame at the start of `buf`, returning it and its length, or
+/// `None` if the whole frame hasn't been received yet. Frames longer than
+/// `MAX_MESSAGE_LEN` are rejected as soon as their header arrives.
+fn decode_frame(buf: &[u8]) -> Result<Option<(Frame, usize)>> {
+    if buf.len() < 2 {
+        return Ok(None);
+    }
+    if buf[1] & 0x80 != 0 {
+        return Err(format_err!("WebSocket server sent a masked frame"));
+    }
+    let (len, header_len): (usize, usize) = match buf[1] & 0x7f {
+        126 if buf.len() >= 4 => (usize::from(u16::from_be_bytes([buf[2], buf[3]])), 4),
+        127 if buf.len() >= 10 => {
+            let mut len = [0; 8];
+            len.copy_from_slice(&buf[2..10]);
+            let len = u64::from_be_bytes(len);
+            match usize::try_from(len) {
+                Ok(len) if len <= MAX_MESSAGE_LEN => (len, 10),
+                _ => return Err(format_err!("WebSocket frame is too long ({} bytes)", len)),
+            }
+        }
+        126 | 127 => return Ok(None),
+        len => (usize::from(len), 2),
+    };
+    if len > MAX_MESSAGE_LEN {
+        return Err(format_err!("WebSocket frame is too long ({} bytes)", len));
+    }
+    let frame_len = header_len
+        .checked_add(len)
+        .ok_or_else(|| format_err!("WebSocket frame is too long ({} bytes)", len))?;
+    if buf.len() < frame_len {
+        return Ok(None);
+    }
+    let frame = Frame {
+        fin: buf[0] & 0x80 != 0,
+        opcode: buf[0] & 0x0f,
+        payload: buf[header_len..frame_len].to_vec(),
+    };
+    Ok(Some((frame, frame_len)))
+}
+
+/// Checks the server's response to the opening handshake at the start of
+/// `buf`, returning its length, or `None` if it hasn't all been received yet.
+fn decode_handshake(buf: &[u8]) -> Result<Option<usize>> {
+    let head = &buf[..buf.len().min(MAX_HANDSHAKE_LEN)];
+    let end = match head.windows(4).position(|w| w == b"\r\n\r\n") {
+        Some(end) => end,
+        None if buf.len() >= MAX_HANDSHAKE_LEN => {
+            return Err(format_err!(
+                "WebSocket handshake is longer than {} bytes",
+                MAX_HANDSHAKE_LEN
+            ))
+        }
+        None => return Ok(None),
+    };
+    let head = String::from_utf8_lossy(&buf[..end]);
+    let status = head.lines().next().unwrap_or("");
+    match status.split_whitespace().nth(1) {
+        Some("101") => Ok(Some(end + 4)),
+        _ => Err(format_err!("WebSocket handshake failed: {}", status)),
+    }
+}
+
+/// The messages received on a connection, after its handshake.
+struct Frames<R> {
+    reader: R,
+    buf: Vec<u8>,
+    handshaken: bool,
+    // The opcode and payload so far of a fragmented message.
+    fragments: Option<(u8, Vec<u8>)>,
+    sender: UnboundedSender<Vec<u8>>,
+}
+
+impl<R: AsyncRead> Frames<R> {
+    /// Handles a frame, returning the message that it completes, if any.
+    fn handle(&mut self, frame: Frame) -> Result<Option<(u8, Vec<u8>)>> {
+        match frame.opcode {
+            PING => {
+                // The writer may have already gone, in which case the
+                // connection is closing anyway.
+                let _ = self
+                    .sender
+                    .unbounded_send(encode_frame(PONG, &frame.payload));
+                Ok(None)
+            }
+            PONG => Ok(None),
+            0 => {
+                let (opcode, mut payload) = self
+                    .fragments
+                    .take()
+                    .ok_or_else(|| format_err!("Unexpected WebSocket continuation frame"))?;
+                if payload.len() + frame.payload.len() > MAX_MESSAGE_LEN {
+                    return Err(format_err!("WebSocket message is too long"));
+                }
+                payload.extend_from_slice(&frame.payload);
+                if frame.fin {
+                    Ok(Some((opcode, payload)))
+                } else {
+                    self.fragments = Some((opcode, payload));
+                    Ok(None)
+                }
+            }
+            opcode if frame.fin => Ok(Some((opcode, frame.payload))),
+            opcode => {
+                self.fragments = Some((opcode, frame.payload));
+                Ok(None)
+            }
+        }
+    }
+}
+
+impl<R: AsyncRead> Stream for Frames<R> {
+    type Item = String;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<String>, Error> {
+        loop {
+            if !self.handshaken {
+                if let Some(len) = decode_handshake(&self.buf)? {
+                    self.buf.drain(..len);
+                    self.handshaken = true;
+                }
+            }
+            if self.handshaken {
+                if let Some((frame, len)) = decode_frame(&self.buf)? {
+                    self.buf.drain(..len);
+                    match self.handle(frame)? {
+                        Some((CLOSE, _)) => return Ok(Async::Ready(None)),
+                        Some((TEXT, payload)) => {
+                            let text = String::from_utf8(payload)
+                                .context("Invalid UTF-8 in WebSocket message")?;
+                            return Ok(Async::Ready(Some(text)));
+                        }
+                        // Binary messages aren't needed by anything.
+                        Some(_) | None => continue,
+                    }
+                }
+            }
+
+            let mut chunk = [0; 4096];
+            let n = try_ready!(self.reader.poll_read(&mut chunk));
+            if n == 0 {
+                return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+            }
+            self.buf.extend_from_slice(&chunk[..n]);
+        }
+    }
+}
+
+/// A connection to a WebSocket server, which text messages can be sent on.
+///
+/// The connection is closed once this and its `Messages` are both dropped.
+#[derive(Clone)]
+pub(crate) struct WebSocket {
+    sender: UnboundedSender<Vec<u8>>,
+}
+
+impl WebSocket {
+    pub fn send(&self, text: &str) -> Result<()> {
+        self.sender
+            .unbounded_send(encode_frame(TEXT, text.as_bytes()))
+            .map_err(|_| format_err!("WebSocket connection closed"))
+    }
+}
+
+/// Connects to `ws://<address><path>`, where `address` is a `host:port`.
+pub(crate) fn connect(
+    handle: &Handle,
+    address: &str,
+    path: &str,
+) -> Box<dyn Future<Item = (WebSocket, Messages), Error = Error>> {
+    debug!("Connecting to ws://{}{}", address, path);
+    let handshake = format!(
+        "GET {} HTTP/1.1\r\nHost: {}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+         Sec-WebSocket-Key: {}\r\nSec-WebSocket-Version: 13\r\n\r\n",
+        path,
+        address,
+        base64(&nonce())
+    );
+    let handle = handle.clone();
+    let connecting = {
+        let handle = handle.clone();
+        http::resolve(address).and_then(move |addresses| http::connect(&handle, addresses))
+    };
+    Box::new(
+        connecting
+            .and_then(move |socket| tokio_io::io::write_all(socket, handshake).from_err())
+            .map(move |(socket, _)| {
+                let (reader, writer) = socket.split();
+                let (sender, receiver) = mpsc::unbounded::<Vec<u8>>();
+                // The writer finishes once every sender has been dropped.
+                let writing = receiver
+                    .fold(writer, |writer, frame| {
+                        tokio_io::io::write_all(writer, frame)
+                            .map(|(writer, _)| writer)
+                            .map_err(|e| debug!("Failed to write to WebSocket: {}", e))
+                    })
+                    .map(|_| ());
+                handle.spawn(writing);
+
+                let messages = Frames {
+                    reader,
+                    buf: Vec::new(),
+                    handshaken: false,
+                    fragments: None,
+                    sender: sender.clone(),
+                };
+                let messages: Messages = Box::new(messages);
+                (WebSocket { sender }, messages)
+            }),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::{base64, decode_frame, decode_handshake, Frame};
+
+    #[test]
+    fn works() {
+        assert_eq!(base64(b"cnx"), "Y254");
+        assert_eq!(base64(b"cnx!"), "Y254IQ==");
+        assert_eq!(base64(b"cnx!!"), "Y254ISE=");
+
+        let response = b"HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\n\r\n\x81";
+        assert_eq!(
+            decode_handshake(response).unwrap(),
+            Some(response.len() - 1)
+        );
+        assert!(decode_handshake(b"HTTP/1.1 403 Forbidden\r\n\r\n").is_err());
+        assert_eq!(decode_handshake(b"HTTP/1.1 101 Switching").unwrap(), None);
+        let endless = [
+            b"HTTP/1.1 101 Switching Protocols\r\nX: ".to_vec(),
+            vec![b'x'; 8192],
+        ]
+        .concat();
+        assert!(decode_handshake(&endless).is_err());
+
+        let frame = b"\x81\x02hi\x89";
+        assert_eq!(
+            decode_frame(frame).unwrap(),
+            Some((
+                Frame {
+                    fin: true,
+                    opcode: 1,
+                    payload: b"hi".to_vec(),
+                },
+                4
+            ))
+        );
+        assert_eq!(decode_frame(&frame[..3]).unwrap(), None);
+
+        // Lengths which would overflow, or just waste memory, are refused
+        // without waiting for the payload.
+        let huge = b"\x81\x7f\xff\xff\xff\xff\xff\xff\xff\xff";
+        assert!(decode_frame(huge).is_err());
+        let long = b"\x81\x7f\x00\x00\x00\x00\x01\x00\x00\x01";
+        assert!(decode_frame(long).is_err());
+        let extended = b"\x81\x7e\x00\x03abc";
+        assert_eq!(decode_frame(extended).unwrap().map(|(_, len)| len), Some(7));
+    }
+}
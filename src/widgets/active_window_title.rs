@@ -1,9 +1,24 @@
+use std::cell::RefCell;
+
+use log::warn;
 use tokio_core::reactor::Handle;
-use xcb;
 use xcb_util::ewmh;
 
-use crate::text::{Attributes, Text};
-use crate::{Cnx, Result};
+use crate::text::{Attributes, Ellipsis, Graphic, Icon, Text};
+use crate::{xcb_ext, Cnx, Result};
+
+/// How long a title can be before it is shortened.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum MaxWidth {
+    Chars(usize),
+    Pixels(f64),
+}
+
+/// The height of the window icons we ask for, which is then scaled to fit.
+const ICON_SIZE: u32 = 32;
+
+/// Picks the attributes for a window's class, if not the default ones.
+type ClassAttributes = Box<dyn Fn(&str) -> Option<Attributes>>;
 
 /// Shows the title of the currently focused window.
 ///
@@ -12,12 +27,30 @@ use crate::{Cnx, Result};
 /// of the root window to determine which window is currently focused.
 ///
 /// The widgets content stretches to fill all available space. If the title is
-/// too large for the available space, it will be truncated.
+/// too large for the available space, it will be truncated. Titles can also
+/// be shortened to a maximum width of their own, see [`with_max_chars()`] and
+/// [`with_max_width()`].
+///
+/// The title can be shown with different attributes depending on the window's
+/// class, e.g. to color terminals differently, see
+/// [`with_class_attributes()`]. It can also show the window's icon
+/// (`_NET_WM_ICON`) before the title, as tall as the text, see
+/// [`with_icon()`].
 ///
 /// [`EWMH`]: https://specifications.freedesktop.org/wm-spec/wm-spec-latest.html
+/// [`with_max_chars()`]: #method.with_max_chars
+/// [`with_max_width()`]: #method.with_max_width
+/// [`with_class_attributes()`]: #method.with_class_attributes
+/// [`with_icon()`]: #method.with_icon
 pub struct ActiveWindowTitle {
     tokio_handle: Handle,
     attr: Attributes,
+    max_width: Option<(MaxWidth, Ellipsis)>,
+    class_attributes: Option<ClassAttributes>,
+    show_icon: bool,
+    // The icon of the window which was last active, so that it isn't fetched
+    // again each time the title changes.
+    icon: RefCell<Option<(xcb::Window, Icon)>>,
 }
 
 impl ActiveWindowTitle {
@@ -51,6 +84,7 @@ impl ActiveWindowTitle {
     ///     fg_color: Color::white(),
     ///     bg_color: None,
     ///     padding: Padding::new(8.0, 8.0, 0.0, 0.0),
+    ///     border: None,
     /// };
     ///
     /// let mut cnx = Cnx::new(Position::Top)?;
@@ -63,12 +97,114 @@ impl ActiveWindowTitle {
         ActiveWindowTitle {
             tokio_handle: cnx.handle(),
             attr,
+            max_width: None,
+            class_attributes: None,
+            show_icon: false,
+            icon: RefCell::new(None),
+        }
+    }
+
+    /// Shortens titles longer than `max_chars` characters, cutting off the
+    /// part given by `ellipsis`.
+    pub fn with_max_chars(mut self, max_chars: usize, ellipsis: Ellipsis) -> ActiveWindowTitle {
+        self.max_width = Some((MaxWidth::Chars(max_chars), ellipsis));
+        self
+    }
+
+    /// Shortens titles wider than `max_width` pixels (not counting the
+    /// padding), cutting off the part given by `ellipsis`.
+    pub fn with_max_width(mut self, max_width: f64, ellipsis: Ellipsis) -> ActiveWindowTitle {
+        self.max_width = Some((MaxWidth::Pixels(max_width), ellipsis));
+        self
+    }
+
+    /// Shows the title with the [`Attributes`] that `class_attributes`
+    /// returns for the focused window's class (the second string of its
+    /// `WM_CLASS`, e.g. `Alacritty`), or the widget's own if it returns
+    /// None.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use cnx::*;
+    /// # use cnx::text::*;
+    /// # use cnx::widgets::*;
+    /// #
+    /// # fn run() -> ::cnx::Result<()> {
+    /// let attr = Attributes {
+    ///     font: Font::new("SourceCodePro 21"),
+    ///     fg_color: Color::white(),
+    ///     bg_color: None,
+    ///     padding: Padding::new(8.0, 8.0, 0.0, 0.0),
+    ///     border: None,
+    /// };
+    /// let terminal_attr = Attributes {
+    ///     fg_color: Color::blue(),
+    ///     ..attr.clone()
+    /// };
+    ///
+    /// let mut cnx = Cnx::new(Position::Top)?;
+    /// let title = ActiveWindowTitle::new(&cnx, attr.clone())
+    ///     .with_max_chars(60, Ellipsis::Middle)
+    ///     .with_class_attributes(move |class| match class {
+    ///         "Alacritty" | "XTerm" => Some(terminal_attr.clone()),
+    ///         _ => None,
+    ///     });
+    /// cnx.add_widget(title);
+    /// # Ok(())
+    /// # }
+    /// # fn main() { run().unwrap(); }
+    /// ```
+    ///
+    /// [`Attributes`]: ../text/struct.Attributes.html
+    pub fn with_class_attributes<F>(mut self, class_attributes: F) -> ActiveWindowTitle
+    where
+        F: Fn(&str) -> Option<Attributes> + 'static,
+    {
+        self.class_attributes = Some(Box::new(class_attributes));
+        self
+    }
+
+    /// Shows the focused window's icon before its title, for windows which
+    /// have one.
+    pub fn with_icon(mut self) -> ActiveWindowTitle {
+        self.show_icon = true;
+        self
+    }
+
+    fn window_icon(&self, conn: &ewmh::Connection, window: xcb::Window) -> Option<Icon> {
+        let mut cached = self.icon.borrow_mut();
+        match *cached {
+            Some((cached_window, ref icon)) if cached_window == window => Some(icon.clone()),
+            // Windows often set their icon after they are first focused, so
+            // keep trying until they have one.
+            _ => {
+                let icon = xcb_ext::window_icon(conn, window, ICON_SIZE)?;
+                *cached = Some((window, icon.clone()));
+                Some(icon)
+            }
+        }
+    }
+
+    fn shorten(&self, title: String, attr: &Attributes) -> String {
+        match self.max_width {
+            None => title,
+            Some((MaxWidth::Chars(max_chars), ellipsis)) => ellipsis.shorten(&title, max_chars),
+            Some((MaxWidth::Pixels(max_width), ellipsis)) => {
+                match ellipsis.shorten_to_width(&title, &attr.font, max_width) {
+                    Ok(shortened) => shortened,
+                    Err(e) => {
+                        warn!("Failed to measure window title: {}", e);
+                        title
+                    }
+                }
+            }
         }
     }
 
     fn on_change(&self, conn: &ewmh::Connection, screen_idx: i32) -> Result<Vec<Text>> {
-        let title = ewmh::get_active_window(conn, screen_idx)
-            .get_reply()
+        let active_window = ewmh::get_active_window(conn, screen_idx).get_reply().ok();
+        let title = active_window
             .and_then(|active_window| {
                 // x_properties_widget!() will only register for notifications on the
                 // root window, so will only receive notifications when the active window
@@ -76,20 +212,31 @@ impl ActiveWindowTitle {
                 // change notifications, so that we can see when the currently active
                 // window changes title. (We'll continue to receive notifications after
                 // it is no longer the active window, but this isn't a big deal).
-                let attributes = [(xcb::CW_EVENT_MASK, xcb::EVENT_MASK_PROPERTY_CHANGE)];
-                xcb::change_window_attributes(conn, active_window, &attributes);
-                conn.flush();
+                xcb_ext::select_property_events(conn, active_window);
 
-                ewmh::get_wm_name(conn, active_window).get_reply()
+                xcb_ext::window_title(conn, active_window)
             })
-            .map(|reply| reply.string().to_owned())
-            .unwrap_or_else(|_| "".to_owned());
-
-        Ok(vec![Text {
-            attr: self.attr.clone(),
-            text: title,
-            stretch: true,
-        }])
+            .unwrap_or_default();
+
+        let attr = match (&self.class_attributes, active_window) {
+            (Some(class_attributes), Some(active_window)) => {
+                xcb_ext::window_class(conn, active_window)
+                    .and_then(|class| class_attributes(&class))
+            }
+            _ => None,
+        }
+        .unwrap_or_else(|| self.attr.clone());
+
+        let icon = match active_window {
+            Some(active_window) if self.show_icon => self.window_icon(conn, active_window),
+            _ => None,
+        };
+        let icon = icon
+            .map(|icon| Text::new(attr.clone(), String::new()).with_graphic(Graphic::Icon(icon)));
+
+        let title = self.shorten(title, &attr);
+        let title = Text::new(attr, title).with_stretch();
+        Ok(icon.into_iter().chain(Some(title)).collect())
     }
 }
 
@@ -97,3 +244,18 @@ x_properties_widget!(ActiveWindowTitle, tokio_handle, on_change; [
     ACTIVE_WINDOW,
     WM_NAME
 ]);
+
+#[cfg(test)]
+mod test {
+    use crate::text::Ellipsis;
+
+    #[test]
+    fn works() {
+        let title = "vim ~/src/cnx/src/lib.rs";
+        assert_eq!(Ellipsis::End.shorten(title, 24), title);
+        assert_eq!(Ellipsis::Start.shorten(title, 10), "…rc/lib.rs");
+        assert_eq!(Ellipsis::Middle.shorten(title, 10), "vim ~…b.rs");
+        assert_eq!(Ellipsis::End.shorten(title, 10), "vim ~/src…");
+        assert_eq!(Ellipsis::End.shorten("ünïcödé", 4), "ünï…");
+    }
+}
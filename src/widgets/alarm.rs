@@ -0,0 +1,316 @@
+use std::mem;
+use std::process::Command;
+use std::time::Duration;
+
+use chrono::prelude::*;
+use futures::unsync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use futures::{stream, Future, Stream};
+
+use super::{Click, ClickHandler, Widget, WidgetStream};
+use crate::error::{format_err, Error, ResultExt};
+use crate::process;
+use crate::text::{Attributes, Text};
+use crate::timer::Timer;
+use crate::zoneinfo::Zone;
+use crate::{Cnx, Result};
+
+/// Returns the most recent time that the alarm at `time` went off, as of
+/// `now`.
+fn last_occurrence(now: &DateTime<FixedOffset>, time: NaiveTime) -> DateTime<FixedOffset> {
+    let today = now.naive_local().date().and_time(time);
+    let naive = if today > now.naive_local() {
+        today - chrono::Duration::days(1)
+    } else {
+        today
+    };
+    // Fixed offsets are never ambiguous.
+    now.offset().from_local_datetime(&naive).unwrap()
+}
+
+/// Returns the next time that the alarm at `time` will go off, after `now`.
+fn next_occurrence(now: &DateTime<FixedOffset>, time: NaiveTime) -> DateTime<FixedOffset> {
+    last_occurrence(now, time) + chrono::Duration::days(1)
+}
+
+/// The timezone which an [`Alarm`]'s times are in.
+///
+/// [`Alarm`]: struct.Alarm.html
+enum AlarmZone {
+    Local,
+    Fixed(FixedOffset),
+    Zone(Zone),
+}
+
+enum Event {
+    Tick,
+    Dismiss,
+}
+
+/// Shows the next of a set of daily alarms, and goes off when it is due.
+///
+/// This widget shows the time of the next alarm, e.g. `alarm 07:30`. When an
+/// alarm is due, the widget changes to `ALARM 07:30` in its urgent
+/// [`Attributes`], until it is clicked. The alarm can also send a desktop
+/// notification (see [`with_notification()`]) or run a command (see
+/// [`with_command()`]).
+///
+/// Alarm times are in the local timezone, so they go off at the same time of
+/// day when the timezone changes, e.g. when daylight saving time starts. Use
+/// [`with_zone()`] or [`with_utc_offset()`] to set them in a different
+/// timezone instead.
+///
+/// [`Attributes`]: ../text/struct.Attributes.html
+/// [`with_notification()`]: #method.with_notification
+/// [`with_command()`]: #method.with_command
+/// [`with_zone()`]: #method.with_zone
+/// [`with_utc_offset()`]: #method.with_utc_offset
+pub struct Alarm {
+    timer: Timer,
+    attr: Attributes,
+    urgent_attr: Attributes,
+    times: Vec<NaiveTime>,
+    zone: AlarmZone,
+    notify: bool,
+    command: Option<String>,
+    dismiss_sender: UnboundedSender<()>,
+    dismissals: UnboundedReceiver<()>,
+}
+
+impl Alarm {
+    /// Creates a new Alarm widget.
+    ///
+    /// Creates a new `Alarm` widget for alarms at each of the `times`, which
+    /// are given as `HH:MM`, e.g. `07:30`. Its text will be displayed with
+    /// the given `attr` [`Attributes`], or the `urgent_attr` while an alarm
+    /// is going off.
+    ///
+    /// The [`Cnx`] instance is borrowed during construction in order to get
+    /// access to handles of its event loop. However, it is not borrowed for
+    /// the lifetime of the widget. See the [`cnx_add_widget!()`] for more
+    /// discussion about the lifetime of the borrow.
+    ///
+    /// [`Attributes`]: ../text/struct.Attributes.html
+    /// [`Cnx`]: ../struct.Cnx.html
+    /// [`cnx_add_widget!()`]: ../macro.cnx_add_widget.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use cnx::*;
+    /// # use cnx::text::*;
+    /// # use cnx::widgets::*;
+    /// #
+    /// # fn run() -> ::cnx::Result<()> {
+    /// let attr = Attributes {
+    ///     font: Font::new("SourceCodePro 21"),
+    ///     fg_color: "ffffff".parse().unwrap(),
+    ///     bg_color: None,
+    ///     padding: Padding::new(8.0, 8.0, 0.0, 0.0),
+    ///     border: None,
+    /// };
+    /// let mut urgent_attr = attr.clone();
+    /// urgent_attr.bg_color = Some(Color::red());
+    ///
+    /// let mut cnx = Cnx::new(Position::Top)?;
+    /// let alarm = Alarm::new(&cnx, attr, urgent_attr, &["07:30", "13:00"])?;
+    /// cnx.add_widget(alarm.with_notification());
+    /// # Ok(())
+    /// # }
+    /// # fn main() { run().unwrap(); }
+    /// ```
+    pub fn new(
+        cnx: &Cnx,
+        attr: Attributes,
+        urgent_attr: Attributes,
+        times: &[&str],
+    ) -> Result<Alarm> {
+        let times = times
+            .iter()
+            .map(|time| {
+                NaiveTime::parse_from_str(time, "%H:%M")
+                    .with_context(|_| format!("Invalid alarm time: {}", time))
+            })
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        if times.is_empty() {
+            return Err(format_err!("No alarm times given"));
+        }
+        let (dismiss_sender, dismissals) = mpsc::unbounded();
+        Ok(Alarm {
+            timer: cnx.timer(),
+            attr,
+            urgent_attr,
+            times,
+            zone: AlarmZone::Local,
+            notify: false,
+            command: None,
+            dismiss_sender,
+            dismissals,
+        })
+    }
+
+    /// Sets the alarms in `zone`, e.g. `Europe/Berlin`, rather than in the
+    /// local timezone. They are shown in that zone, and follow its daylight
+    /// saving time.
+    pub fn with_zone(mut self, zone: Zone) -> Alarm {
+        self.zone = AlarmZone::Zone(zone);
+        self
+    }
+
+    /// Sets the alarms at a fixed `offset` from UTC, rather than in the local
+    /// timezone. They are shown at that offset, which never changes for
+    /// daylight saving time. See [`with_zone()`] for that.
+    ///
+    /// [`with_zone()`]: #method.with_zone
+    pub fn with_utc_offset(mut self, offset: FixedOffset) -> Alarm {
+        self.zone = AlarmZone::Fixed(offset);
+        self
+    }
+
+    /// Sends a desktop notification (using `notify-send`) when an alarm goes
+    /// off.
+    pub fn with_notification(mut self) -> Alarm {
+        self.notify = true;
+        self
+    }
+
+    /// Runs `command` (with `sh -c`) when an alarm goes off, e.g. to play a
+    /// sound.
+    pub fn with_command(mut self, command: String) -> Alarm {
+        self.command = Some(command);
+        self
+    }
+
+    fn now(&self) -> DateTime<FixedOffset> {
+        match self.zone {
+            AlarmZone::Local => {
+                let now = Local::now();
+                now.with_timezone(now.offset())
+            }
+            AlarmZone::Fixed(ref offset) => Utc::now().with_timezone(offset),
+            AlarmZone::Zone(ref zone) => {
+                let now = Utc::now();
+                now.with_timezone(&zone.offset_at(now.timestamp()).0)
+            }
+        }
+    }
+
+    /// Runs the notification and command, if any, for the alarm at `time`.
+    fn go_off(&self, time: &DateTime<FixedOffset>) {
+        let time = time.format("%H:%M").to_string();
+        if self.notify {
+            process::notify("critical", "Alarm", &time);
+        }
+        if let Some(ref command) = self.command {
+            let mut sh = Command::new("sh");
+            sh.arg("-c").arg(command).env("CNX_ALARM", &time);
+            process::run_detached(sh);
+        }
+    }
+}
+
+impl Widget for Alarm {
+    fn click_handler(&mut self) -> Option<ClickHandler> {
+        let sender = self.dismiss_sender.clone();
+        Some(Box::new(move |_: Click| {
+            // The widget may have stopped, in which case there's nothing to
+            // dismiss.
+            let _ = sender.unbounded_send(());
+            Ok(())
+        }))
+    }
+
+    fn stream(self: Box<Self>) -> Result<WidgetStream> {
+        let mut alarm = *self;
+        let timer = alarm.timer.clone();
+        let dismissals = mem::replace(&mut alarm.dismissals, mpsc::unbounded().1);
+
+        // Like the Clock, wake up at the start of each minute.
+        let ticks = stream::unfold(Duration::from_secs(0), move |sleep_for| {
+            Some(timer.sleep(sleep_for).map(|()| {
+                let sleep_for = Duration::from_secs(60 - u64::from(Local::now().second()));
+                (Event::Tick, sleep_for)
+            }))
+        })
+        .then(|r| r.context("Error in tokio_timer stream"))
+        .map_err(Error::from);
+        let dismissals = dismissals
+            .map(|()| Event::Dismiss)
+            .map_err(|()| format_err!("Alarm dismissal channel closed"));
+
+        let mut checked = alarm.now();
+        let mut going_off: Option<DateTime<FixedOffset>> = None;
+        let stream = ticks.select(dismissals).map(move |event| {
+            let now = alarm.now();
+            match event {
+                Event::Tick => {
+                    let due = alarm
+                        .times
+                        .iter()
+                        .map(|&time| last_occurrence(&now, time))
+                        .filter(|&time| time > checked)
+                        .max();
+                    if let Some(due) = due {
+                        alarm.go_off(&due);
+                        going_off = Some(due);
+                    }
+                    checked = now;
+                }
+                Event::Dismiss => going_off = None,
+            }
+
+            let (attr, text) = match going_off {
+                Some(ref time) => (
+                    alarm.urgent_attr.clone(),
+                    format!("ALARM {}", time.format("%H:%M")),
+                ),
+                None => {
+                    let next = alarm
+                        .times
+                        .iter()
+                        .map(|&time| next_occurrence(&now, time))
+                        .min()
+                        .unwrap();
+                    (
+                        alarm.attr.clone(),
+                        format!("alarm {}", next.format("%H:%M")),
+                    )
+                }
+            };
+            vec![Text::new(attr, text)]
+        });
+
+        Ok(Box::new(stream))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::prelude::*;
+
+    use super::{last_occurrence, next_occurrence};
+
+    #[test]
+    fn finds_occurrences() {
+        let offset = FixedOffset::east(3600);
+        let now = offset.ymd(2019, 3, 31).and_hms(7, 45, 0);
+        let morning = NaiveTime::from_hms(7, 30, 0);
+        let evening = NaiveTime::from_hms(20, 0, 0);
+
+        assert_eq!(
+            last_occurrence(&now, morning),
+            offset.ymd(2019, 3, 31).and_hms(7, 30, 0)
+        );
+        assert_eq!(
+            next_occurrence(&now, morning),
+            offset.ymd(2019, 4, 1).and_hms(7, 30, 0)
+        );
+        assert_eq!(
+            last_occurrence(&now, evening),
+            offset.ymd(2019, 3, 30).and_hms(20, 0, 0)
+        );
+        assert_eq!(
+            next_occurrence(&now, evening),
+            offset.ymd(2019, 3, 31).and_hms(20, 0, 0)
+        );
+    }
+}
@@ -0,0 +1,274 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use futures::sync::mpsc::{self, UnboundedReceiver};
+use futures::{future, stream, Future, Stream};
+use log::warn;
+use tokio_core::reactor::Handle;
+
+use super::{Click, ClickHandler, Refresh, Widget, WidgetStream};
+use crate::busctl::{Bus, Busctl};
+use crate::error::{format_err, Error, ResultExt};
+use crate::text::{Attributes, Text};
+use crate::timer::Timer;
+use crate::{Cnx, Result};
+
+/// Returns the brightness after scrolling `up` (or down) from `brightness`
+/// by `step` percent of `max`.
+///
+/// It always moves by at least one, but never past `max`, and never turns the
+/// backlight off entirely.
+fn scrolled(brightness: u32, max: u32, step: u32, up: bool) -> u32 {
+    let step = (u64::from(max) * u64::from(step) / 100).max(1) as u32;
+    if up {
+        brightness.saturating_add(step).min(max)
+    } else {
+        brightness.saturating_sub(step).max(1.min(max))
+    }
+}
+
+fn read_number(path: &Path) -> Result<u32> {
+    let contents =
+        fs::read_to_string(path).with_context(|_| format!("Failed to read {}", path.display()))?;
+    let number = contents
+        .trim()
+        .parse::<u32>()
+        .with_context(|_| format!("Invalid number in {}: {:?}", path.display(), contents))?;
+    Ok(number)
+}
+
+/// Returns the directory of the named backlight device in `backlight_dir`, or
+/// of the first one if no `name` is given.
+fn find_device(backlight_dir: &Path, name: Option<&str>) -> Result<PathBuf> {
+    if let Some(name) = name {
+        return Ok(backlight_dir.join(name));
+    }
+    let mut paths = fs::read_dir(backlight_dir)
+        .with_context(|_| format!("Failed to list {}", backlight_dir.display()))?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .collect::<Vec<PathBuf>>();
+    paths.sort();
+    paths
+        .into_iter()
+        .next()
+        .ok_or_else(|| format_err!("No backlight devices in {}", backlight_dir.display()))
+}
+
+/// Sets the brightness of the backlight `device`, either by writing to its
+/// `brightness` file or, if that isn't allowed, by asking `logind`.
+fn set_brightness(
+    busctl: &Busctl,
+    device: &Path,
+    brightness: u32,
+) -> Box<dyn Future<Item = (), Error = Error>> {
+    let path = device.join("brightness");
+    let error = match fs::write(&path, brightness.to_string()) {
+        Ok(()) => return Box::new(future::ok(())),
+        Err(e) => e,
+    };
+    // Only root can write to sysfs (unless a udev rule says otherwise), but
+    // logind lets the user of a session set the brightness of its display.
+    if error.kind() != io::ErrorKind::PermissionDenied {
+        let error = Err(error).with_context(|_| format!("Failed to write {}", path.display()));
+        return Box::new(future::result(error));
+    }
+
+    let name = match device.file_name() {
+        Some(name) => name.to_string_lossy().into_owned(),
+        None => {
+            let error = format_err!("Invalid backlight path: {}", device.display());
+            return Box::new(future::err(error));
+        }
+    };
+    let set = busctl.call(&[
+        "call",
+        "org.freedesktop.login1",
+        "/org/freedesktop/login1/session/auto",
+        "org.freedesktop.login1.Session",
+        "SetBrightness",
+        "ssu",
+        "backlight",
+        &name,
+        &brightness.to_string(),
+    ]);
+    Box::new(set.map(|_| ()))
+}
+
+/// Shows the brightness of the screen's backlight.
+///
+/// This widget shows the brightness of a backlight device in
+/// [`/sys/class/backlight/`], as a percentage of its maximum brightness.
+/// Unless another is chosen with [`with_device()`], the first device is shown
+/// (e.g. `intel_backlight`).
+///
+/// Scrolling over the widget raises or lowers the brightness, by 5% of the
+/// maximum at a time unless a different step is given with [`with_step()`].
+/// The brightness is written to the device's `brightness` file if Cnx is
+/// allowed to (e.g. by a udev rule), and otherwise set with `logind`'s
+/// `SetBrightness` call, which doesn't need root. That expects the `busctl`
+/// executable (part of `systemd`) to be available in the `PATH`.
+///
+/// [`/sys/class/backlight/`]: https://www.kernel.org/doc/Documentation/ABI/stable/sysfs-class-backlight
+/// [`with_device()`]: #method.with_device
+/// [`with_step()`]: #method.with_step
+pub struct Backlight {
+    timer: Timer,
+    handle: Handle,
+    busctl: Busctl,
+    update_interval: Duration,
+    backlight_dir: PathBuf,
+    device: Option<String>,
+    step: u32,
+    attr: Attributes,
+    // Woken by the click handler, to show a new brightness straight away.
+    redraws: Option<UnboundedReceiver<()>>,
+}
+
+impl Backlight {
+    /// Creates a new Backlight widget.
+    ///
+    /// Creates a new `Backlight` widget, whose text will be displayed with the
+    /// given [`Attributes`].
+    ///
+    /// The [`Cnx`] instance is borrowed during construction in order to get
+    /// access to handles of its event loop. However, it is not borrowed for the
+    /// lifetime of the widget. See the [`cnx_add_widget!()`] for more discussion
+    /// about the lifetime of the borrow.
+    ///
+    /// [`Attributes`]: ../text/struct.Attributes.html
+    /// [`Cnx`]: ../struct.Cnx.html
+    /// [`cnx_add_widget!()`]: ../macro.cnx_add_widget.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use cnx::*;
+    /// # use cnx::text::*;
+    /// # use cnx::widgets::*;
+    /// #
+    /// # fn run() -> ::cnx::Result<()> {
+    /// let attr = Attributes {
+    ///     font: Font::new("SourceCodePro 21"),
+    ///     fg_color: "ffffff".parse().unwrap(),
+    ///     bg_color: None,
+    ///     padding: Padding::new(8.0, 8.0, 0.0, 0.0),
+    ///     border: None,
+    /// };
+    ///
+    /// let mut cnx = Cnx::new(Position::Top)?;
+    /// cnx.add_widget(Backlight::new(&cnx, attr.clone()).with_step(10));
+    /// # Ok(())
+    /// # }
+    /// # fn main() { run().unwrap(); }
+    /// ```
+    pub fn new(cnx: &Cnx, attr: Attributes) -> Backlight {
+        Backlight {
+            timer: cnx.timer(),
+            handle: cnx.handle(),
+            busctl: Busctl::new(cnx, Bus::System),
+            update_interval: Duration::from_secs(5),
+            backlight_dir: PathBuf::from("/sys/class/backlight"),
+            device: None,
+            step: 5,
+            attr,
+            redraws: None,
+        }
+    }
+
+    /// Shows the named device in `/sys/class/backlight`, e.g.
+    /// `amdgpu_bl0`, rather than the first one.
+    pub fn with_device<S: Into<String>>(mut self, name: S) -> Backlight {
+        self.device = Some(name.into());
+        self
+    }
+
+    /// Sets how far each scroll moves the brightness, as a percentage of the
+    /// maximum brightness. Defaults to 5.
+    pub fn with_step(mut self, percent: u32) -> Backlight {
+        self.step = percent;
+        self
+    }
+
+    fn tick(&self) -> Result<Vec<Text>> {
+        let device = find_device(&self.backlight_dir, self.device.as_deref())?;
+        let brightness = read_number(&device.join("brightness"))?;
+        let max = read_number(&device.join("max_brightness"))?;
+        let percentage = f64::from(brightness) / f64::from(max.max(1)) * 100.0;
+        Ok(vec![Text::new(
+            self.attr.clone(),
+            format!("{:.0}%", percentage),
+        )])
+    }
+}
+
+impl Widget for Backlight {
+    fn stream(self: Box<Self>) -> Result<WidgetStream> {
+        self.refreshable_stream(Box::new(stream::empty()))
+    }
+
+    fn refreshable_stream(mut self: Box<Self>, refresh: Refresh) -> Result<WidgetStream> {
+        let ticks = self.timer.interval(self.update_interval).select(refresh);
+        let events: Box<dyn Stream<Item = (), Error = Error>> = match self.redraws.take() {
+            Some(redraws) => Box::new(
+                ticks.select(redraws.map_err(|()| format_err!("Failed to receive redraw"))),
+            ),
+            None => Box::new(ticks),
+        };
+
+        let initial = stream::once::<_, Error>(self.tick());
+        let text_stream = events.and_then(move |()| self.tick());
+        Ok(Box::new(initial.chain(text_stream)))
+    }
+
+    fn click_handler(&mut self) -> Option<ClickHandler> {
+        let (sender, receiver) = mpsc::unbounded();
+        self.redraws = Some(receiver);
+
+        let handle = self.handle.clone();
+        let busctl = self.busctl.clone();
+        let backlight_dir = self.backlight_dir.clone();
+        let name = self.device.clone();
+        let step = self.step;
+        Some(Box::new(move |click: Click| {
+            let up = match click.button {
+                4 => true,
+                5 => false,
+                _ => return Ok(()),
+            };
+            let device = find_device(&backlight_dir, name.as_deref())?;
+            let brightness = read_number(&device.join("brightness"))?;
+            let max = read_number(&device.join("max_brightness"))?;
+            let set = set_brightness(&busctl, &device, scrolled(brightness, max, step, up));
+            let sender = sender.clone();
+            handle.spawn(
+                set.map(move |()| {
+                    // The stream only ends when the widget does, so this
+                    // can't fail while it matters.
+                    let _ = sender.unbounded_send(());
+                })
+                .map_err(|e| warn!("Failed to set the brightness: {}", e)),
+            );
+            Ok(())
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::scrolled;
+
+    #[test]
+    fn scrolls_brightness() {
+        assert_eq!(scrolled(500, 1000, 5, true), 550);
+        assert_eq!(scrolled(500, 1000, 5, false), 450);
+        assert_eq!(scrolled(990, 1000, 5, true), 1000);
+        // Not all the way off.
+        assert_eq!(scrolled(30, 1000, 5, false), 1);
+        assert_eq!(scrolled(1, 1000, 5, false), 1);
+        // Devices with few levels still move a step at a time.
+        assert_eq!(scrolled(3, 7, 5, true), 4);
+        assert_eq!(scrolled(3, 7, 5, false), 2);
+    }
+}
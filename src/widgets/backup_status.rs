@@ -0,0 +1,246 @@
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use futures::{stream, Stream};
+use log::warn;
+
+use crate::inotify::Inotify;
+use crate::text::{Attributes, Color, Text};
+use crate::timer::Timer;
+use crate::widgets::Refresh;
+use crate::{Cnx, Result};
+
+/// A backup run, as recorded in a stamp file.
+#[derive(Debug, PartialEq)]
+struct Run {
+    // Seconds since the Unix epoch, if the stamp file records it.
+    finished: Option<u64>,
+    succeeded: bool,
+}
+
+/// Parses a stamp file, which contains `<timestamp> [<exit status>]`, or
+/// anything else if only its modification time matters.
+fn parse_stamp(contents: &str) -> Run {
+    let mut parts = contents.split_whitespace();
+    let finished = parts.next().and_then(|t| t.parse::<u64>().ok());
+    let status = match finished {
+        Some(_) => parts.next().and_then(|s| s.parse::<i32>().ok()),
+        None => None,
+    };
+    Run {
+        finished,
+        succeeded: status.unwrap_or(0) == 0,
+    }
+}
+
+/// Formats `age` as a rough number of minutes, hours or days.
+fn format_age(age: Duration) -> String {
+    let minutes = age.as_secs() / 60;
+    match minutes {
+        0..=59 => format!("{}m", minutes),
+        60..=2879 => format!("{}h", minutes / 60),
+        _ => format!("{}d", minutes / (60 * 24)),
+    }
+}
+
+/// Shows how long ago the last backup ran.
+///
+/// This widget reads a stamp file which a backup script (e.g. a wrapper
+/// around `borgmatic` or `restic`) writes after each run, and shows how long
+/// ago it finished, e.g. `backup 5h`. If the last successful backup is older
+/// than the configured maximum age, or the last run failed, the widget's text
+/// changes to the specified `warning_color`.
+///
+/// The stamp file may contain the time that the run finished, as seconds
+/// since the Unix epoch, optionally followed by the backup's exit status.
+/// Otherwise only its modification time is used, and the run is assumed to
+/// have succeeded. For example:
+///
+/// ```sh
+/// restic backup ~
+/// echo "$(date +%s) $?" > ~/.local/state/backup-stamp
+/// ```
+///
+/// The widget notices as soon as the stamp file is written, and otherwise
+/// updates every minute.
+pub struct BackupStatus {
+    timer: Timer,
+    update_interval: Duration,
+    inotify: Inotify,
+    attr: Attributes,
+    warning_color: Color,
+    stamp: PathBuf,
+    max_age: Duration,
+}
+
+impl BackupStatus {
+    ///  Creates a new BackupStatus widget.
+    ///
+    ///  Creates a new `BackupStatus` widget, which reads the `stamp` file and
+    ///  whose text will be displayed with the given [`Attributes`]. The text
+    ///  is displayed with `warning_color` once the last successful backup is
+    ///  older than `max_age`.
+    ///
+    ///  The [`Cnx`] instance is borrowed during construction in order to get
+    ///  access to handles of its event loop. However, it is not borrowed for
+    ///  the lifetime of the widget. See the [`cnx_add_widget!()`] for more
+    ///  discussion about the lifetime of the borrow.
+    ///
+    /// [`Attributes`]: ../text/struct.Attributes.html
+    /// [`Cnx`]: ../struct.Cnx.html
+    /// [`cnx_add_widget!()`]: ../macro.cnx_add_widget.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #[macro_use]
+    /// # extern crate cnx;
+    /// #
+    /// # use std::time::Duration;
+    /// #
+    /// # use cnx::*;
+    /// # use cnx::text::*;
+    /// # use cnx::widgets::*;
+    /// #
+    /// # fn run() -> ::cnx::Result<()> {
+    /// let attr = Attributes {
+    ///     font: Font::new("SourceCodePro 21"),
+    ///     fg_color: Color::white(),
+    ///     bg_color: None,
+    ///     padding: Padding::new(8.0, 8.0, 0.0, 0.0),
+    ///     border: None,
+    /// };
+    ///
+    /// let mut cnx = Cnx::new(Position::Top)?;
+    /// let stamp = "/home/user/.local/state/backup-stamp".into();
+    /// let day = Duration::from_secs(24 * 60 * 60);
+    /// cnx_add_widget!(cnx, BackupStatus::new(&cnx, attr.clone(), Color::red(), stamp, day));
+    /// # Ok(())
+    /// # }
+    /// # fn main() { run().unwrap(); }
+    /// ```
+    pub fn new(
+        cnx: &Cnx,
+        attr: Attributes,
+        warning_color: Color,
+        stamp: PathBuf,
+        max_age: Duration,
+    ) -> BackupStatus {
+        BackupStatus {
+            timer: cnx.timer(),
+            update_interval: Duration::from_secs(60),
+            inotify: cnx.inotify(),
+            attr,
+            warning_color,
+            stamp,
+            max_age,
+        }
+    }
+
+    /// Returns a stream which yields whenever the stamp file is written.
+    fn stamp_events(&self) -> Result<Refresh> {
+        // Backup scripts often replace the stamp file, rather than writing to
+        // it, so watch its directory.
+        let stamp = self.stamp.clone();
+        let events = match self.stamp.parent() {
+            Some(dir) => self.inotify.watch(dir),
+            None => return Ok(Box::new(stream::empty())),
+        };
+        match events {
+            Ok(events) => Ok(Box::new(
+                events.filter(move |event| event.path == stamp).map(|_| ()),
+            )),
+            Err(e) => {
+                warn!(
+                    "BackupStatus won't notice backups until its next update: {}",
+                    e
+                );
+                Ok(Box::new(stream::empty()))
+            }
+        }
+    }
+
+    fn tick(&self) -> Result<Vec<Text>> {
+        let (finished, succeeded) = match fs::read_to_string(&self.stamp) {
+            Ok(contents) => {
+                let run = parse_stamp(&contents);
+                // A time too far in the future to represent is as unknown as
+                // a missing one.
+                let finished = run
+                    .finished
+                    .and_then(|secs| UNIX_EPOCH.checked_add(Duration::from_secs(secs)))
+                    .or_else(|| {
+                        fs::metadata(&self.stamp)
+                            .and_then(|metadata| metadata.modified())
+                            .ok()
+                    });
+                (finished, run.succeeded)
+            }
+            // There's no stamp until the first backup.
+            Err(_) => (None, true),
+        };
+
+        let age = finished.map(|finished| {
+            SystemTime::now()
+                .duration_since(finished)
+                .unwrap_or_default()
+        });
+        let text = match (age, succeeded) {
+            (Some(age), true) => format!("backup {}", format_age(age)),
+            (Some(age), false) => format!("backup failed {}", format_age(age)),
+            (None, _) => "backup never".to_owned(),
+        };
+
+        // A failed run doesn't tell us when the last successful one was, so
+        // always warn about it.
+        let mut attr = self.attr.clone();
+        let overdue = match age {
+            Some(age) => age > self.max_age,
+            None => true,
+        };
+        if overdue || !succeeded {
+            attr.fg_color = self.warning_color.clone();
+        }
+
+        Ok(vec![Text::new(attr, text)])
+    }
+}
+
+timer_widget!(BackupStatus, timer, update_interval, tick; stamp_events);
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::{format_age, parse_stamp, Run};
+
+    #[test]
+    fn works() {
+        assert_eq!(
+            parse_stamp("1677700000 0\n"),
+            Run {
+                finished: Some(1_677_700_000),
+                succeeded: true,
+            }
+        );
+        assert_eq!(
+            parse_stamp("1677700000 2"),
+            Run {
+                finished: Some(1_677_700_000),
+                succeeded: false,
+            }
+        );
+        assert_eq!(
+            parse_stamp(""),
+            Run {
+                finished: None,
+                succeeded: true,
+            }
+        );
+
+        assert_eq!(format_age(Duration::from_secs(59)), "0m");
+        assert_eq!(format_age(Duration::from_secs(3 * 3600 + 59)), "3h");
+        assert_eq!(format_age(Duration::from_secs(3 * 86400)), "3d");
+    }
+}
@@ -1,21 +1,37 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
 use std::f64;
-use std::fs::File;
-use std::io::Read;
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::result;
 use std::str::FromStr;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use failure::{format_err, Error, ResultExt};
-use tokio_timer::Timer;
+use futures::{stream, Stream};
+use log::{debug, warn};
 
-use crate::text::{Attributes, Color, Text};
+use crate::error::{format_err, Error, ResultExt};
+use crate::netlink::{Family, Netlink, NetlinkEvent};
+use crate::template::{Template, Value};
+use crate::text::{Attributes, Color, Text, Threshold};
+use crate::timer::Timer;
+use crate::widgets::Refresh;
 use crate::{Cnx, Result};
 
+/// The default format, e.g. `(42% - 1:05)`.
+const DEFAULT_FORMAT: &str = "({percent}% - {time_left})";
+
+/// How far back the (dis)charge rate is averaged over, as the instantaneous
+/// rate jumps around with the load.
+const RATE_WINDOW: Duration = Duration::from_secs(5 * 60);
+
 #[derive(Clone, Debug, Eq, PartialEq)]
 enum Status {
     Full,
     Charging,
     Discharging,
+    // e.g. while a ThinkPad charges or drains its other battery first.
+    NotCharging,
     Unknown,
 }
 
@@ -27,30 +43,230 @@ impl FromStr for Status {
             "Full" => Ok(Status::Full),
             "Charging" => Ok(Status::Charging),
             "Discharging" => Ok(Status::Discharging),
+            "Not charging" => Ok(Status::NotCharging),
             "Unknown" => Ok(Status::Unknown),
             _ => Err(format_err!("Unknown Status: {}", s)),
         }
     }
 }
 
+/// The state of a battery, or of several batteries added together.
+#[derive(Clone, Debug, PartialEq)]
+struct Reading {
+    // The energy stored now and when full, and the rate at which it is
+    // (dis)charging. These are in µWh and µW, or in µAh and µA for batteries
+    // which don't know their voltage.
+    now: f64,
+    full: f64,
+    rate: f64,
+    status: Status,
+}
+
+impl Reading {
+    /// Adds up the `readings` of several batteries, as if they were one.
+    fn combine(readings: &[Reading]) -> Reading {
+        let any = |status| readings.iter().any(|reading| reading.status == status);
+        // Usually only one battery (dis)charges at a time.
+        let status = if any(Status::Discharging) {
+            Status::Discharging
+        } else if any(Status::Charging) {
+            Status::Charging
+        } else if readings
+            .iter()
+            .all(|reading| reading.status == Status::Full)
+        {
+            Status::Full
+        } else {
+            Status::Unknown
+        };
+        Reading {
+            now: readings.iter().map(|reading| reading.now).sum(),
+            full: readings.iter().map(|reading| reading.full).sum(),
+            rate: readings.iter().map(|reading| reading.rate).sum(),
+            status,
+        }
+    }
+
+    fn percentage(&self) -> f64 {
+        // Some batteries report nothing while they're being calibrated.
+        if self.full <= 0.0 {
+            return 0.0;
+        }
+        (self.now / self.full) * 100.0
+    }
+
+    /// Fills in `format`'s `{percent}` and `{time_left}`, as `H:MM`, to empty
+    /// if discharging, or to full if charging.
+    fn format(&self, format: &Template) -> String {
+        let time = match self.status {
+            Status::Discharging if self.rate > 0.0 => self.now / self.rate,
+            Status::Charging if self.rate > 0.0 => (self.full - self.now) / self.rate,
+            _ => 0.0,
+        };
+        let hours = time as u64;
+        let minutes = (time * 60.0) as u64 % 60;
+        format.render(&[
+            ("percent", Value::Number(self.percentage(), 0)),
+            (
+                "time_left",
+                Value::Text(format!("{}:{:02}", hours, minutes)),
+            ),
+        ])
+    }
+}
+
+/// The average (dis)charge rate over the last `RATE_WINDOW`, since the
+/// battery started (dis)charging.
+#[derive(Debug, Default)]
+struct RateAverage {
+    status: Option<Status>,
+    rates: VecDeque<(Instant, f64)>,
+}
+
+impl RateAverage {
+    /// Adds the `reading`'s rate, taken at `now`, and returns the reading
+    /// with the average rate instead.
+    fn smooth(&mut self, reading: &Reading, now: Instant) -> Reading {
+        // The rate while charging says nothing about the rate discharging.
+        if self.status.as_ref() != Some(&reading.status) {
+            self.status = Some(reading.status.clone());
+            self.rates.clear();
+        }
+        self.rates.push_back((now, reading.rate));
+        while let Some(&(time, _)) = self.rates.front() {
+            if now.duration_since(time) <= RATE_WINDOW {
+                break;
+            }
+            self.rates.pop_front();
+        }
+        let total: f64 = self.rates.iter().map(|&(_, rate)| rate).sum();
+        Reading {
+            rate: total / self.rates.len() as f64,
+            ..reading.clone()
+        }
+    }
+}
+
+fn load_value<T>(battery: &Path, file: &str) -> Result<T>
+where
+    T: FromStr,
+    <T as FromStr>::Err: Into<Error>,
+{
+    let path = battery.join(file);
+    let contents = fs::read_to_string(&path).with_context(|_| {
+        format!(
+            "Could not load value from battery status file: {}",
+            path.display()
+        )
+    })?;
+    let value = FromStr::from_str(contents.trim())
+        .map_err(|e: <T as FromStr>::Err| e.into())
+        .with_context(|_| format!("Failed to parse value in {}", path.display()))?;
+    Ok(value)
+}
+
+/// Reads the state of the `battery`, e.g. `/sys/class/power_supply/BAT0`.
+fn read_battery(battery: &Path) -> Result<Reading> {
+    let status = load_value(battery, "status")?;
+    // Batteries report either energy and power, or charge and current.
+    if let Ok(now) = load_value::<f64>(battery, "energy_now") {
+        return Ok(Reading {
+            now,
+            full: load_value(battery, "energy_full")?,
+            rate: load_value(battery, "power_now").unwrap_or(0.0),
+            status,
+        });
+    }
+    let now: f64 = load_value(battery, "charge_now")?;
+    let full: f64 = load_value(battery, "charge_full")?;
+    let rate: f64 = load_value(battery, "current_avg")
+        .or_else(|_| load_value(battery, "current_now"))
+        .unwrap_or(0.0);
+    // Convert to energy, so that these can be added to batteries which
+    // report energy.
+    let volts = load_value::<f64>(battery, "voltage_min_design")
+        .or_else(|_| load_value::<f64>(battery, "voltage_now"))
+        .map_or(1.0, |microvolts| microvolts / 1_000_000.0);
+    Ok(Reading {
+        now: now * volts,
+        full: full * volts,
+        rate: rate.abs() * volts,
+        status,
+    })
+}
+
+/// Reads the state of the `battery`, unless it can't be read, or it is a
+/// hot-swappable battery's empty bay.
+fn read_present_battery(battery: &Path) -> Option<Reading> {
+    if load_value::<u8>(battery, "present").ok() == Some(0) {
+        debug!("Skipping {}, which isn't present", battery.display());
+        return None;
+    }
+    match read_battery(battery) {
+        Ok(reading) => Some(reading),
+        Err(e) => {
+            debug!("Skipping {}: {}", battery.display(), e);
+            None
+        }
+    }
+}
+
+/// Finds the batteries (e.g. `BAT0` and `BAT1`) in `power_supply_dir`.
+fn find_batteries(power_supply_dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut batteries = fs::read_dir(power_supply_dir)
+        .with_context(|_| format!("Failed to list {}", power_supply_dir.display()))?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_name().to_string_lossy().starts_with("BAT"))
+        .map(|entry| entry.path())
+        .collect::<Vec<_>>();
+    batteries.sort();
+    Ok(batteries)
+}
+
 /// Shows battery charge percentage and (dis)charge time.
 ///
 /// This widget shows the battery's current charge percentage and the amount of
 /// remaining (dis)charge time, depending on whether the battery is charging or
-/// discharging. The format of the output is `(PP% HH:MM)`.
+/// discharging. The format of the output is `(PP% - H:MM)`, or another
+/// format can be given with [`with_format()`].
+///
+/// The time remaining is estimated from the average (dis)charge rate over the
+/// last five minutes, as the instantaneous rate is too jittery to be useful.
+///
+/// Laptops with more than one battery (e.g. ThinkPads with `BAT0` and `BAT1`)
+/// have them added up as if they were one, or they can be shown separately,
+/// see [`with_separate_batteries()`].
 ///
 /// When the battery has less than 10% charge remaining, the widget's text will
-/// change to the specified `warning_color`.
+/// change to the specified `warning_color`, or other levels can be given with
+/// [`with_threshold()`].
+///
+/// Battery charge information is read from [`/sys/class/power_supply/BAT*/`].
 ///
-/// Battery charge information is read from [`/sys/class/power_supply/BAT0/`].
+/// The battery is checked every minute, and whenever the kernel reports that
+/// a power supply (such as the charger) has changed.
 ///
-/// [`/sys/class/power_supply/BAT0/`]: https://www.kernel.org/doc/Documentation/power/power_supply_class.txt
+/// While the battery is discharging, Cnx switches to low-power mode (unless
+/// this has been disabled with [`Cnx::set_low_power()`]).
+///
+/// [`Cnx::set_low_power()`]: ../struct.Cnx.html#method.set_low_power
+/// [`with_threshold()`]: #method.with_threshold
+/// [`with_format()`]: #method.with_format
+/// [`with_separate_batteries()`]: #method.with_separate_batteries
+/// [`/sys/class/power_supply/BAT*/`]: https://www.kernel.org/doc/Documentation/power/power_supply_class.txt
 pub struct Battery {
     timer: Timer,
     update_interval: Duration,
-    battery: String,
+    netlink: Netlink,
+    power_supply_dir: PathBuf,
+    separate: bool,
+    format: Template,
+    // The average rate of each battery, or of all of them combined, keyed
+    // by the power supply directory.
+    rates: RefCell<HashMap<PathBuf, RateAverage>>,
     attr: Attributes,
-    warning_color: Color,
+    // The attributes for low charge levels, while discharging.
+    threshold: Threshold<f64>,
 }
 
 impl Battery {
@@ -87,6 +303,7 @@ impl Battery {
     ///     fg_color: Color::white(),
     ///     bg_color: None,
     ///     padding: Padding::new(8.0, 8.0, 0.0, 0.0),
+    ///     border: None,
     /// };
     ///
     /// let mut cnx = Cnx::new(Position::Top)?;
@@ -96,80 +313,180 @@ impl Battery {
     /// # fn main() { run().unwrap(); }
     /// ```
     pub fn new(cnx: &Cnx, attr: Attributes, warning_color: Color) -> Battery {
+        let mut warning_attr = attr.clone();
+        warning_attr.fg_color = warning_color;
         Battery {
             timer: cnx.timer(),
             update_interval: Duration::from_secs(60),
-            battery: "BAT0".to_owned(),
+            netlink: cnx.netlink(),
+            power_supply_dir: PathBuf::from("/sys/class/power_supply"),
+            separate: false,
+            format: DEFAULT_FORMAT.parse().expect("Invalid default format"),
+            rates: RefCell::new(HashMap::new()),
             attr,
-            warning_color,
+            threshold: Threshold::falling().warn(10.0, warning_attr),
         }
     }
 
-    fn load_value_inner<T>(&self, file: &str) -> Result<T>
-    where
-        T: FromStr,
-        <T as FromStr>::Err: Into<Error>,
-    {
-        let path = format!("/sys/class/power_supply/{}/{}", self.battery, file);
-        let mut file = File::open(path)?;
-        let mut contents = String::new();
-        file.read_to_string(&mut contents)?;
-        let s = FromStr::from_str(contents.trim())
-            .map_err(|e: <T as FromStr>::Err| e.into())
-            .context("Failed to parse value")?;
-        Ok(s)
-    }
-
-    fn load_value<T>(&self, file: &str) -> Result<T>
-    where
-        T: FromStr,
-        <T as FromStr>::Err: Into<Error>,
-    {
-        let value = self
-            .load_value_inner(file)
-            .with_context(|_| format!("Could not load value from battery status file: {}", file))?;
-        Ok(value)
+    /// Shows the charge percentage with the [`Attributes`] of the levels of
+    /// `threshold` while discharging, rather than in the `warning_color` once
+    /// it is 10% or less.
+    ///
+    /// [`Attributes`]: ../text/struct.Attributes.html
+    pub fn with_threshold(mut self, threshold: Threshold<f64>) -> Battery {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Shows `format` rather than `({percent}% - {time_left})`, in which
+    /// `{percent}` is replaced by the charge percentage and `{time_left}` by
+    /// the time to empty or full, as `H:MM`.
+    pub fn with_format(mut self, format: Template) -> Battery {
+        self.format = format;
+        self
+    }
+
+    /// Shows each battery's charge and time remaining separately, rather
+    /// than adding them all up.
+    pub fn with_separate_batteries(mut self) -> Battery {
+        self.separate = true;
+        self
+    }
+
+    /// Returns a stream which yields whenever the kernel reports a change to
+    /// a power supply, e.g. the charger being plugged in, so that we don't
+    /// have to wait for the next tick to notice.
+    fn power_supply_events(&self) -> Result<Refresh> {
+        let events = self
+            .netlink
+            .subscribe(Family::Uevent, |event| match *event {
+                NetlinkEvent::Uevent { ref subsystem, .. } => subsystem == "power_supply",
+                _ => false,
+            });
+        match events {
+            Ok(events) => Ok(Box::new(events.map(|_| ()))),
+            Err(e) => {
+                warn!("Battery won't notice power supply changes: {}", e);
+                Ok(Box::new(stream::empty()))
+            }
+        }
+    }
+
+    fn render(&self, reading: &Reading) -> Text {
+        // If we're discharging and the charge is low, then render with
+        // special warning attributes.
+        let attr = if reading.status == Status::Discharging {
+            self.threshold.apply(&reading.percentage(), &self.attr)
+        } else {
+            self.attr.clone()
+        };
+        Text::new(attr, reading.format(&self.format))
     }
 
     fn tick(&self) -> Result<Vec<Text>> {
-        let full: f64 = self.load_value("charge_full")?;
-        let now: f64 = self.load_value("charge_now")?;
-        let percentage = (now / full) * 100.0;
-
-        // If we're discharging, show time to empty.
-        // If we're charging, show time to full.
-        let power: f64 = self
-            .load_value("current_avg")
-            .or_else(|_| self.load_value("current_now"))?;
-        let status: Status = self.load_value("status")?;
-        let time = match status {
-            Status::Discharging => now / power,
-            Status::Charging => (full - now) / power,
-            _ => 0.0,
+        let (batteries, readings): (Vec<_>, Vec<_>) = find_batteries(&self.power_supply_dir)?
+            .into_iter()
+            .filter_map(|battery| {
+                let reading = read_present_battery(&battery)?;
+                Some((battery, reading))
+            })
+            .unzip();
+        if readings.is_empty() {
+            return Err(format_err!(
+                "No batteries in {}",
+                self.power_supply_dir.display()
+            ));
+        }
+        let combined = Reading::combine(&readings);
+        self.timer
+            .power()
+            .set_discharging(combined.status == Status::Discharging);
+
+        let now = Instant::now();
+        let mut rates = self.rates.borrow_mut();
+        if self.separate {
+            Ok(batteries
+                .into_iter()
+                .zip(readings.iter())
+                .map(|(battery, reading)| {
+                    self.render(&rates.entry(battery).or_default().smooth(reading, now))
+                })
+                .collect())
+        } else {
+            let rate = rates.entry(self.power_supply_dir.clone()).or_default();
+            Ok(vec![self.render(&rate.smooth(&combined, now))])
+        }
+    }
+}
+
+timer_widget!(Battery, timer, update_interval, tick; power_supply_events);
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use super::{RateAverage, Reading, Status, DEFAULT_FORMAT};
+    use crate::template::Template;
+
+    #[test]
+    fn works() {
+        let default: Template = DEFAULT_FORMAT.parse().unwrap();
+        let bat0 = Reading {
+            now: 10_000_000.0,
+            full: 20_000_000.0,
+            rate: 5_000_000.0,
+            status: Status::Discharging,
         };
-        let hours = time as u64;
-        let minutes = (time * 60.0) as u64 % 60;
+        assert_eq!(bat0.format(&default), "(50% - 2:00)");
 
-        let text = format!(
-            "({percentage:.0}% - {hours}:{minutes:02})",
-            percentage = percentage,
-            hours = hours,
-            minutes = minutes
+        let bat1 = Reading {
+            now: 20_000_000.0,
+            full: 20_000_000.0,
+            rate: 0.0,
+            status: Status::NotCharging,
+        };
+        let combined = Reading::combine(&[bat0, bat1.clone()]);
+        assert_eq!(combined.status, Status::Discharging);
+        assert_eq!(combined.format(&default), "(75% - 6:00)");
+
+        let charging = Reading {
+            now: 15_000_000.0,
+            rate: 10_000_000.0,
+            status: Status::Charging,
+            ..bat1.clone()
+        };
+        let combined = Reading::combine(&[charging.clone(), bat1]);
+        assert_eq!(combined.status, Status::Charging);
+        assert_eq!(combined.format(&default), "(88% - 0:30)");
+        assert_eq!(
+            combined.format(&"{percent:.1}% ({time_left})".parse().unwrap()),
+            "87.5% (0:30)"
         );
 
-        // If we're discharging and have <=10% left, then render with a
-        // special warning color.
-        let mut attr = self.attr.clone();
-        if status == Status::Discharging && percentage <= 10.0 {
-            attr.fg_color = self.warning_color.clone()
-        }
+        let mut average = RateAverage::default();
+        let start = Instant::now();
+        let minutes = |n: u64| start + Duration::from_secs(n * 60);
+        assert_eq!(average.smooth(&charging, minutes(0)).rate, 10_000_000.0);
+        let slower = Reading {
+            rate: 5_000_000.0,
+            ..charging.clone()
+        };
+        assert_eq!(average.smooth(&slower, minutes(1)).rate, 7_500_000.0);
+        // The first rate is too old to count.
+        assert_eq!(average.smooth(&slower, minutes(6)).rate, 5_000_000.0);
+        // Discharging starts afresh.
+        let discharging = Reading {
+            rate: 2_000_000.0,
+            status: Status::Discharging,
+            ..charging
+        };
+        assert_eq!(average.smooth(&discharging, minutes(7)).rate, 2_000_000.0);
 
-        Ok(vec![Text {
-            attr,
-            text,
-            stretch: false,
-        }])
+        let calibrating = Reading {
+            now: 0.0,
+            full: 0.0,
+            ..discharging
+        };
+        assert_eq!(calibrating.format(&default), "(0% - 0:00)");
     }
 }
-
-timer_widget!(Battery, timer, update_interval, tick);
@@ -1,22 +1,237 @@
+use std::cell::{Cell, RefCell};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
 use std::time::Duration;
 
 use chrono::prelude::*;
-use failure::ResultExt;
+use futures::unsync::mpsc::{self, UnboundedReceiver, UnboundedSender};
 use futures::{stream, Future, Stream};
-use tokio_timer::Timer;
+use log::warn;
 
-use super::{Widget, WidgetStream};
+use super::{Click, ClickHandler, Refresh, Widget, WidgetStream};
+use crate::error::{format_err, ResultExt};
+use crate::inotify::{FileEventKind, Inotify};
+use crate::popup::Popup;
 use crate::text::{Attributes, Text};
+use crate::timer::Timer;
+use crate::zoneinfo::Zone;
 use crate::{Cnx, Result};
 
+// The longest event read from a calendar, so that a broken one can't make us
+// fill memory.
+const MAX_EVENT_DAYS: i64 = 366;
+
+/// A special date, on which the [`Clock`] is highlighted.
+///
+/// [`Clock`]: struct.Clock.html
+#[derive(Clone, Debug, PartialEq)]
+pub struct Holiday {
+    /// The day of the holiday. If it's `yearly`, the year is ignored.
+    pub date: NaiveDate,
+    /// Whether the holiday is on the same day every year, e.g. a birthday.
+    pub yearly: bool,
+    /// A short label to show after the time, e.g. `Holiday`.
+    pub label: Option<String>,
+}
+
+impl Holiday {
+    /// A holiday on `date`, without a label.
+    pub fn new(date: NaiveDate) -> Holiday {
+        Holiday {
+            date,
+            yearly: false,
+            label: None,
+        }
+    }
+
+    /// A holiday on the same `month` and `day` every year, without a label.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there's no such day, e.g. 31st February.
+    pub fn yearly(month: u32, day: u32) -> Holiday {
+        Holiday {
+            // A leap year, so that 29th February is allowed.
+            date: NaiveDate::from_ymd(2000, month, day),
+            yearly: true,
+            label: None,
+        }
+    }
+
+    /// Shows `label` after the time on the holiday.
+    pub fn with_label<S: Into<String>>(mut self, label: S) -> Holiday {
+        self.label = Some(label.into());
+        self
+    }
+
+    fn falls_on(&self, date: NaiveDate) -> bool {
+        if self.yearly {
+            (self.date.month(), self.date.day()) == (date.month(), date.day())
+        } else {
+            self.date == date
+        }
+    }
+}
+
+/// Reads the date at the end of an iCalendar `DTSTART` or `DTEND` line, e.g.
+/// `DTSTART;VALUE=DATE:20261225`, ignoring any time.
+fn parse_ics_date(value: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(value.get(..8)?, "%Y%m%d").ok()
+}
+
+/// Unescapes an iCalendar text value, e.g. `Boxing Day\, UK`.
+fn unescape_ics(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('n') | Some('N') => unescaped.push(' '),
+                Some(c) => unescaped.push(c),
+                None => {}
+            },
+            c => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+/// Reads the holidays from an iCalendar file (`.ics`), with each event's
+/// summary as its label. Events spanning several days become a holiday on
+/// each of them, and yearly events are yearly holidays.
+fn parse_ics(ics: &str) -> Vec<Holiday> {
+    // Long lines are folded onto lines starting with whitespace.
+    let mut lines: Vec<String> = Vec::new();
+    for line in ics.lines() {
+        match (
+            line.strip_prefix(|c| c == ' ' || c == '\t'),
+            lines.last_mut(),
+        ) {
+            (Some(rest), Some(last)) => last.push_str(rest),
+            _ => lines.push(line.to_owned()),
+        }
+    }
+
+    let mut holidays = Vec::new();
+    let (mut start, mut end, mut yearly, mut summary) = (None, None, false, None);
+    for line in &lines {
+        let (name, value) = match line.split_once(':') {
+            Some((name, value)) => (name.split(';').next().unwrap_or(name), value),
+            None => continue,
+        };
+        match name {
+            "BEGIN" if value == "VEVENT" => {
+                start = None;
+                end = None;
+                yearly = false;
+                summary = None;
+            }
+            "DTSTART" => start = parse_ics_date(value),
+            "DTEND" => end = parse_ics_date(value),
+            "RRULE" => yearly = value.split(';').any(|part| part == "FREQ=YEARLY"),
+            "SUMMARY" => summary = Some(unescape_ics(value)),
+            "END" if value == "VEVENT" => {
+                let start = match start {
+                    Some(start) => start,
+                    None => continue,
+                };
+                // The end date is the day after the event.
+                let days = end.map_or(1, |end| (end - start).num_days());
+                for day in 0..days.clamp(1, MAX_EVENT_DAYS) {
+                    holidays.push(Holiday {
+                        date: start + chrono::Duration::days(day),
+                        yearly,
+                        label: summary.clone(),
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+    holidays
+}
+
+/// Lays out the month of `today` as a calendar, with `today` in bold (as
+/// Pango markup) and weeks starting on Monday, e.g.:
+///
+/// ```text
+///     October 2026
+/// Mo Tu We Th Fr Sa Su
+///           1  2  3  4
+///  5  6  7  8  9 10 11
+/// ```
+fn month_calendar(today: NaiveDate) -> String {
+    let first = NaiveDate::from_ymd(today.year(), today.month(), 1);
+    let title = first.format("%B %Y").to_string();
+    let mut lines = vec![
+        format!("{:^20}", title).trim_end().to_owned(),
+        "Mo Tu We Th Fr Sa Su".to_owned(),
+    ];
+    let mut week = "   ".repeat(first.weekday().num_days_from_monday() as usize);
+    let mut date = first;
+    while date.month() == today.month() {
+        if date == today {
+            week.push_str(&format!("<b>{:2}</b>", date.day()));
+        } else {
+            week.push_str(&format!("{:2}", date.day()));
+        }
+        if date.weekday() == Weekday::Sun {
+            lines.push(std::mem::take(&mut week));
+        } else {
+            week.push(' ');
+        }
+        date = date.succ();
+    }
+    if !week.is_empty() {
+        lines.push(week.trim_end().to_owned());
+    }
+    lines.join("\n")
+}
+
 /// Shows the current time and date.
 ///
 /// This widget shows the current time and date, in the form `%Y-%m-%d %a %I:%M
 /// %p`, e.g. `2017-09-01 Fri 12:51 PM`.
+///
+/// The clock can be highlighted on holidays (or any other special dates),
+/// showing them with different [`Attributes`] and a label after the time, e.g.
+/// `2017-12-25 Mon 09:00 AM • Christmas Day`. See [`with_holidays()`] and
+/// [`with_holiday_calendar()`].
+///
+/// The time in other time zones can be shown after the local time, e.g. UTC
+/// or a colleague's, see [`with_zones()`].
+///
+/// Clicking the clock can switch it to another format, e.g. from just the
+/// time to the full date, and show a month calendar until it is clicked
+/// again. See [`with_alternate_format()`] and [`with_calendar_popup()`].
+///
+/// [`Attributes`]: ../text/struct.Attributes.html
+/// [`with_holidays()`]: #method.with_holidays
+/// [`with_holiday_calendar()`]: #method.with_holiday_calendar
+/// [`with_zones()`]: #method.with_zones
+/// [`with_alternate_format()`]: #method.with_alternate_format
+/// [`with_calendar_popup()`]: #method.with_calendar_popup
 pub struct Clock {
     format: String,
+    alternate_format: Option<String>,
+    calendar_popup: bool,
+    // Whether the clock has been clicked (an odd number of times), and so
+    // shows the alternate format.
+    toggled: Rc<Cell<bool>>,
+    clicks: UnboundedSender<()>,
+    // Taken by the stream, to redraw the clock when it is clicked.
+    clicked: Option<UnboundedReceiver<()>>,
     timer: Timer,
+    inotify: Inotify,
     attr: Attributes,
+    holidays: Vec<Holiday>,
+    calendar: Option<PathBuf>,
+    // The holidays last read from the calendar.
+    calendar_holidays: RefCell<Vec<Holiday>>,
+    holiday_attr: Option<Attributes>,
+    // The label, zone and format of each other time zone to show.
+    zones: Vec<(String, Zone, String)>,
 }
 
 impl Clock {
@@ -37,9 +252,6 @@ impl Clock {
     /// # Examples
     ///
     /// ```
-    /// # #[macro_use]
-    /// # extern crate cnx;
-    /// #
     /// # use cnx::*;
     /// # use cnx::text::*;
     /// # use cnx::widgets::*;
@@ -50,49 +262,386 @@ impl Clock {
     ///     fg_color: Color::white(),
     ///     bg_color: None,
     ///     padding: Padding::new(8.0, 8.0, 0.0, 0.0),
+    ///     border: None,
     /// };
     ///
     /// let mut cnx = Cnx::new(Position::Top)?;
-    /// cnx.add_widget(String::from("%Y-%m-%d %a %I:%M %p")) Clock::new(&cnx, attr.clone()));
+    /// cnx.add_widget(Clock::new(&cnx, String::from("%Y-%m-%d %a %I:%M %p"), attr.clone()));
     /// # Ok(())
     /// # }
     /// # fn main() { run().unwrap(); }
     /// ```
     pub fn new(cnx: &Cnx, format: String, attr: Attributes) -> Clock {
+        let (clicks, clicked) = mpsc::unbounded();
         Clock {
             format,
+            alternate_format: None,
+            calendar_popup: false,
+            toggled: Rc::new(Cell::new(false)),
+            clicks,
+            clicked: Some(clicked),
             timer: cnx.timer(),
+            inotify: cnx.inotify(),
             attr,
+            holidays: Vec::new(),
+            calendar: None,
+            calendar_holidays: RefCell::new(Vec::new()),
+            holiday_attr: None,
+            zones: Vec::new(),
+        }
+    }
+
+    /// Shows the clock with `attr` on each of the `holidays`, followed by
+    /// their labels.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use cnx::*;
+    /// # use cnx::text::*;
+    /// # use cnx::widgets::*;
+    /// #
+    /// # fn run() -> ::cnx::Result<()> {
+    /// # let attr = Attributes {
+    /// #     font: Font::new("SourceCodePro 21"),
+    /// #     fg_color: Color::white(),
+    /// #     bg_color: None,
+    /// #     padding: Padding::new(8.0, 8.0, 0.0, 0.0),
+    /// #     border: None,
+    /// # };
+    /// let mut holiday_attr = attr.clone();
+    /// holiday_attr.fg_color = Color::red();
+    ///
+    /// let mut cnx = Cnx::new(Position::Top)?;
+    /// let holidays = vec![
+    ///     Holiday::yearly(12, 25).with_label("Holiday"),
+    ///     Holiday::yearly(4, 1),
+    /// ];
+    /// let clock = Clock::new(&cnx, "%H:%M".to_owned(), attr).with_holidays(holidays, holiday_attr);
+    /// cnx.add_widget(clock);
+    /// # Ok(())
+    /// # }
+    /// # fn main() { run().unwrap(); }
+    /// ```
+    pub fn with_holidays(mut self, holidays: Vec<Holiday>, attr: Attributes) -> Clock {
+        self.holidays = holidays;
+        self.holiday_attr = Some(attr);
+        self
+    }
+
+    /// Shows the clock with `attr` on the days of the events in the
+    /// iCalendar file (`.ics`) at `path`, followed by their summaries. Public
+    /// holiday calendars can be downloaded from most calendar services.
+    ///
+    /// The file is read when the clock starts, and again whenever it changes.
+    /// If it can't be read, a warning is logged and only the holidays given
+    /// to [`with_holidays()`] are highlighted.
+    ///
+    /// [`with_holidays()`]: #method.with_holidays
+    pub fn with_holiday_calendar<P: Into<PathBuf>>(mut self, path: P, attr: Attributes) -> Clock {
+        self.calendar = Some(path.into());
+        self.holiday_attr = Some(attr);
+        self
+    }
+
+    /// Shows the time in each of the `zones` after the local time, as a
+    /// separate text: its label (if it isn't empty), followed by the time in
+    /// that zone, formatted with its format. In these formats, `%Z` is the
+    /// zone's abbreviation at the time, e.g. `CEST`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use cnx::*;
+    /// # use cnx::text::*;
+    /// # use cnx::widgets::*;
+    /// # use cnx::zoneinfo::Zone;
+    /// #
+    /// # fn run() -> ::cnx::Result<()> {
+    /// # let attr = Attributes {
+    /// #     font: Font::new("SourceCodePro 21"),
+    /// #     fg_color: Color::white(),
+    /// #     bg_color: None,
+    /// #     padding: Padding::new(8.0, 8.0, 0.0, 0.0),
+    /// #     border: None,
+    /// # };
+    /// let mut cnx = Cnx::new(Position::Top)?;
+    /// let zones = vec![
+    ///     ("UTC".to_owned(), Zone::utc(), "%H:%M".to_owned()),
+    ///     ("SF".to_owned(), Zone::load("America/Los_Angeles")?, "%H:%M %Z".to_owned()),
+    /// ];
+    /// cnx.add_widget(Clock::new(&cnx, "%H:%M".to_owned(), attr).with_zones(zones));
+    /// # Ok(())
+    /// # }
+    /// # fn main() { run().unwrap(); }
+    /// ```
+    pub fn with_zones(mut self, zones: Vec<(String, Zone, String)>) -> Clock {
+        self.zones = zones;
+        self
+    }
+
+    /// Shows the clock in `format` instead once it is left-clicked, until it
+    /// is clicked again.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use cnx::*;
+    /// # use cnx::text::*;
+    /// # use cnx::widgets::*;
+    /// #
+    /// # fn run() -> ::cnx::Result<()> {
+    /// # let attr = Attributes {
+    /// #     font: Font::new("SourceCodePro 21"),
+    /// #     fg_color: Color::white(),
+    /// #     bg_color: None,
+    /// #     padding: Padding::new(8.0, 8.0, 0.0, 0.0),
+    /// #     border: None,
+    /// # };
+    /// let mut cnx = Cnx::new(Position::Top)?;
+    /// let clock = Clock::new(&cnx, "%H:%M".to_owned(), attr)
+    ///     .with_alternate_format("%A %-d %B %Y, %H:%M".to_owned())
+    ///     .with_calendar_popup();
+    /// cnx.add_widget(clock);
+    /// # Ok(())
+    /// # }
+    /// # fn main() { run().unwrap(); }
+    /// ```
+    pub fn with_alternate_format(mut self, format: String) -> Clock {
+        self.alternate_format = Some(format);
+        self
+    }
+
+    /// Shows a calendar of the current month (with today in bold) in a popup
+    /// window by the pointer once the clock is left-clicked, until it is
+    /// clicked again.
+    ///
+    /// The calendar is drawn with the clock's [`Attributes`], so its columns
+    /// only line up in a monospace font.
+    ///
+    /// [`Attributes`]: ../text/struct.Attributes.html
+    pub fn with_calendar_popup(mut self) -> Clock {
+        self.calendar_popup = true;
+        self
+    }
+
+    /// Reads the holidays from the calendar, if there is one.
+    fn read_calendar(&self) -> Vec<Holiday> {
+        let path = match self.calendar {
+            Some(ref path) => path,
+            None => return Vec::new(),
+        };
+        match fs::read_to_string(path) {
+            Ok(ics) => parse_ics(&ics),
+            Err(e) => {
+                warn!("Failed to read holidays from {}: {}", path.display(), e);
+                Vec::new()
+            }
+        }
+    }
+
+    /// Returns a stream which yields whenever the calendar changes, or
+    /// doesn't yield if there is no calendar or it can't be watched.
+    fn calendar_changes(&self) -> Refresh {
+        let path = match self.calendar {
+            Some(ref path) => path.clone(),
+            None => return Box::new(stream::empty()),
+        };
+        // Calendars are usually replaced when they are downloaded again,
+        // which would end a watch on the file itself, so watch its directory.
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_owned(),
+            _ => Path::new(".").to_owned(),
+        };
+        let name = path.file_name().map(ToOwned::to_owned);
+        match self.inotify.watch(&dir) {
+            Ok(events) => Box::new(
+                events
+                    .filter(move |event| {
+                        event.kind != FileEventKind::Removed
+                            && event.path.file_name() == name.as_deref()
+                    })
+                    .map(|_| ()),
+            ),
+            Err(e) => {
+                warn!("Clock won't notice changes to {}: {}", path.display(), e);
+                Box::new(stream::empty())
+            }
+        }
+    }
+
+    fn render(&self, now: DateTime<Local>) -> Vec<Text> {
+        let format = match self.alternate_format {
+            Some(ref format) if self.toggled.get() => format,
+            _ => &self.format,
+        };
+        let mut text = now.format(format).to_string();
+        let calendar_holidays = self.calendar_holidays.borrow();
+        let today: Vec<_> = self
+            .holidays
+            .iter()
+            .chain(calendar_holidays.iter())
+            .filter(|holiday| holiday.falls_on(now.date().naive_local()))
+            .collect();
+        let attr = match self.holiday_attr {
+            Some(ref attr) if !today.is_empty() => attr.clone(),
+            _ => self.attr.clone(),
+        };
+        let labels: Vec<_> = today
+            .iter()
+            .filter_map(|holiday| holiday.label.as_deref())
+            .collect();
+        if !labels.is_empty() {
+            text = format!("{} • {}", text, labels.join(", "));
         }
+        let mut texts = vec![Text::new(attr, text)];
+        for (label, zone, format) in &self.zones {
+            let (offset, abbreviation) = zone.offset_at(now.timestamp());
+            // chrono would show the offset, as it doesn't know the zone.
+            let format = format.replace("%Z", &abbreviation.replace('%', "%%"));
+            let time = now.with_timezone(&offset).format(&format).to_string();
+            let text = if label.is_empty() {
+                time
+            } else {
+                format!("{} {}", label, time)
+            };
+            texts.push(Text::new(self.attr.clone(), text));
+        }
+        texts
     }
 }
 
 impl Widget for Clock {
+    fn click_handler(&mut self) -> Option<ClickHandler> {
+        if self.alternate_format.is_none() && !self.calendar_popup {
+            return None;
+        }
+        let toggled = self.toggled.clone();
+        let clicks = self.clicks.clone();
+        let calendar_popup = self.calendar_popup;
+        let attr = self.attr.clone();
+        let mut popup = None;
+        Some(Box::new(move |click: Click| {
+            if click.button != 1 {
+                return Ok(());
+            }
+            toggled.set(!toggled.get());
+            // The widget may have stopped, in which case there's nothing to
+            // redraw.
+            let _ = clicks.unbounded_send(());
+            // Dropping the popup closes it.
+            if popup.take().is_none() && calendar_popup {
+                popup = Some(Popup::show(
+                    Text::new(attr.clone(), month_calendar(Local::today().naive_local()))
+                        .with_markup(),
+                )?);
+            }
+            Ok(())
+        }))
+    }
+
     fn stream(self: Box<Self>) -> Result<WidgetStream> {
         // As we're not showing seconds, we can sleep for however long it takes
         // until the minutes changes between updates. Initially sleep for 0 seconds
         // so that our `self.timer.sleep()` expires immediately.
         let sleep_for = Duration::from_secs(0);
+        let mut clock = *self;
+        *clock.calendar_holidays.borrow_mut() = clock.read_calendar();
+        let clicked = clock.clicked.take();
+        let clock = Rc::new(clock);
+        let redrawn = clock.clone();
+        let clicks = stream::iter_ok::<_, ()>(clicked)
+            .flatten()
+            .map_err(|()| format_err!("Clock channel closed"))
+            .map(move |()| redrawn.render(Local::now()));
+        let reread = clock.clone();
+        let calendar_changes = clock.calendar_changes().map(move |()| {
+            *reread.calendar_holidays.borrow_mut() = reread.read_calendar();
+            reread.render(Local::now())
+        });
         let stream = stream::unfold(sleep_for, move |sleep_for| {
             // Avoid having to move self into the .map() closure.
-            let attr = self.attr.clone();
-            let format_str = self.format.clone();
-            Some(self.timer.sleep(sleep_for).map(move |()| {
+            let clock = clock.clone();
+            Some(clock.timer.sleep(sleep_for).map(move |()| {
                 let now = Local::now();
-                let formatted = now.format(&format_str).to_string();
-                let texts = vec![Text {
-                    attr: attr,
-                    text: formatted,
-                    stretch: false,
-                }];
-
+                let texts = clock.render(now);
                 let sleep_for = Duration::from_secs(60 - u64::from(now.second()));
                 (texts, sleep_for)
             }))
         })
-        .then(|r| r.context("Error in tokio_timer stream"))
-        .map_err(|e| e.into());
+        .then(|r| r.context("Error in tokio_timer stream"));
+
+        Ok(Box::new(stream.select(clicks).select(calendar_changes)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::NaiveDate;
+
+    use super::{month_calendar, parse_ics, Holiday};
+
+    #[test]
+    fn works() {
+        let ics = "BEGIN:VCALENDAR\r\n\
+                   BEGIN:VEVENT\r\n\
+                   DTSTART;VALUE=DATE:20261225\r\n\
+                   DTEND;VALUE=DATE:20261227\r\n\
+                   SUMMARY:Christmas\\, and Boxing\r\n  Day\r\n\
+                   END:VEVENT\r\n\
+                   BEGIN:VEVENT\r\n\
+                   DTSTART:20260401T090000Z\r\n\
+                   RRULE:FREQ=YEARLY;BYMONTH=4\r\n\
+                   END:VEVENT\r\n\
+                   END:VCALENDAR\r\n";
+        let label = Some("Christmas, and Boxing Day".to_owned());
+        assert_eq!(
+            parse_ics(ics),
+            vec![
+                Holiday {
+                    date: NaiveDate::from_ymd(2026, 12, 25),
+                    yearly: false,
+                    label: label.clone(),
+                },
+                Holiday {
+                    date: NaiveDate::from_ymd(2026, 12, 26),
+                    yearly: false,
+                    label,
+                },
+                Holiday {
+                    date: NaiveDate::from_ymd(2026, 4, 1),
+                    yearly: true,
+                    label: None,
+                },
+            ]
+        );
+
+        let april_fools = Holiday::yearly(4, 1);
+        assert!(april_fools.falls_on(NaiveDate::from_ymd(2031, 4, 1)));
+        assert!(!april_fools.falls_on(NaiveDate::from_ymd(2031, 4, 2)));
+        assert!(!Holiday::new(NaiveDate::from_ymd(2026, 4, 1))
+            .falls_on(NaiveDate::from_ymd(2027, 4, 1)));
+    }
 
-        Ok(Box::new(stream))
+    #[test]
+    fn shows_month_calendar() {
+        assert_eq!(
+            month_calendar(NaiveDate::from_ymd(2026, 10, 14)),
+            "    October 2026\n\
+             Mo Tu We Th Fr Sa Su\n\
+             \x20         1  2  3  4\n\
+             \x205  6  7  8  9 10 11\n\
+             12 13 <b>14</b> 15 16 17 18\n\
+             19 20 21 22 23 24 25\n\
+             26 27 28 29 30 31"
+        );
+        // A month starting on a Monday, and ending on a Sunday.
+        let february = month_calendar(NaiveDate::from_ymd(2027, 2, 1));
+        assert!(february.ends_with(
+            "\n<b> 1</b>  2  3  4  5  6  7\n\
+             \x208  9 10 11 12 13 14\n\
+             15 16 17 18 19 20 21\n\
+             22 23 24 25 26 27 28"
+        ));
     }
 }
@@ -0,0 +1,241 @@
+use std::cell::Cell;
+use std::rc::Rc;
+use std::time::Duration;
+
+use futures::{stream, Future, Stream};
+use log::{debug, warn};
+use tokio_core::reactor::Handle;
+
+use super::{Refresh, Widget, WidgetStream};
+use crate::error::{format_err, Error};
+use crate::http;
+use crate::netlink::{Family, Netlink, NetlinkEvent};
+use crate::text::{Attributes, Text};
+use crate::timer::Timer;
+use crate::{Cnx, Result};
+
+/// How long the network is left to settle after a change, before probing.
+const SETTLE: Duration = Duration::from_secs(2);
+
+/// How long a probe can take before we count ourselves offline.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often we probe again while we aren't online, e.g. to notice logging in
+/// to a captive portal, which the kernel doesn't see.
+const RETRY_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Whether we can reach the internet.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum State {
+    Online,
+    // Something answered the probe, but not with the expected response, so
+    // presumably a captive portal is intercepting our requests.
+    Portal,
+    Offline,
+}
+
+/// Works out the `State` from the status code of the probe's response, or the
+/// error sending it.
+fn classify(status: &Result<u16>) -> State {
+    match *status {
+        Ok(204) => State::Online,
+        Ok(_) => State::Portal,
+        Err(_) => State::Offline,
+    }
+}
+
+/// Shows whether we're online, behind a captive portal or offline.
+///
+/// This widget checks for a connection by requesting a page which always
+/// responds with `204 No Content`, `http://connectivitycheck.gstatic.com/generate_204`
+/// by default (see [`with_probe()`]). If some other response comes back, a
+/// captive portal is presumably intercepting the request, e.g. on hotel or
+/// airport Wi-Fi. If no response comes back at all (or the probe's host can't
+/// be resolved), we're offline.
+///
+/// Rather than polling, the check is made whenever the kernel reports that
+/// a network interface, address or route has changed, once things settle.
+/// While we aren't online it is also repeated every 30 seconds, to notice
+/// logging in to a captive portal.
+///
+/// [`with_probe()`]: #method.with_probe
+pub struct Connectivity {
+    handle: Handle,
+    timer: Timer,
+    netlink: Netlink,
+    attr: Attributes,
+    host: String,
+    path: String,
+    // The texts for each state: online, captive portal and offline.
+    labels: [String; 3],
+}
+
+impl Connectivity {
+    /// Creates a new Connectivity widget.
+    ///
+    /// Creates a new `Connectivity` widget, whose text will be displayed with
+    /// the given [`Attributes`]. It shows `online`, `portal` or `offline`,
+    /// unless other labels are given with [`with_labels()`].
+    ///
+    /// The [`Cnx`] instance is borrowed during construction in order to get
+    /// access to handles of its event loop. However, it is not borrowed for the
+    /// lifetime of the widget. See the [`cnx_add_widget!()`] for more discussion
+    /// about the lifetime of the borrow.
+    ///
+    /// [`Attributes`]: ../text/struct.Attributes.html
+    /// [`with_labels()`]: #method.with_labels
+    /// [`Cnx`]: ../struct.Cnx.html
+    /// [`cnx_add_widget!()`]: ../macro.cnx_add_widget.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use cnx::*;
+    /// # use cnx::text::*;
+    /// # use cnx::widgets::*;
+    /// #
+    /// # fn run() -> ::cnx::Result<()> {
+    /// let attr = Attributes {
+    ///     font: Font::new("SourceCodePro 21"),
+    ///     fg_color: Color::white(),
+    ///     bg_color: None,
+    ///     padding: Padding::new(8.0, 8.0, 0.0, 0.0),
+    ///     border: None,
+    /// };
+    ///
+    /// let mut cnx = Cnx::new(Position::Top)?;
+    /// // Only show something when there's a problem.
+    /// let connectivity = Connectivity::new(&cnx, attr.clone());
+    /// cnx.add_widget(connectivity.with_labels("", "⚠ portal", "✗ offline"));
+    /// # Ok(())
+    /// # }
+    /// # fn main() { run().unwrap(); }
+    /// ```
+    pub fn new(cnx: &Cnx, attr: Attributes) -> Connectivity {
+        Connectivity {
+            handle: cnx.handle(),
+            timer: cnx.timer(),
+            netlink: cnx.netlink(),
+            attr,
+            host: "connectivitycheck.gstatic.com".to_owned(),
+            path: "/generate_204".to_owned(),
+            labels: [
+                "online".to_owned(),
+                "portal".to_owned(),
+                "offline".to_owned(),
+            ],
+        }
+    }
+
+    /// Probes `http://<host><path>` instead, e.g. on a server of your own,
+    /// which must respond with `204 No Content`.
+    pub fn with_probe<H: Into<String>, P: Into<String>>(
+        mut self,
+        host: H,
+        path: P,
+    ) -> Connectivity {
+        self.host = host.into();
+        self.path = path.into();
+        self
+    }
+
+    /// Shows `online`, `portal` or `offline` for each state instead. Empty
+    /// labels show nothing, e.g. to only show something when there's a
+    /// problem.
+    pub fn with_labels<O, P, F>(mut self, online: O, portal: P, offline: F) -> Connectivity
+    where
+        O: Into<String>,
+        P: Into<String>,
+        F: Into<String>,
+    {
+        self.labels = [online.into(), portal.into(), offline.into()];
+        self
+    }
+
+    /// Returns a stream which yields whenever the kernel reports a change
+    /// which could affect our connection, once the changes stop.
+    fn network_changes(&self) -> Box<dyn Stream<Item = (), Error = Error>> {
+        let changes = self.netlink.subscribe(Family::Route, |event| match *event {
+            NetlinkEvent::Link { .. }
+            | NetlinkEvent::Address { .. }
+            | NetlinkEvent::Route { .. } => true,
+            NetlinkEvent::Uevent { .. } => false,
+        });
+        match changes {
+            Ok(changes) => Box::new(self.timer.debounced(changes, SETTLE)),
+            Err(e) => {
+                warn!("Connectivity won't notice network changes: {}", e);
+                Box::new(stream::empty())
+            }
+        }
+    }
+
+    fn render(&self, state: State) -> Vec<Text> {
+        let label = match state {
+            State::Online => &self.labels[0],
+            State::Portal => &self.labels[1],
+            State::Offline => &self.labels[2],
+        };
+        if label.is_empty() {
+            return Vec::new();
+        }
+        vec![Text::new(self.attr.clone(), label.clone())]
+    }
+}
+
+impl Widget for Connectivity {
+    fn stream(self: Box<Self>) -> Result<WidgetStream> {
+        self.refreshable_stream(Box::new(stream::empty()))
+    }
+
+    fn refreshable_stream(self: Box<Self>, refresh: Refresh) -> Result<WidgetStream> {
+        let state = Rc::new(Cell::new(State::Offline));
+        let last_state = state.clone();
+        let retries = self
+            .timer
+            .interval(RETRY_INTERVAL)
+            .filter(move |()| last_state.get() != State::Online);
+        let probes = stream::once(Ok(()))
+            .chain(self.network_changes())
+            .select(retries)
+            .select(refresh);
+
+        let (handle, timer) = (self.handle.clone(), self.timer.clone());
+        let (host, path) = (self.host.clone(), self.path.clone());
+        let stream = probes.and_then(move |()| {
+            let timeout = timer
+                .sleep(PROBE_TIMEOUT)
+                .then(|_| Err(format_err!("Timed out")));
+            let state = state.clone();
+            http::status(&handle, &timer, &host, &path)
+                .select(timeout)
+                .map(|(status, _)| status)
+                .map_err(|(e, _)| e)
+                .then(move |status| {
+                    if let Err(ref e) = status {
+                        debug!("Connectivity probe failed: {}", e);
+                    }
+                    state.set(classify(&status));
+                    Ok(state.get())
+                })
+        });
+        Ok(Box::new(stream.map(move |state| self.render(state))))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{classify, State};
+    use crate::error::format_err;
+
+    #[test]
+    fn works() {
+        assert_eq!(classify(&Ok(204)), State::Online);
+        assert_eq!(classify(&Ok(200)), State::Portal);
+        assert_eq!(classify(&Ok(302)), State::Portal);
+        assert_eq!(
+            classify(&Err(format_err!("Failed to resolve"))),
+            State::Offline
+        );
+    }
+}
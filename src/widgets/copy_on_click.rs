@@ -0,0 +1,128 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use futures::{stream, Stream};
+use log::error;
+use tokio_core::reactor::Handle;
+
+use super::{Click, ClickHandler, LayoutHandler, Refresh, Widget, WidgetStream};
+use crate::clipboard::Clipboard;
+use crate::text;
+use crate::{Cnx, Result};
+
+/// Copies another widget's text to the clipboard when it is middle-clicked.
+///
+/// This wraps any widget, showing it as it is, but copying whatever it is
+/// currently showing to the `CLIPBOARD` selection when it is clicked with the
+/// middle mouse button, e.g. to copy an IP address or the title of the song
+/// that is playing. The widget's texts are joined with spaces.
+///
+/// Other clicks are passed on to the widget. Because of how X selections
+/// work, the copied text can only be pasted while Cnx is running.
+pub struct CopyOnClick<W> {
+    inner: W,
+    handle: Handle,
+    // The widget's current texts, as they will be copied.
+    text: Rc<RefCell<String>>,
+    clipboard: Option<Rc<Clipboard>>,
+}
+
+impl<W: Widget> CopyOnClick<W> {
+    ///  Creates a new CopyOnClick widget.
+    ///
+    ///  Creates a new `CopyOnClick` widget, which shows the given `widget`,
+    ///  and copies its text when it is middle-clicked.
+    ///
+    ///  The [`Cnx`] instance is borrowed during construction in order to get
+    ///  access to handles of its event loop. However, it is not borrowed for
+    ///  the lifetime of the widget. See the [`cnx_add_widget!()`] for more
+    ///  discussion about the lifetime of the borrow.
+    ///
+    /// [`Cnx`]: ../struct.Cnx.html
+    /// [`cnx_add_widget!()`]: ../macro.cnx_add_widget.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #[macro_use]
+    /// # extern crate cnx;
+    /// #
+    /// # use cnx::*;
+    /// # use cnx::text::*;
+    /// # use cnx::widgets::*;
+    /// #
+    /// # fn run() -> ::cnx::Result<()> {
+    /// let attr = Attributes {
+    ///     font: Font::new("SourceCodePro 21"),
+    ///     fg_color: Color::white(),
+    ///     bg_color: None,
+    ///     padding: Padding::new(8.0, 8.0, 0.0, 0.0),
+    ///     border: None,
+    /// };
+    ///
+    /// let mut cnx = Cnx::new(Position::Top)?;
+    /// let title = ActiveWindowTitle::new(&cnx, attr.clone());
+    /// cnx_add_widget!(cnx, CopyOnClick::new(&cnx, title));
+    /// # Ok(())
+    /// # }
+    /// # fn main() { run().unwrap(); }
+    /// ```
+    pub fn new(cnx: &Cnx, widget: W) -> CopyOnClick<W> {
+        CopyOnClick {
+            inner: widget,
+            handle: cnx.handle(),
+            text: Rc::new(RefCell::new(String::new())),
+            clipboard: None,
+        }
+    }
+}
+
+impl<W: Widget + 'static> Widget for CopyOnClick<W> {
+    fn stream(self: Box<Self>) -> Result<WidgetStream> {
+        self.refreshable_stream(Box::new(stream::empty()))
+    }
+
+    fn refreshable_stream(self: Box<Self>, refresh: Refresh) -> Result<WidgetStream> {
+        let this = *self;
+        let text = this.text;
+        let texts = Box::new(this.inner)
+            .refreshable_stream(refresh)?
+            .inspect(move |texts| *text.borrow_mut() = text::join_plain_text(texts));
+
+        // The requests for the copied text come to our clipboard's window, so
+        // answer them as part of the widget's stream.
+        match this.clipboard {
+            Some(clipboard) => {
+                let requests = clipboard.serve(&this.handle)?.filter_map(|()| None);
+                Ok(Box::new(texts.select(requests)))
+            }
+            None => Ok(Box::new(texts)),
+        }
+    }
+
+    fn click_handler(&mut self) -> Option<ClickHandler> {
+        let mut inner = self.inner.click_handler();
+        match Clipboard::new() {
+            Ok(clipboard) => self.clipboard = Some(clipboard),
+            Err(e) => {
+                error!("CopyOnClick won't be able to copy: {}", e);
+                return inner;
+            }
+        }
+
+        let clipboard = self.clipboard.clone()?;
+        let text = self.text.clone();
+        Some(Box::new(move |click: Click| {
+            if click.button == 2 {
+                return clipboard.copy(text.borrow().clone());
+            }
+            match inner {
+                Some(ref mut handler) => handler(click),
+                None => Ok(()),
+            }
+        }))
+    }
+    fn layout_handler(&mut self) -> Option<LayoutHandler> {
+        self.inner.layout_handler()
+    }
+}
@@ -0,0 +1,255 @@
+use std::cell::RefCell;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use futures::unsync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use futures::{stream, Stream};
+
+use super::{Click, ClickHandler, Refresh};
+use crate::error::{format_err, ResultExt};
+use crate::parse::sysfs;
+use crate::template::{Template, Value};
+use crate::text::{Attributes, Text};
+use crate::timer::Timer;
+use crate::{Cnx, Result};
+
+/// The frequencies and governors of the CPUs.
+#[derive(Debug, PartialEq)]
+struct Cpus {
+    // The current frequency of each CPU, in kHz.
+    frequencies: Vec<u64>,
+    // The governor of each CPU.
+    governors: Vec<String>,
+}
+
+/// A frequency in kHz, in GHz to two decimal places, e.g. `2.40`.
+fn ghz(khz: u64) -> Value {
+    Value::Number(khz as f64 / 1_000_000.0, 2)
+}
+
+/// Summarizes the CPUs' frequencies, as the values for a format.
+fn summarize(cpus: &Cpus) -> Vec<(&'static str, Value)> {
+    let mut values = Vec::new();
+    let frequencies = &cpus.frequencies;
+    if let (Some(min), Some(max)) = (frequencies.iter().min(), frequencies.iter().max()) {
+        let avg = frequencies.iter().sum::<u64>() / frequencies.len() as u64;
+        values.push(("min", ghz(*min)));
+        values.push(("avg", ghz(avg)));
+        values.push(("max", ghz(*max)));
+    }
+    // CPUs almost always share a governor, but show each if they don't.
+    let mut governors = cpus.governors.clone();
+    governors.dedup();
+    values.push(("governor", Value::Text(governors.join("/"))));
+    values
+}
+
+/// Picks the governor after `current` out of the space separated list of
+/// `available` governors, wrapping around.
+fn next_governor<'a>(available: &'a str, current: &str) -> Option<&'a str> {
+    let governors = available.split_whitespace().collect::<Vec<_>>();
+    match governors.iter().position(|&governor| governor == current) {
+        Some(i) => Some(governors[(i + 1) % governors.len()]),
+        None => governors.first().cloned(),
+    }
+}
+
+fn read_trimmed(path: &Path) -> Result<String> {
+    let contents =
+        fs::read_to_string(path).with_context(|_| format!("Failed to read {}", path.display()))?;
+    Ok(contents.trim().to_owned())
+}
+
+/// Finds the `cpufreq` directory of each CPU under `cpu_dir`, e.g.
+/// `/sys/devices/system/cpu/cpu0/cpufreq`.
+fn find_cpufreq_dirs(cpu_dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut dirs = fs::read_dir(cpu_dir)
+        .with_context(|_| format!("Failed to list {}", cpu_dir.display()))?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            let name = entry.file_name();
+            sysfs::cpu_number(&name.to_string_lossy()).is_some()
+        })
+        .map(|entry| entry.path().join("cpufreq"))
+        // CPUs which are offline have no frequency.
+        .filter(|path| path.join("scaling_cur_freq").exists())
+        .collect::<Vec<_>>();
+    dirs.sort();
+    Ok(dirs)
+}
+
+fn read_cpus(cpu_dir: &Path) -> Result<Cpus> {
+    let dirs = find_cpufreq_dirs(cpu_dir)?;
+    if dirs.is_empty() {
+        return Err(format_err!("No CPUs have cpufreq in {}", cpu_dir.display()));
+    }
+    let frequencies = dirs
+        .iter()
+        .map(|dir| sysfs::kilohertz(&read_trimmed(&dir.join("scaling_cur_freq"))?))
+        .collect::<Result<_>>()?;
+    let governors = dirs
+        .iter()
+        .map(|dir| read_trimmed(&dir.join("scaling_governor")))
+        .collect::<Result<_>>()?;
+    Ok(Cpus {
+        frequencies,
+        governors,
+    })
+}
+
+/// Switches every CPU under `cpu_dir` to the governor after the first CPU's.
+fn cycle_governor(cpu_dir: &Path) -> Result<()> {
+    let dirs = find_cpufreq_dirs(cpu_dir)?;
+    let first = match dirs.first() {
+        Some(first) => first,
+        None => return Ok(()),
+    };
+    let available = read_trimmed(&first.join("scaling_available_governors"))?;
+    let current = read_trimmed(&first.join("scaling_governor"))?;
+    let next = match next_governor(&available, &current) {
+        Some(next) => next,
+        None => return Ok(()),
+    };
+    for dir in &dirs {
+        let path = dir.join("scaling_governor");
+        fs::write(&path, next)
+            .with_context(|_| format!("Failed to set governor in {}", path.display()))?;
+    }
+    Ok(())
+}
+
+/// Shows the frequency and governor of the CPUs.
+///
+/// This widget reads the current frequency of each CPU from
+/// `/sys/devices/system/cpu/cpu*/cpufreq/`, and shows them as formatted by a
+/// [`Template`], in which `{min}`, `{avg}` and `{max}` are replaced by the
+/// lowest, average and highest frequency of the CPUs in GHz (e.g. `2.40`, or
+/// `2.4` for `{avg:.1}`), and `{governor}` by the CPUs' governor (e.g.
+/// `powersave`).
+///
+/// Clicking on the widget switches every CPU to the next of the available
+/// governors. Changing the governor needs permission to write to
+/// `scaling_governor`, e.g. given by a udev rule, or the click fails.
+///
+/// [`Template`]: ../template/struct.Template.html
+pub struct CpuFreq {
+    timer: Timer,
+    update_interval: Duration,
+    cpu_dir: PathBuf,
+    attr: Attributes,
+    format: Template,
+    // Told whenever the governor is changed, to update straight away.
+    cycled: UnboundedSender<()>,
+    cycles: RefCell<Option<UnboundedReceiver<()>>>,
+}
+
+impl CpuFreq {
+    /// Creates a new CPU Frequency widget.
+    ///
+    /// Creates a new `CpuFreq` widget, whose text will be displayed with the
+    /// given [`Attributes`]. The widget shows `format`, e.g. `{avg}GHz
+    /// {governor}`.
+    ///
+    /// The [`Cnx`] instance is borrowed during construction in order to get
+    /// access to handles of its event loop. However, it is not borrowed for the
+    /// lifetime of the widget. See the [`cnx_add_widget!()`] for more discussion
+    /// about the lifetime of the borrow.
+    ///
+    /// [`Attributes`]: ../text/struct.Attributes.html
+    /// [`Cnx`]: ../struct.Cnx.html
+    /// [`cnx_add_widget!()`]: ../macro.cnx_add_widget.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use cnx::*;
+    /// # use cnx::text::*;
+    /// # use cnx::widgets::*;
+    /// #
+    /// # fn run() -> ::cnx::Result<()> {
+    /// let attr = Attributes {
+    ///     font: Font::new("SourceCodePro 21"),
+    ///     fg_color: Color::white(),
+    ///     bg_color: None,
+    ///     padding: Padding::new(8.0, 8.0, 0.0, 0.0),
+    ///     border: None,
+    /// };
+    ///
+    /// let mut cnx = Cnx::new(Position::Top)?;
+    /// let format = "{avg}GHz {governor}".parse()?;
+    /// cnx.add_widget(CpuFreq::new(&cnx, attr.clone(), format));
+    /// # Ok(())
+    /// # }
+    /// # fn main() { run().unwrap(); }
+    /// ```
+    pub fn new(cnx: &Cnx, attr: Attributes, format: Template) -> CpuFreq {
+        let (cycled, cycles) = mpsc::unbounded();
+        CpuFreq {
+            timer: cnx.timer(),
+            update_interval: Duration::from_secs(5),
+            cpu_dir: PathBuf::from("/sys/devices/system/cpu"),
+            attr,
+            format,
+            cycled,
+            cycles: RefCell::new(Some(cycles)),
+        }
+    }
+
+    fn governor_events(&self) -> Result<Refresh> {
+        match self.cycles.borrow_mut().take() {
+            Some(cycles) => Ok(Box::new(
+                cycles.map_err(|()| format_err!("CpuFreq channel closed")),
+            )),
+            None => Ok(Box::new(stream::empty())),
+        }
+    }
+
+    fn tick(&self) -> Result<Vec<Text>> {
+        let cpus = read_cpus(&self.cpu_dir).context("Failed to read CPU frequencies")?;
+        Ok(vec![Text::new(
+            self.attr.clone(),
+            self.format.render(&summarize(&cpus)),
+        )])
+    }
+
+    /// Returns a `ClickHandler` which switches to the next governor.
+    fn governor_click_handler(&mut self) -> Option<ClickHandler> {
+        let cpu_dir = self.cpu_dir.clone();
+        let cycled = self.cycled.clone();
+        Some(Box::new(move |click: Click| {
+            if click.button != 1 {
+                return Ok(());
+            }
+            cycle_governor(&cpu_dir)?;
+            // The widget may have stopped, in which case there's nothing to
+            // update.
+            let _ = cycled.unbounded_send(());
+            Ok(())
+        }))
+    }
+}
+
+timer_widget!(CpuFreq, timer, update_interval, tick; governor_events; governor_click_handler);
+
+#[cfg(test)]
+mod test {
+    use super::{next_governor, summarize, Cpus};
+    use crate::template::Template;
+
+    #[test]
+    fn works() {
+        let cpus = Cpus {
+            frequencies: vec![800_000, 2_400_000, 3_100_000, 1_700_000],
+            governors: vec!["powersave".to_owned(); 4],
+        };
+        let format: Template = "{min} {avg} {max:.1} {governor}".parse().unwrap();
+        assert_eq!(format.render(&summarize(&cpus)), "0.80 2.00 3.1 powersave");
+
+        let available = "performance powersave\n";
+        assert_eq!(next_governor(available, "performance"), Some("powersave"));
+        assert_eq!(next_governor(available, "powersave"), Some("performance"));
+        assert_eq!(next_governor(available, "schedutil"), Some("performance"));
+        assert_eq!(next_governor("", "powersave"), None);
+    }
+}
@@ -0,0 +1,85 @@
+use futures::unsync::mpsc::UnboundedReceiver;
+use futures::{stream, Stream};
+
+use super::{Widget, WidgetStream};
+use crate::error::format_err;
+use crate::text::{Attributes, Text};
+use crate::{Cnx, Result};
+
+/// Shows whatever text it is sent, e.g. by a script over IPC.
+///
+/// The text is set with [`Control::set_text()`], or by writing a command like
+/// `{"command": "set_text", "widget": "vpn", "text": "🔒 VPN"}` to the IPC
+/// socket (see [`Cnx::enable_ipc()`]). Until then, and whenever the text is
+/// empty, the widget is hidden entirely.
+///
+/// [`Control::set_text()`]: ../struct.Control.html#method.set_text
+/// [`Cnx::enable_ipc()`]: ../struct.Cnx.html#method.enable_ipc
+pub struct Custom {
+    attr: Attributes,
+    texts: UnboundedReceiver<String>,
+}
+
+impl Custom {
+    /// Creates a new Custom widget.
+    ///
+    /// Creates a new `Custom` widget called `name`, whose text will be
+    /// displayed with the given [`Attributes`]. Text sent to `name` is shown
+    /// by this widget, rather than by any other `Custom` widget called `name`
+    /// which was created before it.
+    ///
+    /// The [`Cnx`] instance is borrowed during construction in order to get
+    /// access to handles of its event loop. However, it is not borrowed for the
+    /// lifetime of the widget. See the [`cnx_add_widget!()`] for more discussion
+    /// about the lifetime of the borrow.
+    ///
+    /// [`Attributes`]: ../text/struct.Attributes.html
+    /// [`Cnx`]: ../struct.Cnx.html
+    /// [`cnx_add_widget!()`]: ../macro.cnx_add_widget.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use cnx::*;
+    /// # use cnx::text::*;
+    /// # use cnx::widgets::*;
+    /// #
+    /// # fn run() -> ::cnx::Result<()> {
+    /// let attr = Attributes {
+    ///     font: Font::new("SourceCodePro 21"),
+    ///     fg_color: Color::white(),
+    ///     bg_color: None,
+    ///     padding: Padding::new(8.0, 8.0, 0.0, 0.0),
+    ///     border: None,
+    /// };
+    ///
+    /// let mut cnx = Cnx::new(Position::Top)?;
+    /// cnx.enable_ipc();
+    /// cnx.add_widget(Custom::new(&cnx, attr.clone(), "vpn"));
+    /// # Ok(())
+    /// # }
+    /// # fn main() { run().unwrap(); }
+    /// ```
+    pub fn new<S: Into<String>>(cnx: &Cnx, attr: Attributes, name: S) -> Custom {
+        Custom {
+            attr,
+            texts: cnx.control().texts(name.into()),
+        }
+    }
+}
+
+impl Widget for Custom {
+    fn stream(self: Box<Self>) -> Result<WidgetStream> {
+        let attr = self.attr;
+        let texts = self
+            .texts
+            .map(move |text| {
+                if text.is_empty() {
+                    return Vec::new();
+                }
+                vec![Text::new(attr.clone(), text)]
+            })
+            .map_err(|()| format_err!("Custom widget's channel closed"));
+        Ok(Box::new(stream::once(Ok(Vec::new())).chain(texts)))
+    }
+}
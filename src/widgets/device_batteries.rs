@@ -0,0 +1,169 @@
+use std::time::Duration;
+
+use futures::{stream, Future, Stream};
+use log::warn;
+use tokio_core::reactor::Handle;
+
+use crate::busctl::{Bus, Busctl};
+use crate::error::Error;
+use crate::text::{Attributes, Color, Text};
+use crate::timer::Timer;
+use crate::upower::{self, Kind};
+use crate::widgets::Refresh;
+use crate::{Cnx, Result};
+
+/// The icon shown for each kind of device.
+fn icon(kind: Kind) -> &'static str {
+    match kind {
+        Kind::Mouse | Kind::Touchpad => "🖱",
+        Kind::Keyboard => "⌨",
+        Kind::GamingInput => "🎮",
+        Kind::Headset | Kind::Headphones => "🎧",
+        Kind::Speakers => "🔊",
+        Kind::Phone => "📱",
+        Kind::Tablet | Kind::Pen => "✎",
+        Kind::LinePower | Kind::Battery | Kind::Other => "🔋",
+    }
+}
+
+/// Shows the battery of each wireless device, e.g. mice and keyboards.
+///
+/// This widget asks [UPower] for the devices with batteries which don't power
+/// the system itself, such as wireless mice, keyboards, gamepads and
+/// headphones, and shows each one's charge with an icon for the kind of
+/// device, e.g. `🖱 85%`. Devices are shown in the order that UPower lists
+/// them, and the widget is hidden while there aren't any. Each device's model
+/// can be shown too, see [`with_names()`].
+///
+/// When a device has less than 10% charge remaining, its text will change to
+/// the specified `warning_color`.
+///
+/// The widget updates as soon as UPower reports a change, e.g. a device
+/// connecting, and every minute in case a change is missed. It expects the
+/// `busctl` executable (part of `systemd`) and the `gdbus` executable (part
+/// of GLib) to be available in the `PATH`.
+///
+/// To show the laptop's own battery, see [`UPowerBattery`].
+///
+/// [UPower]: https://upower.freedesktop.org/
+/// [`with_names()`]: #method.with_names
+/// [`UPowerBattery`]: struct.UPowerBattery.html
+pub struct DeviceBatteries {
+    timer: Timer,
+    update_interval: Duration,
+    handle: Handle,
+    busctl: Busctl,
+    attr: Attributes,
+    warning_color: Color,
+    names: bool,
+}
+
+impl DeviceBatteries {
+    ///  Creates a new DeviceBatteries widget.
+    ///
+    ///  Creates a new `DeviceBatteries` widget, whose text will be displayed
+    ///  with the given [`Attributes`]. The caller can provide use the
+    ///  `warning_color` argument, to control the [`Color`] of the text once a
+    ///  device has less than 10% charge remaining.
+    ///
+    ///  The [`Cnx`] instance is borrowed during construction in order to get
+    ///  access to handles of its event loop. However, it is not borrowed for
+    ///  the lifetime of the widget. See the [`cnx_add_widget!()`] for more
+    ///  discussion about the lifetime of the borrow.
+    ///
+    /// [`Attributes`]: ../text/struct.Attributes.html
+    /// [`Color`]: ../text/struct.Color.html
+    /// [`Cnx`]: ../struct.Cnx.html
+    /// [`cnx_add_widget!()`]: ../macro.cnx_add_widget.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #[macro_use]
+    /// # extern crate cnx;
+    /// #
+    /// # use cnx::*;
+    /// # use cnx::text::*;
+    /// # use cnx::widgets::*;
+    /// #
+    /// # fn run() -> ::cnx::Result<()> {
+    /// let attr = Attributes {
+    ///     font: Font::new("SourceCodePro 21"),
+    ///     fg_color: Color::white(),
+    ///     bg_color: None,
+    ///     padding: Padding::new(8.0, 8.0, 0.0, 0.0),
+    ///     border: None,
+    /// };
+    ///
+    /// let mut cnx = Cnx::new(Position::Top)?;
+    /// cnx_add_widget!(cnx, DeviceBatteries::new(&cnx, attr.clone(), Color::red()));
+    /// # Ok(())
+    /// # }
+    /// # fn main() { run().unwrap(); }
+    /// ```
+    pub fn new(cnx: &Cnx, attr: Attributes, warning_color: Color) -> DeviceBatteries {
+        DeviceBatteries {
+            timer: cnx.timer(),
+            update_interval: Duration::from_secs(60),
+            handle: cnx.handle(),
+            busctl: Busctl::new(cnx, Bus::System),
+            attr,
+            warning_color,
+            names: false,
+        }
+    }
+
+    /// Shows each device's model after its icon, e.g. `🖱 MX Master 3 85%`.
+    pub fn with_names(mut self) -> DeviceBatteries {
+        self.names = true;
+        self
+    }
+
+    /// Returns a stream which yields whenever UPower reports a change.
+    fn upower_events(&self) -> Result<Refresh> {
+        match upower::monitor(&self.handle) {
+            Ok(lines) => Ok(Box::new(lines.map(|_| ()))),
+            Err(e) => {
+                warn!(
+                    "DeviceBatteries won't notice changes until its next update: {}",
+                    e
+                );
+                Ok(Box::new(stream::empty()))
+            }
+        }
+    }
+
+    fn tick(&self) -> Box<dyn Future<Item = Vec<Text>, Error = Error>> {
+        let attr = self.attr.clone();
+        let warning_color = self.warning_color.clone();
+        let names = self.names;
+        Box::new(upower::devices(&self.busctl).map(move |devices| {
+            devices
+                .into_iter()
+                .filter(|device| !device.power_supply && device.present)
+                .filter(|device| device.kind != Kind::LinePower)
+                .map(|device| {
+                    let text = if names && !device.model.is_empty() {
+                        format!(
+                            "{} {} {:.0}%",
+                            icon(device.kind),
+                            device.model,
+                            device.percentage
+                        )
+                    } else {
+                        format!("{} {:.0}%", icon(device.kind), device.percentage)
+                    };
+
+                    let mut attr = attr.clone();
+                    if device.percentage <= 10.0 {
+                        attr.fg_color = warning_color.clone()
+                    }
+
+                    Text::new(attr, text)
+                })
+                .collect()
+        }))
+    }
+}
+
+timer_widget!(DeviceBatteries, timer, update_interval, tick; upower_events);
@@ -0,0 +1,162 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use log::warn;
+
+use crate::error::{format_err, ResultExt};
+use crate::parse::sysfs;
+use crate::text::{Attributes, Text, Threshold};
+use crate::timer::Timer;
+use crate::{Cnx, Result};
+
+/// A single drive, as exposed by the kernel's `drivetemp` hwmon driver.
+#[derive(Debug, PartialEq)]
+struct Drive {
+    model: String,
+    temp: f64,
+}
+
+fn read_trimmed(path: &Path) -> Result<String> {
+    let contents =
+        fs::read_to_string(path).with_context(|_| format!("Failed to read {}", path.display()))?;
+    Ok(contents.trim().to_owned())
+}
+
+/// Finds all `drivetemp` hwmon devices under `hwmon_dir`, in a stable order.
+fn find_drives(hwmon_dir: &Path) -> Result<Vec<Drive>> {
+    let mut paths = fs::read_dir(hwmon_dir)
+        .with_context(|_| format!("Failed to list {}", hwmon_dir.display()))?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| {
+            read_trimmed(&path.join("name"))
+                .map(|name| name == "drivetemp")
+                .unwrap_or(false)
+        })
+        .collect::<Vec<PathBuf>>();
+    paths.sort();
+
+    // One unreadable drive (e.g. one which is being removed) shouldn't hide
+    // the others.
+    Ok(paths
+        .into_iter()
+        .filter_map(|path| match read_drive(&path) {
+            Ok(drive) => Some(drive),
+            Err(e) => {
+                warn!("Skipping drive {}: {}", path.display(), e);
+                None
+            }
+        })
+        .collect())
+}
+
+/// Reads the drive of the `drivetemp` hwmon device at `path`.
+fn read_drive(path: &Path) -> Result<Drive> {
+    // `device` links to the SCSI device, which knows the disk's model.
+    // Fall back to the hwmon name (e.g. `hwmon3`) if it doesn't.
+    let model = read_trimmed(&path.join("device").join("model"))
+        .ok()
+        .filter(|model| !model.is_empty())
+        .or_else(|| {
+            path.file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+        })
+        .ok_or_else(|| format_err!("Invalid hwmon path: {}", path.display()))?;
+    let temp = sysfs::millidegrees(&read_trimmed(&path.join("temp1_input"))?)?;
+    Ok(Drive { model, temp })
+}
+
+/// Shows the temperature of each hard disk.
+///
+/// This widget shows the temperature of each disk that is exposed by the
+/// kernel's [`drivetemp`] hwmon driver, labelled by the disk's model. Unlike
+/// polling `smartctl`, reading these values does not wake sleeping disks.
+///
+/// The `drivetemp` module must be loaded (e.g. `modprobe drivetemp`) for any
+/// disks to be shown.
+///
+/// Disks which are getting hot can be shown with other attributes, see
+/// [`with_threshold()`].
+///
+/// [`drivetemp`]: https://www.kernel.org/doc/html/latest/hwmon/drivetemp.html
+/// [`with_threshold()`]: #method.with_threshold
+pub struct DiskTemperature {
+    timer: Timer,
+    update_interval: Duration,
+    hwmon_dir: PathBuf,
+    attr: Attributes,
+    threshold: Threshold<f64>,
+}
+
+impl DiskTemperature {
+    /// Creates a new Disk Temperature widget.
+    ///
+    /// Creates a new `DiskTemperature` widget, whose text will be displayed
+    /// with the given [`Attributes`]. Each disk is shown as a separate text,
+    /// in the form `<model> <temp>°C`.
+    ///
+    /// The [`Cnx`] instance is borrowed during construction in order to get
+    /// access to handles of its event loop. However, it is not borrowed for the
+    /// lifetime of the widget. See the [`cnx_add_widget!()`] for more discussion
+    /// about the lifetime of the borrow.
+    ///
+    /// [`Attributes`]: ../text/struct.Attributes.html
+    /// [`Cnx`]: ../struct.Cnx.html
+    /// [`cnx_add_widget!()`]: ../macro.cnx_add_widget.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use cnx::*;
+    /// # use cnx::text::*;
+    /// # use cnx::widgets::*;
+    /// #
+    /// # fn run() -> ::cnx::Result<()> {
+    /// let attr = Attributes {
+    ///     font: Font::new("SourceCodePro 21"),
+    ///     fg_color: "ffffff".parse().unwrap(),
+    ///     bg_color: None,
+    ///     padding: Padding::new(8.0, 8.0, 0.0, 0.0),
+    ///     border: None,
+    /// };
+    ///
+    /// let mut cnx = Cnx::new(Position::Top)?;
+    /// cnx.add_widget(DiskTemperature::new(&cnx, attr.clone()));
+    /// # Ok(())
+    /// # }
+    /// # fn main() { run().unwrap(); }
+    /// ```
+    pub fn new(cnx: &Cnx, attr: Attributes) -> DiskTemperature {
+        DiskTemperature {
+            timer: cnx.timer(),
+            update_interval: Duration::from_secs(60),
+            hwmon_dir: PathBuf::from("/sys/class/hwmon"),
+            attr,
+            threshold: Threshold::new(),
+        }
+    }
+
+    /// Shows each disk's temperature with the [`Attributes`] of the levels of
+    /// `threshold`, in °C, once it reaches them.
+    ///
+    /// [`Attributes`]: ../text/struct.Attributes.html
+    pub fn with_threshold(mut self, threshold: Threshold<f64>) -> DiskTemperature {
+        self.threshold = threshold;
+        self
+    }
+
+    fn tick(&self) -> Result<Vec<Text>> {
+        let drives = find_drives(&self.hwmon_dir).context("Failed to read drivetemp sensors")?;
+        Ok(drives
+            .into_iter()
+            .map(|Drive { model, temp }| {
+                Text::new(
+                    self.threshold.apply(&temp, &self.attr),
+                    format!("{} {:.0}°C", model, temp),
+                )
+            })
+            .collect())
+    }
+}
+
+timer_widget!(DiskTemperature, timer, update_interval, tick);
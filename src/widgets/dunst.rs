@@ -0,0 +1,190 @@
+use std::cell::RefCell;
+use std::process::Command;
+use std::time::Duration;
+
+use futures::unsync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use futures::{stream, Stream};
+
+use super::{Click, ClickHandler, Refresh};
+use crate::error::{format_err, ResultExt};
+use crate::json::{self, Value};
+use crate::text::{Attributes, Text};
+use crate::timer::Timer;
+use crate::{Cnx, Result};
+
+/// Summarises the output of `dunstctl history`.
+///
+/// The history is a D-Bus `aa{sv}` serialised as JSON, with the most recent
+/// notification first. Returns the number of notifications and the summary of
+/// the most recent one.
+fn parse_history(output: &str) -> Result<(usize, Option<String>)> {
+    let history = json::parse(output)?;
+    let notifications = history
+        .get("data")
+        .and_then(|data| data.at(0))
+        .and_then(Value::as_array)
+        .ok_or_else(|| format_err!("Unexpected format of dunst history"))?;
+    let summary = notifications
+        .first()
+        .and_then(|n| n.get("summary"))
+        .and_then(|s| s.get("data"))
+        .and_then(Value::as_str)
+        .map(ToOwned::to_owned);
+    Ok((notifications.len(), summary))
+}
+
+/// Shows the number of notifications in [`dunst`]'s history, and the summary
+/// of the most recent one.
+///
+/// This widget periodically runs `dunstctl history`, so requires `dunstctl` to
+/// be available in the `PATH`. When the history is empty, the widget is hidden
+/// entirely.
+///
+/// Clicking on the widget shows the most recent notification again, with
+/// `dunstctl history-pop`.
+///
+/// [`dunst`]: https://dunst-project.org/
+pub struct Dunst {
+    timer: Timer,
+    update_interval: Duration,
+    attr: Attributes,
+    // Woken by the click handler, to show the shorter history straight away.
+    popped: UnboundedSender<()>,
+    pops: RefCell<Option<UnboundedReceiver<()>>>,
+}
+
+impl Dunst {
+    /// Creates a new Dunst widget.
+    ///
+    /// Creates a new `Dunst` widget, whose text will be displayed with the
+    /// given [`Attributes`]. The text is in the form `<count> <summary>`.
+    ///
+    /// The [`Cnx`] instance is borrowed during construction in order to get
+    /// access to handles of its event loop. However, it is not borrowed for the
+    /// lifetime of the widget. See the [`cnx_add_widget!()`] for more discussion
+    /// about the lifetime of the borrow.
+    ///
+    /// [`Attributes`]: ../text/struct.Attributes.html
+    /// [`Cnx`]: ../struct.Cnx.html
+    /// [`cnx_add_widget!()`]: ../macro.cnx_add_widget.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use cnx::*;
+    /// # use cnx::text::*;
+    /// # use cnx::widgets::*;
+    /// #
+    /// # fn run() -> ::cnx::Result<()> {
+    /// let attr = Attributes {
+    ///     font: Font::new("SourceCodePro 21"),
+    ///     fg_color: "ffffff".parse().unwrap(),
+    ///     bg_color: None,
+    ///     padding: Padding::new(8.0, 8.0, 0.0, 0.0),
+    ///     border: None,
+    /// };
+    ///
+    /// let mut cnx = Cnx::new(Position::Top)?;
+    /// cnx.add_widget(Dunst::new(&cnx, attr.clone()));
+    /// # Ok(())
+    /// # }
+    /// # fn main() { run().unwrap(); }
+    /// ```
+    pub fn new(cnx: &Cnx, attr: Attributes) -> Dunst {
+        let (popped, pops) = mpsc::unbounded();
+        Dunst {
+            timer: cnx.timer(),
+            update_interval: Duration::from_secs(10),
+            attr,
+            popped,
+            pops: RefCell::new(Some(pops)),
+        }
+    }
+
+    fn pop_events(&self) -> Result<Refresh> {
+        match self.pops.borrow_mut().take() {
+            Some(pops) => Ok(Box::new(
+                pops.map_err(|()| format_err!("Dunst channel closed")),
+            )),
+            None => Ok(Box::new(stream::empty())),
+        }
+    }
+
+    fn tick(&self) -> Result<Vec<Text>> {
+        let output = Command::new("dunstctl")
+            .arg("history")
+            .output()
+            .context("Failed to run `dunstctl history`")?;
+        let string = String::from_utf8(output.stdout).context("Invalid UTF-8 in dunst history")?;
+        let (count, summary) =
+            parse_history(&string).context("Failed to parse `dunstctl history` output")?;
+        if count == 0 {
+            return Ok(Vec::new());
+        }
+
+        let text = match summary {
+            Some(summary) => format!("{} {}", count, summary),
+            None => count.to_string(),
+        };
+        Ok(vec![Text::new(self.attr.clone(), text)])
+    }
+
+    /// Returns a `ClickHandler` which redisplays the most recent notification
+    /// from the history.
+    fn history_pop_click_handler(&mut self) -> Option<ClickHandler> {
+        let popped = self.popped.clone();
+        Some(Box::new(move |click: Click| {
+            if click.button != 1 {
+                return Ok(());
+            }
+            let status = Command::new("dunstctl")
+                .arg("history-pop")
+                .status()
+                .context("Failed to run `dunstctl history-pop`")?;
+            if !status.success() {
+                return Err(format_err!("`dunstctl history-pop` failed: {}", status));
+            }
+            // The widget may have stopped, in which case there's nothing to
+            // update.
+            let _ = popped.unbounded_send(());
+            Ok(())
+        }))
+    }
+}
+
+timer_widget!(Dunst, timer, update_interval, tick; pop_events; history_pop_click_handler);
+
+#[cfg(test)]
+mod test {
+    use super::parse_history;
+
+    #[test]
+    fn works() {
+        let output = r#"{
+        "type": "aa{sv}",
+        "data": [
+                [
+                        {
+                                "body" : { "type" : "s", "data" : "" },
+                                "summary" : { "type" : "s", "data" : "Build finished" },
+                                "id" : { "type" : "i", "data" : 12 }
+                        },
+                        {
+                                "summary" : { "type" : "s", "data" : "Older" },
+                                "id" : { "type" : "i", "data" : 11 }
+                        }
+                ]
+        ]
+}
+"#;
+        assert_eq!(
+            parse_history(output).unwrap(),
+            (2, Some("Build finished".to_owned()))
+        );
+        assert_eq!(
+            parse_history(r#"{"type": "aa{sv}", "data": [[]]}"#).unwrap(),
+            (0, None)
+        );
+        assert!(parse_history("{}").is_err());
+    }
+}
@@ -0,0 +1,160 @@
+use std::process::Command;
+use std::rc::Rc;
+use std::time::Duration;
+
+use futures::{stream, Future, Stream};
+use log::warn;
+use tokio_core::reactor::Handle;
+
+use super::{Widget, WidgetStream};
+use crate::error::Error;
+use crate::process;
+use crate::text::{Attributes, Text};
+use crate::timer::Timer;
+use crate::{Cnx, Result};
+
+// How long a command run every interval may take before it is killed.
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+fn sh(command: &str) -> Command {
+    let mut sh = Command::new("sh");
+    sh.arg("-c").arg(command);
+    sh
+}
+
+/// Shows the output of a shell command.
+///
+/// This widget lets you show anything which a script can print, without
+/// writing a widget in Rust. It runs in one of two modes:
+///
+///  - [`Exec::new()`] runs the command every interval, and shows the first
+///    line that it prints, like `i3blocks` or `polybar`'s `custom/script`.
+///  - [`Exec::streaming()`] runs the command once, and shows each line as it
+///    is printed. This suits commands which report changes as they happen,
+///    e.g. `xtitle -s` or a `while` loop in the script itself.
+///
+/// Commands are run with `sh -c`, so can use pipes, redirections and so on.
+/// If a command exits with a non-zero status a warning is logged, but its
+/// output is still shown. A command run every interval is killed if it
+/// hasn't finished within a minute. Nothing is shown once a streaming
+/// command exits.
+///
+/// [`Exec::new()`]: #method.new
+/// [`Exec::streaming()`]: #method.streaming
+pub struct Exec {
+    timer: Timer,
+    handle: Handle,
+    attr: Attributes,
+    command: String,
+    // `None` if the command is long-running.
+    update_interval: Option<Duration>,
+}
+
+impl Exec {
+    ///  Creates a new Exec widget, which runs `command` every
+    ///  `update_interval`.
+    ///
+    ///  Creates a new `Exec` widget, which shows the first line that
+    ///  `command` prints with the given [`Attributes`].
+    ///
+    ///  The [`Cnx`] instance is borrowed during construction in order to get
+    ///  access to handles of its event loop. However, it is not borrowed for
+    ///  the lifetime of the widget. See the [`cnx_add_widget!()`] for more
+    ///  discussion about the lifetime of the borrow.
+    ///
+    /// [`Attributes`]: ../text/struct.Attributes.html
+    /// [`Cnx`]: ../struct.Cnx.html
+    /// [`cnx_add_widget!()`]: ../macro.cnx_add_widget.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::time::Duration;
+    /// #
+    /// # use cnx::*;
+    /// # use cnx::text::*;
+    /// # use cnx::widgets::*;
+    /// #
+    /// # fn run() -> ::cnx::Result<()> {
+    /// let attr = Attributes {
+    ///     font: Font::new("SourceCodePro 21"),
+    ///     fg_color: "ffffff".parse().unwrap(),
+    ///     bg_color: None,
+    ///     padding: Padding::new(8.0, 8.0, 0.0, 0.0),
+    ///     border: None,
+    /// };
+    ///
+    /// let mut cnx = Cnx::new(Position::Top)?;
+    /// let updates = "checkupdates | wc -l".to_owned();
+    /// cnx.add_widget(Exec::new(&cnx, attr.clone(), updates, Duration::from_secs(3600)));
+    /// cnx.add_widget(Exec::streaming(&cnx, attr.clone(), "xtitle -s".to_owned()));
+    /// # Ok(())
+    /// # }
+    /// # fn main() { run().unwrap(); }
+    /// ```
+    pub fn new(cnx: &Cnx, attr: Attributes, command: String, update_interval: Duration) -> Exec {
+        Exec {
+            timer: cnx.timer(),
+            handle: cnx.handle(),
+            attr,
+            command,
+            update_interval: Some(update_interval),
+        }
+    }
+
+    ///  Creates a new Exec widget, which runs `command` once and shows each
+    ///  line that it prints.
+    ///
+    ///  See [`Exec::new()`] for more details.
+    ///
+    /// [`Exec::new()`]: #method.new
+    pub fn streaming(cnx: &Cnx, attr: Attributes, command: String) -> Exec {
+        Exec {
+            timer: cnx.timer(),
+            handle: cnx.handle(),
+            attr,
+            command,
+            update_interval: None,
+        }
+    }
+
+    fn render(&self, line: &str) -> Vec<Text> {
+        let line = line.trim();
+        if line.is_empty() {
+            return Vec::new();
+        }
+        vec![Text::new(self.attr.clone(), line.to_owned())]
+    }
+
+    fn tick(self: Rc<Self>) -> Box<dyn Future<Item = Vec<Text>, Error = Error>> {
+        let output = process::output(&self.handle, &self.timer, sh(&self.command), TIMEOUT);
+        Box::new(output.map(move |(status, stdout)| {
+            if !status.success() {
+                warn!("`{}` failed: {}", self.command, status);
+            }
+            let stdout = String::from_utf8_lossy(&stdout);
+            self.render(stdout.lines().next().unwrap_or(""))
+        }))
+    }
+}
+
+impl Widget for Exec {
+    fn stream(self: Box<Self>) -> Result<WidgetStream> {
+        let update_interval = match self.update_interval {
+            Some(update_interval) => update_interval,
+            None => {
+                let lines = process::output_lines(&self.handle, sh(&self.command))?;
+                let texts = lines.map(move |line| self.render(&line));
+                return Ok(Box::new(texts.chain(stream::once(Ok(Vec::new())))));
+            }
+        };
+
+        // The Timer will only fire after the first interval, so run the
+        // command straight away too.
+        let exec = Rc::new(*self);
+        let ticks = stream::once::<_, Error>(Ok(()))
+            .chain(exec.timer.interval(update_interval))
+            .and_then(move |()| exec.clone().tick());
+        Ok(Box::new(ticks))
+    }
+}
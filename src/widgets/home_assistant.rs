@@ -0,0 +1,350 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use futures::{stream, Future, Stream};
+use tokio_core::reactor::Handle;
+
+use super::{Widget, WidgetStream};
+use crate::error::{format_err, Error};
+use crate::json::{self, Value};
+use crate::template;
+use crate::text::{Attributes, Text};
+use crate::timer::Timer;
+use crate::websocket;
+use crate::{Cnx, Result};
+
+const DEFAULT_PORT: u16 = 8123;
+
+/// The state of a Home Assistant entity.
+#[derive(Debug, PartialEq)]
+struct EntityState {
+    state: String,
+    unit: Option<String>,
+}
+
+fn unit(attributes: Option<&Value>) -> Option<String> {
+    attributes
+        .and_then(|a| a.get("unit_of_measurement"))
+        .and_then(Value::as_str)
+        .map(ToOwned::to_owned)
+}
+
+/// Parses a message from Home Assistant's WebSocket API, returning it if it
+/// is an event. Failed authentication and other errors are returned as
+/// errors.
+fn parse_message(message: &str) -> Result<Option<Value>> {
+    let message = json::parse(message)?;
+    match message.get("type").and_then(Value::as_str) {
+        Some("event") => Ok(message.get("event").cloned()),
+        Some("auth_invalid") => {
+            let reason = message.get("message").and_then(Value::as_str);
+            Err(format_err!(
+                "Home Assistant authentication failed: {}",
+                reason.unwrap_or("invalid access token")
+            ))
+        }
+        Some("result") if message.get("success") == Some(&Value::Bool(false)) => {
+            let error = message.get("error").and_then(|e| e.get("message"));
+            Err(format_err!(
+                "Home Assistant subscription failed: {}",
+                error.and_then(Value::as_str).unwrap_or("unknown error")
+            ))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Applies an event of a `subscribe_entities` subscription to `entities`.
+///
+/// Events contain the entities' initial states (`a`), changes to them (`c`),
+/// and entities which were removed (`r`).
+fn apply_event(event: &Value, entities: &mut HashMap<String, EntityState>) {
+    if let Some(Value::Object(added)) = event.get("a") {
+        for (entity_id, entity) in added {
+            if let Some(state) = entity.get("s").and_then(Value::as_str) {
+                entities.insert(
+                    entity_id.clone(),
+                    EntityState {
+                        state: state.to_owned(),
+                        unit: unit(entity.get("a")),
+                    },
+                );
+            }
+        }
+    }
+    if let Some(Value::Object(changed)) = event.get("c") {
+        for (entity_id, diff) in changed {
+            let entity = match entities.get_mut(entity_id) {
+                Some(entity) => entity,
+                None => continue,
+            };
+            if let Some(additions) = diff.get("+") {
+                if let Some(state) = additions.get("s").and_then(Value::as_str) {
+                    entity.state = state.to_owned();
+                }
+                if let Some(unit) = unit(additions.get("a")) {
+                    entity.unit = Some(unit);
+                }
+            }
+            let removed_attributes = diff
+                .get("-")
+                .and_then(|removals| removals.get("a"))
+                .and_then(Value::as_array)
+                .unwrap_or(&[]);
+            if removed_attributes
+                .iter()
+                .any(|a| a.as_str() == Some("unit_of_measurement"))
+            {
+                entity.unit = None;
+            }
+        }
+    }
+    if let Some(removed) = event.get("r").and_then(Value::as_array) {
+        for entity_id in removed.iter().filter_map(Value::as_str) {
+            entities.remove(entity_id);
+        }
+    }
+}
+
+/// Shows the states of Home Assistant entities.
+///
+/// This widget connects to [Home Assistant]'s WebSocket API and shows the
+/// states of one or more entities, such as a thermostat's temperature or
+/// whether a door is open, using a format string. Each entity is given a
+/// name when it is added with [`with_entity()`], and `{name}` in the format
+/// string is replaced by its state, including its unit if it has one (e.g.
+/// `21.5 °C`). Entities which are unavailable are shown as `?`.
+///
+/// States are shown as soon as they change. If the connection to Home
+/// Assistant is lost, a warning is logged and the widget reconnects, waiting
+/// longer between each attempt while Home Assistant is unreachable.
+///
+/// The widget needs a [long-lived access token], which can be created from
+/// your Home Assistant profile. Only unencrypted connections are supported,
+/// so Home Assistant should be reachable over `http://` (e.g. on the local
+/// network).
+///
+/// [Home Assistant]: https://www.home-assistant.io/
+/// [`with_entity()`]: #method.with_entity
+/// [long-lived access token]: https://developers.home-assistant.io/docs/auth_api/#long-lived-access-token
+pub struct HomeAssistant {
+    timer: Timer,
+    handle: Handle,
+    attr: Attributes,
+    address: String,
+    token: String,
+    format: String,
+    // (name, entity ID) pairs.
+    entities: Vec<(String, String)>,
+}
+
+impl HomeAssistant {
+    ///  Creates a new HomeAssistant widget.
+    ///
+    ///  Creates a new `HomeAssistant` widget, whose text will be displayed
+    ///  with the given [`Attributes`]. The widget connects to Home Assistant
+    ///  at `address`, which is either a `host` or `host:port` (the default
+    ///  port being 8123), authenticating with the long-lived access `token`.
+    ///  It shows `format`, e.g. `{thermostat} {door}`.
+    ///
+    ///  The [`Cnx`] instance is borrowed during construction in order to get
+    ///  access to handles of its event loop. However, it is not borrowed for
+    ///  the lifetime of the widget. See the [`cnx_add_widget!()`] for more
+    ///  discussion about the lifetime of the borrow.
+    ///
+    /// [`Attributes`]: ../text/struct.Attributes.html
+    /// [`Cnx`]: ../struct.Cnx.html
+    /// [`cnx_add_widget!()`]: ../macro.cnx_add_widget.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use cnx::*;
+    /// # use cnx::text::*;
+    /// # use cnx::widgets::*;
+    /// #
+    /// # fn run() -> ::cnx::Result<()> {
+    /// let attr = Attributes {
+    ///     font: Font::new("SourceCodePro 21"),
+    ///     fg_color: "ffffff".parse().unwrap(),
+    ///     bg_color: None,
+    ///     padding: Padding::new(8.0, 8.0, 0.0, 0.0),
+    ///     border: None,
+    /// };
+    ///
+    /// let mut cnx = Cnx::new(Position::Top)?;
+    /// let token = std::env::var("HASS_TOKEN").unwrap_or_default();
+    /// let hass = HomeAssistant::new(
+    ///     &cnx,
+    ///     attr.clone(),
+    ///     "homeassistant.local".to_owned(),
+    ///     token,
+    ///     "{inside} door: {door}".to_owned(),
+    /// )
+    /// .with_entity("inside", "sensor.living_room_temperature")
+    /// .with_entity("door", "binary_sensor.front_door");
+    /// cnx.add_widget(hass);
+    /// # Ok(())
+    /// # }
+    /// # fn main() { run().unwrap(); }
+    /// ```
+    pub fn new(
+        cnx: &Cnx,
+        attr: Attributes,
+        address: String,
+        token: String,
+        format: String,
+    ) -> HomeAssistant {
+        let address = if address.contains(':') {
+            address
+        } else {
+            format!("{}:{}", address, DEFAULT_PORT)
+        };
+        HomeAssistant {
+            timer: cnx.timer(),
+            handle: cnx.handle(),
+            attr,
+            address,
+            token,
+            format,
+            entities: Vec::new(),
+        }
+    }
+
+    /// Shows the state of `entity_id`, e.g. `sensor.outside_temperature`, in
+    /// place of `{name}`.
+    pub fn with_entity(mut self, name: &str, entity_id: &str) -> HomeAssistant {
+        self.entities
+            .push((name.to_lowercase(), entity_id.to_owned()));
+        self
+    }
+
+    /// Connects and subscribes to the entities, returning the messages that
+    /// Home Assistant sends.
+    fn connect(&self) -> Box<dyn Stream<Item = Value, Error = Error>> {
+        let auth = format!(
+            r#"{{"type":"auth","access_token":{}}}"#,
+            json::quote(&self.token)
+        );
+        let entity_ids = self
+            .entities
+            .iter()
+            .map(|(_, entity_id)| json::quote(entity_id))
+            .collect::<Vec<_>>();
+        let subscribe = format!(
+            r#"{{"id":1,"type":"subscribe_entities","entity_ids":[{}]}}"#,
+            entity_ids.join(",")
+        );
+
+        let connection = websocket::connect(&self.handle, &self.address, "/api/websocket");
+        Box::new(
+            connection
+                .and_then(move |(socket, messages)| {
+                    // Home Assistant handles messages in order, so the
+                    // subscription can be sent before authentication finishes.
+                    socket.send(&auth)?;
+                    socket.send(&subscribe)?;
+                    // Only events count as receiving something, so that we
+                    // back off if authentication keeps failing. Keep the
+                    // socket open for as long as we're reading.
+                    Ok(messages
+                        .and_then(move |message| {
+                            let _ = &socket;
+                            parse_message(&message)
+                        })
+                        .filter_map(|event| event))
+                })
+                .flatten_stream(),
+        )
+    }
+
+    fn render(&self, states: &HashMap<String, EntityState>) -> Vec<Text> {
+        let values = self
+            .entities
+            .iter()
+            .map(|(name, entity_id)| {
+                let value = match states.get(entity_id) {
+                    Some(EntityState { state, .. })
+                        if state == "unavailable" || state == "unknown" =>
+                    {
+                        "?".to_owned()
+                    }
+                    Some(EntityState {
+                        state,
+                        unit: Some(unit),
+                    }) => format!("{} {}", state, unit),
+                    Some(EntityState { state, unit: None }) => state.clone(),
+                    None => "?".to_owned(),
+                };
+                (name.clone(), value)
+            })
+            .collect();
+        vec![Text::new(
+            self.attr.clone(),
+            template::fill(&self.format, &values),
+        )]
+    }
+}
+
+impl Widget for HomeAssistant {
+    fn stream(self: Box<Self>) -> Result<WidgetStream> {
+        let hass = Rc::new(*self);
+        let messages = {
+            let service = format!("Home Assistant at {}", hass.address);
+            let hass = hass.clone();
+            hass.timer
+                .clone()
+                .reconnecting(service, move || Ok(hass.connect()))
+        };
+
+        let mut states = HashMap::new();
+        let stream = stream::once(Ok(None))
+            .chain(messages.map(Some))
+            .map(move |event| {
+                if let Some(event) = event {
+                    apply_event(&event, &mut states);
+                }
+                hass.render(&states)
+            });
+
+        Ok(Box::new(stream))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::{apply_event, parse_message, EntityState};
+
+    #[test]
+    fn works() {
+        let mut entities = HashMap::new();
+        let mut apply = |message| {
+            if let Some(event) = parse_message(message).unwrap() {
+                apply_event(&event, &mut entities);
+            }
+        };
+        apply(r#"{"type":"auth_required","ha_version":"2023.3.1"}"#);
+        apply(
+            r#"{"id":1,"type":"event","event":{"a":{"sensor.inside":{"s":"21.5","a":{"unit_of_measurement":"°C","friendly_name":"Inside"},"c":"01GT","lc":1677700000.0},"binary_sensor.door":{"s":"off","a":{},"c":"01GU","lc":1677700000.0}}}}"#,
+        );
+        apply(
+            r#"{"id":1,"type":"event","event":{"c":{"sensor.inside":{"+":{"s":"22.0","c":"01GV","lc":1677700100.0}}}}}"#,
+        );
+        apply(r#"{"id":1,"type":"event","event":{"r":["binary_sensor.door"]}}"#);
+        assert_eq!(
+            entities.get("sensor.inside"),
+            Some(&EntityState {
+                state: "22.0".to_owned(),
+                unit: Some("°C".to_owned()),
+            })
+        );
+        assert_eq!(entities.get("binary_sensor.door"), None);
+
+        assert!(parse_message(
+            r#"{"type":"auth_invalid","message":"Invalid access token or password"}"#
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,239 @@
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::Duration;
+
+use futures::{stream, Future, Stream};
+use log::warn;
+use tokio_core::reactor::Handle;
+
+use super::{Widget, WidgetStream};
+use crate::error::ResultExt;
+use crate::geoclue::{self, Location};
+use crate::parse::sysfs;
+use crate::template;
+use crate::text::{Attributes, Text};
+use crate::timer::Timer;
+use crate::weather::{self, IconSet, Observation};
+use crate::{Cnx, Result};
+
+enum Event {
+    // Time to read the indoor sensor again.
+    Indoor,
+    // `None` if the weather couldn't be fetched, in which case the last
+    // observation is kept.
+    Outdoor(Option<Observation>),
+}
+
+fn format_temperature(temperature: Option<f64>) -> String {
+    match temperature {
+        Some(temperature) => format!("{:.0}°C", temperature),
+        None => "?".to_owned(),
+    }
+}
+
+/// Shows the temperature indoors, from a local sensor, alongside the weather
+/// outdoors.
+///
+/// This widget reads the indoor temperature from a hwmon sensor (e.g. a USB
+/// thermometer, or the motherboard's ambient sensor), and the outdoor
+/// temperature and conditions from [Open-Meteo], and shows them together
+/// using a format string. The default format is `{indoor} / {icon}
+/// {outdoor}`, e.g. `21°C / 🌧 13°C`; `{condition}` is also available, as a
+/// short description such as `rain`.
+///
+/// The indoor temperature is read every minute, and the weather fetched every
+/// 15 minutes. If either can't be read, a warning is logged and `?` is shown
+/// in its place (or the last weather that was fetched is kept).
+///
+/// The weather can follow the computer's location with [GeoClue] instead of
+/// using fixed coordinates, see [`with_geoclue()`].
+///
+/// [Open-Meteo]: https://open-meteo.com/
+/// [GeoClue]: https://gitlab.freedesktop.org/geoclue/geoclue/-/wikis/home
+/// [`with_geoclue()`]: #method.with_geoclue
+pub struct IndoorOutdoor {
+    timer: Timer,
+    handle: Handle,
+    attr: Attributes,
+    sensor: PathBuf,
+    location: Location,
+    geoclue: bool,
+    format: String,
+    icon_set: IconSet,
+}
+
+impl IndoorOutdoor {
+    ///  Creates a new IndoorOutdoor widget.
+    ///
+    ///  Creates a new `IndoorOutdoor` widget, whose text will be displayed
+    ///  with the given [`Attributes`]. The indoor temperature is read from
+    ///  the hwmon `sensor`, i.e. a `temp*_input` file such as
+    ///  `/sys/class/hwmon/hwmon2/temp1_input`, and the weather is the weather
+    ///  at `latitude` and `longitude`.
+    ///
+    ///  The [`Cnx`] instance is borrowed during construction in order to get
+    ///  access to handles of its event loop. However, it is not borrowed for
+    ///  the lifetime of the widget. See the [`cnx_add_widget!()`] for more
+    ///  discussion about the lifetime of the borrow.
+    ///
+    /// [`Attributes`]: ../text/struct.Attributes.html
+    /// [`Cnx`]: ../struct.Cnx.html
+    /// [`cnx_add_widget!()`]: ../macro.cnx_add_widget.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use cnx::*;
+    /// # use cnx::text::*;
+    /// # use cnx::widgets::*;
+    /// #
+    /// # fn run() -> ::cnx::Result<()> {
+    /// let attr = Attributes {
+    ///     font: Font::new("SourceCodePro 21"),
+    ///     fg_color: "ffffff".parse().unwrap(),
+    ///     bg_color: None,
+    ///     padding: Padding::new(8.0, 8.0, 0.0, 0.0),
+    ///     border: None,
+    /// };
+    ///
+    /// let mut cnx = Cnx::new(Position::Top)?;
+    /// let sensor = "/sys/class/hwmon/hwmon2/temp1_input".into();
+    /// cnx.add_widget(IndoorOutdoor::new(&cnx, attr.clone(), sensor, 51.5, -0.12));
+    /// # Ok(())
+    /// # }
+    /// # fn main() { run().unwrap(); }
+    /// ```
+    pub fn new(
+        cnx: &Cnx,
+        attr: Attributes,
+        sensor: PathBuf,
+        latitude: f64,
+        longitude: f64,
+    ) -> IndoorOutdoor {
+        IndoorOutdoor {
+            timer: cnx.timer(),
+            handle: cnx.handle(),
+            attr,
+            sensor,
+            location: Location::new(latitude, longitude),
+            geoclue: false,
+            format: "{indoor} / {icon} {outdoor}".to_owned(),
+            icon_set: IconSet::default(),
+        }
+    }
+
+    /// Shows `format` instead, in which `{indoor}`, `{outdoor}`, `{icon}` and
+    /// `{condition}` are replaced.
+    pub fn with_format(mut self, format: String) -> IndoorOutdoor {
+        self.format = format;
+        self
+    }
+
+    /// Uses `icon_set` for the `{icon}`, rather than emoji.
+    pub fn with_icon_set(mut self, icon_set: IconSet) -> IndoorOutdoor {
+        self.icon_set = icon_set;
+        self
+    }
+
+    /// Follows the computer's location with GeoClue, fetching the weather
+    /// again whenever it moves more than a few kilometres. The coordinates
+    /// given to [`new()`] are used until GeoClue finds the location, or if
+    /// it can't. This requires GeoClue's `where-am-i` demo to be installed.
+    ///
+    /// [`new()`]: #method.new
+    pub fn with_geoclue(mut self) -> IndoorOutdoor {
+        self.geoclue = true;
+        self
+    }
+
+    fn read_indoor(&self) -> Option<f64> {
+        let temperature = fs::read_to_string(&self.sensor)
+            .with_context(|_| format!("Failed to read {}", self.sensor.display()))
+            .and_then(|contents| sysfs::millidegrees(&contents));
+        match temperature {
+            Ok(temperature) => Some(temperature),
+            Err(e) => {
+                warn!("Failed to read indoor temperature: {}", e);
+                None
+            }
+        }
+    }
+
+    fn render(&self, indoor: Option<f64>, outdoor: Option<&Observation>) -> Vec<Text> {
+        let mut values = HashMap::new();
+        values.insert("indoor".to_owned(), format_temperature(indoor));
+        values.insert(
+            "outdoor".to_owned(),
+            format_temperature(outdoor.map(|o| o.temperature)),
+        );
+        let (icon, condition) = match outdoor {
+            Some(o) => (
+                self.icon_set.icon(o.condition, o.is_day),
+                IconSet::Text.icon(o.condition, o.is_day),
+            ),
+            None => ("?", "?"),
+        };
+        values.insert("icon".to_owned(), icon.to_owned());
+        values.insert("condition".to_owned(), condition.to_owned());
+
+        vec![Text::new(
+            self.attr.clone(),
+            template::fill(&self.format, &values),
+        )]
+    }
+}
+
+impl Widget for IndoorOutdoor {
+    fn stream(self: Box<Self>) -> Result<WidgetStream> {
+        // Intervals only fire after their first interval, so start with an
+        // initial reading of each.
+        let indoor = stream::once(Ok(()))
+            .chain(self.timer.interval(Duration::from_secs(60)))
+            .map(|()| Event::Indoor);
+        let outdoor = stream::once(Ok(())).chain(self.timer.interval(Duration::from_secs(15 * 60)));
+
+        // Fetch the weather straight away whenever we move, too.
+        let location = Rc::new(Cell::new(self.location));
+        let outdoor: Box<dyn Stream<Item = (), Error = _>> = if self.geoclue {
+            let moved = location.clone();
+            let moves = geoclue::locations(&self.handle, self.location)
+                .map(move |new_location| moved.set(new_location));
+            Box::new(outdoor.select(moves))
+        } else {
+            Box::new(outdoor)
+        };
+
+        let (handle, timer) = (self.handle.clone(), self.timer.clone());
+        let outdoor = outdoor.and_then(move |()| {
+            let Location {
+                latitude,
+                longitude,
+            } = location.get();
+            weather::open_meteo(&handle, &timer, latitude, longitude).then(|result| {
+                Ok(Event::Outdoor(match result {
+                    Ok(observation) => Some(observation),
+                    Err(e) => {
+                        warn!("Failed to fetch the weather: {}", e);
+                        None
+                    }
+                }))
+            })
+        });
+
+        let mut indoor_temperature = None;
+        let mut outdoor_observation = None;
+        let stream = indoor.select(outdoor).map(move |event| {
+            match event {
+                Event::Indoor => indoor_temperature = self.read_indoor(),
+                Event::Outdoor(Some(observation)) => outdoor_observation = Some(observation),
+                Event::Outdoor(None) => {}
+            }
+            self.render(indoor_temperature, outdoor_observation.as_ref())
+        });
+
+        Ok(Box::new(stream))
+    }
+}
@@ -0,0 +1,174 @@
+use std::time::Duration;
+
+use futures::Future;
+
+use crate::busctl::{Bus, Busctl};
+use crate::error::{format_err, Error, ResultExt};
+use crate::json::{self, Value};
+use crate::text::{Attributes, Text};
+use crate::timer::Timer;
+use crate::{Cnx, Result};
+
+#[derive(Debug, PartialEq)]
+struct Inhibitor {
+    what: String,
+    who: String,
+    why: String,
+    mode: String,
+}
+
+/// Parses the JSON output of calling logind's `ListInhibitors` with `busctl`.
+///
+/// The reply is a single `a(ssssuu)`, with each struct serialised as an array
+/// of `what`, `who`, `why`, `mode`, `uid` and `pid`.
+fn parse_inhibitors(output: &str) -> Result<Vec<Inhibitor>> {
+    let reply = json::parse(output)?;
+    let inhibitors = reply
+        .get("data")
+        .and_then(|data| data.at(0))
+        .and_then(Value::as_array)
+        .ok_or_else(|| format_err!("Unexpected format of ListInhibitors reply"))?;
+    inhibitors
+        .iter()
+        .map(|inhibitor| {
+            let field = |i| {
+                inhibitor
+                    .at(i)
+                    .and_then(Value::as_str)
+                    .map(ToOwned::to_owned)
+                    .ok_or_else(|| format_err!("Unexpected format of inhibitor: {:?}", inhibitor))
+            };
+            Ok(Inhibitor {
+                what: field(0)?,
+                who: field(1)?,
+                why: field(2)?,
+                mode: field(3)?,
+            })
+        })
+        .collect()
+}
+
+/// Shows what is currently preventing the system from sleeping or idling.
+///
+/// This widget periodically asks [`logind`] for its list of inhibitor locks,
+/// and shows each lock which blocks suspend or idle (e.g. screen blanking) in
+/// the form `<who>: <why>`, e.g. `Firefox: video playing`. Locks which only
+/// delay suspend are not shown. When nothing is inhibiting idle or sleep, the
+/// widget is hidden entirely.
+///
+/// It expects the `busctl` executable (part of `systemd`) to be available in
+/// the `PATH`.
+///
+/// [`logind`]: https://www.freedesktop.org/wiki/Software/systemd/inhibit/
+pub struct Inhibitors {
+    timer: Timer,
+    update_interval: Duration,
+    attr: Attributes,
+    busctl: Busctl,
+}
+
+impl Inhibitors {
+    /// Creates a new Inhibitors widget.
+    ///
+    /// Creates a new `Inhibitors` widget, whose text will be displayed with
+    /// the given [`Attributes`]. Each inhibitor lock is shown as a separate
+    /// text.
+    ///
+    /// The [`Cnx`] instance is borrowed during construction in order to get
+    /// access to handles of its event loop. However, it is not borrowed for the
+    /// lifetime of the widget. See the [`cnx_add_widget!()`] for more discussion
+    /// about the lifetime of the borrow.
+    ///
+    /// [`Attributes`]: ../text/struct.Attributes.html
+    /// [`Cnx`]: ../struct.Cnx.html
+    /// [`cnx_add_widget!()`]: ../macro.cnx_add_widget.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use cnx::*;
+    /// # use cnx::text::*;
+    /// # use cnx::widgets::*;
+    /// #
+    /// # fn run() -> ::cnx::Result<()> {
+    /// let attr = Attributes {
+    ///     font: Font::new("SourceCodePro 21"),
+    ///     fg_color: "ffffff".parse().unwrap(),
+    ///     bg_color: None,
+    ///     padding: Padding::new(8.0, 8.0, 0.0, 0.0),
+    ///     border: None,
+    /// };
+    ///
+    /// let mut cnx = Cnx::new(Position::Top)?;
+    /// cnx.add_widget(Inhibitors::new(&cnx, attr.clone()));
+    /// # Ok(())
+    /// # }
+    /// # fn main() { run().unwrap(); }
+    /// ```
+    pub fn new(cnx: &Cnx, attr: Attributes) -> Inhibitors {
+        Inhibitors {
+            timer: cnx.timer(),
+            update_interval: Duration::from_secs(30),
+            attr,
+            busctl: Busctl::new(cnx, Bus::System),
+        }
+    }
+
+    fn tick(&self) -> Box<dyn Future<Item = Vec<Text>, Error = Error>> {
+        let output = self.busctl.call(&[
+            "call",
+            "org.freedesktop.login1",
+            "/org/freedesktop/login1",
+            "org.freedesktop.login1.Manager",
+            "ListInhibitors",
+        ]);
+        let attr = self.attr.clone();
+        Box::new(output.and_then(move |output| render(&attr, &output)))
+    }
+}
+
+/// Shows each inhibitor in the reply to `ListInhibitors` which blocks sleep or
+/// idling.
+fn render(attr: &Attributes, output: &str) -> Result<Vec<Text>> {
+    let inhibitors = parse_inhibitors(output).context("Failed to parse inhibitor locks")?;
+    Ok(inhibitors
+        .into_iter()
+        .filter(|inhibitor| inhibitor.mode == "block")
+        .filter(|inhibitor| {
+            inhibitor
+                .what
+                .split(':')
+                .any(|what| what == "sleep" || what == "idle")
+        })
+        .map(|inhibitor| {
+            Text::new(
+                attr.clone(),
+                format!("{}: {}", inhibitor.who, inhibitor.why),
+            )
+        })
+        .collect())
+}
+
+timer_widget!(Inhibitors, timer, update_interval, tick);
+
+#[cfg(test)]
+mod test {
+    use super::{parse_inhibitors, Inhibitor};
+
+    #[test]
+    fn works() {
+        let output = r#"{"type":"a(ssssuu)","data":[[["sleep","NetworkManager","NetworkManager needs to turn off networks","delay",0,812],["idle:sleep","Firefox","video playing","block",1000,4242]]]}"#;
+        let inhibitors = parse_inhibitors(output).unwrap();
+        assert_eq!(inhibitors.len(), 2);
+        assert_eq!(
+            inhibitors[1],
+            Inhibitor {
+                what: "idle:sleep".to_owned(),
+                who: "Firefox".to_owned(),
+                why: "video playing".to_owned(),
+                mode: "block".to_owned(),
+            }
+        );
+        assert!(parse_inhibitors(r#"{"type":"a(ssssuu)","data":[[[1]]]}"#).is_err());
+    }
+}
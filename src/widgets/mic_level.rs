@@ -0,0 +1,235 @@
+use std::cell::RefCell;
+use std::process::Command;
+use std::rc::Rc;
+use std::time::Duration;
+
+use futures::Stream;
+use tokio_core::reactor::Handle;
+
+use super::{Widget, WidgetStream};
+use crate::process;
+use crate::text::{Attributes, Gauge, Graph, Graphic, Orientation, Text};
+use crate::timer::Timer;
+use crate::{Cnx, Result};
+
+/// How often the meter shows the loudest sample since it was last shown.
+const UPDATE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The quietest level the meter shows, in dB below the loudest possible.
+const RANGE_DB: f64 = 60.0;
+
+/// Finds the peak of a stream of audio, as 16-bit little-endian samples,
+/// which may be split between chunks at any byte.
+#[derive(Debug, Default)]
+struct Meter {
+    // The first byte of a sample which was split between chunks.
+    odd_byte: Option<u8>,
+    peak: u16,
+}
+
+impl Meter {
+    fn feed(&mut self, mut bytes: &[u8]) {
+        if let (Some(low), Some((&high, rest))) = (self.odd_byte, bytes.split_first()) {
+            self.sample(i16::from_le_bytes([low, high]));
+            self.odd_byte = None;
+            bytes = rest;
+        }
+        let samples = bytes.chunks_exact(2);
+        if let [low] = *samples.remainder() {
+            self.odd_byte = Some(low);
+        }
+        for sample in samples {
+            self.sample(i16::from_le_bytes([sample[0], sample[1]]));
+        }
+    }
+
+    fn sample(&mut self, sample: i16) {
+        self.peak = self.peak.max(sample.unsigned_abs());
+    }
+
+    /// Returns the level of the peak since the last call, from 0.0 (`RANGE_DB`
+    /// below full scale, or quieter) to 1.0 (full scale).
+    fn take(&mut self) -> f64 {
+        let peak = f64::from(std::mem::take(&mut self.peak)) / f64::from(i16::MAX);
+        if peak <= 0.0 {
+            return 0.0;
+        }
+        (1.0 + 20.0 * peak.log10() / RANGE_DB).clamp(0.0, 1.0)
+    }
+}
+
+/// Shows a live level meter of a microphone.
+///
+/// This widget records from PulseAudio's default source (or PipeWire's, via
+/// `pipewire-pulse`) with `parec`, and shows how loud the input is as a
+/// [`Gauge`] in the text's `fg_color`, updating ten times a second. It's
+/// useful to check that the right microphone is picking you up, e.g. before
+/// joining a call.
+///
+/// The meter shows the peak level on a logarithmic scale, as audio tools
+/// usually do, so that speech doesn't just barely move it. With
+/// [`with_history()`], it shows a [`Graph`] of the last few seconds' levels
+/// instead.
+///
+/// It expects the `parec` executable (part of PulseAudio's utilities) to be
+/// available in the `PATH`.
+///
+/// [`Gauge`]: ../text/struct.Gauge.html
+/// [`Graph`]: ../text/struct.Graph.html
+/// [`with_history()`]: #method.with_history
+pub struct MicLevel {
+    handle: Handle,
+    timer: Timer,
+    attr: Attributes,
+    source: String,
+    width: f64,
+    // The recent levels, if they're shown rather than just the current one.
+    history: Option<Graph>,
+}
+
+impl MicLevel {
+    /// Creates a new Mic Level widget.
+    ///
+    /// Creates a new `MicLevel` widget, whose meter will be displayed with the
+    /// given [`Attributes`].
+    ///
+    /// The [`Cnx`] instance is borrowed during construction in order to get
+    /// access to handles of its event loop. However, it is not borrowed for the
+    /// lifetime of the widget. See the [`cnx_add_widget!()`] for more discussion
+    /// about the lifetime of the borrow.
+    ///
+    /// [`Attributes`]: ../text/struct.Attributes.html
+    /// [`Cnx`]: ../struct.Cnx.html
+    /// [`cnx_add_widget!()`]: ../macro.cnx_add_widget.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use cnx::*;
+    /// # use cnx::text::*;
+    /// # use cnx::widgets::*;
+    /// #
+    /// # fn run() -> ::cnx::Result<()> {
+    /// let attr = Attributes {
+    ///     font: Font::new("SourceCodePro 21"),
+    ///     fg_color: Color::white(),
+    ///     bg_color: None,
+    ///     padding: Padding::new(8.0, 8.0, 0.0, 0.0),
+    ///     border: None,
+    /// };
+    ///
+    /// let mut cnx = Cnx::new(Position::Top)?;
+    /// cnx.add_widget(MicLevel::new(&cnx, attr.clone()).with_width(60.0));
+    /// # Ok(())
+    /// # }
+    /// # fn main() { run().unwrap(); }
+    /// ```
+    pub fn new(cnx: &Cnx, attr: Attributes) -> MicLevel {
+        MicLevel {
+            handle: cnx.handle(),
+            timer: cnx.timer(),
+            attr,
+            source: "@DEFAULT_SOURCE@".to_owned(),
+            width: 40.0,
+            history: None,
+        }
+    }
+
+    /// Shows the level of the named source, as listed by `pactl list short
+    /// sources`, rather than the default source.
+    pub fn with_source<S: Into<String>>(mut self, source: S) -> MicLevel {
+        self.source = source.into();
+        self
+    }
+
+    /// Draws the meter `width` pixels wide (not counting the padding),
+    /// rather than 40.
+    pub fn with_width(mut self, width: f64) -> MicLevel {
+        self.width = width;
+        if let Some(ref mut history) = self.history {
+            history.width = width;
+        }
+        self
+    }
+
+    /// Draws the levels of the last `seconds` as a [`Graph`], scrolling
+    /// from right to left, rather than a meter of the current level.
+    ///
+    /// [`Graph`]: ../text/struct.Graph.html
+    pub fn with_history(mut self, seconds: u32) -> MicLevel {
+        let samples = seconds as usize * (1000 / UPDATE_INTERVAL.as_millis() as usize);
+        let mut history = Graph::new(samples, self.width, self.attr.fg_color.clone());
+        history.max = Some(1.0);
+        self.history = Some(history);
+        self
+    }
+
+    fn render(&mut self, level: f64) -> Vec<Text> {
+        let graphic = match self.history {
+            Some(ref mut history) => {
+                history.push(level);
+                Graphic::Graph(history.clone())
+            }
+            None => Graphic::Gauge(Gauge {
+                value: level,
+                width: self.width,
+                fill_color: self.attr.fg_color.clone(),
+                empty_color: None,
+                orientation: Orientation::Horizontal,
+            }),
+        };
+        vec![Text::new(self.attr.clone(), String::new()).with_graphic(graphic)]
+    }
+}
+
+impl Widget for MicLevel {
+    fn stream(self: Box<Self>) -> Result<WidgetStream> {
+        let mut parec = Command::new("parec");
+        parec.args([
+            &format!("--device={}", self.source),
+            "--raw",
+            "--format=s16le",
+            "--channels=1",
+            // Plenty to find the peaks of speech, without much to read.
+            "--rate=8000",
+            "--latency-msec=50",
+        ]);
+        let chunks = process::output_chunks(&self.handle, parec)?;
+
+        let meter = Rc::new(RefCell::new(Meter::default()));
+        let fed = meter.clone();
+        let samples = chunks.filter_map(move |chunk| {
+            fed.borrow_mut().feed(&chunk);
+            None
+        });
+        let mut widget = self;
+        let updates = widget.timer.interval(UPDATE_INTERVAL).map(move |()| {
+            let level = meter.borrow_mut().take();
+            widget.render(level)
+        });
+        Ok(Box::new(samples.select(updates)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Meter;
+
+    #[test]
+    fn works() {
+        let mut meter = Meter::default();
+        assert_eq!(meter.take(), 0.0);
+
+        // -16384 (-6dB), split between chunks, then a quieter 256.
+        meter.feed(&[0x00]);
+        meter.feed(&[0xc0, 0x00]);
+        meter.feed(&[0x01]);
+        assert!((meter.take() - 0.9).abs() < 0.001);
+
+        meter.feed(&[0xff, 0x7f]);
+        assert_eq!(meter.take(), 1.0);
+        // Too quiet to show.
+        meter.feed(&[0x01, 0x00]);
+        assert_eq!(meter.take(), 0.0);
+    }
+}
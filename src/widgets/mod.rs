@@ -1,9 +1,16 @@
 //! Built-in widgets
 
-use failure::Error;
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+use std::time::Duration;
+
+use futures::unsync::mpsc::{self, UnboundedSender};
 use futures::{Async, Poll, Stream};
 
-use crate::text::Text;
+use crate::bar::Region;
+use crate::error::{format_err, Error};
+use crate::text::{Attributes, Text};
 use crate::Result;
 
 /// The stream of `Vec<Text>` returned by each widget.
@@ -15,6 +22,13 @@ use crate::Result;
 /// [`Widget`]: trait.Widget.html
 pub type WidgetStream = Box<dyn Stream<Item = Vec<Text>, Error = Error>>;
 
+/// A stream which yields whenever a widget has been asked to refresh.
+///
+/// See [`Widget::refreshable_stream()`].
+///
+/// [`Widget::refreshable_stream()`]: trait.Widget.html#method.refreshable_stream
+pub type Refresh = Box<dyn Stream<Item = (), Error = Error>>;
+
 /// The main trait implemented by all widgets.
 ///
 /// This simple trait defines a widget. A widget is essentially just a
@@ -26,27 +40,437 @@ pub type WidgetStream = Box<dyn Stream<Item = Vec<Text>, Error = Error>>;
 ///
 /// [widget-stream]: https://docs.rs/futures/0.1.15/futures/stream/trait.Stream.html
 pub trait Widget {
-    ///
+    /// Consumes the widget, returning the stream of the texts it shows,
+    /// which yields whenever they change.
     fn stream(self: Box<Self>) -> Result<WidgetStream>;
+
+    /// Like [`stream()`], but the widget should also update whenever
+    /// `refresh` yields, e.g. because of a call to [`Control::refresh()`].
+    ///
+    /// The default implementation ignores `refresh`, which suits widgets that
+    /// are notified of every change anyway. Widgets which poll should update
+    /// immediately instead of waiting for their next poll.
+    ///
+    /// [`stream()`]: #tymethod.stream
+    /// [`Control::refresh()`]: ../struct.Control.html#method.refresh
+    fn refreshable_stream(self: Box<Self>, _refresh: Refresh) -> Result<WidgetStream> {
+        self.stream()
+    }
+
+    /// Returns a handler for mouse clicks on the widget, if it handles them.
+    ///
+    /// As [`stream()`] consumes the widget, the handler is taken from the
+    /// widget before its stream is created. It is then called with a
+    /// [`Click`] for each mouse button press (including the scroll wheel) on
+    /// any of the widget's texts. Errors returned by the handler are logged,
+    /// but do not stop the bar.
+    ///
+    /// The default implementation returns `None`, so that clicks on the widget
+    /// are ignored.
+    ///
+    /// [`stream()`]: #tymethod.stream
+    /// [`Click`]: struct.Click.html
+    fn click_handler(&mut self) -> Option<ClickHandler> {
+        None
+    }
+
+    /// Returns a handler which is told where the widget's texts are on the
+    /// bar, if it needs to know.
+    ///
+    /// Like [`click_handler()`], this is taken from the widget before its
+    /// stream is created. It is then called with a [`Placement`] whenever the
+    /// widget's texts move, which widgets that embed their own X windows in
+    /// the bar (like the [`Tray`]) use to position those windows.
+    ///
+    /// The default implementation returns `None`.
+    ///
+    /// [`click_handler()`]: #method.click_handler
+    /// [`Placement`]: struct.Placement.html
+    /// [`Tray`]: struct.Tray.html
+    fn layout_handler(&mut self) -> Option<LayoutHandler> {
+        None
+    }
+}
+
+/// A mouse button press on a widget.
+///
+/// Passed to the widget's [`ClickHandler`].
+///
+/// [`ClickHandler`]: type.ClickHandler.html
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Click {
+    /// The button which was pressed, numbered as X numbers them: `1` is the
+    /// left button, `2` the middle and `3` the right. Scrolling the wheel up
+    /// and down presses buttons `4` and `5` respectively.
+    pub button: u8,
+    /// The horizontal position of the pointer, relative to the left edge of
+    /// the text which was clicked.
+    pub x: f64,
+    /// The vertical position of the pointer, relative to the top edge of the
+    /// text which was clicked.
+    pub y: f64,
+    /// The index of the clicked text, within the texts most recently yielded
+    /// by the widget's stream.
+    pub text: usize,
+}
+
+/// The handler returned by [`Widget::click_handler()`].
+///
+/// [`Widget::click_handler()`]: trait.Widget.html#method.click_handler
+pub type ClickHandler = Box<dyn FnMut(Click) -> Result<()>>;
+
+/// The area of a bar covered by one of a widget's texts, relative to the top
+/// left of the bar.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Area {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+/// Where a widget's texts are on one of the bars.
+///
+/// Passed to the widget's [`LayoutHandler`].
+///
+/// [`LayoutHandler`]: type.LayoutHandler.html
+#[derive(Clone, Debug, PartialEq)]
+pub struct Placement {
+    /// The bar's X window.
+    pub window: xcb::Window,
+    /// The area covered by each of the widget's texts, in order. This is
+    /// empty if the widget has been hidden to make room for others.
+    pub texts: Vec<Area>,
+}
+
+/// The handler returned by [`Widget::layout_handler()`].
+///
+/// [`Widget::layout_handler()`]: trait.Widget.html#method.layout_handler
+pub type LayoutHandler = Box<dyn FnMut(Placement) -> Result<()>>;
+
+/// Options controlling how a widget is laid out on the bar.
+///
+/// These are returned by [`Cnx::add_widget()`], so that they can be set when
+/// the widget is added:
+///
+/// ```no_run
+/// # use cnx::*;
+/// # use cnx::text::*;
+/// # use cnx::widgets::*;
+/// #
+/// # fn run() -> ::cnx::Result<()> {
+/// # let attr = Attributes {
+/// #     font: Font::new("SourceCodePro 21"),
+/// #     fg_color: "ffffff".parse().unwrap(),
+/// #     bg_color: None,
+/// #     padding: Padding::new(8.0, 8.0, 0.0, 0.0),
+/// #     border: None,
+/// # };
+/// let mut cnx = Cnx::new(Position::Top)?;
+/// cnx.add_widget(ActiveWindowTitle::new(&cnx, attr.clone()));
+/// cnx.add_widget(Clock::new(&cnx, "%H:%M".to_owned(), attr.clone()))
+///     .priority(255);
+/// # Ok(())
+/// # }
+/// # fn main() { run().unwrap(); }
+/// ```
+///
+/// [`Cnx::add_widget()`]: ../struct.Cnx.html#method.add_widget
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct WidgetOptions {
+    pub(crate) priority: u8,
+    pub(crate) monitor: Option<String>,
+    pub(crate) name: Option<String>,
+    pub(crate) region: Region,
+    pub(crate) scratchpad: bool,
+    pub(crate) restart_policy: RestartPolicy,
+    pub(crate) placeholder: Option<Text>,
+    pub(crate) min_width: f64,
+    // The widest text the widget is expected to show, to reserve room for.
+    pub(crate) widest: Option<Text>,
+    pub(crate) dependencies: Vec<Dependency>,
+    // Creates the widget again when it's restarted, if it was added with
+    // `Cnx::add_restartable_widget()`.
+    pub(crate) factory: Option<WidgetFactory>,
+}
+
+/// Creates a new instance of a widget, each time it's restarted after failing.
+#[derive(Clone)]
+pub(crate) struct WidgetFactory(Rc<RefCell<CreateWidget>>);
+
+type CreateWidget = dyn FnMut() -> Result<Box<dyn Widget>>;
+
+impl WidgetFactory {
+    pub fn new<F>(factory: F) -> WidgetFactory
+    where
+        F: FnMut() -> Result<Box<dyn Widget>> + 'static,
+    {
+        WidgetFactory(Rc::new(RefCell::new(factory)))
+    }
+
+    /// Creates a new instance of the widget.
+    pub fn create(&self) -> Result<Box<dyn Widget>> {
+        (self.0.borrow_mut())()
+    }
+
+    /// Returns a factory which passes each of this one's widgets through
+    /// `wrap`, e.g. to wrap them in another widget.
+    pub fn map<F>(self, mut wrap: F) -> WidgetFactory
+    where
+        F: FnMut(Box<dyn Widget>) -> Box<dyn Widget> + 'static,
+    {
+        WidgetFactory::new(move || Ok(wrap(self.create()?)))
+    }
+}
+
+impl fmt::Debug for WidgetFactory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("WidgetFactory")
+    }
+}
+
+impl PartialEq for WidgetFactory {
+    fn eq(&self, other: &WidgetFactory) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+/// Something shared which a widget relies on, which may not be ready as soon
+/// as Cnx starts, e.g. at login.
+///
+/// Set with [`WidgetOptions::depends_on()`].
+///
+/// [`WidgetOptions::depends_on()`]: struct.WidgetOptions.html#method.depends_on
+#[derive(Clone, Debug, PartialEq)]
+pub enum Dependency {
+    /// The D-Bus session bus, e.g. for the [`Mpris`] widget. Ready once its
+    /// socket accepts connections.
+    ///
+    /// [`Mpris`]: struct.Mpris.html
+    SessionBus,
+    /// The D-Bus system bus, e.g. for the [`UPowerBattery`] widget. Ready once
+    /// its socket accepts connections.
+    ///
+    /// [`UPowerBattery`]: struct.UPowerBattery.html
+    SystemBus,
+    /// The named network interface, e.g. `wlan0` for the [`Wireless`] widget.
+    /// Ready once the kernel has it, which may take a while for some drivers.
+    ///
+    /// [`Wireless`]: struct.Wireless.html
+    Interface(String),
+    /// The named X selection, e.g. `_NET_SYSTEM_TRAY_S0` for a system tray,
+    /// or `_NET_WM_CM_S0` for a compositor. Ready once a program owns it.
+    Selection(String),
+}
+
+impl fmt::Display for Dependency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Dependency::SessionBus => write!(f, "the D-Bus session bus"),
+            Dependency::SystemBus => write!(f, "the D-Bus system bus"),
+            Dependency::Interface(ref name) => write!(f, "the {} interface", name),
+            Dependency::Selection(ref name) => write!(f, "an owner of the {} selection", name),
+        }
+    }
+}
+
+/// What to do when a widget's stream returns an error.
+///
+/// Set with [`WidgetOptions::restart_policy()`].
+///
+/// [`WidgetOptions::restart_policy()`]: struct.WidgetOptions.html#method.restart_policy
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RestartPolicy {
+    /// Show a placeholder (`⚠` and the widget's [name]) in place of the
+    /// widget, and restart it after `initial`. The wait doubles after each
+    /// error in a row, up to `max`, and the widget reappears as soon as its
+    /// stream yields again. This is the default, waiting from one second up
+    /// to five minutes.
+    ///
+    /// Widgets added with [`Cnx::add_restartable_widget()`] are restarted by
+    /// creating them again. As other widgets are consumed by their
+    /// [`stream()`], their stream is polled again instead, which only helps
+    /// if the stream carries on after an error.
+    ///
+    /// [name]: struct.WidgetOptions.html#method.name
+    /// [`Cnx::add_restartable_widget()`]: ../struct.Cnx.html#method.add_restartable_widget
+    /// [`stream()`]: trait.Widget.html#tymethod.stream
+    Backoff { initial: Duration, max: Duration },
+    /// Show the placeholder in place of the widget, and never poll it again.
+    Never,
+    /// Stop Cnx, returning the error from [`Cnx::run()`].
+    ///
+    /// [`Cnx::run()`]: ../struct.Cnx.html#method.run
+    Fail,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> RestartPolicy {
+        RestartPolicy::Backoff {
+            initial: Duration::from_secs(1),
+            max: Duration::from_secs(300),
+        }
+    }
+}
+
+impl WidgetOptions {
+    /// Sets the priority of the widget, which defaults to 0.
+    ///
+    /// When there isn't enough space on the bar for every widget, the widgets
+    /// with the lowest priority are hidden entirely, until everything fits or
+    /// only the highest priority widgets remain. Then, if there still isn't
+    /// enough space, the remaining widgets are truncated, again starting with
+    /// the lowest priority.
+    pub fn priority(&mut self, priority: u8) -> &mut WidgetOptions {
+        self.priority = priority;
+        self
+    }
+
+    /// Names the widget, so that it can be refreshed, hidden or shown once Cnx
+    /// is running, using a [`Control`] or over IPC.
+    ///
+    /// [`Control`]: ../struct.Control.html
+    pub fn name<S: Into<String>>(&mut self, name: S) -> &mut WidgetOptions {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Restricts the widget to the bar on the monitor connected to the named
+    /// RandR output, e.g. `HDMI-1`.
+    ///
+    /// This is only useful when Cnx shows a bar on more than one monitor (see
+    /// [`Cnx::set_monitors()`]). By default, widgets are shown on every bar.
+    ///
+    /// [`Cnx::set_monitors()`]: ../struct.Cnx.html#method.set_monitors
+    pub fn monitor<S: Into<String>>(&mut self, output: S) -> &mut WidgetOptions {
+        self.monitor = Some(output.into());
+        self
+    }
+
+    /// Sets what happens when the widget fails, which defaults to retrying it
+    /// with backoff. See [`RestartPolicy`].
+    ///
+    /// [`RestartPolicy`]: enum.RestartPolicy.html
+    pub fn restart_policy(&mut self, policy: RestartPolicy) -> &mut WidgetOptions {
+        self.restart_policy = policy;
+        self
+    }
+
+    /// Makes the widget take up at least `width` pixels, even while its texts
+    /// are narrower. Any extra space is left after its texts.
+    pub fn min_width(&mut self, width: f64) -> &mut WidgetOptions {
+        self.min_width = width;
+        self
+    }
+
+    /// Reserves enough width for the widget to show `widest` with the given
+    /// [`Attributes`], e.g. `100%` for a percentage or `88:88` for a time, so
+    /// that the bar's layout doesn't shift as the widget's text changes.
+    ///
+    /// The text is measured once Cnx is running, in the same way as the
+    /// widget's texts, and acts as a [`min_width()`].
+    ///
+    /// [`Attributes`]: ../text/struct.Attributes.html
+    /// [`min_width()`]: #method.min_width
+    pub fn reserve_width<S: Into<String>>(
+        &mut self,
+        attr: &Attributes,
+        widest: S,
+    ) -> &mut WidgetOptions {
+        self.widest = Some(Text::new(attr.clone(), widest.into()));
+        self
+    }
+
+    /// Shows `text` in place of the widget until it first has something to
+    /// show, e.g. `…`, rather than leaving it empty. This keeps the layout of
+    /// the bar stable while widgets start up. See also
+    /// [`Cnx::set_placeholder()`].
+    ///
+    /// [`Cnx::set_placeholder()`]: ../struct.Cnx.html#method.set_placeholder
+    pub fn placeholder(&mut self, text: Text) -> &mut WidgetOptions {
+        self.placeholder = Some(text);
+        self
+    }
+
+    /// Doesn't start the widget until `dependency` is ready, checking every
+    /// second, rather than letting it fail and be restarted. This can be
+    /// called more than once for widgets with several dependencies.
+    ///
+    /// Meanwhile, the widget's [`placeholder()`] is shown, if it has one.
+    ///
+    /// [`placeholder()`]: #method.placeholder
+    pub fn depends_on(&mut self, dependency: Dependency) -> &mut WidgetOptions {
+        self.dependencies.push(dependency);
+        self
+    }
 }
 
+// `$tick` returns the widget's texts, either as a `Result` or, for widgets
+// which mustn't block the event loop, as a future.
 macro_rules! timer_widget {
     ($widget:ty, $timer:ident, $interval:ident, $tick:ident) => {
+        timer_widget!(@impl $widget, $timer, $interval, $tick, _widget => {
+            Ok(Box::new(futures::stream::empty()))
+        } {});
+    };
+    // As well as ticking every interval, also tick whenever the stream
+    // returned by the `$events` method yields.
+    ($widget:ty, $timer:ident, $interval:ident, $tick:ident; $events:ident) => {
+        timer_widget!(@impl $widget, $timer, $interval, $tick, widget => {
+            widget.$events()
+        } {});
+    };
+    // As above, and also handle clicks with the handler returned by the
+    // `$click_handler` method.
+    ($widget:ty, $timer:ident, $interval:ident, $tick:ident; $events:ident; $click_handler:ident) => {
+        timer_widget!(@impl $widget, $timer, $interval, $tick, widget => {
+            widget.$events()
+        } {
+            fn click_handler(&mut self) -> Option<crate::widgets::ClickHandler> {
+                self.$click_handler()
+            }
+        });
+    };
+    (@impl $widget:ty, $timer:ident, $interval:ident, $tick:ident, $this:ident => $events:block { $($extra:tt)* }) => {
         impl crate::widgets::Widget for $widget {
+            $($extra)*
+
             fn stream(self: Box<Self>) -> crate::Result<crate::widgets::WidgetStream> {
-                use failure::Error;
+                self.refreshable_stream(Box::new(futures::stream::empty()))
+            }
+
+            fn refreshable_stream(
+                self: Box<Self>,
+                refresh: crate::widgets::Refresh,
+            ) -> crate::Result<crate::widgets::WidgetStream> {
+                use crate::Error;
                 use futures::{stream, Stream};
 
                 // The Timer will only fire after the first interval. To avoid
-                // waiting for the initial state, call the tick ourselves.
-                let initial = stream::once::<_, Error>(self.$tick());
+                // waiting for the initial state, tick straight away too.
+                let initial = stream::once::<_, Error>(Ok(()));
 
-                let timer_stream = self.$timer.interval(self.$interval);
-                let text_stream = timer_stream
-                    .map_err(|e| e.into())
-                    .and_then(move |_| self.$tick());
+                // Tick early whenever we're asked to refresh.
+                let events: crate::Result<crate::widgets::Refresh> = {
+                    let $this = &self;
+                    $events
+                };
+                // The interval may adapt to how often the texts change.
+                let pace = self.$timer.pace(self.$interval);
+                let timer_stream = self
+                    .$timer
+                    .paced(&pace)
+                    .select(refresh)
+                    .select(events?);
+                let text_stream = initial.chain(timer_stream).and_then(move |()| self.$tick());
 
-                Ok(Box::new(initial.chain(text_stream)))
+                let mut last = None;
+                Ok(Box::new(text_stream.inspect(move |texts| {
+                    if pace.is_adaptive() {
+                        pace.record(last.as_ref() != Some(texts));
+                        last = Some(texts.clone());
+                    }
+                })))
             }
         }
     };
@@ -56,55 +480,25 @@ macro_rules! x_properties_widget {
     ($widget:ty, $handle:ident, $on_change:ident; [ $( $property:ident ),+ ])  => {
         impl crate::widgets::Widget for $widget {
             fn stream(self: Box<Self>) -> crate::Result<crate::widgets::WidgetStream> {
-                use std::rc::Rc;
-
-                use failure::{format_err, Error, ResultExt};
+                use crate::Error;
                 use futures::{stream, Stream};
-                use xcb;
-                use xcb::xproto::{PropertyNotifyEvent, PROPERTY_NOTIFY};
-
-                use crate::bar::XcbEventStream;
-
-                let (xcb_conn, screen_idx) = xcb::Connection::connect(None)
-                    .context("Failed to connect to X server")?;
-                let root_window = xcb_conn
-                    .get_setup()
-                    .roots()
-                    .nth(screen_idx as usize)
-                    .ok_or_else(|| format_err!("Invalid screen"))?
-                    .root();
-                let ewmh_conn = ewmh::Connection::connect(xcb_conn)
-                    .map_err(|(e, _)| e)
-                    .context("Failed to wrap xcb::Connection in ewmh::Connection")?;
-                let conn = Rc::new(ewmh_conn);
-
-                let properties = [ $( conn.$property() ),+ ];
-
-                // Register for all PROPERTY_CHANGE events. We'll filter out the ones
-                // that are interesting below.
-                let attributes = [(xcb::CW_EVENT_MASK, xcb::EVENT_MASK_PROPERTY_CHANGE)];
-                xcb::change_window_attributes(&conn, root_window, &attributes);
-                conn.flush();
+
+                use crate::xcb_ext;
+
+                let (conn, screen_idx) = xcb_ext::connect()?;
+                let root_window = xcb_ext::root_window(&conn, screen_idx)?;
+                let properties = vec![ $( conn.$property() ),+ ];
 
                 // Pretend there was an initial property change to get the initial
-                // contents of the widget, then allow our stream of XCB events to
-                // call the callback for actual changes.
+                // contents of the widget, then allow our stream of property
+                // changes to call the callback for actual changes.
                 let initial = stream::once::<_, Error>(self.$on_change(&conn, screen_idx));
 
-                let xcb_stream = XcbEventStream::new(conn.clone(), &self.$handle)?;
-                let text_stream = xcb_stream.filter_map(move |event| {
-                    if event.response_type() == PROPERTY_NOTIFY {
-                        let event: &PropertyNotifyEvent = unsafe { xcb::cast_event(&event) };
-                        if properties.iter().any(|p| *p == event.atom()) {
-                            // We don't actually care about the event, just that
-                            // it occurred.
-                            return Some(());
-                        }
-                    }
-                    None
-                }).and_then(move |()| {
-                    self.$on_change(&conn, screen_idx)
-                });
+                let changes =
+                    xcb_ext::watch_properties(&self.$handle, conn.clone(), root_window, properties)?;
+                // We don't actually care which property changed, just that one
+                // did.
+                let text_stream = changes.and_then(move |_| self.$on_change(&conn, screen_idx));
 
                 Ok(Box::new(initial.chain(text_stream)))
             }
@@ -114,33 +508,125 @@ macro_rules! x_properties_widget {
 
 // Defined after macros because of macro scoping rules:
 mod active_window_title;
+mod alarm;
+mod backlight;
+mod backup_status;
 mod battery;
 mod clock;
+mod connectivity;
+mod copy_on_click;
+mod cpu_freq;
+mod custom;
+mod device_batteries;
+mod disk_temperature;
+mod dunst;
+mod exec;
+mod home_assistant;
+mod indoor_outdoor;
+mod inhibitors;
+#[cfg(feature = "pulse-widget")]
+mod mic_level;
+mod mpd;
+mod mpris;
+mod mqtt;
 mod pager;
+#[cfg(feature = "pulse-widget")]
+mod pulse_volume;
+mod root_property;
+mod screen_capture;
 mod sensors;
+mod session_lock;
+mod status_notifier;
+mod stopwatch;
+mod systemd_timers;
+mod taskbar;
+mod threshold;
+mod time_tracker;
+mod tray;
+mod update_check;
+mod upower_battery;
+mod usb_toast;
 #[cfg(feature = "volume-widget")]
 mod volume;
+#[cfg(feature = "weather-widget")]
+mod weather;
+mod wireless;
+mod wm_state;
 
 pub use self::active_window_title::ActiveWindowTitle;
+pub use self::alarm::Alarm;
+pub use self::backlight::Backlight;
+pub use self::backup_status::BackupStatus;
 pub use self::battery::Battery;
-pub use self::clock::Clock;
+pub use self::clock::{Clock, Holiday};
+pub use self::connectivity::Connectivity;
+pub use self::copy_on_click::CopyOnClick;
+pub use self::cpu_freq::CpuFreq;
+pub use self::custom::Custom;
+pub use self::device_batteries::DeviceBatteries;
+pub use self::disk_temperature::DiskTemperature;
+pub use self::dunst::Dunst;
+pub use self::exec::Exec;
+pub use self::home_assistant::HomeAssistant;
+pub use self::indoor_outdoor::IndoorOutdoor;
+pub use self::inhibitors::Inhibitors;
+#[cfg(feature = "pulse-widget")]
+pub use self::mic_level::MicLevel;
+pub use self::mpd::Mpd;
+pub use self::mpris::Mpris;
+pub use self::mqtt::Mqtt;
 pub use self::pager::Pager;
+#[cfg(feature = "pulse-widget")]
+pub use self::pulse_volume::PulseVolume;
+pub use self::root_property::RootProperty;
+pub use self::screen_capture::ScreenCapture;
 pub use self::sensors::Sensors;
+pub use self::session_lock::SessionLock;
+pub use self::status_notifier::StatusNotifier;
+pub use self::stopwatch::{Stopwatch, StopwatchAction};
+pub use self::systemd_timers::SystemdTimers;
+pub use self::taskbar::Taskbar;
+pub use self::threshold::Threshold;
+pub use self::time_tracker::{TimeTracker, TimeTrackerDisplay};
+pub use self::tray::Tray;
+pub use self::update_check::UpdateCheck;
+pub use self::upower_battery::UPowerBattery;
+pub use self::usb_toast::UsbToast;
 #[cfg(feature = "volume-widget")]
 pub use self::volume::Volume;
+#[cfg(feature = "weather-widget")]
+pub use self::weather::Weather;
+pub use self::wireless::Wireless;
+pub use self::wm_state::WmState;
 
 pub(crate) struct WidgetList {
     vec: Vec<Box<dyn Stream<Item = Vec<Text>, Error = Error>>>,
 }
 
 impl WidgetList {
-    pub fn new(widgets: Vec<Box<dyn Widget>>) -> Result<WidgetList> {
-        Ok(WidgetList {
-            vec: widgets
-                .into_iter()
-                .map(|w| w.stream())
-                .collect::<Result<Vec<_>>>()?,
-        })
+    /// Creates the list of `widgets`' streams, returning it along with a
+    /// sender for each widget which asks it to refresh.
+    ///
+    /// Each widget is passed to `start`, along with its index and the stream
+    /// of its refreshes, which starts it and decides what happens when it
+    /// fails.
+    pub fn new<F>(
+        widgets: Vec<Box<dyn Widget>>,
+        mut start: F,
+    ) -> (WidgetList, Vec<UnboundedSender<()>>)
+    where
+        F: FnMut(usize, Box<dyn Widget>, Refresh) -> WidgetStream,
+    {
+        let (vec, refreshers) = widgets
+            .into_iter()
+            .enumerate()
+            .map(|(i, w)| {
+                let (sender, receiver) = mpsc::unbounded();
+                let refresh = receiver.map_err(|()| format_err!("Refresh channel closed"));
+                (start(i, w, Box::new(refresh)), sender)
+            })
+            .unzip();
+        (WidgetList { vec }, refreshers)
     }
 }
 
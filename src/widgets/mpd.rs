@@ -0,0 +1,295 @@
+use std::collections::HashMap;
+use std::env;
+use std::io::BufReader;
+use std::net::ToSocketAddrs;
+use std::rc::Rc;
+
+use futures::{future, stream, Future, Stream};
+use tokio_core::net::TcpStream;
+use tokio_core::reactor::{Handle, PollEvented};
+use tokio_io::io::{Lines, WriteHalf};
+use tokio_io::{AsyncRead, AsyncWrite};
+
+use super::{Widget, WidgetStream};
+use crate::error::{format_err, Error, ResultExt};
+use crate::template;
+use crate::text::{Attributes, Text};
+use crate::timer::Timer;
+use crate::{Cnx, Result};
+
+/// Parses the `key: value` lines of an MPD response. Keys are lowercased.
+fn parse_response(lines: &[String]) -> HashMap<String, String> {
+    lines
+        .iter()
+        .filter_map(|line| {
+            let mut parts = line.splitn(2, ": ");
+            Some((parts.next()?.to_lowercase(), parts.next()?.to_owned()))
+        })
+        .collect()
+}
+
+type Reader<T> = Lines<BufReader<tokio_io::io::ReadHalf<T>>>;
+type Response<T> = Box<dyn Future<Item = (Vec<String>, WriteHalf<T>, Reader<T>), Error = Error>>;
+
+/// Reads the lines of a response from MPD, up to and including its final
+/// `OK` (or `ACK`, if the command failed).
+fn read_response<T>(
+    reader: Reader<T>,
+) -> Box<dyn Future<Item = (Vec<String>, Reader<T>), Error = Error>>
+where
+    T: AsyncRead + 'static,
+{
+    Box::new(future::loop_fn(
+        (Vec::new(), reader),
+        |(mut lines, reader)| {
+            reader
+                .into_future()
+                .map_err(|(e, _)| Error::from(e))
+                .and_then(|(line, reader)| {
+                    let line = line.ok_or_else(|| format_err!("MPD closed the connection"))?;
+                    if line == "OK" {
+                        Ok(future::Loop::Break((lines, reader)))
+                    } else if line.starts_with("ACK ") {
+                        Err(format_err!("MPD command failed: {}", line))
+                    } else {
+                        lines.push(line);
+                        Ok(future::Loop::Continue((lines, reader)))
+                    }
+                })
+        },
+    ))
+}
+
+/// Sends `command` to MPD, returning its response.
+fn command<T>(writer: WriteHalf<T>, reader: Reader<T>, command: &'static str) -> Response<T>
+where
+    T: AsyncRead + AsyncWrite + 'static,
+{
+    Box::new(
+        tokio_io::io::write_all(writer, command.as_bytes())
+            .map_err(Error::from)
+            .and_then(|(writer, _)| {
+                read_response(reader).map(|(lines, reader)| (lines, writer, reader))
+            }),
+    )
+}
+
+/// Shows the song that MPD is playing.
+///
+/// This widget connects to [`MPD`] and shows the song which is playing (or
+/// paused), using a format string such as `{artist} - {title}`. `{key}` is
+/// replaced by the song's tag, e.g. `{album}` or `{file}`, and `{state}` by
+/// `play` or `pause`. Nothing is shown while MPD is stopped.
+///
+/// The widget uses MPD's `idle` command to be told when the song or playback
+/// state changes, so doesn't poll. If MPD isn't running, or the connection is
+/// lost, a warning is logged and the widget is hidden until it reconnects,
+/// waiting longer between each attempt while MPD stays away.
+///
+/// [`MPD`]: https://www.musicpd.org/
+pub struct Mpd {
+    handle: Handle,
+    timer: Timer,
+    attr: Attributes,
+    address: String,
+    format: String,
+}
+
+impl Mpd {
+    ///  Creates a new Mpd widget.
+    ///
+    ///  Creates a new `Mpd` widget, whose text will be displayed with the
+    ///  given [`Attributes`]. The widget shows `format`, e.g. `{artist} -
+    ///  {title}`, for the current song.
+    ///
+    ///  The widget connects to MPD at `$MPD_HOST` and `$MPD_PORT`, or at
+    ///  `localhost:6600` if they are not set. Use [`with_address()`] to
+    ///  connect elsewhere.
+    ///
+    ///  The [`Cnx`] instance is borrowed during construction in order to get
+    ///  access to handles of its event loop. However, it is not borrowed for
+    ///  the lifetime of the widget. See the [`cnx_add_widget!()`] for more
+    ///  discussion about the lifetime of the borrow.
+    ///
+    /// [`Attributes`]: ../text/struct.Attributes.html
+    /// [`with_address()`]: #method.with_address
+    /// [`Cnx`]: ../struct.Cnx.html
+    /// [`cnx_add_widget!()`]: ../macro.cnx_add_widget.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use cnx::*;
+    /// # use cnx::text::*;
+    /// # use cnx::widgets::*;
+    /// #
+    /// # fn run() -> ::cnx::Result<()> {
+    /// let attr = Attributes {
+    ///     font: Font::new("SourceCodePro 21"),
+    ///     fg_color: "ffffff".parse().unwrap(),
+    ///     bg_color: None,
+    ///     padding: Padding::new(8.0, 8.0, 0.0, 0.0),
+    ///     border: None,
+    /// };
+    ///
+    /// let mut cnx = Cnx::new(Position::Top)?;
+    /// cnx.add_widget(Mpd::new(&cnx, attr.clone(), "{artist} - {title}".to_owned()));
+    /// # Ok(())
+    /// # }
+    /// # fn main() { run().unwrap(); }
+    /// ```
+    pub fn new(cnx: &Cnx, attr: Attributes, format: String) -> Mpd {
+        let host = env::var("MPD_HOST").unwrap_or_else(|_| "localhost".to_owned());
+        let port = env::var("MPD_PORT").unwrap_or_else(|_| "6600".to_owned());
+        Mpd {
+            handle: cnx.handle(),
+            timer: cnx.timer(),
+            attr,
+            address: format!("{}:{}", host, port),
+            format,
+        }
+    }
+
+    /// Connects to MPD at `address` instead, which is either a `host:port`
+    /// or the path of MPD's Unix socket, e.g. `/run/mpd/socket`.
+    pub fn with_address(mut self, address: String) -> Mpd {
+        self.address = address;
+        self
+    }
+
+    fn on_change(&self, responses: &[String]) -> Vec<Text> {
+        let song = parse_response(responses);
+        match song.get("state").map(String::as_str) {
+            Some("play") | Some("pause") => {}
+            _ => return Vec::new(),
+        }
+        vec![Text::new(
+            self.attr.clone(),
+            template::fill(&self.format, &song),
+        )]
+    }
+
+    /// Shows the current song, and then whatever is playing after each
+    /// change, until the connection is lost.
+    fn songs<T>(self: Rc<Self>, connection: T) -> WidgetStream
+    where
+        T: AsyncRead + AsyncWrite + 'static,
+    {
+        let (reader, writer) = connection.split();
+        let reader = tokio_io::io::lines(BufReader::new(reader));
+
+        // MPD greets us with `OK MPD <version>`.
+        let greeting = reader
+            .into_future()
+            .map_err(|(e, _)| Error::from(e))
+            .and_then(|(greeting, reader)| match greeting {
+                Some(ref greeting) if greeting.starts_with("OK MPD ") => Ok(reader),
+                _ => Err(format_err!("Unexpected greeting from MPD: {:?}", greeting)),
+            });
+
+        let songs = greeting
+            .map(move |reader| {
+                stream::unfold(Some((writer, reader, true)), move |state| {
+                    let (writer, reader, first) = state?;
+                    // Wait for something to change, unless this is the first
+                    // time around.
+                    let changed: Box<dyn Future<Item = _, Error = Error>> = if first {
+                        Box::new(future::ok((writer, reader)))
+                    } else {
+                        Box::new(
+                            command(writer, reader, "idle player\n")
+                                .map(|(_, writer, reader)| (writer, reader)),
+                        )
+                    };
+                    let current = changed.and_then(|(writer, reader)| {
+                        command(
+                            writer,
+                            reader,
+                            "command_list_begin\nstatus\ncurrentsong\ncommand_list_end\n",
+                        )
+                    });
+                    Some(
+                        current
+                            .map(|(lines, writer, reader)| (lines, Some((writer, reader, false)))),
+                    )
+                })
+            })
+            .flatten_stream()
+            .map(move |lines| self.on_change(&lines));
+        Box::new(songs)
+    }
+
+    /// Connects to MPD, returning the stream of its songs.
+    fn connect(self: Rc<Self>) -> Result<WidgetStream> {
+        if self.address.starts_with('/') {
+            let socket = mio_uds::UnixStream::connect(&self.address)
+                .with_context(|_| format!("Failed to connect to MPD at {}", self.address))?;
+            let socket = PollEvented::new(socket, &self.handle)?;
+            return Ok(self.songs(socket));
+        }
+
+        let address = self
+            .address
+            .to_socket_addrs()
+            .with_context(|_| format!("Invalid MPD address: {}", self.address))?
+            .next()
+            .ok_or_else(|| format_err!("Invalid MPD address: {}", self.address))?;
+        let connecting = TcpStream::connect(&address, &self.handle);
+        Ok(Box::new(
+            connecting
+                .map_err(Error::from)
+                .map(move |socket| self.songs(socket))
+                .flatten_stream(),
+        ))
+    }
+}
+
+impl Widget for Mpd {
+    fn stream(self: Box<Self>) -> Result<WidgetStream> {
+        let mpd = Rc::new(*self);
+        let service = format!("MPD at {}", mpd.address);
+        let timer = mpd.timer.clone();
+        Ok(timer.reconnecting(service, move || {
+            let songs = mpd.clone().connect()?;
+            // Hide the song once the connection is lost, until we reconnect.
+            let mut connected = false;
+            let songs = songs
+                .then(move |result| {
+                    let items = match result {
+                        Ok(texts) => {
+                            connected = true;
+                            vec![Ok(texts)]
+                        }
+                        Err(e) if connected => vec![Ok(Vec::new()), Err(e)],
+                        Err(e) => vec![Err(e)],
+                    };
+                    Ok::<_, Error>(stream::iter_result(items))
+                })
+                .flatten();
+            Ok(Box::new(songs))
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::parse_response;
+    use crate::template;
+
+    #[test]
+    fn works() {
+        let response = vec![
+            "volume: 80".to_owned(),
+            "state: play".to_owned(),
+            "file: music/get-lucky.flac".to_owned(),
+            "Artist: Daft Punk".to_owned(),
+            "Title: Get Lucky".to_owned(),
+        ];
+        let song = parse_response(&response);
+        assert_eq!(
+            template::fill("{Artist} - {title} [{state}]", &song),
+            "Daft Punk - Get Lucky [play]"
+        );
+        assert_eq!(template::fill("{album}{title}", &song), "Get Lucky");
+    }
+}
@@ -0,0 +1,306 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Duration;
+
+use futures::{future, stream, Future, Stream};
+use log::warn;
+use tokio_core::reactor::Handle;
+
+use super::{Click, ClickHandler, Refresh};
+use crate::busctl::{self, Bus, Busctl};
+use crate::error::{format_err, Error, ResultExt};
+use crate::json::{self, Value};
+use crate::text::{Attributes, Text};
+use crate::timer::Timer;
+use crate::{Cnx, Result};
+
+const BUS_NAME_PREFIX: &str = "org.mpris.MediaPlayer2.";
+const OBJECT_PATH: &str = "/org/mpris/MediaPlayer2";
+const PLAYER_INTERFACE: &str = "org.mpris.MediaPlayer2.Player";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum PlaybackStatus {
+    Playing,
+    Paused,
+    Stopped,
+}
+
+#[derive(Debug, PartialEq)]
+struct NowPlaying {
+    status: PlaybackStatus,
+    artist: Option<String>,
+    title: Option<String>,
+}
+
+/// Parses the reply to the bus's `ListNames`, returning the names of the MPRIS
+/// players.
+fn parse_players(output: &str) -> Result<Vec<String>> {
+    let reply = json::parse(output)?;
+    let names = reply
+        .get("data")
+        .and_then(|data| data.at(0))
+        .and_then(Value::as_array)
+        .ok_or_else(|| format_err!("Unexpected format of ListNames reply"))?;
+    Ok(names
+        .iter()
+        .filter_map(Value::as_str)
+        .filter(|name| name.starts_with(BUS_NAME_PREFIX))
+        .map(ToOwned::to_owned)
+        .collect())
+}
+
+/// Parses the output of getting a player's `PlaybackStatus` and `Metadata`
+/// properties, which `busctl` prints one per line.
+fn parse_now_playing(output: &str) -> Result<NowPlaying> {
+    let mut lines = output.lines();
+    let status = json::parse(lines.next().unwrap_or(""))?;
+    let status = match status.get("data").and_then(Value::as_str) {
+        Some("Playing") => PlaybackStatus::Playing,
+        Some("Paused") => PlaybackStatus::Paused,
+        Some("Stopped") => PlaybackStatus::Stopped,
+        _ => return Err(format_err!("Unexpected PlaybackStatus: {:?}", status)),
+    };
+
+    let metadata = json::parse(lines.next().unwrap_or(""))?;
+    let metadata = metadata.get("data");
+    // Each entry is a variant, i.e. an object with a type and data.
+    let entry = |key| {
+        metadata
+            .and_then(|m| m.get(key))
+            .and_then(|v| v.get("data"))
+    };
+    let artist = entry("xesam:artist")
+        .and_then(Value::as_array)
+        .map(|artists| {
+            artists
+                .iter()
+                .filter_map(Value::as_str)
+                .collect::<Vec<_>>()
+                .join(", ")
+        })
+        .filter(|artist| !artist.is_empty());
+    let title = entry("xesam:title")
+        .and_then(Value::as_str)
+        .filter(|title| !title.is_empty())
+        .map(ToOwned::to_owned);
+
+    Ok(NowPlaying {
+        status,
+        artist,
+        title,
+    })
+}
+
+/// Returns the player to show: a playing player is preferred over a paused
+/// one, and stopped players aren't shown.
+fn best_player<I>(players: I) -> Option<(String, NowPlaying)>
+where
+    I: IntoIterator<Item = (String, NowPlaying)>,
+{
+    let mut best: Option<(String, NowPlaying)> = None;
+    for (player, now_playing) in players {
+        let better = match (&best, now_playing.status) {
+            (_, PlaybackStatus::Stopped) => false,
+            (None, _) => true,
+            (Some((_, best)), PlaybackStatus::Playing) => best.status != PlaybackStatus::Playing,
+            (Some(_), PlaybackStatus::Paused) => false,
+        };
+        if better {
+            best = Some((player, now_playing));
+        }
+    }
+    best
+}
+
+/// Shows what an MPRIS media player (e.g. Spotify or mpv) is playing.
+///
+/// This widget shows the artist and title of the track being played by an
+/// [`MPRIS`] media player, e.g. `Artist - Title`, followed by `(paused)` if it
+/// is paused. If more than one player is running, a playing player is
+/// preferred over a paused one. When no player is playing or paused, the
+/// widget is hidden entirely.
+///
+/// Clicking on the widget toggles between playing and pausing.
+///
+/// The widget updates whenever a player reports a change over D-Bus, and
+/// every 10 seconds in case a change is missed. It expects the `busctl`
+/// executable (part of `systemd`) to be available in the `PATH`.
+///
+/// [`MPRIS`]: https://specifications.freedesktop.org/mpris-spec/latest/
+pub struct Mpris {
+    timer: Timer,
+    update_interval: Duration,
+    handle: Handle,
+    attr: Attributes,
+    busctl: Busctl,
+    // The player being shown, which is sent clicks.
+    player: Rc<RefCell<Option<String>>>,
+}
+
+impl Mpris {
+    ///  Creates a new Mpris widget.
+    ///
+    ///  Creates a new `Mpris` widget, whose text will be displayed with the
+    ///  given [`Attributes`].
+    ///
+    ///  The [`Cnx`] instance is borrowed during construction in order to get
+    ///  access to handles of its event loop. However, it is not borrowed for
+    ///  the lifetime of the widget. See the [`cnx_add_widget!()`] for more
+    ///  discussion about the lifetime of the borrow.
+    ///
+    /// [`Attributes`]: ../text/struct.Attributes.html
+    /// [`Cnx`]: ../struct.Cnx.html
+    /// [`cnx_add_widget!()`]: ../macro.cnx_add_widget.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use cnx::*;
+    /// # use cnx::text::*;
+    /// # use cnx::widgets::*;
+    /// #
+    /// # fn run() -> ::cnx::Result<()> {
+    /// let attr = Attributes {
+    ///     font: Font::new("SourceCodePro 21"),
+    ///     fg_color: "ffffff".parse().unwrap(),
+    ///     bg_color: None,
+    ///     padding: Padding::new(8.0, 8.0, 0.0, 0.0),
+    ///     border: None,
+    /// };
+    ///
+    /// let mut cnx = Cnx::new(Position::Top)?;
+    /// cnx.add_widget(Mpris::new(&cnx, attr.clone()));
+    /// # Ok(())
+    /// # }
+    /// # fn main() { run().unwrap(); }
+    /// ```
+    pub fn new(cnx: &Cnx, attr: Attributes) -> Mpris {
+        Mpris {
+            timer: cnx.timer(),
+            update_interval: Duration::from_secs(10),
+            handle: cnx.handle(),
+            attr,
+            busctl: Busctl::new(cnx, Bus::User),
+            player: Rc::new(RefCell::new(None)),
+        }
+    }
+
+    /// Returns a stream which yields whenever a player's properties change,
+    /// by watching the bus with `busctl monitor`.
+    fn player_events(&self) -> Result<Refresh> {
+        let match_rule = "type='signal',interface='org.freedesktop.DBus.Properties',path='/org/mpris/MediaPlayer2'";
+        match busctl::monitor(&self.handle, Bus::User, match_rule) {
+            Ok(lines) => Ok(Box::new(lines.map(|_| ()))),
+            Err(e) => {
+                warn!("Mpris won't notice changes until its next update: {}", e);
+                Ok(Box::new(stream::empty()))
+            }
+        }
+    }
+
+    fn tick(&self) -> Box<dyn Future<Item = Vec<Text>, Error = Error>> {
+        let listed = self.busctl.call(&[
+            "call",
+            "org.freedesktop.DBus",
+            "/org/freedesktop/DBus",
+            "org.freedesktop.DBus",
+            "ListNames",
+        ]);
+        let busctl = self.busctl.clone();
+        let players = listed
+            .and_then(|output| parse_players(&output).context("Failed to list MPRIS players"))
+            .and_then(move |players| {
+                future::join_all(players.into_iter().map(move |player| {
+                    let output = busctl.call(&[
+                        "get-property",
+                        &player,
+                        OBJECT_PATH,
+                        PLAYER_INTERFACE,
+                        "PlaybackStatus",
+                        "Metadata",
+                    ]);
+                    // Players may exit at any time, or not implement the
+                    // Player interface properly.
+                    output.then(move |output| {
+                        let now_playing = output.and_then(|output| parse_now_playing(&output));
+                        Ok(now_playing.ok().map(|now_playing| (player, now_playing)))
+                    })
+                }))
+            });
+
+        let attr = self.attr.clone();
+        let shown = self.player.clone();
+        Box::new(players.map(move |players| {
+            let (player, now_playing) = match best_player(players.into_iter().flatten()) {
+                Some(best) => best,
+                None => {
+                    *shown.borrow_mut() = None;
+                    return Vec::new();
+                }
+            };
+            *shown.borrow_mut() = Some(player.clone());
+
+            let mut text = match (now_playing.artist, now_playing.title) {
+                (Some(artist), Some(title)) => format!("{} - {}", artist, title),
+                (None, Some(title)) => title,
+                (Some(artist), None) => artist,
+                (None, None) => player[BUS_NAME_PREFIX.len()..].to_owned(),
+            };
+            if now_playing.status == PlaybackStatus::Paused {
+                text.push_str(" (paused)");
+            }
+            vec![Text::new(attr, text)]
+        }))
+    }
+
+    /// Returns a `ClickHandler` which toggles between playing and pausing the
+    /// player that is being shown.
+    fn play_pause(&mut self) -> Option<ClickHandler> {
+        let busctl = self.busctl.clone();
+        let player = self.player.clone();
+        Some(Box::new(move |click: Click| {
+            if click.button != 1 {
+                return Ok(());
+            }
+            if let Some(ref player) = *player.borrow() {
+                busctl.spawn(&["call", player, OBJECT_PATH, PLAYER_INTERFACE, "PlayPause"]);
+            }
+            Ok(())
+        }))
+    }
+}
+
+timer_widget!(Mpris, timer, update_interval, tick; player_events; play_pause);
+
+#[cfg(test)]
+mod test {
+    use super::{parse_now_playing, parse_players, NowPlaying, PlaybackStatus};
+
+    #[test]
+    fn works() {
+        let names = r#"{"type":"as","data":[["org.freedesktop.DBus",":1.7","org.mpris.MediaPlayer2.spotify","org.mpris.MediaPlayer2.mpv"]]}"#;
+        assert_eq!(
+            parse_players(names).unwrap(),
+            vec![
+                "org.mpris.MediaPlayer2.spotify".to_owned(),
+                "org.mpris.MediaPlayer2.mpv".to_owned(),
+            ]
+        );
+
+        let properties = concat!(
+            r#"{"type":"s","data":"Paused"}"#,
+            "\n",
+            r#"{"type":"a{sv}","data":{"mpris:length":{"type":"t","data":227000000},"xesam:artist":{"type":"as","data":["Daft Punk","Pharrell Williams"]},"xesam:title":{"type":"s","data":"Get Lucky"}}}"#,
+            "\n"
+        );
+        assert_eq!(
+            parse_now_playing(properties).unwrap(),
+            NowPlaying {
+                status: PlaybackStatus::Paused,
+                artist: Some("Daft Punk, Pharrell Williams".to_owned()),
+                title: Some("Get Lucky".to_owned()),
+            }
+        );
+        assert!(parse_now_playing(r#"{"type":"s","data":"Dancing"}"#).is_err());
+    }
+}
@@ -0,0 +1,161 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use futures::{stream, Stream};
+use tokio_core::reactor::Handle;
+
+use super::{Widget, WidgetStream};
+use crate::mqtt;
+use crate::template;
+use crate::text::{Attributes, Text};
+use crate::timer::Timer;
+use crate::{Cnx, Result};
+
+/// Shows the latest messages published to MQTT topics.
+///
+/// This widget subscribes to one or more topics on an [MQTT] broker, such as
+/// those published by Home Assistant, ESPHome or Zigbee2MQTT sensors, and
+/// shows the latest message from each using a format string. Each topic is
+/// given a name when it is added with [`with_topic()`], and `{name}` in the
+/// format string is replaced by the topic's latest message (or `?` until one
+/// is received).
+///
+/// Messages are shown as soon as they are published. If the connection to
+/// the broker is lost, a warning is logged and the widget reconnects, waiting
+/// longer between each attempt (up to five minutes) while the broker is
+/// unreachable.
+///
+/// [MQTT]: https://mqtt.org/
+/// [`with_topic()`]: #method.with_topic
+pub struct Mqtt {
+    timer: Timer,
+    handle: Handle,
+    attr: Attributes,
+    broker: String,
+    format: String,
+    // (name, topic) pairs.
+    topics: Vec<(String, String)>,
+    credentials: Option<(String, String)>,
+}
+
+impl Mqtt {
+    ///  Creates a new Mqtt widget.
+    ///
+    ///  Creates a new `Mqtt` widget, whose text will be displayed with the
+    ///  given [`Attributes`]. The widget connects to the MQTT broker at
+    ///  `broker`, which is either a `host` or `host:port`, and shows
+    ///  `format`, e.g. `{inside}°C {humidity}%`.
+    ///
+    ///  The [`Cnx`] instance is borrowed during construction in order to get
+    ///  access to handles of its event loop. However, it is not borrowed for
+    ///  the lifetime of the widget. See the [`cnx_add_widget!()`] for more
+    ///  discussion about the lifetime of the borrow.
+    ///
+    /// [`Attributes`]: ../text/struct.Attributes.html
+    /// [`Cnx`]: ../struct.Cnx.html
+    /// [`cnx_add_widget!()`]: ../macro.cnx_add_widget.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use cnx::*;
+    /// # use cnx::text::*;
+    /// # use cnx::widgets::*;
+    /// #
+    /// # fn run() -> ::cnx::Result<()> {
+    /// let attr = Attributes {
+    ///     font: Font::new("SourceCodePro 21"),
+    ///     fg_color: "ffffff".parse().unwrap(),
+    ///     bg_color: None,
+    ///     padding: Padding::new(8.0, 8.0, 0.0, 0.0),
+    ///     border: None,
+    /// };
+    ///
+    /// let mut cnx = Cnx::new(Position::Top)?;
+    /// let mqtt = Mqtt::new(&cnx, attr.clone(), "homeassistant.local".to_owned(), "{inside}°C".to_owned())
+    ///     .with_topic("inside", "esphome/living-room/sensor/temperature/state");
+    /// cnx.add_widget(mqtt);
+    /// # Ok(())
+    /// # }
+    /// # fn main() { run().unwrap(); }
+    /// ```
+    pub fn new(cnx: &Cnx, attr: Attributes, broker: String, format: String) -> Mqtt {
+        Mqtt {
+            timer: cnx.timer(),
+            handle: cnx.handle(),
+            attr,
+            broker,
+            format,
+            topics: Vec::new(),
+            credentials: None,
+        }
+    }
+
+    /// Subscribes to `topic`, showing its latest message in place of `{name}`.
+    /// The topic may contain the `+` and `#` wildcards, in which case the
+    /// latest message on any matching topic is shown.
+    pub fn with_topic(mut self, name: &str, topic: &str) -> Mqtt {
+        self.topics.push((name.to_lowercase(), topic.to_owned()));
+        self
+    }
+
+    /// Logs in to the broker with `username` and `password`.
+    pub fn with_credentials(mut self, username: String, password: String) -> Mqtt {
+        self.credentials = Some((username, password));
+        self
+    }
+
+    fn render(&self, values: &HashMap<String, String>) -> Vec<Text> {
+        vec![Text::new(
+            self.attr.clone(),
+            template::fill(&self.format, values),
+        )]
+    }
+}
+
+impl Widget for Mqtt {
+    fn stream(self: Box<Self>) -> Result<WidgetStream> {
+        let mqtt = Rc::new(*self);
+        let filters = mqtt
+            .topics
+            .iter()
+            .map(|(_, topic)| topic.clone())
+            .collect::<Vec<_>>();
+
+        let connections = {
+            let timer = mqtt.timer.clone();
+            let service = format!("MQTT broker {}", mqtt.broker);
+            let mqtt = mqtt.clone();
+            timer.reconnecting(service, move || {
+                mqtt::subscribe(
+                    &mqtt.handle,
+                    &mqtt.timer,
+                    &mqtt.broker,
+                    mqtt.credentials.as_ref(),
+                    &filters,
+                )
+            })
+        };
+
+        let mut values = mqtt
+            .topics
+            .iter()
+            .map(|(name, _)| (name.clone(), "?".to_owned()))
+            .collect::<HashMap<_, _>>();
+        let stream = stream::once(Ok(None))
+            .chain(connections.map(Some))
+            .map(move |message| {
+                if let Some((topic, payload)) = message {
+                    let payload = String::from_utf8_lossy(&payload).trim().to_owned();
+                    for (name, filter) in &mqtt.topics {
+                        if mqtt::topic_matches(filter, &topic) {
+                            values.insert(name.clone(), payload.clone());
+                        }
+                    }
+                }
+                mqtt.render(&values)
+            });
+
+        Ok(Box::new(stream))
+    }
+}
@@ -1,23 +1,66 @@
+use std::cell::RefCell;
+use std::mem;
+use std::rc::Rc;
+use std::time::Duration;
+
+use futures::{stream, Stream};
+use log::error;
 use tokio_core::reactor::Handle;
 use xcb_util::ewmh;
 
-use crate::text::{Attributes, Text};
-use crate::{Cnx, Result};
+use super::{Click, ClickHandler, Widget, WidgetStream};
+use crate::animation::Animation;
+use crate::error::ResultExt;
+use crate::text::{Attributes, Border, Color, Dot, DotShape, Graphic, Text};
+use crate::{xcb_ext, Cnx, Result};
 
 /// Shows the WM's workspaces/groups, highlighting whichever is currently
 /// active.
 ///
 /// This widget shows the WM's workspaces/groups, as determined by the [`EWMH`]
 /// `_NET_NUMBER_OF_DESKTOPS` and `_NET_DESKTOP_NAMES` and
-/// `_NET_CURRENT_DESKTOP` properties. The active workspace is highlighted.
+/// `_NET_CURRENT_DESKTOP` properties. The active workspace is highlighted,
+/// e.g. with a background color or an underline (see [`Border`]).
+///
+/// Clicking on a workspace asks the WM to switch to it, and scrolling over
+/// the widget switches to the previous (up) or next (down) workspace,
+/// wrapping around at either end.
+///
+/// The active workspace can optionally be animated, so that its highlight
+/// slides across to the newly active workspace. See [`with_animation()`].
+/// Workspaces with a window demanding attention (`_NET_WM_STATE_DEMANDS_ATTENTION`)
+/// can be highlighted too, see [`with_urgent()`], and workspaces without any
+/// windows can be hidden, see [`hide_empty()`].
+///
+/// For minimal bars, each workspace can be drawn as a small dot instead of
+/// its name, see [`with_dots()`].
 ///
 /// [`EWMH`]: https://specifications.freedesktop.org/wm-spec/wm-spec-latest.html
+/// [`Border`]: ../text/struct.Border.html
+/// [`with_animation()`]: #method.with_animation
+/// [`with_urgent()`]: #method.with_urgent
+/// [`hide_empty()`]: #method.hide_empty
+/// [`with_dots()`]: #method.with_dots
 pub struct Pager {
+    inner: PagerInner,
+    animation: Option<Animation>,
+}
+
+struct PagerInner {
     tokio_handle: Handle,
     active_attr: Attributes,
     inactive_attr: Attributes,
+    urgent_attr: Option<Attributes>,
+    hide_empty: bool,
+    // The shape and size of the dots drawn instead of names, if any.
+    dots: Option<(DotShape, f64)>,
+    // The workspace shown by each text, for the click handler.
+    desktops: Rc<RefCell<Vec<u32>>>,
 }
 
+// The `_NET_WM_DESKTOP` of windows which are shown on every desktop.
+const ALL_DESKTOPS: u32 = 0xffff_ffff;
+
 impl Pager {
     ///  Creates a new Pager widget.
     ///
@@ -51,9 +94,10 @@ impl Pager {
     ///     fg_color: Color::white(),
     ///     bg_color: None,
     ///     padding: Padding::new(8.0, 8.0, 0.0, 0.0),
+    ///     border: None,
     /// };
     /// let mut active_attr = attr.clone();
-    /// active_attr.bg_color = Some(Color::blue());
+    /// active_attr.border = Some(Border::underline(Color::blue(), 3.0));
     ///
     /// let mut cnx = Cnx::new(Position::Top)?;
     /// cnx_add_widget!(cnx, Pager::new(&cnx, active_attr, attr.clone()));
@@ -63,55 +107,316 @@ impl Pager {
     /// ```
     pub fn new(cnx: &Cnx, active_attr: Attributes, inactive_attr: Attributes) -> Pager {
         Pager {
-            tokio_handle: cnx.handle(),
-            active_attr,
-            inactive_attr,
+            inner: PagerInner {
+                tokio_handle: cnx.handle(),
+                active_attr,
+                inactive_attr,
+                urgent_attr: None,
+                hide_empty: false,
+                dots: None,
+                desktops: Rc::new(RefCell::new(Vec::new())),
+            },
+            animation: None,
+        }
+    }
+
+    /// Shows the workspaces with a window demanding attention (e.g. a chat
+    /// client with a new message) with the `urgent_attr` [`Attributes`],
+    /// unless they are active.
+    ///
+    /// [`Attributes`]: ../text/struct.Attributes.html
+    pub fn with_urgent(mut self, urgent_attr: Attributes) -> Pager {
+        self.inner.urgent_attr = Some(urgent_attr);
+        self
+    }
+
+    /// Hides the workspaces without any windows on them, other than the
+    /// active workspace.
+    pub fn hide_empty(mut self) -> Pager {
+        self.inner.hide_empty = true;
+        self
+    }
+
+    /// Draws each workspace as a dot of the given `shape`, `size` pixels
+    /// across, instead of its name.
+    ///
+    /// Dots are drawn in the `fg_color` of the workspace's [`Attributes`], so
+    /// the active workspace's dot is in the `active_attr`'s color. Dots are
+    /// filled in for workspaces with windows on them, and outlined for empty
+    /// ones. Workspaces with a window demanding attention are red, unless
+    /// other attributes are given with [`with_urgent()`].
+    ///
+    /// [`Attributes`]: ../text/struct.Attributes.html
+    /// [`with_urgent()`]: #method.with_urgent
+    pub fn with_dots(mut self, shape: DotShape, size: f64) -> Pager {
+        self.inner.dots = Some((shape, size));
+        self
+    }
+
+    /// Animates the highlight when the active workspace changes.
+    ///
+    /// When the active workspace changes, the colors of the `active_attr`
+    /// [`Attributes`] slide from the previously active workspace to the newly
+    /// active one over the given `duration`, fading in and out of any
+    /// workspaces in between.
+    ///
+    /// [`Attributes`]: ../text/struct.Attributes.html
+    pub fn with_animation(mut self, cnx: &Cnx, duration: Duration) -> Pager {
+        self.animation = Some(cnx.animation(duration));
+        self
+    }
+}
+
+/// Renders one frame of the highlight sliding from the `from`th to the `to`th
+/// workspace, `t` (0.0 to 1.0) of the way through the animation.
+fn animation_frame(
+    texts: &[Text],
+    from: usize,
+    to: usize,
+    t: f64,
+    active_attr: &Attributes,
+    inactive_attr: &Attributes,
+) -> Vec<Text> {
+    let position = from as f64 + (to as f64 - from as f64) * t;
+    let active_bg = active_attr.bg_color.clone().unwrap_or_default();
+
+    texts
+        .iter()
+        .enumerate()
+        .map(|(i, text)| {
+            // How much of this workspace is currently covered by the highlight.
+            let coverage = (1.0 - (i as f64 - position).abs()).max(0.0);
+            // Urgent workspaces keep their own attributes when uncovered.
+            let uncovered = if text.attr == *active_attr {
+                inactive_attr
+            } else {
+                &text.attr
+            };
+            let attr = if coverage >= 1.0 {
+                active_attr.clone()
+            } else if coverage <= 0.0 {
+                uncovered.clone()
+            } else {
+                let uncovered_bg = uncovered.bg_color.clone().unwrap_or_default();
+                let mut attr = uncovered.clone();
+                attr.fg_color = uncovered.fg_color.blend(&active_attr.fg_color, coverage);
+                attr.bg_color = Some(uncovered_bg.blend(&active_bg, coverage));
+                if let Some(ref active) = active_attr.border {
+                    // Fade in an underline (or other border) which only the
+                    // active workspace has.
+                    let uncovered_color = match uncovered.border {
+                        Some(ref border) => border.color.clone(),
+                        None => active.color.clone().with_alpha(0.0),
+                    };
+                    attr.border = Some(Border {
+                        color: uncovered_color.blend(&active.color, coverage),
+                        ..active.clone()
+                    });
+                }
+                attr
+            };
+            Text {
+                attr,
+                ..text.clone()
+            }
+        })
+        .collect()
+}
+
+impl Widget for Pager {
+    fn stream(self: Box<Self>) -> Result<WidgetStream> {
+        let Pager { inner, animation } = *self;
+        let active_attr = inner.active_attr.clone();
+        let inactive_attr = inner.inactive_attr.clone();
+        let stream = Box::new(inner).stream()?;
+
+        let animation = match animation {
+            // There's nothing to animate if the active workspace looks the same
+            // as every other.
+            Some(animation) if active_attr != inactive_attr => animation,
+            _ => return Ok(stream),
+        };
+
+        let mut previous = None;
+        let stream = stream
+            .map(move |texts| -> WidgetStream {
+                let current = texts.iter().position(|text| text.attr == active_attr);
+                match (mem::replace(&mut previous, current), current) {
+                    (Some(from), Some(to)) if from != to => {
+                        let active_attr = active_attr.clone();
+                        let inactive_attr = inactive_attr.clone();
+                        Box::new(animation.frames().map(move |t| {
+                            animation_frame(&texts, from, to, t, &active_attr, &inactive_attr)
+                        }))
+                    }
+                    _ => Box::new(stream::once(Ok(texts))),
+                }
+            })
+            .flatten();
+
+        Ok(Box::new(stream))
+    }
+
+    fn click_handler(&mut self) -> Option<ClickHandler> {
+        match desktop_switcher(self.inner.desktops.clone()) {
+            Ok(handler) => Some(handler),
+            Err(e) => {
+                error!("Pager won't respond to clicks: {}", e);
+                None
+            }
         }
     }
+}
+
+/// Returns the workspace to switch to when scrolling with `button` from the
+/// `current` of `number` workspaces, or None if `button` isn't a scroll.
+fn scrolled_desktop(button: u8, current: u32, number: u32) -> Option<u32> {
+    if number == 0 {
+        return None;
+    }
+    match button {
+        4 => Some(current.checked_sub(1).unwrap_or(number - 1).min(number - 1)),
+        5 => Some((current + 1) % number),
+        _ => None,
+    }
+}
+
+/// Returns a `ClickHandler` which switches to the clicked workspace, or the
+/// neighbouring workspace when scrolled.
+fn desktop_switcher(desktops: Rc<RefCell<Vec<u32>>>) -> Result<ClickHandler> {
+    let (conn, screen_idx) = xcb_ext::connect()?;
+    Ok(Box::new(move |click: Click| {
+        let desktop = match click.button {
+            1 => match desktops.borrow().get(click.text) {
+                Some(&desktop) => desktop,
+                None => return Ok(()),
+            },
+            button => {
+                let current = ewmh::get_current_desktop(&conn, screen_idx)
+                    .get_reply()
+                    .unwrap_or(0);
+                let number = ewmh::get_number_of_desktops(&conn, screen_idx)
+                    .get_reply()
+                    .unwrap_or(0);
+                match scrolled_desktop(button, current, number) {
+                    Some(desktop) => desktop,
+                    None => return Ok(()),
+                }
+            }
+        };
+        ewmh::request_change_current_desktop(&conn, screen_idx, desktop, xcb::CURRENT_TIME)
+            .request_check()
+            .context("Failed to switch workspace")?;
+        Ok(())
+    }))
+}
+
+/// The windows on each of the workspaces.
+#[derive(Default)]
+struct Occupancy {
+    // The number of windows on each workspace, excluding those shown on
+    // every workspace.
+    windows: Vec<usize>,
+    // Whether each workspace has a window demanding attention.
+    urgent: Vec<bool>,
+}
+
+impl PagerInner {
+    /// Finds the windows on each of the `number` workspaces.
+    fn occupancy(&self, conn: &ewmh::Connection, screen_idx: i32, number: usize) -> Occupancy {
+        let clients = match ewmh::get_client_list(conn, screen_idx).get_reply() {
+            Ok(reply) => reply.windows().to_vec(),
+            Err(_) => Vec::new(),
+        };
+        // Send every request before waiting for any of the replies.
+        let cookies = clients
+            .iter()
+            .map(|&window| {
+                // Like the Taskbar, watch each window we see so that we notice
+                // it moving or demanding attention.
+                xcb_ext::select_property_events(conn, window);
+                (
+                    ewmh::get_wm_desktop(conn, window),
+                    ewmh::get_wm_state(conn, window),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let mut occupancy = Occupancy {
+            windows: vec![0; number],
+            urgent: vec![false; number],
+        };
+        for (desktop, state) in cookies {
+            let desktop = match desktop.get_reply() {
+                Ok(desktop) if desktop != ALL_DESKTOPS && (desktop as usize) < number => {
+                    desktop as usize
+                }
+                _ => continue,
+            };
+            occupancy.windows[desktop] += 1;
+            let urgent = state
+                .get_reply()
+                .map(|reply| reply.atoms().contains(&conn.WM_STATE_DEMANDS_ATTENTION()))
+                .unwrap_or(false);
+            occupancy.urgent[desktop] |= urgent;
+        }
+        occupancy
+    }
 
     fn on_change(&self, conn: &ewmh::Connection, screen_idx: i32) -> Result<Vec<Text>> {
-        let number = ewmh::get_number_of_desktops(conn, screen_idx)
-            .get_reply()
-            .unwrap_or(0) as usize;
         let current = ewmh::get_current_desktop(conn, screen_idx)
             .get_reply()
             .unwrap_or(0) as usize;
-        let names_reply = ewmh::get_desktop_names(conn, screen_idx).get_reply();
-        let mut names = match names_reply {
-            Ok(ref r) => r.strings(),
-            Err(_) => Vec::new(),
+        let names = xcb_ext::desktop_names(conn, screen_idx);
+        let urgent_attr = self.urgent_attr.clone().or_else(|| {
+            self.dots.map(|_| Attributes {
+                fg_color: Color::red(),
+                ..self.inactive_attr.clone()
+            })
+        });
+        let occupancy = if urgent_attr.is_some() || self.hide_empty {
+            self.occupancy(conn, screen_idx, names.len())
+        } else {
+            Occupancy::default()
         };
 
-        // EWMH states that `number` may not equal `names.len()`, as there may
-        // be unnamed desktops, or more desktops than are currently in use.
-        if names.len() > number {
-            names.truncate(number);
-        } else if number > names.len() {
-            let num_unnamed = number - names.len();
-            names.extend(vec!["?"; num_unnamed]);
+        let mut desktops = Vec::new();
+        let mut texts = Vec::new();
+        for (i, name) in names.into_iter().enumerate() {
+            let empty = occupancy.windows.get(i) == Some(&0);
+            if self.hide_empty && empty && i != current {
+                continue;
+            }
+            let attr = match urgent_attr {
+                _ if i == current => self.active_attr.clone(),
+                Some(ref urgent_attr) if occupancy.urgent.get(i) == Some(&true) => {
+                    urgent_attr.clone()
+                }
+                _ => self.inactive_attr.clone(),
+            };
+            let graphic = self.dots.map(|(shape, size)| {
+                Graphic::Dot(Dot {
+                    shape,
+                    size,
+                    filled: !empty || i == current,
+                })
+            });
+            desktops.push(i as u32);
+            let mut text = Text::new(attr, name);
+            text.graphic = graphic;
+            texts.push(text);
         }
 
-        Ok(names
-            .into_iter()
-            .enumerate()
-            .map(|(i, name)| {
-                let attr = if i == current {
-                    self.active_attr.clone()
-                } else {
-                    self.inactive_attr.clone()
-                };
-                Text {
-                    attr,
-                    text: name.to_owned(),
-                    stretch: false,
-                }
-            })
-            .collect())
+        *self.desktops.borrow_mut() = desktops;
+        Ok(texts)
     }
 }
 
-x_properties_widget!(Pager, tokio_handle, on_change; [
+x_properties_widget!(PagerInner, tokio_handle, on_change; [
     NUMBER_OF_DESKTOPS,
     CURRENT_DESKTOP,
-    DESKTOP_NAMES
+    DESKTOP_NAMES,
+    CLIENT_LIST,
+    WM_DESKTOP,
+    WM_STATE
 ]);
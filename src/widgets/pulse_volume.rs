@@ -0,0 +1,554 @@
+use std::process::Command;
+use std::rc::Rc;
+use std::time::Duration;
+
+use futures::{future, stream, Future, Stream};
+use log::warn;
+use tokio_core::reactor::Handle;
+
+use super::{Click, ClickHandler, Widget, WidgetStream};
+use crate::error::{format_err, Error, ResultExt};
+use crate::process;
+use crate::text::{Attributes, Text};
+use crate::timer::Timer;
+use crate::{Cnx, Result};
+
+// How long `pactl` may take (e.g. to list the sinks or streams) before it is
+// killed.
+const PACTL_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Changes come in bursts (e.g. switching sinks changes the server, both
+// sinks and every stream), so the sinks are only listed again once they have
+// stopped for this long.
+const SETTLE: Duration = Duration::from_millis(100);
+
+/// A PulseAudio sink, as listed by `pactl list sinks`.
+#[derive(Debug, Default, PartialEq)]
+struct Sink {
+    name: String,
+    description: String,
+    muted: bool,
+    volume: u32,
+}
+
+/// A stream playing to a sink, as listed by `pactl list sink-inputs`.
+#[derive(Debug, Default, PartialEq)]
+struct PlaybackStream {
+    application: String,
+    muted: bool,
+    volume: u32,
+}
+
+/// Runs `pactl` with `args`, in the C locale so that its output can be
+/// parsed, without blocking the event loop. Fails if `pactl` does.
+fn pactl(
+    handle: &Handle,
+    timer: &Timer,
+    args: &[&str],
+) -> Box<dyn Future<Item = String, Error = Error>> {
+    let mut command = Command::new("pactl");
+    command.args(args).env("LC_ALL", "C");
+    let description = format!("`pactl {}`", args.join(" "));
+    Box::new(
+        process::output(handle, timer, command, PACTL_TIMEOUT).and_then(move |(status, stdout)| {
+            if !status.success() {
+                return Err(format_err!("{} failed: {}", description, status));
+            }
+            String::from_utf8(stdout).context("Invalid UTF-8 in pactl output")
+        }),
+    )
+}
+
+/// Formats a volume as a percentage, or `M` if it is muted.
+fn format_volume(muted: bool, volume: u32) -> String {
+    if muted {
+        "M".to_owned()
+    } else {
+        format!("{}%", volume)
+    }
+}
+
+/// Parses the name of the default sink from the output of `pactl info`.
+fn parse_default_sink(info: &str) -> Option<&str> {
+    info.lines()
+        .filter_map(|line| line.trim().strip_prefix("Default Sink:"))
+        .map(str::trim)
+        .next()
+}
+
+/// Parses the indices (or names) in the first two columns of the output of
+/// `pactl list short <type>`.
+fn parse_short_list(list: &str) -> Vec<(&str, &str)> {
+    list.lines()
+        .filter_map(|line| {
+            let mut columns = line.split('\t');
+            Some((columns.next()?, columns.next()?))
+        })
+        .collect()
+}
+
+/// Picks the sink after `default` out of `sinks`, wrapping around, or the
+/// first sink if `default` isn't one of them.
+fn next_sink<'a>(sinks: &[&'a str], default: Option<&str>) -> Option<&'a str> {
+    match sinks.iter().position(|&name| Some(name) == default) {
+        Some(i) => Some(sinks[(i + 1) % sinks.len()]),
+        None => sinks.first().cloned(),
+    }
+}
+
+/// Makes the next sink after the default sink the default, and moves every
+/// stream onto it. Only the sinks named in `only` are chosen from, if there
+/// are any.
+fn cycle_default_sink(
+    handle: &Handle,
+    timer: &Timer,
+    only: &[String],
+) -> Box<dyn Future<Item = (), Error = Error>> {
+    let listed =
+        pactl(handle, timer, &["info"]).join(pactl(handle, timer, &["list", "short", "sinks"]));
+    let (handle, timer) = (handle.clone(), timer.clone());
+    let only = only.to_vec();
+    Box::new(listed.and_then(
+        move |(info, sinks)| -> Box<dyn Future<Item = _, Error = _>> {
+            let default = parse_default_sink(&info);
+            let sinks = parse_short_list(&sinks)
+                .into_iter()
+                .map(|(_, name)| name)
+                .collect::<Vec<_>>();
+            // Sinks which aren't currently plugged in are skipped.
+            let sinks = if only.is_empty() {
+                sinks
+            } else {
+                only.iter()
+                    .map(String::as_str)
+                    .filter(|name| sinks.contains(name))
+                    .collect()
+            };
+            let next = match next_sink(&sinks, default) {
+                Some(next) => next.to_owned(),
+                None => return Box::new(future::ok(())),
+            };
+
+            let set = pactl(&handle, &timer, &["set-default-sink", &next]);
+            // Existing streams stay on the old sink unless they're moved.
+            let inputs = {
+                let (handle, timer) = (handle.clone(), timer.clone());
+                set.and_then(move |_| pactl(&handle, &timer, &["list", "short", "sink-inputs"]))
+            };
+            Box::new(inputs.and_then(move |inputs| {
+                let moves = parse_short_list(&inputs)
+                    .into_iter()
+                    .map(|(index, _)| {
+                        let index = index.to_owned();
+                        let next = next.clone();
+                        let moved = pactl(&handle, &timer, &["move-sink-input", &index, &next]);
+                        // A stream which can't be moved (e.g. it has just
+                        // ended) shouldn't leave the rest behind.
+                        moved.then(move |moved| {
+                            if let Err(e) = moved {
+                                warn!("Failed to move stream {} to {}: {}", index, next, e);
+                            }
+                            Ok(())
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                future::join_all(moves).map(|_| ())
+            }))
+        },
+    ))
+}
+
+/// Parses a `Volume` from the output of `pactl list`, e.g. `front-left: 39321
+/// /  60% / -13.31 dB,   front-right: ...`, as the average of its channels'
+/// volumes.
+fn parse_volume(value: &str) -> Option<u32> {
+    let percentages = value
+        .split(['/', ','])
+        .filter_map(|part| part.trim().strip_suffix('%'))
+        .filter_map(|percentage| percentage.trim().parse::<u32>().ok())
+        .collect::<Vec<_>>();
+    if percentages.is_empty() {
+        return None;
+    }
+    Some(percentages.iter().sum::<u32>() / percentages.len() as u32)
+}
+
+/// Parses the output of `pactl list sink-inputs`. Streams which don't say
+/// which application they're from are named after their media, if anything.
+fn parse_playback_streams(list: &str) -> Vec<PlaybackStream> {
+    let mut streams = Vec::new();
+    for line in list.lines() {
+        if line.starts_with("Sink Input #") {
+            streams.push(PlaybackStream::default());
+            continue;
+        }
+        let stream = match streams.last_mut() {
+            Some(stream) => stream,
+            None => continue,
+        };
+        let line = line.trim();
+        // Properties look like `application.name = "Firefox"`.
+        if let Some((key, value)) = line.split_once(" = ") {
+            let value = value.trim_matches('"');
+            match key {
+                "application.name" => stream.application = value.to_owned(),
+                "media.name" if stream.application.is_empty() => {
+                    stream.application = value.to_owned()
+                }
+                _ => {}
+            }
+            continue;
+        }
+        match line.split_once(':') {
+            Some(("Mute", value)) => stream.muted = value.trim() == "yes",
+            Some(("Volume", value)) => stream.volume = parse_volume(value).unwrap_or_default(),
+            _ => {}
+        }
+    }
+    streams
+}
+
+/// Parses the output of `pactl list sinks`.
+///
+/// The volume of each sink is the average of its channels' volumes.
+fn parse_sinks(list: &str) -> Vec<Sink> {
+    let mut sinks = Vec::new();
+    for line in list.lines() {
+        if line.starts_with("Sink #") {
+            sinks.push(Sink::default());
+            continue;
+        }
+        let sink = match sinks.last_mut() {
+            Some(sink) => sink,
+            None => continue,
+        };
+        let mut parts = line.trim().splitn(2, ':');
+        let (key, value) = match (parts.next(), parts.next()) {
+            (Some(key), Some(value)) => (key, value.trim()),
+            _ => continue,
+        };
+        match key {
+            "Name" => sink.name = value.to_owned(),
+            "Description" => sink.description = value.to_owned(),
+            "Mute" => sink.muted = value == "yes",
+            "Volume" => {
+                if let Some(volume) = parse_volume(value) {
+                    sink.volume = volume;
+                }
+            }
+            _ => {}
+        }
+    }
+    sinks
+}
+
+/// Shows the volume of PulseAudio's default sink, and the sink's name.
+///
+/// This widget shows the description of PulseAudio's default sink (e.g.
+/// `Built-in Audio Analog Stereo`) and its volume, or '`M`' if it is muted.
+///
+/// Clicking on the widget toggles mute, and scrolling over it raises or
+/// lowers the volume. Middle- or right-clicking switches to the next output
+/// (e.g. from speakers to headphones), moving any playing streams across too.
+///
+/// By default, every sink is switched between. [`with_sink()`] limits this to
+/// the given sinks, in order, and shows a short name for each (e.g. `Phones
+/// 60%`) rather than its full description.
+///
+/// It can also show each application which is playing something, with its
+/// own volume (e.g. `Firefox 80%`), for as long as it is playing. See
+/// [`with_streams()`].
+///
+/// The widget doesn't speak PulseAudio's native protocol. Instead, it runs
+/// `pactl subscribe`, so that it is told about changes to the volume, mute
+/// status and default sink as soon as they happen rather than polling, and
+/// runs `pactl` again to list the sinks after each burst of changes. It
+/// requires `pactl` to be installed, which is also provided by PipeWire's
+/// PulseAudio compatibility.
+///
+/// This widget is only available with the `pulse-widget` feature.
+///
+/// [`with_sink()`]: #method.with_sink
+/// [`with_streams()`]: #method.with_streams
+pub struct PulseVolume {
+    handle: Handle,
+    timer: Timer,
+    attr: Attributes,
+    // (name, short name) pairs.
+    sinks: Vec<(String, String)>,
+    show_streams: bool,
+}
+
+impl PulseVolume {
+    /// Creates a new PulseVolume widget.
+    ///
+    /// Creates a new `PulseVolume` widget, whose text will be displayed with
+    /// the given [`Attributes`].
+    ///
+    /// The [`Cnx`] instance is borrowed during construction in order to get
+    /// access to handles of its event loop. However, it is not borrowed for the
+    /// lifetime of the widget. See the [`cnx_add_widget!()`] for more discussion
+    /// about the lifetime of the borrow.
+    ///
+    /// [`Attributes`]: ../text/struct.Attributes.html
+    /// [`Cnx`]: ../struct.Cnx.html
+    /// [`cnx_add_widget!()`]: ../macro.cnx_add_widget.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #[macro_use]
+    /// # extern crate cnx;
+    /// #
+    /// # use cnx::*;
+    /// # use cnx::text::*;
+    /// # use cnx::widgets::*;
+    /// #
+    /// # fn run() -> ::cnx::Result<()> {
+    /// let attr = Attributes {
+    ///     font: Font::new("SourceCodePro 21"),
+    ///     fg_color: Color::white(),
+    ///     bg_color: None,
+    ///     padding: Padding::new(8.0, 8.0, 0.0, 0.0),
+    ///     border: None,
+    /// };
+    ///
+    /// let mut cnx = Cnx::new(Position::Top)?;
+    /// cnx_add_widget!(cnx, PulseVolume::new(&cnx, attr.clone()));
+    /// # Ok(())
+    /// # }
+    /// # fn main() { run().unwrap(); }
+    /// ```
+    pub fn new(cnx: &Cnx, attr: Attributes) -> PulseVolume {
+        PulseVolume {
+            handle: cnx.handle(),
+            timer: cnx.timer(),
+            attr,
+            sinks: Vec::new(),
+            show_streams: false,
+        }
+    }
+
+    /// Switches between the sink called `name` (as listed by `pactl list
+    /// short sinks`), and any others given, showing it as `short_name`.
+    pub fn with_sink<S, T>(mut self, name: S, short_name: T) -> PulseVolume
+    where
+        S: Into<String>,
+        T: Into<String>,
+    {
+        self.sinks.push((name.into(), short_name.into()));
+        self
+    }
+
+    /// Also shows each playback stream's application and volume, e.g.
+    /// `Firefox 80%`, after the sink, while there are any.
+    pub fn with_streams(mut self) -> PulseVolume {
+        self.show_streams = true;
+        self
+    }
+
+    fn text(&self, text: String) -> Text {
+        Text::new(self.attr.clone(), text)
+    }
+
+    fn pactl_output(&self, args: &[&str]) -> Box<dyn Future<Item = String, Error = Error>> {
+        pactl(&self.handle, &self.timer, args)
+    }
+
+    /// Lists the sinks (and streams) again, returning the texts to show.
+    fn on_change(self: Rc<Self>) -> Box<dyn Future<Item = Vec<Text>, Error = Error>> {
+        let inputs: Box<dyn Future<Item = _, Error = _>> = if self.show_streams {
+            Box::new(self.pactl_output(&["list", "sink-inputs"]).map(Some))
+        } else {
+            Box::new(future::ok(None))
+        };
+        let listed = self
+            .pactl_output(&["info"])
+            .join3(self.pactl_output(&["list", "sinks"]), inputs);
+        Box::new(
+            listed.and_then(move |(info, sinks, inputs)| {
+                self.render(&info, &sinks, inputs.as_deref())
+            }),
+        )
+    }
+
+    /// Renders the output of `pactl info`, `pactl list sinks` and, if the
+    /// streams are shown, `pactl list sink-inputs`.
+    fn render(&self, info: &str, sinks: &str, inputs: Option<&str>) -> Result<Vec<Text>> {
+        let default = parse_default_sink(info)
+            .ok_or_else(|| format_err!("No default sink in `pactl info` output"))?;
+        let sinks = parse_sinks(sinks);
+        let sink = match sinks.iter().find(|sink| sink.name == default) {
+            Some(sink) => sink,
+            // The default sink may have just been removed.
+            None => return Ok(Vec::new()),
+        };
+
+        let name = self
+            .sinks
+            .iter()
+            .find(|(name, _)| *name == sink.name)
+            .map_or(&sink.description, |(_, short_name)| short_name);
+        let mut texts = vec![self.text(format!(
+            "{} {}",
+            name,
+            format_volume(sink.muted, sink.volume)
+        ))];
+
+        if let Some(inputs) = inputs {
+            let streams = parse_playback_streams(inputs);
+            texts.extend(streams.into_iter().map(|stream| {
+                self.text(format!(
+                    "{} {}",
+                    stream.application,
+                    format_volume(stream.muted, stream.volume)
+                ))
+            }));
+        }
+        Ok(texts)
+    }
+}
+
+impl Widget for PulseVolume {
+    fn click_handler(&mut self) -> Option<ClickHandler> {
+        let sinks = self
+            .sinks
+            .iter()
+            .map(|(name, _)| name.clone())
+            .collect::<Vec<_>>();
+        let handle = self.handle.clone();
+        let timer = self.timer.clone();
+        Some(Box::new(move |click: Click| {
+            let changed = match click.button {
+                2 | 3 => cycle_default_sink(&handle, &timer, &sinks),
+                button => {
+                    let args: &[&str] = match button {
+                        1 => &["set-sink-mute", "@DEFAULT_SINK@", "toggle"],
+                        4 => &["set-sink-volume", "@DEFAULT_SINK@", "+5%"],
+                        5 => &["set-sink-volume", "@DEFAULT_SINK@", "-5%"],
+                        _ => return Ok(()),
+                    };
+                    Box::new(pactl(&handle, &timer, args).map(|_| ()))
+                }
+            };
+            // The widget updates once PulseAudio reports the change.
+            handle.spawn(changed.map_err(|e| warn!("Failed to change the sink or volume: {}", e)));
+            Ok(())
+        }))
+    }
+
+    fn stream(self: Box<Self>) -> Result<WidgetStream> {
+        let mut subscribe = Command::new("pactl");
+        subscribe.arg("subscribe").env("LC_ALL", "C");
+        let events = process::output_lines(&self.handle, subscribe)?;
+
+        // e.g. `Event 'change' on sink #0`. Changes to the server include
+        // changes to the default sink.
+        let show_streams = self.show_streams;
+        let changes = events.filter(move |line| {
+            line.contains(" on sink #")
+                || line.contains(" on server")
+                || (show_streams && line.contains(" on sink-input #"))
+        });
+        let changes = stream::once::<_, Error>(Ok(())).chain(self.timer.debounced(changes, SETTLE));
+
+        let widget = Rc::new(*self);
+        Ok(Box::new(
+            changes.and_then(move |()| widget.clone().on_change()),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        next_sink, parse_default_sink, parse_playback_streams, parse_short_list, parse_sinks,
+        PlaybackStream, Sink,
+    };
+
+    #[test]
+    fn parses_sinks() {
+        let info = "Server Name: pulseaudio\nDefault Sink: alsa_output.analog-stereo\n";
+        assert_eq!(parse_default_sink(info), Some("alsa_output.analog-stereo"));
+
+        let short =
+            "0\talsa_output.analog-stereo\tmodule-alsa-card.c\ts16le 2ch 44100Hz\tRUNNING\n\
+                     3\tbluez_sink.headphones\tmodule-bluez5-device.c\ts16le 2ch 48000Hz\tIDLE\n";
+        assert_eq!(
+            parse_short_list(short),
+            vec![
+                ("0", "alsa_output.analog-stereo"),
+                ("3", "bluez_sink.headphones")
+            ]
+        );
+
+        let names = ["speakers", "headphones", "hdmi"];
+        assert_eq!(next_sink(&names, Some("headphones")), Some("hdmi"));
+        assert_eq!(next_sink(&names, Some("hdmi")), Some("speakers"));
+        assert_eq!(next_sink(&names, Some("unplugged")), Some("speakers"));
+        assert_eq!(next_sink(&[], Some("hdmi")), None);
+
+        let list = "\
+Sink #0
+\tState: RUNNING
+\tName: alsa_output.analog-stereo
+\tDescription: Built-in Audio Analog Stereo
+\tMute: no
+\tVolume: front-left: 39321 /  60% / -13.31 dB,   front-right: 45875 /  70% / -9.29 dB
+\t        balance 0.10
+Sink #1
+\tName: bluez_sink.headphones
+\tDescription: Headphones
+\tMute: yes
+\tVolume: mono: 65536 / 100% / 0.00 dB
+";
+        assert_eq!(
+            parse_sinks(list),
+            vec![
+                Sink {
+                    name: "alsa_output.analog-stereo".to_owned(),
+                    description: "Built-in Audio Analog Stereo".to_owned(),
+                    muted: false,
+                    volume: 65,
+                },
+                Sink {
+                    name: "bluez_sink.headphones".to_owned(),
+                    description: "Headphones".to_owned(),
+                    muted: true,
+                    volume: 100,
+                },
+            ]
+        );
+
+        let inputs = "\
+Sink Input #42
+\tDriver: protocol-native.c
+\tSink: 0
+\tMute: no
+\tVolume: front-left: 52429 /  80% / -5.81 dB,   front-right: 52429 /  80% / -5.81 dB
+\tProperties:
+\t\tmedia.name = \"Playback\"
+\t\tapplication.name = \"Firefox\"
+Sink Input #43
+\tMute: yes
+\tVolume: mono: 65536 / 100% / 0.00 dB
+\tProperties:
+\t\tmedia.name = \"ALSA Playback\"
+";
+        assert_eq!(
+            parse_playback_streams(inputs),
+            vec![
+                PlaybackStream {
+                    application: "Firefox".to_owned(),
+                    muted: false,
+                    volume: 80,
+                },
+                PlaybackStream {
+                    application: "ALSA Playback".to_owned(),
+                    muted: true,
+                    volume: 100,
+                },
+            ]
+        );
+    }
+}
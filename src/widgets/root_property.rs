@@ -0,0 +1,93 @@
+use futures::{stream, Stream};
+use tokio_core::reactor::Handle;
+
+use super::{Widget, WidgetStream};
+use crate::error::Error;
+use crate::text::{Attributes, Text};
+use crate::{xcb_ext, Cnx, Result};
+
+/// Shows the value of a property of the root window.
+///
+/// This is the simplest way for a script to put something on the bar, with
+/// nothing but `xprop`, e.g. `xprop -root -f _VPN 8u -set _VPN "🔒 work"`.
+/// The widget updates as soon as the property changes, and is hidden entirely
+/// while the property isn't set (e.g. after `xprop -root -remove _VPN`) or is
+/// blank.
+///
+/// Strings (`UTF8_STRING` or `STRING`) are shown as they are, and numbers
+/// (e.g. `CARDINAL`) in decimal. To show several properties in one widget, use
+/// [`WmState`] instead.
+///
+/// [`WmState`]: struct.WmState.html
+pub struct RootProperty {
+    handle: Handle,
+    attr: Attributes,
+    property: String,
+}
+
+impl RootProperty {
+    /// Creates a new Root Property widget.
+    ///
+    /// Creates a new `RootProperty` widget, which shows the value of the root
+    /// window's `property`, e.g. `_VPN`, with the given [`Attributes`].
+    ///
+    /// The [`Cnx`] instance is borrowed during construction in order to get
+    /// access to handles of its event loop. However, it is not borrowed for the
+    /// lifetime of the widget. See the [`cnx_add_widget!()`] for more discussion
+    /// about the lifetime of the borrow.
+    ///
+    /// [`Attributes`]: ../text/struct.Attributes.html
+    /// [`Cnx`]: ../struct.Cnx.html
+    /// [`cnx_add_widget!()`]: ../macro.cnx_add_widget.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use cnx::*;
+    /// # use cnx::text::*;
+    /// # use cnx::widgets::*;
+    /// #
+    /// # fn run() -> ::cnx::Result<()> {
+    /// let attr = Attributes {
+    ///     font: Font::new("SourceCodePro 21"),
+    ///     fg_color: Color::white(),
+    ///     bg_color: None,
+    ///     padding: Padding::new(8.0, 8.0, 0.0, 0.0),
+    ///     border: None,
+    /// };
+    ///
+    /// let mut cnx = Cnx::new(Position::Top)?;
+    /// cnx.add_widget(RootProperty::new(&cnx, attr.clone(), "_VPN"));
+    /// # Ok(())
+    /// # }
+    /// # fn main() { run().unwrap(); }
+    /// ```
+    pub fn new<S: Into<String>>(cnx: &Cnx, attr: Attributes, property: S) -> RootProperty {
+        RootProperty {
+            handle: cnx.handle(),
+            attr,
+            property: property.into(),
+        }
+    }
+
+    fn render(&self, value: String) -> Vec<Text> {
+        if value.trim().is_empty() {
+            return Vec::new();
+        }
+        vec![Text::new(self.attr.clone(), value)]
+    }
+}
+
+impl Widget for RootProperty {
+    fn stream(self: Box<Self>) -> Result<WidgetStream> {
+        let (conn, screen_idx) = xcb_ext::connect()?;
+        let root = xcb_ext::root_window(&conn, screen_idx)?;
+        let atom = xcb_ext::intern_atom(&conn, &self.property)?;
+
+        let initial =
+            stream::once::<_, Error>(Ok(self.render(xcb_ext::property_text(&conn, root, atom))));
+        let changes = xcb_ext::watch_properties(&self.handle, conn.clone(), root, vec![atom])?
+            .map(move |_| self.render(xcb_ext::property_text(&conn, root, atom)));
+        Ok(Box::new(initial.chain(changes)))
+    }
+}
@@ -0,0 +1,129 @@
+use std::fs;
+use std::time::Duration;
+
+use log::debug;
+
+use crate::error::ResultExt;
+use crate::parse::proc;
+use crate::text::{Attributes, Text};
+use crate::timer::Timer;
+use crate::{Cnx, Result};
+
+/// Process names of common X11 screen recorders and screencasting tools.
+const DEFAULT_RECORDERS: &[&str] = &[
+    "ffmpeg",
+    "obs",
+    "simplescreenrec",
+    "recordmydesktop",
+    "peek",
+    "kazam",
+    "vokoscreen",
+    "vokoscreenNG",
+    "byzanz-record",
+    "wf-recorder",
+];
+
+/// Returns the names of any running processes which are in `recorders`.
+///
+/// Only the `comm` of each process is compared, which the kernel truncates to
+/// 15 characters.
+fn running_recorders(recorders: &[String]) -> Result<Vec<String>> {
+    let mut running = fs::read_dir("/proc")
+        .context("Failed to list /proc")?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| proc::pid(&entry.file_name().to_string_lossy()).is_some())
+        // Processes can exit while we're iterating, so ignore any we can't read.
+        .filter_map(|entry| fs::read_to_string(entry.path().join("comm")).ok())
+        .map(|comm| comm.trim().to_owned())
+        .filter(|comm| recorders.iter().any(|r| r == comm))
+        .collect::<Vec<_>>();
+    running.sort();
+    running.dedup();
+    Ok(running)
+}
+
+/// Shows an indicator while the screen is being recorded.
+///
+/// This widget periodically looks for running screen recorders (such as
+/// `ffmpeg`, `obs` or `simplescreenrecorder`) and shows a prominent recording
+/// dot while any of them are running. When nothing is recording, the widget is
+/// hidden entirely.
+///
+/// X11 recorders can capture the screen without any co-operation from the
+/// X server, so looking for their processes is the most reliable way of
+/// noticing them.
+pub struct ScreenCapture {
+    timer: Timer,
+    update_interval: Duration,
+    attr: Attributes,
+    recorders: Vec<String>,
+}
+
+impl ScreenCapture {
+    /// Creates a new Screen Capture widget.
+    ///
+    /// Creates a new `ScreenCapture` widget, whose recording dot will be
+    /// displayed with the given [`Attributes`]. Typically you'll want to use a
+    /// bright `fg_color`, so that the dot is hard to miss.
+    ///
+    /// The [`Cnx`] instance is borrowed during construction in order to get
+    /// access to handles of its event loop. However, it is not borrowed for the
+    /// lifetime of the widget. See the [`cnx_add_widget!()`] for more discussion
+    /// about the lifetime of the borrow.
+    ///
+    /// [`Attributes`]: ../text/struct.Attributes.html
+    /// [`Cnx`]: ../struct.Cnx.html
+    /// [`cnx_add_widget!()`]: ../macro.cnx_add_widget.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use cnx::*;
+    /// # use cnx::text::*;
+    /// # use cnx::widgets::*;
+    /// #
+    /// # fn run() -> ::cnx::Result<()> {
+    /// let attr = Attributes {
+    ///     font: Font::new("SourceCodePro 21"),
+    ///     fg_color: "ff0000".parse().unwrap(),
+    ///     bg_color: None,
+    ///     padding: Padding::new(8.0, 8.0, 0.0, 0.0),
+    ///     border: None,
+    /// };
+    ///
+    /// let mut cnx = Cnx::new(Position::Top)?;
+    /// cnx.add_widget(ScreenCapture::new(&cnx, attr.clone()));
+    /// # Ok(())
+    /// # }
+    /// # fn main() { run().unwrap(); }
+    /// ```
+    pub fn new(cnx: &Cnx, attr: Attributes) -> ScreenCapture {
+        ScreenCapture {
+            timer: cnx.timer(),
+            update_interval: Duration::from_secs(5),
+            attr,
+            recorders: DEFAULT_RECORDERS.iter().map(|&r| r.to_owned()).collect(),
+        }
+    }
+
+    /// Also treats processes with the given name as screen recorders.
+    ///
+    /// Process names are compared against `/proc/<pid>/comm`, which the kernel
+    /// truncates to 15 characters.
+    pub fn with_recorder<S: Into<String>>(mut self, name: S) -> ScreenCapture {
+        self.recorders.push(name.into());
+        self
+    }
+
+    fn tick(&self) -> Result<Vec<Text>> {
+        let running = running_recorders(&self.recorders)?;
+        if running.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        debug!("Screen is being recorded by: {}", running.join(", "));
+        Ok(vec![Text::new(self.attr.clone(), "●".to_owned())])
+    }
+}
+
+timer_widget!(ScreenCapture, timer, update_interval, tick);
@@ -1,59 +1,116 @@
-use std::collections::HashMap;
-use std::process::Command;
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 
-use failure::ResultExt;
-use lazy_static::lazy_static;
-use regex::Regex;
-use tokio_timer::Timer;
-
-use crate::text::{Attributes, Text};
+use crate::error::ResultExt;
+use crate::parse::sysfs;
+use crate::text::{Attributes, Color, Text, Threshold};
+use crate::timer::Timer;
 use crate::{Cnx, Result};
 
+/// A temperature sensor, as exposed by a hwmon chip.
 #[derive(Debug, PartialEq)]
-struct Value<'a> {
-    temp: &'a str,
-    units: &'a str,
+struct Reading {
+    // The name of the chip's driver, e.g. `coretemp`.
+    chip: String,
+    // The sensor's label, e.g. `Core 0`, or its name (e.g. `temp1`) if it has
+    // no label.
+    label: String,
+    temp: f64,
+    // The temperatures the chip considers high and critical, if it knows.
+    high: Option<f64>,
+    critical: Option<f64>,
 }
 
-/// Parses the output of the `sensors` executable from `lm_sensors`.
-fn parse_sensors_output(output: &str) -> Result<HashMap<&str, Value<'_>>> {
-    lazy_static! {
-        static ref RE: Regex = Regex::new(
-            // Note: we ignore + but capture -
-            r"\n(?P<name>[\w ]+):\s+\+?(?P<temp>-?\d+\.\d+).(?P<units>[C|F])"
-        ).expect("Failed to compile regex for parsing sensors output");
+impl Reading {
+    /// Whether this is the sensor given by `name`, which is either a label
+    /// (e.g. `Core 0`), or a chip and label (e.g. `coretemp/Core 0`).
+    fn is(&self, name: &str) -> bool {
+        match name.find('/') {
+            Some(i) => self.chip == name[..i] && self.label == name[i + 1..],
+            None => self.label == name,
+        }
     }
+}
 
-    let mut map = HashMap::new();
-    for mat in RE.captures_iter(output) {
-        // These .unwraps() are harmless. If we have a match, we have these groups.
-        map.insert(
-            mat.name("name").unwrap().as_str(),
-            Value {
-                temp: mat.name("temp").unwrap().as_str(),
-                units: mat.name("units").unwrap().as_str(),
-            },
-        );
-    }
+fn read_trimmed(path: &Path) -> Option<String> {
+    fs::read_to_string(path)
+        .ok()
+        .map(|contents| contents.trim().to_owned())
+}
+
+/// Reads the temperature in the file at `path`, if there is one.
+fn read_temp(path: &Path) -> Option<f64> {
+    sysfs::millidegrees(&read_trimmed(path)?).ok()
+}
 
-    Ok(map)
+/// Reads every temperature sensor of the hwmon chip in `chip_dir`, e.g.
+/// `/sys/class/hwmon/hwmon2`.
+fn read_chip(chip_dir: &Path) -> Result<Vec<Reading>> {
+    let chip = read_trimmed(&chip_dir.join("name")).unwrap_or_default();
+    let mut inputs = fs::read_dir(chip_dir)
+        .with_context(|_| format!("Failed to list {}", chip_dir.display()))?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            sysfs::temp_input(&name)
+        })
+        .collect::<Vec<_>>();
+    inputs.sort();
+
+    Ok(inputs
+        .into_iter()
+        .filter_map(|n| {
+            let file = |suffix: &str| chip_dir.join(format!("temp{}_{}", n, suffix));
+            Some(Reading {
+                chip: chip.clone(),
+                label: read_trimmed(&file("label")).unwrap_or_else(|| format!("temp{}", n)),
+                // Sensors can fail to read, e.g. while their device sleeps.
+                temp: read_temp(&file("input"))?,
+                high: read_temp(&file("max")),
+                critical: read_temp(&file("crit")),
+            })
+        })
+        .collect())
+}
+
+/// Reads every temperature sensor of every hwmon chip under `hwmon_dir`.
+fn read_hwmon(hwmon_dir: &Path) -> Result<Vec<Reading>> {
+    let mut chips = fs::read_dir(hwmon_dir)
+        .with_context(|_| format!("Failed to list {}", hwmon_dir.display()))?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .collect::<Vec<PathBuf>>();
+    chips.sort();
+    let mut readings = Vec::new();
+    for chip in chips {
+        readings.extend(read_chip(&chip)?);
+    }
+    Ok(readings)
 }
 
 /// Shows the temperature from one or more sensors.
 ///
-/// This widget shows the temperature reported by one or more sensors from the
-/// output of the `sensors` command, which is part of the [`lm_sensors`]
-/// package.
+/// This widget shows the temperature reported by one or more of the
+/// kernel's hwmon sensors, e.g. the CPU's, read from `/sys/class/hwmon/`.
+/// These are the same sensors that the `sensors` command from
+/// [`lm_sensors`] shows, but reading them directly doesn't need any other
+/// programs.
 ///
-/// It expects the `sensors` executable to be available in the `PATH`.
+/// Each temperature is shown in yellow once it reaches the temperature its
+/// chip considers high, and in red once it reaches the critical temperature,
+/// if the chip knows these. Other temperatures and attributes can be used
+/// instead, see [`with_threshold()`].
 ///
 /// [`lm_sensors`]: https://wiki.archlinux.org/index.php/lm_sensors
+/// [`with_threshold()`]: #method.with_threshold
 pub struct Sensors {
     timer: Timer,
     update_interval: Duration,
+    hwmon_dir: PathBuf,
     attr: Attributes,
     sensors: Vec<String>,
+    // The attributes for each level, rather than those of the sensors' chips.
+    threshold: Option<Threshold<f64>>,
 }
 
 impl Sensors {
@@ -62,8 +119,12 @@ impl Sensors {
     /// Creates a new `Sensors` widget, whose text will be displayed with the
     /// given [`Attributes`].
     ///
-    /// A list of sensor names should be passed as the `sensors` argument. (You
-    /// can discover the names by running the `sensors` utility in a terminal).
+    /// A list of sensor names should be passed as the `sensors` argument.
+    /// Each is either a sensor's label (e.g. `Core 0`, as shown by the
+    /// `sensors` utility), or the name of its chip and its label, e.g.
+    /// `coretemp/Package id 0`, to pick between chips with the same labels.
+    /// Sensors without a label are named after their files, e.g.
+    /// `acpitz/temp1`.
     ///
     /// The [`Cnx`] instance is borrowed during construction in order to get
     /// access to handles of its event loop. However, it is not borrowed for the
@@ -90,6 +151,7 @@ impl Sensors {
     ///     fg_color: Color::white(),
     ///     bg_color: None,
     ///     padding: Padding::new(8.0, 8.0, 0.0, 0.0),
+    ///     border: None,
     /// };
     ///
     /// let mut cnx = Cnx::new(Position::Top)?;
@@ -105,32 +167,62 @@ impl Sensors {
         Sensors {
             timer: cnx.timer(),
             update_interval: Duration::from_secs(60),
+            hwmon_dir: PathBuf::from("/sys/class/hwmon"),
             attr,
             sensors: sensors.into_iter().map(Into::into).collect(),
+            threshold: None,
+        }
+    }
+
+    /// Shows each sensor's temperature with the [`Attributes`] of the levels
+    /// of `threshold`, in °C, rather than those of its chip.
+    ///
+    /// [`Attributes`]: ../text/struct.Attributes.html
+    pub fn with_threshold(mut self, threshold: Threshold<f64>) -> Sensors {
+        self.threshold = Some(threshold);
+        self
+    }
+
+    /// The levels of `reading`'s chip, at which it is shown in yellow and
+    /// then red.
+    fn chip_threshold(&self, reading: &Reading) -> Threshold<f64> {
+        let with_color = |color: Color| Attributes {
+            fg_color: color,
+            ..self.attr.clone()
+        };
+        let mut threshold = Threshold::new();
+        if let Some(high) = reading.high {
+            threshold = threshold.warn(high, with_color(Color::yellow()));
+        }
+        if let Some(critical) = reading.critical {
+            threshold = threshold.crit(critical, with_color(Color::red()));
+        }
+        threshold
+    }
+
+    /// The attributes of the text for `reading`.
+    fn attributes(&self, reading: &Reading) -> Attributes {
+        match self.threshold {
+            Some(ref threshold) => threshold.apply(&reading.temp, &self.attr),
+            None => self
+                .chip_threshold(reading)
+                .apply(&reading.temp, &self.attr),
         }
     }
 
     fn tick(&self) -> Result<Vec<Text>> {
-        let output = Command::new("sensors")
-            .output()
-            .context("Failed to run `sensors`")?;
-        let string = String::from_utf8(output.stdout).context("Invalid UTF-8 in sensors output")?;
-        let parsed = parse_sensors_output(&string).context("Failed to parse `sensors` output")?;
-        self.sensors
+        let readings = read_hwmon(&self.hwmon_dir).context("Failed to read hwmon sensors")?;
+        Ok(self
+            .sensors
             .iter()
-            .map(|sensor_name| {
-                let text = parsed
-                    .get::<str>(sensor_name)
-                    .map_or("?".to_owned(), |&Value { temp, units }| {
-                        format!("{}°{}", temp, units)
-                    });
-                Ok(Text {
-                    attr: self.attr.clone(),
-                    text,
-                    stretch: false,
-                })
+            .map(|sensor| {
+                let (attr, text) = match readings.iter().find(|reading| reading.is(sensor)) {
+                    Some(reading) => (self.attributes(reading), format!("{:.1}°C", reading.temp)),
+                    None => (self.attr.clone(), "?".to_owned()),
+                };
+                Text::new(attr, text)
             })
-            .collect()
+            .collect())
     }
 }
 
@@ -138,53 +230,21 @@ timer_widget!(Sensors, timer, update_interval, tick);
 
 #[cfg(test)]
 mod test {
-    use super::{parse_sensors_output, Value};
+    use super::Reading;
 
     #[test]
     fn works() {
-        let output = r#"applesmc-isa-0300
-Adapter: ISA adapter
-Right Side  :    0 RPM  (min = 2000 RPM, max = 6199 RPM)
-Ts1S:         -127.0 C
-Ts2S:          +34.0 F
-
-coretemp-isa-0000
-Adapter: ISA adapter
-Package id 0:  +58.0 C  (high = +105.0 C, crit = +105.0 C)
-Core 0:        +53.0 C  (high = +105.0 C, crit = +105.0 C)
-Core 1:        +58.0 C  (high = +105.0 C, crit = +105.0 C)
-"#;
-
-        let parsed = parse_sensors_output(output).unwrap();
-        assert_eq!(
-            parsed.get("Core 0"),
-            Some(&Value {
-                temp: "53.0",
-                units: "C",
-            })
-        );
-        assert_eq!(
-            parsed.get("Core 1"),
-            Some(&Value {
-                temp: "58.0",
-                units: "C",
-            })
-        );
-        assert_eq!(
-            parsed.get("Ts1S"),
-            Some(&Value {
-                temp: "-127.0",
-                units: "C",
-            })
-        );
-        assert_eq!(
-            parsed.get("Ts2S"),
-            Some(&Value {
-                temp: "34.0",
-                units: "F",
-            })
-        );
-
-        assert_eq!(parsed.len(), 5);
+        let reading = Reading {
+            chip: "coretemp".to_owned(),
+            label: "Core 0".to_owned(),
+            temp: 53.0,
+            high: Some(80.0),
+            critical: Some(100.0),
+        };
+        assert!(reading.is("Core 0"));
+        assert!(reading.is("coretemp/Core 0"));
+        assert!(!reading.is("k10temp/Core 0"));
+        assert!(!reading.is("Core 1"));
+        assert!(!reading.is("coretemp"));
     }
 }
@@ -0,0 +1,186 @@
+use std::cell::RefCell;
+use std::time::Duration;
+
+use futures::unsync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use futures::{stream, Future, Stream};
+use log::warn;
+use tokio_core::reactor::Handle;
+
+use super::{Click, ClickHandler, Refresh};
+use crate::busctl::{Bus, Busctl};
+use crate::error::{format_err, Error, ResultExt};
+use crate::json::{self, Value};
+use crate::text::{Attributes, Text};
+use crate::timer::Timer;
+use crate::{Cnx, Result};
+
+const LOGIN1: &str = "org.freedesktop.login1";
+// logind resolves `auto` to the session of whoever asks.
+const SESSION_PATH: &str = "/org/freedesktop/login1/session/auto";
+const SESSION_INTERFACE: &str = "org.freedesktop.login1.Session";
+
+/// Parses the JSON output of getting a session's `LockedHint` property with
+/// `busctl`, which is a single boolean.
+fn parse_locked_hint(output: &str) -> Result<bool> {
+    json::parse(output)?
+        .get("data")
+        .and_then(Value::as_bool)
+        .ok_or_else(|| format_err!("Unexpected format of LockedHint property"))
+}
+
+/// Shows whether the session is locked, and locks it when clicked.
+///
+/// This widget periodically asks [`logind`] whether our session is marked
+/// as locked (its `LockedHint`, which screen lockers like `xss-lock` set),
+/// and shows `🔓` or `🔒`, or the labels given with [`with_labels()`].
+///
+/// Clicking on the widget asks `logind` to lock the session (like `loginctl
+/// lock-session`), which asks the session's screen locker to lock it, giving
+/// a lock button on the bar.
+///
+/// It expects the `busctl` executable (part of `systemd`) to be available in
+/// the `PATH`.
+///
+/// [`logind`]: https://www.freedesktop.org/software/systemd/man/org.freedesktop.login1.html
+/// [`with_labels()`]: #method.with_labels
+pub struct SessionLock {
+    timer: Timer,
+    update_interval: Duration,
+    handle: Handle,
+    attr: Attributes,
+    busctl: Busctl,
+    // The texts for the unlocked and locked session.
+    labels: (String, String),
+    // Told whenever the session is locked, to update straight away.
+    locked: UnboundedSender<()>,
+    locks: RefCell<Option<UnboundedReceiver<()>>>,
+}
+
+impl SessionLock {
+    /// Creates a new Session Lock widget.
+    ///
+    /// Creates a new `SessionLock` widget, whose text will be displayed with
+    /// the given [`Attributes`].
+    ///
+    /// The [`Cnx`] instance is borrowed during construction in order to get
+    /// access to handles of its event loop. However, it is not borrowed for the
+    /// lifetime of the widget. See the [`cnx_add_widget!()`] for more discussion
+    /// about the lifetime of the borrow.
+    ///
+    /// [`Attributes`]: ../text/struct.Attributes.html
+    /// [`Cnx`]: ../struct.Cnx.html
+    /// [`cnx_add_widget!()`]: ../macro.cnx_add_widget.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use cnx::*;
+    /// # use cnx::text::*;
+    /// # use cnx::widgets::*;
+    /// #
+    /// # fn run() -> ::cnx::Result<()> {
+    /// let attr = Attributes {
+    ///     font: Font::new("SourceCodePro 21"),
+    ///     fg_color: Color::white(),
+    ///     bg_color: None,
+    ///     padding: Padding::new(8.0, 8.0, 0.0, 0.0),
+    ///     border: None,
+    /// };
+    ///
+    /// let mut cnx = Cnx::new(Position::Top)?;
+    /// cnx.add_widget(SessionLock::new(&cnx, attr.clone()).with_labels("lock", "locked"));
+    /// # Ok(())
+    /// # }
+    /// # fn main() { run().unwrap(); }
+    /// ```
+    pub fn new(cnx: &Cnx, attr: Attributes) -> SessionLock {
+        let (locked, locks) = mpsc::unbounded();
+        SessionLock {
+            timer: cnx.timer(),
+            update_interval: Duration::from_secs(5),
+            handle: cnx.handle(),
+            attr,
+            busctl: Busctl::new(cnx, Bus::System),
+            labels: ("🔓".to_owned(), "🔒".to_owned()),
+            locked,
+            locks: RefCell::new(Some(locks)),
+        }
+    }
+
+    /// Shows `unlocked` or `locked` instead. Empty labels show nothing.
+    pub fn with_labels<U: Into<String>, L: Into<String>>(
+        mut self,
+        unlocked: U,
+        locked: L,
+    ) -> SessionLock {
+        self.labels = (unlocked.into(), locked.into());
+        self
+    }
+
+    fn lock_events(&self) -> Result<Refresh> {
+        match self.locks.borrow_mut().take() {
+            Some(locks) => Ok(Box::new(
+                locks.map_err(|()| format_err!("SessionLock channel closed")),
+            )),
+            None => Ok(Box::new(stream::empty())),
+        }
+    }
+
+    fn tick(&self) -> Box<dyn Future<Item = Vec<Text>, Error = Error>> {
+        let output = self.busctl.call(&[
+            "get-property",
+            LOGIN1,
+            SESSION_PATH,
+            SESSION_INTERFACE,
+            "LockedHint",
+        ]);
+        let attr = self.attr.clone();
+        let labels = self.labels.clone();
+        Box::new(output.and_then(move |output| {
+            let locked =
+                parse_locked_hint(&output).context("Failed to parse session's LockedHint")?;
+            let label = if locked { labels.1 } else { labels.0 };
+            if label.is_empty() {
+                return Ok(Vec::new());
+            }
+            Ok(vec![Text::new(attr, label)])
+        }))
+    }
+
+    /// Returns a `ClickHandler` which locks the session.
+    fn lock_click_handler(&mut self) -> Option<ClickHandler> {
+        let handle = self.handle.clone();
+        let busctl = self.busctl.clone();
+        let locked = self.locked.clone();
+        Some(Box::new(move |click: Click| {
+            if click.button != 1 {
+                return Ok(());
+            }
+            let lock = busctl.call(&["call", LOGIN1, SESSION_PATH, SESSION_INTERFACE, "Lock"]);
+            let locked = locked.clone();
+            handle.spawn(
+                lock.map(move |_| {
+                    // The widget may have stopped, in which case there's
+                    // nothing to update.
+                    let _ = locked.unbounded_send(());
+                })
+                .map_err(|e| warn!("Failed to lock the session: {}", e)),
+            );
+            Ok(())
+        }))
+    }
+}
+
+timer_widget!(SessionLock, timer, update_interval, tick; lock_events; lock_click_handler);
+
+#[cfg(test)]
+mod test {
+    use super::parse_locked_hint;
+
+    #[test]
+    fn works() {
+        assert!(parse_locked_hint(r#"{"type":"b","data":true}"#).unwrap());
+        assert!(!parse_locked_hint(r#"{"type":"b","data":false}"#).unwrap());
+        assert!(parse_locked_hint(r#"{"type":"s","data":"yes"}"#).is_err());
+    }
+}
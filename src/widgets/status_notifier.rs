@@ -0,0 +1,412 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Duration;
+
+use futures::{future, stream, Future, Stream};
+use log::warn;
+use tokio_core::reactor::Handle;
+
+use super::{Click, ClickHandler, Refresh};
+use crate::busctl::{self, Bus, Busctl};
+use crate::error::{format_err, Error, ResultExt};
+use crate::json::{self, Value};
+use crate::parse::ewmh as ewmh_parse;
+use crate::text::{Attributes, Graphic, Icon, Text};
+use crate::timer::Timer;
+use crate::{xcb_ext, Cnx, Result};
+
+const WATCHER: &str = "org.kde.StatusNotifierWatcher";
+const WATCHER_PATH: &str = "/StatusNotifierWatcher";
+const ITEM_INTERFACE: &str = "org.kde.StatusNotifierItem";
+// Where an item is, if it registers with only its bus name.
+const DEFAULT_ITEM_PATH: &str = "/StatusNotifierItem";
+// The size of icon to prefer, of those an item has.
+const ICON_SIZE: u32 = 32;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Status {
+    Passive,
+    Active,
+    NeedsAttention,
+}
+
+#[derive(Debug, PartialEq)]
+struct Item {
+    status: Status,
+    title: Option<String>,
+    icon: Option<Icon>,
+    // Whether the item only has a menu, so that clicks should show it.
+    is_menu: bool,
+}
+
+/// Splits one of the watcher's `RegisteredStatusNotifierItems`, e.g.
+/// `:1.52/org/ayatana/NotificationItem/nm_applet`, into the item's bus name
+/// and object path.
+fn parse_item_address(item: &str) -> (String, String) {
+    match item.find('/') {
+        Some(i) => (item[..i].to_owned(), item[i..].to_owned()),
+        None => (item.to_owned(), DEFAULT_ITEM_PATH.to_owned()),
+    }
+}
+
+/// Parses the JSON output of getting the watcher's
+/// `RegisteredStatusNotifierItems` property, returning each item's bus name
+/// and object path.
+fn parse_registered_items(output: &str) -> Result<Vec<(String, String)>> {
+    let items = json::parse(output)?;
+    let items = items
+        .get("data")
+        .and_then(Value::as_array)
+        .ok_or_else(|| format_err!("Unexpected format of RegisteredStatusNotifierItems"))?;
+    Ok(items
+        .iter()
+        .filter_map(Value::as_str)
+        .map(parse_item_address)
+        .collect())
+}
+
+/// Converts an `IconPixmap` (an array of each size of icon's width, height
+/// and ARGB bytes in network byte order) into the layout of `_NET_WM_ICON`,
+/// and picks the best size from it. Sizes whose bytes don't add up are
+/// skipped.
+fn parse_pixmaps(pixmaps: &[Value]) -> Option<Icon> {
+    let mut data = Vec::new();
+    for pixmap in pixmaps {
+        let dimension = |i| pixmap.at(i).and_then(Value::as_f64).map(|n| n as u32);
+        let (width, height) = match (dimension(0), dimension(1)) {
+            (Some(width), Some(height)) => (width, height),
+            _ => continue,
+        };
+        let bytes = match pixmap.at(2).and_then(Value::as_array) {
+            Some(bytes) => bytes
+                .iter()
+                .map(|byte| byte.as_f64().map(|n| n as u8))
+                .collect::<Option<Vec<_>>>(),
+            None => continue,
+        };
+        match bytes {
+            Some(ref bytes) if bytes.len() as u64 == u64::from(width) * u64::from(height) * 4 => {
+                data.extend([width, height]);
+                data.extend(
+                    bytes
+                        .chunks(4)
+                        .map(|pixel| u32::from_be_bytes([pixel[0], pixel[1], pixel[2], pixel[3]])),
+                );
+            }
+            _ => continue,
+        }
+    }
+    let (width, height, pixels) = ewmh_parse::pick_icon(&data, ICON_SIZE)?;
+    Icon::new(width, height, pixels.to_vec())
+}
+
+/// Parses the JSON output of calling `GetAll` on an item's properties.
+fn parse_item(output: &str) -> Result<Item> {
+    let reply = json::parse(output)?;
+    let properties = reply.get("data").and_then(|data| data.at(0));
+    // Each property is a variant, i.e. an object with a type and data.
+    let property = |key| {
+        properties
+            .and_then(|p| p.get(key))
+            .and_then(|v| v.get("data"))
+    };
+    let status = match property("Status").and_then(Value::as_str) {
+        Some("Passive") => Status::Passive,
+        Some("Active") => Status::Active,
+        Some("NeedsAttention") => Status::NeedsAttention,
+        status => return Err(format_err!("Unexpected item Status: {:?}", status)),
+    };
+    let string = |key| {
+        property(key)
+            .and_then(Value::as_str)
+            .filter(|s| !s.is_empty())
+            .map(ToOwned::to_owned)
+    };
+    let title = string("Title").or_else(|| string("Id"));
+    let pixmap = |key| {
+        property(key)
+            .and_then(Value::as_array)
+            .and_then(parse_pixmaps)
+    };
+    let icon = match status {
+        Status::NeedsAttention => pixmap("AttentionIconPixmap").or_else(|| pixmap("IconPixmap")),
+        _ => pixmap("IconPixmap"),
+    };
+    let is_menu = property("ItemIsMenu")
+        .and_then(Value::as_bool)
+        .unwrap_or(false);
+    Ok(Item {
+        status,
+        title,
+        icon,
+        is_menu,
+    })
+}
+
+/// Returns where the pointer is on the screen, for items to place their
+/// menus and windows by.
+fn pointer_position() -> Result<(i16, i16)> {
+    let (conn, screen_idx) = xcb_ext::connect()?;
+    let root = xcb_ext::root_window(&conn, screen_idx)?;
+    let pointer = xcb::query_pointer(&conn, root)
+        .get_reply()
+        .context("Failed to query the pointer")?;
+    Ok((pointer.root_x(), pointer.root_y()))
+}
+
+/// Shows the icons of applications' StatusNotifierItems (also known as
+/// AppIndicators), e.g. `nm-applet`'s, Telegram's or Discord's.
+///
+/// This widget shows each item registered with the session's
+/// [`StatusNotifierWatcher`], in the order they registered, as its icon or, if
+/// it only names an icon in a theme, its title. Items which say they are
+/// passive are hidden, and items which need attention (e.g. for an unread
+/// message) are shown with the `attention_attr` [`Attributes`].
+///
+/// Clicking on an item activates it, usually showing or hiding its window.
+/// Middle-clicking asks it for its secondary action, and right-clicking asks
+/// it to show its context menu. Items which export their menu with
+/// `com.canonical.dbusmenu`, rather than showing it themselves, don't have
+/// their menu shown, as that would need a D-Bus connection of our own.
+///
+/// For the same reason, Cnx can't be the `StatusNotifierWatcher` itself, so
+/// one must be running, e.g. KDE's or the standalone
+/// `status-notifier-watcher`, nor can it register as a
+/// `StatusNotifierHost`. Applications which can't find a watcher, or which
+/// wait for a host to register with it, fall back to the XEmbed protocol,
+/// which the [`Tray`] widget supports.
+///
+/// The widget updates whenever an item registers, unregisters or changes,
+/// and every minute in case a change is missed. It expects the `busctl`
+/// executable (part of `systemd`) to be available in the `PATH`.
+///
+/// [`StatusNotifierWatcher`]: https://www.freedesktop.org/wiki/Specifications/StatusNotifierItem/
+/// [`Attributes`]: ../text/struct.Attributes.html
+/// [`Tray`]: struct.Tray.html
+pub struct StatusNotifier {
+    timer: Timer,
+    update_interval: Duration,
+    handle: Handle,
+    attr: Attributes,
+    attention_attr: Attributes,
+    busctl: Busctl,
+    // The bus name and object path of each item being shown, and whether it
+    // only has a menu, in the order of their texts, which are sent clicks.
+    shown: Rc<RefCell<Vec<(String, String, bool)>>>,
+}
+
+impl StatusNotifier {
+    /// Creates a new Status Notifier widget.
+    ///
+    /// Creates a new `StatusNotifier` widget, whose items will be displayed
+    /// with the given [`Attributes`], or the `attention_attr` while they need
+    /// attention. Each item is shown as a separate text.
+    ///
+    /// The [`Cnx`] instance is borrowed during construction in order to get
+    /// access to handles of its event loop. However, it is not borrowed for the
+    /// lifetime of the widget. See the [`cnx_add_widget!()`] for more discussion
+    /// about the lifetime of the borrow.
+    ///
+    /// [`Attributes`]: ../text/struct.Attributes.html
+    /// [`Cnx`]: ../struct.Cnx.html
+    /// [`cnx_add_widget!()`]: ../macro.cnx_add_widget.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use cnx::*;
+    /// # use cnx::text::*;
+    /// # use cnx::widgets::*;
+    /// #
+    /// # fn run() -> ::cnx::Result<()> {
+    /// let attr = Attributes {
+    ///     font: Font::new("SourceCodePro 21"),
+    ///     fg_color: "ffffff".parse().unwrap(),
+    ///     bg_color: None,
+    ///     padding: Padding::new(8.0, 8.0, 0.0, 0.0),
+    ///     border: None,
+    /// };
+    /// let mut attention_attr = attr.clone();
+    /// attention_attr.bg_color = Some(Color::red());
+    ///
+    /// let mut cnx = Cnx::new(Position::Top)?;
+    /// cnx.add_widget(StatusNotifier::new(&cnx, attr, attention_attr));
+    /// # Ok(())
+    /// # }
+    /// # fn main() { run().unwrap(); }
+    /// ```
+    pub fn new(cnx: &Cnx, attr: Attributes, attention_attr: Attributes) -> StatusNotifier {
+        StatusNotifier {
+            timer: cnx.timer(),
+            update_interval: Duration::from_secs(60),
+            handle: cnx.handle(),
+            attr,
+            attention_attr,
+            busctl: Busctl::new(cnx, Bus::User),
+            shown: Rc::new(RefCell::new(Vec::new())),
+        }
+    }
+
+    /// Returns a stream which yields whenever an item registers, unregisters
+    /// or changes, by watching the bus with `busctl monitor`.
+    fn item_events(&self) -> Result<Refresh> {
+        let watcher = format!("type='signal',interface='{}'", WATCHER);
+        let items = format!("type='signal',interface='{}'", ITEM_INTERFACE);
+        let events = busctl::monitor(&self.handle, Bus::User, &watcher).and_then(|watcher| {
+            let items = busctl::monitor(&self.handle, Bus::User, &items)?;
+            Ok(watcher.select(items))
+        });
+        match events {
+            Ok(lines) => Ok(Box::new(lines.map(|_| ()))),
+            Err(e) => {
+                warn!(
+                    "StatusNotifier won't notice changes until its next update: {}",
+                    e
+                );
+                Ok(Box::new(stream::empty()))
+            }
+        }
+    }
+
+    fn tick(&self) -> Box<dyn Future<Item = Vec<Text>, Error = Error>> {
+        let registered = self.busctl.call(&[
+            "get-property",
+            WATCHER,
+            WATCHER_PATH,
+            WATCHER,
+            "RegisteredStatusNotifierItems",
+        ]);
+        let busctl = self.busctl.clone();
+        let items = registered
+            .and_then(|output| {
+                parse_registered_items(&output).context(
+                    "Failed to list StatusNotifierItems (is a StatusNotifierWatcher running?)",
+                )
+            })
+            .and_then(move |items| {
+                future::join_all(items.into_iter().map(move |(name, path)| {
+                    let output = busctl.call(&[
+                        "call",
+                        &name,
+                        &path,
+                        "org.freedesktop.DBus.Properties",
+                        "GetAll",
+                        "s",
+                        ITEM_INTERFACE,
+                    ]);
+                    // Items may go away at any time, before the watcher
+                    // notices.
+                    output.then(move |output| {
+                        let item = output.and_then(|output| parse_item(&output));
+                        Ok(item.ok().map(|item| (name, path, item)))
+                    })
+                }))
+            });
+
+        let attr = self.attr.clone();
+        let attention_attr = self.attention_attr.clone();
+        let shown = self.shown.clone();
+        Box::new(items.map(move |items| {
+            let mut addresses = Vec::new();
+            let mut texts = Vec::new();
+            for (name, path, item) in items.into_iter().flatten() {
+                let attr = match item.status {
+                    Status::Passive => continue,
+                    Status::Active => attr.clone(),
+                    Status::NeedsAttention => attention_attr.clone(),
+                };
+                texts.push(match item.icon {
+                    Some(icon) => Text::new(attr, String::new()).with_graphic(Graphic::Icon(icon)),
+                    None => Text::new(attr, item.title.unwrap_or_else(|| name.clone())),
+                });
+                addresses.push((name, path, item.is_menu));
+            }
+            *shown.borrow_mut() = addresses;
+            texts
+        }))
+    }
+
+    /// Returns a `ClickHandler` which passes clicks on to the item which was
+    /// clicked.
+    fn click_item(&mut self) -> Option<ClickHandler> {
+        let busctl = self.busctl.clone();
+        let shown = self.shown.clone();
+        Some(Box::new(move |click: Click| {
+            let shown = shown.borrow();
+            let (name, path, is_menu) = match shown.get(click.text) {
+                Some(item) => item,
+                None => return Ok(()),
+            };
+            let method = match click.button {
+                1 if *is_menu => "ContextMenu",
+                1 => "Activate",
+                2 => "SecondaryActivate",
+                3 => "ContextMenu",
+                _ => return Ok(()),
+            };
+            let (x, y) = pointer_position().unwrap_or_else(|e| {
+                warn!("Failed to find where StatusNotifier was clicked: {}", e);
+                (0, 0)
+            });
+            let (x, y) = (x.to_string(), y.to_string());
+            busctl.spawn(&["call", name, path, ITEM_INTERFACE, method, "ii", &x, &y]);
+            Ok(())
+        }))
+    }
+}
+
+timer_widget!(StatusNotifier, timer, update_interval, tick; item_events; click_item);
+
+#[cfg(test)]
+mod test {
+    use super::{parse_item, parse_registered_items, Item, Status};
+    use crate::text::Icon;
+
+    #[test]
+    fn works() {
+        let registered = r#"{"type":"as","data":[":1.52/org/ayatana/NotificationItem/nm_applet","org.kde.StatusNotifierItem-1234-1"]}"#;
+        assert_eq!(
+            parse_registered_items(registered).unwrap(),
+            vec![
+                (
+                    ":1.52".to_owned(),
+                    "/org/ayatana/NotificationItem/nm_applet".to_owned()
+                ),
+                (
+                    "org.kde.StatusNotifierItem-1234-1".to_owned(),
+                    "/StatusNotifierItem".to_owned()
+                ),
+            ]
+        );
+
+        // A 1x1 and a 2x2 icon, of which the larger is picked, and a broken
+        // one which is skipped.
+        let properties = concat!(
+            r#"{"type":"a{sv}","data":[{"Id":{"type":"s","data":"telegram"},"#,
+            r#""Title":{"type":"s","data":""},"Status":{"type":"s","data":"NeedsAttention"},"#,
+            r#""IconPixmap":{"type":"a(iiay)","data":[[1,1,[255,255,0,0]],"#,
+            r#"[2,2,[255,0,255,0,128,0,0,255,0,0,0,0,255,255,255,255]],[4,4,[1,2,3]]]}}]}"#
+        );
+        assert_eq!(
+            parse_item(properties).unwrap(),
+            Item {
+                status: Status::NeedsAttention,
+                title: Some("telegram".to_owned()),
+                icon: Icon::new(2, 2, vec![0xff00_ff00, 0x8000_00ff, 0, 0xffff_ffff]),
+                is_menu: false,
+            }
+        );
+
+        let properties = r#"{"type":"a{sv}","data":[{"Title":{"type":"s","data":"Network"},"Status":{"type":"s","data":"Active"},"IconName":{"type":"s","data":"nm-signal-75"},"ItemIsMenu":{"type":"b","data":true}}]}"#;
+        assert_eq!(
+            parse_item(properties).unwrap(),
+            Item {
+                status: Status::Active,
+                title: Some("Network".to_owned()),
+                icon: None,
+                is_menu: true,
+            }
+        );
+        assert!(parse_item(r#"{"type":"a{sv}","data":[{}]}"#).is_err());
+    }
+}
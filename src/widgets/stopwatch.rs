@@ -0,0 +1,292 @@
+use std::time::{Duration, Instant};
+
+use futures::unsync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use futures::{Async, Poll, Stream};
+
+use super::{Click, ClickHandler, Widget, WidgetStream};
+use crate::error::{format_err, Error};
+use crate::text::{Attributes, Text};
+use crate::timer::Timer;
+use crate::{Cnx, Result};
+
+/// How often the elapsed time is redrawn while the stopwatch is running.
+const TICK: Duration = Duration::from_millis(100);
+
+/// Something to do to a [`Stopwatch`], sent with [`Control::stopwatch()`].
+///
+/// [`Stopwatch`]: struct.Stopwatch.html
+/// [`Control::stopwatch()`]: ../struct.Control.html#method.stopwatch
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StopwatchAction {
+    /// Start timing, if the stopwatch isn't already.
+    Start,
+    /// Stop timing, keeping the time elapsed so far.
+    Stop,
+    /// Start timing if the stopwatch is stopped, or stop it if it's running.
+    Toggle,
+    /// Stop timing, and go back to zero.
+    Reset,
+}
+
+impl StopwatchAction {
+    /// Parses an action as written over IPC, e.g. `start`.
+    pub(crate) fn parse(s: &str) -> Result<StopwatchAction> {
+        match s {
+            "start" => Ok(StopwatchAction::Start),
+            "stop" => Ok(StopwatchAction::Stop),
+            "toggle" => Ok(StopwatchAction::Toggle),
+            "reset" => Ok(StopwatchAction::Reset),
+            _ => Err(format_err!("Unknown stopwatch action: {:?}", s)),
+        }
+    }
+}
+
+/// The time a stopwatch has measured.
+#[derive(Debug, Default, PartialEq)]
+struct Elapsed {
+    // The time measured before the stopwatch was last started.
+    before: Duration,
+    // When the stopwatch was started, while it is running.
+    since: Option<Instant>,
+}
+
+impl Elapsed {
+    fn apply(&mut self, action: StopwatchAction, now: Instant) {
+        match action {
+            StopwatchAction::Start if self.since.is_none() => self.since = Some(now),
+            StopwatchAction::Stop => {
+                self.before = self.at(now);
+                self.since = None;
+            }
+            StopwatchAction::Toggle => {
+                let action = match self.since {
+                    Some(_) => StopwatchAction::Stop,
+                    None => StopwatchAction::Start,
+                };
+                self.apply(action, now);
+            }
+            StopwatchAction::Reset => *self = Elapsed::default(),
+            StopwatchAction::Start => {}
+        }
+    }
+
+    /// The time measured as of `now`.
+    fn at(&self, now: Instant) -> Duration {
+        match self.since {
+            Some(since) => self.before + now.saturating_duration_since(since),
+            None => self.before,
+        }
+    }
+}
+
+/// Formats an elapsed time to the tenth of a second, e.g. `0:07.3`, `12:34.5`
+/// or `1:02:03.4`.
+fn format_elapsed(elapsed: Duration) -> String {
+    let tenths = elapsed.as_millis() / 100;
+    let (seconds, tenths) = (tenths / 10, tenths % 10);
+    let (minutes, seconds) = (seconds / 60, seconds % 60);
+    if minutes < 60 {
+        format!("{}:{:02}.{}", minutes, seconds, tenths)
+    } else {
+        format!(
+            "{}:{:02}:{:02}.{}",
+            minutes / 60,
+            minutes % 60,
+            seconds,
+            tenths
+        )
+    }
+}
+
+/// Times something, to the tenth of a second.
+///
+/// The stopwatch is started and stopped by left-clicking it, and reset by
+/// right-clicking it. It can also be controlled with
+/// [`Control::stopwatch()`], or by writing a command like `stopwatch toggle
+/// tea` to the IPC socket (see [`Cnx::enable_ipc()`]).
+///
+/// The time is only redrawn while the stopwatch is running and the bar is
+/// shown, so a stopped stopwatch costs nothing.
+///
+/// [`Control::stopwatch()`]: ../struct.Control.html#method.stopwatch
+/// [`Cnx::enable_ipc()`]: ../struct.Cnx.html#method.enable_ipc
+pub struct Stopwatch {
+    timer: Timer,
+    attr: Attributes,
+    clicks: UnboundedSender<StopwatchAction>,
+    actions: UnboundedReceiver<StopwatchAction>,
+}
+
+impl Stopwatch {
+    /// Creates a new Stopwatch widget.
+    ///
+    /// Creates a new `Stopwatch` widget called `name`, whose time will be
+    /// displayed with the given [`Attributes`]. The stopwatch starts at zero,
+    /// stopped.
+    ///
+    /// The [`Cnx`] instance is borrowed during construction in order to get
+    /// access to handles of its event loop. However, it is not borrowed for the
+    /// lifetime of the widget. See the [`cnx_add_widget!()`] for more discussion
+    /// about the lifetime of the borrow.
+    ///
+    /// [`Attributes`]: ../text/struct.Attributes.html
+    /// [`Cnx`]: ../struct.Cnx.html
+    /// [`cnx_add_widget!()`]: ../macro.cnx_add_widget.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use cnx::*;
+    /// # use cnx::text::*;
+    /// # use cnx::widgets::*;
+    /// #
+    /// # fn run() -> ::cnx::Result<()> {
+    /// let attr = Attributes {
+    ///     font: Font::new("SourceCodePro 21"),
+    ///     fg_color: Color::white(),
+    ///     bg_color: None,
+    ///     padding: Padding::new(8.0, 8.0, 0.0, 0.0),
+    ///     border: None,
+    /// };
+    ///
+    /// let mut cnx = Cnx::new(Position::Top)?;
+    /// cnx.enable_ipc();
+    /// cnx.add_widget(Stopwatch::new(&cnx, attr.clone(), "tea"));
+    /// # Ok(())
+    /// # }
+    /// # fn main() { run().unwrap(); }
+    /// ```
+    pub fn new<S: Into<String>>(cnx: &Cnx, attr: Attributes, name: S) -> Stopwatch {
+        let (clicks, actions) = mpsc::unbounded();
+        cnx.control().add_stopwatch(name.into(), clicks.clone());
+        Stopwatch {
+            timer: cnx.timer(),
+            attr,
+            clicks,
+            actions,
+        }
+    }
+}
+
+impl Widget for Stopwatch {
+    fn click_handler(&mut self) -> Option<ClickHandler> {
+        let clicks = self.clicks.clone();
+        Some(Box::new(move |click: Click| {
+            let action = match click.button {
+                1 => StopwatchAction::Toggle,
+                3 => StopwatchAction::Reset,
+                _ => return Ok(()),
+            };
+            // The widget may have stopped, in which case there's nothing to
+            // control.
+            let _ = clicks.unbounded_send(action);
+            Ok(())
+        }))
+    }
+
+    fn stream(self: Box<Self>) -> Result<WidgetStream> {
+        let stopwatch = *self;
+        Ok(Box::new(Ticking {
+            timer: stopwatch.timer,
+            attr: stopwatch.attr,
+            actions: stopwatch.actions,
+            ticks: None,
+            elapsed: Elapsed::default(),
+            drawn: false,
+        }))
+    }
+}
+
+/// The stopwatch's stream, which only ticks while the stopwatch is running.
+struct Ticking {
+    timer: Timer,
+    attr: Attributes,
+    actions: UnboundedReceiver<StopwatchAction>,
+    // Polling ticks, which (like every poll) are held back while the bars are
+    // hidden.
+    ticks: Option<Box<dyn Stream<Item = (), Error = Error>>>,
+    elapsed: Elapsed,
+    // Whether the time has been drawn at all yet.
+    drawn: bool,
+}
+
+impl Ticking {
+    fn texts(&mut self) -> Vec<Text> {
+        self.drawn = true;
+        vec![Text::new(
+            self.attr.clone(),
+            format_elapsed(self.elapsed.at(Instant::now())),
+        )]
+    }
+}
+
+impl Stream for Ticking {
+    type Item = Vec<Text>;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        if !self.drawn {
+            return Ok(Async::Ready(Some(self.texts())));
+        }
+
+        // The widget keeps a sender for its clicks, so this never ends.
+        if let Async::Ready(Some(action)) = self
+            .actions
+            .poll()
+            .map_err(|()| format_err!("Stopwatch channel closed"))?
+        {
+            self.elapsed.apply(action, Instant::now());
+            self.ticks = match self.elapsed.since {
+                Some(_) => Some(self.timer.interval(TICK)),
+                None => None,
+            };
+            return Ok(Async::Ready(Some(self.texts())));
+        }
+
+        if let Some(ref mut ticks) = self.ticks {
+            if let Async::Ready(Some(())) = ticks.poll()? {
+                return Ok(Async::Ready(Some(self.texts())));
+            }
+        }
+        Ok(Async::NotReady)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use super::{format_elapsed, Elapsed, StopwatchAction};
+
+    #[test]
+    fn works() {
+        assert_eq!(format_elapsed(Duration::from_millis(7_350)), "0:07.3");
+        assert_eq!(format_elapsed(Duration::from_millis(754_500)), "12:34.5");
+        assert_eq!(
+            format_elapsed(Duration::from_millis(3_723_400)),
+            "1:02:03.4"
+        );
+
+        let start = Instant::now();
+        let mut elapsed = Elapsed::default();
+        elapsed.apply(StopwatchAction::Start, start);
+        assert_eq!(
+            elapsed.at(start + Duration::from_secs(2)),
+            Duration::from_secs(2)
+        );
+        elapsed.apply(StopwatchAction::Toggle, start + Duration::from_secs(3));
+        assert_eq!(
+            elapsed.at(start + Duration::from_secs(9)),
+            Duration::from_secs(3)
+        );
+        elapsed.apply(StopwatchAction::Start, start + Duration::from_secs(10));
+        elapsed.apply(StopwatchAction::Start, start + Duration::from_secs(11));
+        assert_eq!(
+            elapsed.at(start + Duration::from_secs(12)),
+            Duration::from_secs(5)
+        );
+        elapsed.apply(StopwatchAction::Reset, start + Duration::from_secs(12));
+        assert_eq!(elapsed, Elapsed::default());
+        assert!(StopwatchAction::parse("explode").is_err());
+    }
+}
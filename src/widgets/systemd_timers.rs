@@ -0,0 +1,330 @@
+use std::time::Duration;
+
+use chrono::prelude::*;
+use futures::{future, Future};
+
+use crate::busctl::{Bus, Busctl};
+use crate::error::{format_err, Error, ResultExt};
+use crate::json::{self, Value};
+use crate::text::{Attributes, Color, Text};
+use crate::timer::Timer;
+use crate::{Cnx, Result};
+
+const SYSTEMD: &str = "org.freedesktop.systemd1";
+const MANAGER_PATH: &str = "/org/freedesktop/systemd1";
+const MANAGER_INTERFACE: &str = "org.freedesktop.systemd1.Manager";
+
+/// Parses the reply to `ListUnitsByPatterns`, returning the name and object
+/// path of each unit.
+///
+/// The reply is a single `a(ssssssouso)`, with each struct serialised as an
+/// array whose first element is the unit's name and seventh is its path.
+fn parse_units(output: &str) -> Result<Vec<(String, String)>> {
+    let reply = json::parse(output)?;
+    let units = reply
+        .get("data")
+        .and_then(|data| data.at(0))
+        .and_then(Value::as_array)
+        .ok_or_else(|| format_err!("Unexpected format of ListUnitsByPatterns reply"))?;
+    units
+        .iter()
+        .map(|unit| {
+            let name = unit.at(0).and_then(Value::as_str);
+            let path = unit.at(6).and_then(Value::as_str);
+            match (name, path) {
+                (Some(name), Some(path)) => Ok((name.to_owned(), path.to_owned())),
+                _ => Err(format_err!("Unexpected format of unit: {:?}", unit)),
+            }
+        })
+        .collect()
+}
+
+/// Parses the reply to `GetUnit`, returning the unit's object path.
+fn parse_unit_path(output: &str) -> Result<String> {
+    json::parse(output)?
+        .get("data")
+        .and_then(|data| data.at(0))
+        .and_then(Value::as_str)
+        .map(ToOwned::to_owned)
+        .ok_or_else(|| format_err!("Unexpected format of GetUnit reply"))
+}
+
+/// Parses the output of getting properties with `busctl`, which prints each
+/// property's value on its own line.
+fn parse_properties(output: &str) -> Result<Vec<Value>> {
+    output
+        .lines()
+        .map(|line| {
+            json::parse(line)?
+                .get("data")
+                .cloned()
+                .ok_or_else(|| format_err!("Unexpected format of property: {}", line))
+        })
+        .collect()
+}
+
+/// A timer unit, and what we know about the unit that it triggers.
+#[derive(Debug, PartialEq)]
+struct TimerUnit {
+    name: String,
+    // When the timer next elapses, in microseconds since the Unix epoch.
+    next_elapse: Option<u64>,
+    triggered_unit_failed: bool,
+}
+
+/// Shows when the next systemd timer is due, and any which failed.
+///
+/// This widget lists systemd's timer units over D-Bus (e.g. `backup.timer`),
+/// and shows when the next of them will elapse, e.g. `backup 14:00`. If the
+/// last run of the unit that a timer triggers failed (e.g. `backup.service`),
+/// the timer is also shown as failed, in the specified `warning_color`, until
+/// the failure is reset or the unit next succeeds.
+///
+/// The timers are checked every minute. The widget expects the `busctl`
+/// executable (part of `systemd`) to be available in the `PATH`.
+pub struct SystemdTimers {
+    timer: Timer,
+    update_interval: Duration,
+    attr: Attributes,
+    warning_color: Color,
+    busctl: Busctl,
+    patterns: Vec<String>,
+}
+
+impl SystemdTimers {
+    ///  Creates a new SystemdTimers widget.
+    ///
+    ///  Creates a new `SystemdTimers` widget, whose text will be displayed
+    ///  with the given [`Attributes`], or `warning_color` for timers whose
+    ///  unit failed. By default, every timer of the system manager is shown.
+    ///
+    ///  The [`Cnx`] instance is borrowed during construction in order to get
+    ///  access to handles of its event loop. However, it is not borrowed for
+    ///  the lifetime of the widget. See the [`cnx_add_widget!()`] for more
+    ///  discussion about the lifetime of the borrow.
+    ///
+    /// [`Attributes`]: ../text/struct.Attributes.html
+    /// [`Cnx`]: ../struct.Cnx.html
+    /// [`cnx_add_widget!()`]: ../macro.cnx_add_widget.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #[macro_use]
+    /// # extern crate cnx;
+    /// #
+    /// # use cnx::*;
+    /// # use cnx::text::*;
+    /// # use cnx::widgets::*;
+    /// #
+    /// # fn run() -> ::cnx::Result<()> {
+    /// let attr = Attributes {
+    ///     font: Font::new("SourceCodePro 21"),
+    ///     fg_color: Color::white(),
+    ///     bg_color: None,
+    ///     padding: Padding::new(8.0, 8.0, 0.0, 0.0),
+    ///     border: None,
+    /// };
+    ///
+    /// let mut cnx = Cnx::new(Position::Top)?;
+    /// let timers = SystemdTimers::new(&cnx, attr.clone(), Color::red())
+    ///     .with_timers(&["backup-*.timer"]);
+    /// cnx_add_widget!(cnx, timers);
+    /// # Ok(())
+    /// # }
+    /// # fn main() { run().unwrap(); }
+    /// ```
+    pub fn new(cnx: &Cnx, attr: Attributes, warning_color: Color) -> SystemdTimers {
+        SystemdTimers {
+            timer: cnx.timer(),
+            update_interval: Duration::from_secs(60),
+            attr,
+            warning_color,
+            busctl: Busctl::new(cnx, Bus::System),
+            patterns: vec!["*.timer".to_owned()],
+        }
+    }
+
+    /// Shows the timers of the user's service manager (i.e. `systemctl
+    /// --user`), rather than the system's.
+    pub fn for_user(mut self) -> SystemdTimers {
+        self.busctl = self.busctl.on(Bus::User);
+        self
+    }
+
+    /// Only shows the timers matching `patterns`, e.g. `backup.timer` or
+    /// `backup-*.timer`.
+    pub fn with_timers(mut self, patterns: &[&str]) -> SystemdTimers {
+        self.patterns = patterns.iter().map(|p| (*p).to_owned()).collect();
+        self
+    }
+
+    fn tick(&self) -> Box<dyn Future<Item = Vec<Text>, Error = Error>> {
+        let mut args = vec![
+            "call",
+            SYSTEMD,
+            MANAGER_PATH,
+            MANAGER_INTERFACE,
+            "ListUnitsByPatterns",
+            "asas",
+            // Any state.
+            "0",
+        ];
+        let count = self.patterns.len().to_string();
+        args.push(&count);
+        args.extend(self.patterns.iter().map(String::as_str));
+        let busctl = self.busctl.clone();
+        let timers = self
+            .busctl
+            .call(&args)
+            .and_then(|output| parse_units(&output).context("Failed to list timers"))
+            .and_then(move |units| {
+                future::join_all(
+                    units
+                        .into_iter()
+                        .map(move |(name, path)| load_timer(&busctl, name, &path)),
+                )
+            });
+
+        let attr = self.attr.clone();
+        let warning_color = self.warning_color.clone();
+        Box::new(timers.map(move |timers| render(&attr, &warning_color, &timers)))
+    }
+}
+
+/// Loads the timer called `name`, whose object path is `path`, and whether
+/// the unit it triggers failed.
+fn load_timer(
+    busctl: &Busctl,
+    name: String,
+    path: &str,
+) -> Box<dyn Future<Item = TimerUnit, Error = Error>> {
+    let properties = busctl
+        .call(&[
+            "get-property",
+            SYSTEMD,
+            path,
+            "org.freedesktop.systemd1.Timer",
+            "NextElapseUSecRealtime",
+            "Unit",
+        ])
+        .and_then(|output| parse_properties(&output));
+    let busctl = busctl.clone();
+    Box::new(properties.and_then(move |properties| {
+        // Timers which won't elapse again (e.g. `OnBootSec=` timers) have a
+        // next elapse of 0.
+        let next_elapse = properties
+            .first()
+            .and_then(Value::as_f64)
+            .map(|usec| usec as u64)
+            .filter(|&usec| usec > 0);
+        let unit = match properties.get(1).and_then(Value::as_str) {
+            Some(unit) => unit,
+            None => {
+                return future::Either::A(future::err(format_err!("No Unit for timer {}", name)))
+            }
+        };
+
+        let unit_path = busctl.call(&[
+            "call",
+            SYSTEMD,
+            MANAGER_PATH,
+            MANAGER_INTERFACE,
+            "GetUnit",
+            "s",
+            unit,
+        ]);
+        // The triggered unit is only loaded once it has run.
+        let failed = unit_path.then(move |output| -> Box<dyn Future<Item = _, Error = _>> {
+            let output = match output {
+                Ok(output) => output,
+                Err(_) => return Box::new(future::ok(false)),
+            };
+            let unit_path = match parse_unit_path(&output) {
+                Ok(unit_path) => unit_path,
+                Err(e) => return Box::new(future::err(e)),
+            };
+            let state = busctl.call(&[
+                "get-property",
+                SYSTEMD,
+                &unit_path,
+                "org.freedesktop.systemd1.Unit",
+                "ActiveState",
+            ]);
+            Box::new(state.and_then(|output| {
+                let properties = parse_properties(&output)?;
+                Ok(properties.first().and_then(Value::as_str) == Some("failed"))
+            }))
+        });
+        future::Either::B(failed.map(move |triggered_unit_failed| TimerUnit {
+            name,
+            next_elapse,
+            triggered_unit_failed,
+        }))
+    }))
+}
+
+/// Shows any timers whose triggered units failed, and then the next timer to
+/// elapse.
+fn render(attr: &Attributes, warning_color: &Color, timers: &[TimerUnit]) -> Vec<Text> {
+    let mut texts = Vec::new();
+    for timer in timers.iter().filter(|timer| timer.triggered_unit_failed) {
+        let mut attr = attr.clone();
+        attr.fg_color = warning_color.clone();
+        texts.push(Text::new(
+            attr,
+            format!("{} failed", timer.name.trim_end_matches(".timer")),
+        ));
+    }
+
+    let next = timers
+        .iter()
+        .filter_map(|timer| Some((timer.next_elapse?, timer)))
+        .min_by_key(|&(next_elapse, _)| next_elapse);
+    if let Some((next_elapse, timer)) = next {
+        let when = Local.timestamp((next_elapse / 1_000_000) as i64, 0);
+        let format = if when.date() == Local::today() {
+            "%H:%M"
+        } else {
+            "%a %H:%M"
+        };
+        texts.push(Text::new(
+            attr.clone(),
+            format!(
+                "{} {}",
+                timer.name.trim_end_matches(".timer"),
+                when.format(format)
+            ),
+        ));
+    }
+    texts
+}
+
+timer_widget!(SystemdTimers, timer, update_interval, tick);
+
+#[cfg(test)]
+mod test {
+    use super::{parse_properties, parse_units};
+    use crate::json::Value;
+
+    #[test]
+    fn works() {
+        let units = r#"{"type":"a(ssssssouso)","data":[[["backup.timer","Nightly backup","loaded","active","waiting","","/org/freedesktop/systemd1/unit/backup_2etimer",0,"","/"]]]}"#;
+        assert_eq!(
+            parse_units(units).unwrap(),
+            vec![(
+                "backup.timer".to_owned(),
+                "/org/freedesktop/systemd1/unit/backup_2etimer".to_owned()
+            )]
+        );
+
+        let properties = "{\"type\":\"t\",\"data\":1677722400000000}\n{\"type\":\"s\",\"data\":\"backup.service\"}\n";
+        assert_eq!(
+            parse_properties(properties).unwrap(),
+            vec![
+                Value::Number(1_677_722_400_000_000.0),
+                Value::String("backup.service".to_owned())
+            ]
+        );
+    }
+}
@@ -0,0 +1,207 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use log::error;
+use tokio_core::reactor::Handle;
+use xcb_util::ewmh;
+
+use super::{Click, ClickHandler, Widget, WidgetStream};
+use crate::error::ResultExt;
+use crate::text::{Attributes, Ellipsis, Text};
+use crate::{xcb_ext, Cnx, Result};
+
+// The `_NET_WM_DESKTOP` of windows which are shown on every desktop.
+const ALL_DESKTOPS: u32 = 0xffff_ffff;
+
+/// Shows the windows on the current workspace, highlighting the focused one.
+///
+/// This widget shows the title of each window in the WM's `_NET_CLIENT_LIST`
+/// [`EWMH`] property which is on the current workspace (or on every
+/// workspace), in the order that the WM lists them. The focused window
+/// (`_NET_ACTIVE_WINDOW`) is highlighted. Long titles are truncated, so that
+/// one window doesn't crowd out the others. See [`with_max_title_chars()`].
+///
+/// Clicking on a window asks the WM to activate it.
+///
+/// [`EWMH`]: https://specifications.freedesktop.org/wm-spec/wm-spec-latest.html
+/// [`with_max_title_chars()`]: #method.with_max_title_chars
+pub struct Taskbar {
+    inner: TaskbarInner,
+}
+
+struct TaskbarInner {
+    tokio_handle: Handle,
+    active_attr: Attributes,
+    inactive_attr: Attributes,
+    max_title_chars: usize,
+    // The window shown by each text, for the click handler.
+    windows: Rc<RefCell<Vec<xcb::Window>>>,
+}
+
+impl Taskbar {
+    ///  Creates a new Taskbar widget.
+    ///
+    ///  Creates a new `Taskbar` widget. The widget will list the windows on
+    ///  the current workspace, using the given `inactive_attr` [`Attributes`]
+    ///  for all unfocused windows, and the `active_attr` [`Attributes`] for
+    ///  the focused window.
+    ///
+    ///  The [`Cnx`] instance is borrowed during construction in order to get
+    ///  access to handles of its event loop. However, it is not borrowed for
+    ///  the lifetime of the widget. See the [`cnx_add_widget!()`] for more
+    ///  discussion about the lifetime of the borrow.
+    ///
+    /// [`Attributes`]: ../text/struct.Attributes.html
+    /// [`Cnx`]: ../struct.Cnx.html
+    /// [`cnx_add_widget!()`]: ../macro.cnx_add_widget.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #[macro_use]
+    /// # extern crate cnx;
+    /// #
+    /// # use cnx::*;
+    /// # use cnx::text::*;
+    /// # use cnx::widgets::*;
+    /// #
+    /// # fn run() -> ::cnx::Result<()> {
+    /// let attr = Attributes {
+    ///     font: Font::new("SourceCodePro 21"),
+    ///     fg_color: Color::white(),
+    ///     bg_color: None,
+    ///     padding: Padding::new(8.0, 8.0, 0.0, 0.0),
+    ///     border: None,
+    /// };
+    /// let mut active_attr = attr.clone();
+    /// active_attr.bg_color = Some(Color::blue());
+    ///
+    /// let mut cnx = Cnx::new(Position::Top)?;
+    /// cnx_add_widget!(cnx, Taskbar::new(&cnx, active_attr, attr.clone()));
+    /// # Ok(())
+    /// # }
+    /// # fn main() { run().unwrap(); }
+    /// ```
+    pub fn new(cnx: &Cnx, active_attr: Attributes, inactive_attr: Attributes) -> Taskbar {
+        Taskbar {
+            inner: TaskbarInner {
+                tokio_handle: cnx.handle(),
+                active_attr,
+                inactive_attr,
+                max_title_chars: 24,
+                windows: Rc::new(RefCell::new(Vec::new())),
+            },
+        }
+    }
+
+    /// Truncates each window's title to `max_title_chars` characters, rather
+    /// than the default of 24.
+    pub fn with_max_title_chars(mut self, max_title_chars: usize) -> Taskbar {
+        self.inner.max_title_chars = max_title_chars;
+        self
+    }
+}
+
+impl Widget for Taskbar {
+    fn stream(self: Box<Self>) -> Result<WidgetStream> {
+        Box::new(self.inner).stream()
+    }
+
+    fn click_handler(&mut self) -> Option<ClickHandler> {
+        match window_activator(self.inner.windows.clone()) {
+            Ok(handler) => Some(handler),
+            Err(e) => {
+                error!("Taskbar won't respond to clicks: {}", e);
+                None
+            }
+        }
+    }
+}
+
+/// Returns a `ClickHandler` which activates the clicked window.
+fn window_activator(windows: Rc<RefCell<Vec<xcb::Window>>>) -> Result<ClickHandler> {
+    let (conn, screen_idx) = xcb_ext::connect()?;
+    Ok(Box::new(move |click: Click| {
+        if click.button != 1 {
+            return Ok(());
+        }
+        let window = match windows.borrow().get(click.text) {
+            Some(&window) => window,
+            None => return Ok(()),
+        };
+        let active = ewmh::get_active_window(&conn, screen_idx)
+            .get_reply()
+            .unwrap_or(xcb::NONE);
+        // We're a pager, as far as the WM's focus stealing prevention is
+        // concerned.
+        ewmh::request_change_active_window(
+            &conn,
+            screen_idx,
+            window,
+            ewmh::CLIENT_SOURCE_TYPE_OTHER,
+            xcb::CURRENT_TIME,
+            active,
+        )
+        .request_check()
+        .context("Failed to activate window")?;
+        Ok(())
+    }))
+}
+
+impl TaskbarInner {
+    fn on_change(&self, conn: &ewmh::Connection, screen_idx: i32) -> Result<Vec<Text>> {
+        let current = ewmh::get_current_desktop(conn, screen_idx)
+            .get_reply()
+            .unwrap_or(0);
+        let active = ewmh::get_active_window(conn, screen_idx)
+            .get_reply()
+            .unwrap_or(xcb::NONE);
+        let clients = match ewmh::get_client_list(conn, screen_idx).get_reply() {
+            Ok(reply) => reply.windows().to_vec(),
+            Err(_) => Vec::new(),
+        };
+
+        let mut windows = Vec::new();
+        let mut texts = Vec::new();
+        for window in clients {
+            // Windows without a desktop are treated as being on the current
+            // one, as not all WMs set it.
+            let desktop = ewmh::get_wm_desktop(conn, window)
+                .get_reply()
+                .unwrap_or(current);
+            if desktop != current && desktop != ALL_DESKTOPS {
+                continue;
+            }
+
+            // Like ActiveWindowTitle, watch each window we see so that we
+            // notice its title changing.
+            xcb_ext::select_property_events(conn, window);
+            let title = xcb_ext::window_title(conn, window)
+                .filter(|title| !title.is_empty())
+                .or_else(|| xcb_ext::window_class(conn, window))
+                .unwrap_or_else(|| "?".to_owned());
+
+            let attr = if window == active {
+                self.active_attr.clone()
+            } else {
+                self.inactive_attr.clone()
+            };
+            windows.push(window);
+            texts.push(Text::new(
+                attr,
+                Ellipsis::End.shorten(&title, self.max_title_chars),
+            ));
+        }
+
+        *self.windows.borrow_mut() = windows;
+        Ok(texts)
+    }
+}
+
+x_properties_widget!(TaskbarInner, tokio_handle, on_change; [
+    CLIENT_LIST,
+    ACTIVE_WINDOW,
+    CURRENT_DESKTOP,
+    WM_DESKTOP,
+    WM_NAME
+]);
@@ -0,0 +1,180 @@
+use std::cell::Cell;
+
+use futures::{stream, Stream};
+
+use super::{ClickHandler, Refresh, Widget, WidgetStream};
+use crate::process;
+use crate::text::{self, Severity, Text};
+use crate::Result;
+
+/// Reads the value which a [`Threshold`] checks from its widget's texts.
+///
+/// [`Threshold`]: struct.Threshold.html
+type Extract = Box<dyn Fn(&[Text]) -> Option<f64>>;
+
+/// Returns the `notify-send` urgency for a value which has just become
+/// worse, or `None` if it hasn't.
+fn urgency(previous: Severity, severity: Severity) -> Option<&'static str> {
+    if severity <= previous {
+        return None;
+    }
+    match severity {
+        Severity::Normal => None,
+        Severity::Warn => Some("normal"),
+        Severity::Crit => Some("critical"),
+    }
+}
+
+/// Shows another widget with warning or critical [`Attributes`], once a
+/// number it shows reaches the levels of a [`text::Threshold`].
+///
+/// This wraps any widget whose texts include a number, e.g. a temperature or
+/// a CPU's load. Each time the widget updates, the number is read from its
+/// texts by the given closure, and all of its texts are shown with the
+/// attributes of the worst level the number has reached, or as the widget
+/// shows them otherwise. Updates the number can't be read from are shown as
+/// they are.
+///
+/// This saves every widget from having to implement its own warning colors.
+/// Optionally, a desktop notification can also be sent whenever the number
+/// gets worse, see [`with_notifications()`].
+///
+/// Clicks are passed on to the widget.
+///
+/// [`Attributes`]: ../text/struct.Attributes.html
+/// [`text::Threshold`]: ../text/struct.Threshold.html
+/// [`with_notifications()`]: #method.with_notifications
+pub struct Threshold<W> {
+    inner: W,
+    threshold: text::Threshold<f64>,
+    extract: Extract,
+    // The summary of the notifications sent when the number gets worse.
+    notifications: Option<String>,
+}
+
+impl<W: Widget> Threshold<W> {
+    ///  Creates a new Threshold widget.
+    ///
+    ///  Creates a new `Threshold` widget, which shows the given `widget` with
+    ///  the attributes of the levels of `threshold` that the number read
+    ///  from its texts by `extract` reaches.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #[macro_use]
+    /// # extern crate cnx;
+    /// #
+    /// # use cnx::*;
+    /// # use cnx::text::{Attributes, Color, Font, Padding};
+    /// # use cnx::widgets::*;
+    /// # use std::time::Duration;
+    /// #
+    /// # fn run() -> ::cnx::Result<()> {
+    /// let attr = Attributes {
+    ///     font: Font::new("SourceCodePro 21"),
+    ///     fg_color: Color::white(),
+    ///     bg_color: None,
+    ///     padding: Padding::new(8.0, 8.0, 0.0, 0.0),
+    ///     border: None,
+    /// };
+    /// let mut crit_attr = attr.clone();
+    /// crit_attr.fg_color = Color::red();
+    ///
+    /// let mut cnx = Cnx::new(Position::Top)?;
+    /// let command = "cat /sys/class/thermal/thermal_zone0/temp".to_owned();
+    /// let exec = Exec::new(&cnx, attr.clone(), command, Duration::from_secs(5));
+    /// let threshold = text::Threshold::new().crit(80_000.0, crit_attr);
+    /// cnx_add_widget!(
+    ///     cnx,
+    ///     Threshold::new(exec, threshold, |texts| texts.first()?.text.trim().parse().ok())
+    /// );
+    /// # Ok(())
+    /// # }
+    /// # fn main() { run().unwrap(); }
+    /// ```
+    pub fn new<F>(widget: W, threshold: text::Threshold<f64>, extract: F) -> Threshold<W>
+    where
+        F: Fn(&[Text]) -> Option<f64> + 'static,
+    {
+        Threshold {
+            inner: widget,
+            threshold,
+            extract: Box::new(extract),
+            notifications: None,
+        }
+    }
+
+    /// Sends a desktop notification (using `notify-send`) with the given
+    /// `summary` whenever the number reaches a worse level, with a
+    /// `critical` urgency once it reaches the critical level. The body of the
+    /// notification is the widget's text.
+    pub fn with_notifications<S: Into<String>>(mut self, summary: S) -> Threshold<W> {
+        self.notifications = Some(summary.into());
+        self
+    }
+}
+
+impl<W: Widget + 'static> Widget for Threshold<W> {
+    fn stream(self: Box<Self>) -> Result<WidgetStream> {
+        self.refreshable_stream(Box::new(stream::empty()))
+    }
+
+    fn refreshable_stream(self: Box<Self>, refresh: Refresh) -> Result<WidgetStream> {
+        let Threshold {
+            inner,
+            threshold,
+            extract,
+            notifications,
+        } = *self;
+        let previous = Cell::new(Severity::Normal);
+        let texts = Box::new(inner)
+            .refreshable_stream(refresh)?
+            .map(move |mut texts| {
+                let value = match extract(&texts) {
+                    Some(value) => value,
+                    None => return texts,
+                };
+                let severity = threshold.severity(&value);
+                if let (Some(summary), Some(urgency)) = (
+                    &notifications,
+                    urgency(previous.replace(severity), severity),
+                ) {
+                    let body = texts
+                        .iter()
+                        .map(|text| text.text.as_str())
+                        .collect::<Vec<_>>()
+                        .join(" ");
+                    process::notify(urgency, summary, &body);
+                }
+                if let Some(attr) = threshold.attributes(&value) {
+                    for text in &mut texts {
+                        text.attr = attr.clone();
+                    }
+                }
+                texts
+            });
+        Ok(Box::new(texts))
+    }
+
+    fn click_handler(&mut self) -> Option<ClickHandler> {
+        self.inner.click_handler()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::urgency;
+    use crate::text::Severity;
+
+    #[test]
+    fn works() {
+        assert_eq!(urgency(Severity::Normal, Severity::Normal), None);
+        assert_eq!(urgency(Severity::Normal, Severity::Warn), Some("normal"));
+        assert_eq!(urgency(Severity::Warn, Severity::Crit), Some("critical"));
+        assert_eq!(urgency(Severity::Normal, Severity::Crit), Some("critical"));
+        // Getting better, or staying as bad, isn't news.
+        assert_eq!(urgency(Severity::Crit, Severity::Warn), None);
+        assert_eq!(urgency(Severity::Crit, Severity::Crit), None);
+    }
+}
@@ -0,0 +1,344 @@
+use std::cell::RefCell;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use chrono::prelude::*;
+use futures::{Future, Stream};
+use log::warn;
+use tokio_core::reactor::Handle;
+use xcb_util::ewmh;
+
+use super::{Widget, WidgetStream};
+use crate::error::{format_err, Error, ResultExt};
+use crate::state::State;
+use crate::text::{Attributes, Text};
+use crate::timer::Timer;
+use crate::{xcb_ext, Cnx, Result};
+
+/// What the [`TimeTracker`] widget shows.
+///
+/// [`TimeTracker`]: struct.TimeTracker.html
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TimeTrackerDisplay {
+    /// Show the application which has been focused for longest today, and for
+    /// how long, e.g. `firefox 2h05m`.
+    TopApplication,
+    /// Show the total time any window has been focused today, e.g. `6h40m`.
+    Total,
+}
+
+/// The time each application class has been focused for on a given day.
+#[derive(Debug, PartialEq)]
+struct Usage {
+    date: NaiveDate,
+    totals: HashMap<String, u64>,
+}
+
+impl Usage {
+    fn new(date: NaiveDate) -> Usage {
+        Usage {
+            date,
+            totals: HashMap::new(),
+        }
+    }
+
+    /// Reads usage previously written by `store()`.
+    ///
+    /// The date is kept under `date`, and the number of seconds each
+    /// application was focused for under `app:` followed by its class.
+    fn restore(state: &State) -> Result<Usage> {
+        let date = state
+            .get("date")
+            .ok_or_else(|| format_err!("Missing date"))?;
+        let date = NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .with_context(|_| format!("Invalid date: {}", date))?;
+        let mut usage = Usage::new(date);
+        for (class, seconds) in state.prefixed("app:") {
+            let seconds = seconds
+                .parse::<u64>()
+                .with_context(|_| format!("Invalid time for {}: {}", class, seconds))?;
+            usage.totals.insert(class.to_owned(), seconds);
+        }
+        Ok(usage)
+    }
+
+    fn store(&self, state: &mut State) {
+        state.clear();
+        state.set("date", self.date.format("%Y-%m-%d"));
+        for (class, seconds) in &self.totals {
+            state.set(&format!("app:{}", class), seconds);
+        }
+    }
+
+    fn top(&self) -> Option<(&str, u64)> {
+        self.totals
+            .iter()
+            .max_by_key(|&(class, seconds)| (*seconds, Reverse(class)))
+            .map(|(class, seconds)| (class.as_str(), *seconds))
+    }
+
+    fn total(&self) -> u64 {
+        self.totals.values().sum()
+    }
+}
+
+/// Formats a number of seconds as hours and minutes, e.g. `1h05m` or `42m`.
+fn format_seconds(seconds: u64) -> String {
+    let minutes = seconds / 60;
+    if minutes < 60 {
+        format!("{}m", minutes)
+    } else {
+        format!("{}h{:02}m", minutes / 60, minutes % 60)
+    }
+}
+
+/// The state shared between the two streams which drive the widget.
+struct Tracker {
+    usage: Usage,
+    // Where the usage is saved, once it has been loaded.
+    state: Option<State>,
+    // The class of the focused window, and when we last credited it with time.
+    focused: Option<(String, Instant)>,
+}
+
+impl Tracker {
+    fn new() -> Tracker {
+        Tracker {
+            usage: Usage::new(Local::today().naive_local()),
+            state: None,
+            focused: None,
+        }
+    }
+
+    /// Adds today's usage from the saved `state` to any which has been
+    /// counted while it was loading.
+    fn restore(&mut self, state: State) {
+        match Usage::restore(&state) {
+            Ok(usage) if usage.date == self.usage.date => {
+                for (class, seconds) in usage.totals {
+                    *self.usage.totals.entry(class).or_insert(0) += seconds;
+                }
+            }
+            Ok(_) => {}
+            // Nothing has been saved yet.
+            Err(_) if state.get("date").is_none() => {}
+            Err(e) => warn!("Ignoring invalid time tracking data: {}", e),
+        }
+        self.state = Some(state);
+    }
+
+    /// Credits the focused application with the time since it was last
+    /// credited, starting afresh if the day has changed.
+    fn credit(&mut self) {
+        let today = Local::today().naive_local();
+        if self.usage.date != today {
+            self.usage = Usage::new(today);
+        }
+        if let Some((ref class, ref mut since)) = self.focused {
+            let now = Instant::now();
+            let elapsed = now.duration_since(*since).as_secs();
+            // Only move `since` forward by whole seconds, so that we don't
+            // lose the remainder each time we're credited.
+            *since += Duration::from_secs(elapsed);
+            *self.usage.totals.entry(class.clone()).or_insert(0) += elapsed;
+        }
+    }
+
+    fn focus(&mut self, class: Option<String>) {
+        self.credit();
+        self.focused = class.map(|class| (class, Instant::now()));
+    }
+
+    /// Saves the usage, unless the saved state hasn't been loaded yet.
+    fn save(&mut self) -> Option<Box<dyn Future<Item = (), Error = Error>>> {
+        let state = self.state.as_mut()?;
+        self.usage.store(state);
+        Some(state.save())
+    }
+}
+
+struct TimeTrackerInner {
+    tokio_handle: Handle,
+    tracker: Rc<RefCell<Tracker>>,
+}
+
+impl TimeTrackerInner {
+    fn on_change(&self, conn: &ewmh::Connection, screen_idx: i32) -> Result<Vec<Text>> {
+        let class = ewmh::get_active_window(conn, screen_idx)
+            .get_reply()
+            .ok()
+            .and_then(|window| xcb_ext::window_class(conn, window));
+        self.tracker.borrow_mut().focus(class);
+        // The text is only updated periodically, by the outer widget.
+        Ok(Vec::new())
+    }
+}
+
+x_properties_widget!(TimeTrackerInner, tokio_handle, on_change; [
+    ACTIVE_WINDOW
+]);
+
+/// Tracks how long each application has been focused for today.
+///
+/// This widget watches the `_NET_ACTIVE_WINDOW` [`EWMH`] property, in the same
+/// way as [`ActiveWindowTitle`], and adds up how long windows of each
+/// application class (the `WM_CLASS` property) have been focused for. It then
+/// shows either the application which has been focused for longest today, or
+/// the total time spent focused on any window, depending on the
+/// [`TimeTrackerDisplay`] it is given.
+///
+/// The totals are saved once a minute to `$XDG_STATE_HOME/cnx/time-tracker`
+/// (usually `~/.local/state/cnx/time-tracker`, see the [`state`] module), so
+/// that they survive Cnx restarting. They are reset at midnight.
+///
+/// The time is counted while a window is focused, whether or not anyone is
+/// actually using it.
+///
+/// [`EWMH`]: https://specifications.freedesktop.org/wm-spec/wm-spec-latest.html
+/// [`ActiveWindowTitle`]: struct.ActiveWindowTitle.html
+/// [`TimeTrackerDisplay`]: enum.TimeTrackerDisplay.html
+/// [`state`]: ../state/index.html
+pub struct TimeTracker {
+    inner: TimeTrackerInner,
+    timer: Timer,
+    update_interval: Duration,
+    display: TimeTrackerDisplay,
+    attr: Attributes,
+}
+
+impl TimeTracker {
+    /// Creates a new Time Tracker widget.
+    ///
+    /// Creates a new `TimeTracker` widget, whose text will be displayed with
+    /// the given [`Attributes`]. The `display` argument controls whether the
+    /// top application or the total focused time is shown.
+    ///
+    /// The [`Cnx`] instance is borrowed during construction in order to get
+    /// access to handles of its event loop. However, it is not borrowed for the
+    /// lifetime of the widget. See the [`cnx_add_widget!()`] for more discussion
+    /// about the lifetime of the borrow.
+    ///
+    /// [`Attributes`]: ../text/struct.Attributes.html
+    /// [`Cnx`]: ../struct.Cnx.html
+    /// [`cnx_add_widget!()`]: ../macro.cnx_add_widget.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use cnx::*;
+    /// # use cnx::text::*;
+    /// # use cnx::widgets::*;
+    /// #
+    /// # fn run() -> ::cnx::Result<()> {
+    /// let attr = Attributes {
+    ///     font: Font::new("SourceCodePro 21"),
+    ///     fg_color: "ffffff".parse().unwrap(),
+    ///     bg_color: None,
+    ///     padding: Padding::new(8.0, 8.0, 0.0, 0.0),
+    ///     border: None,
+    /// };
+    ///
+    /// let mut cnx = Cnx::new(Position::Top)?;
+    /// cnx.add_widget(TimeTracker::new(
+    ///     &cnx,
+    ///     TimeTrackerDisplay::TopApplication,
+    ///     attr.clone(),
+    /// ));
+    /// # Ok(())
+    /// # }
+    /// # fn main() { run().unwrap(); }
+    /// ```
+    pub fn new(cnx: &Cnx, display: TimeTrackerDisplay, attr: Attributes) -> TimeTracker {
+        TimeTracker {
+            inner: TimeTrackerInner {
+                tokio_handle: cnx.handle(),
+                tracker: Rc::new(RefCell::new(Tracker::new())),
+            },
+            timer: cnx.timer(),
+            update_interval: Duration::from_secs(60),
+            display,
+            attr,
+        }
+    }
+}
+
+impl Widget for TimeTracker {
+    fn stream(self: Box<Self>) -> Result<WidgetStream> {
+        let TimeTracker {
+            inner,
+            timer,
+            update_interval,
+            display,
+            attr,
+        } = *self;
+        let handle = inner.tokio_handle.clone();
+        let tracker = inner.tracker.clone();
+        let render = move || -> Result<Vec<Text>> {
+            let mut tracker = tracker.borrow_mut();
+            tracker.credit();
+            if let Some(save) = tracker.save() {
+                handle.spawn(save.map_err(|e| warn!("Failed to save time tracking data: {}", e)));
+            }
+
+            let text = match display {
+                TimeTrackerDisplay::TopApplication => match tracker.usage.top() {
+                    Some((class, seconds)) => format!("{} {}", class, format_seconds(seconds)),
+                    None => return Ok(Vec::new()),
+                },
+                TimeTrackerDisplay::Total => format_seconds(tracker.usage.total()),
+            };
+            Ok(vec![Text::new(attr.clone(), text)])
+        };
+
+        // Focus changes only update the tracker and never yield any texts of
+        // their own. The text is shown as soon as the saved usage has been
+        // loaded, then updated whenever the timer fires.
+        let restored = inner.tracker.clone();
+        let load = State::load("time-tracker").then(move |result| {
+            match result {
+                Ok(state) => restored.borrow_mut().restore(state),
+                Err(e) => warn!("Failed to load time tracking data: {}", e),
+            }
+            Ok(())
+        });
+        let focus_changes = Box::new(inner).stream()?.filter(|texts| !texts.is_empty());
+        let ticks = load
+            .into_stream()
+            .chain(timer.interval(update_interval))
+            .and_then(move |()| render());
+
+        Ok(Box::new(ticks.select(focus_changes)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{format_seconds, Usage};
+    use crate::state::State;
+    use chrono::NaiveDate;
+
+    #[test]
+    fn usage_round_trips() {
+        let mut usage = Usage::new(NaiveDate::from_ymd(2018, 11, 3));
+        usage.totals.insert("Firefox".to_owned(), 3700);
+        usage.totals.insert("URxvt".to_owned(), 125);
+        let mut state = State::empty("time-tracker");
+        usage.store(&mut state);
+        assert_eq!(Usage::restore(&state).unwrap(), usage);
+        assert_eq!(usage.top(), Some(("Firefox", 3700)));
+        assert_eq!(usage.total(), 3825);
+        state.set("app:Firefox", "nope");
+        assert!(Usage::restore(&state).is_err());
+        state.set("date", "yesterday");
+        assert!(Usage::restore(&state).is_err());
+    }
+
+    #[test]
+    fn formats_seconds() {
+        assert_eq!(format_seconds(59), "0m");
+        assert_eq!(format_seconds(42 * 60), "42m");
+        assert_eq!(format_seconds(3900), "1h05m");
+    }
+}
@@ -0,0 +1,475 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use futures::Stream;
+use log::{debug, warn};
+use tokio_core::reactor::Handle;
+use xcb_util::ewmh;
+
+use super::{Area, LayoutHandler, Placement, Widget, WidgetStream};
+use crate::bar::XcbEventStream;
+use crate::error::{format_err, ResultExt};
+use crate::text::{Attributes, Text};
+use crate::{xcb_ext, Cnx, Result};
+
+/// The opcode of a `_NET_SYSTEM_TRAY_OPCODE` message asking to dock an icon.
+const SYSTEM_TRAY_REQUEST_DOCK: u32 = 0;
+/// The `_XEMBED` message telling a client that its window has been embedded.
+const XEMBED_EMBEDDED_NOTIFY: u32 = 0;
+
+fn intern_atom(conn: &xcb::Connection, name: &str) -> Result<xcb::Atom> {
+    let reply = xcb::intern_atom(conn, false, name)
+        .get_reply()
+        .with_context(|_| format!("Failed to intern atom: {}", name))?;
+    Ok(reply.atom())
+}
+
+/// Returns the position of each icon's window within the container, which
+/// starts at the first of the `texts`, for an icon `size` pixels square
+/// centred in each of them.
+fn icon_positions(texts: &[Area], size: u16) -> Vec<(i16, i16)> {
+    let (left, top) = texts.first().map_or((0.0, 0.0), |area| (area.x, area.y));
+    let size = f64::from(size);
+    texts
+        .iter()
+        .map(|area| {
+            let x = area.x - left + (area.width - size) / 2.0;
+            let y = area.y - top + (area.height - size) / 2.0;
+            (x.max(0.0) as i16, y.max(0.0) as i16)
+        })
+        .collect()
+}
+
+struct Icon {
+    window: xcb::Window,
+    // Whether the icon has been reparented into the container yet.
+    embedded: bool,
+}
+
+/// The icons docked in the tray, and the window they are shown in.
+///
+/// This is shared between the widget's stream, which adds and removes icons,
+/// and its layout handler, which moves them to match the widget's texts.
+struct Dock {
+    conn: Option<Rc<ewmh::Connection>>,
+    // The bar the icons are shown on, and the window within it which holds
+    // them. Both are set once the widget is first laid out.
+    bar: Option<xcb::Window>,
+    container: Option<xcb::Window>,
+    icons: Vec<Icon>,
+    icon_size: u16,
+    background: u32,
+    // The root window's depth, visual and colormap. Icons are made with
+    // these, and can't be embedded in a window with a different depth, which
+    // a translucent bar has.
+    visual: (u8, xcb::Visualid, xcb::Colormap),
+}
+
+impl Dock {
+    /// Moves the container, and the icons in it, to where the widget's texts
+    /// are on the bar.
+    fn place(&mut self, placement: Placement) -> Result<()> {
+        let conn = match self.conn {
+            Some(ref conn) => conn.clone(),
+            None => return Ok(()),
+        };
+        // X windows can only be in one place, so the icons are only shown on
+        // the first bar which the widget is laid out on.
+        if *self.bar.get_or_insert(placement.window) != placement.window {
+            return Ok(());
+        }
+
+        let first = match placement.texts.first() {
+            Some(area) => *area,
+            None => {
+                // The widget has been hidden to make room for others.
+                if let Some(container) = self.container {
+                    xcb::unmap_window(&conn, container);
+                    conn.flush();
+                }
+                return Ok(());
+            }
+        };
+        let container = match self.container {
+            Some(container) => container,
+            None => {
+                let container = conn.generate_id();
+                let (depth, visual, colormap) = self.visual;
+                let values = [
+                    (xcb::CW_BACK_PIXEL, self.background),
+                    (xcb::CW_BORDER_PIXEL, 0),
+                    (xcb::CW_COLORMAP, colormap),
+                ];
+                xcb::create_window_checked(
+                    &conn,
+                    depth,
+                    container,
+                    placement.window,
+                    0,
+                    0,
+                    1,
+                    1,
+                    0,
+                    xcb::WINDOW_CLASS_INPUT_OUTPUT as u16,
+                    visual,
+                    &values,
+                )
+                .request_check()
+                .context("Failed to create system tray window")?;
+                self.container = Some(container);
+                container
+            }
+        };
+
+        // The texts are side by side, or stacked on a vertical bar.
+        let (right, bottom) =
+            placement
+                .texts
+                .iter()
+                .fold((first.x, first.y), |(right, bottom), area| {
+                    (
+                        right.max(area.x + area.width),
+                        bottom.max(area.y + area.height),
+                    )
+                });
+        let values = [
+            (xcb::CONFIG_WINDOW_X as u16, first.x as i32 as u32),
+            (xcb::CONFIG_WINDOW_Y as u16, first.y as i32 as u32),
+            (
+                xcb::CONFIG_WINDOW_WIDTH as u16,
+                (right - first.x).max(1.0) as u32,
+            ),
+            (
+                xcb::CONFIG_WINDOW_HEIGHT as u16,
+                (bottom - first.y).max(1.0) as u32,
+            ),
+        ];
+        xcb::configure_window(&conn, container, &values);
+        xcb::map_window(&conn, container);
+
+        let positions = icon_positions(&placement.texts, self.icon_size);
+        for (icon, &(x, y)) in self.icons.iter_mut().zip(&positions) {
+            if !icon.embedded {
+                debug!("Embedding tray icon: {}", icon.window);
+                // Icons show the container's background around them.
+                let values = [(xcb::CW_BACK_PIXMAP, xcb::BACK_PIXMAP_PARENT_RELATIVE)];
+                xcb::change_window_attributes(&conn, icon.window, &values);
+                xcb::reparent_window(&conn, icon.window, container, x, y);
+                // If Cnx exits, the X server gives the icon back to the root
+                // window, rather than destroying it.
+                xcb::change_save_set(&conn, xcb::SET_MODE_INSERT as u8, icon.window);
+                let xembed = intern_atom(&conn, "_XEMBED")?;
+                let data = [xcb::CURRENT_TIME, XEMBED_EMBEDDED_NOTIFY, 0, container, 0];
+                let event = xcb::ClientMessageEvent::new(
+                    32,
+                    icon.window,
+                    xembed,
+                    xcb::ClientMessageData::from_data32(data),
+                );
+                xcb::send_event(&conn, false, icon.window, xcb::EVENT_MASK_NO_EVENT, &event);
+                icon.embedded = true;
+            }
+            let values = [
+                (xcb::CONFIG_WINDOW_X as u16, x as i32 as u32),
+                (xcb::CONFIG_WINDOW_Y as u16, y as i32 as u32),
+                (xcb::CONFIG_WINDOW_WIDTH as u16, u32::from(self.icon_size)),
+                (xcb::CONFIG_WINDOW_HEIGHT as u16, u32::from(self.icon_size)),
+            ];
+            xcb::configure_window(&conn, icon.window, &values);
+            xcb::map_window(&conn, icon.window);
+        }
+        conn.flush();
+        Ok(())
+    }
+
+    /// Forgets the icon with the given `window`, returning whether it was
+    /// docked.
+    fn remove(&mut self, window: xcb::Window) -> bool {
+        let len = self.icons.len();
+        self.icons.retain(|icon| icon.window != window);
+        self.icons.len() != len
+    }
+}
+
+/// Shows a system tray, in which applications can dock their icons.
+///
+/// This widget implements the [System Tray Protocol], which applications
+/// like `nm-applet` use to show an icon (and often a menu) next to the other
+/// widgets. Each icon is a window belonging to its application, which the
+/// tray embeds in the bar using [XEmbed]. Clicks on the icons go straight to
+/// their applications.
+///
+/// Only one program can be the system tray on each X screen, so Cnx will fail
+/// to start if another tray is already running. When Cnx shows a bar on more
+/// than one monitor, the icons are only shown on the first bar which shows
+/// the tray (see [`WidgetOptions::monitor()`]).
+///
+/// The icons are shown on the widget's `bg_color` (or black, if it hasn't
+/// one), rather than on any section background.
+///
+/// [System Tray Protocol]: https://specifications.freedesktop.org/systemtray-spec/systemtray-spec-latest.html
+/// [XEmbed]: https://specifications.freedesktop.org/xembed-spec/xembed-spec-latest.html
+/// [`WidgetOptions::monitor()`]: struct.WidgetOptions.html#method.monitor
+pub struct Tray {
+    handle: Handle,
+    attr: Attributes,
+    dock: Rc<RefCell<Dock>>,
+}
+
+impl Tray {
+    /// Creates a new Tray widget.
+    ///
+    /// Creates a new `Tray` widget. Each icon is 20 pixels square (unless
+    /// another size is given with [`with_icon_size()`]), surrounded by the
+    /// `padding` of the given [`Attributes`].
+    ///
+    /// The [`Cnx`] instance is borrowed during construction in order to get
+    /// access to handles of its event loop. However, it is not borrowed for the
+    /// lifetime of the widget. See the [`cnx_add_widget!()`] for more discussion
+    /// about the lifetime of the borrow.
+    ///
+    /// [`with_icon_size()`]: #method.with_icon_size
+    /// [`Attributes`]: ../text/struct.Attributes.html
+    /// [`Cnx`]: ../struct.Cnx.html
+    /// [`cnx_add_widget!()`]: ../macro.cnx_add_widget.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use cnx::*;
+    /// # use cnx::text::*;
+    /// # use cnx::widgets::*;
+    /// #
+    /// # fn run() -> ::cnx::Result<()> {
+    /// let attr = Attributes {
+    ///     font: Font::new("SourceCodePro 21"),
+    ///     fg_color: "ffffff".parse().unwrap(),
+    ///     bg_color: None,
+    ///     padding: Padding::new(4.0, 4.0, 0.0, 0.0),
+    ///     border: None,
+    /// };
+    ///
+    /// let mut cnx = Cnx::new(Position::Top)?;
+    /// cnx.add_widget(Tray::new(&cnx, attr.clone()).with_icon_size(24));
+    /// # Ok(())
+    /// # }
+    /// # fn main() { run().unwrap(); }
+    /// ```
+    pub fn new(cnx: &Cnx, attr: Attributes) -> Tray {
+        let background = attr.bg_color.clone().unwrap_or_default().to_rgb24();
+        Tray {
+            handle: cnx.handle(),
+            attr,
+            dock: Rc::new(RefCell::new(Dock {
+                conn: None,
+                bar: None,
+                container: None,
+                icons: Vec::new(),
+                icon_size: 20,
+                background,
+                visual: (
+                    xcb::COPY_FROM_PARENT as u8,
+                    xcb::COPY_FROM_PARENT,
+                    xcb::COPY_FROM_PARENT,
+                ),
+            })),
+        }
+    }
+
+    /// Sets the width and height of each icon, in pixels. Defaults to 20.
+    pub fn with_icon_size(self, size: u16) -> Tray {
+        self.dock.borrow_mut().icon_size = size;
+        self
+    }
+}
+
+impl Widget for Tray {
+    fn stream(self: Box<Self>) -> Result<WidgetStream> {
+        let (conn, screen_idx) = xcb_ext::connect()?;
+        let (root, root_depth, root_visual, colormap) = {
+            let screen = conn
+                .get_setup()
+                .roots()
+                .nth(screen_idx as usize)
+                .ok_or_else(|| format_err!("Invalid screen"))?;
+            (
+                screen.root(),
+                screen.root_depth(),
+                screen.root_visual(),
+                screen.default_colormap(),
+            )
+        };
+
+        let selection = intern_atom(&conn, &format!("_NET_SYSTEM_TRAY_S{}", screen_idx))?;
+        let opcode = intern_atom(&conn, "_NET_SYSTEM_TRAY_OPCODE")?;
+        let owner = xcb::get_selection_owner(&conn, selection)
+            .get_reply()
+            .context("Failed to find the system tray")?
+            .owner();
+        if owner != xcb::WINDOW_NONE {
+            return Err(format_err!(
+                "Another system tray is already running (window {})",
+                owner
+            ));
+        }
+
+        // The tray's selection is owned by a window nobody sees.
+        let window = conn.generate_id();
+        xcb::create_window(
+            &conn,
+            0,
+            window,
+            root,
+            -1,
+            -1,
+            1,
+            1,
+            0,
+            xcb::WINDOW_CLASS_INPUT_ONLY as u16,
+            xcb::COPY_FROM_PARENT,
+            &[],
+        );
+        xcb::set_selection_owner(&conn, window, selection, xcb::CURRENT_TIME);
+        let owner = xcb::get_selection_owner(&conn, selection)
+            .get_reply()
+            .context("Failed to become the system tray")?
+            .owner();
+        if owner != window {
+            return Err(format_err!("Failed to become the system tray"));
+        }
+
+        // Icons are laid out horizontally, and should use the root window's
+        // visual, like the window they are embedded in.
+        let orientation = intern_atom(&conn, "_NET_SYSTEM_TRAY_ORIENTATION")?;
+        let visual = intern_atom(&conn, "_NET_SYSTEM_TRAY_VISUAL")?;
+        xcb::change_property(
+            &conn,
+            xcb::PROP_MODE_REPLACE as u8,
+            window,
+            orientation,
+            xcb::ATOM_CARDINAL,
+            32,
+            &[0u32],
+        );
+        xcb::change_property(
+            &conn,
+            xcb::PROP_MODE_REPLACE as u8,
+            window,
+            visual,
+            xcb::ATOM_VISUALID,
+            32,
+            &[root_visual],
+        );
+
+        // Tell any applications which are waiting for a tray that it's here.
+        let manager = intern_atom(&conn, "MANAGER")?;
+        let data = [xcb::CURRENT_TIME, selection, window, 0, 0];
+        let event = xcb::ClientMessageEvent::new(
+            32,
+            root,
+            manager,
+            xcb::ClientMessageData::from_data32(data),
+        );
+        xcb::send_event(&conn, false, root, xcb::EVENT_MASK_STRUCTURE_NOTIFY, &event);
+        conn.flush();
+
+        {
+            let mut dock = self.dock.borrow_mut();
+            dock.conn = Some(conn.clone());
+            dock.visual = (root_depth, root_visual, colormap);
+        }
+        let dock = self.dock.clone();
+        let mut attr = self.attr.clone();
+        attr.padding.left += f64::from(self.dock.borrow().icon_size);
+        let events = XcbEventStream::new(conn.clone(), &self.handle)?;
+        let stream = events
+            .filter_map(move |event| {
+                let mut dock = dock.borrow_mut();
+                let changed = match event.response_type() & !0x80 {
+                    xcb::CLIENT_MESSAGE => {
+                        let event: &xcb::ClientMessageEvent = unsafe { xcb::cast_event(&event) };
+                        let data = event.data().data32();
+                        let icon = data[2];
+                        if event.type_() != opcode
+                            || data[1] != SYSTEM_TRAY_REQUEST_DOCK
+                            || dock.icons.iter().any(|i| i.window == icon)
+                        {
+                            return None;
+                        }
+                        debug!("Docking tray icon: {}", icon);
+                        // Notice when the icon goes away.
+                        let values = [(xcb::CW_EVENT_MASK, xcb::EVENT_MASK_STRUCTURE_NOTIFY)];
+                        xcb::change_window_attributes(&conn, icon, &values);
+                        conn.flush();
+                        dock.icons.push(Icon {
+                            window: icon,
+                            embedded: false,
+                        });
+                        true
+                    }
+                    xcb::DESTROY_NOTIFY => {
+                        let event: &xcb::DestroyNotifyEvent = unsafe { xcb::cast_event(&event) };
+                        dock.remove(event.window())
+                    }
+                    xcb::REPARENT_NOTIFY => {
+                        // The application has taken its icon back.
+                        let event: &xcb::ReparentNotifyEvent = unsafe { xcb::cast_event(&event) };
+                        let embedded = dock
+                            .icons
+                            .iter()
+                            .any(|icon| icon.window == event.window() && icon.embedded);
+                        embedded
+                            && Some(event.parent()) != dock.container
+                            && dock.remove(event.window())
+                    }
+                    xcb::SELECTION_CLEAR => {
+                        warn!("Another program has taken over the system tray");
+                        false
+                    }
+                    _ => false,
+                };
+                if changed {
+                    Some(dock.icons.len())
+                } else {
+                    None
+                }
+            })
+            .map(move |icons| {
+                // Each icon is drawn over a blank text, which reserves its
+                // space on the bar.
+                (0..icons)
+                    .map(|_| Text::new(attr.clone(), String::new()))
+                    .collect()
+            });
+
+        Ok(Box::new(stream))
+    }
+
+    fn layout_handler(&mut self) -> Option<LayoutHandler> {
+        let dock = self.dock.clone();
+        Some(Box::new(move |placement| {
+            dock.borrow_mut().place(placement)
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::icon_positions;
+    use crate::widgets::Area;
+
+    #[test]
+    fn centres_icons() {
+        let area = |x| Area {
+            x,
+            y: 0.0,
+            width: 28.0,
+            height: 24.0,
+        };
+        let texts = [area(100.0), area(128.0), area(156.0)];
+        assert_eq!(icon_positions(&texts, 20), [(4, 2), (32, 2), (60, 2)]);
+        // Icons which are too big are kept inside the container.
+        assert_eq!(icon_positions(&texts[..1], 32), [(0, 0)]);
+        assert!(icon_positions(&[], 20).is_empty());
+    }
+}
@@ -0,0 +1,238 @@
+use std::process::Command;
+use std::rc::Rc;
+use std::time::Duration;
+
+use futures::{stream, Future, Stream};
+use tokio_core::reactor::Handle;
+
+use super::{Refresh, Widget, WidgetStream};
+use crate::error::{format_err, Error, ResultExt};
+use crate::json::{self, Value};
+use crate::process;
+use crate::text::{Attributes, Text};
+use crate::timer::Timer;
+use crate::{Cnx, Result};
+
+// The version of Cnx which is running.
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+// How long `curl` or `git` may take to check for a release before it is
+// killed.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Parses a release version, e.g. `0.2.0` or `v1.10.3`, into its numbers.
+/// Pre-releases (e.g. `1.0.0-beta.1`) aren't releases worth updating to, so
+/// return `None`.
+fn parse_version(version: &str) -> Option<(u64, u64, u64)> {
+    let version = version.trim().trim_start_matches('v');
+    let mut numbers = version.split('.').map(|number| number.parse().ok());
+    let parsed = (numbers.next()??, numbers.next()??, numbers.next()??);
+    match numbers.next() {
+        Some(_) => None,
+        None => Some(parsed),
+    }
+}
+
+/// Finds the newest release version in the output of `git ls-remote --tags`,
+/// whose lines are in the form `<sha>\trefs/tags/<tag>`.
+fn newest_tag(output: &str) -> Option<String> {
+    output
+        .lines()
+        .filter_map(|line| line.split('\t').nth(1))
+        .filter_map(|tag| tag.trim().strip_prefix("refs/tags/"))
+        .filter_map(|tag| Some((parse_version(tag)?, tag)))
+        .max()
+        .map(|(_, tag)| tag.trim_start_matches('v').to_owned())
+}
+
+/// Finds the newest stable version in crates.io's response about Cnx.
+fn crates_io_version(response: &[u8]) -> Result<String> {
+    let string = std::str::from_utf8(response).context("Invalid UTF-8 from crates.io")?;
+    let krate = json::parse(string).context("Failed to parse crates.io response")?;
+    let krate = krate.get("crate");
+    krate
+        .and_then(|krate| krate.get("max_stable_version"))
+        .or_else(|| krate.and_then(|krate| krate.get("max_version")))
+        .and_then(Value::as_str)
+        .map(ToOwned::to_owned)
+        .ok_or_else(|| format_err!("Unexpected format of crates.io response"))
+}
+
+/// Where to look for new releases of Cnx.
+enum Source {
+    CratesIo,
+    GitTags(String),
+}
+
+/// Shows when a newer version of Cnx has been released.
+///
+/// This widget checks once a day whether there is a newer release of Cnx than
+/// the one that is running, and if so shows its version, e.g. `⬆ 0.3.0`.
+/// Otherwise, it is hidden entirely. Pre-releases are ignored.
+///
+/// By default, the latest release on [crates.io] is checked, using `curl`, so
+/// requires `curl` to be available in the `PATH`. Bars built from a fork (or
+/// from git) can check its tags instead, see [`with_git_tags()`]. A check
+/// which hasn't finished within 30 seconds is given up on, and fails like
+/// any other.
+///
+/// [crates.io]: https://crates.io/crates/cnx
+/// [`with_git_tags()`]: #method.with_git_tags
+pub struct UpdateCheck {
+    timer: Timer,
+    handle: Handle,
+    update_interval: Duration,
+    attr: Attributes,
+    source: Source,
+}
+
+impl UpdateCheck {
+    /// Creates a new UpdateCheck widget.
+    ///
+    /// Creates a new `UpdateCheck` widget, whose text will be displayed with
+    /// the given [`Attributes`].
+    ///
+    /// The [`Cnx`] instance is borrowed during construction in order to get
+    /// access to handles of its event loop. However, it is not borrowed for the
+    /// lifetime of the widget. See the [`cnx_add_widget!()`] for more discussion
+    /// about the lifetime of the borrow.
+    ///
+    /// [`Attributes`]: ../text/struct.Attributes.html
+    /// [`Cnx`]: ../struct.Cnx.html
+    /// [`cnx_add_widget!()`]: ../macro.cnx_add_widget.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use cnx::*;
+    /// # use cnx::text::*;
+    /// # use cnx::widgets::*;
+    /// #
+    /// # fn run() -> ::cnx::Result<()> {
+    /// let attr = Attributes {
+    ///     font: Font::new("SourceCodePro 21"),
+    ///     fg_color: "ffffff".parse().unwrap(),
+    ///     bg_color: None,
+    ///     padding: Padding::new(8.0, 8.0, 0.0, 0.0),
+    ///     border: None,
+    /// };
+    ///
+    /// let mut cnx = Cnx::new(Position::Top)?;
+    /// cnx.add_widget(UpdateCheck::new(&cnx, attr.clone()));
+    /// # Ok(())
+    /// # }
+    /// # fn main() { run().unwrap(); }
+    /// ```
+    pub fn new(cnx: &Cnx, attr: Attributes) -> UpdateCheck {
+        UpdateCheck {
+            timer: cnx.timer(),
+            handle: cnx.handle(),
+            update_interval: Duration::from_secs(24 * 60 * 60),
+            attr,
+            source: Source::CratesIo,
+        }
+    }
+
+    /// Checks the release tags (e.g. `v0.3.0`) of the git repository at
+    /// `url`, rather than crates.io. This requires `git` to be available in
+    /// the `PATH`.
+    pub fn with_git_tags<S: Into<String>>(mut self, url: S) -> UpdateCheck {
+        self.source = Source::GitTags(url.into());
+        self
+    }
+
+    /// Returns the version of the newest release.
+    fn latest_version(&self) -> Box<dyn Future<Item = String, Error = Error>> {
+        match self.source {
+            Source::CratesIo => {
+                let mut curl = Command::new("curl");
+                curl.args(["--silent", "--fail", "--max-time", "10", "--user-agent"])
+                    .arg(format!("cnx/{} (update check)", VERSION))
+                    .arg("https://crates.io/api/v1/crates/cnx");
+                let output = process::output(&self.handle, &self.timer, curl, TIMEOUT);
+                Box::new(output.and_then(|(status, stdout)| {
+                    if !status.success() {
+                        return Err(format_err!("Failed to fetch Cnx from crates.io"));
+                    }
+                    crates_io_version(&stdout)
+                }))
+            }
+            Source::GitTags(ref url) => {
+                let mut git = Command::new("git");
+                git.args(["ls-remote", "--tags", "--refs", url]);
+                let output = process::output(&self.handle, &self.timer, git, TIMEOUT);
+                let url = url.clone();
+                Box::new(output.and_then(move |(status, stdout)| {
+                    if !status.success() {
+                        return Err(format_err!("Failed to list tags of {}", url));
+                    }
+                    let string = String::from_utf8(stdout).context("Invalid UTF-8 in tags")?;
+                    newest_tag(&string).ok_or_else(|| format_err!("No release tags in {}", url))
+                }))
+            }
+        }
+    }
+
+    fn tick(self: Rc<Self>) -> Box<dyn Future<Item = Vec<Text>, Error = Error>> {
+        Box::new(self.latest_version().map(move |latest| {
+            let newer = match (parse_version(&latest), parse_version(VERSION)) {
+                (Some(latest), Some(running)) => latest > running,
+                _ => false,
+            };
+            if !newer {
+                return Vec::new();
+            }
+
+            vec![Text::new(self.attr.clone(), format!("⬆ {}", latest))]
+        }))
+    }
+}
+
+impl Widget for UpdateCheck {
+    fn stream(self: Box<Self>) -> Result<WidgetStream> {
+        self.refreshable_stream(Box::new(stream::empty()))
+    }
+
+    fn refreshable_stream(self: Box<Self>, refresh: Refresh) -> Result<WidgetStream> {
+        // The Timer will only fire after the first interval, so check
+        // straight away too, and whenever we're asked to refresh.
+        let pace = self.timer.pace(self.update_interval);
+        let ticks = stream::once::<_, Error>(Ok(())).chain(self.timer.paced(&pace).select(refresh));
+        let update_check = Rc::new(*self);
+        let mut last = None;
+        let texts = ticks
+            .and_then(move |()| update_check.clone().tick())
+            .inspect(move |texts| {
+                if pace.is_adaptive() {
+                    pace.record(last.as_ref() != Some(texts));
+                    last = Some(texts.clone());
+                }
+            });
+        Ok(Box::new(texts))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{crates_io_version, newest_tag, parse_version};
+
+    #[test]
+    fn works() {
+        assert_eq!(parse_version("0.2.0"), Some((0, 2, 0)));
+        assert_eq!(parse_version("v1.10.3"), Some((1, 10, 3)));
+        assert_eq!(parse_version("1.0.0-beta.1"), None);
+        assert_eq!(parse_version("1.0"), None);
+
+        let output = "1a2b\trefs/tags/v0.2.0\n\
+                      3c4d\trefs/tags/v0.10.1\n\
+                      5e6f\trefs/tags/v1.0.0-rc.1\n\
+                      7a8b\trefs/tags/v0.9.0\n";
+        assert_eq!(newest_tag(output), Some("0.10.1".to_owned()));
+        assert_eq!(newest_tag(""), None);
+
+        let response =
+            br#"{"crate": {"max_version": "1.0.0-rc.1", "max_stable_version": "0.3.0"}}"#;
+        assert_eq!(crates_io_version(response).unwrap(), "0.3.0");
+        assert!(crates_io_version(b"{}").is_err());
+    }
+}
@@ -0,0 +1,181 @@
+use std::time::Duration;
+
+use futures::{stream, Future, Stream};
+use log::warn;
+use tokio_core::reactor::Handle;
+
+use crate::busctl::{Bus, Busctl};
+use crate::error::Error;
+use crate::text::{Attributes, Color, Text, Threshold};
+use crate::timer::Timer;
+use crate::upower::{self, Kind, State};
+use crate::widgets::Refresh;
+use crate::{Cnx, Result};
+
+/// Formats `time` as `H:MM`.
+fn format_time(time: Duration) -> String {
+    let minutes = time.as_secs() / 60;
+    format!("{}:{:02}", minutes / 60, minutes % 60)
+}
+
+/// Shows the charge of each battery, from UPower.
+///
+/// This widget is an alternative to [`Battery`], which asks [UPower] for the
+/// batteries that power the system, rather than reading `/sys`. It shows each
+/// battery's charge percentage, followed by the time until it is empty while
+/// discharging, or until it is full while charging, if UPower knows it, e.g.
+/// `(PP% - HH:MM)`. Laptops with more than one battery show each of them.
+///
+/// When a discharging battery has less than 10% charge remaining, its text
+/// will change to the specified `warning_color`, or other levels can be given
+/// with [`with_threshold()`].
+///
+/// The widget updates as soon as UPower reports a change, e.g. the charger
+/// being unplugged, and every minute in case a change is missed. It expects
+/// the `busctl` executable (part of `systemd`) and the `gdbus` executable
+/// (part of GLib) to be available in the `PATH`.
+///
+/// While any battery is discharging, Cnx switches to low-power mode (unless
+/// this has been disabled with [`Cnx::set_low_power()`]).
+///
+/// [`Battery`]: struct.Battery.html
+/// [UPower]: https://upower.freedesktop.org/
+/// [`Cnx::set_low_power()`]: ../struct.Cnx.html#method.set_low_power
+/// [`with_threshold()`]: #method.with_threshold
+pub struct UPowerBattery {
+    timer: Timer,
+    update_interval: Duration,
+    handle: Handle,
+    busctl: Busctl,
+    attr: Attributes,
+    // The attributes for low charge levels, while discharging.
+    threshold: Threshold<f64>,
+}
+
+impl UPowerBattery {
+    ///  Creates a new UPowerBattery widget.
+    ///
+    ///  Creates a new `UPowerBattery` widget, whose text will be displayed
+    ///  with the given [`Attributes`]. The caller can provide use the
+    ///  `warning_color` argument, to control the [`Color`] of the text once a
+    ///  battery has less than 10% charge remaining.
+    ///
+    ///  The [`Cnx`] instance is borrowed during construction in order to get
+    ///  access to handles of its event loop. However, it is not borrowed for
+    ///  the lifetime of the widget. See the [`cnx_add_widget!()`] for more
+    ///  discussion about the lifetime of the borrow.
+    ///
+    /// [`Attributes`]: ../text/struct.Attributes.html
+    /// [`Color`]: ../text/struct.Color.html
+    /// [`Cnx`]: ../struct.Cnx.html
+    /// [`cnx_add_widget!()`]: ../macro.cnx_add_widget.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #[macro_use]
+    /// # extern crate cnx;
+    /// #
+    /// # use cnx::*;
+    /// # use cnx::text::*;
+    /// # use cnx::widgets::*;
+    /// #
+    /// # fn run() -> ::cnx::Result<()> {
+    /// let attr = Attributes {
+    ///     font: Font::new("SourceCodePro 21"),
+    ///     fg_color: Color::white(),
+    ///     bg_color: None,
+    ///     padding: Padding::new(8.0, 8.0, 0.0, 0.0),
+    ///     border: None,
+    /// };
+    ///
+    /// let mut cnx = Cnx::new(Position::Top)?;
+    /// cnx_add_widget!(cnx, UPowerBattery::new(&cnx, attr.clone(), Color::red()));
+    /// # Ok(())
+    /// # }
+    /// # fn main() { run().unwrap(); }
+    /// ```
+    pub fn new(cnx: &Cnx, attr: Attributes, warning_color: Color) -> UPowerBattery {
+        let mut warning_attr = attr.clone();
+        warning_attr.fg_color = warning_color;
+        UPowerBattery {
+            timer: cnx.timer(),
+            update_interval: Duration::from_secs(60),
+            handle: cnx.handle(),
+            busctl: Busctl::new(cnx, Bus::System),
+            attr,
+            threshold: Threshold::falling().warn(10.0, warning_attr),
+        }
+    }
+
+    /// Shows each battery's charge percentage with the [`Attributes`] of the
+    /// levels of `threshold` while discharging, rather than in the
+    /// `warning_color` once it is 10% or less.
+    ///
+    /// [`Attributes`]: ../text/struct.Attributes.html
+    pub fn with_threshold(mut self, threshold: Threshold<f64>) -> UPowerBattery {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Returns a stream which yields whenever UPower reports a change.
+    fn upower_events(&self) -> Result<Refresh> {
+        match upower::monitor(&self.handle) {
+            Ok(lines) => Ok(Box::new(lines.map(|_| ()))),
+            Err(e) => {
+                warn!(
+                    "UPowerBattery won't notice changes until its next update: {}",
+                    e
+                );
+                Ok(Box::new(stream::empty()))
+            }
+        }
+    }
+
+    fn tick(&self) -> Box<dyn Future<Item = Vec<Text>, Error = Error>> {
+        let timer = self.timer.clone();
+        let attr = self.attr.clone();
+        let threshold = self.threshold.clone();
+        Box::new(upower::devices(&self.busctl).map(move |devices| {
+            let batteries = devices
+                .into_iter()
+                .filter(|device| {
+                    device.kind == Kind::Battery && device.power_supply && device.present
+                })
+                .collect::<Vec<_>>();
+
+            timer.power().set_discharging(
+                batteries
+                    .iter()
+                    .any(|battery| battery.state == State::Discharging),
+            );
+
+            batteries
+                .iter()
+                .map(|battery| {
+                    let time = match battery.state {
+                        State::Discharging => battery.time_to_empty,
+                        State::Charging => battery.time_to_full,
+                        _ => None,
+                    };
+                    let text = match time {
+                        Some(time) => {
+                            format!("({:.0}% - {})", battery.percentage, format_time(time))
+                        }
+                        None => format!("({:.0}%)", battery.percentage),
+                    };
+
+                    let attr = if battery.state == State::Discharging {
+                        threshold.apply(&battery.percentage, &attr)
+                    } else {
+                        attr.clone()
+                    };
+
+                    Text::new(attr, text)
+                })
+                .collect()
+        }))
+    }
+}
+
+timer_widget!(UPowerBattery, timer, update_interval, tick; upower_events);
@@ -0,0 +1,184 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use futures::{stream, Stream};
+
+use super::{Widget, WidgetStream};
+use crate::netlink::{Family, Netlink, NetlinkEvent};
+use crate::text::{Attributes, Text};
+use crate::{Cnx, Result};
+
+/// Combines a USB device's `manufacturer` and `product` strings into a name,
+/// e.g. `SanDisk Ultra`. Either may be missing, and products often already
+/// start with their manufacturer.
+fn device_name(manufacturer: Option<&str>, product: Option<&str>) -> Option<String> {
+    match (manufacturer, product) {
+        (Some(m), Some(p)) if p.starts_with(m) => Some(p.to_owned()),
+        (Some(m), Some(p)) => Some(format!("{} {}", m, p)),
+        (None, Some(p)) => Some(p.to_owned()),
+        (Some(m), None) => Some(m.to_owned()),
+        (None, None) => None,
+    }
+}
+
+/// Reads the name of the USB device at `dir` (in `/sys`), or `None` if it is
+/// a hub, which aren't worth mentioning.
+fn read_device(dir: &Path) -> Option<String> {
+    let read = |attribute| {
+        fs::read_to_string(dir.join(attribute))
+            .ok()
+            .map(|value| value.trim().to_owned())
+            .filter(|value| !value.is_empty())
+    };
+    if read("bDeviceClass").as_deref() == Some("09") {
+        return None;
+    }
+    let name = device_name(read("manufacturer").as_deref(), read("product").as_deref());
+    name.or_else(|| {
+        let vendor = read("idVendor")?;
+        let product = read("idProduct")?;
+        Some(format!("USB device {}:{}", vendor, product))
+    })
+}
+
+/// Briefly shows USB devices being plugged in and unplugged.
+///
+/// This widget is usually empty. When a USB device is plugged in, it shows
+/// the device's name for a few seconds, e.g. `+ SanDisk Ultra`, and when one
+/// is unplugged, it shows `- SanDisk Ultra`. USB hubs are ignored.
+///
+/// Devices are noticed as soon as the kernel reports them.
+pub struct UsbToast {
+    netlink: Netlink,
+    attr: Attributes,
+    duration: Duration,
+}
+
+impl UsbToast {
+    ///  Creates a new UsbToast widget.
+    ///
+    ///  Creates a new `UsbToast` widget, whose text will be displayed with the
+    ///  given [`Attributes`] for 5 seconds after each device is plugged in or
+    ///  unplugged.
+    ///
+    ///  The [`Cnx`] instance is borrowed during construction in order to get
+    ///  access to handles of its event loop. However, it is not borrowed for
+    ///  the lifetime of the widget. See the [`cnx_add_widget!()`] for more
+    ///  discussion about the lifetime of the borrow.
+    ///
+    /// [`Attributes`]: ../text/struct.Attributes.html
+    /// [`Cnx`]: ../struct.Cnx.html
+    /// [`cnx_add_widget!()`]: ../macro.cnx_add_widget.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #[macro_use]
+    /// # extern crate cnx;
+    /// #
+    /// # use cnx::*;
+    /// # use cnx::text::*;
+    /// # use cnx::widgets::*;
+    /// #
+    /// # fn run() -> ::cnx::Result<()> {
+    /// let attr = Attributes {
+    ///     font: Font::new("SourceCodePro 21"),
+    ///     fg_color: Color::white(),
+    ///     bg_color: None,
+    ///     padding: Padding::new(8.0, 8.0, 0.0, 0.0),
+    ///     border: None,
+    /// };
+    ///
+    /// let mut cnx = Cnx::new(Position::Top)?;
+    /// cnx_add_widget!(cnx, UsbToast::new(&cnx, attr.clone()));
+    /// # Ok(())
+    /// # }
+    /// # fn main() { run().unwrap(); }
+    /// ```
+    pub fn new(cnx: &Cnx, attr: Attributes) -> UsbToast {
+        UsbToast {
+            netlink: cnx.netlink(),
+            attr,
+            duration: Duration::from_secs(5),
+        }
+    }
+
+    /// Shows each device for `duration`, rather than 5 seconds.
+    pub fn with_duration(mut self, duration: Duration) -> UsbToast {
+        self.duration = duration;
+        self
+    }
+}
+
+impl Widget for UsbToast {
+    fn stream(self: Box<Self>) -> Result<WidgetStream> {
+        // The device's attributes are gone by the time it is unplugged, so
+        // remember the names of the devices which are already plugged in, and
+        // of each one that is plugged in later, by their path in /sys.
+        let mut names: HashMap<String, String> = HashMap::new();
+        if let Ok(entries) = fs::read_dir("/sys/bus/usb/devices") {
+            for entry in entries.filter_map(|entry| entry.ok()) {
+                let dir = match fs::canonicalize(entry.path()) {
+                    Ok(dir) => dir,
+                    Err(_) => continue,
+                };
+                if let (Ok(devpath), Some(name)) = (dir.strip_prefix("/sys"), read_device(&dir)) {
+                    names.insert(format!("/{}", devpath.display()), name);
+                }
+            }
+        }
+
+        let events = self
+            .netlink
+            .subscribe(Family::Uevent, |event| match *event {
+                NetlinkEvent::Uevent { ref subsystem, .. } => {
+                    subsystem == "usb" && event.var("DEVTYPE") == Some("usb_device")
+                }
+                _ => false,
+            })?;
+
+        let attr = self.attr.clone();
+        let duration = self.duration;
+        let toasts = events.filter_map(move |event| {
+            let (action, devpath) = match event {
+                NetlinkEvent::Uevent {
+                    action, devpath, ..
+                } => (action, devpath),
+                _ => return None,
+            };
+            let text = match action.as_str() {
+                "add" => {
+                    let name =
+                        read_device(&Path::new("/sys").join(devpath.trim_start_matches('/')))?;
+                    names.insert(devpath, name.clone());
+                    format!("+ {}", name)
+                }
+                "remove" => format!("- {}", names.remove(&devpath)?),
+                _ => return None,
+            };
+            Some(vec![Text::new(attr.clone(), text).with_timeout(duration)])
+        });
+
+        Ok(Box::new(stream::once(Ok(Vec::new())).chain(toasts)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::device_name;
+
+    #[test]
+    fn works() {
+        assert_eq!(
+            device_name(Some("SanDisk"), Some("Ultra")),
+            Some("SanDisk Ultra".to_owned())
+        );
+        assert_eq!(
+            device_name(Some("Logitech"), Some("Logitech USB Receiver")),
+            Some("Logitech USB Receiver".to_owned())
+        );
+        assert_eq!(device_name(None, None), None);
+    }
+}
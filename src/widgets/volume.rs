@@ -3,14 +3,14 @@ use std::os::unix::io::RawFd;
 
 use alsa::mixer::{SelemChannelId, SelemId};
 use alsa::{self, Mixer, PollDescriptors};
-use failure::{format_err, Error, ResultExt};
 use futures::{Async, Poll, Stream};
 use mio::event::Evented;
 use mio::unix::EventedFd;
 use mio::{self, PollOpt, Ready, Token};
 use tokio_core::reactor::{Handle, PollEvented};
 
-use super::{Widget, WidgetStream};
+use super::{Click, ClickHandler, Widget, WidgetStream};
+use crate::error::{format_err, Error, ResultExt};
 use crate::text::{Attributes, Text};
 use crate::{Cnx, Result};
 
@@ -19,10 +19,14 @@ use crate::{Cnx, Result};
 /// This widget shows the current volume of the default ALSA output, or '`M`' if
 /// the output is muted.
 ///
-/// The widget uses `alsa-lib` to receive events when the volume changes,
-/// avoiding expensive polling. If you do not have `alsa-lib` installed, you
-/// can disable the `volume-widget` feature on the `cnx` crate to avoid
-/// compiling this widget.
+/// Clicking on the widget toggles mute, and scrolling over it raises or
+/// lowers the volume.
+///
+/// The widget uses `alsa-lib` to receive events when the volume changes
+/// (whether from keys or other programs), so changes are shown immediately
+/// without polling, and the widget uses no CPU while the volume is unchanged.
+/// If you do not have `alsa-lib` installed, you can disable the
+/// `volume-widget` feature on the `cnx` crate to avoid compiling this widget.
 pub struct Volume {
     handle: Handle,
     attr: Attributes,
@@ -59,6 +63,7 @@ impl Volume {
     ///     fg_color: Color::white(),
     ///     bg_color: None,
     ///     padding: Padding::new(8.0, 8.0, 0.0, 0.0),
+    ///     border: None,
     /// };
     ///
     /// let mut cnx = Cnx::new(Position::Top)?;
@@ -84,6 +89,9 @@ impl Widget for Volume {
         // create a new mixer each time we get an event though.
         let mixer = Mixer::new(mixer_name, true)
             .with_context(|_| format!("Failed to open ALSA mixer: {}", mixer_name))?;
+        // Other controls (e.g. a microphone's) wake us up too, so only redraw
+        // when the output's volume has actually changed.
+        let mut shown = None;
         let stream = AlsaEventStream::new(&self.handle, mixer)?
             .and_then(move |()| {
                 // FrontLeft has special meaning in ALSA and is the channel
@@ -105,18 +113,48 @@ impl Widget for Volume {
                 } else {
                     "M".to_owned()
                 };
-
-                Ok(vec![Text {
-                    attr: self.attr.clone(),
-                    text: text,
-                    stretch: false,
-                }])
+                Ok(text)
             })
             .then(|r| r.context("Error getting ALSA volume information"))
-            .map_err(|e| e.into());
+            .filter(move |text| {
+                let changed = shown.as_ref() != Some(text);
+                shown = Some(text.clone());
+                changed
+            })
+            .map(move |text| vec![Text::new(self.attr.clone(), text)]);
 
         Ok(Box::new(stream))
     }
+
+    fn click_handler(&mut self) -> Option<ClickHandler> {
+        Some(Box::new(|click: Click| {
+            let mixer = Mixer::new("default", true)?;
+            let master = mixer
+                .find_selem(&SelemId::new("Master", 0))
+                .ok_or_else(|| format_err!("Couldn't open Master channel"))?;
+            let channel = SelemChannelId::FrontLeft;
+            let (min, max) = master.get_playback_volume_range();
+            // Scroll in steps of 5%.
+            let step = (max - min) / 20;
+            match click.button {
+                1 => {
+                    let switch = master.get_playback_switch(channel)?;
+                    master.set_playback_switch_all(if switch == 0 { 1 } else { 0 })?;
+                }
+                4 | 5 => {
+                    let volume = master.get_playback_volume(channel)?;
+                    let volume = if click.button == 4 {
+                        volume + step
+                    } else {
+                        volume - step
+                    };
+                    master.set_playback_volume_all(volume.max(min).min(max))?;
+                }
+                _ => {}
+            }
+            Ok(())
+        }))
+    }
 }
 
 struct AlsaEvented(Mixer);
@@ -0,0 +1,255 @@
+use std::cell::Cell;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::{Duration, SystemTime};
+
+use futures::{stream, Future, Stream};
+use log::warn;
+use tokio_core::reactor::Handle;
+
+use super::{Widget, WidgetStream};
+use crate::error::{Error, ResultExt};
+use crate::geoclue::{self, Location};
+use crate::http;
+use crate::text::{Attributes, Text};
+use crate::timer::Timer;
+use crate::weather::{self, IconSet, Observation};
+use crate::{Cnx, Result};
+
+const API_HOST: &str = "api.openweathermap.org";
+
+/// The default location of the cached weather: `$XDG_CACHE_HOME/cnx/weather`.
+fn default_cache_path() -> Option<PathBuf> {
+    env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        .map(|cache| cache.join("cnx").join("weather"))
+}
+
+/// The path of the current weather at `location`, on the API's host. The
+/// path includes the API key, in the clear.
+fn path(location: Location, api_key: &str) -> String {
+    format!(
+        "/data/2.5/weather?lat={}&lon={}&units=metric&appid={}",
+        location.latitude, location.longitude, api_key
+    )
+}
+
+/// Shows the current temperature and weather conditions, from
+/// OpenWeatherMap.
+///
+/// This widget fetches the current weather at a location from
+/// [OpenWeatherMap] every 15 minutes, and shows the temperature with an icon
+/// for the conditions, e.g. `🌧 13°C`. It needs an OpenWeatherMap API key,
+/// which can be created for free.
+///
+/// Cnx can't make HTTPS requests, so the weather is fetched over plain HTTP,
+/// and the API key is sent in cleartext with every request. Anyone on the
+/// network between you and OpenWeatherMap can read it, so use a key which
+/// isn't used for anything else.
+///
+/// The latest weather is cached in `$XDG_CACHE_HOME/cnx/weather`, so that it
+/// is shown straight away when Cnx starts, without fetching it again if it is
+/// recent. If the weather can't be fetched (e.g. when offline), a warning is
+/// logged and the last weather that was fetched is shown until it can be.
+///
+/// The weather can follow the computer's location with [GeoClue] instead of
+/// using fixed coordinates, see [`with_geoclue()`].
+///
+/// This widget is only available with the `weather-widget` feature.
+///
+/// [OpenWeatherMap]: https://openweathermap.org/
+/// [GeoClue]: https://gitlab.freedesktop.org/geoclue/geoclue/-/wikis/home
+/// [`with_geoclue()`]: #method.with_geoclue
+pub struct Weather {
+    timer: Timer,
+    update_interval: Duration,
+    handle: Handle,
+    attr: Attributes,
+    api_key: String,
+    location: Location,
+    geoclue: bool,
+    icon_set: IconSet,
+    cache_path: Option<PathBuf>,
+}
+
+impl Weather {
+    ///  Creates a new Weather widget.
+    ///
+    ///  Creates a new `Weather` widget, whose text will be displayed with the
+    ///  given [`Attributes`]. The widget shows the weather at `latitude` and
+    ///  `longitude`, using the OpenWeatherMap `api_key`.
+    ///
+    ///  The [`Cnx`] instance is borrowed during construction in order to get
+    ///  access to handles of its event loop. However, it is not borrowed for
+    ///  the lifetime of the widget. See the [`cnx_add_widget!()`] for more
+    ///  discussion about the lifetime of the borrow.
+    ///
+    /// [`Attributes`]: ../text/struct.Attributes.html
+    /// [`Cnx`]: ../struct.Cnx.html
+    /// [`cnx_add_widget!()`]: ../macro.cnx_add_widget.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #[macro_use]
+    /// # extern crate cnx;
+    /// #
+    /// # use cnx::*;
+    /// # use cnx::text::*;
+    /// # use cnx::widgets::*;
+    /// #
+    /// # fn run() -> ::cnx::Result<()> {
+    /// let attr = Attributes {
+    ///     font: Font::new("SourceCodePro 21"),
+    ///     fg_color: Color::white(),
+    ///     bg_color: None,
+    ///     padding: Padding::new(8.0, 8.0, 0.0, 0.0),
+    ///     border: None,
+    /// };
+    ///
+    /// let mut cnx = Cnx::new(Position::Top)?;
+    /// let api_key = std::env::var("OWM_API_KEY").unwrap_or_default();
+    /// cnx_add_widget!(cnx, Weather::new(&cnx, attr.clone(), api_key, 51.5, -0.12));
+    /// # Ok(())
+    /// # }
+    /// # fn main() { run().unwrap(); }
+    /// ```
+    pub fn new(
+        cnx: &Cnx,
+        attr: Attributes,
+        api_key: String,
+        latitude: f64,
+        longitude: f64,
+    ) -> Weather {
+        Weather {
+            timer: cnx.timer(),
+            update_interval: Duration::from_secs(15 * 60),
+            handle: cnx.handle(),
+            attr,
+            api_key,
+            location: Location::new(latitude, longitude),
+            geoclue: false,
+            icon_set: IconSet::default(),
+            cache_path: default_cache_path(),
+        }
+    }
+
+    /// Uses `icon_set` for the conditions, rather than emoji.
+    pub fn with_icon_set(mut self, icon_set: IconSet) -> Weather {
+        self.icon_set = icon_set;
+        self
+    }
+
+    /// Follows the computer's location with GeoClue, fetching the weather
+    /// again whenever it moves more than a few kilometres. The coordinates
+    /// given to [`new()`] are used until GeoClue finds the location, or if
+    /// it can't. This requires GeoClue's `where-am-i` demo to be installed.
+    ///
+    /// [`new()`]: #method.new
+    pub fn with_geoclue(mut self) -> Weather {
+        self.geoclue = true;
+        self
+    }
+
+    /// Returns the cached weather, and how long ago it was fetched.
+    fn load_cache(&self) -> Option<(Observation, Duration)> {
+        let path = self.cache_path.as_ref()?;
+        let body = fs::read_to_string(path).ok()?;
+        let age = fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+            .unwrap_or(self.update_interval);
+        match weather::parse_owm(&body) {
+            Ok(observation) => Some((observation, age)),
+            Err(e) => {
+                warn!("Ignoring invalid cached weather: {}", e);
+                None
+            }
+        }
+    }
+
+    fn save_cache(&self, body: &str) -> Result<()> {
+        if let Some(ref path) = self.cache_path {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)
+                    .with_context(|_| format!("Failed to create {}", parent.display()))?;
+            }
+            fs::write(path, body)
+                .with_context(|_| format!("Failed to write {}", path.display()))?;
+        }
+        Ok(())
+    }
+
+    fn render(&self, observation: Option<&Observation>) -> Vec<Text> {
+        let text = match observation {
+            Some(o) => format!(
+                "{} {:.0}°C",
+                self.icon_set.icon(o.condition, o.is_day),
+                o.temperature
+            ),
+            None => "?".to_owned(),
+        };
+        vec![Text::new(self.attr.clone(), text)]
+    }
+}
+
+impl Widget for Weather {
+    fn stream(self: Box<Self>) -> Result<WidgetStream> {
+        let (mut last, age) = match self.load_cache() {
+            Some((observation, age)) => (Some(observation), age),
+            None => (None, self.update_interval),
+        };
+        // Don't fetch the weather again until the cached weather is due to be
+        // updated.
+        let first_fetch = self
+            .timer
+            .sleep(self.update_interval.checked_sub(age).unwrap_or_default())
+            .map_err(Error::from)
+            .into_stream();
+        let ticks = first_fetch.chain(self.timer.interval(self.update_interval));
+
+        // Fetch the weather straight away whenever we move, too.
+        let location = Rc::new(Cell::new(self.location));
+        let ticks: Box<dyn Stream<Item = (), Error = Error>> = if self.geoclue {
+            let moved = location.clone();
+            let moves = geoclue::locations(&self.handle, self.location)
+                .map(move |new_location| moved.set(new_location));
+            Box::new(ticks.select(moves))
+        } else {
+            Box::new(ticks)
+        };
+
+        let handle = self.handle.clone();
+        let timer = self.timer.clone();
+        let api_key = self.api_key.clone();
+        let fetches = ticks.and_then(move |()| {
+            http::get(&handle, &timer, API_HOST, &path(location.get(), &api_key)).then(Ok)
+        });
+
+        let stream = stream::once(Ok(None))
+            .chain(fetches.map(Some))
+            .map(move |fetched| {
+                let parsed = fetched.map(|body| -> Result<_> {
+                    let body = body?;
+                    Ok((weather::parse_owm(&body)?, body))
+                });
+                match parsed {
+                    Some(Ok((observation, body))) => {
+                        last = Some(observation);
+                        if let Err(e) = self.save_cache(&body) {
+                            warn!("Failed to cache the weather: {}", e);
+                        }
+                    }
+                    Some(Err(e)) => warn!("Failed to update the weather: {}", e),
+                    None => {}
+                }
+                self.render(last.as_ref())
+            });
+
+        Ok(Box::new(stream))
+    }
+}
@@ -0,0 +1,211 @@
+use std::fs;
+use std::io;
+use std::mem;
+use std::time::Duration;
+
+use futures::{stream, Stream};
+use log::{debug, warn};
+
+use crate::error::{format_err, ResultExt};
+use crate::netlink::{Family, Netlink, NetlinkEvent};
+use crate::parse::proc;
+use crate::template::{Template, Value};
+use crate::text::{Attributes, Text};
+use crate::timer::Timer;
+use crate::widgets::Refresh;
+use crate::{Cnx, Result};
+
+// From <linux/wireless.h>.
+const SIOCGIWESSID: u32 = 0x8B1B;
+const IW_ESSID_MAX_SIZE: usize = 32;
+
+/// `struct iw_point`, from <linux/wireless.h>.
+#[repr(C)]
+struct IwPoint {
+    pointer: *mut libc::c_void,
+    length: u16,
+    flags: u16,
+}
+
+/// `struct iwreq`, from <linux/wireless.h>. The union is padded to the size
+/// of its largest member, a `struct sockaddr`.
+#[repr(C)]
+struct IwReq {
+    name: [libc::c_char; libc::IFNAMSIZ],
+    data: IwPoint,
+    _pad: [u8; 16],
+}
+
+/// Gets the SSID of the network that `interface` is connected to, if any,
+/// using the wireless extensions ioctl.
+fn ssid(interface: &str) -> Result<Option<String>> {
+    if interface.len() >= libc::IFNAMSIZ {
+        return Err(format_err!("Invalid interface name: {}", interface));
+    }
+
+    let mut essid = [0u8; IW_ESSID_MAX_SIZE + 1];
+    let mut request: IwReq = unsafe { mem::zeroed() };
+    for (dst, &src) in request.name.iter_mut().zip(interface.as_bytes()) {
+        *dst = src as libc::c_char;
+    }
+    request.data.pointer = essid.as_mut_ptr() as *mut libc::c_void;
+    request.data.length = essid.len() as u16;
+
+    let socket = unsafe { libc::socket(libc::AF_INET, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0) };
+    if socket < 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    let result = unsafe { libc::ioctl(socket, SIOCGIWESSID as _, &mut request) };
+    let error = io::Error::last_os_error();
+    unsafe {
+        libc::close(socket);
+    }
+    if result < 0 {
+        return Err(error).with_context(|_| format!("Failed to get SSID of {}", interface));
+    }
+
+    let length = (request.data.length as usize).min(IW_ESSID_MAX_SIZE);
+    let essid = &essid[..length];
+    let essid = essid.split(|&b| b == 0).next().unwrap_or(essid);
+    if essid.is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some(String::from_utf8_lossy(essid).into_owned()))
+    }
+}
+
+/// Shows the SSID and signal quality of a wireless network interface.
+///
+/// This widget shows the SSID of the network that the interface is connected
+/// to, and the quality of its link, e.g. `home (77%)`. Nothing is shown while
+/// the interface isn't connected to a network. Another format can be given
+/// with [`with_format()`].
+///
+/// The SSID is read using the kernel's wireless extensions, and the link
+/// quality from `/proc/net/wireless`. These are checked every 10 seconds, and
+/// immediately whenever the kernel reports that the interface has changed
+/// (e.g. because it has connected to or disconnected from a network).
+///
+/// [`with_format()`]: #method.with_format
+pub struct Wireless {
+    timer: Timer,
+    update_interval: Duration,
+    netlink: Netlink,
+    interface: String,
+    attr: Attributes,
+    format: Option<Template>,
+}
+
+impl Wireless {
+    ///  Creates a new Wireless widget.
+    ///
+    ///  Creates a new `Wireless` widget for the given network `interface`,
+    ///  e.g. `wlan0`, whose text will be displayed with the given
+    ///  [`Attributes`].
+    ///
+    ///  The [`Cnx`] instance is borrowed during construction in order to get
+    ///  access to handles of its event loop. However, it is not borrowed for
+    ///  the lifetime of the widget. See the [`cnx_add_widget!()`] for more
+    ///  discussion about the lifetime of the borrow.
+    ///
+    /// [`Attributes`]: ../text/struct.Attributes.html
+    /// [`Cnx`]: ../struct.Cnx.html
+    /// [`cnx_add_widget!()`]: ../macro.cnx_add_widget.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #[macro_use]
+    /// # extern crate cnx;
+    /// #
+    /// # use cnx::*;
+    /// # use cnx::text::*;
+    /// # use cnx::widgets::*;
+    /// #
+    /// # fn run() -> ::cnx::Result<()> {
+    /// let attr = Attributes {
+    ///     font: Font::new("SourceCodePro 21"),
+    ///     fg_color: Color::white(),
+    ///     bg_color: None,
+    ///     padding: Padding::new(8.0, 8.0, 0.0, 0.0),
+    ///     border: None,
+    /// };
+    ///
+    /// let mut cnx = Cnx::new(Position::Top)?;
+    /// cnx_add_widget!(cnx, Wireless::new(&cnx, attr.clone(), "wlan0".to_owned()));
+    /// # Ok(())
+    /// # }
+    /// # fn main() { run().unwrap(); }
+    /// ```
+    pub fn new(cnx: &Cnx, attr: Attributes, interface: String) -> Wireless {
+        Wireless {
+            timer: cnx.timer(),
+            update_interval: Duration::from_secs(10),
+            netlink: cnx.netlink(),
+            interface,
+            attr,
+            format: None,
+        }
+    }
+
+    /// Shows `format`, in which `{ssid}` is replaced by the network's SSID
+    /// and `{quality}` by the link quality as a percentage, e.g.
+    /// `{ssid:.10} {quality}%`. The link quality is left out if the driver
+    /// doesn't report it.
+    pub fn with_format(mut self, format: Template) -> Wireless {
+        self.format = Some(format);
+        self
+    }
+
+    /// Returns a stream which yields whenever the kernel reports a change to
+    /// our interface, so that we notice (dis)connections straight away.
+    fn link_events(&self) -> Result<Refresh> {
+        let interface = self.interface.clone();
+        let events = self
+            .netlink
+            .subscribe(Family::Route, move |event| match *event {
+                // The kernel doesn't always include the name.
+                NetlinkEvent::Link {
+                    name: Some(ref name),
+                    ..
+                } => *name == interface,
+                NetlinkEvent::Link { name: None, .. } => true,
+                _ => false,
+            });
+        match events {
+            Ok(events) => Ok(Box::new(events.map(|_| ()))),
+            Err(e) => {
+                warn!("Wireless won't notice link changes: {}", e);
+                Ok(Box::new(stream::empty()))
+            }
+        }
+    }
+
+    fn tick(&self) -> Result<Vec<Text>> {
+        // The interface may have been removed, or be switched off, which we
+        // treat the same as being disconnected.
+        let ssid = match ssid(&self.interface) {
+            Ok(Some(ssid)) => ssid,
+            Ok(None) => return Ok(Vec::new()),
+            Err(e) => {
+                debug!("Treating {} as disconnected: {}", self.interface, e);
+                return Ok(Vec::new());
+            }
+        };
+        let contents = fs::read_to_string("/proc/net/wireless").unwrap_or_default();
+        let quality = proc::link_quality(&contents, &self.interface);
+        let text = match (&self.format, quality) {
+            (Some(format), quality) => {
+                let mut values = vec![("ssid", Value::Text(ssid))];
+                values.extend(quality.map(|quality| ("quality", Value::Number(quality, 0))));
+                format.render(&values)
+            }
+            (None, Some(quality)) => format!("{} ({:.0}%)", ssid, quality),
+            (None, None) => ssid,
+        };
+
+        Ok(vec![Text::new(self.attr.clone(), text)])
+    }
+}
+
+timer_widget!(Wireless, timer, update_interval, tick; link_events);
@@ -0,0 +1,127 @@
+use std::collections::HashMap;
+
+use futures::{stream, Stream};
+use tokio_core::reactor::Handle;
+
+use super::{Widget, WidgetStream};
+use crate::error::Error;
+use crate::template;
+use crate::text::{Attributes, Text};
+use crate::{xcb_ext, Cnx, Result};
+
+/// Shows state which the WM (or a script) sets on the root window.
+///
+/// Many WMs and scripts publish their state as properties of the root window,
+/// e.g. XMonad's current layout, or whether gaps are on. This widget watches
+/// any properties given to [`with_property()`], each under a name, and shows
+/// them using a format string in which `{name}` is replaced by the property's
+/// value. It updates as soon as any of them change.
+///
+/// Strings are shown as they are, numbers in decimal and atoms by name, so
+/// a script can set a property with e.g. `xprop -root -f _GAPS 32c -set _GAPS
+/// 1`. Properties which aren't set are shown as nothing, and the widget is
+/// hidden entirely while its text is blank.
+///
+/// [`with_property()`]: #method.with_property
+pub struct WmState {
+    handle: Handle,
+    attr: Attributes,
+    format: String,
+    // (name, property) pairs.
+    properties: Vec<(String, String)>,
+}
+
+impl WmState {
+    /// Creates a new WM State widget.
+    ///
+    /// Creates a new `WmState` widget, whose text will be displayed with the
+    /// given [`Attributes`]. The widget shows `format`, e.g. `[{layout}]`.
+    ///
+    /// The [`Cnx`] instance is borrowed during construction in order to get
+    /// access to handles of its event loop. However, it is not borrowed for the
+    /// lifetime of the widget. See the [`cnx_add_widget!()`] for more discussion
+    /// about the lifetime of the borrow.
+    ///
+    /// [`Attributes`]: ../text/struct.Attributes.html
+    /// [`Cnx`]: ../struct.Cnx.html
+    /// [`cnx_add_widget!()`]: ../macro.cnx_add_widget.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use cnx::*;
+    /// # use cnx::text::*;
+    /// # use cnx::widgets::*;
+    /// #
+    /// # fn run() -> ::cnx::Result<()> {
+    /// let attr = Attributes {
+    ///     font: Font::new("SourceCodePro 21"),
+    ///     fg_color: Color::white(),
+    ///     bg_color: None,
+    ///     padding: Padding::new(8.0, 8.0, 0.0, 0.0),
+    ///     border: None,
+    /// };
+    ///
+    /// let mut cnx = Cnx::new(Position::Top)?;
+    /// let state = WmState::new(&cnx, attr.clone(), "[{layout}] gaps {gaps}".to_owned())
+    ///     .with_property("layout", "_XMONAD_LAYOUT")
+    ///     .with_property("gaps", "_GAPS");
+    /// cnx.add_widget(state);
+    /// # Ok(())
+    /// # }
+    /// # fn main() { run().unwrap(); }
+    /// ```
+    pub fn new(cnx: &Cnx, attr: Attributes, format: String) -> WmState {
+        WmState {
+            handle: cnx.handle(),
+            attr,
+            format,
+            properties: Vec::new(),
+        }
+    }
+
+    /// Watches the root window's `property`, e.g. `_XMONAD_LAYOUT`, showing
+    /// its value in place of `{name}`.
+    pub fn with_property(mut self, name: &str, property: &str) -> WmState {
+        self.properties
+            .push((name.to_lowercase(), property.to_owned()));
+        self
+    }
+
+    fn render(&self, values: &HashMap<String, String>) -> Vec<Text> {
+        let text = template::fill(&self.format, values);
+        if text.trim().is_empty() {
+            return Vec::new();
+        }
+        vec![Text::new(self.attr.clone(), text)]
+    }
+}
+
+impl Widget for WmState {
+    fn stream(self: Box<Self>) -> Result<WidgetStream> {
+        let (conn, screen_idx) = xcb_ext::connect()?;
+        let root = xcb_ext::root_window(&conn, screen_idx)?;
+        let atoms = self
+            .properties
+            .iter()
+            .map(|(name, property)| Ok((name.clone(), xcb_ext::intern_atom(&conn, property)?)))
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut values = atoms
+            .iter()
+            .map(|&(ref name, atom)| (name.clone(), xcb_ext::property_text(&conn, root, atom)))
+            .collect::<HashMap<_, _>>();
+        let initial = stream::once::<_, Error>(Ok(self.render(&values)));
+
+        let properties = atoms.iter().map(|&(_, atom)| atom).collect();
+        let changes = xcb_ext::watch_properties(&self.handle, conn.clone(), root, properties)?.map(
+            move |changed| {
+                for &(ref name, atom) in atoms.iter().filter(|&&(_, atom)| atom == changed) {
+                    values.insert(name.clone(), xcb_ext::property_text(&conn, root, atom));
+                }
+                self.render(&values)
+            },
+        );
+        Ok(Box::new(initial.chain(changes)))
+    }
+}
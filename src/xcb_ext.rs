@@ -0,0 +1,265 @@
+//! Helpers for widgets which read EWMH properties from the X server.
+//!
+//! These wrap the XCB plumbing which the built-in widgets (e.g. [`Pager`] and
+//! [`ActiveWindowTitle`]) use, so that widgets in other crates don't have to
+//! re-implement it. A widget typically [`connect()`]s, reads the properties
+//! it is interested in, and then uses [`watch_properties()`] to know when to
+//! read them again:
+//!
+//! ```no_run
+//! # use cnx::xcb_ext;
+//! # use futures::Stream;
+//! # use tokio_core::reactor::Core;
+//! #
+//! # fn run() -> ::cnx::Result<()> {
+//! # let core = Core::new()?;
+//! # let handle = core.handle();
+//! let (conn, screen_idx) = xcb_ext::connect()?;
+//! let root = xcb_ext::root_window(&conn, screen_idx)?;
+//! let properties = vec![conn.DESKTOP_NAMES()];
+//! let names = xcb_ext::watch_properties(&handle, conn.clone(), root, properties)?
+//!     .map(move |_| xcb_ext::desktop_names(&conn, screen_idx));
+//! # Ok(())
+//! # }
+//! # fn main() { run().unwrap(); }
+//! ```
+//!
+//! [`Pager`]: ../widgets/struct.Pager.html
+//! [`ActiveWindowTitle`]: ../widgets/struct.ActiveWindowTitle.html
+//! [`connect()`]: fn.connect.html
+//! [`watch_properties()`]: fn.watch_properties.html
+
+use std::rc::Rc;
+
+use futures::Stream;
+use tokio_core::reactor::Handle;
+use xcb::xproto::{PropertyNotifyEvent, PROPERTY_NOTIFY};
+use xcb_util::ewmh;
+
+use crate::bar::XcbEventStream;
+use crate::error::{Error, ResultExt};
+use crate::parse::ewmh as ewmh_parse;
+use crate::text::Icon;
+use crate::Result;
+
+/// The stream returned by [`watch_properties()`], which yields the atom of
+/// each watched property when it changes.
+///
+/// [`watch_properties()`]: fn.watch_properties.html
+pub type PropertyChanges = Box<dyn Stream<Item = xcb::Atom, Error = Error>>;
+
+/// Connects to the X server, returning an EWMH connection and the index of
+/// the default screen.
+pub fn connect() -> Result<(Rc<ewmh::Connection>, i32)> {
+    let (xcb_conn, screen_idx) =
+        xcb::Connection::connect(None).context("Failed to connect to X server")?;
+    let conn = ewmh::Connection::connect(xcb_conn)
+        .map_err(|(e, _)| e)
+        .context("Failed to wrap xcb::Connection in ewmh::Connection")?;
+    Ok((Rc::new(conn), screen_idx))
+}
+
+/// Returns the root window of the `screen_idx`th screen.
+pub fn root_window(conn: &ewmh::Connection, screen_idx: i32) -> Result<xcb::Window> {
+    Ok(conn
+        .get_setup()
+        .roots()
+        .nth(screen_idx as usize)
+        .ok_or_else(|| Error::X("Invalid screen".to_owned()))?
+        .root())
+}
+
+/// Asks the X server to send us `PropertyNotify` events for `window`.
+///
+/// [`watch_properties()`] does this for the window it is given. This is
+/// useful for also watching other windows on the same connection, e.g. the
+/// active window, so that changes to its title are seen.
+///
+/// [`watch_properties()`]: fn.watch_properties.html
+pub fn select_property_events(conn: &ewmh::Connection, window: xcb::Window) {
+    let attributes = [(xcb::CW_EVENT_MASK, xcb::EVENT_MASK_PROPERTY_CHANGE)];
+    xcb::change_window_attributes(conn, window, &attributes);
+    conn.flush();
+}
+
+/// Watches `properties` of `window`, returning a stream which yields the atom
+/// of each property when it changes.
+///
+/// Changes to these properties on any other window which has been watched on
+/// the same connection (see [`select_property_events()`]) are yielded too.
+///
+/// The stream reads every event for `conn`, so `conn` shouldn't be shared
+/// with anything else which reads events.
+///
+/// [`select_property_events()`]: fn.select_property_events.html
+pub fn watch_properties(
+    handle: &Handle,
+    conn: Rc<ewmh::Connection>,
+    window: xcb::Window,
+    properties: Vec<xcb::Atom>,
+) -> Result<PropertyChanges> {
+    select_property_events(&conn, window);
+    let stream = XcbEventStream::new(conn, handle)?.filter_map(move |event| {
+        if event.response_type() == PROPERTY_NOTIFY {
+            let event: &PropertyNotifyEvent = unsafe { xcb::cast_event(&event) };
+            if properties.contains(&event.atom()) {
+                return Some(event.atom());
+            }
+        }
+        None
+    });
+    Ok(Box::new(stream))
+}
+
+/// Returns the atom called `name`, e.g. `_XMONAD_LAYOUT`, creating it if it
+/// doesn't exist yet.
+pub fn intern_atom(conn: &ewmh::Connection, name: &str) -> Result<xcb::Atom> {
+    let reply = xcb::intern_atom(conn, false, name)
+        .get_reply()
+        .with_context(|_| format!("Failed to intern atom {}", name))?;
+    Ok(reply.atom())
+}
+
+/// Returns the value of the `property` of `window` if it is a list of 8-bit
+/// values, e.g. strings, or an empty list if it isn't set.
+fn property_bytes(
+    conn: &ewmh::Connection,
+    window: xcb::Window,
+    property: xcb::Atom,
+    type_: xcb::Atom,
+) -> Vec<u8> {
+    match xcb::get_property(conn, false, window, property, type_, 0, 1024).get_reply() {
+        Ok(ref reply) if reply.format() == 8 => reply.value::<u8>().to_vec(),
+        _ => Vec::new(),
+    }
+}
+
+/// Returns the value of the `property` of `window` as text, or an empty
+/// string if it isn't set.
+///
+/// Strings are returned as they are and numbers in decimal, while atoms are
+/// returned by name. A property with several values has them separated by
+/// spaces.
+pub fn property_text(conn: &ewmh::Connection, window: xcb::Window, property: xcb::Atom) -> String {
+    let reply =
+        xcb::get_property(conn, false, window, property, xcb::ATOM_ANY, 0, 1024).get_reply();
+    let reply = match reply {
+        Ok(reply) => reply,
+        Err(_) => return String::new(),
+    };
+    let values: Vec<String> = match (reply.format(), reply.type_()) {
+        (8, _) => return ewmh_parse::join_strings(reply.value::<u8>()),
+        (32, xcb::ATOM_ATOM) => reply
+            .value::<xcb::Atom>()
+            .iter()
+            .filter_map(|&atom| xcb::get_atom_name(conn, atom).get_reply().ok())
+            .map(|reply| reply.name().to_owned())
+            .collect(),
+        (32, xcb::ATOM_INTEGER) => reply
+            .value::<i32>()
+            .iter()
+            .map(ToString::to_string)
+            .collect(),
+        (32, _) => reply
+            .value::<u32>()
+            .iter()
+            .map(ToString::to_string)
+            .collect(),
+        (16, _) => reply
+            .value::<u16>()
+            .iter()
+            .map(ToString::to_string)
+            .collect(),
+        _ => Vec::new(),
+    };
+    values.join(" ")
+}
+
+/// Returns the name of each desktop, from `_NET_DESKTOP_NAMES`.
+///
+/// EWMH allows there to be more or fewer names than there are desktops
+/// (`_NET_NUMBER_OF_DESKTOPS`), so the names are truncated, or padded with
+/// `"?"` for unnamed desktops, so that there is exactly one per desktop.
+pub fn desktop_names(conn: &ewmh::Connection, screen_idx: i32) -> Vec<String> {
+    let number = ewmh::get_number_of_desktops(conn, screen_idx)
+        .get_reply()
+        .unwrap_or(0);
+    // Read as bytes, as the names needn't be valid UTF-8.
+    let names = match root_window(conn, screen_idx) {
+        Ok(root) => property_bytes(conn, root, conn.DESKTOP_NAMES(), xcb::ATOM_ANY),
+        Err(_) => Vec::new(),
+    };
+    ewmh_parse::desktop_names(ewmh_parse::utf8_strings(&names), number)
+}
+
+/// Returns the title of `window` (its `_NET_WM_NAME`), if it has one.
+///
+/// Unlike `ewmh::get_wm_name()`, this copes with titles which aren't valid
+/// UTF-8.
+pub fn window_title(conn: &ewmh::Connection, window: xcb::Window) -> Option<String> {
+    let title = property_bytes(conn, window, conn.WM_NAME(), xcb::ATOM_ANY);
+    ewmh_parse::utf8_strings(&title).into_iter().next()
+}
+
+/// Returns the class (the second string in `WM_CLASS`) of `window`.
+pub fn window_class(conn: &ewmh::Connection, window: xcb::Window) -> Option<String> {
+    let class = property_bytes(conn, window, xcb::ATOM_WM_CLASS, xcb::ATOM_STRING);
+    ewmh_parse::wm_class(&class)
+}
+
+/// Returns the icon of `window` (its `_NET_WM_ICON`) which is the best fit
+/// for showing at `size` pixels tall, if it has an icon.
+pub fn window_icon(conn: &ewmh::Connection, window: xcb::Window, size: u32) -> Option<Icon> {
+    // Enough for a few sizes of icon, up to 256 pixels square.
+    let reply = xcb::get_property(
+        conn,
+        false,
+        window,
+        conn.WM_ICON(),
+        xcb::ATOM_CARDINAL,
+        0,
+        128 * 1024,
+    )
+    .get_reply()
+    .ok()?;
+    if reply.format() != 32 {
+        return None;
+    }
+    let (width, height, pixels) = ewmh_parse::pick_icon(reply.value::<u32>(), size)?;
+    Icon::new(width, height, pixels.to_vec())
+}
+
+/// Sends a client message to the root window of the `screen_idx`th screen,
+/// as EWMH requires for requests to the WM, e.g. to change the current
+/// desktop.
+///
+/// `window` is the window the message is about, and `message_type` is the
+/// message's atom (e.g. `conn.CURRENT_DESKTOP()`). See the [EWMH spec] for the
+/// meaning of `data` for each message.
+///
+/// [EWMH spec]: https://specifications.freedesktop.org/wm-spec/wm-spec-latest.html
+pub fn send_client_message(
+    conn: &ewmh::Connection,
+    screen_idx: i32,
+    window: xcb::Window,
+    message_type: xcb::Atom,
+    data: [u32; 5],
+) -> Result<()> {
+    let root = root_window(conn, screen_idx)?;
+    let event = xcb::ClientMessageEvent::new(
+        32,
+        window,
+        message_type,
+        xcb::ClientMessageData::from_data32(data),
+    );
+    xcb::send_event_checked(
+        conn,
+        false,
+        root,
+        xcb::EVENT_MASK_SUBSTRUCTURE_NOTIFY | xcb::EVENT_MASK_SUBSTRUCTURE_REDIRECT,
+        &event,
+    )
+    .request_check()
+    .context("Failed to send client message")?;
+    Ok(())
+}
@@ -0,0 +1,538 @@
+//! Time zones from the system's time zone database, e.g. for showing the
+//! time in another zone with the [`Clock`].
+//!
+//! Zones are read from the compiled [`tzfile`]s under `/usr/share/zoneinfo`
+//! (or `$TZDIR`), which are installed on almost every Linux system and kept
+//! up to date by its package manager, so that Cnx doesn't need its own copy
+//! of the database. Times past a zone's last transition, which recent files
+//! leave out, follow the POSIX `TZ` rule at the end of the file.
+//!
+//! ```no_run
+//! use cnx::zoneinfo::Zone;
+//!
+//! # fn run() -> cnx::Result<()> {
+//! let tokyo = Zone::load("Asia/Tokyo")?;
+//! let (offset, abbreviation) = tokyo.offset_at(1_700_000_000);
+//! assert_eq!((offset.local_minus_utc(), abbreviation), (9 * 3600, "JST"));
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`Clock`]: ../widgets/struct.Clock.html
+//! [`tzfile`]: https://man7.org/linux/man-pages/man5/tzfile.5.html
+
+use std::convert::TryInto;
+use std::env;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use chrono::{Datelike, FixedOffset, NaiveDate, NaiveDateTime};
+
+use crate::error::{format_err, ResultExt};
+use crate::Result;
+
+/// The local time of a zone at some point, e.g. `CEST`, 2 hours ahead of UTC.
+#[derive(Clone, Debug, PartialEq)]
+struct LocalTime {
+    // Seconds ahead of UTC.
+    offset: i32,
+    abbreviation: String,
+}
+
+/// The day of the year on which a zone's rule starts or ends daylight saving
+/// time.
+#[derive(Clone, Debug, PartialEq)]
+enum RuleDay {
+    // `Jn`: from 1 to 365, never counting 29th February.
+    Julian(u16),
+    // `n`: from 0 to 365, counting 29th February.
+    Ordinal(u16),
+    // `Mm.w.d`: day `d` (0 is Sunday) of week `w` (5 is the last) of month
+    // `m`.
+    Weekday { month: u32, week: u32, day: u32 },
+}
+
+/// When a zone's rule starts or ends daylight saving time: a day, and the
+/// time on that day (in seconds, which may be negative or past midnight) in
+/// the local time which is ending.
+#[derive(Clone, Debug, PartialEq)]
+struct Change {
+    day: RuleDay,
+    time: i32,
+}
+
+/// A POSIX `TZ` rule, e.g. `CET-1CEST,M3.5.0,M10.5.0/3`.
+#[derive(Clone, Debug, PartialEq)]
+struct Rule {
+    std: LocalTime,
+    // Daylight saving time, with when it starts and ends.
+    dst: Option<(LocalTime, Change, Change)>,
+}
+
+/// A time zone, e.g. `Europe/Berlin`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Zone {
+    // The UTC time (in seconds since the epoch) from which each local time
+    // applies, as an index into `times`.
+    transitions: Vec<(i64, usize)>,
+    times: Vec<LocalTime>,
+    // What applies after the last transition, if the file says.
+    rule: Option<Rule>,
+}
+
+impl Zone {
+    /// Reads the zone with the given name from the time zone database, e.g.
+    /// `America/New_York` or `UTC`.
+    pub fn load(name: &str) -> Result<Zone> {
+        let relative = Path::new(name);
+        if relative
+            .components()
+            .any(|component| !matches!(component, Component::Normal(_)))
+        {
+            return Err(format_err!("Invalid time zone name {:?}", name));
+        }
+        let dir = env::var_os("TZDIR")
+            .map_or_else(|| PathBuf::from("/usr/share/zoneinfo"), PathBuf::from);
+        let path = dir.join(relative);
+        let data =
+            fs::read(&path).with_context(|_| format!("Failed to read {}", path.display()))?;
+        parse_tzif(&data).with_context(|_| format!("Failed to parse {}", path.display()))
+    }
+
+    /// UTC, which needs no database.
+    pub fn utc() -> Zone {
+        Zone {
+            transitions: Vec::new(),
+            times: vec![LocalTime {
+                offset: 0,
+                abbreviation: "UTC".to_owned(),
+            }],
+            rule: None,
+        }
+    }
+
+    /// Returns the zone's offset from UTC at `timestamp` (in seconds since the
+    /// epoch), and its abbreviation then, e.g. `CEST`.
+    pub fn offset_at(&self, timestamp: i64) -> (FixedOffset, &str) {
+        let local = self.local_time_at(timestamp);
+        // Offsets are always within a day of UTC.
+        let offset = FixedOffset::east_opt(local.offset).unwrap_or_else(|| FixedOffset::east(0));
+        (offset, &local.abbreviation)
+    }
+
+    fn local_time_at(&self, timestamp: i64) -> &LocalTime {
+        let last = self.transitions.last().map(|&(time, _)| time);
+        if let Some(ref rule) = self.rule {
+            if last.is_none_or(|last| timestamp >= last) {
+                return rule.local_time_at(timestamp);
+            }
+        }
+        let i = match self
+            .transitions
+            .binary_search_by_key(&timestamp, |&(time, _)| time)
+        {
+            Ok(i) => Some(i),
+            Err(0) => None,
+            Err(i) => Some(i - 1),
+        };
+        match i {
+            Some(i) => &self.times[self.transitions[i].1],
+            // Before the first transition, the first local time applies.
+            None => &self.times[0],
+        }
+    }
+}
+
+impl RuleDay {
+    /// Returns the date of this day in `year`.
+    fn date(&self, year: i32) -> Option<NaiveDate> {
+        match *self {
+            RuleDay::Julian(day) => {
+                let leap = NaiveDate::from_ymd_opt(year, 2, 29).is_some();
+                let ordinal = u32::from(day) + u32::from(leap && day >= 60);
+                NaiveDate::from_yo_opt(year, ordinal)
+            }
+            RuleDay::Ordinal(day) => NaiveDate::from_yo_opt(year, u32::from(day) + 1),
+            RuleDay::Weekday { month, week, day } => {
+                let first = NaiveDate::from_ymd_opt(year, month, 1)?;
+                let first_weekday = first.weekday().num_days_from_sunday();
+                let mut date = 1 + (day + 7 - first_weekday) % 7 + (week - 1) * 7;
+                // The fifth week means the last, which may be the fourth.
+                while NaiveDate::from_ymd_opt(year, month, date).is_none() {
+                    date -= 7;
+                }
+                NaiveDate::from_ymd_opt(year, month, date)
+            }
+        }
+    }
+}
+
+impl Change {
+    /// Returns the UTC timestamp of this change in `year`, while the local
+    /// time is `offset` seconds ahead of UTC.
+    fn timestamp(&self, year: i32, offset: i32) -> Option<i64> {
+        let midnight = self.day.date(year)?.and_hms(0, 0, 0).timestamp();
+        Some(midnight + i64::from(self.time) - i64::from(offset))
+    }
+}
+
+impl Rule {
+    fn local_time_at(&self, timestamp: i64) -> &LocalTime {
+        let (dst, start, end) = match self.dst {
+            Some((ref dst, ref start, ref end)) => (dst, start, end),
+            None => return &self.std,
+        };
+        let local = NaiveDateTime::from_timestamp(timestamp + i64::from(self.std.offset), 0);
+        let year = local.year();
+        let (start, end) = match (
+            start.timestamp(year, self.std.offset),
+            end.timestamp(year, dst.offset),
+        ) {
+            (Some(start), Some(end)) => (start, end),
+            _ => return &self.std,
+        };
+        // In the southern hemisphere, daylight saving time spans the new year.
+        let in_dst = if start < end {
+            start <= timestamp && timestamp < end
+        } else {
+            !(end <= timestamp && timestamp < start)
+        };
+        if in_dst {
+            dst
+        } else {
+            &self.std
+        }
+    }
+}
+
+/// Reads the big-endian integers of a tzfile.
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        if self.data.len() < n {
+            return Err(format_err!("Truncated time zone file"));
+        }
+        let (taken, rest) = self.data.split_at(n);
+        self.data = rest;
+        Ok(taken)
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn i32(&mut self) -> Result<i32> {
+        Ok(i32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn i64(&mut self) -> Result<i64> {
+        Ok(i64::from_be_bytes(self.take(8)?.try_into().unwrap()))
+    }
+}
+
+/// The counts in a tzfile's header, of each part of the data which follows.
+struct Counts {
+    isut: usize,
+    isstd: usize,
+    leap: usize,
+    time: usize,
+    ty: usize,
+    chars: usize,
+}
+
+impl Counts {
+    fn read(reader: &mut Reader<'_>) -> Result<(u8, Counts)> {
+        let header = reader.take(20)?;
+        if &header[..4] != b"TZif" {
+            return Err(format_err!("Not a time zone file"));
+        }
+        let mut count = || reader.u32().map(|count| count as usize);
+        let counts = Counts {
+            isut: count()?,
+            isstd: count()?,
+            leap: count()?,
+            time: count()?,
+            ty: count()?,
+            chars: count()?,
+        };
+        Ok((header[4], counts))
+    }
+
+    /// The length of the version 1 data, with 32-bit times.
+    fn v1_len(&self) -> usize {
+        self.time * 5 + self.ty * 6 + self.chars + self.leap * 8 + self.isstd + self.isut
+    }
+}
+
+/// Parses a tzfile, preferring the 64-bit data and rule of version 2 and later
+/// files over the version 1 data.
+fn parse_tzif(data: &[u8]) -> Result<Zone> {
+    let mut reader = Reader { data };
+    let (version, mut counts) = Counts::read(&mut reader)?;
+    let wide = version >= b'2';
+    if wide {
+        reader.take(counts.v1_len())?;
+        counts = Counts::read(&mut reader)?.1;
+    }
+
+    let mut times = Vec::with_capacity(counts.time);
+    for _ in 0..counts.time {
+        times.push(if wide {
+            reader.i64()?
+        } else {
+            i64::from(reader.i32()?)
+        });
+    }
+    let indices = reader.take(counts.time)?;
+    let mut types = Vec::with_capacity(counts.ty);
+    for _ in 0..counts.ty {
+        let offset = reader.i32()?;
+        let _is_dst = reader.take(1)?;
+        let abbreviation = reader.take(1)?[0] as usize;
+        types.push((offset, abbreviation));
+    }
+    let chars = reader.take(counts.chars)?;
+    let leap_len = if wide { 12 } else { 8 };
+    reader.take(counts.leap * leap_len + counts.isstd + counts.isut)?;
+
+    let local_times = types
+        .into_iter()
+        .map(|(offset, start)| {
+            let abbreviation = chars.get(start..).unwrap_or_default();
+            let end = abbreviation
+                .iter()
+                .position(|&c| c == 0)
+                .unwrap_or(abbreviation.len());
+            LocalTime {
+                offset,
+                abbreviation: String::from_utf8_lossy(&abbreviation[..end]).into_owned(),
+            }
+        })
+        .collect::<Vec<_>>();
+    if local_times.is_empty() {
+        return Err(format_err!("Time zone file has no local times"));
+    }
+    let transitions = times
+        .into_iter()
+        .zip(indices.iter().map(|&i| i as usize))
+        .map(|(time, i)| {
+            if i < local_times.len() {
+                Ok((time, i))
+            } else {
+                Err(format_err!("Invalid local time index {}", i))
+            }
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    // The footer is the rule between newlines, which may be empty.
+    let rule = if wide {
+        let footer = String::from_utf8_lossy(reader.data);
+        match footer.trim_matches('\n') {
+            "" => None,
+            rule => Some(parse_rule(rule)?),
+        }
+    } else {
+        None
+    };
+    Ok(Zone {
+        transitions,
+        times: local_times,
+        rule,
+    })
+}
+
+/// Parses the parts of a POSIX `TZ` rule, e.g. `CET-1CEST,M3.5.0,M10.5.0/3`.
+struct RuleParser<'a> {
+    rule: &'a str,
+    rest: &'a str,
+}
+
+impl<'a> RuleParser<'a> {
+    fn error(&self) -> crate::Error {
+        format_err!("Invalid time zone rule {:?}", self.rule)
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        match self.rest.strip_prefix(c) {
+            Some(rest) => {
+                self.rest = rest;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Parses an abbreviation, e.g. `CET` or `<+03>`.
+    fn name(&mut self) -> Result<String> {
+        let (name, rest) = if self.eat('<') {
+            let end = self.rest.find('>').ok_or_else(|| self.error())?;
+            (&self.rest[..end], &self.rest[end + 1..])
+        } else {
+            let end = self
+                .rest
+                .find(|c: char| !c.is_ascii_alphabetic())
+                .unwrap_or(self.rest.len());
+            (&self.rest[..end], &self.rest[end..])
+        };
+        if name.is_empty() {
+            return Err(self.error());
+        }
+        self.rest = rest;
+        Ok(name.to_owned())
+    }
+
+    fn number(&mut self) -> Result<i32> {
+        let end = self
+            .rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(self.rest.len());
+        let number = self.rest[..end].parse().map_err(|_| self.error())?;
+        self.rest = &self.rest[end..];
+        Ok(number)
+    }
+
+    /// Parses a signed time like `-1`, `5:30` or `167:59:59`, in seconds.
+    fn time(&mut self) -> Result<i32> {
+        let sign = if self.eat('-') {
+            -1
+        } else {
+            self.eat('+');
+            1
+        };
+        // A broken file could give any number of hours.
+        let mut seconds = self.number()?.checked_mul(3600);
+        if self.eat(':') {
+            let minutes = self.number()?;
+            seconds = seconds.and_then(|seconds| seconds.checked_add(minutes.checked_mul(60)?));
+            if self.eat(':') {
+                let extra = self.number()?;
+                seconds = seconds.and_then(|seconds| seconds.checked_add(extra));
+            }
+        }
+        match seconds {
+            Some(seconds) => Ok(sign * seconds),
+            None => Err(self.error()),
+        }
+    }
+
+    fn change(&mut self) -> Result<Change> {
+        let day = if self.eat('J') {
+            RuleDay::Julian(self.number()? as u16)
+        } else if self.eat('M') {
+            let month = self.number()? as u32;
+            if !self.eat('.') {
+                return Err(self.error());
+            }
+            let week = self.number()? as u32;
+            if !self.eat('.') {
+                return Err(self.error());
+            }
+            let day = self.number()? as u32;
+            if !(1..=12).contains(&month) || !(1..=5).contains(&week) || day > 6 {
+                return Err(self.error());
+            }
+            RuleDay::Weekday { month, week, day }
+        } else {
+            RuleDay::Ordinal(self.number()? as u16)
+        };
+        let time = if self.eat('/') {
+            self.time()?
+        } else {
+            2 * 3600
+        };
+        Ok(Change { day, time })
+    }
+}
+
+fn parse_rule(rule: &str) -> Result<Rule> {
+    let mut parser = RuleParser { rule, rest: rule };
+    // POSIX offsets are how far behind UTC the zone is.
+    let std = LocalTime {
+        abbreviation: parser.name()?,
+        offset: -parser.time()?,
+    };
+    if parser.rest.is_empty() {
+        return Ok(Rule { std, dst: None });
+    }
+    let abbreviation = parser.name()?;
+    let offset = if parser.rest.starts_with(',') {
+        std.offset.checked_add(3600).ok_or_else(|| parser.error())?
+    } else {
+        -parser.time()?
+    };
+    let dst = LocalTime {
+        offset,
+        abbreviation,
+    };
+    // Zones with daylight saving time but no rule for it are rare enough to
+    // show as standard time.
+    if !parser.eat(',') {
+        return Ok(Rule { std, dst: None });
+    }
+    let start = parser.change()?;
+    if !parser.eat(',') {
+        return Err(parser.error());
+    }
+    let end = parser.change()?;
+    if !parser.rest.is_empty() {
+        return Err(parser.error());
+    }
+    Ok(Rule {
+        std,
+        dst: Some((dst, start, end)),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::NaiveDate;
+
+    use super::{parse_rule, Zone};
+
+    fn timestamp(year: i32, month: u32, day: u32, hour: u32) -> i64 {
+        NaiveDate::from_ymd(year, month, day)
+            .and_hms(hour, 0, 0)
+            .timestamp()
+    }
+
+    #[test]
+    fn works() {
+        let berlin = Zone {
+            transitions: Vec::new(),
+            times: Zone::utc().times,
+            rule: Some(parse_rule("CET-1CEST,M3.5.0,M10.5.0/3").unwrap()),
+        };
+        let offset = |timestamp| {
+            let (offset, abbreviation) = berlin.offset_at(timestamp);
+            (offset.local_minus_utc() / 3600, abbreviation.to_owned())
+        };
+        assert_eq!(offset(timestamp(2026, 1, 15, 12)), (1, "CET".to_owned()));
+        assert_eq!(offset(timestamp(2026, 7, 15, 12)), (2, "CEST".to_owned()));
+        // Summer time starts at 01:00 UTC on 29th March 2026, and ends at
+        // 01:00 UTC on 25th October.
+        assert_eq!(offset(timestamp(2026, 3, 29, 0)).0, 1);
+        assert_eq!(offset(timestamp(2026, 3, 29, 1)).0, 2);
+        assert_eq!(offset(timestamp(2026, 10, 25, 0)).0, 2);
+        assert_eq!(offset(timestamp(2026, 10, 25, 1)).0, 1);
+
+        let sydney = parse_rule("AEST-10AEDT,M10.1.0,M4.1.0/3").unwrap();
+        assert_eq!(
+            sydney.local_time_at(timestamp(2026, 1, 1, 0)).offset,
+            11 * 3600
+        );
+        assert_eq!(
+            sydney.local_time_at(timestamp(2026, 6, 1, 0)).offset,
+            10 * 3600
+        );
+        let india = parse_rule("IST-5:30").unwrap();
+        assert_eq!(india.local_time_at(0).offset, 5 * 3600 + 30 * 60);
+        assert_eq!(parse_rule("<-03>3").unwrap().std.abbreviation, "-03");
+        assert!(parse_rule("CET-1CEST,M13.5.0,M10.5.0").is_err());
+        assert!(parse_rule("CET-1000000CEST,M3.5.0,M10.5.0").is_err());
+        assert!(parse_rule("CET-1:99999999").is_err());
+        assert!(parse_rule("CET-1CEST,M3.5.0/596524,M10.5.0").is_err());
+    }
+}